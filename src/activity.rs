@@ -0,0 +1,140 @@
+// src/activity.rs
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{doc, DateTime as BsonDateTime, Document};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::notification_channels::dispatch;
+use crate::response::CodedError;
+use crate::tenancy::project_team_id;
+
+/// One entry in a team/project activity feed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActivityEvent {
+    pub event_id: String,
+    pub team_id: String,
+    pub project_id: Option<String>,
+    pub actor_id: String,
+    /// e.g. "ticket_created", "ticket_moved", "doc_edited", "member_joined"
+    pub kind: String,
+    /// Id of the ticket/doc/board/user the event is about
+    pub target_id: String,
+    pub summary: String,
+    pub created_at: BsonDateTime,
+}
+
+impl ActivityEvent {
+    pub fn new(
+        team_id: &str,
+        project_id: Option<&str>,
+        actor_id: &str,
+        kind: &str,
+        target_id: &str,
+        summary: impl Into<String>,
+    ) -> Self {
+        ActivityEvent {
+            event_id: Uuid::new_v4().to_string(),
+            team_id: team_id.to_string(),
+            project_id: project_id.map(String::from),
+            actor_id: actor_id.to_string(),
+            kind: kind.to_string(),
+            target_id: target_id.to_string(),
+            summary: summary.into(),
+            created_at: BsonDateTime::now(),
+        }
+    }
+}
+
+/// Append an event to the feed and send it to the team's notification channels.
+/// Failures are logged, never surfaced to the caller.
+pub async fn record_activity(db: &MongoDB, event: ActivityEvent) {
+    let coll = db.db.collection::<ActivityEvent>("activity_events");
+    if let Err(e) = coll.insert_one(&event).await {
+        error!("Error recording activity {}: {}", event.kind, e);
+        return;
+    }
+    dispatch(&event);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActivityQuery {
+    /// Only return events older than this (cursor from the previous page)
+    pub before: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActivityPage {
+    pub events: Vec<ActivityEvent>,
+    /// Pass as `before` to fetch the next page; None when the feed is exhausted
+    pub next_before: Option<DateTime<Utc>>,
+}
+
+async fn feed(data: &AppState, mut filter: Document, query: &ActivityQuery) -> HttpResponse {
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+    if let Some(before) = query.before {
+        filter.insert("created_at", doc! { "$lt": BsonDateTime::from_chrono(before) });
+    }
+
+    let coll = data.mongodb.db.collection::<ActivityEvent>("activity_events");
+    let mut cursor = match coll.find(filter).sort(doc! { "created_at": -1 }).limit(limit).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching activity: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching activity");
+        }
+    };
+    let mut events = Vec::new();
+    while let Some(res) = cursor.next().await {
+        match res {
+            Ok(ev) => events.push(ev),
+            Err(e) => {
+                error!("Cursor error: {}", e);
+                return HttpResponse::InternalServerError().body("Error reading activity");
+            }
+        }
+    }
+    let next_before = if events.len() as i64 == limit {
+        events.last().map(|e| e.created_at.to_chrono())
+    } else {
+        None
+    };
+    HttpResponse::Ok().json(ActivityPage { events, next_before })
+}
+
+/// GET /teams/{team_id}/activity
+pub async fn get_team_activity(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    query: web::Query<ActivityQuery>,
+) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().body("Not a member of the team");
+    }
+    feed(&data, doc! { "team_id": &*team_id }, &query).await
+}
+
+/// GET /projects/{project_id}/activity
+pub async fn get_project_activity(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    project_id: web::Path<String>,
+    query: web::Query<ActivityQuery>,
+) -> impl Responder {
+    let team_id = match project_team_id(&data.mongodb, &project_id).await {
+        Some(t) => t,
+        None => return HttpResponse::NotFound().error("project_not_found"),
+    };
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().body("Not a member of the team");
+    }
+    feed(&data, doc! { "project_id": &*project_id }, &query).await
+}
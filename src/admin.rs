@@ -0,0 +1,159 @@
+// src/admin.rs
+//
+// Operator-only endpoints: WebSocket inspection, data integrity checks and the
+// Prometheus metrics exporter.
+// Admins are listed by user id in ADMIN_USER_IDS.
+
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use log::{error, info};
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_server::{ForceDisconnect, GetWsStats, WsStats};
+use crate::db_pool::pool_stats;
+use crate::doc_collab::{DocRoomStats, GetDocRooms};
+use crate::impersonation::Impersonation;
+use crate::integrity;
+use crate::response::CodedError;
+use crate::sync::{record_change, Entity, Op, Scope};
+
+/// Impersonation tokens never count as admin, whoever they act as.
+pub(crate) fn is_platform_admin(req: &HttpRequest, data: &AppState) -> bool {
+    if req.extensions().contains::<Impersonation>() {
+        return false;
+    }
+    req.extensions()
+        .get::<String>()
+        .is_some_and(|uid| data.config.admin_user_ids.contains(uid))
+}
+
+#[derive(Serialize)]
+struct WsInspection {
+    chat: WsStats,
+    doc_rooms: Vec<DocRoomStats>,
+}
+
+/// GET /admin/ws
+pub async fn get_ws_stats(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    let chat = match data.chat_server.send(GetWsStats).await {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Error querying chat server: {}", e);
+            return HttpResponse::InternalServerError().body("Error querying chat server");
+        }
+    };
+    let doc_rooms = data.doc_server.send(GetDocRooms).await.unwrap_or_default();
+    HttpResponse::Ok().json(WsInspection { chat, doc_rooms })
+}
+
+/// POST /admin/ws/disconnect/{user_id}
+pub async fn force_disconnect(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    user_id: web::Path<String>,
+) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    match data.chat_server.send(ForceDisconnect { user_id: user_id.into_inner() }).await {
+        Ok(closed) => HttpResponse::Ok().json(serde_json::json!({ "closed_sessions": closed })),
+        Err(e) => {
+            error!("Error disconnecting user: {}", e);
+            HttpResponse::InternalServerError().body("Error disconnecting user")
+        }
+    }
+}
+
+/// GET /admin/integrity
+/// References to missing teams, projects, boards, chats and users; nothing is changed.
+pub async fn check_integrity(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    match integrity::scan(&data.mongodb).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            error!("Error checking data integrity: {}", e);
+            HttpResponse::InternalServerError().body("Error checking data integrity")
+        }
+    }
+}
+
+/// POST /admin/integrity/repair
+/// Removes what the check found where that is safe; see integrity.rs.
+pub async fn repair_integrity(req: HttpRequest, auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    let report = match integrity::repair(&data.mongodb).await {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Error repairing data integrity: {}", e);
+            return HttpResponse::InternalServerError().body("Error repairing data integrity");
+        }
+    };
+    for (ticket_id, project_id) in &report.deleted_tickets {
+        record_change(&data.mongodb, Entity::Ticket, ticket_id, Op::Delete, Scope::Project(project_id)).await;
+    }
+    let repaired: u64 = report.findings.iter().map(|f| f.repaired).sum();
+    info!("Admin {} repaired {} documents with broken references", auth.user_id(), repaired);
+    HttpResponse::Ok().json(report)
+}
+
+/// Compares a presented token without returning early on the first differing byte.
+fn tokens_match(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented.bytes().zip(expected.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// GET /metrics — Prometheus text format. Readable by admins, or by a scraper
+/// presenting METRICS_TOKEN as `Authorization: Bearer ...`.
+pub async fn metrics(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let token_ok = data.config.metrics_token.as_ref().is_some_and(|expected| {
+        req.headers()
+            .get(actix_web::http::header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .is_some_and(|t| tokens_match(t.trim(), expected))
+    });
+    if !token_ok && !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+
+    let stats = match data.chat_server.send(GetWsStats).await {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Error querying chat server: {}", e);
+            return HttpResponse::InternalServerError().body("Error querying chat server");
+        }
+    };
+    let doc_rooms = data.doc_server.send(GetDocRooms).await.unwrap_or_default();
+
+    let mut out = String::new();
+    let mut gauge = |name: &str, help: &str, kind: &str, value: String| {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}\n{} {}", name, help, name, kind, name, value);
+    };
+    gauge("taskline_ws_connected_users", "Users with at least one open WebSocket", "gauge", stats.connected_users.to_string());
+    gauge("taskline_ws_sessions", "Open WebSocket sessions", "gauge", stats.total_sessions.to_string());
+    gauge("taskline_ws_active_rooms", "Chats with traffic in the last five minutes", "gauge", stats.active_rooms.len().to_string());
+    gauge("taskline_ws_messages_total", "Chat messages received over WebSocket", "counter", stats.messages_total.to_string());
+    gauge("taskline_ws_signals_total", "Signalling messages relayed", "counter", stats.signals_total.to_string());
+    gauge("taskline_doc_rooms", "Open collaborative editing rooms", "gauge", doc_rooms.len().to_string());
+    gauge("taskline_uptime_seconds", "Seconds since the chat server started", "gauge", stats.uptime_secs.to_string());
+    let pool = pool_stats();
+    gauge("taskline_mongo_connections", "Open MongoDB connections", "gauge", pool.connections.to_string());
+    gauge("taskline_mongo_connections_in_use", "MongoDB connections checked out by operations", "gauge", pool.in_use.to_string());
+    gauge("taskline_mongo_checkout_failures_total", "Failed MongoDB connection checkouts", "counter", pool.checkout_failures_total.to_string());
+    gauge("taskline_mongo_pool_cleared_total", "MongoDB pools cleared after server errors", "counter", pool.pool_cleared_total.to_string());
+    gauge("taskline_mongo_retries_total", "MongoDB operations retried after transient errors", "counter", pool.retries_total.to_string());
+    gauge("taskline_mongo_retries_exhausted_total", "MongoDB operations that failed after the last retry", "counter", pool.retries_exhausted_total.to_string());
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(out)
+}
@@ -1,6 +1,17 @@
+use std::collections::HashMap;
+
 use actix_web::{web, HttpResponse, Responder};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::doc;
 use serde::{Deserialize, Serialize};
 use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat::{Chat, DBMessage};
+use crate::knowledge_base::rank_documents;
+use crate::response::CodedError;
+use crate::tenancy::{ProjectScope, Repo, TeamScope};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
 
 #[derive(Deserialize, Serialize)]
 pub struct TaskInput {
@@ -65,3 +76,450 @@ pub async fn get_team_morale(
             .body(format!("AI service unreachable: {}", e)),
     }
 }
+
+/// Base URL of the configured AI service.
+pub(crate) fn ai_endpoint(data: &AppState) -> &str {
+    let endpoint = if data.config.ai_use_local {
+        &data.config.ai_local_endpoint
+    } else {
+        &data.config.ai_aws_endpoint
+    };
+    endpoint.trim_end_matches('/')
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    texts: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+/// Embeds `texts` with the AI service, one vector per text, in order.
+pub async fn embed(data: &AppState, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+    if texts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let url = format!("{}/embed", ai_endpoint(data));
+    let resp = data.http_client.post(&url)
+        .json(&EmbedRequest { texts })
+        .send()
+        .await
+        .map_err(|e| format!("AI service unreachable: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("AI service error: {}", resp.status()));
+    }
+    let body = resp.json::<EmbedResponse>().await
+        .map_err(|e| format!("AI response parse error: {}", e))?;
+    if body.embeddings.len() != texts.len() {
+        return Err("AI service returned the wrong number of embeddings".to_string());
+    }
+    Ok(body.embeddings)
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 { 0.0 } else { dot / (norm_a * norm_b) }
+}
+
+/// Texts sent to the AI service per request.
+const EMBED_BATCH: usize = 64;
+
+/// A cached embedding of an entity's text; recomputed when the text changes.
+#[derive(Serialize, Deserialize)]
+struct CachedEmbedding {
+    kind: String,
+    entity_id: String,
+    text: String,
+    vector: Vec<f32>,
+}
+
+/// Embeddings for `(entity_id, text)` pairs of one kind, served from the
+/// `embeddings` collection where the text is unchanged and computed otherwise.
+pub async fn cached_embeddings(
+    data: &AppState,
+    kind: &str,
+    items: Vec<(String, String)>,
+) -> Result<HashMap<String, Vec<f32>>, String> {
+    let coll = data.mongodb.db.collection::<CachedEmbedding>("embeddings");
+    let ids: Vec<&String> = items.iter().map(|(id, _)| id).collect();
+    let mut cached = HashMap::new();
+    let mut cursor = coll.find(doc! { "kind": kind, "entity_id": { "$in": ids } }).await
+        .map_err(|e| e.to_string())?;
+    while let Some(Ok(c)) = cursor.next().await {
+        cached.insert(c.entity_id.clone(), c);
+    }
+
+    let mut out = HashMap::new();
+    let mut missing = Vec::new();
+    for (id, text) in items {
+        match cached.remove(&id) {
+            Some(c) if c.text == text => {
+                out.insert(id, c.vector);
+            }
+            _ => missing.push((id, text)),
+        }
+    }
+    for batch in missing.chunks(EMBED_BATCH) {
+        let texts: Vec<String> = batch.iter().map(|(_, t)| t.clone()).collect();
+        for ((id, text), vector) in batch.iter().zip(embed(data, &texts).await?) {
+            let entry = CachedEmbedding {
+                kind: kind.to_string(),
+                entity_id: id.clone(),
+                text: text.clone(),
+                vector: vector.clone(),
+            };
+            if let Err(e) = coll.replace_one(doc! { "kind": kind, "entity_id": id }, &entry).upsert(true).await {
+                error!("Error caching embedding for {} {}: {}", kind, id, e);
+            }
+            out.insert(id.clone(), vector);
+        }
+    }
+    Ok(out)
+}
+
+/// Scores `items` against `query` by cosine similarity, best match first.
+/// `key` gives each item's id and the text its embedding is computed from.
+pub async fn rank<T>(
+    data: &AppState,
+    kind: &str,
+    query: &[f32],
+    items: Vec<T>,
+    key: impl Fn(&T) -> (String, String),
+) -> Result<Vec<(T, f32)>, String> {
+    let vectors = cached_embeddings(data, kind, items.iter().map(&key).collect()).await?;
+    let mut ranked: Vec<(T, f32)> = items
+        .into_iter()
+        .filter_map(|item| {
+            let score = cosine_similarity(query, vectors.get(&key(&item).0)?);
+            Some((item, score))
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    Ok(ranked)
+}
+
+/// Drops the cached embedding of a deleted entity.
+pub async fn remove_embedding(data: &AppState, kind: &str, entity_id: &str) {
+    if let Err(e) = data.mongodb.db.collection::<CachedEmbedding>("embeddings")
+        .delete_one(doc! { "kind": kind, "entity_id": entity_id })
+        .await
+    {
+        error!("Error removing embedding for {} {}: {}", kind, entity_id, e);
+    }
+}
+
+/// Minimum similarity for a ticket to be reported as a likely duplicate.
+const DUPLICATE_THRESHOLD: f32 = 0.85;
+const MAX_DUPLICATES: usize = 5;
+/// Only the most recent open tickets of a project are compared.
+const DUPLICATE_CANDIDATES: i64 = 200;
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateCandidate {
+    pub ticket_id: String,
+    pub title: String,
+    pub status: String,
+    /// Cosine similarity, 0-1
+    pub score: f32,
+}
+
+const TICKET_EMBEDDING: &str = "ticket";
+
+fn ticket_text(title: &str, description: Option<&str>) -> String {
+    match description.filter(|d| !d.trim().is_empty()) {
+        Some(d) => format!("{}\n\n{}", title, d),
+        None => title.to_string(),
+    }
+}
+
+fn ticket_key(t: &Ticket) -> (String, String) {
+    (t.ticket_id.clone(), ticket_text(&t.title, t.description.as_deref()))
+}
+
+/// Open tickets of the scope's project most similar to the given title/description,
+/// best match first. `exclude` skips the ticket being checked itself.
+pub async fn find_duplicate_tickets(
+    data: &AppState,
+    scope: &ProjectScope,
+    title: &str,
+    description: Option<&str>,
+    exclude: Option<&str>,
+) -> Result<Vec<DuplicateCandidate>, String> {
+    let mut cursor = Repo::<Ticket>::new(&data.mongodb, scope)
+        .find(doc! {
+            "status": { "$nin": CLOSED_STATUSES.to_vec() },
+            "ticket_id": { "$ne": exclude },
+        })
+        .sort(doc! { "created_at": -1 })
+        .limit(DUPLICATE_CANDIDATES)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut tickets = Vec::new();
+    while let Some(Ok(t)) = cursor.next().await {
+        tickets.push(t);
+    }
+    if tickets.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let query = embed(data, &[ticket_text(title, description)]).await?.remove(0);
+    let ranked = rank(data, TICKET_EMBEDDING, &query, tickets, ticket_key).await?;
+    Ok(ranked.into_iter()
+        .take_while(|(_, score)| *score >= DUPLICATE_THRESHOLD)
+        .take(MAX_DUPLICATES)
+        .map(|(t, score)| DuplicateCandidate {
+            ticket_id: t.ticket_id,
+            title: t.title,
+            status: t.status,
+            score,
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+pub struct FindDuplicatesRequest {
+    pub team_id: String,
+    pub project_id: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// Set when checking an existing ticket, so it does not match itself
+    pub ticket_id: Option<String>,
+}
+
+/// POST /ai/tickets/find_duplicates
+pub async fn find_duplicates(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    req: web::Json<FindDuplicatesRequest>,
+) -> impl Responder {
+    let scope = match auth.project_scope(&req.team_id, &req.project_id).await {
+        Ok(scope) => scope,
+        Err(resp) => return resp,
+    };
+    if !auth.is_project_member(&req.project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
+    if req.title.trim().is_empty() {
+        return HttpResponse::BadRequest().body("title is required");
+    }
+    match find_duplicate_tickets(&data, &scope, &req.title, req.description.as_deref(), req.ticket_id.as_deref()).await {
+        Ok(found) => HttpResponse::Ok().json(found),
+        Err(e) => HttpResponse::BadGateway().body(e),
+    }
+}
+
+const ASSISTANT_TICKETS: usize = 5;
+const ASSISTANT_DOCS: usize = 3;
+const ASSISTANT_MESSAGES: usize = 5;
+/// Most recent tickets / messages considered for retrieval.
+const ASSISTANT_TICKET_POOL: i64 = 300;
+const ASSISTANT_MESSAGE_POOL: i64 = 200;
+const MESSAGE_EMBEDDING: &str = "message";
+
+#[derive(Deserialize)]
+pub struct AssistantRequest {
+    pub question: String,
+}
+
+/// A retrieved item the answer may cite as `[n]`.
+#[derive(Debug, Serialize)]
+pub struct AssistantSource {
+    #[serde(rename = "ref")]
+    pub reference: usize,
+    /// "ticket", "doc" or "message"
+    pub kind: &'static str,
+    pub id: String,
+    pub title: String,
+    /// Chat or project the item belongs to
+    pub container_id: String,
+    pub score: f32,
+    #[serde(skip)]
+    text: String,
+}
+
+#[derive(Serialize)]
+struct AssistantContext<'a> {
+    #[serde(rename = "ref")]
+    reference: usize,
+    kind: &'a str,
+    title: &'a str,
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+struct AssistantAiRequest<'a> {
+    question: &'a str,
+    sources: Vec<AssistantContext<'a>>,
+}
+
+#[derive(Deserialize)]
+struct AssistantAiResponse {
+    answer: String,
+}
+
+#[derive(Serialize)]
+pub struct AssistantResponse {
+    pub answer: String,
+    pub sources: Vec<AssistantSource>,
+}
+
+/// Tickets of the team's projects the caller is a member of.
+async fn assistant_tickets(auth: &AuthContext, data: &AppState, team: &TeamScope) -> mongodb::error::Result<Vec<Ticket>> {
+    let mut scope = team.projects(&data.mongodb).await?;
+    let mut project_ids = Vec::new();
+    for id in scope.project_ids() {
+        if auth.is_project_member(id).await {
+            project_ids.push(id.clone());
+        }
+    }
+    scope.retain(|id| project_ids.contains(id));
+    let mut tickets = Vec::new();
+    if project_ids.is_empty() {
+        return Ok(tickets);
+    }
+    let mut cursor = Repo::<Ticket>::across(&data.mongodb, &scope)
+        .find(doc! {})
+        .sort(doc! { "created_at": -1 })
+        .limit(ASSISTANT_TICKET_POOL)
+        .await?;
+    while let Some(t) = cursor.next().await {
+        tickets.push(t?);
+    }
+    Ok(tickets)
+}
+
+/// Recent messages of the team's chats the caller takes part in.
+async fn assistant_messages(data: &AppState, team_id: &str, user_id: &str) -> mongodb::error::Result<Vec<DBMessage>> {
+    let mut chat_ids = Vec::new();
+    let mut cursor = data.mongodb.db.collection::<Chat>("chats")
+        .find(doc! { "team_id": team_id, "participants": user_id })
+        .await?;
+    while let Some(c) = cursor.next().await {
+        chat_ids.push(c?.id_chat);
+    }
+    let mut messages = Vec::new();
+    if chat_ids.is_empty() {
+        return Ok(messages);
+    }
+    let mut cursor = data.mongodb.db.collection::<DBMessage>("messages")
+        .find(doc! { "id_chat": { "$in": chat_ids }, "content": { "$ne": "" } })
+        .sort(doc! { "created_at": -1 })
+        .limit(ASSISTANT_MESSAGE_POOL)
+        .await?;
+    while let Some(m) = cursor.next().await {
+        messages.push(m?);
+    }
+    Ok(messages)
+}
+
+/// POST /ai/teams/{team_id}/assistant
+///
+/// Answers a question from the team's tickets, knowledge base and chats. Only items
+/// the caller can already see are retrieved, and every one handed to the model is
+/// returned as a numbered source.
+pub async fn team_assistant(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    req: web::Json<AssistantRequest>,
+) -> impl Responder {
+    let team = match auth.team_scope(&team_id).await {
+        Ok(team) => team,
+        Err(resp) => return resp,
+    };
+    let question = req.question.trim();
+    if question.is_empty() {
+        return HttpResponse::BadRequest().body("question is required");
+    }
+
+    let query = match embed(&data, &[question.to_string()]).await {
+        Ok(mut v) => v.remove(0),
+        Err(e) => return HttpResponse::BadGateway().body(e),
+    };
+    let (tickets, messages) = match (
+        assistant_tickets(&auth, &data, &team).await,
+        assistant_messages(&data, &team_id, auth.user_id()).await,
+    ) {
+        (Ok(t), Ok(m)) => (t, m),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Error gathering assistant context: {}", e);
+            return HttpResponse::InternalServerError().body("Error gathering context");
+        }
+    };
+
+    let mut sources = Vec::new();
+    let ranked_tickets = rank(&data, TICKET_EMBEDDING, &query, tickets, ticket_key).await;
+    let ranked_docs = rank_documents(&data, &team_id, auth.user_id(), &query, ASSISTANT_DOCS).await;
+    let ranked_messages = rank(&data, MESSAGE_EMBEDDING, &query, messages, |m| (m.id.clone(), m.content.clone())).await;
+    let (ranked_tickets, ranked_docs, ranked_messages) = match (ranked_tickets, ranked_docs, ranked_messages) {
+        (Ok(t), Ok(d), Ok(m)) => (t, d, m),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => return HttpResponse::BadGateway().body(e),
+    };
+    for (t, score) in ranked_tickets.into_iter().take(ASSISTANT_TICKETS) {
+        sources.push(AssistantSource {
+            reference: 0,
+            kind: "ticket",
+            text: format!("Status: {}\n{}", t.status, ticket_text(&t.title, t.description.as_deref())),
+            id: t.ticket_id,
+            title: t.title,
+            container_id: t.project_id,
+            score,
+        });
+    }
+    for hit in ranked_docs {
+        let d = hit.document;
+        sources.push(AssistantSource {
+            reference: 0,
+            kind: "doc",
+            text: d.content,
+            id: d.id,
+            title: d.title,
+            container_id: d.team_id,
+            score: hit.score,
+        });
+    }
+    for (m, score) in ranked_messages.into_iter().take(ASSISTANT_MESSAGES) {
+        sources.push(AssistantSource {
+            reference: 0,
+            kind: "message",
+            title: format!("Message from {} on {}", m.sender_id, m.created_at.format("%Y-%m-%d")),
+            text: m.content,
+            id: m.id,
+            container_id: m.id_chat,
+            score,
+        });
+    }
+    for (i, s) in sources.iter_mut().enumerate() {
+        s.reference = i + 1;
+    }
+
+    let body = AssistantAiRequest {
+        question,
+        sources: sources.iter().map(|s| AssistantContext {
+            reference: s.reference,
+            kind: s.kind,
+            title: &s.title,
+            text: &s.text,
+        }).collect(),
+    };
+    let url = format!("{}/assistant", ai_endpoint(&data));
+    match data.http_client.post(&url).json(&body).send().await {
+        Ok(resp) if resp.status().is_success() => match resp.json::<AssistantAiResponse>().await {
+            Ok(ai) => HttpResponse::Ok().json(AssistantResponse { answer: ai.answer, sources }),
+            Err(e) => HttpResponse::InternalServerError()
+                .body(format!("AI response parse error: {}", e)),
+        },
+        Ok(resp) => HttpResponse::BadGateway()
+            .body(format!("AI service error: {}", resp.status())),
+        Err(e) => HttpResponse::BadGateway()
+            .body(format!("AI service unreachable: {}", e)),
+    }
+}
@@ -0,0 +1,415 @@
+// src/analytics_export.rs
+//
+// Read-only extracts for BI tools. Each dataset is flat and denormalized, with
+// project, board, release and people names next to their ids, so it loads into a
+// warehouse table as is:
+//
+// - `tickets`: one row per ticket, with the time logged on it
+// - `worklogs`: one row per worklog entry
+// - `sprints`: one row per board sprint, with ticket, estimate and logged totals
+//
+// Formats are CSV and NDJSON, streamed from the cursor; there is no Parquet writer
+// among our dependencies, and both load into the usual BI tools. With `?since=` only rows
+// that changed from then on are sent: tickets with events in their log since then,
+// worklog entries ended since then, and sprints containing such a ticket. Deleted
+// tickets are not reported. The `X-Export-Until` header holds the time the export
+// read from, to pass as `since` on the next pull.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::stream::LocalBoxStream;
+use futures_util::{stream, Stream, StreamExt};
+use log::error;
+use mongodb::action::Find;
+use mongodb::bson::{self, doc, Bson, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::Board;
+use crate::chat_db::MongoDB;
+use crate::out_of_office::usernames;
+use crate::project::Project;
+use crate::response::{ndjson_line, NDJSON};
+use crate::tenancy::{ProjectsScope, Repo};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+use crate::ticket_worklog::WorklogEntry;
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsExportQuery {
+    /// "tickets", "worklogs" or "sprints"
+    pub dataset: String,
+    /// "csv" (default) or "ndjson"
+    pub format: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TicketFact {
+    pub ticket_id: String,
+    pub project_id: String,
+    pub project_name: Option<String>,
+    pub board_id: String,
+    pub board_name: Option<String>,
+    pub title: String,
+    pub status: String,
+    pub closed: bool,
+    pub priority: Option<String>,
+    pub ticket_type: Option<String>,
+    pub reporter_id: String,
+    pub reporter_name: Option<String>,
+    pub assignee_id: Option<String>,
+    pub assignee_name: Option<String>,
+    pub sprint: Option<i32>,
+    pub estimate_hours: Option<f64>,
+    pub logged_hours: f64,
+    /// Semicolon-separated
+    pub labels: Option<String>,
+    pub release_id: Option<String>,
+    pub release_name: Option<String>,
+    pub vote_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolution: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorklogFact {
+    pub entry_id: String,
+    pub ticket_id: String,
+    pub ticket_title: Option<String>,
+    pub project_id: Option<String>,
+    pub project_name: Option<String>,
+    pub user_id: String,
+    pub user_name: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub minutes: i64,
+    pub focus_session_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SprintFact {
+    pub project_id: String,
+    pub project_name: Option<String>,
+    pub board_id: String,
+    pub board_name: Option<String>,
+    pub sprint: i32,
+    pub tickets: i64,
+    pub tickets_done: i64,
+    pub unestimated: i64,
+    pub estimate_hours: f64,
+    pub estimate_hours_done: f64,
+    pub logged_hours: f64,
+}
+
+/// Names and totals the rows are joined with, loaded once per export.
+#[derive(Default)]
+struct Lookups {
+    projects: HashMap<String, String>,
+    boards: HashMap<String, String>,
+    releases: HashMap<String, String>,
+    users: HashMap<String, String>,
+    /// ticket_id -> minutes logged
+    logged: HashMap<String, i64>,
+    /// The team's projects, for reading its tickets
+    scope: ProjectsScope,
+}
+
+impl Lookups {
+    fn logged_hours(&self, ticket_id: &str) -> f64 {
+        self.logged.get(ticket_id).copied().unwrap_or(0) as f64 / 60.0
+    }
+}
+
+async fn name_map(find: Find<'_, Document>, id: &str, name: &str) -> mongodb::error::Result<HashMap<String, String>> {
+    let mut cursor = find.projection(doc! { id: 1, name: 1 }).await?;
+    let mut map = HashMap::new();
+    while let Some(d) = cursor.next().await {
+        let d = d?;
+        if let (Ok(k), Ok(v)) = (d.get_str(id), d.get_str(name)) {
+            map.insert(k.to_string(), v.to_string());
+        }
+    }
+    Ok(map)
+}
+
+async fn lookups(db: &MongoDB, team_id: &str) -> mongodb::error::Result<Lookups> {
+    let scope = ProjectsScope::of_team(db, team_id).await?;
+    let project_ids = scope.project_ids();
+    let projects = name_map(Repo::<Project>::across(db, &scope).find_docs(doc! {}), "project_id", "name").await?;
+    let boards = name_map(Repo::<Board>::across(db, &scope).find_docs(doc! {}), "board_id", "name").await?;
+    let releases = db.db.collection::<Document>("releases");
+    let releases = name_map(releases.find(doc! { "project_id": { "$in": project_ids } }), "release_id", "name").await?;
+
+    let members: Vec<String> = db.db
+        .collection::<Document>("user_teams")
+        .distinct("user_id", doc! { "team_id": team_id })
+        .await?
+        .iter()
+        .filter_map(|v| v.as_str().map(str::to_string))
+        .collect();
+    let users = usernames(db, &members).await?.into_iter().collect();
+
+    let pipeline = vec![
+        doc! { "$lookup": { "from": "tickets", "localField": "ticket_id", "foreignField": "ticket_id", "as": "t" } },
+        doc! { "$match": { "t.project_id": { "$in": &project_ids } } },
+        doc! { "$group": { "_id": "$ticket_id", "minutes": { "$sum": "$minutes" } } },
+    ];
+    let mut cursor = db.db.collection::<Document>("ticket_worklog").aggregate(pipeline).await?;
+    let mut logged = HashMap::new();
+    while let Some(d) = cursor.next().await {
+        let d = d?;
+        let minutes = match d.get("minutes") {
+            Some(Bson::Int64(m)) => *m,
+            Some(Bson::Int32(m)) => *m as i64,
+            _ => 0,
+        };
+        if let Ok(id) = d.get_str("_id") {
+            logged.insert(id.to_string(), minutes);
+        }
+    }
+    Ok(Lookups { projects, boards, releases, users, logged, scope })
+}
+
+/// Tickets of the team changed since `since`, or all of them.
+async fn ticket_filter(db: &MongoDB, since: Option<DateTime<Utc>>) -> mongodb::error::Result<Document> {
+    let Some(since) = since else {
+        return Ok(doc! {});
+    };
+    let since = bson::to_bson(&since).unwrap_or_default();
+    let changed = db.db.collection::<Document>("ticket_events").distinct("ticket_id", doc! { "at": { "$gte": &since } }).await?;
+    Ok(doc! {
+        // Tickets older than the event log only have their creation date.
+        "$or": [{ "ticket_id": { "$in": changed } }, { "created_at": { "$gte": since } }],
+    })
+}
+
+fn ticket_fact(t: Ticket, l: &Lookups) -> TicketFact {
+    TicketFact {
+        project_name: l.projects.get(&t.project_id).cloned(),
+        board_name: l.boards.get(&t.board_id).cloned(),
+        closed: CLOSED_STATUSES.contains(&t.status.as_str()),
+        reporter_name: l.users.get(&t.reporter).cloned(),
+        assignee_name: t.assignee.as_ref().and_then(|a| l.users.get(a)).cloned(),
+        logged_hours: l.logged_hours(&t.ticket_id),
+        release_name: t.fix_version.as_ref().and_then(|r| l.releases.get(r)).cloned(),
+        labels: t.labels.map(|l| l.join(";")),
+        ticket_id: t.ticket_id,
+        project_id: t.project_id,
+        board_id: t.board_id,
+        title: t.title,
+        status: t.status,
+        priority: t.priority,
+        ticket_type: t.ticket_type,
+        reporter_id: t.reporter,
+        assignee_id: t.assignee,
+        sprint: t.sprint,
+        estimate_hours: t.estimate_hours,
+        release_id: t.fix_version,
+        vote_count: t.vote_count,
+        created_at: t.created_at,
+        due_date: t.due_date,
+        resolved_at: t.resolved_at,
+        resolution: t.resolution,
+    }
+}
+
+async fn sprint_facts(db: &MongoDB, l: &Lookups, since: Option<DateTime<Utc>>) -> mongodb::error::Result<Vec<SprintFact>> {
+    let mut filter = ticket_filter(db, since).await?;
+    filter.insert("sprint", doc! { "$ne": Bson::Null });
+    let tickets = Repo::<Ticket>::across(db, &l.scope);
+    // Sprints with a changed ticket, recomputed from all of their tickets.
+    let touched = tickets
+        .aggregate(vec![
+            doc! { "$match": filter },
+            doc! { "$group": { "_id": { "board_id": "$board_id", "sprint": "$sprint" } } },
+        ])
+        .await?
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .map(|d| d.map(|d| d.get_document("_id").cloned().unwrap_or_default()))
+        .collect::<mongodb::error::Result<Vec<Document>>>()?;
+    if touched.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut cursor = tickets
+        .find(doc! { "$or": touched })
+        .sort(doc! { "board_id": 1, "sprint": 1 })
+        .await?;
+    let mut facts: Vec<SprintFact> = Vec::new();
+    while let Some(t) = cursor.next().await {
+        let t = t?;
+        let Some(sprint) = t.sprint else { continue };
+        let fact = match facts.last_mut() {
+            Some(f) if f.board_id == t.board_id && f.sprint == sprint => f,
+            _ => {
+                facts.push(SprintFact {
+                    project_id: t.project_id.clone(),
+                    project_name: l.projects.get(&t.project_id).cloned(),
+                    board_id: t.board_id.clone(),
+                    board_name: l.boards.get(&t.board_id).cloned(),
+                    sprint,
+                    tickets: 0,
+                    tickets_done: 0,
+                    unestimated: 0,
+                    estimate_hours: 0.0,
+                    estimate_hours_done: 0.0,
+                    logged_hours: 0.0,
+                });
+                facts.last_mut().expect("just pushed")
+            }
+        };
+        let done = CLOSED_STATUSES.contains(&t.status.as_str());
+        let estimate = t.estimate_hours.unwrap_or(0.0);
+        fact.tickets += 1;
+        fact.tickets_done += i64::from(done);
+        fact.unestimated += i64::from(t.estimate_hours.is_none());
+        fact.estimate_hours += estimate;
+        fact.estimate_hours_done += if done { estimate } else { 0.0 };
+        fact.logged_hours += l.logged_hours(&t.ticket_id);
+    }
+    Ok(facts)
+}
+
+fn stream_error(e: mongodb::error::Error) -> actix_web::Error {
+    error!("Error streaming analytics export: {}", e);
+    ErrorInternalServerError("Error reading export data")
+}
+
+/// Encodes rows as CSV (with a header line) or NDJSON.
+fn encode<T, S>(rows: S, format: &str) -> LocalBoxStream<'static, Result<web::Bytes, actix_web::Error>>
+where
+    T: Serialize + 'static,
+    S: Stream<Item = Result<T, actix_web::Error>> + 'static,
+{
+    if format == "ndjson" {
+        return rows.map(|row| ndjson_line(&row?)).boxed_local();
+    }
+    rows.enumerate()
+        .map(|(n, row)| {
+            let mut writer = csv::WriterBuilder::new().has_headers(n == 0).from_writer(Vec::new());
+            writer.serialize(row?).map_err(ErrorInternalServerError)?;
+            writer.into_inner().map(web::Bytes::from).map_err(|e| ErrorInternalServerError(e.to_string()))
+        })
+        .boxed_local()
+}
+
+/// GET /teams/{team_id}/analytics/export?dataset=tickets|worklogs|sprints&format=csv|ndjson&since=
+/// Team admins only.
+pub async fn export_analytics(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    query: web::Query<AnalyticsExportQuery>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can export analytics");
+    }
+    let format = query.format.clone().unwrap_or_else(|| "csv".to_string());
+    if !["csv", "ndjson"].contains(&format.as_str()) {
+        return HttpResponse::BadRequest().body("format must be 'csv' or 'ndjson'");
+    }
+    let until = Utc::now();
+    let since = query.since;
+    let db = &data.mongodb;
+    let lookups = match lookups(db, &team_id).await {
+        Ok(l) => Rc::new(l),
+        Err(e) => {
+            error!("Error preparing analytics export: {}", e);
+            return HttpResponse::InternalServerError().body("Error preparing export");
+        }
+    };
+
+    let body = match query.dataset.as_str() {
+        "tickets" => {
+            let cursor = match ticket_filter(db, since).await {
+                Ok(filter) => Repo::<Ticket>::across(db, &lookups.scope).find(filter).sort(doc! { "created_at": 1 }).await,
+                Err(e) => Err(e),
+            };
+            match cursor {
+                Ok(c) => {
+                    let l = lookups.clone();
+                    encode(c.map(move |t| t.map(|t| ticket_fact(t, &l)).map_err(stream_error)), &format)
+                }
+                Err(e) => {
+                    error!("Error fetching tickets for export: {}", e);
+                    return HttpResponse::InternalServerError().body("Error preparing export");
+                }
+            }
+        }
+        "worklogs" => {
+            // Ticket titles and projects for the entries; the team's tickets only.
+            let tickets = match Repo::<Ticket>::across(db, &lookups.scope)
+                .find_docs(doc! {})
+                .projection(doc! { "ticket_id": 1, "title": 1, "project_id": 1 })
+                .await
+            {
+                Ok(c) => c.filter_map(|d| async move { d.ok() }).collect::<Vec<_>>().await,
+                Err(e) => {
+                    error!("Error fetching tickets for export: {}", e);
+                    return HttpResponse::InternalServerError().body("Error preparing export");
+                }
+            };
+            let tickets: HashMap<String, (String, String)> = tickets
+                .iter()
+                .filter_map(|d| Some((d.get_str("ticket_id").ok()?.to_string(), (d.get_str("title").ok()?.to_string(), d.get_str("project_id").ok()?.to_string()))))
+                .collect();
+            let mut filter = doc! { "ticket_id": { "$in": tickets.keys().collect::<Vec<_>>() } };
+            if let Some(since) = since {
+                filter.insert("ended_at", doc! { "$gte": bson::to_bson(&since).unwrap_or_default() });
+            }
+            let cursor = match db.db.collection::<WorklogEntry>("ticket_worklog").find(filter).sort(doc! { "ended_at": 1 }).await {
+                Ok(c) => c,
+                Err(e) => {
+                    error!("Error fetching worklog for export: {}", e);
+                    return HttpResponse::InternalServerError().body("Error preparing export");
+                }
+            };
+            let l = lookups.clone();
+            let rows = cursor.map(move |e| {
+                let e = e.map_err(stream_error)?;
+                let ticket = tickets.get(&e.ticket_id);
+                Ok(WorklogFact {
+                    ticket_title: ticket.map(|(title, _)| title.clone()),
+                    project_id: ticket.map(|(_, p)| p.clone()),
+                    project_name: ticket.and_then(|(_, p)| l.projects.get(p)).cloned(),
+                    user_name: l.users.get(&e.user_id).cloned(),
+                    entry_id: e.entry_id,
+                    ticket_id: e.ticket_id,
+                    user_id: e.user_id,
+                    started_at: e.started_at,
+                    ended_at: e.ended_at,
+                    minutes: e.minutes,
+                    focus_session_id: e.focus_session_id,
+                })
+            });
+            encode(rows, &format)
+        }
+        "sprints" => match sprint_facts(db, &lookups, since).await {
+            Ok(facts) => encode(stream::iter(facts.into_iter().map(Ok)), &format),
+            Err(e) => {
+                error!("Error computing sprint facts: {}", e);
+                return HttpResponse::InternalServerError().body("Error preparing export");
+            }
+        },
+        _ => return HttpResponse::BadRequest().body("dataset must be 'tickets', 'worklogs' or 'sprints'"),
+    };
+
+    let content_type = if format == "csv" { "text/csv; charset=utf-8" } else { NDJSON };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("X-Export-Until", until.to_rfc3339_opts(SecondsFormat::Secs, true)))
+        .insert_header((
+            actix_web::http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.{}\"", query.dataset, format),
+        ))
+        .streaming(body)
+}
@@ -0,0 +1,72 @@
+// src/api/mod.rs
+//
+// Versioned HTTP API. Each version lives in its own module and registers its routes
+// through `configure`; main.rs mounts it under `/api/v{n}`. The routes of v1 are also
+// mounted at the root as deprecated aliases for clients that predate versioning.
+//
+// Clients may send `Accept-Version: <n>`; a version not served at the requested path
+// is answered with 406. Every response carries the version that produced it.
+
+pub mod v1;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+
+pub const V1_PREFIX: &str = "/api/v1";
+
+const ACCEPT_VERSION: HeaderName = HeaderName::from_static("accept-version");
+const API_VERSION: HeaderName = HeaderName::from_static("api-version");
+const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+const LINK: HeaderName = HeaderName::from_static("link");
+const WARNING: HeaderName = HeaderName::from_static("warning");
+
+/// Headers browsers may read from API responses.
+pub const EXPOSED_HEADERS: [HeaderName; 4] = [API_VERSION, DEPRECATION, LINK, WARNING];
+
+/// Rejects requests asking for a version other than `served`.
+fn negotiate(req: &ServiceRequest, served: &str) -> Option<HttpResponse> {
+    let requested = req.headers().get(ACCEPT_VERSION)?.to_str().unwrap_or("").trim();
+    let requested = requested.trim_start_matches(['v', 'V']);
+    (requested != served).then(|| {
+        HttpResponse::NotAcceptable().body(format!("API version {} is not available here; this endpoint serves version {}", requested, served))
+    })
+}
+
+/// Middleware for `/api/v1`.
+pub async fn v1_layer(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if let Some(resp) = negotiate(&req, "1") {
+        return Ok(req.into_response(resp).map_into_right_body());
+    }
+    let mut res = next.call(req).await?;
+    res.headers_mut().insert(API_VERSION, HeaderValue::from_static("1"));
+    Ok(res.map_into_left_body())
+}
+
+/// Middleware for the unversioned aliases of v1: same behaviour, plus headers
+/// pointing clients at the versioned path.
+pub async fn legacy_layer(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if let Some(resp) = negotiate(&req, "1") {
+        return Ok(req.into_response(resp).map_into_right_body());
+    }
+    let successor = format!("{}{}", V1_PREFIX, req.path());
+    let mut res = next.call(req).await?;
+    let headers = res.headers_mut();
+    headers.insert(API_VERSION, HeaderValue::from_static("1"));
+    headers.insert(DEPRECATION, HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor)) {
+        headers.insert(LINK, link);
+    }
+    if let Ok(warning) = HeaderValue::from_str(&format!("299 - \"Deprecated API path, use {}\"", successor)) {
+        headers.insert(WARNING, warning);
+    }
+    Ok(res.map_into_left_body())
+}
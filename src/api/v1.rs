@@ -0,0 +1,476 @@
+// src/api/v1.rs
+//
+// Routes of API version 1, mounted under `/api/v1` (and, deprecated, at the root).
+
+use actix_web::web;
+
+use crate::activity::{get_team_activity, get_project_activity};
+use crate::admin::{check_integrity, force_disconnect, get_ws_stats, repair_integrity};
+use crate::analytics_export::export_analytics;
+use crate::api_logs::get_api_usage;
+use crate::ai_endpoints::{find_duplicates, team_assistant};
+use crate::auth::{login, logout, refresh_token, signup, verify_email, resend_verification, setup_password};
+use crate::board::{
+    list_boards, create_board, update_board, delete_board, add_user_to_board,
+};
+use crate::board_automation::{
+    create_automation, delete_automation, list_automations, test_automation, update_automation,
+};
+use crate::board_transfer::{export_board, import_board};
+use crate::bots::{
+    create_bot, delete_bot, install_bot, list_bots, list_chat_bots, post_bot_message, rotate_bot_key, uninstall_bot, update_bot,
+};
+use crate::calendar::{create_event, get_user_events, get_user_ticket_deadlines};
+use crate::calls::{start_call, get_call, join_call, leave_call, end_call};
+use crate::charts::{get_budget_chart, get_burndown_chart, get_velocity_chart};
+use crate::capacity::get_team_capacity;
+use crate::chat::{
+    get_user_chats, create_chat, search_chats, delete_chat,
+    get_single_chat, update_chat, create_message, get_messages, search_messages,
+    pin_message, unpin_message, set_announcement,
+};
+use crate::chat_attachments::{get_attachment, upload_attachments};
+use crate::chat_export::export_chat;
+use crate::config::Config;
+use crate::custom_emoji::{delete_emoji, get_emoji_image, list_emoji, upload_emoji};
+use crate::dashboard_data::{get_dashboard_data, upsert_dashboard_data};
+use crate::dashboard_layouts::{get_layout, get_layout_data, list_widgets, save_layout};
+use crate::dashboard_report::get_dashboard_report;
+use crate::definition_of_done::{get_definition_of_done, list_done_overrides, update_definition_of_done};
+use crate::dependency_graph::get_dependencies;
+use crate::digest::{get_notification_preferences, unsubscribe_digest, update_notification_preferences};
+use crate::do_not_disturb::{get_dnd_settings, mute_chat, unmute_chat, update_dnd_settings};
+use crate::email_ingest::{configure_email_channel, delete_email_channel, get_email_channel, receive_email};
+use crate::encryption::rotate_encryption;
+use crate::escalation::{create_rule, delete_rule, list_escalations, list_rules, update_rule};
+use crate::focus_sessions::{end_focus_session, get_focus_session, get_focus_stats, start_focus_session};
+use crate::holidays::{create_holiday, delete_holiday, import_holidays, list_holidays, update_holiday};
+use crate::impersonation::{impersonate_user, list_impersonation_audit};
+use crate::invite_codes::{create_invite_code, delete_invite_code, list_invite_codes};
+use crate::knowledge_base::{
+    create_document, delete_document, get_team_documents, semantic_search, share_document, update_document,
+};
+use crate::legal_hold::{list_hold_audit, list_holds, place_hold, release_hold};
+use crate::notification_channels::{create_channel, delete_channel, list_channels, test_channel, update_channel};
+use crate::meeting_notes::{get_meeting_notes_settings, set_event_meeting_notes, update_meeting_notes_settings};
+use crate::member_import::import_members;
+use crate::message_translation::translate_message;
+use crate::out_of_office::{clear_out_of_office, get_out_of_office, set_out_of_office};
+use crate::personal_tasks::{
+    convert_personal_task, create_personal_task, delete_personal_task, get_my_work,
+    list_personal_tasks, update_personal_task,
+};
+use crate::project::{
+    create_project, list_projects, get_project, update_project, delete_project, add_user_to_project,
+    duplicate_project,
+};
+use crate::portfolio::get_portfolio_dashboard;
+use crate::quick_search::quick_search;
+use crate::release::{create_release, get_release, list_releases, update_release};
+use crate::reminders::{cancel_reminder, create_reminder, list_reminders};
+use crate::retention::{get_retention_policy, preview_retention, update_retention_policy};
+use crate::saved_reports::{
+    create_report, delete_report, get_report, get_report_results, list_reports, preview_report, update_report,
+};
+use crate::scheduled_messages::{
+    cancel_scheduled_message, list_my_scheduled_messages, list_scheduled_messages, schedule_message,
+};
+use crate::security_policy::{
+    confirm_device, confirm_device_page, delete_security_policy, get_security_policy, list_devices, revoke_device, set_security_policy,
+};
+use crate::sprint_planning::plan_sprint;
+use crate::sso::{
+    create_sso_connection, delete_scim_token, delete_sso_connection, list_sso_connections, rotate_scim_token, sso_callback,
+    start_sso, update_sso_connection,
+};
+use crate::stale_tickets::{get_stale_settings, get_stale_tickets, update_stale_settings};
+use crate::story_map::{get_story_map, update_story_map};
+use crate::sync::get_changes;
+use crate::team_branding::{update_branding, upload_logo, get_logo};
+use crate::team_feedback::{convert_feedback, list_feedback, list_my_feedback, submit_feedback};
+use crate::team_home::{get_team_home, get_team_home_config, update_team_home};
+use crate::team_management::{
+    create_team, get_team_members, get_user_teams, invite_user,
+    get_team, update_team, delete_team, remove_team_member,
+    accept_invitation, decline_invitation, delete_invitations, get_pending_invitations,
+    create_invite_link, get_invite_link, join_via_invite_link,
+};
+use crate::ticket::{
+    create_ticket, list_tickets, get_ticket, update_ticket, delete_ticket, add_comment, reopen_ticket,
+};
+use crate::ticket_assignment::{get_board_settings, update_board_settings};
+use crate::ticket_events::get_ticket_history;
+use crate::ticket_render::render_ticket;
+use crate::estimation_poker::get_poker_session;
+use crate::ticket_comments::{list_comments, set_comment_resolved, toggle_reaction};
+use crate::ticket_move::move_ticket;
+use crate::ticket_references::get_ticket_references;
+use crate::ticket_votes::{list_voted_tickets, unvote_ticket, vote_ticket};
+use crate::ticket_worklog::get_worklog;
+use crate::ticket_watchers::{list_watchers, unwatch_ticket, watch_ticket};
+use crate::ui_preferences::{get_ui_preferences, update_ui_preferences};
+use crate::user_management::{find_user_email, get_user_by_id, get_working_hours, set_working_hours};
+use crate::user_status::{clear_status, get_user_status, set_status};
+use crate::web_socket_server::ws_index;
+use crate::whiteboard::{
+    create_whiteboard, delete_whiteboard, get_whiteboard, list_whiteboards, update_whiteboard,
+};
+
+pub fn configure(cfg: &mut web::ServiceConfig, config: &Config) {
+    cfg
+        // auth
+        .service(
+            web::scope("/auth")
+                .app_data(web::JsonConfig::default().limit(config.json_limit_auth))
+                .route("/signup", web::post().to(signup))
+                .route("/login", web::post().to(login))
+                .route("/logout", web::post().to(logout))
+                .route("/refresh", web::post().to(refresh_token))
+                .route("/verify/{token}", web::get().to(verify_email))
+                .route("/devices/confirm/{token}", web::get().to(confirm_device_page))
+                .route("/devices/confirm/{token}", web::post().to(confirm_device))
+                .route("/resend-verification", web::post().to(resend_verification))
+                .route("/password-setup/{token}", web::post().to(setup_password))
+                .route("/sso/start", web::post().to(start_sso))
+                .route("/sso/callback", web::post().to(sso_callback))
+        )
+        // teams & related
+        .service(
+            web::scope("/teams")
+                .route("/user_teams/{user_id}", web::get().to(get_user_teams))
+                .route("/user_invitations/{user_id}", web::get().to(get_pending_invitations))
+                .route("", web::post().to(create_team))
+                .service(
+                    web::scope("/{team_id}")
+                        .route("", web::get().to(get_team))
+                        .route("", web::put().to(update_team))
+                        .route("", web::delete().to(delete_team))
+                        .service(
+                            web::scope("/members")
+                                .route("", web::get().to(get_team_members))
+                                .route("", web::post().to(invite_user))
+                                .route("", web::delete().to(remove_team_member))
+                                .route("/import", web::post().to(import_members))
+                        )
+                        .service(
+                            web::scope("/invitations")
+                                .route("/accept", web::post().to(accept_invitation))
+                                .route("/decline", web::post().to(decline_invitation))
+                                .route("", web::delete().to(delete_invitations))
+                        )
+                        .route("/invite_links", web::post().to(create_invite_link))
+                        .route("/activity", web::get().to(get_team_activity))
+                        .route("/capacity", web::get().to(get_team_capacity))
+                        .route("/holidays", web::get().to(list_holidays))
+                        .route("/holidays", web::post().to(create_holiday))
+                        .route("/holidays/import", web::post().to(import_holidays))
+                        .route("/holidays/{holiday_id}", web::put().to(update_holiday))
+                        .route("/holidays/{holiday_id}", web::delete().to(delete_holiday))
+                        .route("/security-policy", web::get().to(get_security_policy))
+                        .route("/security-policy", web::put().to(set_security_policy))
+                        .route("/security-policy", web::delete().to(delete_security_policy))
+                        .route("/retention", web::get().to(get_retention_policy))
+                        .route("/retention", web::put().to(update_retention_policy))
+                        .route("/retention/preview", web::get().to(preview_retention))
+                        .route("/retention/holds", web::get().to(list_holds))
+                        .route("/retention/holds", web::post().to(place_hold))
+                        .route("/retention/holds/audit", web::get().to(list_hold_audit))
+                        .route("/retention/holds/{hold_id}", web::delete().to(release_hold))
+                        .route("/emoji", web::get().to(list_emoji))
+                        .route("/emoji", web::post().to(upload_emoji))
+                        .route("/emoji/{name}", web::get().to(get_emoji_image))
+                        .route("/emoji/{name}", web::delete().to(delete_emoji))
+                        .route("/bots", web::get().to(list_bots))
+                        .route("/bots", web::post().to(create_bot))
+                        .route("/bots/{bot_id}", web::put().to(update_bot))
+                        .route("/bots/{bot_id}", web::delete().to(delete_bot))
+                        .route("/bots/{bot_id}/key", web::post().to(rotate_bot_key))
+                        .route("/dashboard/layout", web::get().to(get_layout))
+                        .route("/dashboard/layout", web::put().to(save_layout))
+                        .route("/dashboard/data", web::get().to(get_layout_data))
+                        .route("/analytics/export", web::get().to(export_analytics))
+                        .route("/dependencies", web::get().to(get_dependencies))
+                        .route("/feedback", web::get().to(list_feedback))
+                        .route("/feedback", web::post().to(submit_feedback))
+                        .route("/feedback/mine", web::get().to(list_my_feedback))
+                        .route("/feedback/{feedback_id}/ticket", web::post().to(convert_feedback))
+                        .route("/home", web::get().to(get_team_home))
+                        .route("/home", web::put().to(update_team_home))
+                        .route("/home/config", web::get().to(get_team_home_config))
+                        .route("/meeting-notes", web::get().to(get_meeting_notes_settings))
+                        .route("/notification-channels", web::get().to(list_channels))
+                        .route("/notification-channels", web::post().to(create_channel))
+                        .route("/notification-channels/{channel_id}", web::put().to(update_channel))
+                        .route("/notification-channels/{channel_id}", web::delete().to(delete_channel))
+                        .route("/notification-channels/{channel_id}/test", web::post().to(test_channel))
+                        .service(
+                            web::scope("/reports")
+                                .route("", web::get().to(list_reports))
+                                .route("", web::post().to(create_report))
+                                .route("/preview", web::post().to(preview_report))
+                                .route("/{report_id}", web::get().to(get_report))
+                                .route("/{report_id}", web::put().to(update_report))
+                                .route("/{report_id}", web::delete().to(delete_report))
+                                .route("/{report_id}/results", web::get().to(get_report_results))
+                        )
+                        .route("/meeting-notes", web::put().to(update_meeting_notes_settings))
+                        .service(
+                            web::scope("/escalations")
+                                .route("", web::get().to(list_escalations))
+                                .route("/rules", web::get().to(list_rules))
+                                .route("/rules", web::post().to(create_rule))
+                                .route("/rules/{rule_id}", web::put().to(update_rule))
+                                .route("/rules/{rule_id}", web::delete().to(delete_rule))
+                        )
+                        .route("/branding", web::put().to(update_branding))
+                        .route("/branding/logo", web::post().to(upload_logo))
+                        .route("/branding/logo", web::get().to(get_logo))
+                        .service(
+                            web::scope("/projects")
+                                .route("", web::post().to(create_project))
+                                .route("", web::get().to(list_projects))
+                                .route("/{project_id}", web::get().to(get_project))
+                                .route("/{project_id}", web::put().to(update_project))
+                                .route("/{project_id}", web::delete().to(delete_project))
+                                .route("/{project_id}/members", web::post().to(add_user_to_project))
+                                .service(
+                                    web::scope("/{project_id}/boards")
+                                        .route("", web::get().to(list_boards))
+                                        .route("", web::post().to(create_board))
+                                        .route("/import", web::post().to(import_board))
+                                        .route("/{board_id}/export", web::get().to(export_board))
+                                        .route("/{board_id}", web::put().to(update_board))
+                                        .route("/{board_id}", web::delete().to(delete_board))
+                                        .route("/{board_id}/members", web::post().to(add_user_to_board))
+                                        .route("/{board_id}/settings", web::get().to(get_board_settings))
+                                        .route("/{board_id}/settings", web::put().to(update_board_settings))
+                                        .route("/{board_id}/definition-of-done", web::get().to(get_definition_of_done))
+                                        .route("/{board_id}/definition-of-done", web::put().to(update_definition_of_done))
+                                        .route("/{board_id}/definition-of-done/overrides", web::get().to(list_done_overrides))
+                                        .route("/{board_id}/charts/burndown.png", web::get().to(get_burndown_chart))
+                                        .route("/{board_id}/charts/velocity.png", web::get().to(get_velocity_chart))
+                                        .route("/{board_id}/automations", web::get().to(list_automations))
+                                        .route("/{board_id}/automations", web::post().to(create_automation))
+                                        .route("/{board_id}/automations/{rule_id}", web::put().to(update_automation))
+                                        .route("/{board_id}/automations/{rule_id}", web::delete().to(delete_automation))
+                                        .route("/{board_id}/automations/{rule_id}/test", web::post().to(test_automation))
+                                )
+                                .service(
+                                    web::scope("/{project_id}/whiteboards")
+                                        .route("", web::get().to(list_whiteboards))
+                                        .route("", web::post().to(create_whiteboard))
+                                        .route("/{whiteboard_id}", web::get().to(get_whiteboard))
+                                        .route("/{whiteboard_id}", web::put().to(update_whiteboard))
+                                        .route("/{whiteboard_id}", web::delete().to(delete_whiteboard))
+                                )
+                                .route("/{project_id}/email-channel", web::get().to(get_email_channel))
+                                .route("/{project_id}/email-channel", web::put().to(configure_email_channel))
+                                .route("/{project_id}/email-channel", web::delete().to(delete_email_channel))
+                                .service(
+                                    web::scope("/{project_id}/releases")
+                                        .route("", web::get().to(list_releases))
+                                        .route("", web::post().to(create_release))
+                                        .route("/{release_id}", web::get().to(get_release))
+                                        .route("/{release_id}", web::put().to(update_release))
+                                )
+                                .route("/{project_id}/story-map", web::get().to(get_story_map))
+                                .route("/{project_id}/story-map", web::put().to(update_story_map))
+                                .service(
+                                    web::scope("/{project_id}/tickets")
+                                        .route("", web::get().to(list_tickets))
+                                        .route("", web::post().to(create_ticket))
+                                        .route("/{ticket_id}", web::get().to(get_ticket))
+                                        .route("/{ticket_id}", web::put().to(update_ticket))
+                                        .route("/{ticket_id}", web::delete().to(delete_ticket))
+                                        .route("/{ticket_id}/reopen", web::post().to(reopen_ticket))
+                                        .route("/{ticket_id}/poker", web::get().to(get_poker_session))
+                                        .route("/{ticket_id}/votes", web::post().to(vote_ticket))
+                                        .route("/{ticket_id}/votes", web::delete().to(unvote_ticket))
+                                        .route("/{ticket_id}/watch", web::put().to(watch_ticket))
+                                        .route("/{ticket_id}/watch", web::delete().to(unwatch_ticket))
+                                        .route("/{ticket_id}/watchers", web::get().to(list_watchers))
+                                        .route("/{ticket_id}/comments", web::get().to(list_comments))
+                                        .route("/{ticket_id}/comments", web::post().to(add_comment))
+                                        .route("/{ticket_id}/comments/{comment_id}/reactions", web::post().to(toggle_reaction))
+                                        .route("/{ticket_id}/comments/{comment_id}/resolved", web::put().to(set_comment_resolved))
+                                )
+                        )
+                )
+        )
+        .service(
+            web::scope("/admin")
+                .route("/ws", web::get().to(get_ws_stats))
+                .route("/ws/disconnect/{user_id}", web::post().to(force_disconnect))
+                .route("/encryption/rotate", web::post().to(rotate_encryption))
+                .route("/impersonate/{user_id}", web::post().to(impersonate_user))
+                .route("/impersonation/audit", web::get().to(list_impersonation_audit))
+                .route("/api-usage", web::get().to(get_api_usage))
+                .route("/integrity", web::get().to(check_integrity))
+                .route("/integrity/repair", web::post().to(repair_integrity))
+                .route("/invite-codes", web::get().to(list_invite_codes))
+                .route("/invite-codes", web::post().to(create_invite_code))
+                .route("/invite-codes/{code}", web::delete().to(delete_invite_code))
+                .route("/sso-connections", web::get().to(list_sso_connections))
+                .route("/sso-connections", web::post().to(create_sso_connection))
+                .route("/sso-connections/{connection_id}", web::put().to(update_sso_connection))
+                .route("/sso-connections/{connection_id}", web::delete().to(delete_sso_connection))
+                .route("/sso-connections/{connection_id}/scim-token", web::post().to(rotate_scim_token))
+                .route("/sso-connections/{connection_id}/scim-token", web::delete().to(delete_scim_token))
+        )
+        .route("/sync", web::get().to(get_changes))
+        .route("/digest/unsubscribe/{token}", web::get().to(unsubscribe_digest))
+        // bot API; authorized by the bot's key rather than a user session
+        .service(
+            web::scope("/bot-api")
+                .route("/chats/{chat_id}/messages", web::post().to(post_bot_message))
+        )
+        // inbound mail provider webhook; authorized by the channel token
+        .service(
+            web::scope("/inbound")
+                .app_data(web::JsonConfig::default().limit(config.json_limit_kb))
+                .route("/email/{token}", web::post().to(receive_email))
+        )
+        // shareable invite links
+        .service(
+            web::scope("/invite")
+                .route("/{token}", web::get().to(get_invite_link))
+                .route("/{token}/join", web::post().to(join_via_invite_link))
+        )
+        .service(
+            web::scope("/projects")
+                .route("/{project_id}/activity", web::get().to(get_project_activity))
+                .route("/{project_id}/duplicate", web::post().to(duplicate_project))
+                .route("/{project_id}/insights/stale", web::get().to(get_stale_tickets))
+                .route("/{project_id}/insights/stale/settings", web::get().to(get_stale_settings))
+                .route("/{project_id}/insights/stale/settings", web::put().to(update_stale_settings))
+        )
+        .service(
+            web::scope("/ai")
+                .route("/tickets/find_duplicates", web::post().to(find_duplicates))
+                .route("/teams/{team_id}/assistant", web::post().to(team_assistant))
+        )
+        .service(
+            web::scope("/boards")
+                .route("/{board_id}/sprints/{sprint_id}/plan", web::post().to(plan_sprint))
+        )
+        .service(
+            web::scope("/tickets")
+                .route("/voted", web::get().to(list_voted_tickets))
+                .route("/{ticket_id}/references", web::get().to(get_ticket_references))
+                .route("/{ticket_id}/history", web::get().to(get_ticket_history))
+                .route("/{ticket_id}/render", web::get().to(render_ticket))
+                .route("/{ticket_id}/worklog", web::get().to(get_worklog))
+                .route("/{ticket_id}/move", web::post().to(move_ticket))
+        )
+        //TEAM-DATA
+        .route("/dashboard/widgets", web::get().to(list_widgets))
+        .route("/portfolio/dashboard", web::get().to(get_portfolio_dashboard))
+        .route("/quick_search", web::get().to(quick_search))
+        .service(
+            web::scope("/team-data")
+                .route("/{team_id}", web::get().to(get_dashboard_data))
+                .route("/{team_id}/report", web::get().to(get_dashboard_report))
+                .route("/{team_id}/charts/budget.png", web::get().to(get_budget_chart))
+                .route("/{team_id}", web::put().to(upsert_dashboard_data))
+        )
+        // chats & messages
+        .service(
+            web::scope("/chats")
+                .route("/{user_id}", web::get().to(get_user_chats))
+                .route("", web::post().to(create_chat))
+                .route("/search/{user_id}", web::get().to(search_chats))
+                .route("/{chat_id}", web::patch().to(update_chat))
+                .route("/{chat_id}", web::delete().to(delete_chat))
+                .route("/get/{chat_id}", web::get().to(get_single_chat))
+                .route("/{chat_id}/pins/{message_id}", web::post().to(pin_message))
+                .route("/{chat_id}/pins/{message_id}", web::delete().to(unpin_message))
+                .route("/{chat_id}/announcement", web::put().to(set_announcement))
+                .route("/{chat_id}/export", web::get().to(export_chat))
+                .route("/{chat_id}/mute", web::put().to(mute_chat))
+                .route("/{chat_id}/mute", web::delete().to(unmute_chat))
+                .route("/{chat_id}/bots", web::get().to(list_chat_bots))
+                .route("/{chat_id}/bots/{bot_id}", web::post().to(install_bot))
+                .route("/{chat_id}/bots/{bot_id}", web::delete().to(uninstall_bot))
+                .route("/{chat_id}/calls", web::post().to(start_call))
+                .route("/{chat_id}/calls/{call_id}", web::get().to(get_call))
+                .route("/{chat_id}/calls/{call_id}/join", web::post().to(join_call))
+                .route("/{chat_id}/calls/{call_id}/leave", web::post().to(leave_call))
+                .route("/{chat_id}/calls/{call_id}/end", web::post().to(end_call))
+        )
+        .service(
+            web::scope("/messages")
+                .route("/{chat_id}", web::get().to(get_messages))
+                .route("/{chat_id}/search", web::get().to(search_messages))
+                .route("/{chat_id}", web::post().to(create_message))
+                .route("/{chat_id}/attachments", web::post().to(upload_attachments))
+                .route("/{chat_id}/scheduled", web::get().to(list_scheduled_messages))
+                .route("/{chat_id}/scheduled", web::post().to(schedule_message))
+                .route("/{chat_id}/scheduled/{scheduled_id}", web::delete().to(cancel_scheduled_message))
+                .route("/{chat_id}/{message_id}/translate", web::post().to(translate_message))
+        )
+        .service(
+            web::scope("/attachments")
+                .route("/{attachment_id}", web::get().to(get_attachment))
+        )
+
+        // users
+        .service(
+            web::scope("/users")
+                .route("/find_user_email", web::get().to(find_user_email))
+                .route("/get/{id}", web::get().to(get_user_by_id))
+                .route("/working-hours", web::get().to(get_working_hours))
+                .route("/working-hours", web::post().to(set_working_hours))
+                .route("/me/notification-preferences", web::get().to(get_notification_preferences))
+                .route("/me/notification-preferences", web::put().to(update_notification_preferences))
+                .route("/me/ui-preferences", web::get().to(get_ui_preferences))
+                .route("/me/ui-preferences", web::put().to(update_ui_preferences))
+                .route("/me/focus", web::get().to(get_focus_session))
+                .route("/me/focus", web::post().to(start_focus_session))
+                .route("/me/focus/end", web::post().to(end_focus_session))
+                .route("/me/focus/stats", web::get().to(get_focus_stats))
+                .route("/me/dnd", web::get().to(get_dnd_settings))
+                .route("/me/dnd", web::put().to(update_dnd_settings))
+                .route("/me/scheduled-messages", web::get().to(list_my_scheduled_messages))
+                .route("/me/reminders", web::get().to(list_reminders))
+                .route("/me/reminders", web::post().to(create_reminder))
+                .route("/me/reminders/{reminder_id}", web::delete().to(cancel_reminder))
+                .route("/me/work", web::get().to(get_my_work))
+                .route("/me/tasks", web::get().to(list_personal_tasks))
+                .route("/me/tasks", web::post().to(create_personal_task))
+                .route("/me/tasks/{task_id}", web::put().to(update_personal_task))
+                .route("/me/tasks/{task_id}", web::delete().to(delete_personal_task))
+                .route("/me/tasks/{task_id}/convert", web::post().to(convert_personal_task))
+                .route("/me/status", web::put().to(set_status))
+                .route("/me/status", web::delete().to(clear_status))
+                .route("/me/out-of-office", web::get().to(get_out_of_office))
+                .route("/me/out-of-office", web::put().to(set_out_of_office))
+                .route("/me/out-of-office", web::delete().to(clear_out_of_office))
+                .route("/me/devices", web::get().to(list_devices))
+                .route("/me/devices/{device_id}", web::delete().to(revoke_device))
+                .route("/{id}/status", web::get().to(get_user_status))
+        )
+
+        // websocket
+        .service(web::resource("/ws").route(web::get().to(ws_index)))
+
+        // calendar
+        .service(
+            web::scope("/calendar")
+                .route("/events", web::post().to(create_event))
+                .route("/events/{user_id}", web::get().to(get_user_events))
+                .route("/events/{event_id}/meeting-notes", web::put().to(set_event_meeting_notes))
+                .route("/tickets/{user_id}", web::get().to(get_user_ticket_deadlines))
+        )
+
+        // knowledge base
+        .service(
+            web::scope("/knowledge_base")
+                .app_data(web::JsonConfig::default().limit(config.json_limit_kb))
+                .route("", web::post().to(create_document))
+                .route("/{team_id}", web::get().to(get_team_documents))
+                .route("/{team_id}/semantic_search", web::get().to(semantic_search))
+                .route("/{doc_id}", web::put().to(update_document))
+                .route("/{doc_id}/sharing", web::put().to(share_document))
+                .route("/{doc_id}", web::delete().to(delete_document))
+        );
+}
@@ -0,0 +1,229 @@
+// src/api_logs.rs
+//
+// Opt-in API usage logging (API_LOGGING=true). The middleware hands one record per
+// request to a bounded channel and returns; a background task batches the records
+// into the capped `api_logs` collection. When the channel is full records are
+// dropped rather than slowing requests down. Admins query the aggregates through
+// GET /admin/api-usage. Handlers that must not leave a trace, such as the anonymous
+// feedback box, mark their request with `Unlogged`.
+
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpRequest, HttpResponse, Responder};
+use futures_util::TryStreamExt;
+use log::{error, info, warn};
+use mongodb::bson::{doc, DateTime as BsonDateTime, Document};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::admin::is_platform_admin;
+use crate::app_state::AppState;
+use crate::chat_db::MongoDB;
+use crate::response::CodedError;
+
+const COLLECTION: &str = "api_logs";
+const CHANNEL_SIZE: usize = 10_000;
+const BATCH_SIZE: usize = 500;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+const TOP_N: i64 = 10;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiLog {
+    pub method: String,
+    /// Matched route pattern, e.g. `/api/v1/teams/{team_id}/capacity`
+    pub route: String,
+    pub user_id: Option<String>,
+    pub team_id: Option<String>,
+    pub status: u16,
+    pub latency_ms: u64,
+    pub at: BsonDateTime,
+}
+
+/// Sending half of the log buffer, kept in AppState when logging is enabled.
+#[derive(Clone)]
+pub struct ApiLogger {
+    tx: mpsc::Sender<ApiLog>,
+}
+
+impl ApiLogger {
+    fn record(&self, entry: ApiLog) {
+        if let Err(mpsc::error::TrySendError::Full(_)) = self.tx.try_send(entry) {
+            warn!("API log buffer full, dropping record");
+        }
+    }
+}
+
+/// Create the capped collection if needed and start the background writer.
+pub async fn start(db: std::sync::Arc<MongoDB>, max_bytes: usize) -> ApiLogger {
+    let exists = db
+        .db
+        .list_collection_names()
+        .filter(doc! { "name": COLLECTION })
+        .await
+        .map(|names| !names.is_empty())
+        .unwrap_or(false);
+    if !exists {
+        if let Err(e) = db.db.create_collection(COLLECTION).capped(true).size(max_bytes as u64).await {
+            error!("Error creating capped {} collection: {}", COLLECTION, e);
+        }
+    }
+
+    let (tx, mut rx) = mpsc::channel::<ApiLog>(CHANNEL_SIZE);
+    actix_web::rt::spawn(async move {
+        let coll = db.db.collection::<ApiLog>(COLLECTION);
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut tick = actix_web::rt::time::interval(FLUSH_INTERVAL);
+        loop {
+            tokio::select! {
+                entry = rx.recv() => match entry {
+                    Some(entry) => {
+                        batch.push(entry);
+                        if batch.len() < BATCH_SIZE {
+                            continue;
+                        }
+                    }
+                    None => break,
+                },
+                _ = tick.tick() => {}
+            }
+            if !batch.is_empty() {
+                if let Err(e) = coll.insert_many(batch.drain(..)).await {
+                    error!("Error writing API logs: {}", e);
+                }
+            }
+        }
+    });
+    info!("API request logging enabled");
+    ApiLogger { tx }
+}
+
+/// Request extension set by handlers whose requests must not be logged.
+#[derive(Debug, Clone, Copy)]
+pub struct Unlogged;
+
+/// Middleware recording every request once its response is ready.
+pub async fn log_layer(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let logger = req.app_data::<web::Data<AppState>>().and_then(|d| d.api_logger.clone());
+    let started = Instant::now();
+    let res = next.call(req).await?;
+    if let Some(logger) = logger {
+        let req = res.request();
+        if req.extensions().contains::<Unlogged>() {
+            return Ok(res);
+        }
+        logger.record(ApiLog {
+            method: req.method().to_string(),
+            route: req.match_pattern().unwrap_or_else(|| "<unmatched>".to_string()),
+            user_id: req.extensions().get::<String>().cloned(),
+            team_id: req.match_info().get("team_id").map(String::from),
+            status: res.status().as_u16(),
+            latency_ms: started.elapsed().as_millis() as u64,
+            at: BsonDateTime::now(),
+        });
+    }
+    Ok(res)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    pub hours: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TeamUsage {
+    pub team_id: String,
+    pub requests: i64,
+    pub users: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EndpointUsage {
+    pub method: String,
+    pub route: String,
+    pub requests: i64,
+    pub avg_ms: f64,
+    pub max_ms: i64,
+    pub server_errors: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiUsage {
+    pub hours: i64,
+    pub most_active_teams: Vec<TeamUsage>,
+    pub slowest_endpoints: Vec<EndpointUsage>,
+}
+
+async fn most_active_teams(db: &MongoDB, since: BsonDateTime) -> mongodb::error::Result<Vec<TeamUsage>> {
+    let pipeline = vec![
+        doc! { "$match": { "at": { "$gte": since }, "team_id": { "$type": "string" } } },
+        doc! { "$group": { "_id": "$team_id", "requests": { "$sum": 1 }, "users": { "$addToSet": "$user_id" } } },
+        doc! { "$sort": { "requests": -1 } },
+        doc! { "$limit": TOP_N },
+        doc! { "$project": { "_id": 0, "team_id": "$_id", "requests": 1, "users": { "$size": "$users" } } },
+    ];
+    let cursor = db.db.collection::<Document>(COLLECTION).aggregate(pipeline).with_type::<TeamUsage>().await?;
+    cursor.try_collect().await
+}
+
+async fn slowest_endpoints(db: &MongoDB, since: BsonDateTime) -> mongodb::error::Result<Vec<EndpointUsage>> {
+    let pipeline = vec![
+        doc! { "$match": { "at": { "$gte": since } } },
+        doc! { "$group": {
+            "_id": { "method": "$method", "route": "$route" },
+            "requests": { "$sum": 1 },
+            "avg_ms": { "$avg": "$latency_ms" },
+            "max_ms": { "$max": "$latency_ms" },
+            "server_errors": { "$sum": { "$cond": [{ "$gte": ["$status", 500] }, 1, 0] } },
+        } },
+        doc! { "$sort": { "avg_ms": -1 } },
+        doc! { "$limit": TOP_N },
+        doc! { "$project": {
+            "_id": 0,
+            "method": "$_id.method",
+            "route": "$_id.route",
+            "requests": 1,
+            "avg_ms": 1,
+            "max_ms": { "$toLong": "$max_ms" },
+            "server_errors": 1,
+        } },
+    ];
+    let cursor = db.db.collection::<Document>(COLLECTION).aggregate(pipeline).with_type::<EndpointUsage>().await?;
+    cursor.try_collect().await
+}
+
+/// GET /admin/api-usage?hours=24
+pub async fn get_api_usage(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<UsageQuery>,
+) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    if data.api_logger.is_none() {
+        return HttpResponse::BadRequest().body("API logging is not enabled");
+    }
+    let hours = query.hours.unwrap_or(24);
+    if !(1..=24 * 31).contains(&hours) {
+        return HttpResponse::BadRequest().body("hours must be between 1 and 744");
+    }
+    let since = BsonDateTime::from_millis(BsonDateTime::now().timestamp_millis() - hours * 3_600_000);
+
+    let teams = most_active_teams(&data.mongodb, since).await;
+    let endpoints = slowest_endpoints(&data.mongodb, since).await;
+    match (teams, endpoints) {
+        (Ok(most_active_teams), Ok(slowest_endpoints)) => {
+            HttpResponse::Ok().json(ApiUsage { hours, most_active_teams, slowest_endpoints })
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Error aggregating API usage: {}", e);
+            HttpResponse::InternalServerError().body("Error aggregating API usage")
+        }
+    }
+}
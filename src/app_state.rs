@@ -1,6 +1,9 @@
+use crate::api_logs::ApiLogger;
 use crate::chat_server::ChatServer;
 use crate::chat_db::MongoDB;
 use crate::config::Config;
+use crate::doc_collab::DocServer;
+use crate::whiteboard::WhiteboardServer;
 use actix::Addr;
 use reqwest::Client;
 use std::sync::Arc;
@@ -8,7 +11,11 @@ use std::sync::Arc;
 #[derive(Clone)]
 pub struct AppState {
     pub chat_server: Addr<ChatServer>,
+    pub doc_server: Addr<DocServer>,
+    pub whiteboard_server: Addr<WhiteboardServer>,
     pub mongodb: Arc<MongoDB>,
     pub config: Config,
     pub http_client: Client,
+    /// Present when API_LOGGING is enabled
+    pub api_logger: Option<ApiLogger>,
 }
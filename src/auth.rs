@@ -1,10 +1,37 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
 use bcrypt::{hash, verify, DEFAULT_COST};
 use chrono::{Utc, Duration};
 use jsonwebtoken::{encode, decode, EncodingKey, DecodingKey, Header, Validation};
-use mongodb::bson::{doc, oid::ObjectId, Document};
+use log::error;
+use mongodb::bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 use crate::app_state::AppState;
+use crate::config::Config;
+use crate::invite_codes;
+use crate::mailer::send_email;
+use crate::response::CodedError;
+use crate::role_claims::{self, RoleClaims};
+use crate::security_policy::client_ip;
+use crate::sso;
+
+/// Cookie carrying the JWT when cookie sessions are enabled.
+pub const SESSION_COOKIE: &str = "session";
+/// Readable cookie holding the CSRF token; clients echo it in `X-CSRF-Token`.
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// Signups per IP address within SIGNUP_WINDOW.
+const SIGNUP_LIMIT: u32 = 5;
+const SIGNUP_WINDOW: std::time::Duration = std::time::Duration::from_secs(3600);
+
+static SIGNUPS: OnceLock<Mutex<HashMap<IpAddr, (Instant, u32)>>> = OnceLock::new();
 
 /// Signup info – team_id is optional so new users can sign up without an existing team.
 #[derive(Serialize, Deserialize, Debug)]
@@ -13,6 +40,9 @@ pub struct SignupInfo {
     pub password: String,
     pub email: String,
     pub team_id: Option<String>,
+    /// Required when SIGNUP_INVITE_ONLY is set
+    #[serde(default)]
+    pub invite_code: Option<String>,
 }
 
 /// Login info
@@ -23,26 +53,190 @@ pub struct LoginInfo {
 }
 
 /// JWT Claims – the sub field now holds the unique user identifier (the MongoDB _id as hex)
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,      // Unique user ID (from MongoDB _id)
     pub team_id: String,  // Will be empty if the user is not yet assigned to a team
     pub exp: usize,
+    /// Issue time; absent on tokens from before it was added
+    #[serde(default)]
+    pub iat: usize,
+    /// CSRF token bound to a cookie session; absent for bearer tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub csrf: Option<String>,
+    /// Admin acting as `sub`; set only on impersonation tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonated_by: Option<String>,
+    /// Team and project roles with JWT_ROLE_CLAIMS; see role_claims.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roles: Option<RoleClaims>,
+}
+
+fn encode_claims(claims: &Claims, secret: &str) -> String {
+    encode(&Header::default(), claims, &EncodingKey::from_secret(secret.as_ref())).unwrap()
 }
 
-/// Create a JWT token from the user_id and team_id
-pub fn create_jwt(user_id: &str, team_id: &str, secret: &str) -> String {
+/// Claims of a new 24-hour session
+fn session_claims(user_id: &str, team_id: &str, roles: Option<RoleClaims>) -> Claims {
     let expiration = Utc::now() + Duration::hours(24);
-    let claims = Claims {
+    Claims {
         sub: user_id.to_string(),
         team_id: team_id.to_string(),
         exp: expiration.timestamp() as usize,
+        iat: Utc::now().timestamp() as usize,
+        csrf: None,
+        impersonated_by: None,
+        roles,
+    }
+}
+
+/// Create a short-lived token acting as `user_id` on behalf of a support admin
+pub fn create_impersonation_jwt(
+    user_id: &str,
+    team_id: &str,
+    admin_id: &str,
+    expires_at: chrono::DateTime<Utc>,
+    secret: &str,
+) -> String {
+    let claims = Claims {
+        sub: user_id.to_string(),
+        team_id: team_id.to_string(),
+        exp: expires_at.timestamp() as usize,
+        iat: Utc::now().timestamp() as usize,
+        csrf: None,
+        impersonated_by: Some(admin_id.to_string()),
+        roles: None,
+    };
+    encode_claims(&claims, secret)
+}
+
+pub(crate) fn same_site(config: &Config) -> SameSite {
+    match config.cookie_same_site.to_lowercase().as_str() {
+        "lax" => SameSite::Lax,
+        "none" => SameSite::None,
+        _ => SameSite::Strict,
+    }
+}
+
+/// Build the session + CSRF cookie pair for a logged-in user.
+fn session_cookies(config: &Config, token: String, csrf: String) -> (Cookie<'static>, Cookie<'static>) {
+    let session = Cookie::build(SESSION_COOKIE, token)
+        .path("/")
+        .http_only(true)
+        .secure(config.cookie_secure)
+        .same_site(same_site(config))
+        .max_age(CookieDuration::hours(24))
+        .finish();
+    let csrf = Cookie::build(CSRF_COOKIE, csrf)
+        .path("/")
+        .http_only(false)
+        .secure(config.cookie_secure)
+        .same_site(same_site(config))
+        .max_age(CookieDuration::hours(24))
+        .finish();
+    (session, csrf)
+}
+
+/// Session cookies when cookie sessions are enabled, a bearer token otherwise.
+fn token_response(data: &AppState, mut claims: Claims) -> HttpResponse {
+    if data.config.cookie_sessions {
+        let csrf = Uuid::new_v4().simple().to_string();
+        claims.csrf = Some(csrf.clone());
+        let token = encode_claims(&claims, &data.config.jwt_secret);
+        let (session, csrf_cookie) = session_cookies(&data.config, token, csrf.clone());
+        return HttpResponse::Ok()
+            .cookie(session)
+            .cookie(csrf_cookie)
+            .json(serde_json::json!({ "csrf_token": csrf }));
+    }
+    claims.csrf = None;
+    let token = encode_claims(&claims, &data.config.jwt_secret);
+    HttpResponse::Ok().json(serde_json::json!({ "token": token }))
+}
+
+/// The user's roles for a token, when role claims are enabled. A failed lookup only
+/// leaves them out; the permission checks then query as usual.
+async fn claimed_roles(data: &AppState, user_id: &str) -> Option<RoleClaims> {
+    if !data.config.jwt_role_claims {
+        return None;
+    }
+    role_claims::load(&data.mongodb, user_id).await.unwrap_or_else(|e| {
+        error!("Error loading role claims for {}: {}", user_id, e);
+        None
+    })
+}
+
+/// The response of a successful login.
+pub(crate) async fn session_response(data: &AppState, user_id: &str, team_id: &str) -> HttpResponse {
+    let roles = claimed_roles(data, user_id).await;
+    token_response(data, session_claims(user_id, team_id, roles))
+}
+
+/// POST /auth/refresh
+/// Reissues the caller's token with current role claims. The session keeps its issue
+/// and expiry times, so refreshing does not extend it.
+pub async fn refresh_token(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    let Some(mut claims) = req.extensions().get::<Claims>().cloned() else {
+        return HttpResponse::Unauthorized().error("unauthorized");
     };
-    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref())).unwrap()
+    if claims.impersonated_by.is_some() {
+        return HttpResponse::Forbidden().body("Impersonation tokens cannot be refreshed");
+    }
+    // A deactivated account's sessions are revoked; this also covers instances that
+    // have not seen the revocation yet.
+    let active = match ObjectId::parse_str(&claims.sub) {
+        Ok(oid) => data.mongodb.db.collection::<Document>("users").find_one(doc! { "_id": oid }).projection(doc! { "active": 1 }).await,
+        Err(_) => Ok(None),
+    };
+    match active {
+        Ok(Some(user)) if user.get_bool("active").unwrap_or(true) => {}
+        Ok(_) => return HttpResponse::Unauthorized().body("Account is deactivated"),
+        Err(e) => {
+            error!("Error fetching user {}: {}", claims.sub, e);
+            return HttpResponse::InternalServerError().body("Error refreshing token");
+        }
+    }
+    claims.roles = claimed_roles(&data, &claims.sub).await;
+    token_response(&data, claims)
+}
+
+fn allow_signup(ip: IpAddr) -> bool {
+    let now = Instant::now();
+    let mut map = SIGNUPS.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+    if map.len() > 10_000 {
+        map.retain(|_, (start, _)| now.duration_since(*start) < SIGNUP_WINDOW);
+    }
+    let entry = map.entry(ip).or_insert((now, 0));
+    if now.duration_since(entry.0) >= SIGNUP_WINDOW {
+        *entry = (now, 0);
+    }
+    entry.1 += 1;
+    entry.1 <= SIGNUP_LIMIT
 }
 
 /// Sign-up endpoint
-pub async fn signup(data: web::Data<AppState>, info: web::Json<SignupInfo>) -> impl Responder {
+pub async fn signup(req: HttpRequest, data: web::Data<AppState>, info: web::Json<SignupInfo>) -> impl Responder {
+    if let Some(ip) = client_ip(&req, &data) {
+        if !allow_signup(ip) {
+            return HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", SIGNUP_WINDOW.as_secs().to_string()))
+                .body("Too many signups from this address; try again later");
+        }
+    }
+    if sso::password_login_disabled(&data.mongodb, &info.email).await {
+        return HttpResponse::Forbidden().body("This email domain uses single sign-on");
+    }
+    let invite_code = if data.config.signup_invite_only {
+        let Some(code) = info.invite_code.as_deref().filter(|c| !c.trim().is_empty()) else {
+            return HttpResponse::Forbidden().body("An invite code is required to sign up");
+        };
+        if let Err(reason) = invite_codes::redeem(&data.mongodb, code).await {
+            return HttpResponse::Forbidden().body(reason);
+        }
+        Some(code.trim().to_uppercase())
+    } else {
+        None
+    };
     // Hash the password
     let hashed_password = match hash(&info.password, DEFAULT_COST) {
         Ok(h) => h,
@@ -58,13 +252,193 @@ pub async fn signup(data: web::Data<AppState>, info: web::Json<SignupInfo>) -> i
         "email": &info.email,
         "password": hashed_password,
         "team_id": team,
+        "email_verified": false,
+        "created_at": BsonDateTime::now(),
+        "invite_code": &invite_code,
+    };
+
+    let users_collection = data.mongodb.db.collection::<Document>("users");
+    let user_id = match users_collection.insert_one(user).await {
+        Ok(res) => match res.inserted_id.as_object_id() {
+            Some(oid) => oid.to_hex(),
+            None => return HttpResponse::InternalServerError().body("Error creating user"),
+        },
+        Err(e) => {
+            if let Some(code) = &invite_code {
+                invite_codes::release(&data.mongodb, code).await;
+            }
+            return HttpResponse::InternalServerError().body(format!("Error creating user: {}", e));
+        }
+    };
+
+    if let Err(e) = send_verification(&data, &user_id, &info.email).await {
+        error!("Error sending verification email to {}: {}", info.email, e);
+    }
+    HttpResponse::Ok().body("User created")
+}
+
+/// Verification token emailed after signup
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailVerification {
+    pub token: String,
+    pub user_id: String,
+    pub email: String,
+    pub expires_at: BsonDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+/// Replace any outstanding token for the user with a fresh one and email the link.
+async fn send_verification(data: &AppState, user_id: &str, email: &str) -> Result<(), String> {
+    let verifications = data.mongodb.db.collection::<EmailVerification>("email_verifications");
+    verifications
+        .delete_many(doc! { "user_id": user_id })
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let verification = EmailVerification {
+        token: Uuid::new_v4().simple().to_string(),
+        user_id: user_id.to_string(),
+        email: email.to_string(),
+        expires_at: BsonDateTime::from_millis((Utc::now() + Duration::hours(48)).timestamp_millis()),
+    };
+    verifications.insert_one(&verification).await.map_err(|e| e.to_string())?;
+
+    let link = format!(
+        "{}{}/auth/verify/{}",
+        data.config.app_base_url.trim_end_matches('/'),
+        crate::api::V1_PREFIX,
+        verification.token
+    );
+    let body = format!("Welcome to Taskline!\n\nConfirm your email address by opening:\n{}\n", link);
+    send_email(data, email, "Verify your email address", &body).await
+}
+
+/// Email verification endpoint
+pub async fn verify_email(data: web::Data<AppState>, token: web::Path<String>) -> impl Responder {
+    let verifications = data.mongodb.db.collection::<EmailVerification>("email_verifications");
+    let verification = match verifications.find_one(doc! { "token": &*token }).await {
+        Ok(Some(v)) => v,
+        Ok(None) => return HttpResponse::NotFound().body("Invalid or already used verification link"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error verifying email: {}", e)),
+    };
+    if verification.expires_at < BsonDateTime::now() {
+        return HttpResponse::Gone().body("Verification link has expired");
+    }
+
+    let oid = match ObjectId::parse_str(&verification.user_id) {
+        Ok(oid) => oid,
+        Err(_) => return HttpResponse::InternalServerError().error("user_id_missing"),
+    };
+    let users_collection = data.mongodb.db.collection::<Document>("users");
+    let update = doc! { "$set": { "email_verified": true, "email_verified_at": BsonDateTime::now() } };
+    if let Err(e) = users_collection
+        .update_one(doc! { "_id": oid, "email": &verification.email }, update)
+        .await
+    {
+        return HttpResponse::InternalServerError().body(format!("Error verifying email: {}", e));
+    }
+    let _ = verifications.delete_many(doc! { "user_id": &verification.user_id }).await;
+    HttpResponse::Ok().body("Email verified")
+}
+
+/// Resend-verification endpoint. Always answers the same way so it can't be used to probe emails.
+pub async fn resend_verification(
+    data: web::Data<AppState>,
+    info: web::Json<ResendVerificationRequest>,
+) -> impl Responder {
+    let users_collection = data.mongodb.db.collection::<Document>("users");
+    if let Ok(Some(user)) = users_collection
+        .find_one(doc! { "email": &info.email, "email_verified": false })
+        .await
+    {
+        if let Ok(oid) = user.get_object_id("_id") {
+            if let Err(e) = send_verification(&data, &oid.to_hex(), &info.email).await {
+                error!("Error resending verification email to {}: {}", info.email, e);
+            }
+        }
+    }
+    HttpResponse::Ok().body("If the account exists and is unverified, a new link has been sent")
+}
+
+/// One-time link that lets a pre-provisioned account choose its first password
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordSetup {
+    pub token: String,
+    pub user_id: String,
+    pub expires_at: BsonDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasswordSetupRequest {
+    pub password: String,
+}
+
+/// Email a password setup link to an account created on the user's behalf.
+pub(crate) async fn send_password_setup(data: &AppState, user_id: &str, email: &str, team_name: &str) -> Result<(), String> {
+    let setups = data.mongodb.db.collection::<PasswordSetup>("password_setups");
+    setups.delete_many(doc! { "user_id": user_id }).await.map_err(|e| e.to_string())?;
+
+    let setup = PasswordSetup {
+        token: Uuid::new_v4().simple().to_string(),
+        user_id: user_id.to_string(),
+        expires_at: BsonDateTime::from_millis((Utc::now() + Duration::days(7)).timestamp_millis()),
     };
+    setups.insert_one(&setup).await.map_err(|e| e.to_string())?;
 
+    let link = format!(
+        "{}{}/auth/password-setup/{}",
+        data.config.app_base_url.trim_end_matches('/'),
+        crate::api::V1_PREFIX,
+        setup.token
+    );
+    let body = format!(
+        "An account has been created for you on Taskline as a member of {}.\n\nChoose your password by opening:\n{}\n\nThe link is valid for 7 days.\n",
+        team_name, link
+    );
+    send_email(data, email, "Set up your Taskline account", &body).await
+}
+
+/// Password setup endpoint for pre-provisioned accounts. Opening the emailed link
+/// also confirms the email address.
+pub async fn setup_password(
+    data: web::Data<AppState>,
+    token: web::Path<String>,
+    info: web::Json<PasswordSetupRequest>,
+) -> impl Responder {
+    if info.password.len() < 8 {
+        return HttpResponse::BadRequest().body("Password must be at least 8 characters");
+    }
+    let setups = data.mongodb.db.collection::<PasswordSetup>("password_setups");
+    let setup = match setups.find_one(doc! { "token": &*token }).await {
+        Ok(Some(s)) => s,
+        Ok(None) => return HttpResponse::NotFound().body("Invalid or already used setup link"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error setting password: {}", e)),
+    };
+    if setup.expires_at < BsonDateTime::now() {
+        return HttpResponse::Gone().body("Setup link has expired");
+    }
+    let oid = match ObjectId::parse_str(&setup.user_id) {
+        Ok(oid) => oid,
+        Err(_) => return HttpResponse::InternalServerError().error("user_id_missing"),
+    };
+    let hashed_password = match hash(&info.password, DEFAULT_COST) {
+        Ok(h) => h,
+        Err(_) => return HttpResponse::InternalServerError().body("Error hashing password"),
+    };
+    let update = doc! {
+        "$set": { "password": hashed_password, "email_verified": true, "email_verified_at": BsonDateTime::now() },
+        "$unset": { "must_reset_password": "" },
+    };
     let users_collection = data.mongodb.db.collection::<Document>("users");
-    match users_collection.insert_one(user).await {
-        Ok(_) => HttpResponse::Ok().body("User created"),
-        Err(e) => HttpResponse::InternalServerError().body(format!("Error creating user: {}", e)),
+    if let Err(e) = users_collection.update_one(doc! { "_id": oid }, update).await {
+        return HttpResponse::InternalServerError().body(format!("Error setting password: {}", e));
     }
+    let _ = setups.delete_many(doc! { "user_id": &setup.user_id }).await;
+    HttpResponse::Ok().body("Password set")
 }
 
 /// Login endpoint
@@ -73,25 +447,49 @@ pub async fn login(data: web::Data<AppState>, info: web::Json<LoginInfo>) -> imp
 
     match users_collection.find_one(doc! { "username": &info.username }).await {
         Ok(Some(user)) => {
+            if let Ok(false) = user.get_bool("active") {
+                return HttpResponse::Forbidden().body("Account deactivated");
+            }
+            if sso::password_login_disabled(&data.mongodb, user.get_str("email").unwrap_or("")).await {
+                return HttpResponse::Forbidden().body("Password login is disabled for this domain; sign in with single sign-on");
+            }
+            if let Ok(true) = user.get_bool("must_reset_password") {
+                return HttpResponse::Forbidden().body("Password not set yet; use the setup link sent to your email");
+            }
             let password_hash = match user.get_str("password") {
                 Ok(p) => p,
                 Err(_) => return HttpResponse::InternalServerError().body("Password missing"),
             };
 
             if verify(&info.password, password_hash).unwrap_or(false) {
+                // Legacy accounts have no email_verified field and are treated as verified.
+                if let Ok(false) = user.get_bool("email_verified") {
+                    let grace_ms = data.config.email_verification_grace_hours * 3_600_000;
+                    let created_ms = user.get_datetime("created_at").map(|d| d.timestamp_millis()).unwrap_or(0);
+                    if Utc::now().timestamp_millis() > created_ms + grace_ms {
+                        return HttpResponse::Forbidden().body("Email address not verified");
+                    }
+                }
                 // Use the MongoDB _id as the unique user id (converted to a hex string)
                 let user_id = match user.get_object_id("_id") {
                     Ok(oid) => oid.to_hex(),
-                    Err(_) => return HttpResponse::InternalServerError().body("User ID missing"),
+                    Err(_) => return HttpResponse::InternalServerError().error("user_id_missing"),
                 };
                 // Retrieve team_id; if missing, default to empty string
                 let team_id = user.get_str("team_id").unwrap_or("").to_string();
-                let token = create_jwt(&user_id, &team_id, &data.config.jwt_secret);
-                HttpResponse::Ok().json(serde_json::json!({ "token": token }))
+                session_response(&data, &user_id, &team_id).await
             } else {
                 HttpResponse::Unauthorized().body("Invalid credentials")
             }
         }
-        _ => HttpResponse::Unauthorized().body("User not found"),
+        _ => HttpResponse::Unauthorized().error("user_not_found"),
     }
 }
+
+/// Logout endpoint – clears the session cookies (no-op for bearer tokens)
+pub async fn logout(data: web::Data<AppState>) -> impl Responder {
+    let (mut session, mut csrf) = session_cookies(&data.config, String::new(), String::new());
+    session.make_removal();
+    csrf.make_removal();
+    HttpResponse::Ok().cookie(session).cookie(csrf).body("Logged out")
+}
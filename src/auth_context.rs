@@ -0,0 +1,142 @@
+// src/auth_context.rs
+//
+// Request-scoped view of the authenticated caller. The middleware stores one per
+// request; handlers take it as an extractor instead of reading the raw user id out
+// of the extensions, and membership lookups are memoized for the request's lifetime.
+// When the token carries current role claims (see role_claims.rs) they answer the
+// lookups instead of the database.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use actix_web::{dev::Payload, error::InternalError, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use futures::future::{ready, Ready};
+use mongodb::bson::{doc, Document};
+
+use crate::chat_db::MongoDB;
+use crate::response::CodedError;
+use crate::role_claims::RoleClaims;
+
+#[derive(Clone)]
+pub struct AuthContext {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    user_id: String,
+    db: Arc<MongoDB>,
+    /// team_id -> role ("admin"/"member"), None when not a member
+    team_roles: RefCell<HashMap<String, Option<String>>>,
+    /// project_id -> role ("owner"/"member"/...), None when not a member
+    project_roles: RefCell<HashMap<String, Option<String>>>,
+    /// All of the caller's memberships, from the token
+    claimed: Option<RoleClaims>,
+}
+
+impl AuthContext {
+    /// `claimed` must hold every membership of the user; roles it lacks are treated
+    /// as no membership.
+    pub fn new(user_id: String, db: Arc<MongoDB>, claimed: Option<RoleClaims>) -> Self {
+        AuthContext {
+            inner: Rc::new(Inner {
+                user_id,
+                db,
+                team_roles: RefCell::new(HashMap::new()),
+                project_roles: RefCell::new(HashMap::new()),
+                claimed,
+            }),
+        }
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.inner.user_id
+    }
+
+    pub(crate) fn db(&self) -> &MongoDB {
+        &self.inner.db
+    }
+
+    /// The caller's role in the team, or None when they are not a member.
+    pub async fn team_role(&self, team_id: &str) -> Option<String> {
+        if let Some(role) = self.inner.team_roles.borrow().get(team_id) {
+            return role.clone();
+        }
+        if let Some(claimed) = &self.inner.claimed {
+            return claimed.teams.get(team_id).cloned();
+        }
+        let role = self
+            .inner
+            .db
+            .db
+            .collection::<Document>("user_teams")
+            .find_one(doc! { "team_id": team_id, "user_id": &self.inner.user_id })
+            .await
+            .ok()
+            .flatten()
+            .map(|m| m.get_str("role").unwrap_or("member").to_string());
+        self.inner.team_roles.borrow_mut().insert(team_id.to_string(), role.clone());
+        role
+    }
+
+    pub async fn is_team_member(&self, team_id: &str) -> bool {
+        self.team_role(team_id).await.is_some()
+    }
+
+    pub async fn is_team_admin(&self, team_id: &str) -> bool {
+        self.team_role(team_id).await.as_deref() == Some("admin")
+    }
+
+    /// Every team the caller is a member of.
+    pub async fn team_ids(&self) -> mongodb::error::Result<Vec<String>> {
+        if let Some(claimed) = &self.inner.claimed {
+            return Ok(claimed.teams.keys().cloned().collect());
+        }
+        self.inner.db.user_team_ids(&self.inner.user_id).await
+    }
+
+    /// The caller's role in the project, or None when they are not a member.
+    pub async fn project_role(&self, project_id: &str) -> Option<String> {
+        if let Some(role) = self.inner.project_roles.borrow().get(project_id) {
+            return role.clone();
+        }
+        if let Some(claimed) = &self.inner.claimed {
+            return claimed.projects.get(project_id).cloned();
+        }
+        let role = self
+            .inner
+            .db
+            .db
+            .collection::<Document>("project_memberships")
+            .find_one(doc! { "project_id": project_id, "user_id": &self.inner.user_id })
+            .await
+            .ok()
+            .flatten()
+            .map(|m| m.get_str("role").unwrap_or("member").to_string());
+        self.inner.project_roles.borrow_mut().insert(project_id.to_string(), role.clone());
+        role
+    }
+
+    pub async fn is_project_member(&self, project_id: &str) -> bool {
+        self.project_role(project_id).await.is_some()
+    }
+
+    pub async fn is_project_owner(&self, project_id: &str) -> bool {
+        self.project_role(project_id).await.as_deref() == Some("owner")
+    }
+}
+
+impl FromRequest for AuthContext {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<AuthContext>()
+                .cloned()
+                .ok_or_else(|| InternalError::from_response("Unauthorized", HttpResponse::Unauthorized().error("unauthorized")).into()),
+        )
+    }
+}
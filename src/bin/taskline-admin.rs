@@ -0,0 +1,300 @@
+// src/bin/taskline-admin.rs
+//
+// Operational tasks that do not go through the HTTP API. Reads the same environment
+// (.env, MONGO_URI, ...) as the server and shares its configuration, database setup
+// and migrations by including those modules directly.
+//
+//     taskline-admin create-admin <email> <username>
+//     taskline-admin rotate-jwt-secret [--env-file <path>]
+//     taskline-admin migrate [--dry-run]
+//     taskline-admin rebuild-indexes
+//     taskline-admin export-team <team_id> <out_dir>
+//     taskline-admin verify [--repair]
+
+#[allow(dead_code)]
+#[path = "../config.rs"]
+mod config;
+#[allow(dead_code)]
+#[path = "../db_pool.rs"]
+mod db_pool;
+#[allow(dead_code)]
+#[path = "../chat_db.rs"]
+mod chat_db;
+#[path = "../integrity.rs"]
+mod integrity;
+#[path = "../migrations.rs"]
+mod migrations;
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use futures_util::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, Bson, DateTime as BsonDateTime, Document};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use chat_db::MongoDB;
+use config::Config;
+
+const USAGE: &str = "Usage: taskline-admin <command>
+
+Commands:
+  create-admin <email> <username>        Create a verified user to add to ADMIN_USER_IDS;
+                                         the password is read from TASKLINE_ADMIN_PASSWORD or stdin
+  rotate-jwt-secret [--env-file <path>]  Generate a new JWT_SECRET; with --env-file, write it and
+                                         keep the old one in JWT_PREVIOUS_SECRETS
+  migrate [--dry-run]                    Apply pending data migrations
+  rebuild-indexes                        Create any missing indexes
+  export-team <team_id> <out_dir>        Write the team's data as one NDJSON file per collection
+  verify [--repair]                      Report references to missing teams, projects, boards, chats
+                                         and users; with --repair, remove those that can be removed";
+
+/// Collections exported by `export-team` that carry a `team_id`. Email channels and SSO
+/// connections are deliberately left out, as they hold secrets.
+const TEAM_COLLECTIONS: [&str; 17] = [
+    "teams",
+    "user_teams",
+    "team_invitations",
+    "team_invite_links",
+    "projects",
+    "chats",
+    "knowledge_base",
+    "activity_events",
+    "retention_policies",
+    "team_security_policies",
+    "team_holidays",
+    "custom_emoji",
+    "dashboard_layouts",
+    "escalation_rules",
+    "legal_holds",
+    "automation_rules",
+    "calendar_events",
+];
+/// Collections exported for each of the team's projects, by `project_id`.
+const PROJECT_COLLECTIONS: [&str; 6] = ["project_memberships", "boards", "tickets", "releases", "whiteboards", "stale_settings"];
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args.as_slice() {
+        ["rotate-jwt-secret"] => rotate_jwt_secret(None),
+        ["rotate-jwt-secret", "--env-file", path] => rotate_jwt_secret(Some(Path::new(path))),
+        ["create-admin", email, username] => create_admin(&connect().await, email, username).await,
+        ["migrate"] => migrate(&connect().await, false).await,
+        ["migrate", "--dry-run"] => migrate(&connect().await, true).await,
+        ["rebuild-indexes"] => connect().await.ensure_indexes().await.map(|_| println!("Indexes are up to date")).map_err(|e| e.to_string()),
+        ["export-team", team_id, out_dir] => export_team(&connect().await, team_id, Path::new(out_dir)).await,
+        ["verify"] => verify(&connect().await, false).await,
+        ["verify", "--repair"] => verify(&connect().await, true).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn connect() -> MongoDB {
+    let config = Config::from_env();
+    MongoDB::init(&config).await
+}
+
+async fn create_admin(db: &MongoDB, email: &str, username: &str) -> Result<(), String> {
+    let users = db.db.collection::<Document>("users");
+    let taken = users
+        .find_one(doc! { "$or": [{ "email": email }, { "username": username }] })
+        .await
+        .map_err(|e| e.to_string())?;
+    if taken.is_some() {
+        return Err("a user with this email or username already exists".to_string());
+    }
+    let password = match env::var("TASKLINE_ADMIN_PASSWORD") {
+        Ok(p) => p,
+        Err(_) => {
+            eprint!("Password: ");
+            io::stderr().flush().ok();
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line).map_err(|e| e.to_string())?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    if password.len() < 12 {
+        return Err("the password must be at least 12 characters".to_string());
+    }
+    let hashed = bcrypt::hash(&password, bcrypt::DEFAULT_COST).map_err(|e| e.to_string())?;
+    let user = doc! {
+        "username": username,
+        "email": email,
+        "password": hashed,
+        "team_id": "",
+        "email_verified": true,
+        "created_at": BsonDateTime::now(),
+    };
+    let res = users.insert_one(user).await.map_err(|e| e.to_string())?;
+    let user_id = res.inserted_id.as_object_id().map(|o| o.to_hex()).unwrap_or_default();
+    let mut admins: Vec<String> = env::var("ADMIN_USER_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    admins.push(user_id.clone());
+    println!("Created user {} ({})", username, user_id);
+    println!("Grant admin access by setting ADMIN_USER_IDS={} and restarting the server", admins.join(","));
+    Ok(())
+}
+
+fn rotate_jwt_secret(env_file: Option<&Path>) -> Result<(), String> {
+    let mut bytes = [0u8; 48];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| "could not generate a random secret".to_string())?;
+    let secret = URL_SAFE_NO_PAD.encode(bytes);
+
+    let Some(path) = env_file else {
+        println!("JWT_SECRET={}", secret);
+        println!("Move the current secret to JWT_PREVIOUS_SECRETS so issued tokens stay valid until they expire.");
+        return Ok(());
+    };
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut previous = None;
+    let mut lines: Vec<String> = contents
+        .lines()
+        .filter_map(|line| {
+            if let Some(old) = line.strip_prefix("JWT_SECRET=") {
+                previous = Some(old.to_string());
+                return None;
+            }
+            (!line.starts_with("JWT_PREVIOUS_SECRETS=")).then(|| line.to_string())
+        })
+        .collect();
+    lines.push(format!("JWT_SECRET={}", secret));
+    // Tokens live for a day, so only the secret being replaced needs to keep working.
+    if let Some(old) = previous.filter(|s| !s.is_empty()) {
+        lines.push(format!("JWT_PREVIOUS_SECRETS={}", old));
+    }
+    fs::write(path, lines.join("\n") + "\n").map_err(|e| format!("{}: {}", path.display(), e))?;
+    println!("Wrote a new JWT_SECRET to {}; restart the server to use it", path.display());
+    Ok(())
+}
+
+async fn migrate(db: &MongoDB, dry_run: bool) -> Result<(), String> {
+    let pending = migrations::pending(db).await.map_err(|e| e.to_string())?;
+    if pending.is_empty() {
+        println!("No pending migrations");
+        return Ok(());
+    }
+    for m in pending {
+        if dry_run {
+            println!("pending  {}  {}", m.id, m.description);
+            continue;
+        }
+        let changed = migrations::apply(db, m).await.map_err(|e| format!("{}: {}", m.id, e))?;
+        println!("applied  {}  ({} documents changed)", m.id, changed);
+    }
+    Ok(())
+}
+
+/// Streams the documents matching `filter` into `<out_dir>/<name>.ndjson` as relaxed
+/// extended JSON. Returns the number of documents written.
+async fn export_collection(
+    db: &MongoDB,
+    collection: &str,
+    filter: Document,
+    projection: Option<Document>,
+    out_dir: &Path,
+    name: &str,
+) -> Result<u64, String> {
+    let file = fs::File::create(out_dir.join(format!("{}.ndjson", name))).map_err(|e| e.to_string())?;
+    let mut out = BufWriter::new(file);
+    let mut cursor = db
+        .db
+        .collection::<Document>(collection)
+        .find(filter)
+        .projection(projection.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut count = 0;
+    while let Some(d) = cursor.next().await {
+        let json = Bson::Document(d.map_err(|e| e.to_string())?).into_relaxed_extjson();
+        writeln!(out, "{}", json).map_err(|e| e.to_string())?;
+        count += 1;
+    }
+    out.flush().map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+async fn distinct_strings(db: &MongoDB, collection: &str, field: &str, filter: Document) -> Result<Vec<String>, String> {
+    let values = db.db.collection::<Document>(collection).distinct(field, filter).await.map_err(|e| e.to_string())?;
+    Ok(values.into_iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+}
+
+async fn export_team(db: &MongoDB, team_id: &str, out_dir: &Path) -> Result<(), String> {
+    let team = db.db.collection::<Document>("teams").find_one(doc! { "team_id": team_id }).await.map_err(|e| e.to_string())?;
+    if team.is_none() {
+        return Err(format!("team {} not found", team_id));
+    }
+    fs::create_dir_all(out_dir).map_err(|e| format!("{}: {}", out_dir.display(), e))?;
+
+    let mut report = Vec::new();
+    for coll in TEAM_COLLECTIONS {
+        let n = export_collection(db, coll, doc! { "team_id": team_id }, None, out_dir, coll).await?;
+        report.push((coll, n));
+    }
+    let project_ids = distinct_strings(db, "projects", "project_id", doc! { "team_id": team_id }).await?;
+    for coll in PROJECT_COLLECTIONS {
+        let n = export_collection(db, coll, doc! { "project_id": { "$in": &project_ids } }, None, out_dir, coll).await?;
+        report.push((coll, n));
+    }
+    let chat_ids = distinct_strings(db, "chats", "_id", doc! { "team_id": team_id }).await?;
+    let n = export_collection(db, "messages", doc! { "id_chat": { "$in": &chat_ids } }, None, out_dir, "messages").await?;
+    report.push(("messages", n));
+
+    let member_ids: Vec<ObjectId> = distinct_strings(db, "user_teams", "user_id", doc! { "team_id": team_id })
+        .await?
+        .iter()
+        .filter_map(|id| ObjectId::parse_str(id).ok())
+        .collect();
+    let profile = doc! { "username": 1, "email": 1, "created_at": 1, "active": 1 };
+    let n = export_collection(db, "users", doc! { "_id": { "$in": member_ids } }, Some(profile), out_dir, "users").await?;
+    report.push(("users", n));
+
+    for (coll, n) in report {
+        println!("{:>8}  {}", n, coll);
+    }
+    println!("Exported team {} to {}", team_id, out_dir.display());
+    Ok(())
+}
+
+async fn verify(db: &MongoDB, repair: bool) -> Result<(), String> {
+    let report = if repair { integrity::repair(db).await } else { integrity::scan(db).await }.map_err(|e| e.to_string())?;
+    for f in &report.findings {
+        if f.count == 0 {
+            println!("ok        {}", f.check);
+        } else if f.repaired > 0 {
+            println!("{:<8}  {} missing, {} repaired", f.count, f.check, f.repaired);
+        } else {
+            let hint = if f.repairable { "" } else { " (report only)" };
+            println!("{:<8}  {} missing, e.g. {}{}", f.count, f.check, f.sample.join(", "), hint);
+        }
+    }
+    // Unlike POST /admin/integrity/repair, this cannot publish deleted tickets to sync,
+    // which lives in the server; offline clients keep them until their next bootstrap.
+    let remaining = report.remaining();
+    if remaining > 0 {
+        return Err(format!("{} documents reference missing data", remaining));
+    }
+    println!("No integrity problems found");
+    Ok(())
+}
@@ -1,5 +1,5 @@
 // src/board.rs
-use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use actix_web::{web, HttpResponse, Responder};
 use futures_util::StreamExt;
 use mongodb::bson::{doc, to_document};
 use serde::{Deserialize, Serialize};
@@ -7,7 +7,13 @@ use uuid::Uuid;
 use chrono::Utc;
 use log::{error, info};
 
+use crate::activity::{record_activity, ActivityEvent};
 use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::response::{CodedError, ok, ok_message};
+use crate::tenancy::{ProjectScope, Repo};
+use crate::definition_of_done::DefinitionOfDone;
+use crate::ticket_assignment::AutoAssignRule;
 
 /// The Board model, now with embedded participants.
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,8 +27,20 @@ pub struct Board {
     pub created_at: chrono::DateTime<Utc>,
     pub created_by: String,
     pub participants: Vec<String>,   // ✅ new field
+    /// Ordered column (status) names; empty for boards created before columns existed
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// Rule picking an assignee for tickets created without one
+    #[serde(default)]
+    pub auto_assign: Option<AutoAssignRule>,
+    /// Conditions for moving tickets to a closed status
+    #[serde(default)]
+    pub definition_of_done: Option<DefinitionOfDone>,
 }
 
+/// Sprint lengths a board may set, in weeks
+pub(crate) const SPRINT_WEEKS: std::ops::RangeInclusive<i32> = 1..=8;
+
 /// Request payload for creating/updating a Board
 #[derive(Debug, Deserialize)]
 pub struct CreateOrUpdateBoardRequest {
@@ -30,6 +48,7 @@ pub struct CreateOrUpdateBoardRequest {
     pub description: Option<String>,
     pub board_type: String,
     pub sprint_length: Option<i32>,
+    pub columns: Option<Vec<String>>,
 }
 
 /// Request payload for adding a user to a board
@@ -38,57 +57,62 @@ pub struct AddUserToBoardRequest {
     pub user_id: String,
 }
 
+/// Project members and the board's participants may edit it.
+pub(crate) async fn can_edit_board(auth: &AuthContext, board: &Board) -> bool {
+    board.participants.iter().any(|p| p == auth.user_id()) || auth.is_project_member(&board.project_id).await
+}
+
+/// Only the project owner or the board's creator may delete it.
+async fn can_delete_board(auth: &AuthContext, board: &Board) -> bool {
+    board.created_by == auth.user_id() || auth.is_project_owner(&board.project_id).await
+}
+
+pub(crate) async fn find_board(data: &AppState, scope: &ProjectScope, board_id: &str) -> Result<Board, HttpResponse> {
+    match Repo::<Board>::new(&data.mongodb, scope).find_one(doc! { "board_id": board_id }).await {
+        Ok(Some(b)) => Ok(b),
+        Ok(None) => Err(HttpResponse::NotFound().error("board_not_found")),
+        Err(e) => {
+            error!("Error fetching board: {}", e);
+            Err(HttpResponse::InternalServerError().body("Error fetching board"))
+        }
+    }
+}
+
 /// GET /teams/{team_id}/projects/{project_id}/boards
 /// List all boards for a project.
 pub async fn list_boards(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     path: web::Path<(String, String)>,
 ) -> impl Responder {
     let (team_id, project_id) = path.into_inner();
-    let current_user = if let Some(uid) = req.extensions().get::<String>() {
-        uid.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
 
-    // 1) Must be on the team
-    let user_teams = data.mongodb.db.collection::<mongodb::bson::Document>("user_teams");
-    if user_teams
-        .find_one(doc! { "team_id": &team_id, "user_id": &current_user })
-        .await
-        .ok()
-        .flatten()
-        .is_none()
-    {
-        return HttpResponse::Unauthorized().body("Not a member of this team");
-    }
+    // 1) Must be on the team that owns the project
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
 
     // 2) Must be a project member OR a board participant
-    let project_memberships = data.mongodb.db.collection::<mongodb::bson::Document>("project_memberships");
-    let is_proj_member = project_memberships
-        .find_one(doc! { "project_id": &project_id, "user_id": &current_user })
-        .await
-        .ok()
-        .flatten()
-        .is_some();
+    let is_proj_member = auth.is_project_member(&project_id).await;
 
-    let boards_coll = data.mongodb.db.collection::<Board>("boards");
+    let boards_coll = Repo::<Board>::new(&data.mongodb, &scope);
     if !is_proj_member {
         // if not in project, check board‐level participation
         if boards_coll
-            .find_one(doc! { "project_id": &project_id, "participants": &current_user })
+            .find_one(doc! { "participants": &current_user })
             .await
             .ok()
             .flatten()
             .is_none()
         {
-            return HttpResponse::Unauthorized().body("Not a member of this project or board");
+            return HttpResponse::Unauthorized().error("not_project_or_board_member");
         }
     }
 
     // 3) Fetch and return boards
-    let mut cursor = match boards_coll.find(doc! { "project_id": &project_id }).await {
+    let mut cursor = match boards_coll.find(doc! {}).await {
         Ok(c) => c,
         Err(e) => {
             error!("Error finding boards: {}", e);
@@ -107,25 +131,30 @@ pub async fn list_boards(
         }
     }
 
-    HttpResponse::Ok().json(boards)
+    ok(boards)
 }
 
 /// POST /teams/{team_id}/projects/{project_id}/boards
 /// Create a new board for a project.
 pub async fn create_board(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     path: web::Path<(String, String)>,
     payload: web::Json<CreateOrUpdateBoardRequest>,
 ) -> impl Responder {
     let (team_id, project_id) = path.into_inner();
-    let current_user = if let Some(uid) = req.extensions().get::<String>() {
-        uid.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
+    if matches!(payload.sprint_length, Some(w) if !SPRINT_WEEKS.contains(&w)) {
+        return HttpResponse::BadRequest().body("sprint_length must be between 1 and 8 weeks");
+    }
 
-    // same team/project membership checks as above...
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    if !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
 
     // seed participants with creator
     let new_board = Board {
@@ -138,13 +167,23 @@ pub async fn create_board(
         created_at: Utc::now(),
         created_by: current_user.clone(),
         participants: vec![current_user.clone()], // ✅ include creator
+        columns: payload.columns.clone().unwrap_or_default(),
+        auto_assign: None,
+        definition_of_done: None,
     };
 
-    let boards_coll = data.mongodb.db.collection::<Board>("boards");
-    match boards_coll.insert_one(&new_board).await {
+    match Repo::<Board>::new(&data.mongodb, &scope).insert_one(&new_board).await {
         Ok(_) => {
             info!("Board created: {:?}", new_board.board_id);
-            HttpResponse::Ok().json(new_board)
+            record_activity(&data.mongodb, ActivityEvent::new(
+                &team_id,
+                Some(&new_board.project_id),
+                &current_user,
+                "board_created",
+                &new_board.board_id,
+                format!("created board \"{}\"", new_board.name),
+            )).await;
+            ok(new_board)
         },
         Err(e) => {
             error!("Error inserting board: {}", e);
@@ -156,22 +195,29 @@ pub async fn create_board(
 /// PUT /teams/{team_id}/projects/{project_id}/boards/{board_id}
 /// Update an existing board’s metadata.
 pub async fn update_board(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     path: web::Path<(String, String, String)>,
     payload: web::Json<CreateOrUpdateBoardRequest>,
 ) -> impl Responder {
     let (team_id, project_id, board_id) = path.into_inner();
-    let current_user = if let Some(uid) = req.extensions().get::<String>() {
-        uid.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
+    if matches!(payload.sprint_length, Some(w) if !SPRINT_WEEKS.contains(&w)) {
+        return HttpResponse::BadRequest().body("sprint_length must be between 1 and 8 weeks");
+    }
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
     };
+    let board = match find_board(&data, &scope, &board_id).await {
+        Ok(b) => b,
+        Err(resp) => return resp,
+    };
+    if !can_edit_board(&auth, &board).await {
+        return HttpResponse::Unauthorized().error("not_project_or_board_member");
+    }
 
-    // membership checks...
-
-    let boards_coll = data.mongodb.db.collection::<Board>("boards");
-    let filter = doc! { "board_id": &board_id, "project_id": &project_id };
+    let boards_coll = Repo::<Board>::new(&data.mongodb, &scope);
+    let filter = doc! { "board_id": &board_id };
 
     let mut update_doc = doc! {
         "name": &payload.name,
@@ -184,11 +230,14 @@ pub async fn update_board(
         None
     };
     update_doc.insert("sprint_length", sprint_val);
+    if let Some(columns) = &payload.columns {
+        update_doc.insert("columns", columns);
+    }
 
     let update_op = doc! { "$set": update_doc };
     match boards_coll.update_one(filter, update_op).await {
-        Ok(res) if res.matched_count == 1 => HttpResponse::Ok().body("Board updated"),
-        Ok(_) => HttpResponse::NotFound().body("Board not found"),
+        Ok(res) if res.matched_count == 1 => ok_message("Board updated"),
+        Ok(_) => HttpResponse::NotFound().error("board_not_found"),
         Err(e) => {
             error!("Error updating board: {}", e);
             HttpResponse::InternalServerError().body("Error updating board")
@@ -198,23 +247,26 @@ pub async fn update_board(
 
 /// DELETE /teams/{team_id}/projects/{project_id}/boards/{board_id}
 pub async fn delete_board(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     path: web::Path<(String, String, String)>,
 ) -> impl Responder {
     let (team_id, project_id, board_id) = path.into_inner();
-    let current_user = if let Some(uid) = req.extensions().get::<String>() {
-        uid.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
     };
+    let board = match find_board(&data, &scope, &board_id).await {
+        Ok(b) => b,
+        Err(resp) => return resp,
+    };
+    if !can_delete_board(&auth, &board).await {
+        return HttpResponse::Forbidden().body("Only the project owner or the board's creator can delete it");
+    }
 
-    // membership checks...
-
-    let boards_coll = data.mongodb.db.collection::<Board>("boards");
-    let filter = doc! { "board_id": &board_id, "project_id": &project_id };
-    match boards_coll.delete_one(filter).await {
-        Ok(res) if res.deleted_count == 1 => HttpResponse::Ok().body("Board deleted"),
+    let boards_coll = Repo::<Board>::new(&data.mongodb, &scope);
+    match boards_coll.delete_one(doc! { "board_id": &board_id }).await {
+        Ok(res) if res.deleted_count == 1 => ok_message("Board deleted"),
         Ok(_) => HttpResponse::NotFound().body("Board not found or already deleted"),
         Err(e) => {
             error!("Error deleting board: {}", e);
@@ -226,43 +278,43 @@ pub async fn delete_board(
 /// POST /teams/{team_id}/projects/{project_id}/boards/{board_id}/members
 /// Add an existing project user to a board.
 pub async fn add_user_to_board(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     path: web::Path<(String, String, String)>,
     payload: web::Json<AddUserToBoardRequest>,
 ) -> impl Responder {
     let (team_id, project_id, board_id) = path.into_inner();
-    let current_user = if let Some(uid) = req.extensions().get::<String>() {
-        uid.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
 
-    // 1) Caller must be a team member.
-    let user_teams = data.mongodb.db.collection::<mongodb::bson::Document>("user_teams");
-    let caller_filter = doc! { "team_id": &team_id, "user_id": &current_user };
-    if user_teams.find_one(caller_filter).await.ok().flatten().is_none() {
-        return HttpResponse::Unauthorized().body("Not a member of this team");
+    // 1) Caller must be on the team and able to edit the board.
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    match find_board(&data, &scope, &board_id).await {
+        Ok(board) if can_edit_board(&auth, &board).await => {}
+        Ok(_) => return HttpResponse::Unauthorized().error("not_project_or_board_member"),
+        Err(resp) => return resp,
     }
 
     // 2) Target user must also be a team member.
+    let user_teams = data.mongodb.db.collection::<mongodb::bson::Document>("user_teams");
     let target_filter = doc! { "team_id": &team_id, "user_id": &payload.user_id };
     if user_teams.find_one(target_filter).await.ok().flatten().is_none() {
         return HttpResponse::BadRequest().body("User is not a member of this team");
     }
 
     // 3) Add to the board’s participants array
-    let boards_coll = data.mongodb.db.collection::<Board>("boards");
-    let filter = doc! { "board_id": &board_id, "project_id": &project_id };
+    let boards_coll = Repo::<Board>::new(&data.mongodb, &scope);
+    let filter = doc! { "board_id": &board_id };
     let update = doc! {
         "$addToSet": { "participants": &payload.user_id }
     };
     match boards_coll.update_one(filter, update).await {
         Ok(res) if res.matched_count == 1 => {
             info!("User {} added to board {}", payload.user_id, board_id);
-            HttpResponse::Ok().body("User added to board")
+            ok_message("User added to board")
         }
-        Ok(_) => HttpResponse::NotFound().body("Board not found"),
+        Ok(_) => HttpResponse::NotFound().error("board_not_found"),
         Err(e) => {
             error!("Error adding user to board: {}", e);
             HttpResponse::InternalServerError().body("Error adding user to board")
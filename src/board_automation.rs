@@ -0,0 +1,660 @@
+// src/board_automation.rs
+//
+// Per-board automation rules, e.g. "when a ticket moves to Done, set the resolution
+// date and notify the reporter" or "when the label bug is added, set type Bug and
+// priority High". A rule is a trigger, optional conditions on the ticket and a list
+// of actions. Rules run after every ticket create, update and comment committed on
+// their board through ticket_events.rs, whoever made it (handlers, email, bots, ...);
+// the actions of all rules that fire go into one commit by the "automation" actor,
+// whose own changes never trigger rules again. A rule that would close a
+// ticket missing the board's definition of done is skipped, as there is nobody to
+// override it. The test endpoint is a dry run: it reports what a rule would do to
+// the board's tickets without changing any.
+
+use std::sync::OnceLock;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use mongodb::bson::{self, doc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::activity::{record_activity, ActivityEvent};
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::{can_edit_board, find_board, Board};
+use crate::definition_of_done::board_unmet_conditions;
+use crate::do_not_disturb::notify_user;
+use crate::response::{CodedError, ok};
+use crate::tenancy::{ProjectScope, Repo};
+use crate::ticket::{resolution_changes, Ticket, CLOSED_STATUSES};
+use crate::ticket_events::{commit, CommitError, TicketChange, MAX_ATTEMPTS};
+
+/// Actor id of the commits made by rules.
+pub const AUTOMATION_ACTOR: &str = "automation";
+const MAX_RULES_PER_BOARD: u64 = 50;
+const MAX_ACTIONS_PER_RULE: usize = 10;
+const MAX_MESSAGE_LEN: usize = 500;
+/// Tickets a dry run without `ticket_id` is tried on, most recently created first.
+const DRY_RUN_SAMPLE: i64 = 20;
+
+/// Fields an action may set.
+const SETTABLE_FIELDS: [&str; 4] = ["status", "priority", "ticket_type", "assignee"];
+/// Fields a `field_changed` trigger may watch.
+const WATCHED_FIELDS: [&str; 7] = ["status", "priority", "ticket_type", "assignee", "due_date", "sprint", "labels"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    TicketCreated,
+    /// Either side may be left out; statuses compare case-insensitively
+    StatusChanged { from: Option<String>, to: Option<String> },
+    /// Also fires for a ticket created with the label
+    LabelAdded { label: String },
+    FieldChanged { field: String },
+    Commented,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleConditions {
+    /// Only tickets with one of these priorities; empty matches any
+    #[serde(default)]
+    pub priorities: Vec<String>,
+    /// Only tickets of one of these types; empty matches any
+    #[serde(default)]
+    pub ticket_types: Vec<String>,
+    /// Only tickets carrying all of these labels
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Recipient {
+    Reporter,
+    Assignee,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleAction {
+    /// One of SETTABLE_FIELDS; null clears the field (not allowed for status)
+    SetField { field: String, value: Option<String> },
+    AddLabel { label: String },
+    RemoveLabel { label: String },
+    /// Sets `resolved_at` to the time the rule runs
+    SetResolutionDate,
+    /// Sent to the recipient unless they made the change themselves
+    Notify { recipient: Recipient, message: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutomationRule {
+    pub rule_id: String,
+    pub team_id: String,
+    pub project_id: String,
+    pub board_id: String,
+    pub name: String,
+    pub enabled: bool,
+    pub trigger: Trigger,
+    #[serde(default)]
+    pub conditions: RuleConditions,
+    /// Applied in order
+    pub actions: Vec<RuleAction>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAutomationRequest {
+    pub name: String,
+    pub enabled: Option<bool>,
+    pub trigger: Trigger,
+    #[serde(default)]
+    pub conditions: RuleConditions,
+    pub actions: Vec<RuleAction>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateAutomationRequest {
+    pub name: Option<String>,
+    pub enabled: Option<bool>,
+    pub trigger: Option<Trigger>,
+    pub conditions: Option<RuleConditions>,
+    pub actions: Option<Vec<RuleAction>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TestAutomationRequest {
+    pub ticket_id: Option<String>,
+}
+
+/// What a rule would do to one ticket.
+#[derive(Debug, Serialize)]
+pub struct DryRunResult {
+    pub ticket_id: String,
+    pub title: String,
+    pub conditions_met: bool,
+    /// Empty when the conditions are not met
+    pub changes: Vec<TicketChange>,
+    /// User ids that would be notified
+    pub notify: Vec<String>,
+}
+
+static STATE: OnceLock<AppState> = OnceLock::new();
+
+/// Called once at startup; rules run with the app's database and chat server.
+pub fn init(data: AppState) {
+    let _ = STATE.set(data);
+}
+
+fn rules_coll(data: &AppState) -> mongodb::Collection<AutomationRule> {
+    data.mongodb.db.collection::<AutomationRule>("automation_rules")
+}
+
+fn has_label(ticket: &Ticket, label: &str) -> bool {
+    ticket.labels.iter().flatten().any(|l| l.eq_ignore_ascii_case(label))
+}
+
+fn matches_any(value: Option<&str>, allowed: &[String]) -> bool {
+    allowed.is_empty() || value.is_some_and(|v| allowed.iter().any(|a| a.eq_ignore_ascii_case(v)))
+}
+
+fn field_differs(before: &Ticket, after: &Ticket, field: &str) -> bool {
+    let value = |t: &Ticket| bson::to_document(t).ok().and_then(|d| d.get(field).cloned());
+    value(before) != value(after)
+}
+
+/// Whether going from `before` (None for a new ticket) to `after` fires the trigger.
+fn triggered(trigger: &Trigger, before: Option<&Ticket>, after: &Ticket) -> bool {
+    let Some(before) = before else {
+        return match trigger {
+            Trigger::TicketCreated => true,
+            Trigger::LabelAdded { label } => has_label(after, label),
+            _ => false,
+        };
+    };
+    match trigger {
+        Trigger::TicketCreated => false,
+        Trigger::StatusChanged { from, to } => {
+            !before.status.eq_ignore_ascii_case(&after.status)
+                && from.as_deref().is_none_or(|f| f.eq_ignore_ascii_case(&before.status))
+                && to.as_deref().is_none_or(|t| t.eq_ignore_ascii_case(&after.status))
+        }
+        Trigger::LabelAdded { label } => has_label(after, label) && !has_label(before, label),
+        Trigger::FieldChanged { field } => field_differs(before, after, field),
+        Trigger::Commented => {
+            after.comments.as_ref().map_or(0, Vec::len) > before.comments.as_ref().map_or(0, Vec::len)
+        }
+    }
+}
+
+fn conditions_met(conditions: &RuleConditions, ticket: &Ticket) -> bool {
+    matches_any(ticket.priority.as_deref(), &conditions.priorities)
+        && matches_any(ticket.ticket_type.as_deref(), &conditions.ticket_types)
+        && conditions.labels.iter().all(|l| has_label(ticket, l))
+}
+
+/// Turns the actions into ticket changes, applying each to `working` as it goes so
+/// later actions (and later rules) see the result of earlier ones.
+fn plan_actions(rule: &AutomationRule, working: &mut Ticket, changes: &mut Vec<TicketChange>) {
+    for action in &rule.actions {
+        let planned: Vec<TicketChange> = match action {
+            // Closing or reopening through a rule records the resolution like a manual move.
+            RuleAction::SetField { field, value: Some(status) } if field == "status" => {
+                let mut planned: Vec<TicketChange> = TicketChange::field(working, field, status).into_iter().collect();
+                planned.extend(resolution_changes(working, status, None, AUTOMATION_ACTOR));
+                planned
+            }
+            RuleAction::SetField { field, value } => TicketChange::field(working, field, value).into_iter().collect(),
+            RuleAction::AddLabel { label } => {
+                if has_label(working, label) {
+                    continue;
+                }
+                let mut labels = working.labels.clone().unwrap_or_default();
+                labels.push(label.clone());
+                TicketChange::field(working, "labels", &labels).into_iter().collect()
+            }
+            RuleAction::RemoveLabel { label } => {
+                if !has_label(working, label) {
+                    continue;
+                }
+                let mut labels = working.labels.clone().unwrap_or_default();
+                labels.retain(|l| !l.eq_ignore_ascii_case(label));
+                TicketChange::field(working, "labels", &labels).into_iter().collect()
+            }
+            RuleAction::SetResolutionDate => TicketChange::field(working, "resolved_at", &Some(Utc::now())).into_iter().collect(),
+            RuleAction::Notify { .. } => Vec::new(),
+        };
+        for change in planned {
+            match change.apply(Some(working.clone())) {
+                Ok(Some(t)) => {
+                    *working = t;
+                    changes.push(change);
+                }
+                _ => warn!("Automation rule {} produced an invalid change for ticket {}", rule.rule_id, working.ticket_id),
+            }
+        }
+    }
+}
+
+/// `ticket` after the actions of `rules`, and the changes that get it there.
+fn plan_rules(rules: &[AutomationRule], ticket: &Ticket) -> (Ticket, Vec<TicketChange>) {
+    let mut working = ticket.clone();
+    let mut changes = Vec::new();
+    for rule in rules {
+        plan_actions(rule, &mut working, &mut changes);
+    }
+    (working, changes)
+}
+
+fn closes(before: &Ticket, after: &Ticket) -> bool {
+    !CLOSED_STATUSES.contains(&before.status.as_str()) && CLOSED_STATUSES.contains(&after.status.as_str())
+}
+
+/// (user id, message) pairs of the rule's notify actions; `skip` is left out.
+fn notifications<'a>(rule: &'a AutomationRule, ticket: &Ticket, skip: Option<&str>) -> Vec<(String, &'a str)> {
+    let mut out: Vec<(String, &str)> = Vec::new();
+    for action in &rule.actions {
+        let RuleAction::Notify { recipient, message } = action else { continue };
+        let user_id = match recipient {
+            Recipient::Reporter => Some(ticket.reporter.as_str()).filter(|r| !r.is_empty()),
+            Recipient::Assignee => ticket.assignee.as_deref().filter(|a| !a.is_empty()),
+        };
+        if let Some(user_id) = user_id.filter(|u| Some(*u) != skip) {
+            out.push((user_id.to_string(), message.as_str()));
+        }
+    }
+    out
+}
+
+async fn validate_rule(
+    data: &AppState,
+    team_id: &str,
+    board: &Board,
+    trigger: &Trigger,
+    actions: &[RuleAction],
+) -> Result<(), String> {
+    match trigger {
+        Trigger::LabelAdded { label } if label.trim().is_empty() => return Err("Trigger label must not be empty".to_string()),
+        Trigger::FieldChanged { field } if !WATCHED_FIELDS.contains(&field.as_str()) => {
+            return Err(format!("field_changed can watch {}", WATCHED_FIELDS.join(", ")));
+        }
+        _ => {}
+    }
+    if actions.is_empty() {
+        return Err("A rule needs at least one action".to_string());
+    }
+    if actions.len() > MAX_ACTIONS_PER_RULE {
+        return Err(format!("A rule can have at most {} actions", MAX_ACTIONS_PER_RULE));
+    }
+    for action in actions {
+        match action {
+            RuleAction::SetField { field, .. } if !SETTABLE_FIELDS.contains(&field.as_str()) => {
+                return Err(format!("set_field can set {}", SETTABLE_FIELDS.join(", ")));
+            }
+            RuleAction::SetField { field, value } if field == "status" => {
+                let Some(status) = value.as_deref().filter(|s| !s.trim().is_empty()) else {
+                    return Err("status cannot be cleared".to_string());
+                };
+                if !board.columns.is_empty() && !board.columns.iter().any(|c| c.eq_ignore_ascii_case(status)) {
+                    return Err(format!("\"{}\" is not a column of this board", status));
+                }
+            }
+            RuleAction::SetField { field, value: Some(user_id) }
+                if field == "assignee" && !data.mongodb.check_user_team(user_id, team_id).await.unwrap_or(false) =>
+            {
+                return Err("Assignee must be a member of the same team".to_string());
+            }
+            RuleAction::AddLabel { label } | RuleAction::RemoveLabel { label } if label.trim().is_empty() => {
+                return Err("Labels must not be empty".to_string());
+            }
+            RuleAction::Notify { message, .. } if message.trim().is_empty() || message.len() > MAX_MESSAGE_LEN => {
+                return Err(format!("Notification messages must be 1-{} characters", MAX_MESSAGE_LEN));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Runs the rules of the ticket's board in the background after a committed change by
+/// `actor`; `before` is None for a newly created ticket.
+pub(crate) fn publish(before: Option<&Ticket>, after: Option<&Ticket>, actor: &str) {
+    let (Some(data), Some(after)) = (STATE.get(), after) else {
+        return;
+    };
+    if actor == AUTOMATION_ACTOR {
+        return;
+    }
+    let (before, after, actor) = (before.cloned(), after.clone(), actor.to_string());
+    actix_web::rt::spawn(async move {
+        run_automations(data, before.as_ref(), after, &actor).await;
+    });
+}
+
+async fn run_automations(data: &AppState, before: Option<&Ticket>, after: Ticket, actor: &str) {
+    let filter = doc! { "project_id": &after.project_id, "board_id": &after.board_id, "enabled": true };
+    let mut cursor = match rules_coll(data).find(filter).sort(doc! { "created_at": 1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching automation rules: {}", e);
+            return;
+        }
+    };
+    let mut fired = Vec::new();
+    while let Some(rule) = cursor.next().await {
+        match rule {
+            Ok(rule) if triggered(&rule.trigger, before, &after) && conditions_met(&rule.conditions, &after) => {
+                fired.push(rule)
+            }
+            Ok(_) => {}
+            Err(e) => error!("Error reading automation rule: {}", e),
+        }
+    }
+    if fired.is_empty() {
+        return;
+    }
+
+    // Re-plan on top of the fresh projection if someone else wrote in between.
+    let Some(scope) = ProjectScope::of_project(&data.mongodb, &after.project_id).await else {
+        return;
+    };
+    let tickets_coll = Repo::<Ticket>::new(&data.mongodb, &scope);
+    let mut current = after;
+    let mut blocked: Vec<(AutomationRule, Vec<String>)> = Vec::new();
+    for attempt in 1..=MAX_ATTEMPTS {
+        let (mut working, mut changes) = plan_rules(&fired, &current);
+        if closes(&current, &working) {
+            let unmet = match board_unmet_conditions(data, &scope, &working).await {
+                Ok(unmet) => unmet.into_iter().map(|u| u.message).collect(),
+                Err(_) => vec!["The board could not be read".to_string()],
+            };
+            if !unmet.is_empty() {
+                let (closing, rest): (Vec<_>, Vec<_>) =
+                    fired.into_iter().partition(|rule| closes(&current, &plan_rules(std::slice::from_ref(rule), &current).0));
+                blocked.extend(closing.into_iter().map(|rule| (rule, unmet.clone())));
+                fired = rest;
+                (working, changes) = plan_rules(&fired, &current);
+                if closes(&current, &working) {
+                    changes.clear();
+                }
+            }
+        }
+        if changes.is_empty() {
+            break;
+        }
+        match commit(&data.mongodb, Some(&current), changes, AUTOMATION_ACTOR).await {
+            Ok(Some(updated)) => {
+                current = updated;
+                break;
+            }
+            Ok(None) => return,
+            Err(CommitError::Conflict) if attempt < MAX_ATTEMPTS => {
+                let filter = doc! { "ticket_id": &current.ticket_id };
+                match tickets_coll.find_one(filter).await {
+                    Ok(Some(t)) => current = t,
+                    _ => return,
+                }
+            }
+            Err(e) => {
+                error!("Automation could not update ticket {}: {:?}", current.ticket_id, e);
+                break;
+            }
+        }
+    }
+
+    for (rule, unmet) in &blocked {
+        warn!("Automation rule {} would close ticket {} despite unmet conditions", rule.rule_id, current.ticket_id);
+        record_activity(&data.mongodb, ActivityEvent::new(
+            &rule.team_id, Some(&current.project_id), AUTOMATION_ACTOR, "automation_blocked", &current.ticket_id,
+            format!("did not run rule \"{}\" on ticket \"{}\": {}", rule.name, current.title, unmet.join("; ")),
+        )).await;
+    }
+
+    for rule in &fired {
+        for (user_id, message) in notifications(rule, &current, Some(actor)) {
+            let payload = serde_json::json!({
+                "type": "automation",
+                "rule_id": rule.rule_id,
+                "rule_name": rule.name,
+                "ticket_id": current.ticket_id,
+                "project_id": current.project_id,
+                "title": current.title,
+                "message": message,
+            });
+            notify_user(data, &user_id, payload.to_string()).await;
+        }
+        info!("Automation rule {} ran on ticket {}", rule.rule_id, current.ticket_id);
+        record_activity(&data.mongodb, ActivityEvent::new(
+            &rule.team_id, Some(&current.project_id), AUTOMATION_ACTOR, "automation_ran", &current.ticket_id,
+            format!("ran rule \"{}\" on ticket \"{}\"", rule.name, current.title),
+        )).await;
+    }
+}
+
+/// Team/project checks plus the board edit permission shared by every endpoint.
+async fn editable_board(auth: &AuthContext, data: &AppState, team_id: &str, project_id: &str, board_id: &str) -> Result<(ProjectScope, Board), HttpResponse> {
+    let scope = auth.project_scope(team_id, project_id).await?;
+    let board = find_board(data, &scope, board_id).await?;
+    if !can_edit_board(auth, &board).await {
+        return Err(HttpResponse::Unauthorized().error("not_project_or_board_member"));
+    }
+    Ok((scope, board))
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/boards/{board_id}/automations
+pub async fn list_automations(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (team_id, project_id, board_id) = path.into_inner();
+    if let Err(resp) = editable_board(&auth, &data, &team_id, &project_id, &board_id).await {
+        return resp;
+    }
+    let filter = doc! { "project_id": &project_id, "board_id": &board_id };
+    let mut cursor = match rules_coll(&data).find(filter).sort(doc! { "created_at": 1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching automation rules: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching automation rules");
+        }
+    };
+    let mut rules = Vec::new();
+    while let Some(rule) = cursor.next().await {
+        match rule {
+            Ok(r) => rules.push(r),
+            Err(e) => error!("Error reading automation rule: {}", e),
+        }
+    }
+    ok(rules)
+}
+
+/// POST /teams/{team_id}/projects/{project_id}/boards/{board_id}/automations
+pub async fn create_automation(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    payload: web::Json<CreateAutomationRequest>,
+) -> impl Responder {
+    let (team_id, project_id, board_id) = path.into_inner();
+    let board = match editable_board(&auth, &data, &team_id, &project_id, &board_id).await {
+        Ok((_, b)) => b,
+        Err(resp) => return resp,
+    };
+    let req = payload.into_inner();
+    let name = req.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().body("Rule name is required");
+    }
+    if let Err(msg) = validate_rule(&data, &team_id, &board, &req.trigger, &req.actions).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    match rules_coll(&data).count_documents(doc! { "project_id": &project_id, "board_id": &board_id }).await {
+        Ok(n) if n >= MAX_RULES_PER_BOARD => {
+            return HttpResponse::BadRequest().body(format!("Boards can have at most {} automation rules", MAX_RULES_PER_BOARD))
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("Error counting automation rules: {}", e);
+            return HttpResponse::InternalServerError().body("Error creating automation rule");
+        }
+    }
+
+    let rule = AutomationRule {
+        rule_id: Uuid::new_v4().to_string(),
+        team_id,
+        project_id,
+        board_id,
+        name: name.to_string(),
+        enabled: req.enabled.unwrap_or(true),
+        trigger: req.trigger,
+        conditions: req.conditions,
+        actions: req.actions,
+        created_by: auth.user_id().to_string(),
+        created_at: Utc::now(),
+    };
+    match rules_coll(&data).insert_one(&rule).await {
+        Ok(_) => HttpResponse::Created().json(rule),
+        Err(e) => {
+            error!("Error creating automation rule: {}", e);
+            HttpResponse::InternalServerError().body("Error creating automation rule")
+        }
+    }
+}
+
+/// PUT /teams/{team_id}/projects/{project_id}/boards/{board_id}/automations/{rule_id}
+pub async fn update_automation(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String, String)>,
+    payload: web::Json<UpdateAutomationRequest>,
+) -> impl Responder {
+    let (team_id, project_id, board_id, rule_id) = path.into_inner();
+    let board = match editable_board(&auth, &data, &team_id, &project_id, &board_id).await {
+        Ok((_, b)) => b,
+        Err(resp) => return resp,
+    };
+    let filter = doc! { "rule_id": &rule_id, "project_id": &project_id, "board_id": &board_id };
+    let mut rule = match rules_coll(&data).find_one(filter.clone()).await {
+        Ok(Some(r)) => r,
+        Ok(None) => return HttpResponse::NotFound().error("rule_not_found"),
+        Err(e) => {
+            error!("Error fetching automation rule: {}", e);
+            return HttpResponse::InternalServerError().body("Error updating automation rule");
+        }
+    };
+    let req = payload.into_inner();
+    if let Some(name) = req.name.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+        rule.name = name.to_string();
+    }
+    if let Some(enabled) = req.enabled {
+        rule.enabled = enabled;
+    }
+    if let Some(trigger) = req.trigger {
+        rule.trigger = trigger;
+    }
+    if let Some(conditions) = req.conditions {
+        rule.conditions = conditions;
+    }
+    if let Some(actions) = req.actions {
+        rule.actions = actions;
+    }
+    if let Err(msg) = validate_rule(&data, &team_id, &board, &rule.trigger, &rule.actions).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    match rules_coll(&data).replace_one(filter, &rule).await {
+        Ok(_) => ok(rule),
+        Err(e) => {
+            error!("Error updating automation rule: {}", e);
+            HttpResponse::InternalServerError().body("Error updating automation rule")
+        }
+    }
+}
+
+/// DELETE /teams/{team_id}/projects/{project_id}/boards/{board_id}/automations/{rule_id}
+pub async fn delete_automation(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String, String)>,
+) -> impl Responder {
+    let (team_id, project_id, board_id, rule_id) = path.into_inner();
+    if let Err(resp) = editable_board(&auth, &data, &team_id, &project_id, &board_id).await {
+        return resp;
+    }
+    let filter = doc! { "rule_id": &rule_id, "project_id": &project_id, "board_id": &board_id };
+    match rules_coll(&data).delete_one(filter).await {
+        Ok(res) if res.deleted_count == 0 => HttpResponse::NotFound().error("rule_not_found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Error deleting automation rule: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting automation rule")
+        }
+    }
+}
+
+/// POST /teams/{team_id}/projects/{project_id}/boards/{board_id}/automations/{rule_id}/test
+/// Dry run, also for disabled rules: checks the conditions against one ticket
+/// (`{"ticket_id": ...}`) or the board's most recent tickets, as if the trigger had
+/// fired, and returns the changes and notifications it would make. Nothing is written.
+pub async fn test_automation(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String, String)>,
+    payload: Option<web::Json<TestAutomationRequest>>,
+) -> impl Responder {
+    let (team_id, project_id, board_id, rule_id) = path.into_inner();
+    let scope = match editable_board(&auth, &data, &team_id, &project_id, &board_id).await {
+        Ok((scope, _)) => scope,
+        Err(resp) => return resp,
+    };
+    let filter = doc! { "rule_id": &rule_id, "project_id": &project_id, "board_id": &board_id };
+    let rule = match rules_coll(&data).find_one(filter).await {
+        Ok(Some(r)) => r,
+        Ok(None) => return HttpResponse::NotFound().error("rule_not_found"),
+        Err(e) => {
+            error!("Error fetching automation rule: {}", e);
+            return HttpResponse::InternalServerError().body("Error testing automation rule");
+        }
+    };
+
+    let tickets_coll = Repo::<Ticket>::new(&data.mongodb, &scope);
+    let mut filter = doc! { "board_id": &board_id };
+    let ticket_id = payload.and_then(|p| p.into_inner().ticket_id);
+    if let Some(ticket_id) = &ticket_id {
+        filter.insert("ticket_id", ticket_id);
+    }
+    let mut cursor = match tickets_coll.find(filter).sort(doc! { "created_at": -1 }).limit(DRY_RUN_SAMPLE).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching tickets: {}", e);
+            return HttpResponse::InternalServerError().body("Error testing automation rule");
+        }
+    };
+    let mut results = Vec::new();
+    while let Some(ticket) = cursor.next().await {
+        let ticket = match ticket {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Error reading ticket: {}", e);
+                continue;
+            }
+        };
+        let conditions_met = conditions_met(&rule.conditions, &ticket);
+        let mut changes = Vec::new();
+        let mut notify = Vec::new();
+        if conditions_met {
+            let mut working = ticket.clone();
+            plan_actions(&rule, &mut working, &mut changes);
+            notify = notifications(&rule, &working, None).into_iter().map(|(user_id, _)| user_id).collect();
+        }
+        results.push(DryRunResult { ticket_id: ticket.ticket_id, title: ticket.title, conditions_met, changes, notify });
+    }
+    if ticket_id.is_some() && results.is_empty() {
+        return HttpResponse::NotFound().body("Ticket not found on this board");
+    }
+    ok(results)
+}
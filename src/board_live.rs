@@ -0,0 +1,264 @@
+// src/board_live.rs
+//
+// Live board updates over the WebSocket. A client viewing a board joins its room;
+// every ticket change committed through ticket_events.rs is then pushed to the room:
+//
+//   {"type": "board_join" | "board_leave", "board_id": ...}
+//
+//   {"type": "board_joined", "board_id": ..., "seq": 12}
+//   {"type": "board_event", "board_id": ..., "seq": 13, "event": "created" | "updated" | "moved" | "deleted",
+//    "ticket_id": ..., "ticket": {...}, "from_board_id": ..., "to_board_id": ...}
+//   {"type": "board_conflict", "board_id": ..., "seq": 13, "user_id": ..., "ticket_id": ..., "based_on": 4,
+//    "version": 6, "fields": ["status"], "ticket": {...}}
+//   {"type": "board_error", "board_id": ..., "error": ...}
+//
+// `ticket` is absent for deletions; the board ids only appear on moves, which are
+// sent to both boards. Delivery is best effort. `seq` counts the room's events,
+// starting from the value in `board_joined`: a client that sees a gap, or has to
+// join again after reconnecting, refetches the board instead of patching it.
+// `board_conflict` reports an update of `user_id` rejected by the merge rules in
+// ticket_merge.rs; it changes nothing, so it repeats the room's current `seq`.
+// Rooms live in the ChatServer and are dropped, counter included, with their last
+// session.
+
+use std::sync::OnceLock;
+
+use actix::prelude::*;
+use log::error;
+use mongodb::bson::doc;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::board::Board;
+use crate::chat_db::MongoDB;
+use crate::chat_server::{ChatServer, SignalMessage, WsMessage};
+use crate::tenancy::{ProjectsScope, Repo};
+use crate::ticket::Ticket;
+use crate::ticket_merge::TicketConflict;
+
+static FEED: OnceLock<Addr<ChatServer>> = OnceLock::new();
+
+#[derive(Default)]
+pub struct BoardRoom {
+    seq: u64,
+    sessions: Vec<Recipient<WsMessage>>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoardEventKind {
+    Created,
+    Updated,
+    Moved,
+    Deleted,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct BoardEvent {
+    pub board_id: String,
+    pub kind: BoardEventKind,
+    pub ticket_id: String,
+    pub ticket: Option<Ticket>,
+    /// Set on moves only
+    pub moved: Option<(String, String)>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct BoardConflict {
+    pub board_id: String,
+    pub user_id: String,
+    pub conflict: TicketConflict,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct JoinBoard {
+    pub board_id: String,
+    pub user_id: String,
+    pub addr: Recipient<WsMessage>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct LeaveBoard {
+    /// None leaves every board, when the socket closes
+    pub board_id: Option<String>,
+    pub addr: Recipient<WsMessage>,
+}
+
+/// Called once at startup with the server that holds the rooms.
+pub fn init(chat_server: Addr<ChatServer>) {
+    let _ = FEED.set(chat_server);
+}
+
+/// Pushes a committed ticket change to the rooms of the boards it touches.
+pub(crate) fn publish(before: Option<&Ticket>, after: Option<&Ticket>) {
+    let Some(feed) = FEED.get() else {
+        return;
+    };
+    let event = |board_id: &str, kind, ticket: Option<&Ticket>, moved: Option<(String, String)>| BoardEvent {
+        board_id: board_id.to_string(),
+        kind,
+        ticket_id: after.or(before).map(|t| t.ticket_id.clone()).unwrap_or_default(),
+        ticket: ticket.cloned(),
+        moved,
+    };
+    match (before, after) {
+        (None, Some(t)) => feed.do_send(event(&t.board_id, BoardEventKind::Created, Some(t), None)),
+        (Some(t), None) => feed.do_send(event(&t.board_id, BoardEventKind::Deleted, None, None)),
+        (Some(old), Some(new)) if old.board_id != new.board_id => {
+            let moved = (old.board_id.clone(), new.board_id.clone());
+            feed.do_send(event(&old.board_id, BoardEventKind::Moved, Some(new), Some(moved.clone())));
+            feed.do_send(event(&new.board_id, BoardEventKind::Moved, Some(new), Some(moved)));
+        }
+        (Some(_), Some(t)) => feed.do_send(event(&t.board_id, BoardEventKind::Updated, Some(t), None)),
+        (None, None) => {}
+    }
+}
+
+/// Reports a rejected concurrent update to the board's room.
+pub(crate) fn conflict(board_id: &str, user_id: &str, conflict: &TicketConflict) {
+    if let Some(feed) = FEED.get() {
+        feed.do_send(BoardConflict {
+            board_id: board_id.to_string(),
+            user_id: user_id.to_string(),
+            conflict: conflict.clone(),
+        });
+    }
+}
+
+fn send(addr: &Recipient<WsMessage>, payload: serde_json::Value) {
+    addr.do_send(WsMessage::Signal(SignalMessage { payload: payload.to_string() }));
+}
+
+/// Whether `user_id` may watch the board: the board is in one of their teams and
+/// they are a member of its project.
+async fn can_view(db: &MongoDB, board_id: &str, user_id: &str) -> Result<(), String> {
+    let board = match ProjectsScope::of_user(db, user_id).await {
+        Ok(scope) => Repo::<Board>::across(db, &scope).find_one(doc! { "board_id": board_id }).await,
+        Err(e) => Err(e),
+    };
+    let board = match board {
+        Ok(Some(b)) => b,
+        Ok(None) => return Err("Board not found".to_string()),
+        Err(e) => {
+            error!("Error fetching board: {}", e);
+            return Err("Error fetching board".to_string());
+        }
+    };
+    match db.check_project_membership(user_id, &board.project_id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("Not a member of this project".to_string()),
+        Err(e) => {
+            error!("Error checking project membership: {}", e);
+            Err("Error checking project membership".to_string())
+        }
+    }
+}
+
+impl ChatServer {
+    fn board_leave(&mut self, board_id: &str, addr: &Recipient<WsMessage>) {
+        if let Some(room) = self.boards.get_mut(board_id) {
+            room.sessions.retain(|a| a != addr);
+            if room.sessions.is_empty() {
+                self.boards.remove(board_id);
+            }
+        }
+    }
+}
+
+impl Handler<BoardEvent> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: BoardEvent, _: &mut Context<Self>) {
+        let Some(room) = self.boards.get_mut(&msg.board_id) else {
+            return;
+        };
+        room.seq += 1;
+        let mut payload = json!({
+            "type": "board_event",
+            "board_id": msg.board_id,
+            "seq": room.seq,
+            "event": msg.kind,
+            "ticket_id": msg.ticket_id,
+        });
+        if let Some(ticket) = &msg.ticket {
+            payload["ticket"] = json!(ticket);
+        }
+        if let Some((from, to)) = &msg.moved {
+            payload["from_board_id"] = json!(from);
+            payload["to_board_id"] = json!(to);
+        }
+        let payload = payload.to_string();
+        for addr in &room.sessions {
+            addr.do_send(WsMessage::Signal(SignalMessage { payload: payload.clone() }));
+        }
+    }
+}
+
+impl Handler<BoardConflict> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: BoardConflict, _: &mut Context<Self>) {
+        let Some(room) = self.boards.get(&msg.board_id) else {
+            return;
+        };
+        let mut payload = json!(msg.conflict);
+        payload["type"] = json!("board_conflict");
+        payload["board_id"] = json!(msg.board_id);
+        payload["seq"] = json!(room.seq);
+        payload["user_id"] = json!(msg.user_id);
+        for addr in &room.sessions {
+            send(addr, payload.clone());
+        }
+    }
+}
+
+impl Handler<JoinBoard> for ChatServer {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, msg: JoinBoard, _: &mut Context<Self>) -> Self::Result {
+        let db = self.db.clone();
+        Box::pin(
+            async move {
+                let allowed = can_view(&db, &msg.board_id, &msg.user_id).await;
+                (msg, allowed)
+            }
+            .into_actor(self)
+            .map(|(msg, allowed), act, _| {
+                if let Err(e) = allowed {
+                    send(&msg.addr, json!({ "type": "board_error", "board_id": msg.board_id, "error": e }));
+                    return;
+                }
+                let room = act.boards.entry(msg.board_id.clone()).or_default();
+                if !room.sessions.contains(&msg.addr) {
+                    room.sessions.push(msg.addr.clone());
+                }
+                send(&msg.addr, json!({ "type": "board_joined", "board_id": msg.board_id, "seq": room.seq }));
+            }),
+        )
+    }
+}
+
+impl Handler<LeaveBoard> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: LeaveBoard, _: &mut Context<Self>) {
+        match msg.board_id {
+            Some(board_id) => self.board_leave(&board_id, &msg.addr),
+            None => {
+                let joined: Vec<String> = self
+                    .boards
+                    .iter()
+                    .filter(|(_, room)| room.sessions.contains(&msg.addr))
+                    .map(|(id, _)| id.clone())
+                    .collect();
+                for board_id in joined {
+                    self.board_leave(&board_id, &msg.addr);
+                }
+            }
+        }
+    }
+}
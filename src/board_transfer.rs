@@ -0,0 +1,432 @@
+// src/board_transfer.rs
+//
+// Board export/import. JSON carries the whole board (metadata, columns, tickets), as
+// does NDJSON with one ticket per line; CSV carries tickets only and is meant for
+// spreadsheets. Exports are streamed, so large boards are never held in memory.
+
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use futures_util::stream::LocalBoxStream;
+use futures_util::{future, stream, StreamExt};
+use log::{error, info};
+use mongodb::bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::activity::{record_activity, ActivityEvent};
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::Board;
+use crate::response::{CodedError, NDJSON, ndjson_line};
+use crate::tenancy::{ProjectScope, Repo};
+use crate::ticket::Ticket;
+use crate::sync::{record_change, Entity, Op, Scope};
+use crate::ticket_events::{created_event, TicketEvent};
+
+const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoardExport {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub board: BoardExportMeta,
+    pub tickets: Vec<TicketExport>,
+}
+
+/// Everything in `BoardExport` but the tickets; the first line of an NDJSON export.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoardExportHeader {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub board: BoardExportMeta,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoardExportMeta {
+    pub name: String,
+    pub board_type: String,
+    pub description: Option<String>,
+    pub sprint_length: Option<i32>,
+    #[serde(default)]
+    pub columns: Vec<String>,
+}
+
+/// Flat ticket row, shared by the JSON and CSV formats.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TicketExport {
+    pub ticket_id: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub status: String,
+    pub priority: Option<String>,
+    pub assignee: Option<String>,
+    pub reporter: Option<String>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub ticket_type: Option<String>,
+    pub sprint: Option<i32>,
+    pub rank: Option<f64>,
+    /// Semicolon-separated
+    pub labels: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// "json" (default), "csv" or "ndjson"
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    /// Name for the new board; required for CSV imports
+    pub name: Option<String>,
+}
+
+async fn is_team_member(data: &AppState, team_id: &str, user_id: &str) -> bool {
+    data.mongodb
+        .db
+        .collection::<Document>("user_teams")
+        .find_one(doc! { "team_id": team_id, "user_id": user_id })
+        .await
+        .ok()
+        .flatten()
+        .is_some()
+}
+
+/// Returns `user` if they belong to the team, caching lookups across an import.
+async fn team_user(
+    data: &AppState,
+    team_id: &str,
+    cache: &mut HashMap<String, bool>,
+    user: Option<String>,
+) -> Option<String> {
+    let user = user.filter(|u| !u.is_empty())?;
+    let ok = match cache.get(&user) {
+        Some(ok) => *ok,
+        None => {
+            let ok = is_team_member(data, team_id, &user).await;
+            cache.insert(user.clone(), ok);
+            ok
+        }
+    };
+    ok.then_some(user)
+}
+
+impl From<Ticket> for TicketExport {
+    fn from(t: Ticket) -> Self {
+        TicketExport {
+            ticket_id: t.ticket_id,
+            title: t.title,
+            description: t.description,
+            status: t.status,
+            priority: t.priority,
+            assignee: t.assignee,
+            reporter: Some(t.reporter).filter(|r| !r.is_empty()),
+            due_date: t.due_date,
+            ticket_type: t.ticket_type,
+            sprint: t.sprint,
+            rank: t.rank,
+            labels: t.labels.map(|l| l.join(";")),
+        }
+    }
+}
+
+/// Tickets matching `filter` in export order: column order first, then rank within
+/// the column with unranked tickets last. Statuses that are not columns come after
+/// all columns, by name. Sorted by the database so the export can be streamed.
+async fn tickets_in_board_order(
+    data: &AppState,
+    scope: &ProjectScope,
+    filter: Document,
+    columns: &[String],
+) -> mongodb::error::Result<mongodb::Cursor<Ticket>> {
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$addFields": {
+            "_column": { "$indexOfArray": [columns.to_vec(), "$status"] },
+            "_unranked": { "$eq": [{ "$ifNull": ["$rank", null] }, null] },
+        } },
+        doc! { "$addFields": {
+            "_column": { "$cond": [{ "$lt": ["$_column", 0] }, columns.len() as i64, "$_column"] },
+        } },
+        doc! { "$sort": { "_column": 1, "status": 1, "_unranked": 1, "rank": 1 } },
+        doc! { "$project": { "_column": 0, "_unranked": 0 } },
+    ];
+    Repo::<Ticket>::new(&data.mongodb, scope)
+        .aggregate(pipeline)
+        .allow_disk_use(true)
+        .with_type::<Ticket>()
+        .await
+}
+
+fn export_error(e: mongodb::error::Error) -> actix_web::Error {
+    error!("Error streaming board export: {}", e);
+    ErrorInternalServerError("Error reading tickets")
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/boards/{board_id}/export?format=json|csv|ndjson
+/// Streamed from the tickets cursor. NDJSON puts the board on the first line and one
+/// ticket on each following line.
+pub async fn export_board(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    query: web::Query<ExportQuery>,
+) -> impl Responder {
+    let (team_id, project_id, board_id) = path.into_inner();
+    let current_user = auth.user_id().to_string();
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let format = query.format.clone().unwrap_or_else(|| "json".to_string());
+    if !["json", "csv", "ndjson"].contains(&format.as_str()) {
+        return HttpResponse::BadRequest().body("format must be 'json', 'csv' or 'ndjson'");
+    }
+
+    let boards_coll = Repo::<Board>::new(&data.mongodb, &scope);
+    let board = match boards_coll.find_one(doc! { "board_id": &board_id }).await {
+        Ok(Some(b)) => b,
+        Ok(None) => return HttpResponse::NotFound().error("board_not_found"),
+        Err(e) => {
+            error!("Error fetching board: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching board");
+        }
+    };
+    if !board.participants.contains(&current_user) && !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_or_board_member");
+    }
+
+    let filter = doc! { "board_id": &board_id };
+    let cursor = match tickets_in_board_order(&data, &scope, filter, &board.columns).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching tickets: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_tickets");
+        }
+    };
+    let rows = cursor.map(|t| t.map(TicketExport::from).map_err(export_error)).enumerate();
+
+    let filename = board.name.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+    let header = BoardExportHeader {
+        version: EXPORT_VERSION,
+        exported_at: Utc::now(),
+        board: BoardExportMeta {
+            name: board.name,
+            board_type: board.board_type,
+            description: board.description,
+            sprint_length: board.sprint_length,
+            columns: board.columns,
+        },
+    };
+    let head = match ndjson_line(&header) {
+        Ok(line) => line,
+        Err(e) => return e.error_response(),
+    };
+
+    let (content_type, body): (&str, LocalBoxStream<'static, Result<web::Bytes, actix_web::Error>>) = match format.as_str() {
+        "json" => {
+            // `{"version":..,"exported_at":..,"board":{..},"tickets":[` + tickets + `]}`
+            let head = head.strip_suffix(b"}\n").unwrap_or(&head);
+            let head = web::Bytes::from([head, b",\"tickets\":[".as_slice()].concat());
+            let tickets = rows.map(|(n, row)| {
+                let json = serde_json::to_vec(&row?).map_err(ErrorInternalServerError)?;
+                Ok(web::Bytes::from(if n == 0 { json } else { [b",".as_slice(), &json].concat() }))
+            });
+            let body = stream::once(future::ready(Ok(head)))
+                .chain(tickets)
+                .chain(stream::once(future::ready(Ok(web::Bytes::from_static(b"]}")))));
+            ("application/json", body.boxed_local())
+        }
+        "csv" => {
+            let body = rows.map(|(n, row)| {
+                let mut writer = csv::WriterBuilder::new().has_headers(n == 0).from_writer(Vec::new());
+                writer.serialize(row?).map_err(ErrorInternalServerError)?;
+                writer.into_inner().map(web::Bytes::from).map_err(|e| ErrorInternalServerError(e.to_string()))
+            });
+            ("text/csv; charset=utf-8", body.boxed_local())
+        }
+        _ => {
+            let body = stream::once(future::ready(Ok(head))).chain(rows.map(|(_, row)| ndjson_line(&row?)));
+            (NDJSON, body.boxed_local())
+        }
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.{}\"", filename, format)))
+        .streaming(body)
+}
+
+fn parse_ndjson(body: &[u8]) -> Result<BoardExport, String> {
+    let mut lines = body.split(|b| *b == b'\n').enumerate().filter(|(_, l)| !l.trim_ascii().is_empty());
+    let (_, first) = lines.next().ok_or("Empty board export")?;
+    let header: BoardExportHeader = serde_json::from_slice(first).map_err(|e| format!("Invalid board export: {}", e))?;
+    let tickets = lines
+        .map(|(n, l)| serde_json::from_slice(l).map_err(|e| format!("Invalid ticket on line {}: {}", n + 1, e)))
+        .collect::<Result<Vec<TicketExport>, _>>()?;
+    Ok(BoardExport { version: header.version, exported_at: header.exported_at, board: header.board, tickets })
+}
+
+fn parse_import(req: &HttpRequest, body: &[u8], name: Option<String>) -> Result<BoardExport, String> {
+    let content_type = req.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("");
+    let is_csv = content_type.starts_with("text/csv");
+
+    if !is_csv {
+        let mut export = if content_type.starts_with(NDJSON) {
+            parse_ndjson(body)?
+        } else {
+            serde_json::from_slice::<BoardExport>(body).map_err(|e| format!("Invalid board export: {}", e))?
+        };
+        if export.version > EXPORT_VERSION {
+            return Err(format!("Unsupported export version {}", export.version));
+        }
+        if let Some(name) = name {
+            export.board.name = name;
+        }
+        return Ok(export);
+    }
+
+    let name = name.ok_or("CSV imports need a board name (?name=...)")?;
+    let mut reader = csv::Reader::from_reader(body);
+    let tickets = reader
+        .deserialize::<TicketExport>()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid CSV: {}", e))?;
+    // Columns follow the order in which statuses first appear.
+    let mut columns: Vec<String> = Vec::new();
+    for t in &tickets {
+        if !columns.contains(&t.status) {
+            columns.push(t.status.clone());
+        }
+    }
+    Ok(BoardExport {
+        version: EXPORT_VERSION,
+        exported_at: Utc::now(),
+        board: BoardExportMeta {
+            name,
+            board_type: "kanban".to_string(),
+            description: None,
+            sprint_length: None,
+            columns,
+        },
+        tickets,
+    })
+}
+
+/// POST /teams/{team_id}/projects/{project_id}/boards/import?name=...
+/// Creates a new board from a JSON or NDJSON (Content-Type: application/x-ndjson) export,
+/// or a CSV of tickets (Content-Type: text/csv).
+pub async fn import_board(
+    req: HttpRequest,
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    query: web::Query<ImportQuery>,
+    body: web::Bytes,
+) -> impl Responder {
+    let (team_id, project_id) = path.into_inner();
+    let current_user = auth.user_id().to_string();
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    if !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
+
+    let export = match parse_import(&req, &body, query.into_inner().name) {
+        Ok(e) => e,
+        Err(msg) => return HttpResponse::BadRequest().body(msg),
+    };
+
+    let board = Board {
+        board_id: Uuid::new_v4().to_string(),
+        project_id: project_id.clone(),
+        name: export.board.name,
+        board_type: export.board.board_type,
+        description: export.board.description,
+        sprint_length: export.board.sprint_length,
+        created_at: Utc::now(),
+        created_by: current_user.clone(),
+        participants: vec![current_user.clone()],
+        columns: export.board.columns,
+        // Label mappings name people of the exporting team.
+        auto_assign: None,
+        definition_of_done: None,
+    };
+
+    // People from another team are dropped: assignees are cleared and the importer
+    // becomes the reporter.
+    let mut members = HashMap::new();
+    let mut tickets = Vec::with_capacity(export.tickets.len());
+    for t in export.tickets {
+        let assignee = team_user(&data, &team_id, &mut members, t.assignee).await;
+        let reporter = team_user(&data, &team_id, &mut members, t.reporter).await;
+
+        tickets.push(Ticket {
+            id: None,
+            ticket_id: Uuid::new_v4().to_string(),
+            board_id: board.board_id.clone(),
+            project_id: project_id.clone(),
+            title: t.title,
+            description: t.description,
+            status: t.status,
+            priority: t.priority,
+            reporter: reporter.unwrap_or_else(|| current_user.clone()),
+            requester_email: None,
+            assignee,
+            due_date: t.due_date,
+            ticket_type: t.ticket_type,
+            sprint: t.sprint,
+            rank: t.rank,
+            labels: t.labels.map(|l| {
+                l.split(';').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+            }),
+            fix_version: None,
+            estimate_hours: None,
+            blocked_by: Vec::new(),
+            attachments: None,
+            comments: Some(vec![]),
+            references: Vec::new(),
+            created_at: Utc::now(),
+            resolution: None,
+            resolved_at: None,
+            resolved_by: None,
+            vote_count: 0,
+            version: 1,
+        });
+    }
+
+    let boards_coll = Repo::<Board>::new(&data.mongodb, &scope);
+    if let Err(e) = boards_coll.insert_one(&board).await {
+        error!("Error inserting board: {}", e);
+        return HttpResponse::InternalServerError().body("Error inserting board");
+    }
+    if !tickets.is_empty() {
+        if let Err(e) = Repo::<Ticket>::new(&data.mongodb, &scope).insert_many(&tickets).await {
+            error!("Error inserting imported tickets: {}", e);
+            // Do not leave a half-imported board behind.
+            let _ = boards_coll.delete_one(doc! { "board_id": &board.board_id }).await;
+            return HttpResponse::InternalServerError().body("Error inserting tickets");
+        }
+        let events: Vec<TicketEvent> = tickets.iter().map(|t| created_event(t, &current_user)).collect();
+        if let Err(e) = data.mongodb.db.collection::<TicketEvent>("ticket_events").insert_many(&events).await {
+            error!("Error recording imported ticket events: {}", e);
+        }
+        for t in &tickets {
+            record_change(&data.mongodb, Entity::Ticket, &t.ticket_id, Op::Upsert, Scope::Project(&project_id)).await;
+        }
+    }
+
+    info!("Board {} imported with {} tickets", board.board_id, tickets.len());
+    record_activity(&data.mongodb, ActivityEvent::new(
+        &team_id,
+        Some(&project_id),
+        &current_user,
+        "board_created",
+        &board.board_id,
+        format!("imported board \"{}\" with {} tickets", board.name, tickets.len()),
+    )).await;
+    HttpResponse::Ok().json(board)
+}
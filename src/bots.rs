@@ -0,0 +1,733 @@
+// src/bots.rs
+//
+// Bot accounts and slash commands. Team admins register bots; each bot gets an API
+// key (shown once, stored hashed) and may register slash commands answered by its
+// webhook. A bot only sees and posts to chats it has been installed in, and its
+// messages carry the sender id `bot:{bot_id}`.
+//
+// Messages starting with `/` are checked against the built-in commands and the
+// commands of the bots installed in the chat, both for REST and WebSocket sends.
+// A recognised command is not stored as a message; its reply is posted by the bot
+// instead, or shown to the sender alone where it concerns only them (`/remind`).
+// Anything else is sent as an ordinary message.
+//
+// Webhook calls are signed so bots can tell them from forged ones: the
+// `X-Taskline-Signature` header is `sha256=` and the hex HMAC-SHA256, under the
+// bot's webhook secret, of `{X-Taskline-Timestamp}.{body}`. The secret is shown
+// with the API key when the bot is created or its key rotated; bots created before
+// signing get one on their next rotation and are called unsigned until then.
+
+use std::time::Duration;
+
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use mongodb::bson::doc;
+use ring::hmac;
+use regex::escape;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::activity::{record_activity, ActivityEvent};
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::Board;
+use crate::chat::Chat;
+use crate::chat_server::{MessageResponse, PostBotMessage};
+use crate::encryption::{lookup_hash, EncryptedString};
+use crate::giphy::{giphy_command, giphy_enabled, GIPHY_USAGE};
+use crate::impersonation::Impersonation;
+use crate::outbound;
+use crate::project::Project;
+use crate::reminders::{remind_command, REMIND_USAGE};
+use crate::response::CodedError;
+use crate::tenancy::{ProjectScope, Repo, TeamScope};
+use crate::ticket::Ticket;
+use crate::ticket_assignment::auto_assignee;
+use crate::ticket_events::{commit, TicketChange};
+
+pub const BOT_KEY_HEADER: &str = "X-Bot-Key";
+const SIGNATURE_HEADER: &str = "X-Taskline-Signature";
+const TIMESTAMP_HEADER: &str = "X-Taskline-Timestamp";
+/// Sender id of replies to built-in commands.
+pub const SYSTEM_BOT: &str = "bot:taskline";
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Built-in commands, as (name, usage). `/giphy` only exists when configured.
+pub const BUILTIN_COMMANDS: [(&str, &str); 4] = [
+    ("help", "/help — list the commands available in this chat"),
+    ("ticket", "/ticket create <project>: <title> — create a ticket in a project of this team"),
+    ("remind", REMIND_USAGE),
+    ("giphy", GIPHY_USAGE),
+];
+
+/// Outcome of a slash command.
+pub(crate) enum CommandReply {
+    /// The reply posted in the chat
+    Posted(MessageResponse),
+    /// A reply for the sender alone
+    Private(String),
+    /// The command had nothing to say
+    Silent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bot {
+    pub bot_id: String,
+    pub team_id: String,
+    pub name: String,
+    pub created_by: String,
+    pub api_key_hash: String,
+    /// Receives the bot's slash commands; commands need one
+    pub webhook_url: Option<String>,
+    /// Command names without the leading slash
+    #[serde(default)]
+    pub commands: Vec<String>,
+    /// Signs webhook calls; missing for bots created before signing until rotated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_secret: Option<EncryptedString>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BotView {
+    pub bot_id: String,
+    pub team_id: String,
+    pub name: String,
+    pub sender_id: String,
+    pub webhook_url: Option<String>,
+    pub commands: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Bot> for BotView {
+    fn from(b: Bot) -> Self {
+        Self {
+            sender_id: sender_id(&b.bot_id),
+            bot_id: b.bot_id,
+            team_id: b.team_id,
+            name: b.name,
+            webhook_url: b.webhook_url,
+            commands: b.commands,
+            created_at: b.created_at,
+        }
+    }
+}
+
+/// Returned when a bot is created or its key rotated; the only time the key and the
+/// webhook secret are shown.
+#[derive(Debug, Serialize)]
+pub struct BotWithKey {
+    #[serde(flatten)]
+    pub bot: BotView,
+    pub api_key: String,
+    pub webhook_secret: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BotInstallation {
+    pub bot_id: String,
+    pub chat_id: String,
+    pub installed_by: String,
+    pub installed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBotRequest {
+    pub name: String,
+    pub webhook_url: Option<String>,
+    #[serde(default)]
+    pub commands: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BotMessageRequest {
+    pub content: String,
+}
+
+#[derive(Serialize)]
+struct WebhookCommand<'a> {
+    bot_id: &'a str,
+    command: &'a str,
+    args: &'a str,
+    chat_id: &'a str,
+    user_id: &'a str,
+}
+
+#[derive(Deserialize)]
+struct WebhookReply {
+    text: Option<String>,
+}
+
+fn sender_id(bot_id: &str) -> String {
+    format!("bot:{}", bot_id)
+}
+
+fn bots_coll(data: &AppState) -> mongodb::Collection<Bot> {
+    data.mongodb.db.collection::<Bot>("bots")
+}
+
+fn installs_coll(data: &AppState) -> mongodb::Collection<BotInstallation> {
+    data.mongodb.db.collection::<BotInstallation>("bot_installations")
+}
+
+fn new_api_key() -> String {
+    format!("tlb_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+fn new_webhook_secret() -> String {
+    format!("whsec_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// `sha256=` and the hex HMAC of `{timestamp}.{body}` under the bot's secret.
+fn webhook_signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let mut ctx = hmac::Context::with_key(&key);
+    ctx.update(format!("{}.", timestamp).as_bytes());
+    ctx.update(body);
+    let tag = ctx.sign();
+    let hex: String = tag.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+async fn validate_bot(req: &CreateBotRequest) -> Result<(), String> {
+    if req.name.trim().is_empty() {
+        return Err("Bot name must not be empty".to_string());
+    }
+    if let Some(url) = &req.webhook_url {
+        outbound::check_url(url).await.map_err(|e| format!("webhook_url: {}", e))?;
+    }
+    if !req.commands.is_empty() && req.webhook_url.is_none() {
+        return Err("Bots with commands need a webhook_url".to_string());
+    }
+    for c in &req.commands {
+        if c.is_empty() || !c.chars().all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '-' || ch == '_') {
+            return Err(format!("Invalid command name '{}'", c));
+        }
+        if BUILTIN_COMMANDS.iter().any(|(name, _)| name == c) {
+            return Err(format!("/{} is a built-in command", c));
+        }
+    }
+    Ok(())
+}
+
+/// Split "/name rest of line" into ("name", "rest of line").
+fn parse_command(content: &str) -> Option<(String, String)> {
+    let rest = content.trim().strip_prefix('/')?;
+    let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    (!name.is_empty()).then(|| (name.to_ascii_lowercase(), args.trim().to_string()))
+}
+
+/// Bots installed in a chat.
+async fn installed_bots(data: &AppState, chat_id: &str) -> mongodb::error::Result<Vec<Bot>> {
+    let ids = installs_coll(data).distinct("bot_id", doc! { "chat_id": chat_id }).await?;
+    let mut cursor = bots_coll(data).find(doc! { "bot_id": { "$in": ids } }).await?;
+    let mut bots = Vec::new();
+    while let Some(b) = cursor.next().await {
+        bots.push(b?);
+    }
+    Ok(bots)
+}
+
+async fn post_reply(data: &AppState, sender: String, chat_id: &str, text: String) -> Result<MessageResponse, String> {
+    let post = PostBotMessage { sender_id: sender, chat_id: chat_id.to_string(), content: text };
+    match data.chat_server.send(post).await {
+        Ok(Ok(msg)) => Ok(msg),
+        _ => Err("Failed to post the command reply".to_string()),
+    }
+}
+
+/// `/ticket create <project>: <title>`
+async fn ticket_command(data: &AppState, user_id: &str, chat: &Chat, args: &str) -> Result<String, String> {
+    let usage = || format!("Usage: {}", BUILTIN_COMMANDS[1].1);
+    let spec = args.strip_prefix("create").ok_or_else(usage)?.trim();
+    let (project_name, title) = spec.split_once(':').ok_or_else(usage)?;
+    let (project_name, title) = (project_name.trim(), title.trim());
+    if project_name.is_empty() || title.is_empty() {
+        return Err(usage());
+    }
+    let team_id = chat.team_id.as_deref().ok_or("This chat does not belong to a team")?;
+
+    let team = match TeamScope::of_member(&data.mongodb, team_id, user_id).await {
+        Ok(Some(team)) => team,
+        Ok(None) => return Err("You are not a member of this team".to_string()),
+        Err(e) => {
+            error!("Error checking team membership: {}", e);
+            return Err("Error checking team membership".to_string());
+        }
+    };
+    let name_filter = doc! { "name": { "$regex": format!("^{}$", escape(project_name)), "$options": "i" } };
+    let project = match Repo::<Project>::new(&data.mongodb, &team).find_one(name_filter).await {
+        Ok(Some(p)) => p,
+        Ok(None) => return Err(format!("No project named \"{}\" in this team", project_name)),
+        Err(e) => {
+            error!("Error fetching project: {}", e);
+            return Err("Error fetching project".to_string());
+        }
+    };
+    let member = data
+        .mongodb
+        .db
+        .collection::<mongodb::bson::Document>("project_memberships")
+        .find_one(doc! { "project_id": &project.project_id, "user_id": user_id })
+        .await;
+    if !matches!(member, Ok(Some(_))) {
+        return Err(format!("You are not a member of {}", project.name));
+    }
+    let scope = ProjectScope::of_project(&data.mongodb, &project.project_id).await.ok_or("Error fetching project")?;
+    let board = match Repo::<Board>::new(&data.mongodb, &scope).find(doc! {}).sort(doc! { "created_at": 1 }).limit(1).await {
+        Ok(mut cursor) => cursor.next().await.transpose(),
+        Err(e) => Err(e),
+    };
+    let board_id = match board {
+        Ok(Some(b)) => b.board_id,
+        Ok(None) => return Err(format!("{} has no board to put the ticket on", project.name)),
+        Err(e) => {
+            error!("Error fetching board: {}", e);
+            return Err("Error fetching board".to_string());
+        }
+    };
+    let assignee = auto_assignee(data, &scope, &board_id, &[]).await;
+    let ticket = Ticket {
+        id: None,
+        ticket_id: Uuid::new_v4().to_string(),
+        board_id,
+        project_id: project.project_id.clone(),
+        title: title.to_string(),
+        description: None,
+        status: "To Do".to_string(),
+        priority: None,
+        reporter: user_id.to_string(),
+        requester_email: None,
+        assignee,
+        due_date: None,
+        ticket_type: None,
+        sprint: None,
+        rank: None,
+        labels: None,
+        fix_version: None,
+        estimate_hours: None,
+        blocked_by: Vec::new(),
+        attachments: None,
+        comments: Some(vec![]),
+        references: Vec::new(),
+        created_at: Utc::now(),
+        resolution: None,
+        resolved_at: None,
+        resolved_by: None,
+        vote_count: 0,
+        version: 0,
+    };
+    match commit(&data.mongodb, None, vec![TicketChange::Created { ticket: Box::new(ticket) }], user_id).await {
+        Ok(Some(ticket)) => {
+            record_activity(&data.mongodb, ActivityEvent::new(
+                team_id,
+                Some(&project.project_id),
+                user_id,
+                "ticket_created",
+                &ticket.ticket_id,
+                format!("created ticket \"{}\"", ticket.title),
+            )).await;
+            Ok(format!("Created ticket \"{}\" in {} ({})", ticket.title, project.name, ticket.ticket_id))
+        }
+        Ok(None) => Err("Error creating ticket".to_string()),
+        Err(e) => {
+            error!("Error creating ticket from chat command: {:?}", e);
+            Err("Error creating ticket".to_string())
+        }
+    }
+}
+
+async fn call_webhook(bot: &Bot, command: &str, args: &str, chat_id: &str, user_id: &str) -> Result<Option<String>, String> {
+    let url = bot.webhook_url.as_deref().ok_or("This bot has no webhook")?;
+    let body = WebhookCommand { bot_id: &bot.bot_id, command, args, chat_id, user_id };
+    let body = serde_json::to_vec(&body).map_err(|e| {
+        error!("Error encoding webhook call for bot {}: {}", bot.bot_id, e);
+        format!("{} did not respond", bot.name)
+    })?;
+    let (client, url) = outbound::client_for(url).await.map_err(|e| {
+        warn!("Bot {} webhook refused: {}", bot.bot_id, e);
+        format!("{} did not respond", bot.name)
+    })?;
+    let mut request = client.post(url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+    if let Some(secret) = &bot.webhook_secret {
+        let timestamp = Utc::now().timestamp();
+        request = request
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, webhook_signature(secret.expose(), timestamp, &body));
+    }
+    let resp = request
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| {
+            warn!("Bot {} webhook unreachable: {}", bot.bot_id, e);
+            format!("{} did not respond", bot.name)
+        })?;
+    if !resp.status().is_success() {
+        warn!("Bot {} webhook answered {}", bot.bot_id, resp.status());
+        return Err(format!("{} failed to handle /{}", bot.name, command));
+    }
+    // An empty body means the bot handled the command without replying.
+    let text = resp.text().await.unwrap_or_default();
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    let reply: WebhookReply = serde_json::from_str(&text).map_err(|_| format!("{} sent an invalid reply", bot.name))?;
+    Ok(reply.text.filter(|t| !t.trim().is_empty()))
+}
+
+/// Run `content` as a slash command if it is one known in this chat. None means it
+/// is not a command and should be sent as a normal message; otherwise the reply, or
+/// an error for the sender alone.
+pub(crate) async fn run_command(
+    data: &AppState,
+    user_id: &str,
+    chat_id: &str,
+    content: &str,
+) -> Option<Result<CommandReply, String>> {
+    let (name, args) = parse_command(content)?;
+    let builtin = BUILTIN_COMMANDS.iter().any(|(n, _)| *n == name) && (name != "giphy" || giphy_enabled(data));
+    let bots = match installed_bots(data, chat_id).await {
+        Ok(b) => b,
+        Err(e) => {
+            error!("Error fetching installed bots: {}", e);
+            Vec::new()
+        }
+    };
+    let bot = bots.iter().find(|b| b.commands.contains(&name));
+    if !builtin && bot.is_none() {
+        return None;
+    }
+    let chat = match data.mongodb.db.collection::<Chat>("chats").find_one(doc! { "_id": chat_id, "participants": user_id }).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return Some(Err("You are not a participant in this chat".to_string())),
+        Err(e) => {
+            error!("Error fetching chat: {}", e);
+            return Some(Err("Error fetching chat".to_string()));
+        }
+    };
+
+    let (sender, reply) = match (name.as_str(), bot) {
+        ("help", _) => {
+            let mut lines: Vec<String> = BUILTIN_COMMANDS
+                .iter()
+                .filter(|(n, _)| *n != "giphy" || giphy_enabled(data))
+                .map(|(_, usage)| usage.to_string())
+                .collect();
+            for b in &bots {
+                lines.extend(b.commands.iter().map(|c| format!("/{} — {}", c, b.name)));
+            }
+            (SYSTEM_BOT.to_string(), Ok(Some(lines.join("\n"))))
+        }
+        ("ticket", _) => (SYSTEM_BOT.to_string(), ticket_command(data, user_id, &chat, &args).await.map(Some)),
+        ("remind", _) => {
+            info!("User {} ran /remind in chat {}", user_id, chat_id);
+            return Some(remind_command(data, user_id, chat_id, &args).await.map(CommandReply::Private));
+        }
+        ("giphy", _) => {
+            info!("User {} ran /giphy in chat {}", user_id, chat_id);
+            return Some(giphy_command(data, user_id, chat_id, &args).await.map(CommandReply::Posted));
+        }
+        (_, Some(bot)) => (sender_id(&bot.bot_id), call_webhook(bot, &name, &args, chat_id, user_id).await),
+        _ => return None,
+    };
+    info!("User {} ran /{} in chat {}", user_id, name, chat_id);
+    Some(match reply {
+        Ok(Some(text)) => post_reply(data, sender, chat_id, text).await.map(CommandReply::Posted),
+        Ok(None) => Ok(CommandReply::Silent),
+        Err(e) => Err(e),
+    })
+}
+
+/// POST /teams/{team_id}/bots
+pub async fn create_bot(
+    req: HttpRequest,
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<CreateBotRequest>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage bots");
+    }
+    if req.extensions().contains::<Impersonation>() {
+        return HttpResponse::Forbidden().body("Bots cannot be created while impersonating a user");
+    }
+    let req = payload.into_inner();
+    if let Err(msg) = validate_bot(&req).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    let api_key = new_api_key();
+    let webhook_secret = new_webhook_secret();
+    let bot = Bot {
+        bot_id: Uuid::new_v4().to_string(),
+        team_id: team_id.into_inner(),
+        name: req.name.trim().to_string(),
+        created_by: auth.user_id().to_string(),
+        api_key_hash: lookup_hash(&api_key),
+        webhook_url: req.webhook_url,
+        commands: req.commands,
+        webhook_secret: Some(EncryptedString::new(webhook_secret.clone())),
+        created_at: Utc::now(),
+    };
+    match bots_coll(&data).insert_one(&bot).await {
+        Ok(_) => HttpResponse::Created().json(BotWithKey { bot: bot.into(), api_key, webhook_secret }),
+        Err(e) => {
+            error!("Error creating bot: {}", e);
+            HttpResponse::InternalServerError().body("Error creating bot")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/bots
+pub async fn list_bots(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    let mut cursor = match bots_coll(&data).find(doc! { "team_id": &*team_id }).sort(doc! { "name": 1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching bots: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching bots");
+        }
+    };
+    let mut bots = Vec::new();
+    while let Some(b) = cursor.next().await {
+        match b {
+            Ok(b) => bots.push(BotView::from(b)),
+            Err(e) => {
+                error!("Error reading bots: {}", e);
+                return HttpResponse::InternalServerError().body("Error fetching bots");
+            }
+        }
+    }
+    HttpResponse::Ok().json(bots)
+}
+
+/// PUT /teams/{team_id}/bots/{bot_id}
+/// Renames the bot or changes its webhook and commands; the key and secret stay.
+pub async fn update_bot(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<CreateBotRequest>,
+) -> impl Responder {
+    let (team_id, bot_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage bots");
+    }
+    let req = payload.into_inner();
+    if let Err(msg) = validate_bot(&req).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    let update = doc! { "$set": { "name": req.name.trim(), "webhook_url": req.webhook_url, "commands": req.commands } };
+    match bots_coll(&data)
+        .find_one_and_update(doc! { "bot_id": &bot_id, "team_id": &team_id }, update)
+        .return_document(mongodb::options::ReturnDocument::After)
+        .await
+    {
+        Ok(Some(bot)) => HttpResponse::Ok().json(BotView::from(bot)),
+        Ok(None) => HttpResponse::NotFound().body("Bot not found"),
+        Err(e) => {
+            error!("Error updating bot: {}", e);
+            HttpResponse::InternalServerError().body("Error updating bot")
+        }
+    }
+}
+
+/// POST /teams/{team_id}/bots/{bot_id}/key
+/// Issues a new API key and webhook secret; the old ones stop working immediately.
+pub async fn rotate_bot_key(
+    req: HttpRequest,
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (team_id, bot_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage bots");
+    }
+    if req.extensions().contains::<Impersonation>() {
+        return HttpResponse::Forbidden().body("Bot keys cannot be issued while impersonating a user");
+    }
+    let api_key = new_api_key();
+    let webhook_secret = new_webhook_secret();
+    let sealed = match mongodb::bson::to_bson(&EncryptedString::new(webhook_secret.clone())) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Error sealing bot webhook secret: {}", e);
+            return HttpResponse::InternalServerError().body("Error rotating bot key");
+        }
+    };
+    match bots_coll(&data)
+        .find_one_and_update(
+            doc! { "bot_id": &bot_id, "team_id": &team_id },
+            doc! { "$set": { "api_key_hash": lookup_hash(&api_key), "webhook_secret": sealed } },
+        )
+        .return_document(mongodb::options::ReturnDocument::After)
+        .await
+    {
+        Ok(Some(bot)) => HttpResponse::Ok().json(BotWithKey { bot: bot.into(), api_key, webhook_secret }),
+        Ok(None) => HttpResponse::NotFound().body("Bot not found"),
+        Err(e) => {
+            error!("Error rotating bot key: {}", e);
+            HttpResponse::InternalServerError().body("Error rotating bot key")
+        }
+    }
+}
+
+/// DELETE /teams/{team_id}/bots/{bot_id}
+pub async fn delete_bot(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (team_id, bot_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage bots");
+    }
+    match bots_coll(&data).delete_one(doc! { "bot_id": &bot_id, "team_id": &team_id }).await {
+        Ok(res) if res.deleted_count == 1 => {
+            let _ = installs_coll(&data).delete_many(doc! { "bot_id": &bot_id }).await;
+            HttpResponse::Ok().body("Bot deleted")
+        }
+        Ok(_) => HttpResponse::NotFound().body("Bot not found"),
+        Err(e) => {
+            error!("Error deleting bot: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting bot")
+        }
+    }
+}
+
+/// The chat, if the caller may manage it.
+async fn admin_chat(auth: &AuthContext, data: &AppState, chat_id: &str) -> Result<Chat, HttpResponse> {
+    match data.mongodb.db.collection::<Chat>("chats").find_one(doc! { "_id": chat_id }).await {
+        Ok(Some(chat)) if chat.is_admin(auth.user_id()) => Ok(chat),
+        Ok(Some(_)) => Err(HttpResponse::Forbidden().body("Only chat admins can manage bots")),
+        Ok(None) => Err(HttpResponse::NotFound().body("Chat not found")),
+        Err(e) => Err(HttpResponse::InternalServerError().body(format!("DB error: {}", e))),
+    }
+}
+
+/// GET /chats/{chat_id}/bots
+pub async fn list_chat_bots(auth: AuthContext, data: web::Data<AppState>, chat_id: web::Path<String>) -> impl Responder {
+    let chats = data.mongodb.db.collection::<Chat>("chats");
+    match chats.find_one(doc! { "_id": &*chat_id, "participants": auth.user_id() }).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::Forbidden().error("not_chat_participant"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    }
+    match installed_bots(&data, &chat_id).await {
+        Ok(bots) => HttpResponse::Ok().json(bots.into_iter().map(BotView::from).collect::<Vec<_>>()),
+        Err(e) => {
+            error!("Error fetching installed bots: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching bots")
+        }
+    }
+}
+
+/// POST /chats/{chat_id}/bots/{bot_id}
+pub async fn install_bot(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (chat_id, bot_id) = path.into_inner();
+    let chat = match admin_chat(&auth, &data, &chat_id).await {
+        Ok(c) => c,
+        Err(resp) => return resp,
+    };
+    let bot = match bots_coll(&data).find_one(doc! { "bot_id": &bot_id }).await {
+        Ok(Some(b)) => b,
+        Ok(None) => return HttpResponse::NotFound().body("Bot not found"),
+        Err(e) => {
+            error!("Error fetching bot: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching bot");
+        }
+    };
+    if chat.team_id.as_deref() != Some(bot.team_id.as_str()) {
+        return HttpResponse::BadRequest().body("Bots can only be installed in chats of their team");
+    }
+    let installed = installs_coll(&data).find_one(doc! { "bot_id": &bot_id, "chat_id": &chat_id }).await;
+    if let Ok(Some(_)) = installed {
+        return HttpResponse::Conflict().body("Bot is already installed in this chat");
+    }
+    // Two bots answering the same command would make dispatch ambiguous.
+    if let Ok(others) = installed_bots(&data, &chat_id).await {
+        if let Some(clash) = bot.commands.iter().find(|c| others.iter().any(|o| o.commands.contains(c))) {
+            return HttpResponse::Conflict().body(format!("Another bot in this chat already handles /{}", clash));
+        }
+    }
+    let install = BotInstallation {
+        bot_id: bot_id.clone(),
+        chat_id: chat_id.clone(),
+        installed_by: auth.user_id().to_string(),
+        installed_at: Utc::now(),
+    };
+    match installs_coll(&data).insert_one(&install).await {
+        Ok(_) => HttpResponse::Created().json(install),
+        Err(e) => {
+            error!("Error installing bot: {}", e);
+            HttpResponse::InternalServerError().body("Error installing bot")
+        }
+    }
+}
+
+/// DELETE /chats/{chat_id}/bots/{bot_id}
+pub async fn uninstall_bot(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (chat_id, bot_id) = path.into_inner();
+    if let Err(resp) = admin_chat(&auth, &data, &chat_id).await {
+        return resp;
+    }
+    match installs_coll(&data).delete_one(doc! { "bot_id": &bot_id, "chat_id": &chat_id }).await {
+        Ok(res) if res.deleted_count == 1 => HttpResponse::Ok().body("Bot removed from chat"),
+        Ok(_) => HttpResponse::NotFound().body("Bot is not installed in this chat"),
+        Err(e) => {
+            error!("Error uninstalling bot: {}", e);
+            HttpResponse::InternalServerError().body("Error uninstalling bot")
+        }
+    }
+}
+
+/// POST /bot-api/chats/{chat_id}/messages
+/// Authenticated with the bot's key in `X-Bot-Key`, not a user session.
+pub async fn post_bot_message(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    chat_id: web::Path<String>,
+    payload: web::Json<BotMessageRequest>,
+) -> impl Responder {
+    let Some(key) = req.headers().get(BOT_KEY_HEADER).and_then(|h| h.to_str().ok()) else {
+        return HttpResponse::Unauthorized().body("Missing bot key");
+    };
+    let bot = match bots_coll(&data).find_one(doc! { "api_key_hash": lookup_hash(key.trim()) }).await {
+        Ok(Some(b)) => b,
+        Ok(None) => return HttpResponse::Unauthorized().body("Invalid bot key"),
+        Err(e) => {
+            error!("Error fetching bot: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching bot");
+        }
+    };
+    match installs_coll(&data).find_one(doc! { "bot_id": &bot.bot_id, "chat_id": &*chat_id }).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::Forbidden().body("Bot is not installed in this chat"),
+        Err(e) => {
+            error!("Error fetching bot installation: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching bot installation");
+        }
+    }
+    if payload.content.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Message content must not be empty");
+    }
+    match post_reply(&data, sender_id(&bot.bot_id), &chat_id, payload.into_inner().content).await {
+        Ok(msg) => HttpResponse::Ok().json(msg),
+        Err(e) => HttpResponse::InternalServerError().body(e),
+    }
+}
@@ -1,11 +1,22 @@
-use actix_web::{web, HttpResponse, Responder, HttpRequest, HttpMessage};
-use mongodb::bson::doc;
+use actix_web::{web, HttpResponse, Responder};
+use futures_util::StreamExt;
+use mongodb::bson::{self, doc, Document};
 use serde::{Serialize, Deserialize};
 use chrono::{Utc, DateTime};
 use uuid::Uuid;
 use log::{error};
 use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
 use crate::chat_server::RelaySignal;
+use crate::meeting_links::provision;
+use crate::response::CodedError;
+use crate::sync::{record_change, Entity, Op, Scope};
+use crate::team_management::resolve_user_id;
+use crate::tenancy::{ProjectsScope, Repo};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+
+/// Longest range of ticket deadlines returned at once.
+const MAX_RANGE_DAYS: i64 = 366;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CalendarEvent {
@@ -16,6 +27,24 @@ pub struct CalendarEvent {
     pub end: DateTime<Utc>,
     pub participants: Vec<String>,
     pub created_at: DateTime<Utc>,
+    /// Team the event belongs to; absent on events created before team scoping
+    #[serde(default)]
+    pub team_id: Option<String>,
+    /// Whether a notes document is created when the event ends; None follows the
+    /// team's setting (see meeting_notes.rs)
+    #[serde(default)]
+    pub meeting_notes: Option<bool>,
+    /// Knowledge base document created for the event's notes
+    #[serde(default)]
+    pub notes_doc_id: Option<String>,
+    /// Video meeting room, see meeting_links.rs
+    #[serde(default)]
+    pub meeting_url: Option<String>,
+    #[serde(default)]
+    pub meeting_provider: Option<String>,
+    /// Busy block of a focus session (see focus_sessions.rs)
+    #[serde(default)]
+    pub focus: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,39 +52,107 @@ pub struct CreateEventRequest {
     pub title: String,
     pub start: DateTime<Utc>,
     pub end: DateTime<Utc>,
+    /// User ids, emails or usernames
     pub participants: Vec<String>,
+    /// Owning team; inferred from the teams shared with every participant when omitted
+    pub team_id: Option<String>,
+    /// Overrides the team's meeting notes setting for this event
+    pub meeting_notes: Option<bool>,
+    /// Create a video meeting room when a provider is configured; defaults to true
+    pub meeting_link: Option<bool>,
 }
 
 pub async fn create_event(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     payload: web::Json<CreateEventRequest>,
 ) -> impl Responder {
-    let current_user = req.extensions().get::<String>().cloned().unwrap_or_default();
+    let current_user = auth.user_id().to_string();
 
     if payload.participants.iter().any(|p| p.is_empty()) {
         return HttpResponse::BadRequest().body("Invalid participant IDs provided.");
     }
 
+    // Resolve every participant to an existing user id.
+    let mut participants: Vec<String> = Vec::new();
+    for reference in &payload.participants {
+        match resolve_user_id(&data.mongodb, reference).await {
+            Some(id) if !participants.contains(&id) => participants.push(id),
+            Some(_) => {}
+            None => return HttpResponse::BadRequest().body(format!("Unknown participant: {}", reference)),
+        }
+    }
+
+    // The event's team must contain the creator and every participant.
+    if let Some(team_id) = &payload.team_id {
+        if !auth.is_team_member(team_id).await {
+            return HttpResponse::Unauthorized().error("not_team_member");
+        }
+    }
+    let mut candidates = match &payload.team_id {
+        Some(team_id) => vec![team_id.clone()],
+        None => match data.mongodb.user_team_ids(&current_user).await {
+            Ok(ids) => ids,
+            Err(e) => {
+                error!("Error fetching teams: {}", e);
+                return HttpResponse::InternalServerError().body("Error creating event");
+            }
+        },
+    };
+    for participant in &participants {
+        match data.mongodb.user_team_ids(participant).await {
+            Ok(ids) => candidates.retain(|t| ids.contains(t)),
+            Err(e) => {
+                error!("Error fetching teams: {}", e);
+                return HttpResponse::InternalServerError().body("Error creating event");
+            }
+        }
+    }
+    candidates.sort();
+    let team_id = match candidates.into_iter().next() {
+        Some(t) => t,
+        None => return HttpResponse::BadRequest().body("All participants must share a team with you"),
+    };
+
+    let event_id = Uuid::new_v4().to_string();
+    let link = if payload.meeting_link.unwrap_or(true) {
+        provision(&data, &event_id, &payload.title, payload.start, payload.end).await
+    } else {
+        None
+    };
+
     let new_event = CalendarEvent {
-        event_id: Uuid::new_v4().to_string(),
+        event_id,
         user_id: current_user.clone(),
         title: payload.title.clone(),
         start: payload.start,
         end: payload.end,
-        participants: payload.participants.clone(),
+        participants,
         created_at: Utc::now(),
+        team_id: Some(team_id),
+        meeting_notes: payload.meeting_notes,
+        notes_doc_id: None,
+        meeting_url: link.as_ref().map(|l| l.url.clone()),
+        meeting_provider: link.map(|l| l.provider.to_string()),
+        focus: false,
     };
 
     let collection = data.mongodb.db.collection::<CalendarEvent>("calendar_events");
     match collection.insert_one(&new_event).await {
         Ok(_) => {
-            for participant in &payload.participants {
+            let mut audience = new_event.participants.clone();
+            if !audience.contains(&new_event.user_id) {
+                audience.push(new_event.user_id.clone());
+            }
+            record_change(&data.mongodb, Entity::Event, &new_event.event_id, Op::Upsert, Scope::Users(&audience)).await;
+            for participant in &new_event.participants {
                 let message = serde_json::json!({
                     "type": "calendar_invite",
+                    "event_id": new_event.event_id,
                     "title": payload.title,
                     "start": payload.start,
-                    "end": payload.end
+                    "end": payload.end,
+                    "meeting_url": new_event.meeting_url,
                 }).to_string();
 
                 data.chat_server.do_send(RelaySignal {
@@ -74,13 +171,49 @@ pub async fn create_event(
     }
 }
 
+/// Teams the caller shares with `user_id`, or None when looking at their own
+/// calendar; an error when they share none.
+async fn shared_teams(auth: &AuthContext, data: &AppState, user_id: &str, error_body: &'static str) -> Result<Option<Vec<String>>, HttpResponse> {
+    if user_id == auth.user_id() {
+        return Ok(None);
+    }
+    let (mine, theirs) = match (
+        data.mongodb.user_team_ids(auth.user_id()).await,
+        data.mongodb.user_team_ids(user_id).await,
+    ) {
+        (Ok(mine), Ok(theirs)) => (mine, theirs),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Error fetching teams: {}", e);
+            return Err(HttpResponse::InternalServerError().body(error_body));
+        }
+    };
+    let shared: Vec<String> = mine.into_iter().filter(|t| theirs.contains(t)).collect();
+    if shared.is_empty() {
+        return Err(HttpResponse::Unauthorized().body("You do not share a team with this user"));
+    }
+    Ok(Some(shared))
+}
+
+/// Users see all of their own events; teammates only see the events of teams
+/// they share with them.
 pub async fn get_user_events(
+    auth: AuthContext,
     path: web::Path<String>,
     data: web::Data<AppState>,
 ) -> impl Responder {
-    let user_id = path.into_inner();
+    let user_id = match resolve_user_id(&data.mongodb, &path.into_inner()).await {
+        Some(id) => id,
+        None => return HttpResponse::NotFound().error("user_not_found"),
+    };
+    let mut filter = doc! { "participants": &user_id };
+    match shared_teams(&auth, &data, &user_id, "Error fetching events").await {
+        Ok(None) => {}
+        Ok(Some(shared)) => {
+            filter.insert("team_id", doc! { "$in": shared });
+        }
+        Err(resp) => return resp,
+    }
     let collection = data.mongodb.db.collection::<CalendarEvent>("calendar_events");
-    let filter = doc! { "participants": user_id };
 
     match collection.find(filter).await {
         Ok(mut cursor) => {
@@ -98,3 +231,131 @@ pub async fn get_user_events(
         }
     }
 }
+
+#[derive(Debug, Deserialize)]
+pub struct CalendarRangeQuery {
+    /// Defaults to 30 days ago
+    pub from: Option<DateTime<Utc>>,
+    /// Defaults to 90 days ahead
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// A ticket's due date shown as an all-day item next to the user's events.
+#[derive(Debug, Serialize)]
+pub struct TicketDeadline {
+    pub ticket_id: String,
+    pub project_id: String,
+    pub board_id: String,
+    pub title: String,
+    pub status: String,
+    pub priority: Option<String>,
+    pub due: DateTime<Utc>,
+    pub closed: bool,
+    pub overdue: bool,
+    /// Blocking tickets that are still open, so the agenda can flag deadlines
+    /// that depend on unfinished work
+    pub open_blockers: Vec<String>,
+}
+
+/// Projects among `team_ids` that the caller is a member of.
+async fn visible_projects(data: &AppState, user_id: &str, team_ids: &[String]) -> mongodb::error::Result<Vec<String>> {
+    let member_of = data.mongodb.db.collection::<Document>("project_memberships")
+        .distinct("project_id", doc! { "user_id": user_id })
+        .await?;
+    let mut projects = ProjectsScope::of_teams(&data.mongodb, team_ids).await?;
+    projects.retain(|p| member_of.iter().any(|m| m.as_str() == Some(p)));
+    Ok(projects.project_ids().to_vec())
+}
+
+/// GET /calendar/tickets/{user_id}?from=&to=
+/// Tickets assigned to the user with a due date in the range, as deadline items.
+/// Other users' tickets are limited to projects of shared teams the caller belongs to.
+pub async fn get_user_ticket_deadlines(
+    auth: AuthContext,
+    path: web::Path<String>,
+    query: web::Query<CalendarRangeQuery>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let user_id = match resolve_user_id(&data.mongodb, &path.into_inner()).await {
+        Some(id) => id,
+        None => return HttpResponse::NotFound().error("user_not_found"),
+    };
+    let now = Utc::now();
+    let from = query.from.unwrap_or(now - chrono::Duration::days(30));
+    let to = query.to.unwrap_or(now + chrono::Duration::days(90));
+    if to < from || (to - from).num_days() > MAX_RANGE_DAYS {
+        return HttpResponse::BadRequest().body(format!("The range must end after it starts and span at most {} days", MAX_RANGE_DAYS));
+    }
+
+    // due_date is stored as an RFC 3339 string, so the range compares strings.
+    let filter = doc! {
+        "assignee": &user_id,
+        "due_date": { "$gte": bson::to_bson(&from).unwrap_or_default(), "$lte": bson::to_bson(&to).unwrap_or_default() },
+    };
+    let mut scope = match ProjectsScope::of_user(&data.mongodb, auth.user_id()).await {
+        Ok(scope) => scope,
+        Err(e) => {
+            error!("Error fetching projects: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_tickets");
+        }
+    };
+    match shared_teams(&auth, &data, &user_id, "Error fetching tickets").await {
+        Ok(None) => {}
+        Ok(Some(shared)) => match visible_projects(&data, auth.user_id(), &shared).await {
+            Ok(projects) => scope.retain(|p| projects.contains(p)),
+            Err(e) => {
+                error!("Error fetching projects: {}", e);
+                return HttpResponse::InternalServerError().error("error_fetching_tickets");
+            }
+        },
+        Err(resp) => return resp,
+    }
+
+    let tickets_coll = Repo::<Ticket>::across(&data.mongodb, &scope);
+    let mut tickets = Vec::new();
+    match tickets_coll.find(filter).sort(doc! { "due_date": 1 }).await {
+        Ok(mut cursor) => {
+            while let Some(t) = cursor.next().await {
+                match t {
+                    Ok(t) => tickets.push(t),
+                    Err(e) => error!("Error reading ticket: {}", e),
+                }
+            }
+        }
+        Err(e) => {
+            error!("Error fetching tickets: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_tickets");
+        }
+    }
+
+    let blocker_ids: Vec<&String> = tickets.iter().flat_map(|t| &t.blocked_by).collect();
+    let open_filter = doc! { "ticket_id": { "$in": blocker_ids }, "status": { "$nin": CLOSED_STATUSES.to_vec() } };
+    let open: Vec<String> = match tickets_coll.distinct("ticket_id", open_filter).await {
+        Ok(ids) => ids.iter().filter_map(|v| v.as_str().map(str::to_string)).collect(),
+        Err(e) => {
+            error!("Error fetching blocking tickets: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_tickets");
+        }
+    };
+
+    let deadlines: Vec<TicketDeadline> = tickets
+        .into_iter()
+        .filter_map(|t| {
+            let due = t.due_date?;
+            let closed = CLOSED_STATUSES.contains(&t.status.as_str());
+            Some(TicketDeadline {
+                open_blockers: t.blocked_by.iter().filter(|b| open.contains(b)).cloned().collect(),
+                ticket_id: t.ticket_id,
+                project_id: t.project_id,
+                board_id: t.board_id,
+                title: t.title,
+                status: t.status,
+                priority: t.priority,
+                due,
+                closed,
+                overdue: !closed && due < now,
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(deadlines)
+}
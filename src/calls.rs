@@ -0,0 +1,391 @@
+// src/calls.rs
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use mongodb::bson::{doc, to_bson, Bson, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat::{Chat, DBMessage};
+use crate::chat_server::RelaySignal;
+
+/// A voice/video call inside a chat. State moves ringing -> active -> ended.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CallSession {
+    pub call_id: String,
+    pub chat_id: String,
+    pub initiator_id: String,
+    /// "audio" or "video"
+    pub media: String,
+    /// "ringing", "active" or "ended"
+    pub state: String,
+    pub participants: Vec<CallParticipant>,
+    pub started_at: DateTime<Utc>,
+    pub answered_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+    /// True while the call is ringing or active. A partial unique index on chat_id
+    /// over open calls keeps each chat to one call at a time.
+    #[serde(default)]
+    pub open: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CallParticipant {
+    pub user_id: String,
+    pub joined_at: DateTime<Utc>,
+    pub left_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartCallRequest {
+    pub media: Option<String>,
+}
+
+impl CallSession {
+    fn is_connected(&self, user_id: &str) -> bool {
+        self.participants.iter().any(|p| p.user_id == user_id && p.left_at.is_none())
+    }
+}
+
+/// Filter value matching calls that have not ended.
+fn open_states() -> Document {
+    doc! { "$in": ["ringing", "active"] }
+}
+
+/// Aggregation expression counting the participants still connected to a call.
+fn connected_count() -> Document {
+    doc! { "$size": { "$filter": { "input": "$participants", "cond": { "$eq": ["$$this.left_at", null] } } } }
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(&*e.kind, ErrorKind::Write(WriteFailure::WriteError(w)) if w.code == 11000)
+}
+
+fn date_bson(at: DateTime<Utc>) -> Result<Bson, HttpResponse> {
+    to_bson(&at).map_err(|_| HttpResponse::InternalServerError().body("Error serializing call"))
+}
+
+fn notify(data: &AppState, user_id: &str, chat_id: &str, event: &str, call: &CallSession) {
+    let message = serde_json::json!({
+        "type": event,
+        "call": call,
+    })
+    .to_string();
+    data.chat_server.do_send(RelaySignal {
+        user_id: user_id.to_string(),
+        chat_id: chat_id.to_string(),
+        message,
+    });
+}
+
+fn format_duration(secs: i64) -> String {
+    let secs = secs.max(0);
+    if secs >= 3600 {
+        format!("{}h {}m {}s", secs / 3600, (secs % 3600) / 60, secs % 60)
+    } else {
+        format!("{}m {}s", secs / 60, secs % 60)
+    }
+}
+
+/// Persist the call log as a system message in the chat.
+async fn write_call_log(data: &AppState, call: &CallSession) {
+    let ended = call.ended_at.unwrap_or_else(Utc::now);
+    let content = match call.answered_at {
+        Some(answered) => format!(
+            "{} call ended (duration {})",
+            call.media,
+            format_duration((ended - answered).num_seconds())
+        ),
+        None => format!("Missed {} call", call.media),
+    };
+    let log_msg = DBMessage {
+        id: Uuid::new_v4().to_string(),
+        id_chat: call.chat_id.clone(),
+        sender_id: call.initiator_id.clone(),
+        content,
+        created_at: ended,
+        msg_type: "system".to_string(),
+        attachments: None,
+    };
+    let messages = data.mongodb.db.collection::<DBMessage>("messages");
+    if let Err(e) = messages.insert_one(&log_msg).await {
+        error!("Error writing call log for {}: {}", call.call_id, e);
+    }
+}
+
+async fn load_call(data: &AppState, chat_id: &str, call_id: &str) -> Result<CallSession, HttpResponse> {
+    let calls = data.mongodb.db.collection::<CallSession>("call_sessions");
+    match calls.find_one(doc! { "call_id": call_id, "chat_id": chat_id }).await {
+        Ok(Some(call)) => Ok(call),
+        Ok(None) => Err(HttpResponse::NotFound().body("Call not found")),
+        Err(e) => Err(HttpResponse::InternalServerError().body(format!("DB error: {}", e))),
+    }
+}
+
+async fn require_chat_participant(data: &AppState, chat_id: &str, user_id: &str) -> Result<(), HttpResponse> {
+    let chats = data.mongodb.db.collection::<Chat>("chats");
+    match chats.find_one(doc! { "_id": chat_id, "participants": user_id }).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(HttpResponse::Forbidden().body("Not a participant")),
+        Err(e) => Err(HttpResponse::InternalServerError().body(format!("DB error: {}", e))),
+    }
+}
+
+// ----------------------------------------------------------------------
+// POST /chats/{chat_id}/calls => start a call and ring the other participants
+// ----------------------------------------------------------------------
+pub async fn start_call(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    chat_id_path: web::Path<String>,
+    payload: web::Json<StartCallRequest>,
+) -> impl Responder {
+    let user_id = auth.user_id().to_string();
+    let chat_id = chat_id_path.into_inner();
+    if let Err(resp) = require_chat_participant(&data, &chat_id, &user_id).await {
+        return resp;
+    }
+
+    let media = match payload.media.as_deref() {
+        None | Some("audio") => "audio",
+        Some("video") => "video",
+        Some(_) => return HttpResponse::BadRequest().body("media must be 'audio' or 'video'"),
+    };
+
+    let now = Utc::now();
+    let call = CallSession {
+        call_id: Uuid::new_v4().to_string(),
+        chat_id: chat_id.clone(),
+        initiator_id: user_id.clone(),
+        media: media.to_string(),
+        state: "ringing".to_string(),
+        participants: vec![CallParticipant { user_id: user_id.clone(), joined_at: now, left_at: None }],
+        started_at: now,
+        answered_at: None,
+        ended_at: None,
+        open: true,
+    };
+    let calls = data.mongodb.db.collection::<CallSession>("call_sessions");
+    match calls.insert_one(&call).await {
+        Ok(_) => {}
+        Err(e) if is_duplicate_key(&e) => {
+            return HttpResponse::Conflict().body("A call is already in progress in this chat")
+        }
+        Err(e) => {
+            error!("Error creating call: {}", e);
+            return HttpResponse::InternalServerError().body("Error creating call");
+        }
+    }
+
+    info!("User {} started a {} call in chat {}", user_id, media, chat_id);
+    notify(&data, &user_id, &chat_id, "call_ringing", &call);
+    HttpResponse::Ok().json(call)
+}
+
+// ----------------------------------------------------------------------
+// GET /chats/{chat_id}/calls/{call_id}
+// ----------------------------------------------------------------------
+pub async fn get_call(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (chat_id, call_id) = path.into_inner();
+    let user_id = auth.user_id().to_string();
+    if let Err(resp) = require_chat_participant(&data, &chat_id, &user_id).await {
+        return resp;
+    }
+    match load_call(&data, &chat_id, &call_id).await {
+        Ok(call) => HttpResponse::Ok().json(call),
+        Err(resp) => resp,
+    }
+}
+
+// ----------------------------------------------------------------------
+// POST /chats/{chat_id}/calls/{call_id}/join
+// ----------------------------------------------------------------------
+pub async fn join_call(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (chat_id, call_id) = path.into_inner();
+    let user_id = auth.user_id().to_string();
+    if let Err(resp) = require_chat_participant(&data, &chat_id, &user_id).await {
+        return resp;
+    }
+    let call = match load_call(&data, &chat_id, &call_id).await {
+        Ok(call) => call,
+        Err(resp) => return resp,
+    };
+    if call.state == "ended" {
+        return HttpResponse::BadRequest().body("Call has already ended");
+    }
+    if call.is_connected(&user_id) {
+        return HttpResponse::Ok().json(call);
+    }
+
+    let now = Utc::now();
+    let now_bson = match date_bson(now) {
+        Ok(b) => b,
+        Err(resp) => return resp,
+    };
+    let participant = match to_bson(&CallParticipant { user_id: user_id.clone(), joined_at: now, left_at: None }) {
+        Ok(p) => p,
+        Err(_) => return HttpResponse::InternalServerError().body("Error serializing call"),
+    };
+    // Every update is conditional on the call still being open, so a join that races
+    // an end cannot bring the call back.
+    let calls = data.mongodb.db.collection::<CallSession>("call_sessions");
+    let rejoin = doc! {
+        "call_id": &call_id,
+        "chat_id": &chat_id,
+        "state": open_states(),
+        "participants": { "$elemMatch": { "user_id": &user_id, "left_at": { "$ne": null } } },
+    };
+    let set_back = doc! { "$set": { "participants.$.joined_at": &now_bson, "participants.$.left_at": Bson::Null } };
+    let joined = match calls.update_one(rejoin, set_back).await {
+        Ok(r) if r.matched_count > 0 => Ok(()),
+        Ok(_) => {
+            let first_join = doc! {
+                "call_id": &call_id,
+                "chat_id": &chat_id,
+                "state": open_states(),
+                "participants.user_id": { "$ne": &user_id },
+            };
+            calls.update_one(first_join, doc! { "$push": { "participants": participant } }).await.map(|_| ())
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = joined {
+        return HttpResponse::InternalServerError().body(format!("Failed update: {}", e));
+    }
+
+    let answer = doc! {
+        "call_id": &call_id,
+        "state": "ringing",
+        "$expr": { "$gt": [connected_count(), 1] },
+    };
+    if let Err(e) = calls.update_one(answer, doc! { "$set": { "state": "active", "answered_at": &now_bson } }).await {
+        return HttpResponse::InternalServerError().body(format!("Failed update: {}", e));
+    }
+
+    let call = match load_call(&data, &chat_id, &call_id).await {
+        Ok(call) => call,
+        Err(resp) => return resp,
+    };
+    if !call.is_connected(&user_id) {
+        return HttpResponse::BadRequest().body("Call has already ended");
+    }
+    notify(&data, &user_id, &chat_id, "call_joined", &call);
+    HttpResponse::Ok().json(call)
+}
+
+// ----------------------------------------------------------------------
+// POST /chats/{chat_id}/calls/{call_id}/leave
+//    The call ends once fewer than two participants remain connected.
+// ----------------------------------------------------------------------
+pub async fn leave_call(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (chat_id, call_id) = path.into_inner();
+    let user_id = auth.user_id().to_string();
+    if let Err(resp) = require_chat_participant(&data, &chat_id, &user_id).await {
+        return resp;
+    }
+
+    let now_bson = match date_bson(Utc::now()) {
+        Ok(b) => b,
+        Err(resp) => return resp,
+    };
+    let calls = data.mongodb.db.collection::<CallSession>("call_sessions");
+    let connected = doc! {
+        "call_id": &call_id,
+        "chat_id": &chat_id,
+        "state": open_states(),
+        "participants": { "$elemMatch": { "user_id": &user_id, "left_at": null } },
+    };
+    match calls.update_one(connected, doc! { "$set": { "participants.$.left_at": now_bson } }).await {
+        Ok(r) if r.matched_count > 0 => {}
+        Ok(_) => {
+            // Either the call is over or the caller is not connected to it.
+            return match load_call(&data, &chat_id, &call_id).await {
+                Ok(call) if call.state == "ended" => HttpResponse::Ok().json(call),
+                Ok(_) => HttpResponse::BadRequest().body("You are not in this call"),
+                Err(resp) => resp,
+            };
+        }
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Failed update: {}", e)),
+    }
+
+    // An active call needs two connected participants; a ringing one ends with nobody left.
+    let deserted = doc! {
+        "$expr": { "$lt": [connected_count(), { "$cond": [{ "$eq": ["$state", "active"] }, 2, 1] }] },
+    };
+    finish(&data, &user_id, &chat_id, &call_id, deserted).await
+}
+
+// ----------------------------------------------------------------------
+// POST /chats/{chat_id}/calls/{call_id}/end => hang up for everyone
+// ----------------------------------------------------------------------
+pub async fn end_call(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (chat_id, call_id) = path.into_inner();
+    let user_id = auth.user_id().to_string();
+    if let Err(resp) = require_chat_participant(&data, &chat_id, &user_id).await {
+        return resp;
+    }
+    finish(&data, &user_id, &chat_id, &call_id, doc! {}).await
+}
+
+/// Ends the call if it is still open and matches `condition`, then responds with its
+/// current state. Only the request whose update ends the call writes the call log.
+async fn finish(data: &AppState, user_id: &str, chat_id: &str, call_id: &str, condition: Document) -> HttpResponse {
+    let now_bson = match date_bson(Utc::now()) {
+        Ok(b) => b,
+        Err(resp) => return resp,
+    };
+    let calls = data.mongodb.db.collection::<CallSession>("call_sessions");
+    let mut filter = doc! { "call_id": call_id, "chat_id": chat_id, "state": open_states() };
+    filter.extend(condition);
+    let update = doc! {
+        "$set": {
+            "state": "ended",
+            "open": false,
+            "ended_at": &now_bson,
+            "participants.$[p].left_at": &now_bson,
+        }
+    };
+    let ended = calls
+        .find_one_and_update(filter, update)
+        .array_filters(vec![doc! { "p.left_at": null }])
+        .return_document(ReturnDocument::After)
+        .await;
+    match ended {
+        Ok(Some(call)) => {
+            write_call_log(data, &call).await;
+            notify(data, user_id, chat_id, "call_ended", &call);
+            HttpResponse::Ok().json(call)
+        }
+        Ok(None) => match load_call(data, chat_id, call_id).await {
+            Ok(call) => {
+                if call.state != "ended" {
+                    notify(data, user_id, chat_id, "call_left", &call);
+                }
+                HttpResponse::Ok().json(call)
+            }
+            Err(resp) => resp,
+        },
+        Err(e) => HttpResponse::InternalServerError().body(format!("Failed update: {}", e)),
+    }
+}
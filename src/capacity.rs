@@ -0,0 +1,314 @@
+// src/capacity.rs
+//
+// Team capacity over a date range. A member's available hours are their working
+// hours on working days (weekdays that are not team holidays), minus the time taken by calendar events they own or attend.
+// These are compared with the estimates of their open tickets due in the range, and
+// members whose assigned work exceeds what they have available are flagged.
+// Working hours and range dates are read in the team's time zone.
+
+use std::collections::{HashMap, HashSet};
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Datelike, Days, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::calendar::CalendarEvent;
+use crate::chat_db::MongoDB;
+use crate::holidays::{is_working_day, non_working_days};
+use crate::response::ok;
+use crate::sprint_planning::working_window;
+use crate::team_time::{local_today, team_timezone};
+use crate::tenancy::{Repo, TeamScope};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+
+const DEFAULT_RANGE_DAYS: i64 = 14;
+const MAX_RANGE_DAYS: i64 = 92;
+/// Years a range may fall in, keeping date arithmetic on it far from chrono's limits.
+const RANGE_YEARS: std::ops::RangeInclusive<i32> = 1970..=9999;
+
+#[derive(Debug, Deserialize)]
+pub struct CapacityQuery {
+    /// "YYYY-MM-DD..YYYY-MM-DD", both days included; the next two weeks when omitted
+    pub range: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemberCapacity {
+    pub user_id: String,
+    pub username: Option<String>,
+    pub working_hours: f64,
+    /// Working time taken by calendar events
+    pub event_hours: f64,
+    pub available_hours: f64,
+    pub assigned_hours: f64,
+    pub assigned_tickets: usize,
+    /// Open tickets due in the range without an estimate; not part of assigned_hours
+    pub unestimated_tickets: usize,
+    /// None when the member has no available hours
+    pub utilization_percent: Option<f64>,
+    pub over_allocated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamCapacity {
+    pub team_id: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub timezone: String,
+    pub working_days: usize,
+    /// Team holidays falling on weekdays in the range
+    pub holidays: Vec<NaiveDate>,
+    pub available_hours: f64,
+    pub assigned_hours: f64,
+    pub over_allocated: Vec<String>,
+    pub members: Vec<MemberCapacity>,
+}
+
+struct Member {
+    user_id: String,
+    username: Option<String>,
+    window: (NaiveTime, NaiveTime),
+}
+
+/// Used for members who have not set their working hours.
+fn default_window() -> (NaiveTime, NaiveTime) {
+    (NaiveTime::from_hms_opt(9, 0, 0).expect("valid time"), NaiveTime::from_hms_opt(17, 0, 0).expect("valid time"))
+}
+
+fn parse_range(range: Option<&str>, today: NaiveDate) -> Result<(NaiveDate, NaiveDate), String> {
+    let Some(range) = range else {
+        let end = today.checked_add_days(Days::new(DEFAULT_RANGE_DAYS as u64 - 1)).ok_or("Invalid date range")?;
+        return Ok((today, end));
+    };
+    let (start, end) = range.split_once("..").ok_or("range must look like YYYY-MM-DD..YYYY-MM-DD")?;
+    let parse = |s: &str| {
+        NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d").map_err(|_| format!("Invalid date '{}' in range", s))
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+    if !RANGE_YEARS.contains(&start.year()) || !RANGE_YEARS.contains(&end.year()) {
+        return Err(format!("range must fall within the years {} to {}", RANGE_YEARS.start(), RANGE_YEARS.end()));
+    }
+    if end < start {
+        return Err("range end is before its start".to_string());
+    }
+    if (end - start).num_days() >= MAX_RANGE_DAYS {
+        return Err(format!("range may span at most {} days", MAX_RANGE_DAYS));
+    }
+    Ok((start, end))
+}
+
+fn working_days(start: NaiveDate, end: NaiveDate, holidays: &HashSet<NaiveDate>) -> Vec<NaiveDate> {
+    start.iter_days().take_while(|d| *d <= end).filter(|d| is_working_day(*d, holidays)).collect()
+}
+
+fn local_to_utc(tz: Tz, date: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&date.and_time(time)).earliest().map(|d| d.with_timezone(&Utc))
+}
+
+/// Time within `from..to` covered by any of the events, counting overlaps once.
+fn busy_within(from: DateTime<Utc>, to: DateTime<Utc>, events: &[(DateTime<Utc>, DateTime<Utc>)]) -> Duration {
+    let mut spans: Vec<_> = events
+        .iter()
+        .filter_map(|&(s, e)| {
+            let (s, e) = (s.max(from), e.min(to));
+            (s < e).then_some((s, e))
+        })
+        .collect();
+    spans.sort();
+    let mut busy = Duration::zero();
+    let mut covered = from;
+    for (s, e) in spans {
+        let s = s.max(covered);
+        if e > s {
+            busy += e - s;
+            covered = e;
+        }
+    }
+    busy
+}
+
+fn round_hours(h: f64) -> f64 {
+    (h * 10.0).round() / 10.0
+}
+
+async fn load_members(db: &MongoDB, team_id: &str) -> mongodb::error::Result<Vec<Member>> {
+    let mut ids = Vec::new();
+    let mut cursor = db.db.collection::<Document>("user_teams").find(doc! { "team_id": team_id }).await?;
+    while let Some(m) = cursor.next().await {
+        if let Ok(uid) = m?.get_str("user_id") {
+            ids.push(uid.to_string());
+        }
+    }
+
+    let oids: Vec<ObjectId> = ids.iter().filter_map(|id| ObjectId::parse_str(id).ok()).collect();
+    let mut users = HashMap::new();
+    let mut cursor = db.db.collection::<Document>("users").find(doc! { "_id": { "$in": oids } }).await?;
+    while let Some(user) = cursor.next().await {
+        let user = user?;
+        if let Ok(oid) = user.get_object_id("_id") {
+            users.insert(oid.to_hex(), user);
+        }
+    }
+
+    Ok(ids
+        .into_iter()
+        .map(|user_id| {
+            let user = users.get(&user_id);
+            let field = |key: &str| user.and_then(|u| u.get_str(key).ok());
+            Member {
+                username: field("username").map(String::from),
+                window: working_window(field("working_hours_start"), field("working_hours_end"))
+                    .unwrap_or_else(default_window),
+                user_id,
+            }
+        })
+        .collect())
+}
+
+/// Calendar events per member, from every team: a meeting elsewhere still takes the time.
+async fn member_events(
+    db: &MongoDB,
+    member_ids: &[String],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> mongodb::error::Result<HashMap<String, Vec<(DateTime<Utc>, DateTime<Utc>)>>> {
+    let filter = doc! { "$or": [
+        { "user_id": { "$in": member_ids } },
+        { "participants": { "$in": member_ids } },
+    ] };
+    let mut cursor = db.db.collection::<CalendarEvent>("calendar_events").find(filter).await?;
+    let mut events: HashMap<String, Vec<_>> = HashMap::new();
+    while let Some(event) = cursor.next().await {
+        let event = event?;
+        // Focus blocks are time spent on tickets, not taken from them.
+        if event.focus || event.end <= from || event.start >= to {
+            continue;
+        }
+        let attendees: HashSet<&String> = event.participants.iter().chain(std::iter::once(&event.user_id)).collect();
+        for id in attendees {
+            if member_ids.contains(id) {
+                events.entry(id.clone()).or_default().push((event.start, event.end));
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// Open tickets in the team's projects assigned to a member and due in the range.
+async fn assigned_tickets(
+    db: &MongoDB,
+    team: &TeamScope,
+    member_ids: &[String],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> mongodb::error::Result<Vec<Ticket>> {
+    let projects = team.projects(db).await?;
+    let filter = doc! {
+        "assignee": { "$in": member_ids },
+        "status": { "$nin": CLOSED_STATUSES.to_vec() },
+    };
+    let mut cursor = Repo::<Ticket>::across(db, &projects).find(filter).await?;
+    let mut tickets = Vec::new();
+    while let Some(ticket) = cursor.next().await {
+        let ticket = ticket?;
+        if ticket.due_date.is_some_and(|due| due >= from && due < to) {
+            tickets.push(ticket);
+        }
+    }
+    Ok(tickets)
+}
+
+async fn build(db: &MongoDB, team: &TeamScope, tz: Tz, start: NaiveDate, end: NaiveDate) -> mongodb::error::Result<TeamCapacity> {
+    let midnight = NaiveTime::MIN;
+    let from = local_to_utc(tz, start, midnight).unwrap_or_else(|| start.and_time(midnight).and_utc());
+    let next = end + Duration::days(1);
+    let to = local_to_utc(tz, next, midnight).unwrap_or_else(|| next.and_time(midnight).and_utc());
+
+    let team_id = team.team_id();
+    let members = load_members(db, team_id).await?;
+    let ids: Vec<String> = members.iter().map(|m| m.user_id.clone()).collect();
+    let events = member_events(db, &ids, from, to).await?;
+    let tickets = assigned_tickets(db, team, &ids, from, to).await?;
+    let holidays = non_working_days(db, team_id, start, end).await?;
+    let days = working_days(start, end, &holidays);
+    let mut holidays: Vec<NaiveDate> = holidays.into_iter().filter(|d| is_working_day(*d, &HashSet::new())).collect();
+    holidays.sort();
+
+    let mut out = Vec::with_capacity(members.len());
+    for m in members {
+        let mut working = Duration::zero();
+        let mut busy = Duration::zero();
+        let own_events = events.get(&m.user_id).map(Vec::as_slice).unwrap_or_default();
+        for day in &days {
+            let (Some(day_start), Some(day_end)) = (local_to_utc(tz, *day, m.window.0), local_to_utc(tz, *day, m.window.1)) else {
+                continue;
+            };
+            working += day_end - day_start;
+            busy += busy_within(day_start, day_end, own_events);
+        }
+
+        let own_tickets: Vec<&Ticket> = tickets.iter().filter(|t| t.assignee.as_ref() == Some(&m.user_id)).collect();
+        let assigned: f64 = own_tickets.iter().filter_map(|t| t.estimate_hours).sum();
+        let available = (working - busy).num_minutes() as f64 / 60.0;
+        out.push(MemberCapacity {
+            user_id: m.user_id,
+            username: m.username,
+            working_hours: round_hours(working.num_minutes() as f64 / 60.0),
+            event_hours: round_hours(busy.num_minutes() as f64 / 60.0),
+            available_hours: round_hours(available),
+            assigned_hours: round_hours(assigned),
+            assigned_tickets: own_tickets.len(),
+            unestimated_tickets: own_tickets.iter().filter(|t| t.estimate_hours.is_none()).count(),
+            utilization_percent: (available > 0.0).then(|| round_hours(assigned / available * 100.0)),
+            over_allocated: assigned > available,
+        });
+    }
+    out.sort_by(|a, b| {
+        let name = |m: &MemberCapacity| m.username.clone().unwrap_or_else(|| m.user_id.clone()).to_lowercase();
+        name(a).cmp(&name(b))
+    });
+
+    Ok(TeamCapacity {
+        team_id: team_id.to_string(),
+        start,
+        end,
+        timezone: tz.name().to_string(),
+        working_days: days.len(),
+        holidays,
+        available_hours: round_hours(out.iter().map(|m| m.available_hours).sum()),
+        assigned_hours: round_hours(out.iter().map(|m| m.assigned_hours).sum()),
+        over_allocated: out.iter().filter(|m| m.over_allocated).map(|m| m.user_id.clone()).collect(),
+        members: out,
+    })
+}
+
+/// GET /teams/{team_id}/capacity?range=2024-06-03..2024-06-14
+pub async fn get_team_capacity(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    query: web::Query<CapacityQuery>,
+) -> impl Responder {
+    let team = match auth.team_scope(&team_id).await {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    let tz = team_timezone(&data.mongodb, &team_id).await;
+    let (start, end) = match parse_range(query.range.as_deref(), local_today(tz)) {
+        Ok(r) => r,
+        Err(msg) => return HttpResponse::BadRequest().body(msg),
+    };
+    match build(&data.mongodb, &team, tz, start, end).await {
+        Ok(capacity) => ok(capacity),
+        Err(e) => {
+            error!("Error computing team capacity: {}", e);
+            HttpResponse::InternalServerError().body("Error computing team capacity")
+        }
+    }
+}
@@ -0,0 +1,481 @@
+// src/charts.rs
+//
+// Burndown, velocity and budget charts rendered to PNG on the server, for digest
+// emails and chat integrations that cannot run the web app's JavaScript charts. The
+// images are drawn with plotters into an in-memory bitmap and encoded with `image`:
+// axes, grid lines, numeric tick labels in DejaVu Sans (embedded from assets/fonts,
+// so rendering does not depend on the host's fonts), lines and bars. Titles and
+// legends belong in the surrounding message. Rendering runs on the blocking pool.
+//
+// Besides the usual authentication, a chart URL can carry a signature
+// (`exp` and `sig`, see `signed_chart_url`) so that mail clients and chat services
+// can fetch it without a session. A signature covers the exact path and query and
+// expires after LINK_DAYS; it is made with a key derived for chart links alone, so
+// it is never a valid token signature or the other way round.
+//
+// Burndown: a sprint starts when its first ticket was put into it (or created in it)
+// and lasts the board's `sprint_length` in weeks, two without one. The remaining
+// line is the estimated hours of sprint tickets not yet resolved at the end of each
+// day, or their number when no ticket of the sprint is estimated; the dashed line is
+// the ideal burn from the first day's remaining work.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Once;
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Datelike, Utc};
+use futures_util::StreamExt;
+use image::{ImageFormat, RgbImage};
+use log::error;
+use mongodb::bson::{doc, from_bson, Bson, Document};
+use plotters::prelude::*;
+use ring::{hkdf, hmac};
+use serde::Deserialize;
+
+use crate::api::V1_PREFIX;
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::{can_edit_board, Board, SPRINT_WEEKS};
+use crate::dashboard_data::BudgetInput;
+use crate::response::CodedError;
+use crate::team_time::timestamp;
+use crate::tenancy::{ProjectScope, Repo};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 320;
+/// Space around the chart and for the axis labels, in pixels
+const MARGIN: u32 = 16;
+const X_LABEL_AREA: u32 = 28;
+const Y_LABEL_AREA: u32 = 56;
+const FONT: (&str, u32) = ("DejaVu Sans", 13);
+static FONT_DATA: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+static REGISTER_FONT: Once = Once::new();
+/// Longer x axes only label every few values
+const MAX_X_LABELS: usize = 12;
+const LINK_DAYS: i64 = 30;
+const DEFAULT_SPRINT_WEEKS: i64 = 2;
+const VELOCITY_SPRINTS: i64 = 12;
+
+const BACKGROUND: RGBColor = RGBColor(255, 255, 255);
+const AXIS: RGBColor = RGBColor(110, 110, 110);
+const GRID: RGBColor = RGBColor(228, 228, 228);
+const PRIMARY: RGBColor = RGBColor(52, 101, 164);
+const SECONDARY: RGBColor = RGBColor(150, 150, 150);
+const SPENT: RGBColor = RGBColor(206, 92, 0);
+
+#[derive(Debug, Deserialize)]
+pub struct ChartQuery {
+    /// Burndown only; the board's latest sprint by default
+    pub sprint: Option<i32>,
+    /// Expiry of a signed link, in seconds since the epoch
+    pub exp: Option<i64>,
+    pub sig: Option<String>,
+}
+
+pub(crate) enum Series {
+    /// None leaves a gap, e.g. for days that have not happened yet
+    Line { color: RGBColor, dashed: bool, values: Vec<Option<f64>> },
+    Bars { color: RGBColor, values: Vec<f64> },
+}
+
+pub(crate) struct Chart {
+    pub x_labels: Vec<String>,
+    pub series: Vec<Series>,
+}
+
+/// A round step (1, 2 or 5 times a power of ten) that splits `max` into about four.
+fn tick_step(max: f64) -> f64 {
+    let raw = (max / 4.0).max(f64::EPSILON);
+    let magnitude = 10f64.powf(raw.log10().floor());
+    [1.0, 2.0, 5.0, 10.0].iter().map(|m| m * magnitude).find(|s| *s >= raw).unwrap_or(10.0 * magnitude)
+}
+
+fn axis_label(v: f64) -> String {
+    let trim = |s: String| if s.contains('.') { s.trim_end_matches('0').trim_end_matches('.').to_string() } else { s };
+    match v.abs() {
+        a if a >= 1_000_000.0 => format!("{}M", trim(format!("{:.1}", v / 1_000_000.0))),
+        a if a >= 10_000.0 => format!("{}k", trim(format!("{:.1}", v / 1_000.0))),
+        _ => trim(format!("{:.1}", v)),
+    }
+}
+
+/// Draws the chart and encodes it as PNG.
+pub(crate) fn render_png(chart: &Chart) -> Result<Vec<u8>, String> {
+    REGISTER_FONT.call_once(|| {
+        if plotters::style::register_font(FONT.0, FontStyle::Normal, FONT_DATA).is_err() {
+            error!("The embedded chart font could not be loaded");
+        }
+    });
+    let max = chart
+        .series
+        .iter()
+        .flat_map(|s| match s {
+            Series::Line { values, .. } => values.iter().flatten().copied().collect::<Vec<_>>(),
+            Series::Bars { values, .. } => values.clone(),
+        })
+        .fold(0.0, f64::max);
+    let step = tick_step(if max > 0.0 { max } else { 1.0 });
+    let steps = (max / step).ceil().max(1.0) as usize;
+    let top_value = steps as f64 * step;
+
+    // Each x value gets a slot of width one centred on its index; ticks that fall
+    // between two values stay unlabelled.
+    let n = chart.x_labels.len().max(1);
+    let x_label = |x: &f64| {
+        let i = x.round();
+        if (x - i).abs() > 1e-6 || i < 0.0 {
+            return String::new();
+        }
+        chart.x_labels.get(i as usize).cloned().unwrap_or_default()
+    };
+
+    let mut buffer = vec![0u8; (WIDTH * HEIGHT * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, (WIDTH, HEIGHT)).into_drawing_area();
+        root.fill(&BACKGROUND).map_err(|e| e.to_string())?;
+        let mut plot = ChartBuilder::on(&root)
+            .margin(MARGIN)
+            .x_label_area_size(X_LABEL_AREA)
+            .y_label_area_size(Y_LABEL_AREA)
+            .build_cartesian_2d(-0.5..n as f64 - 0.5, 0.0..top_value)
+            .map_err(|e| e.to_string())?;
+        plot.configure_mesh()
+            .disable_x_mesh()
+            .x_labels(n.min(MAX_X_LABELS))
+            .x_label_formatter(&x_label)
+            .y_labels(steps + 1)
+            .y_label_formatter(&|v| axis_label(*v))
+            .bold_line_style(GRID)
+            .light_line_style(TRANSPARENT)
+            .axis_style(AXIS)
+            .label_style(FONT.into_font().color(&AXIS))
+            .draw()
+            .map_err(|e| e.to_string())?;
+
+        for series in &chart.series {
+            match series {
+                Series::Bars { color, values } => {
+                    let bars = values
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, v)| **v > 0.0)
+                        .map(|(i, v)| Rectangle::new([(i as f64 - 0.3, 0.0), (i as f64 + 0.3, *v)], color.filled()));
+                    plot.draw_series(bars).map_err(|e| e.to_string())?;
+                }
+                Series::Line { color, dashed, values } => {
+                    let points: Vec<Option<(f64, f64)>> = values.iter().enumerate().map(|(i, v)| v.map(|v| (i as f64, v))).collect();
+                    for run in points.split(Option::is_none).filter(|run| !run.is_empty()) {
+                        let run: Vec<(f64, f64)> = run.iter().flatten().copied().collect();
+                        let style = color.stroke_width(2);
+                        if *dashed {
+                            plot.draw_series(DashedLineSeries::new(run.clone(), 6, 6, style)).map_err(|e| e.to_string())?;
+                        } else {
+                            plot.draw_series(LineSeries::new(run.clone(), style)).map_err(|e| e.to_string())?;
+                        }
+                        // Points without a neighbour would otherwise not show.
+                        plot.draw_series(run.iter().map(|p| Circle::new(*p, 2, color.filled()))).map_err(|e| e.to_string())?;
+                    }
+                }
+            }
+        }
+        root.present().map_err(|e| e.to_string())?;
+    }
+
+    let img = RgbImage::from_raw(WIDTH, HEIGHT, buffer).ok_or("Chart buffer has the wrong size")?;
+    let mut out = Cursor::new(Vec::new());
+    img.write_to(&mut out, ImageFormat::Png).map_err(|e| e.to_string())?;
+    Ok(out.into_inner())
+}
+
+fn link_key(data: &AppState) -> hmac::Key {
+    let secret = hkdf::Salt::new(hkdf::HKDF_SHA256, &[]).extract(data.config.jwt_secret.as_bytes());
+    secret.expand(&[b"chart-link"], hmac::HMAC_SHA256).expect("an HMAC key is a valid HKDF length").into()
+}
+
+/// Absolute URL of a chart that can be fetched without a session for LINK_DAYS.
+/// `path` is relative to the v1 API, e.g. `/team-data/{team_id}/charts/budget.png`.
+pub(crate) fn signed_chart_url(data: &AppState, path: &str) -> String {
+    let exp = (Utc::now() + chrono::Duration::days(LINK_DAYS)).timestamp();
+    let separator = if path.contains('?') { '&' } else { '?' };
+    let signed = format!("{}{}{}exp={}", V1_PREFIX, path, separator, exp);
+    let sig = URL_SAFE_NO_PAD.encode(hmac::sign(&link_key(data), signed.as_bytes()).as_ref());
+    format!("{}{}&sig={}", data.config.app_base_url, signed, sig)
+}
+
+/// Whether the request carries an unexpired signature over its path and query.
+fn signature_valid(req: &HttpRequest, data: &AppState, query: &ChartQuery) -> bool {
+    let (Some(exp), Some(sig)) = (query.exp, query.sig.as_deref()) else {
+        return false;
+    };
+    let Some((signed, _)) = req.uri().path_and_query().and_then(|pq| pq.as_str().rsplit_once("&sig=")) else {
+        return false;
+    };
+    let Ok(sig) = URL_SAFE_NO_PAD.decode(sig) else {
+        return false;
+    };
+    exp > Utc::now().timestamp() && hmac::verify(&link_key(data), signed.as_bytes(), &sig).is_ok()
+}
+
+async fn png_response(chart: Chart) -> HttpResponse {
+    match web::block(move || render_png(&chart)).await.map_err(|e| e.to_string()).and_then(|r| r) {
+        Ok(png) => HttpResponse::Ok()
+            .content_type("image/png")
+            .insert_header(("Cache-Control", "private, max-age=300"))
+            .body(png),
+        Err(e) => {
+            error!("Error rendering chart: {}", e);
+            HttpResponse::InternalServerError().body("Error rendering chart")
+        }
+    }
+}
+
+/// The board, when the caller may see it or the link is signed.
+async fn authorize_board(
+    req: &HttpRequest,
+    auth: Option<&AuthContext>,
+    data: &AppState,
+    query: &ChartQuery,
+    (team_id, project_id, board_id): &(String, String, String),
+) -> Result<(ProjectScope, Board), HttpResponse> {
+    // A signed link is enough on its own; without one an anonymous caller is turned
+    // away before anything is read.
+    let signed = signature_valid(req, data, query);
+    if !signed && auth.is_none() {
+        return Err(HttpResponse::Unauthorized().error("not_project_or_board_member"));
+    }
+    // Signed links carry no caller, so the scope comes from the project itself.
+    let scope = match ProjectScope::of_project(&data.mongodb, project_id).await {
+        Some(s) if s.team_id() == team_id => s,
+        _ => return Err(HttpResponse::NotFound().error("board_not_found")),
+    };
+    let board = match Repo::<Board>::new(&data.mongodb, &scope).find_one(doc! { "board_id": board_id }).await {
+        Ok(Some(b)) => b,
+        Ok(None) => return Err(HttpResponse::NotFound().error("board_not_found")),
+        Err(e) => {
+            error!("Error fetching board for chart: {}", e);
+            return Err(HttpResponse::InternalServerError().body("Error fetching board"));
+        }
+    };
+    let allowed = signed || match auth {
+        Some(auth) => can_edit_board(auth, &board).await,
+        None => false,
+    };
+    if !allowed {
+        return Err(HttpResponse::Unauthorized().error("not_project_or_board_member"));
+    }
+    Ok((scope, board))
+}
+
+async fn latest_sprint(data: &AppState, scope: &ProjectScope, board_id: &str) -> mongodb::error::Result<Option<i32>> {
+    let mut cursor = Repo::<Ticket>::new(&data.mongodb, scope)
+        .find(doc! { "board_id": board_id, "sprint": { "$ne": Bson::Null } })
+        .sort(doc! { "sprint": -1 })
+        .limit(1)
+        .await?;
+    Ok(cursor.next().await.transpose()?.and_then(|t| t.sprint))
+}
+
+async fn burndown(data: &AppState, scope: &ProjectScope, board: &Board, sprint: i32) -> mongodb::error::Result<Chart> {
+    let db = &data.mongodb.db;
+    let mut cursor = Repo::<Ticket>::new(&data.mongodb, scope).find(doc! { "board_id": &board.board_id, "sprint": sprint }).await?;
+    let mut tickets = Vec::new();
+    while let Some(t) = cursor.next().await {
+        tickets.push(t?);
+    }
+
+    // When each ticket joined the sprint; tickets created in it have no such event.
+    let ids: Vec<&String> = tickets.iter().map(|t| &t.ticket_id).collect();
+    let pipeline = vec![
+        doc! { "$match": { "ticket_id": { "$in": ids }, "change.type": "field_changed", "change.field": "sprint", "change.new": sprint } },
+        doc! { "$group": { "_id": "$ticket_id", "at": { "$min": "$at" } } },
+    ];
+    let mut cursor = db.collection::<Document>("ticket_events").aggregate(pipeline).await?;
+    let mut joined = HashMap::new();
+    while let Some(d) = cursor.next().await {
+        let d = d?;
+        if let (Ok(id), Some(at)) = (d.get_str("_id"), d.get("at").and_then(timestamp)) {
+            joined.insert(id.to_string(), at);
+        }
+    }
+    let added = |t: &Ticket| joined.get(&t.ticket_id).copied().unwrap_or(t.created_at);
+
+    let by_estimate = tickets.iter().any(|t| t.estimate_hours.is_some());
+    let weight = |t: &Ticket| if by_estimate { t.estimate_hours.unwrap_or(0.0) } else { 1.0 };
+    let now = Utc::now();
+    let start = tickets.iter().map(added).min().unwrap_or(now).date_naive();
+    let days = board.sprint_length.map_or(DEFAULT_SPRINT_WEEKS, |w| w.clamp(*SPRINT_WEEKS.start(), *SPRINT_WEEKS.end()) as i64) * 7;
+
+    let mut remaining = Vec::new();
+    let mut x_labels = Vec::new();
+    for day in 0..=days {
+        let date = start + chrono::Duration::days(day);
+        x_labels.push(format!("{}/{}", date.month(), date.day()));
+        let end_of_day: DateTime<Utc> = (date + chrono::Duration::days(1)).and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc();
+        if end_of_day - chrono::Duration::days(1) > now {
+            remaining.push(None);
+            continue;
+        }
+        let open: f64 = tickets
+            .iter()
+            .filter(|t| added(t) < end_of_day)
+            .filter(|t| !(CLOSED_STATUSES.contains(&t.status.as_str()) && t.resolved_at.is_some_and(|r| r < end_of_day)))
+            .map(weight)
+            .sum();
+        remaining.push(Some(open));
+    }
+    let first = remaining.first().copied().flatten().unwrap_or(0.0);
+    let ideal = (0..=days).map(|d| Some(first * (1.0 - d as f64 / days as f64))).collect();
+    Ok(Chart {
+        x_labels,
+        series: vec![
+            Series::Line { color: SECONDARY, dashed: true, values: ideal },
+            Series::Line { color: PRIMARY, dashed: false, values: remaining },
+        ],
+    })
+}
+
+async fn velocity(data: &AppState, scope: &ProjectScope, board_id: &str) -> mongodb::error::Result<Chart> {
+    let pipeline = vec![
+        doc! { "$match": { "board_id": board_id, "sprint": { "$ne": Bson::Null } } },
+        doc! { "$group": {
+            "_id": "$sprint",
+            "done": { "$sum": { "$cond": [{ "$in": ["$status", CLOSED_STATUSES.to_vec()] }, 1, 0] } },
+            "done_hours": { "$sum": { "$cond": [{ "$in": ["$status", CLOSED_STATUSES.to_vec()] }, { "$ifNull": ["$estimate_hours", 0] }, 0] } },
+            "estimated": { "$sum": { "$cond": [{ "$eq": [{ "$type": "$estimate_hours" }, "missing"] }, 0, 1] } },
+        } },
+        doc! { "$sort": { "_id": -1 } },
+        doc! { "$limit": VELOCITY_SPRINTS },
+    ];
+    let mut cursor = Repo::<Ticket>::new(&data.mongodb, scope).aggregate(pipeline).await?;
+    let mut sprints = Vec::new();
+    while let Some(d) = cursor.next().await {
+        sprints.push(d?);
+    }
+    sprints.reverse();
+    let num = |d: &Document, key: &str| match d.get(key) {
+        Some(Bson::Int32(v)) => *v as f64,
+        Some(Bson::Int64(v)) => *v as f64,
+        Some(Bson::Double(v)) => *v,
+        _ => 0.0,
+    };
+    // Completed hours when the board estimates, completed tickets otherwise.
+    let by_estimate = sprints.iter().any(|d| num(d, "estimated") > 0.0);
+    Ok(Chart {
+        x_labels: sprints.iter().map(|d| num(d, "_id").to_string()).collect(),
+        series: vec![Series::Bars {
+            color: PRIMARY,
+            values: sprints.iter().map(|d| num(d, if by_estimate { "done_hours" } else { "done" })).collect(),
+        }],
+    })
+}
+
+/// Planned spend (the annual budget, evenly per month) against actual spend, both
+/// cumulative over the current year.
+async fn budget(data: &AppState, team_id: &str) -> mongodb::error::Result<Chart> {
+    let stored = data.mongodb.db.collection::<Document>("dashboard_data").find_one(doc! { "teamId": team_id }).await?;
+    let input = stored
+        .and_then(|mut d| d.remove("budgetInput"))
+        .and_then(|b| from_bson::<BudgetInput>(b).ok())
+        .unwrap_or(BudgetInput { total_annual_budget: 0.0, monthly_drains: Vec::new() });
+    let current_month = Utc::now().month0() as usize;
+    let mut spent = 0.0;
+    let mut actual = Vec::new();
+    for month in 0..12 {
+        spent += input.monthly_drains.get(month).copied().unwrap_or(0.0);
+        actual.push((month <= current_month).then_some(spent));
+    }
+    Ok(Chart {
+        x_labels: (1..=12).map(|m| m.to_string()).collect(),
+        series: vec![
+            Series::Line {
+                color: SECONDARY,
+                dashed: true,
+                values: (1..=12).map(|m| Some(input.total_annual_budget * m as f64 / 12.0)).collect(),
+            },
+            Series::Line { color: SPENT, dashed: false, values: actual },
+        ],
+    })
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/boards/{board_id}/charts/burndown.png?sprint=
+pub async fn get_burndown_chart(
+    req: HttpRequest,
+    auth: Option<AuthContext>,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    query: web::Query<ChartQuery>,
+) -> impl Responder {
+    let (scope, board) = match authorize_board(&req, auth.as_ref(), &data, &query, &path).await {
+        Ok(b) => b,
+        Err(resp) => return resp,
+    };
+    let sprint = match query.sprint {
+        Some(s) => s,
+        None => match latest_sprint(&data, &scope, &board.board_id).await {
+            Ok(Some(s)) => s,
+            Ok(None) => return HttpResponse::NotFound().body("The board has no sprints"),
+            Err(e) => {
+                error!("Error finding latest sprint: {}", e);
+                return HttpResponse::InternalServerError().body("Error rendering chart");
+            }
+        },
+    };
+    match burndown(&data, &scope, &board, sprint).await {
+        Ok(chart) => png_response(chart).await,
+        Err(e) => {
+            error!("Error computing burndown: {}", e);
+            HttpResponse::InternalServerError().body("Error rendering chart")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/boards/{board_id}/charts/velocity.png
+/// The last VELOCITY_SPRINTS sprints.
+pub async fn get_velocity_chart(
+    req: HttpRequest,
+    auth: Option<AuthContext>,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    query: web::Query<ChartQuery>,
+) -> impl Responder {
+    let (scope, board) = match authorize_board(&req, auth.as_ref(), &data, &query, &path).await {
+        Ok(b) => b,
+        Err(resp) => return resp,
+    };
+    match velocity(&data, &scope, &board.board_id).await {
+        Ok(chart) => png_response(chart).await,
+        Err(e) => {
+            error!("Error computing velocity: {}", e);
+            HttpResponse::InternalServerError().body("Error rendering chart")
+        }
+    }
+}
+
+/// GET /team-data/{team_id}/charts/budget.png
+pub async fn get_budget_chart(
+    req: HttpRequest,
+    auth: Option<AuthContext>,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    query: web::Query<ChartQuery>,
+) -> impl Responder {
+    let allowed = signature_valid(&req, &data, &query)
+        || match &auth {
+            Some(auth) => auth.is_team_member(&team_id).await,
+            None => false,
+        };
+    if !allowed {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    match budget(&data, &team_id).await {
+        Ok(chart) => png_response(chart).await,
+        Err(e) => {
+            error!("Error computing budget chart: {}", e);
+            HttpResponse::InternalServerError().body("Error rendering chart")
+        }
+    }
+}
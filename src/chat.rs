@@ -1,12 +1,19 @@
 use bson::serde_helpers::chrono_datetime_as_bson_datetime;
-use actix_web::{web, HttpResponse, Responder, HttpRequest, HttpMessage};
+use actix_web::{web, HttpResponse, Responder};
 use bson::DateTime;
 use futures_util::StreamExt;
-use mongodb::bson::{self, doc, DateTime as BsonDateTime};
+use mongodb::bson::{self, doc, DateTime as BsonDateTime, Document};
 use serde::{Deserialize, Serialize};
 use chrono::Utc;
 
 use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::bots::{run_command, CommandReply};
+use crate::chat_attachments::ChatAttachment;
+use crate::fields::{select, FieldSet, FieldsQuery};
+use crate::legal_hold::chat_under_hold;
+use crate::response::CodedError;
+use crate::sync::{record_change, Entity, Op, Scope};
 use crate::chat_server::{CreateMessage as CreateMessageActor};
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -18,6 +25,32 @@ pub struct Chat {
     pub group_name: Option<String>,
     pub created_at: BsonDateTime,
     pub last_message_at: BsonDateTime,
+    /// Pinned message ids, oldest first (at most MAX_PINS)
+    #[serde(default)]
+    pub pins: Vec<String>,
+    /// Banner shown at the top of the chat, editable by group admins
+    #[serde(default)]
+    pub announcement: Option<String>,
+    /// Users allowed to manage the chat. Empty for legacy chats, in which case
+    /// every participant is treated as an admin.
+    #[serde(default)]
+    pub admins: Vec<String>,
+    /// Team the chat was created in; absent on chats created before team scoping
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team_id: Option<String>,
+}
+
+/// Maximum number of pinned messages per chat.
+pub const MAX_PINS: usize = 10;
+
+impl Chat {
+    pub fn is_admin(&self, user_id: &str) -> bool {
+        if self.admins.is_empty() {
+            self.participants.iter().any(|p| p == user_id)
+        } else {
+            self.admins.iter().any(|a| a == user_id)
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -50,17 +83,24 @@ pub struct DBMessage {
     pub created_at: chrono::DateTime<Utc>,
     #[serde(rename = "type")]
     pub msg_type: String,
-    pub attachments: Option<String>,
+    #[serde(default, deserialize_with = "crate::chat_attachments::legacy_attachments")]
+    pub attachments: Option<Vec<ChatAttachment>>,
 }
 
 // ----------------------------------------------------------------------
 // GET /chats/{user_id} => list all chats in which that user participates
 // ----------------------------------------------------------------------
 pub async fn get_user_chats(
+    auth: AuthContext,
     data: web::Data<AppState>,
     user_id_path: web::Path<String>,
+    query: web::Query<FieldsQuery>,
 ) -> impl Responder {
     let user_id_str = user_id_path.into_inner(); // store in a binding
+    if user_id_str != auth.user_id() {
+        return HttpResponse::Unauthorized().body("Cannot access other user's chats");
+    }
+    let fields = FieldSet::parse(query.fields.as_deref(), &["_id"]);
     let chats_collection = data.mongodb.db.collection::<Chat>("chats");
 
     let filter = doc! { "participants": &user_id_str };
@@ -81,7 +121,7 @@ pub async fn get_user_chats(
             }
         }
     }
-    HttpResponse::Ok().json(chats)
+    HttpResponse::Ok().json(select(&chats, &fields))
 }
 
 // ----------------------------------------------------------------------
@@ -91,14 +131,10 @@ pub async fn get_user_chats(
 pub async fn get_single_chat(
     data: web::Data<AppState>,
     chat_id_path: web::Path<String>,
-    req: HttpRequest,
+    auth: AuthContext,
 ) -> impl Responder {
     // Optionally ensure the user is authorized:
-    let user_id_opt = req.extensions().get::<String>().cloned();
-    if user_id_opt.is_none() {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    }
-    let user_id = user_id_opt.unwrap();
+    let user_id = auth.user_id().to_string();
     let chat_id_str = chat_id_path.into_inner();
 
     let chats_collection = data.mongodb.db.collection::<Chat>("chats");
@@ -106,7 +142,7 @@ pub async fn get_single_chat(
         Ok(Some(chat_doc)) => {
             // if you want to ensure user is a participant:
             if !chat_doc.participants.contains(&user_id) {
-                return HttpResponse::Forbidden().body("You are not a participant of this chat.");
+                return HttpResponse::Forbidden().error("not_chat_participant");
             }
             HttpResponse::Ok().json(chat_doc)
         }
@@ -119,10 +155,17 @@ pub async fn get_single_chat(
 // GET /messages/{chat_id} => fetch all messages for a given chat
 // ----------------------------------------------------------------------
 pub async fn get_messages(
+    auth: AuthContext,
     data: web::Data<AppState>,
     chat_id_path: web::Path<String>,
 ) -> impl Responder {
     let chat_id_str = chat_id_path.into_inner();
+    let chats = data.mongodb.db.collection::<Chat>("chats");
+    match chats.find_one(doc! { "_id": &chat_id_str, "participants": auth.user_id() }).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::Forbidden().error("not_chat_participant"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    }
     let messages_collection = data.mongodb.db.collection::<DBMessage>("messages");
 
     let filter = doc! { "id_chat": &chat_id_str };
@@ -156,9 +199,17 @@ pub async fn get_messages(
 // POST /chats => create a new chat
 // ----------------------------------------------------------------------
 pub async fn create_chat(
+    auth: AuthContext,
     data: web::Data<AppState>,
     chat_info: web::Json<CreateChatRequest>,
 ) -> impl Responder {
+    let creator = auth.user_id().to_string();
+    if !chat_info.participants.contains(&creator) {
+        return HttpResponse::BadRequest().body("The creator must be a participant of the chat");
+    }
+    if !chat_info.team_id.is_empty() && !auth.is_team_member(&chat_info.team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
     let new_chat_id = uuid::Uuid::new_v4().to_string();
     let now = Utc::now();
 
@@ -181,12 +232,17 @@ pub async fn create_chat(
         group_name: if is_group { Some(group_name) } else { None },
         created_at: DateTime::from(now),
         last_message_at: DateTime::from(now),
+        pins: Vec::new(),
+        announcement: None,
+        admins: vec![creator],
+        team_id: Some(chat_info.team_id.clone()).filter(|t| !t.is_empty()),
     };
 
     let chats_collection = data.mongodb.db.collection::<Chat>("chats");
     if let Err(e) = chats_collection.insert_one(&new_chat).await {
         return HttpResponse::InternalServerError().body(format!("Failed to create chat: {}", e));
     }
+    record_change(&data.mongodb, Entity::Chat, &new_chat.id_chat, Op::Upsert, Scope::Users(&new_chat.participants)).await;
 
     // Possibly create an initial message if desired:
     // For example, we do chat_info.message = "Chat initiated."
@@ -203,11 +259,15 @@ pub async fn create_chat(
 // GET /chats/search/{user_id}?q=someQuery => example search
 // ----------------------------------------------------------------------
 pub async fn search_chats(
+    auth: AuthContext,
     data: web::Data<AppState>,
     path: web::Path<String>,
     query: web::Query<std::collections::HashMap<String, String>>,
 ) -> impl Responder {
     let user_id_str = path.into_inner();
+    if user_id_str != auth.user_id() {
+        return HttpResponse::Unauthorized().body("Cannot access other user's chats");
+    }
     let _search_str = query.get("q").unwrap_or(&"".to_string()).to_lowercase();
 
     let chats_collection = data.mongodb.db.collection::<Chat>("chats");
@@ -236,16 +296,12 @@ pub async fn search_chats(
 pub async fn delete_chat(
     data: web::Data<AppState>,
     chat_id_path: web::Path<String>,
-    req: HttpRequest,
+    auth: AuthContext,
 ) -> impl Responder {
     let chat_id_str = chat_id_path.into_inner();
 
     // Must have user_id from auth
-    let user_id_opt = req.extensions().get::<String>().cloned();
-    if user_id_opt.is_none() {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    }
-    let user_id = user_id_opt.unwrap();
+    let user_id = auth.user_id().to_string();
 
     let chats_collection = data.mongodb.db.collection::<Chat>("chats");
     let filter = doc! { "_id": &chat_id_str };
@@ -260,12 +316,28 @@ pub async fn delete_chat(
     if !chat_doc.participants.iter().any(|p| p == &user_id) {
         return HttpResponse::Unauthorized().body("Not a participant in the chat");
     }
+    match chat_under_hold(&data.mongodb, &chat_id_str, &chat_doc.participants).await {
+        Ok(false) => {}
+        Ok(true) => return HttpResponse::Conflict().body("This chat is under legal hold and cannot be deleted"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error checking legal holds: {}", e)),
+    }
 
     match chats_collection.delete_one(filter).await {
         Ok(_) => {
             // Also remove all messages in this chat
             let messages_collection = data.mongodb.db.collection::<DBMessage>("messages");
             let _ = messages_collection.delete_many(doc! { "id_chat": &chat_id_str }).await;
+            let _ = data.mongodb.db.collection::<Document>("message_translations")
+                .delete_many(doc! { "id_chat": &chat_id_str })
+                .await;
+            let _ = data.mongodb.db.collection::<Document>("scheduled_messages")
+                .delete_many(doc! { "chat_id": &chat_id_str, "status": "pending" })
+                .await;
+            let _ = data.mongodb.db.collection::<Document>("bot_installations")
+                .delete_many(doc! { "chat_id": &chat_id_str })
+                .await;
+            // Clients drop the chat's messages along with it.
+            record_change(&data.mongodb, Entity::Chat, &chat_id_str, Op::Delete, Scope::Users(&chat_doc.participants)).await;
             HttpResponse::Ok().body("Chat deleted successfully")
         },
         Err(e) => HttpResponse::InternalServerError().body(format!("Error deleting chat: {}", e)),
@@ -274,26 +346,23 @@ pub async fn delete_chat(
 pub async fn update_chat(
     data: web::Data<AppState>,
     chat_id_path: web::Path<String>,
-    req: HttpRequest,
+    auth: AuthContext,
     upd: web::Json<UpdateChatRequest>,
 ) -> impl Responder {
     // 1) Auth
-    let user_id = match req.extensions().get::<String>().cloned() {
-        Some(id) => id,
-        None => return HttpResponse::Unauthorized().body("Unauthorized"),
-    };
+    let user_id = auth.user_id().to_string();
     let chat_id = chat_id_path.into_inner();
 
     // 2) Ensure the user is a participant
     let coll = data.mongodb.db.collection::<Chat>("chats");
-    match coll
+    let before = match coll
         .find_one(doc! { "_id": &chat_id, "participants": &user_id })
         .await
     {
-        Ok(Some(_)) => {}
+        Ok(Some(c)) => c,
         Ok(None)    => return HttpResponse::Forbidden().body("Not a participant"),
         Err(e)      => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
-    }
+    };
 
     // 3) Build an update with a _BSON_ DateTime
     let now: BsonDateTime = BsonDateTime::from_chrono(Utc::now());
@@ -325,7 +394,18 @@ pub async fn update_chat(
         .find_one(doc! { "_id": &chat_id })
         .await
     {
-        Ok(Some(chat)) => HttpResponse::Ok().json(chat),
+        Ok(Some(chat)) => {
+            let removed: Vec<String> = before
+                .participants
+                .into_iter()
+                .filter(|p| !chat.participants.contains(p))
+                .collect();
+            if !removed.is_empty() {
+                record_change(&data.mongodb, Entity::Chat, &chat_id, Op::Delete, Scope::Users(&removed)).await;
+            }
+            record_change(&data.mongodb, Entity::Chat, &chat_id, Op::Upsert, Scope::Users(&chat.participants)).await;
+            HttpResponse::Ok().json(chat)
+        }
         Ok(None)       => HttpResponse::NotFound().body("Chat not found after update"),
         Err(e)         => HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
     }
@@ -334,12 +414,15 @@ pub async fn update_chat(
 // POST /messages/{chat_id} => create a new message
 // ----------------------------------------------------------------------
 pub async fn create_message(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     chat_id_path: web::Path<String>,
     payload: web::Json<CreateMessagePayload>,
 ) -> impl Responder {
     let chat_id_str = chat_id_path.into_inner();
+    if payload.sender_id != auth.user_id() {
+        return HttpResponse::Forbidden().body("Messages can only be sent as yourself");
+    }
 
     // Confirm user is in chat doc
     let chats_collection = data.mongodb.db.collection::<Chat>("chats");
@@ -353,6 +436,18 @@ pub async fn create_message(
         }
     }
 
+    // Slash commands are answered by a bot instead of being posted.
+    if let Some(result) = run_command(&data, &payload.sender_id, &chat_id_str, &payload.content).await {
+        return match result {
+            Ok(CommandReply::Posted(reply)) => HttpResponse::Ok().json(reply),
+            Ok(CommandReply::Private(text)) => {
+                HttpResponse::Ok().json(serde_json::json!({ "type": "command_reply", "message": text }))
+            }
+            Ok(CommandReply::Silent) => HttpResponse::Accepted().finish(),
+            Err(msg) => HttpResponse::BadRequest().body(msg),
+        };
+    }
+
     // Send actor message
     let create_msg = crate::chat_server::CreateMessage {
         user_id: payload.sender_id.clone(),
@@ -367,4 +462,289 @@ pub async fn create_message(
         Ok(Err(_)) => HttpResponse::InternalServerError().body("Failed to create message"),
         Err(e) => HttpResponse::InternalServerError().body(format!("Actor mailbox error: {:?}", e)),
     }
-}
\ No newline at end of file
+}
+#[derive(Deserialize, Debug)]
+pub struct MessageSearchQuery {
+    pub q: String,
+    pub from: Option<chrono::DateTime<Utc>>,
+    pub to: Option<chrono::DateTime<Utc>>,
+    pub sender: Option<String>,
+    /// Number of messages to include before and after each hit
+    pub context: Option<i64>,
+    pub page: Option<u64>,
+    pub page_size: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MessageSearchHit {
+    pub message: DBMessage,
+    pub before: Vec<DBMessage>,
+    pub after: Vec<DBMessage>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MessageSearchResponse {
+    pub results: Vec<MessageSearchHit>,
+    pub total: u64,
+    pub page: u64,
+    pub page_size: i64,
+}
+
+async fn collect_messages(
+    coll: &mongodb::Collection<DBMessage>,
+    filter: bson::Document,
+    sort: bson::Document,
+    limit: i64,
+) -> mongodb::error::Result<Vec<DBMessage>> {
+    let mut cursor = coll.find(filter).sort(sort).limit(limit).await?;
+    let mut out = Vec::new();
+    while let Some(res) = cursor.next().await {
+        out.push(res?);
+    }
+    Ok(out)
+}
+
+// ----------------------------------------------------------------------
+// GET /messages/{chat_id}/search?q=&from=&to=&sender= => full-text search in one chat
+// ----------------------------------------------------------------------
+pub async fn search_messages(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    chat_id_path: web::Path<String>,
+    query: web::Query<MessageSearchQuery>,
+) -> impl Responder {
+    let user_id = auth.user_id().to_string();
+    let chat_id = chat_id_path.into_inner();
+
+    let chats_collection = data.mongodb.db.collection::<Chat>("chats");
+    match chats_collection
+        .find_one(doc! { "_id": &chat_id, "participants": &user_id })
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::Forbidden().error("not_chat_participant"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    }
+
+    if query.q.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Query must not be empty");
+    }
+    let context = query.context.unwrap_or(2).clamp(0, 10);
+    let page = query.page.unwrap_or(0);
+    let page_size = query.page_size.unwrap_or(20).clamp(1, 100);
+
+    // created_at is stored as an RFC 3339 string, so range filters compare strings.
+    let mut filter = doc! { "id_chat": &chat_id, "$text": { "$search": query.q.trim() } };
+    let mut range = doc! {};
+    if let Some(from) = query.from {
+        range.insert("$gte", bson::to_bson(&from).unwrap_or_default());
+    }
+    if let Some(to) = query.to {
+        range.insert("$lte", bson::to_bson(&to).unwrap_or_default());
+    }
+    if !range.is_empty() {
+        filter.insert("created_at", range);
+    }
+    if let Some(sender) = &query.sender {
+        filter.insert("sender_id", sender);
+    }
+
+    let messages_collection = data.mongodb.db.collection::<DBMessage>("messages");
+    let total = match messages_collection.count_documents(filter.clone()).await {
+        Ok(n) => n,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error searching messages: {}", e)),
+    };
+
+    let mut cursor = match messages_collection
+        .find(filter)
+        .sort(doc! { "created_at": -1 })
+        .skip(page * page_size as u64)
+        .limit(page_size)
+        .await
+    {
+        Ok(c) => c,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error searching messages: {}", e)),
+    };
+
+    let mut hits = Vec::new();
+    while let Some(res) = cursor.next().await {
+        match res {
+            Ok(msg) => hits.push(msg),
+            Err(e) => return HttpResponse::InternalServerError().body(format!("Error iterating messages: {}", e)),
+        }
+    }
+
+    let mut results = Vec::with_capacity(hits.len());
+    for message in hits {
+        let (mut before, after) = if context > 0 {
+            let before = collect_messages(
+                &messages_collection,
+                doc! { "id_chat": &chat_id, "created_at": { "$lt": bson::to_bson(&message.created_at).unwrap_or_default() } },
+                doc! { "created_at": -1 },
+                context,
+            )
+            .await;
+            let after = collect_messages(
+                &messages_collection,
+                doc! { "id_chat": &chat_id, "created_at": { "$gt": bson::to_bson(&message.created_at).unwrap_or_default() } },
+                doc! { "created_at": 1 },
+                context,
+            )
+            .await;
+            match (before, after) {
+                (Ok(b), Ok(a)) => (b, a),
+                (Err(e), _) | (_, Err(e)) => {
+                    return HttpResponse::InternalServerError().body(format!("Error loading context: {}", e))
+                }
+            }
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        before.reverse();
+        results.push(MessageSearchHit { message, before, after });
+    }
+
+    HttpResponse::Ok().json(MessageSearchResponse { results, total, page, page_size })
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnnouncementRequest {
+    pub announcement: Option<String>,
+}
+
+/// Tell every other participant that the chat's pins or banner changed.
+fn broadcast_chat_update(data: &AppState, user_id: &str, chat: &Chat) {
+    let message = serde_json::json!({
+        "type": "chat_pins_updated",
+        "chat_id": chat.id_chat,
+        "pins": chat.pins,
+        "announcement": chat.announcement,
+    })
+    .to_string();
+    data.chat_server.do_send(crate::chat_server::RelaySignal {
+        user_id: user_id.to_string(),
+        chat_id: chat.id_chat.clone(),
+        message,
+    });
+}
+
+async fn set_pins(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    pin: bool,
+) -> HttpResponse {
+    let user_id = auth.user_id().to_string();
+    let (chat_id, message_id) = path.into_inner();
+
+    let coll = data.mongodb.db.collection::<Chat>("chats");
+    let chat = match coll.find_one(doc! { "_id": &chat_id, "participants": &user_id }).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return HttpResponse::Forbidden().body("Not a participant"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    };
+
+    let update = if pin {
+        if chat.pins.contains(&message_id) {
+            return HttpResponse::Ok().json(chat);
+        }
+        if chat.pins.len() >= MAX_PINS {
+            return HttpResponse::BadRequest().body(format!("A chat can have at most {} pinned messages", MAX_PINS));
+        }
+        let messages = data.mongodb.db.collection::<DBMessage>("messages");
+        match messages.find_one(doc! { "_id": &message_id, "id_chat": &chat_id }).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return HttpResponse::NotFound().body("Message not found in this chat"),
+            Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+        }
+        doc! { "$push": { "pins": &message_id } }
+    } else {
+        doc! { "$pull": { "pins": &message_id } }
+    };
+
+    // Guard the size again in the filter so concurrent pins can't exceed the cap.
+    let mut filter = doc! { "_id": &chat_id };
+    if pin {
+        filter.insert(format!("pins.{}", MAX_PINS - 1), doc! { "$exists": false });
+    }
+    match coll.update_one(filter, update).await {
+        Ok(res) if res.matched_count == 0 => {
+            return HttpResponse::BadRequest().body(format!("A chat can have at most {} pinned messages", MAX_PINS))
+        }
+        Ok(_) => {}
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Failed update: {}", e)),
+    }
+
+    match coll.find_one(doc! { "_id": &chat_id }).await {
+        Ok(Some(chat)) => {
+            broadcast_chat_update(&data, &user_id, &chat);
+            record_change(&data.mongodb, Entity::Chat, &chat_id, Op::Upsert, Scope::Users(&chat.participants)).await;
+            HttpResponse::Ok().json(chat)
+        }
+        Ok(None) => HttpResponse::NotFound().body("Chat not found after update"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    }
+}
+
+// ----------------------------------------------------------------------
+// POST /chats/{chat_id}/pins/{message_id} => pin a message
+// ----------------------------------------------------------------------
+pub async fn pin_message(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    set_pins(auth, data, path, true).await
+}
+
+// ----------------------------------------------------------------------
+// DELETE /chats/{chat_id}/pins/{message_id} => unpin a message
+// ----------------------------------------------------------------------
+pub async fn unpin_message(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    set_pins(auth, data, path, false).await
+}
+
+// ----------------------------------------------------------------------
+// PUT /chats/{chat_id}/announcement => set or clear the banner (admins only)
+// ----------------------------------------------------------------------
+pub async fn set_announcement(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    chat_id_path: web::Path<String>,
+    payload: web::Json<AnnouncementRequest>,
+) -> impl Responder {
+    let user_id = auth.user_id().to_string();
+    let chat_id = chat_id_path.into_inner();
+
+    let coll = data.mongodb.db.collection::<Chat>("chats");
+    let mut chat = match coll.find_one(doc! { "_id": &chat_id }).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return HttpResponse::NotFound().body("Chat not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    };
+    if !chat.is_admin(&user_id) {
+        return HttpResponse::Forbidden().body("Only chat admins can edit the announcement");
+    }
+
+    let announcement = payload
+        .announcement
+        .as_ref()
+        .map(|a| a.trim().to_string())
+        .filter(|a| !a.is_empty());
+    let update = match &announcement {
+        Some(text) => doc! { "$set": { "announcement": text } },
+        None => doc! { "$unset": { "announcement": "" } },
+    };
+    if let Err(e) = coll.update_one(doc! { "_id": &chat_id }, update).await {
+        return HttpResponse::InternalServerError().body(format!("Failed update: {}", e));
+    }
+
+    chat.announcement = announcement;
+    broadcast_chat_update(&data, &user_id, &chat);
+    record_change(&data.mongodb, Entity::Chat, &chat_id, Op::Upsert, Scope::Users(&chat.participants)).await;
+    HttpResponse::Ok().json(chat)
+}
@@ -0,0 +1,384 @@
+// src/chat_attachments.rs
+
+use std::io::Cursor;
+
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse, Responder};
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use futures_util::{Stream, StreamExt};
+use image::ImageFormat;
+use log::{error, info, warn};
+use mongodb::bson::{doc, oid::ObjectId, Bson};
+use mongodb::gridfs::{GridFsBucket, GridFsDownloadStream};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat::Chat;
+use crate::chat_server::CreateMessage;
+use crate::response::CodedError;
+
+/// Largest text part accepted alongside the files.
+const MAX_CONTENT_BYTES: usize = 64 * 1024;
+/// Most parts (files and text) accepted in one upload.
+const MAX_PARTS: usize = 20;
+/// Downloads are sent in chunks of this size.
+const DOWNLOAD_CHUNK_BYTES: usize = 64 * 1024;
+/// Images above this size are stored but not thumbnailed.
+const MAX_THUMBNAIL_SOURCE_BYTES: usize = 10 * 1024 * 1024;
+const THUMBNAIL_SIZE: u32 = 256;
+/// Types shown in the browser; everything else is served as a download.
+const INLINE_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// Attachment descriptor stored inline on a message and pushed over WS.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatAttachment {
+    /// GridFS file id (hex), or `giphy:{id}` for linked GIFs
+    pub attachment_id: String,
+    pub filename: String,
+    pub content_type: String,
+    pub size: u64,
+    /// GridFS file id of the generated thumbnail, images only
+    pub thumbnail_id: Option<String>,
+    /// Set on linked attachments, which are not stored in GridFS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// Messages stored before uploads existed carry `attachments` as a bare link;
+/// those read as a single linked attachment so older chats still load.
+pub fn legacy_attachments<'de, D>(deserializer: D) -> Result<Option<Vec<ChatAttachment>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        List(Vec<ChatAttachment>),
+        Link(String),
+    }
+    Ok(match Option::<Stored>::deserialize(deserializer)? {
+        Some(Stored::List(list)) => Some(list),
+        Some(Stored::Link(link)) if !link.is_empty() => Some(vec![ChatAttachment {
+            attachment_id: String::new(),
+            filename: link.clone(),
+            content_type: "application/octet-stream".to_string(),
+            size: 0,
+            thumbnail_id: None,
+            url: Some(link),
+        }]),
+        _ => None,
+    })
+}
+
+/// `Content-Disposition` value with an ASCII fallback name and the exact name
+/// percent-encoded as of RFC 6266.
+fn content_disposition(disposition: &str, filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || " ._-".contains(c) { c } else { '_' })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"._-~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+    format!("{}; filename=\"{}\"; filename*=UTF-8''{}", disposition, fallback, encoded)
+}
+
+fn bucket(data: &AppState) -> GridFsBucket {
+    data.mongodb.db.gridfs_bucket(None)
+}
+
+async fn store_file(
+    bucket: &GridFsBucket,
+    filename: &str,
+    content_type: &str,
+    chat_id: &str,
+    bytes: &[u8],
+) -> Result<ObjectId, String> {
+    let mut upload = bucket
+        .open_upload_stream(filename)
+        .metadata(doc! { "chat_id": chat_id, "content_type": content_type })
+        .await
+        .map_err(|e| e.to_string())?;
+    upload.write_all(bytes).await.map_err(|e| e.to_string())?;
+    upload.close().await.map_err(|e| e.to_string())?;
+    upload
+        .id()
+        .as_object_id()
+        .ok_or_else(|| "GridFS returned a non-ObjectId id".to_string())
+}
+
+/// Downscale an image to a PNG thumbnail. Returns None for anything `image` can't decode.
+fn make_thumbnail(bytes: &[u8]) -> Option<Vec<u8>> {
+    let img = image::load_from_memory(bytes).ok()?;
+    let thumb = img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    let mut out = Cursor::new(Vec::new());
+    thumb.write_to(&mut out, ImageFormat::Png).ok()?;
+    Some(out.into_inner())
+}
+
+/// Removes stored attachments and their thumbnails of a message that was not created.
+async fn discard_attachments(bucket: &GridFsBucket, attachments: &[ChatAttachment]) {
+    for a in attachments {
+        for id in std::iter::once(&a.attachment_id).chain(&a.thumbnail_id) {
+            let Ok(oid) = ObjectId::parse_str(id) else {
+                continue;
+            };
+            if let Err(e) = bucket.delete(Bson::ObjectId(oid)).await {
+                warn!("Error removing attachment {}: {}", id, e);
+            }
+        }
+    }
+}
+
+async fn require_participant(data: &AppState, chat_id: &str, user_id: &str) -> Result<(), HttpResponse> {
+    let chats_collection = data.mongodb.db.collection::<Chat>("chats");
+    match chats_collection
+        .find_one(doc! { "_id": chat_id, "participants": user_id })
+        .await
+    {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(HttpResponse::Forbidden().error("not_chat_participant")),
+        Err(e) => Err(HttpResponse::InternalServerError().body(format!("DB error: {}", e))),
+    }
+}
+
+// ----------------------------------------------------------------------
+// POST /messages/{chat_id}/attachments => multipart upload bound to a new message
+//    Parts named "files" are stored; an optional "content" part becomes the text.
+// ----------------------------------------------------------------------
+pub async fn upload_attachments(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    chat_id_path: web::Path<String>,
+    mut payload: Multipart,
+) -> impl Responder {
+    let user_id = auth.user_id().to_string();
+    let chat_id = chat_id_path.into_inner();
+    if let Err(resp) = require_participant(&data, &chat_id, &user_id).await {
+        return resp;
+    }
+
+    let bucket = bucket(&data);
+    let mut content = String::new();
+    let mut attachments: Vec<ChatAttachment> = Vec::new();
+
+    // Parts are stored as they arrive; if a later one fails, the earlier ones go too.
+    let received: Result<(), HttpResponse> = async {
+        let mut parts = 0;
+        while let Some(item) = payload.next().await {
+            parts += 1;
+            if parts > MAX_PARTS {
+                return Err(HttpResponse::PayloadTooLarge().body(format!("At most {} parts can be uploaded at once", MAX_PARTS)));
+            }
+            let mut field = match item {
+                Ok(f) => f,
+                Err(e) => return Err(HttpResponse::BadRequest().body(format!("Malformed multipart: {}", e))),
+            };
+
+            let field_name = field.name().unwrap_or("").to_string();
+
+            if field_name == "content" {
+                let mut bytes: Vec<u8> = Vec::new();
+                while let Some(chunk) = field.next().await {
+                    let chunk = match chunk {
+                        Ok(c) => c,
+                        Err(e) => return Err(HttpResponse::BadRequest().body(format!("Upload interrupted: {}", e))),
+                    };
+                    if bytes.len() + chunk.len() > MAX_CONTENT_BYTES {
+                        return Err(HttpResponse::PayloadTooLarge().body("Message content exceeds the size limit"));
+                    }
+                    bytes.extend_from_slice(&chunk);
+                }
+                content = String::from_utf8_lossy(&bytes).into_owned();
+                continue;
+            }
+
+            let filename = field
+                .content_disposition()
+                .and_then(|cd| cd.get_filename())
+                .unwrap_or("attachment")
+                .to_string();
+            let content_type = field
+                .content_type()
+                .map(|m| m.essence_str().to_string())
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+
+            // Stream each chunk straight into GridFS; only images small enough to
+            // thumbnail are also kept in memory.
+            let mut upload = match bucket
+                .open_upload_stream(&filename)
+                .metadata(doc! { "chat_id": &chat_id, "content_type": &content_type })
+                .await
+            {
+                Ok(u) => u,
+                Err(e) => {
+                    error!("Error opening attachment upload: {}", e);
+                    return Err(HttpResponse::InternalServerError().body("Error storing attachment"));
+                }
+            };
+            let mut image_bytes: Option<Vec<u8>> = content_type.starts_with("image/").then(Vec::new);
+            let mut size: usize = 0;
+            while let Some(chunk) = field.next().await {
+                let chunk = match chunk {
+                    Ok(c) => c,
+                    Err(e) => {
+                        let _ = upload.abort().await;
+                        return Err(HttpResponse::BadRequest().body(format!("Upload interrupted: {}", e)));
+                    }
+                };
+                size += chunk.len();
+                if size > data.config.upload_limit {
+                    let _ = upload.abort().await;
+                    return Err(HttpResponse::PayloadTooLarge().body("Attachment exceeds the size limit"));
+                }
+                if let Err(e) = upload.write_all(&chunk).await {
+                    error!("Error writing attachment: {}", e);
+                    let _ = upload.abort().await;
+                    return Err(HttpResponse::InternalServerError().body("Error storing attachment"));
+                }
+                if let Some(buf) = image_bytes.as_mut() {
+                    if buf.len() + chunk.len() > MAX_THUMBNAIL_SOURCE_BYTES {
+                        image_bytes = None;
+                    } else {
+                        buf.extend_from_slice(&chunk);
+                    }
+                }
+            }
+            if let Err(e) = upload.close().await {
+                error!("Error finalizing attachment: {}", e);
+                return Err(HttpResponse::InternalServerError().body("Error storing attachment"));
+            }
+            let file_id = match upload.id().as_object_id() {
+                Some(id) => id,
+                None => return Err(HttpResponse::InternalServerError().body("Error storing attachment")),
+            };
+
+            let mut thumbnail_id = None;
+            if let Some(bytes) = image_bytes {
+                // Decoding and resizing is CPU-bound; keep it off the async workers.
+                if let Ok(Some(thumb)) = web::block(move || make_thumbnail(&bytes)).await {
+                    let thumb_name = format!("thumb_{}.png", filename);
+                    match store_file(&bucket, &thumb_name, "image/png", &chat_id, &thumb).await {
+                        Ok(id) => thumbnail_id = Some(id.to_hex()),
+                        Err(e) => error!("Error storing thumbnail: {}", e),
+                    }
+                }
+            }
+
+            attachments.push(ChatAttachment {
+                attachment_id: file_id.to_hex(),
+                filename,
+                content_type,
+                size: size as u64,
+                thumbnail_id,
+                url: None,
+            });
+        }
+        Ok(())
+    }
+    .await;
+    if let Err(resp) = received {
+        discard_attachments(&bucket, &attachments).await;
+        return resp;
+    }
+
+    if attachments.is_empty() {
+        return HttpResponse::BadRequest().body("No files were uploaded");
+    }
+    info!("User {} uploaded {} attachment(s) to chat {}", user_id, attachments.len(), chat_id);
+
+    let create_msg = CreateMessage {
+        user_id,
+        chat_id,
+        content,
+        attachments: Some(attachments.clone()),
+    };
+    let resp = match data.chat_server.send(create_msg).await {
+        Ok(Ok(msg_response)) => return HttpResponse::Ok().json(msg_response),
+        Ok(Err(_)) => HttpResponse::InternalServerError().body("Failed to create message"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Actor mailbox error: {:?}", e)),
+    };
+    discard_attachments(&bucket, &attachments).await;
+    resp
+}
+
+// ----------------------------------------------------------------------
+// GET /attachments/{attachment_id} => raw file or thumbnail (participants only)
+// ----------------------------------------------------------------------
+pub async fn get_attachment(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    attachment_id: web::Path<String>,
+) -> impl Responder {
+    let user_id = auth.user_id().to_string();
+    let oid = match ObjectId::parse_str(attachment_id.as_str()) {
+        Ok(oid) => oid,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid attachment id"),
+    };
+
+    let bucket = bucket(&data);
+    let file = match bucket.find_one(doc! { "_id": oid }).await {
+        Ok(Some(f)) => f,
+        Ok(None) => return HttpResponse::NotFound().body("Attachment not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    };
+    // Files received by email belong to a project rather than a chat.
+    let project_id = file.metadata.as_ref().and_then(|m| m.get_str("project_id").ok());
+    if let Some(project_id) = project_id {
+        if !auth.is_project_member(project_id).await {
+            return HttpResponse::Forbidden().body("You are not a member of this project.");
+        }
+    } else {
+        let chat_id = file
+            .metadata
+            .as_ref()
+            .and_then(|m| m.get_str("chat_id").ok())
+            .unwrap_or("")
+            .to_string();
+        if let Err(resp) = require_participant(&data, &chat_id, &user_id).await {
+            return resp;
+        }
+    }
+
+    let stream = match bucket.open_download_stream(Bson::ObjectId(oid)).await {
+        Ok(s) => s,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Download failed: {}", e)),
+    };
+
+    let content_type = file
+        .metadata
+        .as_ref()
+        .and_then(|m| m.get_str("content_type").ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let filename = file.filename.unwrap_or_else(|| "attachment".to_string());
+    // Uploaders choose the content type, so only plain raster images render inline.
+    let disposition = if INLINE_TYPES.contains(&content_type.as_str()) { "inline" } else { "attachment" };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("Content-Disposition", content_disposition(disposition, &filename)))
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .no_chunking(file.length)
+        .streaming(download_body(stream))
+}
+
+/// The stored file as a response body, read from GridFS chunk by chunk.
+fn download_body(stream: GridFsDownloadStream) -> impl Stream<Item = std::io::Result<web::Bytes>> {
+    futures_util::stream::try_unfold(stream, |mut stream| async move {
+        let mut buf = vec![0u8; DOWNLOAD_CHUNK_BYTES];
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buf.truncate(read);
+        Ok(Some((web::Bytes::from(buf), stream)))
+    })
+}
@@ -1,21 +1,378 @@
 // File: chat_db.rs
 
-use mongodb::{options::ClientOptions, Client, Database};
+use mongodb::{options::{ClientOptions, Collation, CollationStrength, IndexOptions}, Client, Database, IndexModel};
 use mongodb::bson::{doc, Document};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::db_pool::{self, RetryPolicy};
+
+/// Case-insensitive (collection, scope field, name field) indexes behind quick search's
+/// prefix lookups, see quick_search.rs.
+pub const PREFIX_INDEXES: [(&str, &str, &str); 7] = [
+    ("tickets", "project_id", "title"),
+    ("projects", "team_id", "name"),
+    ("boards", "project_id", "name"),
+    ("knowledge_base", "team_id", "title"),
+    ("chats", "participants", "group_name"),
+    ("users", "_id", "username"),
+    ("users", "_id", "email"),
+];
+
+/// Collation of `PREFIX_INDEXES`; queries must use the same one to be served by them.
+pub fn prefix_collation() -> Collation {
+    Collation::builder().locale("en").strength(CollationStrength::Secondary).build()
+}
 
 pub struct MongoDB {
     pub client: Client,
     pub db: Database,
+    /// Retries for transient errors, applied by the repository layer
+    pub retry: RetryPolicy,
 }
 
 impl MongoDB {
-    pub async fn init(uri: &str, db_name: &str) -> Self {
-        let client_options = ClientOptions::parse(uri)
+    pub async fn init(config: &Config) -> Self {
+        let mut client_options = ClientOptions::parse(&config.mongo_uri)
             .await
             .expect("Failed to parse MongoDB connection string");
+        if let Some(n) = config.mongo_max_pool_size {
+            client_options.max_pool_size = Some(n);
+        }
+        if let Some(n) = config.mongo_min_pool_size {
+            client_options.min_pool_size = Some(n);
+        }
+        if let Some(ms) = config.mongo_connect_timeout_ms {
+            client_options.connect_timeout = Some(Duration::from_millis(ms));
+        }
+        if let Some(ms) = config.mongo_server_selection_timeout_ms {
+            client_options.server_selection_timeout = Some(Duration::from_millis(ms));
+        }
+        if let Some(retry) = config.mongo_retry_writes {
+            client_options.retry_writes = Some(retry);
+        }
+        client_options.cmap_event_handler = Some(db_pool::cmap_handler());
         let client = Client::with_options(client_options).expect("Failed to initialize client");
-        let db = client.database(db_name);
-        MongoDB { client, db }
+        let db = client.database(&config.database_name);
+        let retry = RetryPolicy {
+            attempts: config.mongo_retry_attempts,
+            base_delay: Duration::from_millis(config.mongo_retry_base_ms),
+        };
+        MongoDB { client, db, retry }
+    }
+
+    /// Creates the indexes the handlers rely on. Safe to call on every startup.
+    pub async fn ensure_indexes(&self) -> mongodb::error::Result<()> {
+        let messages = self.db.collection::<Document>("messages");
+        messages
+            .create_index(IndexModel::builder().keys(doc! { "id_chat": 1, "content": "text" }).build())
+            .await?;
+        messages
+            .create_index(IndexModel::builder().keys(doc! { "id_chat": 1, "created_at": 1 }).build())
+            .await?;
+        self.db
+            .collection::<Document>("sync_log")
+            .create_index(IndexModel::builder().keys(doc! { "seq": 1 }).build())
+            .await?;
+        // One event per sequence number; a second writer racing on the same ticket fails here.
+        self.db
+            .collection::<Document>("ticket_events")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "ticket_id": 1, "seq": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        // One membership per user and team; concurrent joins fail here.
+        self.db
+            .collection::<Document>("user_teams")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "team_id": 1, "user_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        // One role version per user; `role_claims::bump` upserts it.
+        self.db
+            .collection::<Document>("role_versions")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("notification_preferences")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        // An escalation rule fires at most once per ticket.
+        self.db
+            .collection::<Document>("escalations")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "rule_id": 1, "ticket_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("dashboard_layouts")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user_id": 1, "team_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("whiteboards")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "whiteboard_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        // One vote per user and ticket.
+        self.db
+            .collection::<Document>("ticket_votes")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "ticket_id": 1, "user_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        // One watch per user and ticket.
+        self.db
+            .collection::<Document>("ticket_watchers")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "ticket_id": 1, "user_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        // Each inbound Message-ID is processed once per channel.
+        self.db
+            .collection::<Document>("inbound_emails")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "channel_id": 1, "message_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("email_channels")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "token_hash": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .unique(true)
+                            .partial_filter_expression(doc! { "token_hash": { "$type": "string" } })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("embeddings")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "kind": 1, "entity_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("message_translations")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "message_id": 1, "lang": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("scheduled_messages")
+            .create_index(IndexModel::builder().keys(doc! { "status": 1, "send_at": 1 }).build())
+            .await?;
+        self.db
+            .collection::<Document>("reminders")
+            .create_index(IndexModel::builder().keys(doc! { "status": 1, "remind_at": 1 }).build())
+            .await?;
+        self.db
+            .collection::<Document>("automation_rules")
+            .create_index(IndexModel::builder().keys(doc! { "project_id": 1, "board_id": 1 }).build())
+            .await?;
+        self.db
+            .collection::<Document>("stale_settings")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "project_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("team_holidays")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "team_id": 1, "date": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("member_imports")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "team_id": 1, "key": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("invite_codes")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "code": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("trusted_devices")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "device_hash": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("team_security_policies")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "team_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        // A domain signs in through at most one SSO connection.
+        self.db
+            .collection::<Document>("sso_connections")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "domains": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("sso_connections")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "scim_token_hash": 1 })
+                    .options(IndexOptions::builder().unique(true).sparse(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("custom_emoji")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "team_id": 1, "name": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("bot_installations")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "chat_id": 1, "bot_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        for collection in ["dnd_settings", "dnd_backlog", "user_statuses", "out_of_office"] {
+            self.db
+                .collection::<Document>(collection)
+                .create_index(
+                    IndexModel::builder()
+                        .keys(doc! { "user_id": 1 })
+                        .options(IndexOptions::builder().unique(true).build())
+                        .build(),
+                )
+                .await?;
+        }
+        for (collection, scope, name) in PREFIX_INDEXES {
+            self.db
+                .collection::<Document>(collection)
+                .create_index(
+                    IndexModel::builder()
+                        .keys(doc! { scope: 1, name: 1 })
+                        .options(IndexOptions::builder().collation(prefix_collation()).build())
+                        .build(),
+                )
+                .await?;
+        }
+        // At most one running focus session per user, and one worklog entry per session.
+        self.db
+            .collection::<Document>("focus_sessions")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user_id": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .unique(true)
+                            .partial_filter_expression(doc! { "ended_at": { "$type": "null" } })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        self.db
+            .collection::<Document>("ticket_worklog")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "focus_session_id": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .unique(true)
+                            .partial_filter_expression(doc! { "focus_session_id": { "$type": "string" } })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        // At most one ringing or active call per chat.
+        self.db
+            .collection::<Document>("call_sessions")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "chat_id": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .unique(true)
+                            .partial_filter_expression(doc! { "open": true })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        Ok(())
     }
 
     /// Returns a BSON filter document for the provided team_id.
@@ -37,6 +394,20 @@ impl MongoDB {
         Ok(result.is_some())
     }
 
+    /// Ids of every team the user belongs to.
+    pub async fn user_team_ids(&self, user_id: &str) -> mongodb::error::Result<Vec<String>> {
+        let collection = self.db.collection::<Document>("user_teams");
+        let values = collection.distinct("team_id", doc! { "user_id": user_id }).await?;
+        Ok(values.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+    }
+
+    /// Projects the user is a member of.
+    pub async fn user_project_ids(&self, user_id: &str) -> mongodb::error::Result<Vec<String>> {
+        let collection = self.db.collection::<Document>("project_memberships");
+        let values = collection.distinct("project_id", doc! { "user_id": user_id }).await?;
+        Ok(values.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+    }
+
     /// Checks if the user is a member of the project.
     pub async fn check_project_membership(&self, user_id: &str, project_id: &str) -> mongodb::error::Result<bool> {
         let collection = self.db.collection::<Document>("project_memberships");
@@ -0,0 +1,282 @@
+// src/chat_export.rs
+//
+// Full chat history export for compliance and archival. The response is streamed
+// straight from the messages cursor, so even very long chats are never held in
+// memory; because each export reads the whole conversation, users are limited to a
+// few per window.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{http::header, web, HttpResponse, Responder};
+use chrono::Utc;
+use futures_util::{future, stream, StreamExt};
+use log::{error, info};
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::api::V1_PREFIX;
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::bots::SYSTEM_BOT;
+use crate::chat::{Chat, DBMessage};
+use crate::dashboard_report::html_escape;
+use crate::response::{CodedError, NDJSON};
+
+/// Exports per user within EXPORT_WINDOW.
+const EXPORT_LIMIT: u32 = 3;
+const EXPORT_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+static EXPORTS: OnceLock<Mutex<HashMap<String, (Instant, u32)>>> = OnceLock::new();
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    pub format: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    Json,
+    /// Chat metadata on the first line, then one message per line
+    Ndjson,
+    Txt,
+    Html,
+}
+
+#[derive(Serialize)]
+struct ExportAttachment<'a> {
+    filename: &'a str,
+    content_type: &'a str,
+    size: u64,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct ExportMessage<'a> {
+    id: &'a str,
+    sender_id: &'a str,
+    sender_name: &'a str,
+    content: &'a str,
+    #[serde(rename = "type")]
+    msg_type: &'a str,
+    created_at: String,
+    attachments: Vec<ExportAttachment<'a>>,
+}
+
+/// Fixed-window limiter; returns false once the user has used up their exports.
+fn allow(user_id: &str) -> bool {
+    let now = Instant::now();
+    let mut map = EXPORTS.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+    if map.len() > 10_000 {
+        map.retain(|_, (start, _)| now.duration_since(*start) < EXPORT_WINDOW);
+    }
+    let entry = map.entry(user_id.to_string()).or_insert((now, 0));
+    if now.duration_since(entry.0) >= EXPORT_WINDOW {
+        *entry = (now, 0);
+    }
+    entry.1 += 1;
+    entry.1 <= EXPORT_LIMIT
+}
+
+/// Display names for user and bot sender ids; unknown ids map to themselves.
+async fn sender_names(data: &AppState, ids: &HashSet<String>) -> mongodb::error::Result<HashMap<String, String>> {
+    let mut names: HashMap<String, String> = ids.iter().map(|id| (id.clone(), id.clone())).collect();
+    names.insert(SYSTEM_BOT.to_string(), "Taskline".to_string());
+
+    let oids: Vec<ObjectId> = ids.iter().filter_map(|id| ObjectId::parse_str(id).ok()).collect();
+    let mut users = data.mongodb.db.collection::<Document>("users").find(doc! { "_id": { "$in": oids } }).await?;
+    while let Some(user) = users.next().await {
+        let user = user?;
+        let Ok(oid) = user.get_object_id("_id") else { continue };
+        let name = user.get_str("username").or_else(|_| user.get_str("email")).unwrap_or_default();
+        if !name.is_empty() {
+            names.insert(oid.to_hex(), name.to_string());
+        }
+    }
+
+    let bot_ids: Vec<&str> = ids.iter().filter_map(|id| id.strip_prefix("bot:")).collect();
+    let mut bots = data.mongodb.db.collection::<Document>("bots").find(doc! { "bot_id": { "$in": bot_ids } }).await?;
+    while let Some(bot) = bots.next().await {
+        let bot = bot?;
+        if let (Ok(id), Ok(name)) = (bot.get_str("bot_id"), bot.get_str("name")) {
+            names.insert(format!("bot:{}", id), format!("{} (bot)", name));
+        }
+    }
+    Ok(names)
+}
+
+fn render_message(format: ExportFormat, first: bool, msg: &DBMessage, names: &HashMap<String, String>, base_url: &str) -> String {
+    let sender_name = names.get(&msg.sender_id).map_or(msg.sender_id.as_str(), String::as_str);
+    let attachments: Vec<ExportAttachment> = msg
+        .attachments
+        .iter()
+        .flatten()
+        .map(|a| ExportAttachment {
+            filename: &a.filename,
+            content_type: &a.content_type,
+            size: a.size,
+            url: a.url.clone().unwrap_or_else(|| format!("{}/attachments/{}", base_url, a.attachment_id)),
+        })
+        .collect();
+    let time = msg.created_at.format("%Y-%m-%d %H:%M:%S UTC");
+    match format {
+        ExportFormat::Json | ExportFormat::Ndjson => {
+            let entry = ExportMessage {
+                id: &msg.id,
+                sender_id: &msg.sender_id,
+                sender_name,
+                content: &msg.content,
+                msg_type: &msg.msg_type,
+                created_at: msg.created_at.to_rfc3339(),
+                attachments,
+            };
+            let json = serde_json::to_string(&entry).unwrap_or_default();
+            match format {
+                ExportFormat::Ndjson => format!("{}\n", json),
+                _ if first => json,
+                _ => format!(",{}", json),
+            }
+        }
+        ExportFormat::Txt => {
+            let mut out = format!("[{}] {}: {}\n", time, sender_name, msg.content);
+            for a in &attachments {
+                out.push_str(&format!("    [attachment] {} <{}>\n", a.filename, a.url));
+            }
+            out
+        }
+        ExportFormat::Html => {
+            let mut out = format!(
+                "<div class=\"msg\"><span class=\"time\">{}</span> <b>{}</b>: {}",
+                time,
+                html_escape(sender_name),
+                html_escape(&msg.content).replace('\n', "<br>")
+            );
+            for a in &attachments {
+                out.push_str(&format!(
+                    "<div class=\"att\"><a href=\"{}\">{}</a></div>",
+                    html_escape(&a.url),
+                    html_escape(a.filename)
+                ));
+            }
+            out.push_str("</div>\n");
+            out
+        }
+    }
+}
+
+/// Everything before the first message, and everything after the last.
+fn frame(format: ExportFormat, chat: &Chat, names: &HashMap<String, String>) -> (String, String) {
+    let title = chat.group_name.clone().filter(|n| !n.is_empty()).unwrap_or_else(|| "Direct chat".to_string());
+    let participants: Vec<&str> = chat
+        .participants
+        .iter()
+        .map(|p| names.get(p).map_or(p.as_str(), String::as_str))
+        .collect();
+    let exported_at = Utc::now().to_rfc3339();
+    match format {
+        ExportFormat::Json | ExportFormat::Ndjson => {
+            let meta = serde_json::json!({
+                "chat_id": chat.id_chat,
+                "name": title,
+                "participants": chat.participants.iter().zip(&participants)
+                    .map(|(id, name)| serde_json::json!({ "user_id": id, "name": name }))
+                    .collect::<Vec<_>>(),
+                "exported_at": exported_at,
+            })
+            .to_string();
+            if let ExportFormat::Ndjson = format {
+                return (format!("{}\n", meta), String::new());
+            }
+            // Reopen the metadata object to append the streamed messages array.
+            (format!("{},\"messages\":[", meta.strip_suffix('}').unwrap_or(&meta)), "]}".to_string())
+        }
+        ExportFormat::Txt => (
+            format!("Chat: {}\nParticipants: {}\nExported: {}\n\n", title, participants.join(", "), exported_at),
+            String::new(),
+        ),
+        ExportFormat::Html => (
+            format!(
+                "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title></head><body>\n\
+                 <h1>{0}</h1>\n<p>Participants: {1}<br>Exported: {2}</p>\n",
+                html_escape(&title),
+                html_escape(&participants.join(", ")),
+                exported_at
+            ),
+            "</body></html>\n".to_string(),
+        ),
+    }
+}
+
+// ----------------------------------------------------------------------
+// GET /chats/{chat_id}/export?format=json|ndjson|txt|html
+//    Participants only; streamed, oldest message first.
+// ----------------------------------------------------------------------
+pub async fn export_chat(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    chat_id: web::Path<String>,
+    query: web::Query<ExportQuery>,
+) -> impl Responder {
+    let (format, extension, content_type) = match query.format.as_deref().unwrap_or("json") {
+        "json" => (ExportFormat::Json, "json", "application/json"),
+        "ndjson" => (ExportFormat::Ndjson, "ndjson", NDJSON),
+        "txt" => (ExportFormat::Txt, "txt", "text/plain; charset=utf-8"),
+        "html" => (ExportFormat::Html, "html", "text/html; charset=utf-8"),
+        _ => return HttpResponse::BadRequest().body("format must be json, ndjson, txt or html"),
+    };
+    let chat_id = chat_id.into_inner();
+    let chats = data.mongodb.db.collection::<Chat>("chats");
+    let chat = match chats.find_one(doc! { "_id": &chat_id, "participants": auth.user_id() }).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return HttpResponse::Forbidden().error("not_chat_participant"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    };
+    if !allow(auth.user_id()) {
+        return HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", EXPORT_WINDOW.as_secs().to_string()))
+            .body("Too many exports, try again later");
+    }
+
+    let messages = data.mongodb.db.collection::<DBMessage>("messages");
+    let senders: HashSet<String> = match messages.distinct("sender_id", doc! { "id_chat": &chat_id }).await {
+        Ok(ids) => ids.iter().filter_map(|v| v.as_str().map(str::to_string)).chain(chat.participants.iter().cloned()).collect(),
+        Err(e) => {
+            error!("Error fetching chat senders: {}", e);
+            return HttpResponse::InternalServerError().body("Error exporting chat");
+        }
+    };
+    let names = match sender_names(&data, &senders).await {
+        Ok(n) => n,
+        Err(e) => {
+            error!("Error resolving sender names: {}", e);
+            return HttpResponse::InternalServerError().body("Error exporting chat");
+        }
+    };
+    let cursor = match messages.find(doc! { "id_chat": &chat_id }).sort(doc! { "created_at": 1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching messages: {}", e);
+            return HttpResponse::InternalServerError().body("Error exporting chat");
+        }
+    };
+    info!("User {} exported chat {} as {}", auth.user_id(), chat_id, extension);
+
+    let (head, tail) = frame(format, &chat, &names);
+    let base_url = format!("{}{}", data.config.app_base_url.trim_end_matches('/'), V1_PREFIX);
+    let body = stream::once(future::ready(Ok(web::Bytes::from(head))))
+        .chain(cursor.enumerate().map(move |(n, msg)| match msg {
+            Ok(msg) => Ok(web::Bytes::from(render_message(format, n == 0, &msg, &names, &base_url))),
+            Err(e) => {
+                error!("Error streaming chat export: {}", e);
+                Err(ErrorInternalServerError("Error reading messages"))
+            }
+        }))
+        .chain(stream::once(future::ready(Ok(web::Bytes::from(tail)))));
+
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"chat_{}.{}\"", chat_id, extension)))
+        .streaming(body)
+}
@@ -6,9 +6,17 @@ use mongodb::bson::{doc, DateTime as BsonDateTime};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use log::{error, info};
 
 use crate::app_state::AppState;
+use crate::board_live::BoardRoom;
+use crate::chat_attachments::ChatAttachment;
+use crate::do_not_disturb::quiet_recipients;
+use crate::emoji::expand_shortcodes;
+use crate::estimation_poker::PokerSession;
+use crate::out_of_office::auto_replies;
+use crate::sync::{record_change, Entity, Op, Scope};
 
 #[derive(Message)]
 #[rtype(result = "()")]
@@ -16,6 +24,9 @@ pub struct ChatMessage {
     pub chat_id: String,
     pub sender_id: String,
     pub content: String,
+    pub attachments: Vec<ChatAttachment>,
+    /// Deliver without alerting: the recipient muted the chat or is in do-not-disturb
+    pub silent: bool,
 }
 
 #[derive(Message)]
@@ -29,6 +40,8 @@ pub struct SignalMessage {
 pub enum WsMessage {
     Chat(ChatMessage),
     Signal(SignalMessage),
+    /// Ask the session to close its socket with the given reason.
+    Close(String),
 }
 
 #[derive(Message)]
@@ -52,7 +65,7 @@ pub struct CreateMessage {
     pub user_id: String,
     pub chat_id: String,
     pub content: String,
-    pub attachments: Option<String>,
+    pub attachments: Option<Vec<ChatAttachment>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -63,10 +76,10 @@ pub struct MessageResponse {
     pub content: String,
     pub created_at: DateTime<Utc>,
     pub msg_type: String,
-    pub attachments: Option<String>,
+    pub attachments: Option<Vec<ChatAttachment>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chat {
     #[serde(rename = "_id")]
     pub id_chat: String,
@@ -85,10 +98,22 @@ pub struct RelaySignal {
     pub message: String,
 }
 
+/// Chats with a message within this window count as active rooms.
+const ACTIVE_ROOM_WINDOW: Duration = Duration::from_secs(300);
+
 pub struct ChatServer {
     // Change sessions to support multiple connections per user.
     sessions: HashMap<String, Vec<Recipient<WsMessage>>>,
-    db: Arc<MongoDB>,
+    pub(crate) db: Arc<MongoDB>,
+    started_at: Instant,
+    messages_total: u64,
+    signals_total: u64,
+    /// Last time a message or signal passed through each chat.
+    chat_activity: HashMap<String, Instant>,
+    /// Planning poker sessions by ticket id.
+    pub(crate) poker: HashMap<String, PokerSession>,
+    /// Sessions watching each board, by board id.
+    pub(crate) boards: HashMap<String, BoardRoom>,
 }
 
 impl ChatServer {
@@ -96,9 +121,30 @@ impl ChatServer {
         ChatServer {
             sessions: HashMap::new(),
             db,
+            started_at: Instant::now(),
+            messages_total: 0,
+            signals_total: 0,
+            chat_activity: HashMap::new(),
+            poker: HashMap::new(),
+            boards: HashMap::new(),
+        }
+    }
+
+    /// Send a JSON payload to every open session of one user.
+    pub(crate) fn push(&self, user_id: &str, payload: &str) {
+        if let Some(addrs) = self.sessions.get(user_id) {
+            for addr in addrs {
+                addr.do_send(WsMessage::Signal(SignalMessage { payload: payload.to_string() }));
+            }
         }
     }
 
+    fn touch_chat(&mut self, chat_id: &str) {
+        let now = Instant::now();
+        self.chat_activity.insert(chat_id.to_string(), now);
+        self.chat_activity.retain(|_, t| now.duration_since(*t) < ACTIVE_ROOM_WINDOW);
+    }
+
     async fn get_chat_by_id(&self, chat_id_str: &str) -> Option<Chat> {
         let collection = self.db.db.collection::<Chat>("chats");
         match collection.find_one(doc! { "_id": chat_id_str }).await {
@@ -139,10 +185,79 @@ impl Handler<Disconnect> for ChatServer {
     }
 }
 
+/// Store a message and push it to every participant other than the sender.
+async fn store_and_broadcast(
+    db: Arc<MongoDB>,
+    sessions_map: HashMap<String, Vec<Recipient<WsMessage>>>,
+    chat_doc: Chat,
+    sender_id: String,
+    content: String,
+    attachments: Option<Vec<ChatAttachment>>,
+    msg_type: &str,
+) -> Result<MessageResponse, ()> {
+    let content = expand_shortcodes(&content);
+    let now = Utc::now();
+    let new_msg_id = uuid::Uuid::new_v4().to_string();
+    #[derive(Serialize)]
+    struct DBMessage {
+        #[serde(rename = "_id")]
+        pub id: String,
+        pub id_chat: String,
+        pub sender_id: String,
+        pub content: String,
+        pub created_at: DateTime<Utc>,
+        #[serde(rename = "type")]
+        pub msg_type: String,
+        pub attachments: Option<Vec<ChatAttachment>>,
+    }
+    let new_db_msg = DBMessage {
+        id: new_msg_id.clone(),
+        id_chat: chat_doc.id_chat.clone(),
+        sender_id: sender_id.clone(),
+        content: content.clone(),
+        created_at: now,
+        msg_type: msg_type.to_string(),
+        attachments: attachments.clone(),
+    };
+    let messages_coll = db.db.collection::<DBMessage>("messages");
+    if messages_coll.insert_one(&new_db_msg).await.is_err() {
+        return Err(());
+    }
+    record_change(&db, Entity::Message, &new_msg_id, Op::Upsert, Scope::Users(&chat_doc.participants)).await;
+    let recipients: Vec<String> =
+        chat_doc.participants.iter().filter(|p| *p != &sender_id).cloned().collect();
+    let quiet = quiet_recipients(&db, &chat_doc.id_chat, &recipients).await;
+    for participant_id in &recipients {
+        if let Some(ws_addrs) = sessions_map.get(participant_id) {
+            // Send to all active connections for that user.
+            for addr in ws_addrs {
+                addr.do_send(WsMessage::Chat(ChatMessage {
+                    chat_id: chat_doc.id_chat.clone(),
+                    sender_id: sender_id.clone(),
+                    content: content.clone(),
+                    attachments: attachments.clone().unwrap_or_default(),
+                    silent: quiet.contains(participant_id),
+                }));
+            }
+        }
+    }
+    Ok(MessageResponse {
+        id: new_msg_id,
+        id_chat: chat_doc.id_chat,
+        sender_id,
+        content,
+        created_at: now,
+        msg_type: msg_type.to_string(),
+        attachments,
+    })
+}
+
 impl Handler<CreateMessage> for ChatServer {
     type Result = ResponseFuture<Result<MessageResponse, ()>>;
 
     fn handle(&mut self, msg: CreateMessage, _: &mut Context<Self>) -> Self::Result {
+        self.messages_total += 1;
+        self.touch_chat(&msg.chat_id);
         let db = self.db.clone();
         let sessions_map = self.sessions.clone();
         Box::pin(async move {
@@ -154,56 +269,45 @@ impl Handler<CreateMessage> for ChatServer {
             if !chat_doc.participants.contains(&msg.user_id) {
                 return Err(());
             }
-            let now = Utc::now();
-            let new_msg_id = uuid::Uuid::new_v4().to_string();
-            #[derive(Serialize)]
-            struct DBMessage {
-                #[serde(rename = "_id")]
-                pub id: String,
-                pub id_chat: String,
-                pub sender_id: String,
-                pub content: String,
-                pub created_at: DateTime<Utc>,
-                #[serde(rename = "type")]
-                pub msg_type: String,
-                pub attachments: Option<String>,
+            let response = store_and_broadcast(
+                db.clone(), sessions_map.clone(), chat_doc.clone(), msg.user_id, msg.content, msg.attachments, "text",
+            ).await?;
+            // Out-of-office replies to mentions follow the message that triggered them.
+            let replies = auto_replies(&db, &chat_doc.id_chat, &chat_doc.participants, &response.sender_id, &response.content).await;
+            for (user_id, text) in replies {
+                let _ = store_and_broadcast(db.clone(), sessions_map.clone(), chat_doc.clone(), user_id, text, None, "auto_reply").await;
             }
-            let new_db_msg = DBMessage {
-                id: new_msg_id.clone(),
-                id_chat: msg.chat_id.clone(),
-                sender_id: msg.user_id.clone(),
-                content: msg.content.clone(),
-                created_at: now,
-                msg_type: "text".to_string(),
-                attachments: msg.attachments.clone(),
+            Ok(response)
+        })
+    }
+}
+
+/// A message posted by a bot. The caller has already checked that the bot is
+/// installed in the chat.
+#[derive(Message)]
+#[rtype(result = "Result<MessageResponse, ()>")]
+pub struct PostBotMessage {
+    /// Sender id recorded on the message, `bot:{bot_id}`
+    pub sender_id: String,
+    pub chat_id: String,
+    pub content: String,
+}
+
+impl Handler<PostBotMessage> for ChatServer {
+    type Result = ResponseFuture<Result<MessageResponse, ()>>;
+
+    fn handle(&mut self, msg: PostBotMessage, _: &mut Context<Self>) -> Self::Result {
+        self.messages_total += 1;
+        self.touch_chat(&msg.chat_id);
+        let db = self.db.clone();
+        let sessions_map = self.sessions.clone();
+        Box::pin(async move {
+            let chats_coll = db.db.collection::<Chat>("chats");
+            let chat_doc = match chats_coll.find_one(doc! { "_id": &msg.chat_id }).await {
+                Ok(Some(c)) => c,
+                _ => return Err(()),
             };
-            let messages_coll = db.db.collection::<DBMessage>("messages");
-            if messages_coll.insert_one(&new_db_msg).await.is_err() {
-                return Err(());
-            }
-            for participant_id in &chat_doc.participants {
-                if participant_id != &msg.user_id {
-                    if let Some(ws_addrs) = sessions_map.get(participant_id) {
-                        // Send to all active connections for that user.
-                        for addr in ws_addrs {
-                            addr.do_send(WsMessage::Chat(ChatMessage {
-                                chat_id: msg.chat_id.clone(),
-                                sender_id: msg.user_id.clone(),
-                                content: msg.content.clone(),
-                            }));
-                        }
-                    }
-                }
-            }
-            Ok(MessageResponse {
-                id: new_msg_id,
-                id_chat: msg.chat_id,
-                sender_id: msg.user_id,
-                content: msg.content,
-                created_at: now,
-                msg_type: "text".to_string(),
-                attachments: msg.attachments,
-            })
+            store_and_broadcast(db, sessions_map, chat_doc, msg.sender_id, msg.content, None, "bot").await
         })
     }
 }
@@ -212,6 +316,8 @@ impl Handler<RelaySignal> for ChatServer {
     type Result = ResponseFuture<()>;
 
     fn handle(&mut self, msg: RelaySignal, _ctx: &mut Context<Self>) -> Self::Result {
+        self.signals_total += 1;
+        self.touch_chat(&msg.chat_id);
         let sessions_map = self.sessions.clone();
         let db = self.db.clone();
         Box::pin(async move {
@@ -232,3 +338,131 @@ impl Handler<RelaySignal> for ChatServer {
         })
     }
 }
+
+#[derive(Debug, Serialize)]
+pub struct UserSessions {
+    pub user_id: String,
+    pub sessions: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WsStats {
+    pub connected_users: usize,
+    pub total_sessions: usize,
+    pub sessions_per_user: Vec<UserSessions>,
+    /// Chats with traffic in the last five minutes
+    pub active_rooms: Vec<String>,
+    pub messages_total: u64,
+    pub signals_total: u64,
+    pub uptime_secs: u64,
+    pub messages_per_minute: f64,
+}
+
+#[derive(Message)]
+#[rtype(result = "WsStats")]
+pub struct GetWsStats;
+
+impl Handler<GetWsStats> for ChatServer {
+    type Result = MessageResult<GetWsStats>;
+
+    fn handle(&mut self, _: GetWsStats, _: &mut Context<Self>) -> Self::Result {
+        let now = Instant::now();
+        let mut sessions_per_user: Vec<UserSessions> = self
+            .sessions
+            .iter()
+            .map(|(user_id, addrs)| UserSessions { user_id: user_id.clone(), sessions: addrs.len() })
+            .collect();
+        sessions_per_user.sort_by_key(|s| std::cmp::Reverse(s.sessions));
+
+        let uptime = now.duration_since(self.started_at);
+        let minutes = (uptime.as_secs_f64() / 60.0).max(1.0 / 60.0);
+        MessageResult(WsStats {
+            connected_users: self.sessions.len(),
+            total_sessions: self.sessions.values().map(|v| v.len()).sum(),
+            sessions_per_user,
+            active_rooms: self
+                .chat_activity
+                .iter()
+                .filter(|(_, t)| now.duration_since(**t) < ACTIVE_ROOM_WINDOW)
+                .map(|(id, _)| id.clone())
+                .collect(),
+            messages_total: self.messages_total,
+            signals_total: self.signals_total,
+            uptime_secs: uptime.as_secs(),
+            messages_per_minute: self.messages_total as f64 / minutes,
+        })
+    }
+}
+
+/// Close every WebSocket belonging to `user_id`; returns how many were closed.
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct ForceDisconnect {
+    pub user_id: String,
+}
+
+impl Handler<ForceDisconnect> for ChatServer {
+    type Result = usize;
+
+    fn handle(&mut self, msg: ForceDisconnect, _: &mut Context<Self>) -> Self::Result {
+        match self.sessions.remove(&msg.user_id) {
+            Some(addrs) => {
+                info!("Force-disconnecting {} session(s) of user {}", addrs.len(), msg.user_id);
+                for addr in &addrs {
+                    addr.do_send(WsMessage::Close("Disconnected by administrator".to_string()));
+                }
+                addrs.len()
+            }
+            None => 0,
+        }
+    }
+}
+
+/// Push a JSON payload to every open session of one user.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct NotifyUser {
+    pub user_id: String,
+    pub payload: String,
+}
+
+impl Handler<NotifyUser> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyUser, _: &mut Context<Self>) {
+        self.push(&msg.user_id, &msg.payload);
+    }
+}
+
+/// Like `NotifyUser`, but reports whether the user had an open session to push to.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct PushIfOnline {
+    pub user_id: String,
+    pub payload: String,
+}
+
+impl Handler<PushIfOnline> for ChatServer {
+    type Result = bool;
+
+    fn handle(&mut self, msg: PushIfOnline, _: &mut Context<Self>) -> Self::Result {
+        let online = self.sessions.get(&msg.user_id).is_some_and(|addrs| !addrs.is_empty());
+        self.push(&msg.user_id, &msg.payload);
+        online
+    }
+}
+
+/// Whether the user has at least one open WebSocket.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct IsOnline {
+    pub user_id: String,
+}
+
+impl Handler<IsOnline> for ChatServer {
+    type Result = bool;
+
+    fn handle(&mut self, msg: IsOnline, _: &mut Context<Self>) -> Self::Result {
+        self.sessions.get(&msg.user_id).is_some_and(|addrs| !addrs.is_empty())
+    }
+}
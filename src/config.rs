@@ -5,11 +5,78 @@ use mongodb::bson::doc;
 pub struct Config {
     pub mongo_uri: String,
     pub database_name: String,
+    /// Pool and timeout overrides; unset keeps the connection string's (or driver's) value
+    pub mongo_max_pool_size: Option<u32>,
+    pub mongo_min_pool_size: Option<u32>,
+    pub mongo_connect_timeout_ms: Option<u64>,
+    pub mongo_server_selection_timeout_ms: Option<u64>,
+    pub mongo_retry_writes: Option<bool>,
+    /// Tries per operation in the repository layer's retry wrapper, including the first
+    pub mongo_retry_attempts: u32,
+    /// Backoff before the first retry; doubles on each further retry
+    pub mongo_retry_base_ms: u64,
     pub jwt_secret: String,
     pub default_team_id: Option<String>,
     pub ai_local_endpoint: String,
     pub ai_aws_endpoint: String,
     pub ai_use_local: bool,
+    /// JSON body limits in bytes, per route group
+    pub json_limit_default: usize,
+    pub json_limit_auth: usize,
+    pub json_limit_kb: usize,
+    /// Maximum size in bytes of a single uploaded file
+    pub upload_limit: usize,
+    /// Issue the JWT as an httpOnly cookie (with CSRF protection) instead of in the body
+    pub cookie_sessions: bool,
+    pub cookie_secure: bool,
+    /// "strict", "lax" or "none"
+    pub cookie_same_site: String,
+    /// Public URL of this API, used to build links in emails
+    pub app_base_url: String,
+    pub email_api_endpoint: Option<String>,
+    pub email_from: String,
+    /// How long an unverified account may still log in after signup
+    pub email_verification_grace_hours: i64,
+    /// Comma-separated allowed origins; entries may use a leading `*.` wildcard
+    pub cors_origins: String,
+    /// Allow any origin (development only)
+    pub cors_permissive: bool,
+    /// Users allowed to call the /admin endpoints
+    pub admin_user_ids: Vec<String>,
+    /// Bearer token accepted by /metrics for scrapers
+    pub metrics_token: Option<String>,
+    /// Email the dashboard report to team admins once a week
+    pub weekly_reports: bool,
+    /// Send users their daily/weekly activity digests
+    pub digest_emails: bool,
+    /// Comma-separated `key_id:base64-key` pairs for encryption at rest; the first encrypts
+    pub encryption_keys: Option<String>,
+    /// Record API usage into the capped `api_logs` collection
+    pub api_logging: bool,
+    /// Size cap of `api_logs` in bytes
+    pub api_log_max_bytes: usize,
+    /// Enables the `/giphy` chat command
+    pub giphy_api_key: Option<String>,
+    /// Reverse proxies (addresses or CIDRs) whose `X-Forwarded-For` hop is believed
+    pub trusted_proxies: Vec<String>,
+    /// Require an admin-issued invite code to sign up
+    pub signup_invite_only: bool,
+    /// Video meeting provider for calendar events: `zoom`, `google_meet` or `jitsi`
+    pub meeting_provider: Option<String>,
+    /// Jitsi room URL with a `{room}` placeholder; also the fallback when the provider fails
+    pub jitsi_url_pattern: Option<String>,
+    /// Zoom server-to-server OAuth app
+    pub zoom_account_id: Option<String>,
+    pub zoom_client_id: Option<String>,
+    pub zoom_client_secret: Option<String>,
+    /// Google OAuth client and the refresh token of the account that owns the meetings
+    pub google_meet_client_id: Option<String>,
+    pub google_meet_client_secret: Option<String>,
+    pub google_meet_refresh_token: Option<String>,
+    /// Reads pull request state for boards' definition of done; public repositories work without it
+    pub github_token: Option<String>,
+    /// Put the user's team and project roles into their tokens (see role_claims.rs)
+    pub jwt_role_claims: bool,
 }
 
 impl Config {
@@ -23,6 +90,22 @@ impl Config {
         Self {
             mongo_uri: env::var("MONGO_URI").expect("MONGO_URI must be set"),
             database_name: env::var("DATABASE_NAME").unwrap_or_else(|_| "chat_db".to_string()),
+            mongo_max_pool_size: env::var("MONGO_MAX_POOL_SIZE").ok().and_then(|v| v.parse().ok()),
+            mongo_min_pool_size: env::var("MONGO_MIN_POOL_SIZE").ok().and_then(|v| v.parse().ok()),
+            mongo_connect_timeout_ms: env::var("MONGO_CONNECT_TIMEOUT_MS").ok().and_then(|v| v.parse().ok()),
+            mongo_server_selection_timeout_ms: env::var("MONGO_SERVER_SELECTION_TIMEOUT_MS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            mongo_retry_writes: env::var("MONGO_RETRY_WRITES").ok().and_then(|v| v.parse().ok()),
+            mongo_retry_attempts: env::var("MONGO_RETRY_ATTEMPTS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|n| *n >= 1)
+                .unwrap_or(3),
+            mongo_retry_base_ms: env::var("MONGO_RETRY_BASE_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(50),
             jwt_secret: env::var("JWT_SECRET").expect("JWT_SECRET must be set"),
             default_team_id: env::var("DEFAULT_TEAM_ID").ok(),
             ai_local_endpoint: env::var("AI_LOCAL_ENDPOINT")
@@ -30,6 +113,73 @@ impl Config {
             ai_aws_endpoint: env::var("AI_AWS_ENDPOINT")
                 .expect("AI_AWS_ENDPOINT must be set"),
             ai_use_local,
+            json_limit_default: limit_from_env("JSON_LIMIT_DEFAULT", 256 * 1024),
+            json_limit_auth: limit_from_env("JSON_LIMIT_AUTH", 8 * 1024),
+            json_limit_kb: limit_from_env("JSON_LIMIT_KB", 4 * 1024 * 1024),
+            upload_limit: limit_from_env("UPLOAD_LIMIT", 25 * 1024 * 1024),
+            cookie_sessions: env::var("COOKIE_SESSIONS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(false),
+            cookie_secure: env::var("COOKIE_SECURE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(true),
+            cookie_same_site: env::var("COOKIE_SAME_SITE").unwrap_or_else(|_| "strict".to_string()),
+            app_base_url: env::var("APP_BASE_URL").unwrap_or_else(|_| "http://localhost:8080".to_string()),
+            email_api_endpoint: env::var("EMAIL_API_ENDPOINT").ok(),
+            email_from: env::var("EMAIL_FROM").unwrap_or_else(|_| "no-reply@taskline.local".to_string()),
+            email_verification_grace_hours: env::var("EMAIL_VERIFICATION_GRACE_HOURS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(24),
+            // FRONTEND_ORIGIN is still honoured for single-origin deployments.
+            cors_origins: env::var("FRONTEND_ORIGINS")
+                .or_else(|_| env::var("FRONTEND_ORIGIN"))
+                .unwrap_or_else(|_| "http://localhost:3000".to_string()),
+            cors_permissive: env::var("CORS_PERMISSIVE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(false),
+            admin_user_ids: env::var("ADMIN_USER_IDS")
+                .map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+                .unwrap_or_default(),
+            metrics_token: env::var("METRICS_TOKEN").ok().filter(|t| !t.is_empty()),
+            weekly_reports: env::var("WEEKLY_REPORTS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(false),
+            digest_emails: env::var("DIGEST_EMAILS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(false),
+            encryption_keys: env::var("ENCRYPTION_KEYS").ok().filter(|k| !k.trim().is_empty()),
+            api_logging: env::var("API_LOGGING")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(false),
+            api_log_max_bytes: limit_from_env("API_LOG_MAX_BYTES", 256 * 1024 * 1024),
+            giphy_api_key: env::var("GIPHY_API_KEY").ok().filter(|k| !k.is_empty()),
+            trusted_proxies: env::var("TRUSTED_PROXIES")
+                .map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+                .unwrap_or_default(),
+            signup_invite_only: env::var("SIGNUP_INVITE_ONLY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(false),
+            meeting_provider: non_empty_env("MEETING_PROVIDER"),
+            jitsi_url_pattern: non_empty_env("JITSI_URL_PATTERN"),
+            zoom_account_id: non_empty_env("ZOOM_ACCOUNT_ID"),
+            zoom_client_id: non_empty_env("ZOOM_CLIENT_ID"),
+            zoom_client_secret: non_empty_env("ZOOM_CLIENT_SECRET"),
+            google_meet_client_id: non_empty_env("GOOGLE_MEET_CLIENT_ID"),
+            google_meet_client_secret: non_empty_env("GOOGLE_MEET_CLIENT_SECRET"),
+            google_meet_refresh_token: non_empty_env("GOOGLE_MEET_REFRESH_TOKEN"),
+            github_token: non_empty_env("GITHUB_TOKEN"),
+            jwt_role_claims: env::var("JWT_ROLE_CLAIMS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(false),
         }
     }
 
@@ -37,3 +187,11 @@ impl Config {
         self.default_team_id.as_ref().map(|team_id| doc! { "team_id": team_id })
     }
 }
+
+fn limit_from_env(key: &str, default: usize) -> usize {
+    env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+fn non_empty_env(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.trim().is_empty())
+}
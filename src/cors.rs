@@ -0,0 +1,96 @@
+// src/cors.rs
+//
+// Allowed-origin handling for CORS: exact origins plus `*.` wildcard subdomains.
+
+use actix_cors::Cors;
+use actix_web::http;
+
+/// One entry of the allowed-origins list.
+#[derive(Debug, Clone)]
+pub enum OriginRule {
+    /// e.g. `https://app.example.com`
+    Exact(String),
+    /// e.g. `https://*.preview.example.com` -> scheme `https`, suffix `.preview.example.com`
+    Wildcard { scheme: String, suffix: String },
+}
+
+impl OriginRule {
+    /// Parse and validate a single configured origin.
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let raw = raw.trim().trim_end_matches('/');
+        let (scheme, host) = raw
+            .split_once("://")
+            .ok_or_else(|| format!("CORS origin '{}' is missing a scheme", raw))?;
+        if scheme != "http" && scheme != "https" {
+            return Err(format!("CORS origin '{}' must use http or https", raw));
+        }
+        if host.is_empty() || host.contains('/') {
+            return Err(format!("CORS origin '{}' must not contain a path", raw));
+        }
+        if let Some(rest) = host.strip_prefix("*.") {
+            if rest.is_empty() || rest.contains('*') || !rest.contains('.') {
+                return Err(format!("CORS wildcard '{}' must look like scheme://*.domain.tld", raw));
+            }
+            return Ok(OriginRule::Wildcard { scheme: scheme.to_string(), suffix: format!(".{}", rest) });
+        }
+        if host.contains('*') {
+            return Err(format!("CORS origin '{}' may only use a leading '*.' wildcard", raw));
+        }
+        Ok(OriginRule::Exact(raw.to_string()))
+    }
+
+    pub fn matches(&self, origin: &str) -> bool {
+        match self {
+            OriginRule::Exact(o) => o == origin,
+            OriginRule::Wildcard { scheme, suffix } => origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|o| o.strip_prefix("://"))
+                .map(|host| {
+                    host.len() > suffix.len()
+                        && host.ends_with(suffix.as_str())
+                        && !host[..host.len() - suffix.len()].contains('/')
+                })
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Parse the comma-separated origins list, failing on the first invalid entry.
+pub fn parse_origins(list: &str) -> Result<Vec<OriginRule>, String> {
+    let rules = list
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(OriginRule::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    if rules.is_empty() {
+        return Err("No CORS origins configured".to_string());
+    }
+    Ok(rules)
+}
+
+/// Build the CORS middleware. `permissive` is meant for local development only.
+pub fn build_cors(rules: &[OriginRule], permissive: bool) -> Cors {
+    if permissive {
+        return Cors::permissive();
+    }
+
+    let rules = rules.to_vec();
+    Cors::default()
+        .allowed_origin_fn(move |origin, _req_head| {
+            origin
+                .to_str()
+                .map(|o| rules.iter().any(|r| r.matches(o)))
+                .unwrap_or(false)
+        })
+        .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"])
+        .allowed_headers(vec![
+            http::header::CONTENT_TYPE,
+            http::header::ACCEPT,
+            http::header::AUTHORIZATION,
+            http::header::HeaderName::from_static("x-csrf-token"),
+            http::header::HeaderName::from_static("accept-version"),
+        ])
+        .expose_headers(crate::api::EXPOSED_HEADERS)
+        .supports_credentials()
+        .max_age(3600)
+}
@@ -0,0 +1,253 @@
+// src/custom_emoji.rs
+//
+// Team custom emoji. Any team member can upload one (a name plus a small image kept
+// in GridFS); only team admins can delete them. They are used as `:name:` in the
+// team's chat messages and as ticket comment reactions. Message text keeps the
+// shortcode as typed; clients render it from the listing, or straight from
+// `/teams/{team_id}/emoji/{name}`.
+
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use futures_util::StreamExt;
+use image::ImageFormat;
+use log::{error, info};
+use mongodb::bson::{doc, oid::ObjectId, Bson};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::emoji::is_builtin_shortcode;
+use crate::response::CodedError;
+
+/// Largest emoji image accepted, in bytes.
+const MAX_EMOJI_BYTES: usize = 256 * 1024;
+const MAX_EMOJI_PER_TEAM: u64 = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomEmoji {
+    pub team_id: String,
+    /// Used as `:name:`
+    pub name: String,
+    /// GridFS id (hex) of the image
+    pub file_id: String,
+    pub content_type: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+fn emoji_coll(db: &MongoDB) -> mongodb::Collection<CustomEmoji> {
+    db.db.collection::<CustomEmoji>("custom_emoji")
+}
+
+fn valid_name(name: &str) -> bool {
+    (2..=32).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+/// Whether `:name:` is an emoji of the team.
+pub async fn team_has_emoji(db: &MongoDB, team_id: &str, name: &str) -> mongodb::error::Result<bool> {
+    Ok(emoji_coll(db).find_one(doc! { "team_id": team_id, "name": name }).await?.is_some())
+}
+
+/// GET /teams/{team_id}/emoji
+pub async fn list_emoji(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    let mut cursor = match emoji_coll(&data.mongodb).find(doc! { "team_id": &*team_id }).sort(doc! { "name": 1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching custom emoji: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching custom emoji");
+        }
+    };
+    let mut emoji = Vec::new();
+    while let Some(e) = cursor.next().await {
+        match e {
+            Ok(e) => emoji.push(e),
+            Err(e) => error!("Error reading custom emoji: {}", e),
+        }
+    }
+    HttpResponse::Ok().json(emoji)
+}
+
+/// POST /teams/{team_id}/emoji
+/// Multipart with a "name" part and an "image" part (PNG, GIF, JPEG or WebP).
+pub async fn upload_emoji(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    mut payload: Multipart,
+) -> impl Responder {
+    let team_id = team_id.into_inner();
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+
+    let mut name = String::new();
+    let mut image: Vec<u8> = Vec::new();
+    while let Some(item) = payload.next().await {
+        let mut field = match item {
+            Ok(f) => f,
+            Err(e) => return HttpResponse::BadRequest().body(format!("Malformed multipart: {}", e)),
+        };
+        let field_name = field.name().unwrap_or("").to_string();
+        let mut bytes: Vec<u8> = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = match chunk {
+                Ok(c) => c,
+                Err(e) => return HttpResponse::BadRequest().body(format!("Upload interrupted: {}", e)),
+            };
+            if bytes.len() + chunk.len() > MAX_EMOJI_BYTES {
+                return HttpResponse::PayloadTooLarge().body("Emoji image exceeds the size limit");
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        match field_name.as_str() {
+            "name" => name = String::from_utf8_lossy(&bytes).trim().trim_matches(':').to_ascii_lowercase(),
+            "image" => image = bytes,
+            _ => {}
+        }
+    }
+
+    if !valid_name(&name) {
+        return HttpResponse::BadRequest().body("Name must be 2-32 characters of a-z, 0-9, '_' or '-'");
+    }
+    if is_builtin_shortcode(&name) {
+        return HttpResponse::Conflict().body(format!(":{}: is a built-in emoji", name));
+    }
+    // Trust the bytes, not the declared content type.
+    let content_type = match image::guess_format(&image) {
+        Ok(ImageFormat::Png) => "image/png",
+        Ok(ImageFormat::Gif) => "image/gif",
+        Ok(ImageFormat::Jpeg) => "image/jpeg",
+        Ok(ImageFormat::WebP) => "image/webp",
+        _ => return HttpResponse::BadRequest().body("Image must be PNG, GIF, JPEG or WebP"),
+    };
+    match team_has_emoji(&data.mongodb, &team_id, &name).await {
+        Ok(false) => {}
+        Ok(true) => return HttpResponse::Conflict().body(format!(":{}: already exists", name)),
+        Err(e) => {
+            error!("Error checking custom emoji: {}", e);
+            return HttpResponse::InternalServerError().body("Error storing emoji");
+        }
+    }
+    match emoji_coll(&data.mongodb).count_documents(doc! { "team_id": &team_id }).await {
+        Ok(n) if n >= MAX_EMOJI_PER_TEAM => {
+            return HttpResponse::BadRequest().body(format!("Teams can have at most {} custom emoji", MAX_EMOJI_PER_TEAM))
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("Error counting custom emoji: {}", e);
+            return HttpResponse::InternalServerError().body("Error storing emoji");
+        }
+    }
+
+    let bucket = data.mongodb.db.gridfs_bucket(None);
+    let mut upload = match bucket
+        .open_upload_stream(format!("emoji_{}_{}", team_id, name))
+        .metadata(doc! { "team_id": &team_id, "content_type": content_type })
+        .await
+    {
+        Ok(u) => u,
+        Err(e) => {
+            error!("Error opening emoji upload: {}", e);
+            return HttpResponse::InternalServerError().body("Error storing emoji");
+        }
+    };
+    if let Err(e) = upload.write_all(&image).await {
+        error!("Error writing emoji: {}", e);
+        return HttpResponse::InternalServerError().body("Error storing emoji");
+    }
+    if let Err(e) = upload.close().await {
+        error!("Error finalizing emoji: {}", e);
+        return HttpResponse::InternalServerError().body("Error storing emoji");
+    }
+    let file_id = match upload.id().as_object_id() {
+        Some(id) => id,
+        None => return HttpResponse::InternalServerError().body("Error storing emoji"),
+    };
+
+    let emoji = CustomEmoji {
+        team_id,
+        name,
+        file_id: file_id.to_hex(),
+        content_type: content_type.to_string(),
+        created_by: auth.user_id().to_string(),
+        created_at: Utc::now(),
+    };
+    // The unique index settles a race between two uploads of the same name.
+    if let Err(e) = emoji_coll(&data.mongodb).insert_one(&emoji).await {
+        let _ = bucket.delete(Bson::ObjectId(file_id)).await;
+        if matches!(&*e.kind, mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(w)) if w.code == 11000) {
+            return HttpResponse::Conflict().body(format!(":{}: already exists", emoji.name));
+        }
+        error!("Error saving custom emoji: {}", e);
+        return HttpResponse::InternalServerError().body("Error storing emoji");
+    }
+    info!("User {} added :{}: to team {}", emoji.created_by, emoji.name, emoji.team_id);
+    HttpResponse::Created().json(emoji)
+}
+
+/// GET /teams/{team_id}/emoji/{name}
+pub async fn get_emoji_image(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (team_id, name) = path.into_inner();
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    let emoji = match emoji_coll(&data.mongodb).find_one(doc! { "team_id": &team_id, "name": &name }).await {
+        Ok(Some(e)) => e,
+        Ok(None) => return HttpResponse::NotFound().body("Emoji not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error fetching emoji: {}", e)),
+    };
+    let Ok(oid) = ObjectId::parse_str(&emoji.file_id) else {
+        return HttpResponse::NotFound().body("Emoji not found");
+    };
+    let bucket = data.mongodb.db.gridfs_bucket(None);
+    let mut stream = match bucket.open_download_stream(Bson::ObjectId(oid)).await {
+        Ok(s) => s,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error fetching emoji: {}", e)),
+    };
+    let mut bytes = Vec::new();
+    if let Err(e) = stream.read_to_end(&mut bytes).await {
+        return HttpResponse::InternalServerError().body(format!("Error fetching emoji: {}", e));
+    }
+    HttpResponse::Ok()
+        .content_type(emoji.content_type)
+        .insert_header(("Cache-Control", "private, max-age=86400"))
+        .body(bytes)
+}
+
+/// DELETE /teams/{team_id}/emoji/{name}
+/// Team admins only. Existing reactions and messages keep the shortcode text.
+pub async fn delete_emoji(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (team_id, name) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can delete custom emoji");
+    }
+    match emoji_coll(&data.mongodb).find_one_and_delete(doc! { "team_id": &team_id, "name": &name }).await {
+        Ok(Some(emoji)) => {
+            if let Ok(oid) = ObjectId::parse_str(&emoji.file_id) {
+                let _ = data.mongodb.db.gridfs_bucket(None).delete(Bson::ObjectId(oid)).await;
+            }
+            info!("User {} deleted :{}: from team {}", auth.user_id(), name, team_id);
+            HttpResponse::NoContent().finish()
+        }
+        Ok(None) => HttpResponse::NotFound().body("Emoji not found"),
+        Err(e) => {
+            error!("Error deleting custom emoji: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting emoji")
+        }
+    }
+}
@@ -1,7 +1,7 @@
 // src/dashboard_data.rs
 
 use actix_web::{error::ErrorInternalServerError, web, Error, HttpResponse};
-use chrono::{Datelike, Utc};
+use chrono::{Datelike, Duration};
 use futures::stream::TryStreamExt;
 use mongodb::{
     bson::{doc, from_bson, to_bson, Bson, DateTime as BsonDateTime, Document},
@@ -10,6 +10,12 @@ use mongodb::{
 use serde::{Deserialize, Serialize};
 
 use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::response::CodedError;
+use crate::team_time::{local_date, local_today, team_timezone, timestamp};
+use crate::tenancy::{ProjectsScope, Repo};
+use crate::ticket::Ticket;
 
 /// Only budget data comes from the frontend
 #[derive(Debug, Deserialize)]
@@ -38,9 +44,12 @@ fn coll(state: &AppState) -> Collection<Document> {
 async fn compute_full_dashboard(
     team_id: &str,
     budget_input: BudgetInput,
-    db: &mongodb::Database,
+    mongodb: &MongoDB,
+    tz: chrono_tz::Tz,
 ) -> Result<Document, Error> {
+    let db = &mongodb.db;
     let mut doc = Document::new();
+    let today = local_today(tz);
 
     // 1) Always include teamId & budgetInput
     doc.insert("teamId", team_id);
@@ -50,25 +59,14 @@ async fn compute_full_dashboard(
     );
 
     // 2) Fetch all project IDs for this team
-    let project_docs: Vec<Document> = db
-        .collection::<Document>("projects")
-        .find(doc! { "team_id": team_id })
-        .await
-        .map_err(ErrorInternalServerError)?
-        .try_collect()
-        .await
-        .map_err(ErrorInternalServerError)?;
-    let project_ids: Vec<String> = project_docs
-        .iter()
-        .filter_map(|p| p.get_str("project_id").ok().map(String::from))
-        .collect();
+    let projects = ProjectsScope::of_team(mongodb, team_id).await.map_err(ErrorInternalServerError)?;
 
     // 3) Fetch all tickets for those projects
-    let tickets: Vec<Document> = if project_ids.is_empty() {
+    let tickets: Vec<Document> = if projects.project_ids().is_empty() {
         Vec::new()
     } else {
-        db.collection::<Document>("tickets")
-            .find(doc! { "project_id": { "$in": project_ids.clone() } })
+        Repo::<Ticket>::across(mongodb, &projects)
+            .find_docs(doc! {})
             .await
             .map_err(ErrorInternalServerError)?
             .try_collect()
@@ -79,27 +77,33 @@ async fn compute_full_dashboard(
     // 4) ticketSummary
     let mut open = 0;
     let mut closed = 0;
+    let mut overdue = 0;
     let mut total_days = 0.0;
+    let mut resolved = 0;
     for t in &tickets {
         let status = t.get_str("status").unwrap_or("").to_lowercase();
         let is_closed = matches!(status.as_str(), "done" | "closed" | "resolved");
         if is_closed {
             closed += 1;
-            if let (Ok(created), Ok(due)) =
-                (t.get_datetime("created_at"), t.get_datetime("due_date"))
+            // Tickets closed before resolutions were recorded have no resolved_at.
+            if let (Some(created), Some(resolved_at)) =
+                (t.get("created_at").and_then(timestamp), t.get("resolved_at").and_then(timestamp))
             {
-                let secs = (due.to_chrono() - created.to_chrono()).num_seconds();
-                if secs > 0 {
-                    total_days += secs as f64 / 86_400.0;
-                }
+                let secs = (resolved_at - created).num_seconds().max(0);
+                total_days += secs as f64 / 86_400.0;
+                resolved += 1;
             }
         } else {
             open += 1;
+            // Overdue once the due day has passed in the team's time zone.
+            if t.get("due_date").and_then(|d| local_date(d, tz)).is_some_and(|due| due < today) {
+                overdue += 1;
+            }
         }
     }
     let total_tickets = tickets.len() as i32;
-    let avg_resolution = if closed > 0 {
-        (total_days / closed as f64 * 10.0).round() / 10.0
+    let avg_resolution = if resolved > 0 {
+        (total_days / resolved as f64 * 10.0).round() / 10.0
     } else {
         0.0
     };
@@ -109,6 +113,7 @@ async fn compute_full_dashboard(
             "totalTickets": total_tickets,
             "openTickets": open,
             "closedTickets": closed,
+            "overdueTickets": overdue,
             "avgResolutionTime": avg_resolution
         },
     );
@@ -119,7 +124,7 @@ async fn compute_full_dashboard(
     doc.insert("taskMetrics", doc! { "onTrack": on_track, "delayed": delayed });
 
     // 6) Budget chart calculations
-    let current_month = Utc::now().month0() as usize;
+    let current_month = today.month0() as usize;
     let spent: f64 = budget_input
         .monthly_drains
         .iter()
@@ -224,7 +229,7 @@ async fn compute_full_dashboard(
     doc.insert("aiTaskList", Bson::Array(vec![]));
 
     // 12) Project stats
-    let total_projects = project_docs.len() as i32;
+    let total_projects = projects.project_ids().len() as i32;
     doc.insert("projectStats", doc! { "activeProjects": total_projects, "completedProjects": 0 });
 
     // 13) Chat metrics, upcoming events, working hours stubs
@@ -232,20 +237,50 @@ async fn compute_full_dashboard(
     doc.insert("upcomingEvents", Bson::Array(vec![]));
     doc.insert("workingHours", doc! { "averageStart": "09:00", "averageEnd": "17:00" });
 
+    // 14) Members out of office at some point this week (Monday to Sunday)
+    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    let sunday = monday + Duration::days(6);
+    let member_ids = db
+        .collection::<Document>("user_teams")
+        .distinct("user_id", doc! { "team_id": team_id })
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let absences: Vec<Document> = db
+        .collection::<Document>("out_of_office")
+        .find(doc! { "user_id": { "$in": member_ids } })
+        .await
+        .map_err(ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let out_of_office: Vec<Bson> = absences
+        .iter()
+        .filter(|a| {
+            let start = a.get("start").and_then(|d| local_date(d, tz));
+            let end = a.get("end").and_then(|d| local_date(d, tz));
+            matches!((start, end), (Some(start), Some(end)) if start <= sunday && end >= monday)
+        })
+        .map(|a| {
+            Bson::Document(doc! {
+                "userId": a.get_str("user_id").unwrap_or(""),
+                "start": a.get("start").cloned().unwrap_or(Bson::Null),
+                "end": a.get("end").cloned().unwrap_or(Bson::Null),
+                "delegate": a.get("delegate").cloned().unwrap_or(Bson::Null),
+            })
+        })
+        .collect();
+    doc.insert("outOfOffice", Bson::Array(out_of_office));
+
     Ok(doc)
 }
 
-/// GET /team-data/{team_id}
-pub async fn get_dashboard_data(
-    path: web::Path<String>,
-    state: web::Data<AppState>,
-) -> Result<HttpResponse, Error> {
-    let team_id = path.into_inner();
-    let dashboards = coll(&state);
+/// Recompute the dashboard for a team from its stored budget input.
+pub(crate) async fn load_dashboard(team_id: &str, state: &AppState) -> Result<Document, Error> {
+    let dashboards = coll(state);
 
     // Pull stored budgetInput (or default zeros)
     let input = dashboards
-        .find_one(doc! { "teamId": &team_id })
+        .find_one(doc! { "teamId": team_id })
         .await
         .map_err(ErrorInternalServerError)?
         .and_then(|mut existing| {
@@ -259,19 +294,35 @@ pub async fn get_dashboard_data(
         });
 
     // Recompute everything
-    let full = compute_full_dashboard(&team_id, input, &state.mongodb.db)
-        .await
-        .map_err(ErrorInternalServerError)?;
+    let tz = team_timezone(&state.mongodb, team_id).await;
+    compute_full_dashboard(team_id, input, &state.mongodb, tz).await
+}
+
+/// GET /team-data/{team_id}
+pub async fn get_dashboard_data(
+    auth: AuthContext,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let team_id = path.into_inner();
+    if !auth.is_team_member(&team_id).await {
+        return Ok(HttpResponse::Unauthorized().error("not_team_member"));
+    }
+    let full = load_dashboard(&team_id, &state).await?;
     Ok(HttpResponse::Ok().json(full))
 }
 
 /// PUT /team-data/{team_id}
 pub async fn upsert_dashboard_data(
+    auth: AuthContext,
     path: web::Path<String>,
     payload: web::Json<DashboardInput>,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
     let team_id = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return Ok(HttpResponse::Unauthorized().body("Only team admins can change the budget"));
+    }
     let input = payload.into_inner().budget_input;
 
     // Store the raw budgetInput
@@ -290,7 +341,8 @@ pub async fn upsert_dashboard_data(
     }
 
     // Return the freshly computed dashboard
-    let full = compute_full_dashboard(&team_id, input, &state.mongodb.db)
+    let tz = team_timezone(&state.mongodb, &team_id).await;
+    let full = compute_full_dashboard(&team_id, input, &state.mongodb, tz)
         .await
         .map_err(ErrorInternalServerError)?;
     Ok(HttpResponse::Ok().json(full))
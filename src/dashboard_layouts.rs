@@ -0,0 +1,309 @@
+// src/dashboard_layouts.rs
+//
+// Per-user dashboards. Each member arranges widgets from a fixed registry on a
+// 12-column grid and saves the layout per team in `dashboard_layouts`. The data
+// endpoint returns, for each placed widget, only the metrics that widget needs,
+// taken from the team dashboard computed in `dashboard_data`.
+
+use std::collections::HashSet;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Utc};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::calendar::CalendarEvent;
+use crate::dashboard_data::load_dashboard;
+use crate::response::{CodedError, ok};
+
+const GRID_COLUMNS: u32 = 12;
+const MAX_WIDGETS: usize = 24;
+const DEFAULT_CALENDAR_DAYS: i64 = 14;
+const MAX_CALENDAR_DAYS: i64 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WidgetKind {
+    TicketSummary,
+    Burndown,
+    Calendar,
+    Morale,
+    Budget,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WidgetSpec {
+    pub kind: WidgetKind,
+    pub title: &'static str,
+    /// Top-level keys of the team dashboard the widget reads
+    pub metrics: &'static [&'static str],
+    pub default_w: u32,
+    pub default_h: u32,
+}
+
+/// Every widget a layout can contain.
+pub const WIDGETS: [WidgetSpec; 5] = [
+    WidgetSpec {
+        kind: WidgetKind::TicketSummary,
+        title: "Ticket summary",
+        metrics: &["ticketSummary", "priority"],
+        default_w: 6,
+        default_h: 4,
+    },
+    WidgetSpec {
+        kind: WidgetKind::Burndown,
+        title: "Burndown",
+        metrics: &["taskMetrics", "completion"],
+        default_w: 6,
+        default_h: 4,
+    },
+    WidgetSpec {
+        kind: WidgetKind::Calendar,
+        title: "Upcoming events",
+        metrics: &["upcomingEvents"],
+        default_w: 4,
+        default_h: 4,
+    },
+    WidgetSpec {
+        kind: WidgetKind::Morale,
+        title: "Team morale",
+        metrics: &["morale"],
+        default_w: 4,
+        default_h: 3,
+    },
+    WidgetSpec {
+        kind: WidgetKind::Budget,
+        title: "Budget",
+        metrics: &["budget", "budgetInput"],
+        default_w: 4,
+        default_h: 3,
+    },
+];
+
+fn spec(kind: WidgetKind) -> &'static WidgetSpec {
+    WIDGETS.iter().find(|w| w.kind == kind).expect("every widget kind is registered")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WidgetPlacement {
+    /// Client-chosen id, unique within the layout
+    pub id: String,
+    pub widget: WidgetKind,
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+    /// Widget-specific settings, e.g. `{"days": 30}` for the calendar
+    #[serde(default)]
+    pub config: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DashboardLayout {
+    pub user_id: String,
+    pub team_id: String,
+    pub widgets: Vec<WidgetPlacement>,
+    /// None for the default layout that has not been saved yet
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveLayoutRequest {
+    pub widgets: Vec<WidgetPlacement>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WidgetData {
+    pub id: String,
+    pub widget: WidgetKind,
+    pub data: Document,
+}
+
+/// Every widget, two per row, in registry order.
+fn default_layout(user_id: &str, team_id: &str) -> DashboardLayout {
+    let mut y = 0;
+    let widgets = WIDGETS
+        .iter()
+        .enumerate()
+        .map(|(i, w)| {
+            let x = if i % 2 == 0 { 0 } else { GRID_COLUMNS / 2 };
+            let placement = WidgetPlacement {
+                id: serde_json::to_value(w.kind).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_default(),
+                widget: w.kind,
+                x,
+                y,
+                w: w.default_w,
+                h: w.default_h,
+                config: Value::Null,
+            };
+            if i % 2 == 1 {
+                y += w.default_h.max(WIDGETS[i - 1].default_h);
+            }
+            placement
+        })
+        .collect();
+    DashboardLayout { user_id: user_id.to_string(), team_id: team_id.to_string(), widgets, updated_at: None }
+}
+
+fn validate_layout(widgets: &[WidgetPlacement]) -> Result<(), String> {
+    if widgets.len() > MAX_WIDGETS {
+        return Err(format!("A dashboard holds at most {} widgets", MAX_WIDGETS));
+    }
+    let mut ids = HashSet::new();
+    for w in widgets {
+        if w.id.trim().is_empty() || !ids.insert(w.id.as_str()) {
+            return Err(format!("Widget ids must be unique and non-empty: \"{}\"", w.id));
+        }
+        if w.w == 0 || w.h == 0 || w.x.saturating_add(w.w) > GRID_COLUMNS {
+            return Err(format!("Widget \"{}\" does not fit the {}-column grid", w.id, GRID_COLUMNS));
+        }
+        if !(w.config.is_null() || w.config.is_object()) {
+            return Err(format!("Config of widget \"{}\" must be an object", w.id));
+        }
+    }
+    Ok(())
+}
+
+async fn find_layout(data: &AppState, user_id: &str, team_id: &str) -> mongodb::error::Result<DashboardLayout> {
+    let layouts = data.mongodb.db.collection::<DashboardLayout>("dashboard_layouts");
+    let saved = layouts.find_one(doc! { "user_id": user_id, "team_id": team_id }).await?;
+    Ok(saved.unwrap_or_else(|| default_layout(user_id, team_id)))
+}
+
+/// Team events starting within the next `days`, soonest first.
+async fn upcoming_events(data: &AppState, team_id: &str, days: i64) -> mongodb::error::Result<Vec<CalendarEvent>> {
+    let now = Utc::now();
+    let until = now + Duration::days(days);
+    let events = data.mongodb.db.collection::<CalendarEvent>("calendar_events");
+    let mut cursor = events.find(doc! { "team_id": team_id }).await?;
+    let mut upcoming = Vec::new();
+    while let Some(event) = cursor.next().await {
+        let event = event?;
+        if event.end >= now && event.start <= until {
+            upcoming.push(event);
+        }
+    }
+    upcoming.sort_by_key(|e| e.start);
+    Ok(upcoming)
+}
+
+/// GET /dashboard/widgets
+pub async fn list_widgets() -> impl Responder {
+    ok(&WIDGETS[..])
+}
+
+/// GET /teams/{team_id}/dashboard/layout
+/// The caller's layout, or the default one when they have not saved any.
+pub async fn get_layout(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    match find_layout(&data, auth.user_id(), &team_id).await {
+        Ok(layout) => ok(layout),
+        Err(e) => {
+            error!("Error fetching dashboard layout: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching dashboard layout")
+        }
+    }
+}
+
+/// PUT /teams/{team_id}/dashboard/layout
+pub async fn save_layout(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<SaveLayoutRequest>,
+) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    if let Err(msg) = validate_layout(&payload.widgets) {
+        return HttpResponse::BadRequest().body(msg);
+    }
+
+    let layout = DashboardLayout {
+        user_id: auth.user_id().to_string(),
+        team_id: team_id.into_inner(),
+        widgets: payload.into_inner().widgets,
+        updated_at: Some(Utc::now()),
+    };
+    let layouts = data.mongodb.db.collection::<DashboardLayout>("dashboard_layouts");
+    let filter = doc! { "user_id": &layout.user_id, "team_id": &layout.team_id };
+    match layouts.replace_one(filter, &layout).upsert(true).await {
+        Ok(_) => ok(layout),
+        Err(e) => {
+            error!("Error saving dashboard layout: {}", e);
+            HttpResponse::InternalServerError().body("Error saving dashboard layout")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/dashboard/data
+/// Data for each widget of the caller's layout, in layout order.
+pub async fn get_layout_data(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    let layout = match find_layout(&data, auth.user_id(), &team_id).await {
+        Ok(layout) => layout,
+        Err(e) => {
+            error!("Error fetching dashboard layout: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching dashboard layout");
+        }
+    };
+    if layout.widgets.is_empty() {
+        return ok(Vec::<WidgetData>::new());
+    }
+
+    let dashboard = match load_dashboard(&team_id, &data).await {
+        Ok(d) => d,
+        Err(e) => {
+            error!("Error computing dashboard: {}", e);
+            return HttpResponse::InternalServerError().body("Error computing dashboard");
+        }
+    };
+
+    let mut widgets = Vec::with_capacity(layout.widgets.len());
+    for placement in layout.widgets {
+        let mut metrics = Document::new();
+        for key in spec(placement.widget).metrics {
+            if let Some(value) = dashboard.get(*key) {
+                metrics.insert(*key, value.clone());
+            }
+        }
+        // The team dashboard has no calendar data of its own.
+        if placement.widget == WidgetKind::Calendar {
+            let days = placement
+                .config
+                .get("days")
+                .and_then(Value::as_i64)
+                .unwrap_or(DEFAULT_CALENDAR_DAYS)
+                .clamp(1, MAX_CALENDAR_DAYS);
+            match upcoming_events(&data, &team_id, days).await {
+                Ok(events) => {
+                    let events = mongodb::bson::to_bson(&events).unwrap_or_default();
+                    metrics.insert("upcomingEvents", events);
+                }
+                Err(e) => {
+                    error!("Error fetching calendar events: {}", e);
+                    return HttpResponse::InternalServerError().body("Error fetching calendar events");
+                }
+            }
+        }
+        widgets.push(WidgetData { id: placement.id, widget: placement.widget, data: metrics });
+    }
+    ok(widgets)
+}
@@ -0,0 +1,388 @@
+// src/dashboard_report.rs
+//
+// Printable weekly report built from the computed dashboard: HTML or a small
+// hand-written PDF (Helvetica, text only), plus the optional weekly email job.
+
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use futures::stream::TryStreamExt;
+use log::{error, info};
+use mongodb::bson::{doc, oid::ObjectId, Bson, DateTime as BsonDateTime, Document};
+use serde::Deserialize;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::dashboard_data::load_dashboard;
+use crate::mailer::send_email;
+use crate::response::CodedError;
+use crate::team_time::{local_today, team_timezone};
+
+/// How often the weekly job checks for teams that are due a report.
+const REPORT_CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+const REPORT_PERIOD_DAYS: i64 = 7;
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    /// "pdf" or "html" (default)
+    pub format: Option<String>,
+}
+
+/// One titled block of the report.
+struct Section {
+    title: &'static str,
+    rows: Vec<(String, String)>,
+}
+
+fn num(doc: &Document, section: &str, key: &str) -> f64 {
+    match doc.get_document(section).ok().and_then(|d| d.get(key)) {
+        Some(Bson::Int32(v)) => *v as f64,
+        Some(Bson::Int64(v)) => *v as f64,
+        Some(Bson::Double(v)) => *v,
+        _ => 0.0,
+    }
+}
+
+fn fmt_num(v: f64) -> String {
+    if v.fract() == 0.0 {
+        format!("{}", v as i64)
+    } else {
+        format!("{:.2}", v)
+    }
+}
+
+fn build_sections(dashboard: &Document) -> Vec<Section> {
+    let row = |label: &str, v: f64| (label.to_string(), fmt_num(v));
+    vec![
+        Section {
+            title: "Key indicators",
+            rows: vec![
+                row("Tasks completed", num(dashboard, "kpiData", "tasksCompleted")),
+                row("Tasks total", num(dashboard, "kpiData", "tasksTotal")),
+                row("Budget spent", num(dashboard, "kpiData", "budgetSpent")),
+                (
+                    "Budget used".to_string(),
+                    format!("{}%", fmt_num(num(dashboard, "kpiData", "budgetPercent"))),
+                ),
+                row("Active projects", num(dashboard, "projectStats", "activeProjects")),
+            ],
+        },
+        Section {
+            title: "Tickets",
+            rows: vec![
+                row("Total", num(dashboard, "ticketSummary", "totalTickets")),
+                row("Open", num(dashboard, "ticketSummary", "openTickets")),
+                row("Closed", num(dashboard, "ticketSummary", "closedTickets")),
+                (
+                    "Avg. resolution (days)".to_string(),
+                    fmt_num(num(dashboard, "ticketSummary", "avgResolutionTime")),
+                ),
+            ],
+        },
+        Section {
+            title: "Budget",
+            rows: vec![
+                row(
+                    "Annual budget",
+                    num(dashboard, "budgetInput", "totalAnnualBudget"),
+                ),
+                row("Spent to date", num(dashboard, "kpiData", "budgetSpent")),
+            ],
+        },
+        Section {
+            title: "Open tickets by priority",
+            rows: vec![
+                row("High", num(dashboard, "priority", "high")),
+                row("Medium", num(dashboard, "priority", "medium")),
+                row("Low", num(dashboard, "priority", "low")),
+            ],
+        },
+    ]
+}
+
+fn report_title(team_name: &str, tz: chrono_tz::Tz) -> String {
+    format!("Weekly report - {} ({})", team_name, local_today(tz).format("%Y-%m-%d"))
+}
+
+pub(crate) fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn render_html(title: &str, sections: &[Section]) -> String {
+    let mut body = String::new();
+    for section in sections {
+        body.push_str(&format!("<h2>{}</h2>\n<table>\n", html_escape(section.title)));
+        for (label, value) in &section.rows {
+            body.push_str(&format!(
+                "<tr><th>{}</th><td>{}</td></tr>\n",
+                html_escape(label),
+                html_escape(value)
+            ));
+        }
+        body.push_str("</table>\n");
+    }
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{t}</title>\
+         <style>body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse;margin-bottom:1em}}\
+         th,td{{border:1px solid #ccc;padding:4px 12px;text-align:left}}\
+         @media print{{body{{margin:0}}}}</style></head>\n<body>\n<h1>{t}</h1>\n{body}</body></html>\n",
+        t = html_escape(title),
+        body = body
+    )
+}
+
+fn render_text(title: &str, sections: &[Section]) -> String {
+    let mut out = format!("{}\n\n", title);
+    for section in sections {
+        out.push_str(&format!("{}\n", section.title));
+        for (label, value) in &section.rows {
+            out.push_str(&format!("  {}: {}\n", label, value));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Escape a line for a PDF literal string; anything outside ASCII becomes '?'.
+fn pdf_escape(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '(' | ')' | '\\' => format!("\\{}", c),
+            c if c.is_ascii() && !c.is_ascii_control() => c.to_string(),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
+
+/// Left margin of PDF pages, in points.
+pub(crate) const PDF_MARGIN: i32 = 56;
+
+fn render_pdf(title: &str, sections: &[Section]) -> Vec<u8> {
+    // (font size, x, text) per line
+    let mut lines: Vec<(i32, i32, String)> = vec![(18, PDF_MARGIN, title.to_string()), (11, PDF_MARGIN, String::new())];
+    for section in sections {
+        lines.push((13, PDF_MARGIN, section.title.to_string()));
+        for (label, value) in &section.rows {
+            lines.push((11, PDF_MARGIN + 16, format!("{}: {}", label, value)));
+        }
+        lines.push((11, PDF_MARGIN, String::new()));
+    }
+    write_pdf(&lines)
+}
+
+/// Lay `(font size, x, text)` lines out on A4 pages and serialise a minimal PDF 1.4 file.
+pub(crate) fn write_pdf(lines: &[(i32, i32, String)]) -> Vec<u8> {
+    const PAGE_HEIGHT: i32 = 842;
+    const MARGIN: i32 = PDF_MARGIN;
+    const LEADING: i32 = 16;
+
+    let per_page = ((PAGE_HEIGHT - 2 * MARGIN) / LEADING) as usize;
+    let pages: Vec<&[(i32, i32, String)]> = lines.chunks(per_page).collect();
+
+    // Object numbers: 1 catalog, 2 page tree, 3 font, then (page, content) pairs.
+    let mut objects: Vec<String> = Vec::new();
+    objects.push("<< /Type /Catalog /Pages 2 0 R >>".to_string());
+    let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", 4 + i * 2)).collect();
+    objects.push(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()));
+    objects.push("<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string());
+    for (i, page) in pages.iter().enumerate() {
+        let mut stream = String::new();
+        let mut y = PAGE_HEIGHT - MARGIN;
+        for (size, x, text) in page.iter() {
+            if !text.is_empty() {
+                stream.push_str(&format!("BT /F1 {} Tf {} {} Td ({}) Tj ET\n", size, x, y, pdf_escape(text)));
+            }
+            y -= LEADING;
+        }
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 {}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            PAGE_HEIGHT,
+            5 + i * 2
+        ));
+        objects.push(format!("<< /Length {} >>\nstream\n{}endstream", stream.len(), stream));
+    }
+
+    let mut out = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, obj) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, obj));
+    }
+    let xref_at = out.len();
+    out.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+    for off in offsets {
+        out.push_str(&format!("{:010} 00000 n \n", off));
+    }
+    out.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref_at
+    ));
+    out.into_bytes()
+}
+
+async fn team_name(state: &AppState, team_id: &str) -> String {
+    state
+        .mongodb
+        .db
+        .collection::<Document>("teams")
+        .find_one(doc! { "team_id": team_id })
+        .await
+        .ok()
+        .flatten()
+        .and_then(|t| t.get_str("name").ok().map(String::from))
+        .unwrap_or_else(|| team_id.to_string())
+}
+
+/// GET /team-data/{team_id}/report?format=pdf|html
+pub async fn get_dashboard_report(
+    auth: AuthContext,
+    path: web::Path<String>,
+    query: web::Query<ReportQuery>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let team_id = path.into_inner();
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+
+    let dashboard = match load_dashboard(&team_id, &state).await {
+        Ok(d) => d,
+        Err(e) => {
+            error!("Error computing dashboard for report: {}", e);
+            return HttpResponse::InternalServerError().body("Error computing dashboard");
+        }
+    };
+    let tz = team_timezone(&state.mongodb, &team_id).await;
+    let title = report_title(&team_name(&state, &team_id).await, tz);
+    let sections = build_sections(&dashboard);
+
+    match query.format.as_deref().unwrap_or("html") {
+        "pdf" => HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header((
+                "Content-Disposition",
+                format!("inline; filename=\"report-{}.pdf\"", Utc::now().format("%Y-%m-%d")),
+            ))
+            .body(render_pdf(&title, &sections)),
+        "html" => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(render_html(&title, &sections)),
+        _ => HttpResponse::BadRequest().body("format must be 'pdf' or 'html'"),
+    }
+}
+
+/// Email addresses of every admin of the team.
+async fn team_admin_emails(state: &AppState, team_id: &str) -> Vec<String> {
+    let db = &state.mongodb.db;
+    let admin_ids: Vec<ObjectId> = match db
+        .collection::<Document>("user_teams")
+        .find(doc! { "team_id": team_id, "role": "admin" })
+        .await
+    {
+        Ok(cursor) => cursor
+            .try_collect::<Vec<Document>>()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|m| m.get_str("user_id").ok().and_then(|id| ObjectId::parse_str(id).ok()))
+            .collect(),
+        Err(e) => {
+            error!("Error fetching admins of team {}: {}", team_id, e);
+            return Vec::new();
+        }
+    };
+    if admin_ids.is_empty() {
+        return Vec::new();
+    }
+    match db.collection::<Document>("users").find(doc! { "_id": { "$in": admin_ids } }).await {
+        Ok(cursor) => cursor
+            .try_collect::<Vec<Document>>()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|u| u.get_str("email").ok().map(String::from))
+            .collect(),
+        Err(e) => {
+            error!("Error fetching admin emails of team {}: {}", team_id, e);
+            Vec::new()
+        }
+    }
+}
+
+/// Send the report to the admins of every team that has not had one in the last week.
+async fn send_due_reports(state: &AppState) {
+    let cutoff = BsonDateTime::from_chrono(Utc::now() - chrono::Duration::days(REPORT_PERIOD_DAYS));
+    let teams: Vec<Document> = match state.mongodb.db.collection::<Document>("teams").find(doc! {}).await {
+        Ok(cursor) => cursor.try_collect().await.unwrap_or_default(),
+        Err(e) => {
+            error!("Error listing teams for weekly reports: {}", e);
+            return;
+        }
+    };
+    let dashboards = state.mongodb.db.collection::<Document>("dashboard_data");
+
+    for team in teams {
+        let team_id = match team.get_str("team_id") {
+            Ok(id) => id.to_string(),
+            Err(_) => continue,
+        };
+        let last_sent = dashboards
+            .find_one(doc! { "teamId": &team_id })
+            .await
+            .ok()
+            .flatten()
+            .and_then(|d| d.get_datetime("lastReportSentAt").ok().copied());
+        if last_sent.is_some_and(|t| t > cutoff) {
+            continue;
+        }
+
+        let recipients = team_admin_emails(state, &team_id).await;
+        if recipients.is_empty() {
+            continue;
+        }
+        let dashboard = match load_dashboard(&team_id, state).await {
+            Ok(d) => d,
+            Err(e) => {
+                error!("Error computing dashboard for team {}: {}", team_id, e);
+                continue;
+            }
+        };
+        let tz = team_timezone(&state.mongodb, &team_id).await;
+        let title = report_title(team.get_str("name").unwrap_or(&team_id), tz);
+        let text = format!(
+            "{}The printable version is available at {}{}/team-data/{}/report?format=pdf\n",
+            render_text(&title, &build_sections(&dashboard)),
+            state.config.app_base_url,
+            crate::api::V1_PREFIX,
+            team_id
+        );
+        for to in &recipients {
+            let _ = send_email(state, to, &title, &text).await;
+        }
+
+        if let Err(e) = dashboards
+            .update_one(
+                doc! { "teamId": &team_id },
+                doc! { "$set": { "teamId": &team_id, "lastReportSentAt": BsonDateTime::now() } },
+            )
+            .upsert(true)
+            .await
+        {
+            error!("Error recording report time for team {}: {}", team_id, e);
+        }
+        info!("Weekly report for team {} sent to {} admin(s)", team_id, recipients.len());
+    }
+}
+
+/// Start the background job that emails weekly reports to team admins.
+pub fn spawn_weekly_reports(state: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(REPORT_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            send_due_reports(&state).await;
+        }
+    });
+}
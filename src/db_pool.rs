@@ -0,0 +1,131 @@
+// src/db_pool.rs
+//
+// MongoDB connection pool monitoring and a retry policy for transient errors. The
+// driver reports pool events to `cmap_handler`, which keeps the counters shown by
+// /metrics and /readyz. `RetryPolicy::run` re-runs an operation that failed with a
+// network, pool or server-selection error, sleeping a jittered exponential backoff
+// in between; it is meant for reads and idempotent writes only.
+
+use std::future::IntoFuture;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+use log::warn;
+use mongodb::error::ErrorKind;
+use mongodb::event::cmap::CmapEvent;
+use mongodb::event::EventHandler;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Server error codes the driver itself treats as retryable.
+const RETRYABLE_CODES: [i32; 12] = [6, 7, 89, 91, 189, 262, 9001, 10107, 11600, 11602, 13435, 13436];
+const MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+static OPEN: AtomicI64 = AtomicI64::new(0);
+static IN_USE: AtomicI64 = AtomicI64::new(0);
+static CHECKOUT_FAILURES: AtomicU64 = AtomicU64::new(0);
+static POOL_CLEARED: AtomicU64 = AtomicU64::new(0);
+static RETRIES: AtomicU64 = AtomicU64::new(0);
+static RETRIES_EXHAUSTED: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStats {
+    /// Open connections across all servers
+    pub connections: i64,
+    /// Connections currently checked out by operations
+    pub in_use: i64,
+    pub checkout_failures_total: u64,
+    /// Times a pool was cleared after a server error
+    pub pool_cleared_total: u64,
+    pub retries_total: u64,
+    /// Operations that still failed after the last retry
+    pub retries_exhausted_total: u64,
+}
+
+pub fn pool_stats() -> PoolStats {
+    PoolStats {
+        connections: OPEN.load(Ordering::Relaxed),
+        in_use: IN_USE.load(Ordering::Relaxed),
+        checkout_failures_total: CHECKOUT_FAILURES.load(Ordering::Relaxed),
+        pool_cleared_total: POOL_CLEARED.load(Ordering::Relaxed),
+        retries_total: RETRIES.load(Ordering::Relaxed),
+        retries_exhausted_total: RETRIES_EXHAUSTED.load(Ordering::Relaxed),
+    }
+}
+
+/// Handler for `ClientOptions::cmap_event_handler`.
+pub fn cmap_handler() -> EventHandler<CmapEvent> {
+    EventHandler::callback(|event| match event {
+        CmapEvent::ConnectionCreated(_) => {
+            OPEN.fetch_add(1, Ordering::Relaxed);
+        }
+        CmapEvent::ConnectionClosed(_) => {
+            OPEN.fetch_sub(1, Ordering::Relaxed);
+        }
+        CmapEvent::ConnectionCheckedOut(_) => {
+            IN_USE.fetch_add(1, Ordering::Relaxed);
+        }
+        CmapEvent::ConnectionCheckedIn(_) => {
+            IN_USE.fetch_sub(1, Ordering::Relaxed);
+        }
+        CmapEvent::ConnectionCheckoutFailed(_) => {
+            CHECKOUT_FAILURES.fetch_add(1, Ordering::Relaxed);
+        }
+        CmapEvent::PoolCleared(_) => {
+            POOL_CLEARED.fetch_add(1, Ordering::Relaxed);
+        }
+        _ => {}
+    })
+}
+
+/// Errors that may go away on their own: lost connections, a cleared pool, no
+/// reachable primary, or a server that is stepping down or restarting.
+pub fn is_transient(e: &mongodb::error::Error) -> bool {
+    if e.contains_label("RetryableWriteError") || e.contains_label("TransientTransactionError") {
+        return true;
+    }
+    match &*e.kind {
+        ErrorKind::Io(_) | ErrorKind::ConnectionPoolCleared { .. } | ErrorKind::ServerSelection { .. } => true,
+        ErrorKind::Command(c) => RETRYABLE_CODES.contains(&c.code),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total tries, including the first
+    pub attempts: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Delay before retry `n` (1-based): a random point in `[0, base * 2^(n-1)]`.
+    fn backoff(&self, n: u32) -> Duration {
+        let cap = self.base_delay.saturating_mul(1 << (n - 1).min(16)).min(MAX_BACKOFF);
+        let jitter = (Uuid::new_v4().as_u128() % 1_000) as u32;
+        cap.mul_f64(jitter as f64 / 1_000.0)
+    }
+
+    /// Runs the operation built by `op`, retrying transient failures.
+    pub async fn run<T, A>(&self, mut op: impl FnMut() -> A) -> mongodb::error::Result<T>
+    where
+        A: IntoFuture<Output = mongodb::error::Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Err(e) if is_transient(&e) => {
+                    if attempt >= self.attempts {
+                        RETRIES_EXHAUSTED.fetch_add(1, Ordering::Relaxed);
+                        return Err(e);
+                    }
+                    RETRIES.fetch_add(1, Ordering::Relaxed);
+                    warn!("Transient MongoDB error, retry {} of {}: {}", attempt, self.attempts - 1, e);
+                    tokio::time::sleep(self.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
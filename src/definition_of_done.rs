@@ -0,0 +1,331 @@
+// src/definition_of_done.rs
+//
+// Per-board definition of done: conditions a ticket must meet before it can move to
+// a closed status. Tickets have no separate checklist, so "checklist complete" means
+// every markdown task item (`- [ ]`) in the description is ticked. "Pull request
+// merged" needs at least one GitHub pull request URL in the description, comments or
+// attachments, and all linked pull requests merged; their state is read from the
+// GitHub API, with GITHUB_TOKEN for private repositories.
+//
+// update_ticket answers a closing update that misses conditions with 422 and the
+// list of unmet conditions. The project owner can override with
+// `override_definition_of_done`; each override is kept in
+// `definition_of_done_overrides`. Automation rules have nobody to override, so a
+// rule that would close a ticket missing conditions does not run.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{doc, to_bson};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::{can_edit_board, find_board, Board};
+use crate::response::{CodedError, ok};
+use crate::tenancy::{ProjectScope, Repo};
+use crate::ticket::Ticket;
+
+const GITHUB_TIMEOUT: Duration = Duration::from_secs(5);
+/// Linked pull requests checked per ticket; with more the condition is not met.
+const MAX_PULL_REQUESTS: usize = 10;
+const OVERRIDES_PAGE: i64 = 200;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DefinitionOfDone {
+    /// Every task item in the description is ticked
+    #[serde(default)]
+    pub checklist_complete: bool,
+    /// A pull request is linked and every linked one is merged
+    #[serde(default)]
+    pub pull_request_merged: bool,
+    /// `estimate_hours` is set
+    #[serde(default)]
+    pub estimate_set: bool,
+}
+
+impl DefinitionOfDone {
+    fn is_empty(&self) -> bool {
+        !(self.checklist_complete || self.pull_request_merged || self.estimate_set)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnmetCondition {
+    /// `checklist_complete`, `pull_request_merged` or `estimate_set`
+    pub condition: &'static str,
+    pub message: String,
+}
+
+/// Body of the 422 for a closing update that misses conditions.
+#[derive(Debug, Serialize)]
+pub struct DoneRejection {
+    pub message: String,
+    pub ticket_id: String,
+    pub board_id: String,
+    pub unmet: Vec<UnmetCondition>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DoneOverride {
+    pub override_id: String,
+    pub team_id: String,
+    pub project_id: String,
+    pub board_id: String,
+    pub ticket_id: String,
+    pub status: String,
+    /// Conditions that were not met
+    pub unmet: Vec<String>,
+    pub overridden_by: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequestState {
+    merged: bool,
+}
+
+fn task_items(description: &str) -> (usize, usize) {
+    static TASK: OnceLock<Regex> = OnceLock::new();
+    let re = TASK.get_or_init(|| Regex::new(r"(?m)^\s*[-*+]\s+\[([ xX])\]").expect("valid task regex"));
+    re.captures_iter(description).fold((0, 0), |(total, open), c| (total + 1, open + usize::from(&c[1] == " ")))
+}
+
+/// `(owner, repo, number)` of every GitHub pull request linked from the ticket.
+fn pull_requests(ticket: &Ticket) -> Vec<(String, String, String)> {
+    static PULL: OnceLock<Regex> = OnceLock::new();
+    let re = PULL.get_or_init(|| {
+        Regex::new(r"https://github\.com/([A-Za-z0-9_.-]+)/([A-Za-z0-9_.-]+)/pull/(\d+)").expect("valid pull request regex")
+    });
+    let texts = ticket
+        .description
+        .iter()
+        .map(String::as_str)
+        .chain(ticket.comments.iter().flatten().map(|c| c.content.as_str()))
+        .chain(ticket.attachments.iter().flatten().map(String::as_str));
+    let mut found = Vec::new();
+    for text in texts {
+        for c in re.captures_iter(text) {
+            let pr = (c[1].to_string(), c[2].to_string(), c[3].to_string());
+            if !found.contains(&pr) {
+                found.push(pr);
+            }
+        }
+    }
+    found
+}
+
+async fn is_merged(data: &AppState, owner: &str, repo: &str, number: &str) -> Result<bool, String> {
+    let url = format!("https://api.github.com/repos/{}/{}/pulls/{}", owner, repo, number);
+    let mut req = data.http_client.get(url)
+        .timeout(GITHUB_TIMEOUT)
+        .header(reqwest::header::USER_AGENT, "taskline")
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json");
+    if let Some(token) = &data.config.github_token {
+        req = req.bearer_auth(token);
+    }
+    let state: PullRequestState = req
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(state.merged)
+}
+
+/// Conditions of `dod` that `ticket` does not meet.
+pub(crate) async fn unmet_conditions(data: &AppState, dod: &DefinitionOfDone, ticket: &Ticket) -> Vec<UnmetCondition> {
+    let mut unmet = Vec::new();
+    if dod.checklist_complete {
+        let (total, open) = task_items(ticket.description.as_deref().unwrap_or_default());
+        if open > 0 {
+            unmet.push(UnmetCondition {
+                condition: "checklist_complete",
+                message: format!("{} of {} checklist items are not complete", open, total),
+            });
+        }
+    }
+    if dod.pull_request_merged {
+        let prs = pull_requests(ticket);
+        if prs.is_empty() {
+            unmet.push(UnmetCondition { condition: "pull_request_merged", message: "No pull request is linked".to_string() });
+        }
+        if prs.len() > MAX_PULL_REQUESTS {
+            unmet.push(UnmetCondition {
+                condition: "pull_request_merged",
+                message: format!("{} pull requests are linked; at most {} can be checked", prs.len(), MAX_PULL_REQUESTS),
+            });
+        }
+        let prs = &prs[..prs.len().min(MAX_PULL_REQUESTS)];
+        let states = join_all(prs.iter().map(|(owner, repo, number)| is_merged(data, owner, repo, number))).await;
+        for ((owner, repo, number), state) in prs.iter().zip(states) {
+            let message = match state {
+                Ok(true) => continue,
+                Ok(false) => format!("Pull request {}/{}#{} is not merged", owner, repo, number),
+                Err(e) => format!("Could not check pull request {}/{}#{}: {}", owner, repo, number, e),
+            };
+            unmet.push(UnmetCondition { condition: "pull_request_merged", message });
+        }
+    }
+    if dod.estimate_set && ticket.estimate_hours.is_none() {
+        unmet.push(UnmetCondition { condition: "estimate_set", message: "No estimate is set".to_string() });
+    }
+    unmet
+}
+
+/// Conditions of the ticket's board definition of done that `ticket` misses.
+pub(crate) async fn board_unmet_conditions(data: &AppState, scope: &ProjectScope, ticket: &Ticket) -> Result<Vec<UnmetCondition>, HttpResponse> {
+    let board = find_board(data, scope, &ticket.board_id).await?;
+    match board.definition_of_done.filter(|d| !d.is_empty()) {
+        Some(dod) => Ok(unmet_conditions(data, &dod, ticket).await),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Checks `ticket`, as it would be after the update, against its board's definition
+/// of done. Returns the overridden conditions, empty when all are met, or the
+/// response to send instead of closing the ticket.
+pub(crate) async fn check_closing(
+    auth: &AuthContext,
+    data: &AppState,
+    scope: &ProjectScope,
+    ticket: &Ticket,
+    override_requested: bool,
+) -> Result<Vec<String>, HttpResponse> {
+    let unmet = board_unmet_conditions(data, scope, ticket).await?;
+    if unmet.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !override_requested {
+        return Err(HttpResponse::UnprocessableEntity().json(DoneRejection {
+            message: format!("The ticket does not meet the board's definition of done: {}", unmet.iter().map(|u| u.message.as_str()).collect::<Vec<_>>().join("; ")),
+            ticket_id: ticket.ticket_id.clone(),
+            board_id: ticket.board_id.clone(),
+            unmet,
+        }));
+    }
+    if !auth.is_project_owner(&ticket.project_id).await {
+        return Err(HttpResponse::Forbidden().body("Only the project owner can override the definition of done"));
+    }
+    Ok(unmet.into_iter().map(|u| u.message).collect())
+}
+
+/// Keeps the audit entry for a ticket closed despite unmet conditions.
+pub(crate) async fn record_override(data: &AppState, team_id: &str, ticket: &Ticket, unmet: Vec<String>, user_id: &str) {
+    let entry = DoneOverride {
+        override_id: Uuid::new_v4().to_string(),
+        team_id: team_id.to_string(),
+        project_id: ticket.project_id.clone(),
+        board_id: ticket.board_id.clone(),
+        ticket_id: ticket.ticket_id.clone(),
+        status: ticket.status.clone(),
+        unmet,
+        overridden_by: user_id.to_string(),
+        at: Utc::now(),
+    };
+    let coll = data.mongodb.db.collection::<DoneOverride>("definition_of_done_overrides");
+    if let Err(e) = coll.insert_one(&entry).await {
+        error!("Error recording definition of done override for {}: {}", ticket.ticket_id, e);
+    }
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/boards/{board_id}/definition-of-done
+pub async fn get_definition_of_done(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (team_id, project_id, board_id) = path.into_inner();
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let board = match find_board(&data, &scope, &board_id).await {
+        Ok(b) => b,
+        Err(resp) => return resp,
+    };
+    if !can_edit_board(&auth, &board).await {
+        return HttpResponse::Unauthorized().error("not_project_or_board_member");
+    }
+    ok(board.definition_of_done.unwrap_or_default())
+}
+
+/// PUT /teams/{team_id}/projects/{project_id}/boards/{board_id}/definition-of-done
+/// Project owner only; all conditions false turns enforcement off.
+pub async fn update_definition_of_done(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    payload: web::Json<DefinitionOfDone>,
+) -> impl Responder {
+    let (team_id, project_id, board_id) = path.into_inner();
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    if !auth.is_project_owner(&project_id).await {
+        return HttpResponse::Forbidden().body("Only the project owner can change the definition of done");
+    }
+    let dod = payload.into_inner();
+    let value = match to_bson(&Some(&dod).filter(|d| !d.is_empty())) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Error serializing definition of done: {}", e);
+            return HttpResponse::InternalServerError().body("Error updating definition of done");
+        }
+    };
+    let boards_coll = Repo::<Board>::new(&data.mongodb, &scope);
+    match boards_coll.update_one(doc! { "board_id": &board_id }, doc! { "$set": { "definition_of_done": value } }).await {
+        Ok(res) if res.matched_count == 1 => ok(dod),
+        Ok(_) => HttpResponse::NotFound().error("board_not_found"),
+        Err(e) => {
+            error!("Error updating definition of done: {}", e);
+            HttpResponse::InternalServerError().body("Error updating definition of done")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/boards/{board_id}/definition-of-done/overrides
+/// Most recent first.
+pub async fn list_done_overrides(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (team_id, project_id, board_id) = path.into_inner();
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    if let Err(resp) = find_board(&data, &scope, &board_id).await {
+        return resp;
+    }
+    if !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
+    let coll = data.mongodb.db.collection::<DoneOverride>("definition_of_done_overrides");
+    let mut cursor = match coll.find(doc! { "board_id": &board_id }).sort(doc! { "at": -1 }).limit(OVERRIDES_PAGE).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching definition of done overrides: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching overrides");
+        }
+    };
+    let mut entries = Vec::new();
+    while let Some(entry) = cursor.next().await {
+        match entry {
+            Ok(e) => entries.push(e),
+            Err(e) => error!("Error reading definition of done override: {}", e),
+        }
+    }
+    ok(entries)
+}
@@ -0,0 +1,366 @@
+// src/dependency_graph.rs
+//
+// Team-wide ticket dependency graph for rendering. Edges come from `blocked_by`
+// (blocker → blocked ticket) and from `ticket:` references in ticket text, which
+// may cross projects. Cycles and the critical path are worked out on the
+// `blocks` edges only; references are shown but do not order work.
+
+use std::collections::{HashMap, HashSet};
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::project::Project;
+use crate::response::{CodedError, ok};
+use crate::tenancy::{ProjectsScope, Repo};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+use crate::ticket_references::TicketReference;
+
+/// Graphs are cut off at this many tickets.
+const MAX_NODES: usize = 2000;
+/// Remaining effort assumed for open tickets without an estimate, so that chains
+/// of unestimated tickets still rank by length.
+const UNESTIMATED_HOURS: f64 = 1.0;
+
+#[derive(Debug, Deserialize)]
+pub struct DependencyQuery {
+    /// Only the graph around this project's tickets
+    pub project_id: Option<String>,
+    /// Keep closed tickets; they never count towards the critical path
+    #[serde(default)]
+    pub include_closed: bool,
+}
+
+/// The ticket fields the graph needs.
+#[derive(Debug, Deserialize)]
+struct GraphTicket {
+    ticket_id: String,
+    title: String,
+    project_id: String,
+    status: String,
+    assignee: Option<String>,
+    due_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    estimate_hours: Option<f64>,
+    #[serde(default)]
+    blocked_by: Vec<String>,
+    #[serde(default)]
+    references: Vec<TicketReference>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GraphNode {
+    pub ticket_id: String,
+    pub title: String,
+    pub project_id: String,
+    pub project_name: String,
+    pub status: String,
+    pub closed: bool,
+    pub assignee: Option<String>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub estimate_hours: Option<f64>,
+    pub in_cycle: bool,
+    pub on_critical_path: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+    /// "blocks" (`from` must finish before `to`) or "references"
+    pub kind: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CriticalPath {
+    /// Blockers first
+    pub ticket_ids: Vec<String>,
+    /// Remaining effort along the path
+    pub total_hours: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DependencyGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    /// Each cycle lists the tickets that block each other in a loop
+    pub cycles: Vec<Vec<String>>,
+    pub critical_path: CriticalPath,
+    /// More than MAX_NODES tickets were linked; some were left out
+    pub truncated: bool,
+}
+
+fn is_closed(status: &str) -> bool {
+    CLOSED_STATUSES.contains(&status)
+}
+
+/// Strongly connected components of the `blocks` graph that contain a cycle
+/// (Kosaraju, iterative so deep chains cannot overflow the stack).
+fn find_cycles(succ: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = succ.len();
+    let mut pred = vec![Vec::new(); n];
+    for (i, targets) in succ.iter().enumerate() {
+        for &j in targets {
+            pred[j].push(i);
+        }
+    }
+
+    let mut visited = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    for start in 0..n {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut stack = vec![(start, 0)];
+        while let Some((node, next)) = stack.pop() {
+            match succ[node].get(next) {
+                Some(&child) => {
+                    stack.push((node, next + 1));
+                    if !visited[child] {
+                        visited[child] = true;
+                        stack.push((child, 0));
+                    }
+                }
+                None => order.push(node),
+            }
+        }
+    }
+
+    let mut assigned = vec![false; n];
+    let mut cycles = Vec::new();
+    for &start in order.iter().rev() {
+        if assigned[start] {
+            continue;
+        }
+        assigned[start] = true;
+        let mut members = Vec::new();
+        let mut stack = vec![start];
+        while let Some(node) = stack.pop() {
+            members.push(node);
+            for &p in &pred[node] {
+                if !assigned[p] {
+                    assigned[p] = true;
+                    stack.push(p);
+                }
+            }
+        }
+        // A single ticket is only a cycle when it blocks itself.
+        if members.len() > 1 || succ[start].contains(&start) {
+            cycles.push(members);
+        }
+    }
+    cycles
+}
+
+/// Longest chain by remaining effort through the acyclic part of the graph.
+fn critical_path(succ: &[Vec<usize>], weight: &[f64], in_cycle: &[bool]) -> (Vec<usize>, f64) {
+    let n = succ.len();
+    let mut indegree = vec![0usize; n];
+    for (i, targets) in succ.iter().enumerate() {
+        if in_cycle[i] {
+            continue;
+        }
+        for &j in targets {
+            if !in_cycle[j] {
+                indegree[j] += 1;
+            }
+        }
+    }
+    let mut queue: Vec<usize> = (0..n).filter(|&i| !in_cycle[i] && indegree[i] == 0).collect();
+    let mut dist: Vec<f64> = weight.to_vec();
+    let mut prev = vec![None; n];
+    while let Some(i) = queue.pop() {
+        for &j in &succ[i] {
+            if in_cycle[j] {
+                continue;
+            }
+            if dist[i] + weight[j] > dist[j] {
+                dist[j] = dist[i] + weight[j];
+                prev[j] = Some(i);
+            }
+            indegree[j] -= 1;
+            if indegree[j] == 0 {
+                queue.push(j);
+            }
+        }
+    }
+
+    let end = (0..n)
+        .filter(|&i| !in_cycle[i] && dist[i] > 0.0)
+        .max_by(|&a, &b| dist[a].total_cmp(&dist[b]));
+    let Some(end) = end else { return (Vec::new(), 0.0) };
+    let mut path = vec![end];
+    while let Some(p) = prev[*path.last().unwrap()] {
+        path.push(p);
+    }
+    path.reverse();
+    (path, dist[end])
+}
+
+async fn load_tickets(data: &AppState, scope: &ProjectsScope, filter: Document) -> mongodb::error::Result<Vec<GraphTicket>> {
+    let projection = doc! {
+        "ticket_id": 1, "title": 1, "project_id": 1, "status": 1, "assignee": 1, "due_date": 1,
+        "estimate_hours": 1, "blocked_by": 1, "references": 1,
+    };
+    let pipeline = vec![doc! { "$match": filter }, doc! { "$project": projection }];
+    let mut cursor = Repo::<Ticket>::across(&data.mongodb, scope).aggregate(pipeline).with_type::<GraphTicket>().await?;
+    let mut out = Vec::new();
+    while let Some(t) = cursor.next().await {
+        out.push(t?);
+    }
+    Ok(out)
+}
+
+/// GET /teams/{team_id}/dependencies?project_id=&include_closed=
+///
+/// Admins see every project of the team, other members the projects they belong to.
+/// Only tickets with at least one link are part of the graph.
+pub async fn get_dependencies(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    query: web::Query<DependencyQuery>,
+) -> impl Responder {
+    let team = match auth.team_scope(&team_id).await {
+        Ok(team) => team,
+        Err(resp) => return resp,
+    };
+    let mut scope = match team.projects(&data.mongodb).await {
+        Ok(scope) => scope,
+        Err(e) => {
+            error!("Error fetching projects: {}", e);
+            return HttpResponse::InternalServerError().body("Error building dependency graph");
+        }
+    };
+    let is_admin = auth.is_team_admin(&team_id).await;
+
+    let mut project_names = HashMap::new();
+    match Repo::<Project>::new(&data.mongodb, &team).find_docs(doc! {}).await {
+        Ok(mut cursor) => {
+            while let Some(Ok(p)) = cursor.next().await {
+                let (Ok(id), name) = (p.get_str("project_id"), p.get_str("name").unwrap_or_default()) else { continue };
+                if is_admin || auth.is_project_member(id).await {
+                    project_names.insert(id.to_string(), name.to_string());
+                }
+            }
+        }
+        Err(e) => {
+            error!("Error fetching projects: {}", e);
+            return HttpResponse::InternalServerError().body("Error building dependency graph");
+        }
+    }
+    if let Some(project_id) = &query.project_id {
+        if !project_names.contains_key(project_id) {
+            return HttpResponse::NotFound().error("project_not_found");
+        }
+    }
+    scope.retain(|id| project_names.contains_key(id));
+
+    // Tickets with outgoing links, then whatever they point at that was not loaded yet.
+    let mut filter = doc! {
+        "$or": [{ "blocked_by.0": { "$exists": true } }, { "references.kind": "ticket" }],
+    };
+    if let Some(project_id) = &query.project_id {
+        filter.insert("project_id", project_id);
+    }
+    let mut tickets = match load_tickets(&data, &scope, filter).await {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Error fetching linked tickets: {}", e);
+            return HttpResponse::InternalServerError().body("Error building dependency graph");
+        }
+    };
+    let loaded: HashSet<String> = tickets.iter().map(|t| t.ticket_id.clone()).collect();
+    let targets: HashSet<&String> = tickets
+        .iter()
+        .flat_map(|t| t.blocked_by.iter().chain(t.references.iter().filter(|r| r.kind == "ticket").map(|r| &r.id)))
+        .filter(|id| !loaded.contains(*id))
+        .collect();
+    if !targets.is_empty() {
+        let filter = doc! { "ticket_id": { "$in": targets.into_iter().collect::<Vec<_>>() } };
+        match load_tickets(&data, &scope, filter).await {
+            Ok(more) => tickets.extend(more),
+            Err(e) => {
+                error!("Error fetching linked tickets: {}", e);
+                return HttpResponse::InternalServerError().body("Error building dependency graph");
+            }
+        }
+    }
+    if !query.include_closed {
+        tickets.retain(|t| !is_closed(&t.status));
+    }
+    let truncated = tickets.len() > MAX_NODES;
+    tickets.truncate(MAX_NODES);
+
+    let index: HashMap<&str, usize> = tickets.iter().enumerate().map(|(i, t)| (t.ticket_id.as_str(), i)).collect();
+    let mut succ: Vec<Vec<usize>> = vec![Vec::new(); tickets.len()];
+    let mut edges = Vec::new();
+    for (i, t) in tickets.iter().enumerate() {
+        for blocker in &t.blocked_by {
+            if let Some(&b) = index.get(blocker.as_str()) {
+                if !succ[b].contains(&i) {
+                    succ[b].push(i);
+                    edges.push(GraphEdge { from: blocker.clone(), to: t.ticket_id.clone(), kind: "blocks" });
+                }
+            }
+        }
+        for r in t.references.iter().filter(|r| r.kind == "ticket" && r.id != t.ticket_id) {
+            if index.contains_key(r.id.as_str()) {
+                edges.push(GraphEdge { from: t.ticket_id.clone(), to: r.id.clone(), kind: "references" });
+            }
+        }
+    }
+    // Targets that fell out (closed, other team) can leave a ticket without any edge.
+    let linked: HashSet<&str> = edges.iter().flat_map(|e| [e.from.as_str(), e.to.as_str()]).collect();
+
+    let cycles = find_cycles(&succ);
+    let mut in_cycle = vec![false; tickets.len()];
+    for &i in cycles.iter().flatten() {
+        in_cycle[i] = true;
+    }
+    let weight: Vec<f64> = tickets
+        .iter()
+        .map(|t| if is_closed(&t.status) { 0.0 } else { t.estimate_hours.unwrap_or(UNESTIMATED_HOURS) })
+        .collect();
+    let (path, total_hours) = critical_path(&succ, &weight, &in_cycle);
+    let mut on_path = vec![false; tickets.len()];
+    for &i in &path {
+        on_path[i] = true;
+    }
+
+    let critical_path = CriticalPath {
+        ticket_ids: path.iter().map(|&i| tickets[i].ticket_id.clone()).collect(),
+        total_hours,
+    };
+    let cycles = cycles
+        .iter()
+        .map(|c| c.iter().map(|&i| tickets[i].ticket_id.clone()).collect())
+        .collect();
+    let nodes = tickets
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| linked.contains(t.ticket_id.as_str()))
+        .map(|(i, t)| GraphNode {
+            ticket_id: t.ticket_id.clone(),
+            title: t.title.clone(),
+            project_id: t.project_id.clone(),
+            project_name: project_names.get(&t.project_id).cloned().unwrap_or_default(),
+            status: t.status.clone(),
+            closed: is_closed(&t.status),
+            assignee: t.assignee.clone(),
+            due_date: t.due_date,
+            estimate_hours: t.estimate_hours,
+            in_cycle: in_cycle[i],
+            on_critical_path: on_path[i],
+        })
+        .collect();
+
+    ok(DependencyGraph { nodes, edges, cycles, critical_path, truncated })
+}
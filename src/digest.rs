@@ -0,0 +1,403 @@
+// src/digest.rs
+//
+// Daily or weekly digest emails: newly assigned tickets, @mentions in ticket
+// comments and chats, upcoming due dates, and progress of the sprints the user is
+// working in. Users choose the frequency (or opt out) in their notification
+// preferences; every email carries a one-click unsubscribe link. Emails are written
+// in the language chosen there, see i18n.rs.
+
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::{error, info};
+use mongodb::bson::{doc, DateTime as BsonDateTime};
+use regex::escape;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::V1_PREFIX;
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::Board;
+use crate::charts::signed_chart_url;
+use crate::chat::{Chat, DBMessage};
+use crate::i18n::{self, Lang};
+use crate::mailer::send_email;
+use crate::response::CodedError;
+use crate::tenancy::{project_team_id, ProjectsScope, Repo};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+use crate::ticket_events::{TicketChange, TicketEvent};
+use crate::user_management::User;
+
+const DIGEST_CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+pub const FREQUENCIES: [&str; 3] = ["off", "daily", "weekly"];
+const DEFAULT_FREQUENCY: &str = "weekly";
+/// Due dates this far ahead are listed as upcoming.
+const UPCOMING_DAYS: i64 = 7;
+/// Cap per section so a busy week does not produce an unreadable email.
+const MAX_ITEMS: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationPreferences {
+    pub user_id: String,
+    /// "off", "daily" or "weekly"
+    pub digest: String,
+    pub last_digest_at: Option<BsonDateTime>,
+    pub unsubscribe_token: String,
+    /// Language of the digest emails
+    #[serde(default)]
+    pub language: Lang,
+}
+
+impl NotificationPreferences {
+    fn new(user_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            digest: DEFAULT_FREQUENCY.to_string(),
+            last_digest_at: None,
+            unsubscribe_token: Uuid::new_v4().simple().to_string(),
+            language: Lang::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreferencesView {
+    pub digest: String,
+    pub last_digest_at: Option<DateTime<Utc>>,
+    pub language: Lang,
+}
+
+impl From<NotificationPreferences> for PreferencesView {
+    fn from(p: NotificationPreferences) -> Self {
+        Self { digest: p.digest, last_digest_at: p.last_digest_at.map(|t| t.to_chrono()), language: p.language }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePreferencesRequest {
+    pub digest: Option<String>,
+    /// Language tag such as "en" or "de-AT"
+    pub language: Option<String>,
+}
+
+fn prefs_coll(data: &AppState) -> mongodb::Collection<NotificationPreferences> {
+    data.mongodb.db.collection::<NotificationPreferences>("notification_preferences")
+}
+
+/// The user's preferences, creating the defaults on first use.
+async fn load_preferences(data: &AppState, user_id: &str) -> mongodb::error::Result<NotificationPreferences> {
+    let coll = prefs_coll(data);
+    if let Some(p) = coll.find_one(doc! { "user_id": user_id }).await? {
+        return Ok(p);
+    }
+    let prefs = NotificationPreferences::new(user_id);
+    coll.insert_one(&prefs).await?;
+    Ok(prefs)
+}
+
+/// GET /users/me/notification-preferences
+pub async fn get_notification_preferences(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    match load_preferences(&data, auth.user_id()).await {
+        Ok(p) => HttpResponse::Ok().json(PreferencesView::from(p)),
+        Err(e) => {
+            error!("Error fetching notification preferences: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching notification preferences")
+        }
+    }
+}
+
+/// PUT /users/me/notification-preferences
+pub async fn update_notification_preferences(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    payload: web::Json<UpdatePreferencesRequest>,
+) -> impl Responder {
+    let mut set = doc! {};
+    if let Some(digest) = &payload.digest {
+        if !FREQUENCIES.contains(&digest.as_str()) {
+            return HttpResponse::BadRequest().body(format!("digest must be one of {}", FREQUENCIES.join(", ")));
+        }
+        set.insert("digest", digest);
+    }
+    if let Some(tag) = &payload.language {
+        let Some(lang) = Lang::parse(tag) else {
+            let tags: Vec<&str> = Lang::SUPPORTED.iter().map(|l| l.tag()).collect();
+            return HttpResponse::BadRequest().body(format!("language must be one of {}", tags.join(", ")));
+        };
+        set.insert("language", lang.tag());
+    }
+    if set.is_empty() {
+        return HttpResponse::BadRequest().error("no_fields_to_update");
+    }
+    if let Err(e) = load_preferences(&data, auth.user_id()).await {
+        error!("Error fetching notification preferences: {}", e);
+        return HttpResponse::InternalServerError().body("Error updating notification preferences");
+    }
+    match prefs_coll(&data)
+        .find_one_and_update(doc! { "user_id": auth.user_id() }, doc! { "$set": set })
+        .return_document(mongodb::options::ReturnDocument::After)
+        .await
+    {
+        Ok(Some(p)) => HttpResponse::Ok().json(PreferencesView::from(p)),
+        Ok(None) => HttpResponse::NotFound().body("Preferences not found"),
+        Err(e) => {
+            error!("Error updating notification preferences: {}", e);
+            HttpResponse::InternalServerError().body("Error updating notification preferences")
+        }
+    }
+}
+
+/// GET /digest/unsubscribe/{token}
+///
+/// Linked from every digest email, so it works without a session.
+pub async fn unsubscribe_digest(data: web::Data<AppState>, token: web::Path<String>) -> impl Responder {
+    match prefs_coll(&data)
+        .update_one(doc! { "unsubscribe_token": &*token }, doc! { "$set": { "digest": "off" } })
+        .await
+    {
+        Ok(res) if res.matched_count == 0 => HttpResponse::NotFound().body("Unknown unsubscribe link"),
+        Ok(_) => HttpResponse::Ok().body("You will no longer receive digest emails."),
+        Err(e) => {
+            error!("Error unsubscribing from digests: {}", e);
+            HttpResponse::InternalServerError().body("Error unsubscribing")
+        }
+    }
+}
+
+#[derive(Default)]
+struct Digest {
+    assigned: Vec<String>,
+    mentions: Vec<String>,
+    upcoming: Vec<String>,
+    sprints: Vec<String>,
+}
+
+impl Digest {
+    fn is_empty(&self) -> bool {
+        self.assigned.is_empty() && self.mentions.is_empty() && self.upcoming.is_empty() && self.sprints.is_empty()
+    }
+
+    fn render(&self, lang: Lang, heading: &str, unsubscribe_url: &str) -> String {
+        let mut out = format!("{}\n", heading);
+        for (title, items) in [
+            ("digest.assigned", &self.assigned),
+            ("digest.mentions", &self.mentions),
+            ("digest.upcoming", &self.upcoming),
+            ("digest.sprints", &self.sprints),
+        ] {
+            if items.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("\n{}\n", i18n::text(title, lang)));
+            for item in items.iter().take(MAX_ITEMS) {
+                out.push_str(&format!("  - {}\n", item));
+            }
+            if items.len() > MAX_ITEMS {
+                let more = (items.len() - MAX_ITEMS).to_string();
+                out.push_str(&format!("  {}\n", i18n::format("digest.more", lang, &[&more])));
+            }
+        }
+        out.push_str(&format!("\n{}\n", i18n::format("digest.unsubscribe", lang, &[unsubscribe_url])));
+        out
+    }
+}
+
+async fn build_digest(
+    data: &AppState,
+    user_id: &str,
+    username: Option<&str>,
+    since: DateTime<Utc>,
+    lang: Lang,
+) -> mongodb::error::Result<Digest> {
+    let db = &data.mongodb.db;
+    // Only tickets of the teams the user is still on.
+    let scope = ProjectsScope::of_user(&data.mongodb, user_id).await?;
+    let tickets = Repo::<Ticket>::across(&data.mongodb, &scope);
+    let boards = Repo::<Board>::across(&data.mongodb, &scope);
+    let mut digest = Digest::default();
+    let mention = username.map(|u| format!(r"@{}\b", escape(u)));
+
+    // Assignments and ticket comment mentions come from the ticket event log.
+    let mut or = vec![
+        doc! { "change.type": "created", "change.ticket.assignee": user_id },
+        doc! { "change.type": "field_changed", "change.field": "assignee", "change.new": user_id },
+    ];
+    if let Some(pattern) = &mention {
+        or.push(doc! { "change.type": "commented", "change.comment.content": { "$regex": pattern } });
+    }
+    let mut cursor = db
+        .collection::<TicketEvent>("ticket_events")
+        .find(doc! { "$or": or, "actor_id": { "$ne": user_id } })
+        .await?;
+    let mut events = Vec::new();
+    while let Some(e) = cursor.next().await {
+        let e = e?;
+        if e.at >= since {
+            events.push(e);
+        }
+    }
+    let ticket_ids: Vec<&String> = events.iter().map(|e| &e.ticket_id).collect();
+    let mut titles = BTreeMap::new();
+    let mut cursor = tickets.find(doc! { "ticket_id": { "$in": ticket_ids } }).await?;
+    while let Some(t) = cursor.next().await {
+        let t = t?;
+        titles.insert(t.ticket_id.clone(), t.title);
+    }
+    let mut assigned = HashSet::new();
+    for e in &events {
+        // Tickets deleted since are left out.
+        let title = match titles.get(&e.ticket_id) {
+            Some(t) => t,
+            None => continue,
+        };
+        match &e.change {
+            TicketChange::Commented { comment } => {
+                digest.mentions.push(i18n::format("digest.mention_ticket", lang, &[title, &comment.content]));
+            }
+            _ if assigned.insert(&e.ticket_id) => digest.assigned.push(title.clone()),
+            _ => {}
+        }
+    }
+
+    // Chat mentions in chats the user takes part in.
+    if let Some(pattern) = &mention {
+        let mut chat_ids = Vec::new();
+        let mut cursor = db.collection::<Chat>("chats").find(doc! { "participants": user_id }).await?;
+        while let Some(c) = cursor.next().await {
+            chat_ids.push(c?.id_chat);
+        }
+        let mut cursor = db
+            .collection::<DBMessage>("messages")
+            .find(doc! {
+                "id_chat": { "$in": chat_ids },
+                "sender_id": { "$ne": user_id },
+                "content": { "$regex": pattern },
+            })
+            .sort(doc! { "created_at": -1 })
+            .limit(MAX_ITEMS as i64 * 5)
+            .await?;
+        while let Some(m) = cursor.next().await {
+            let m = m?;
+            if m.created_at >= since {
+                digest.mentions.push(i18n::format("digest.mention_chat", lang, &[&m.content]));
+            }
+        }
+    }
+
+    // Due dates and sprint progress from the user's open tickets.
+    let mut cursor = tickets
+        .find(doc! { "assignee": user_id, "status": { "$nin": CLOSED_STATUSES.to_vec() } })
+        .await?;
+    let now = Utc::now();
+    let horizon = now + chrono::Duration::days(UPCOMING_DAYS);
+    let mut sprints = BTreeMap::new();
+    while let Some(t) = cursor.next().await {
+        let t = t?;
+        if let Some(due) = t.due_date.filter(|d| *d <= horizon) {
+            let code = if due < now { "digest.overdue" } else { "digest.due" };
+            digest.upcoming.push(i18n::format(code, lang, &[&t.title, &due.format("%Y-%m-%d").to_string()]));
+        }
+        if let Some(sprint) = t.sprint {
+            sprints.insert((t.board_id.clone(), sprint), ());
+        }
+    }
+    for (board_id, sprint) in sprints.into_keys() {
+        let total = tickets.count_documents(doc! { "board_id": &board_id, "sprint": sprint }).await?;
+        let done = tickets
+            .count_documents(doc! { "board_id": &board_id, "sprint": sprint, "status": { "$in": CLOSED_STATUSES.to_vec() } })
+            .await?;
+        let board = boards.find_one(doc! { "board_id": &board_id }).await?;
+        let project_id = board.as_ref().map(|b| b.project_id.clone());
+        let name = board.map(|b| b.name).unwrap_or_else(|| board_id.clone());
+        let (sprint_no, done, total) = (sprint.to_string(), done.to_string(), total.to_string());
+        let mut item = i18n::format("digest.sprint", lang, &[&name, &sprint_no, &done, &total]);
+        if let Some(project_id) = project_id {
+            if let Some(team_id) = project_team_id(&data.mongodb, &project_id).await {
+                let path = format!("/teams/{}/projects/{}/boards/{}/charts/burndown.png?sprint={}", team_id, project_id, board_id, sprint);
+                item.push_str(&format!("\n    {}", i18n::format("digest.burndown", lang, &[&signed_chart_url(data, &path)])));
+            }
+        }
+        digest.sprints.push(item);
+    }
+    Ok(digest)
+}
+
+/// Email every user whose digest is due and advance their `last_digest_at`.
+async fn send_due_digests(data: &AppState) {
+    let mut cursor = match data.mongodb.db.collection::<User>("users").find(doc! {}).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error listing users for digests: {}", e);
+            return;
+        }
+    };
+    let now = Utc::now();
+    let mut sent = 0;
+    while let Some(user) = cursor.next().await {
+        // Accounts that do not fit the model (e.g. no email) are skipped, not fatal.
+        let user = match user {
+            Ok(u) => u,
+            Err(_) => continue,
+        };
+        let user_id = user.id.to_hex();
+        let prefs = match load_preferences(data, &user_id).await {
+            Ok(p) => p,
+            Err(e) => {
+                error!("Error fetching notification preferences of {}: {}", user_id, e);
+                continue;
+            }
+        };
+        let period = match prefs.digest.as_str() {
+            "daily" => chrono::Duration::days(1),
+            "weekly" => chrono::Duration::days(7),
+            _ => continue,
+        };
+        let since = prefs.last_digest_at.map_or(now - period, |t| t.to_chrono());
+        if since + period > now {
+            continue;
+        }
+
+        let digest = match build_digest(data, &user_id, user.username.as_deref(), since, prefs.language).await {
+            Ok(d) => d,
+            Err(e) => {
+                error!("Error building digest for {}: {}", user_id, e);
+                continue;
+            }
+        };
+        if !digest.is_empty() {
+            let lang = prefs.language;
+            let subject = i18n::text(if prefs.digest == "daily" { "digest.subject.daily" } else { "digest.subject.weekly" }, lang);
+            let unsubscribe_url = format!("{}{}/digest/unsubscribe/{}", data.config.app_base_url, V1_PREFIX, prefs.unsubscribe_token);
+            let heading = i18n::format("digest.heading", lang, &[&since.format("%Y-%m-%d %H:%M UTC").to_string()]);
+            if send_email(data, &user.email, subject, &digest.render(lang, &heading, &unsubscribe_url)).await.is_err() {
+                // Retried on the next check.
+                continue;
+            }
+            sent += 1;
+        }
+        if let Err(e) = prefs_coll(data)
+            .update_one(doc! { "user_id": &user_id }, doc! { "$set": { "last_digest_at": BsonDateTime::from_chrono(now) } })
+            .await
+        {
+            error!("Error recording digest time for {}: {}", user_id, e);
+        }
+    }
+    if sent > 0 {
+        info!("Sent {} digest email(s)", sent);
+    }
+}
+
+/// Start the background job that sends digest emails.
+pub fn spawn_digests(data: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(DIGEST_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            send_due_digests(&data).await;
+        }
+    });
+}
@@ -0,0 +1,509 @@
+// src/do_not_disturb.rs
+//
+// Per-chat mutes and do-not-disturb windows. A muted chat still delivers its
+// messages over the WebSocket, flagged `silent` so clients skip the alert. While a
+// user is in DND, chat messages are flagged the same way and counted, and
+// notifications pushed through `notify_user` are held back; once the window ends the
+// user gets one `dnd_summary` push with everything they missed. The summary is sent
+// by the instance holding the user's WebSocket; until one exists it stays stored.
+//
+// DND is either a fixed local window (which may wrap midnight) or "outside working
+// hours": evenings, nights and weekends relative to the user's working hours. On top
+// of either, `until` puts the user in DND for a while, e.g. during a focus session.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{doc, oid::ObjectId, to_bson, Document};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat::Chat;
+use crate::chat_db::MongoDB;
+use crate::chat_server::{IsOnline, NotifyUser, PushIfOnline};
+use crate::response::CodedError;
+use crate::sprint_planning::working_window;
+use crate::team_time::parse_timezone;
+
+const SUMMARY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Held-back notifications kept per user; older ones are dropped from the summary.
+const MAX_DEFERRED: i32 = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DndMode {
+    #[default]
+    Off,
+    OutsideWorkingHours,
+    Custom,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMute {
+    pub chat_id: String,
+    /// Muted indefinitely when unset
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DndSettings {
+    pub user_id: String,
+    #[serde(default)]
+    pub mode: DndMode,
+    /// "HH:MM" local start of the `custom` window
+    pub start: Option<String>,
+    /// "HH:MM" local end of the `custom` window
+    pub end: Option<String>,
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    #[serde(default)]
+    pub muted_chats: Vec<ChatMute>,
+    /// In DND regardless of `mode` until then
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+impl DndSettings {
+    fn new(user_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            mode: DndMode::Off,
+            start: None,
+            end: None,
+            timezone: default_timezone(),
+            muted_chats: Vec::new(),
+            until: None,
+        }
+    }
+
+    fn is_muted(&self, chat_id: &str, now: DateTime<Utc>) -> bool {
+        self.muted_chats
+            .iter()
+            .any(|m| m.chat_id == chat_id && m.until.is_none_or(|u| u > now))
+    }
+
+    /// Whether `now` falls in the user's DND window. `working` is the user's working
+    /// hours, only consulted in `outside_working_hours` mode.
+    fn in_dnd(&self, working: Option<(NaiveTime, NaiveTime)>, now: DateTime<Utc>) -> bool {
+        if self.until.is_some_and(|u| u > now) {
+            return true;
+        }
+        let local = now.with_timezone(&self.timezone.parse::<Tz>().unwrap_or(Tz::UTC));
+        match self.mode {
+            DndMode::Off => false,
+            DndMode::Custom => match (parse_time(self.start.as_deref()), parse_time(self.end.as_deref())) {
+                (Some(start), Some(end)) => in_window(local.time(), start, end),
+                _ => false,
+            },
+            // Without working hours there is nothing to be outside of.
+            DndMode::OutsideWorkingHours => working.is_some_and(|(start, end)| {
+                matches!(local.weekday(), Weekday::Sat | Weekday::Sun) || !in_window(local.time(), start, end)
+            }),
+        }
+    }
+}
+
+fn parse_time(value: Option<&str>) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(value?, "%H:%M").ok()
+}
+
+/// `[start, end)`, wrapping past midnight when `end` is earlier than `start`.
+fn in_window(t: NaiveTime, start: NaiveTime, end: NaiveTime) -> bool {
+    if start <= end {
+        t >= start && t < end
+    } else {
+        t >= start || t < end
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDndRequest {
+    pub mode: DndMode,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub timezone: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MuteChatRequest {
+    pub until: Option<DateTime<Utc>>,
+}
+
+fn settings_coll(db: &MongoDB) -> mongodb::Collection<DndSettings> {
+    db.db.collection::<DndSettings>("dnd_settings")
+}
+
+fn backlog_coll(db: &MongoDB) -> mongodb::Collection<Document> {
+    db.db.collection::<Document>("dnd_backlog")
+}
+
+/// Settings and working hours of each user that has DND settings stored.
+async fn load_states(
+    db: &MongoDB,
+    user_ids: &[String],
+) -> mongodb::error::Result<HashMap<String, (DndSettings, Option<(NaiveTime, NaiveTime)>)>> {
+    let mut states = HashMap::new();
+    let mut cursor = settings_coll(db).find(doc! { "user_id": { "$in": user_ids } }).await?;
+    while let Some(s) = cursor.next().await {
+        let s = s?;
+        states.insert(s.user_id.clone(), (s, None));
+    }
+    let oids: Vec<ObjectId> = states
+        .values()
+        .filter(|(s, _)| s.mode == DndMode::OutsideWorkingHours)
+        .filter_map(|(s, _)| ObjectId::parse_str(&s.user_id).ok())
+        .collect();
+    if oids.is_empty() {
+        return Ok(states);
+    }
+    let mut cursor = db.db.collection::<Document>("users").find(doc! { "_id": { "$in": oids } }).await?;
+    while let Some(user) = cursor.next().await {
+        let user = user?;
+        let Ok(oid) = user.get_object_id("_id") else { continue };
+        if let Some((_, working)) = states.get_mut(&oid.to_hex()) {
+            *working = working_window(user.get_str("working_hours_start").ok(), user.get_str("working_hours_end").ok());
+        }
+    }
+    Ok(states)
+}
+
+/// Recipients of a chat message who should not be alerted: those who muted the
+/// chat, and those in DND, whose missed count for the chat is bumped for the summary.
+pub(crate) async fn quiet_recipients(db: &MongoDB, chat_id: &str, user_ids: &[String]) -> HashSet<String> {
+    let states = match load_states(db, user_ids).await {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Error loading DND settings: {}", e);
+            return HashSet::new();
+        }
+    };
+    let now = Utc::now();
+    let mut quiet = HashSet::new();
+    for (user_id, (settings, working)) in &states {
+        if settings.is_muted(chat_id, now) {
+            quiet.insert(user_id.clone());
+        } else if settings.in_dnd(*working, now) {
+            quiet.insert(user_id.clone());
+            let field = format!("missed_messages.{}", chat_id);
+            let update = doc! { "$inc": { field: 1 } };
+            if let Err(e) = backlog_coll(db).update_one(doc! { "user_id": user_id }, update).upsert(true).await {
+                error!("Error recording missed message for {}: {}", user_id, e);
+            }
+        }
+    }
+    quiet
+}
+
+/// Whether the user is in DND right now.
+pub(crate) async fn in_dnd_now(db: &MongoDB, user_id: &str) -> bool {
+    match load_states(db, &[user_id.to_string()]).await {
+        Ok(states) => states.get(user_id).is_some_and(|(s, working)| s.in_dnd(*working, Utc::now())),
+        Err(e) => {
+            error!("Error loading DND settings: {}", e);
+            false
+        }
+    }
+}
+
+/// Until when the user is in DND whatever their schedule, if set.
+pub(crate) async fn dnd_until(db: &MongoDB, user_id: &str) -> mongodb::error::Result<Option<DateTime<Utc>>> {
+    Ok(find_settings(db, user_id).await?.until)
+}
+
+/// Puts the user in DND until `until` whatever their schedule; None ends it.
+pub(crate) async fn set_dnd_until(db: &MongoDB, user_id: &str, until: Option<DateTime<Utc>>) -> mongodb::error::Result<()> {
+    let until = to_bson(&until).unwrap_or_default();
+    settings_coll(db).update_one(doc! { "user_id": user_id }, doc! { "$set": { "until": until } }).upsert(true).await?;
+    Ok(())
+}
+
+/// Push a notification to the user's sessions, or hold it for the summary while the
+/// user is in DND.
+pub(crate) async fn notify_user(data: &AppState, user_id: &str, payload: String) {
+    if let Err(e) = try_notify_user(data, user_id, payload).await {
+        error!("Error deferring notification for {}: {}", user_id, e);
+    }
+}
+
+/// `notify_user` for callers that retry: fails when a held-back notification could
+/// not be stored.
+pub(crate) async fn try_notify_user(data: &AppState, user_id: &str, payload: String) -> mongodb::error::Result<()> {
+    if !in_dnd_now(&data.mongodb, user_id).await {
+        data.chat_server.do_send(NotifyUser { user_id: user_id.to_string(), payload });
+        return Ok(());
+    }
+    let update = doc! { "$push": { "notifications": { "$each": [payload], "$slice": -MAX_DEFERRED } } };
+    backlog_coll(&data.mongodb).update_one(doc! { "user_id": user_id }, update).upsert(true).await?;
+    Ok(())
+}
+
+/// Puts a taken backlog back when its summary could not be pushed, merged with
+/// anything deferred in the meantime.
+async fn restore_backlog(db: &MongoDB, user_id: &str, backlog: &Document) {
+    let mut update = doc! {};
+    if let Ok(notifications) = backlog.get_array("notifications") {
+        update.insert(
+            "$push",
+            doc! { "notifications": { "$each": notifications.clone(), "$position": 0, "$slice": -MAX_DEFERRED } },
+        );
+    }
+    if let Ok(missed) = backlog.get_document("missed_messages") {
+        let inc: Document = missed
+            .iter()
+            .map(|(chat_id, n)| (format!("missed_messages.{}", chat_id), n.clone()))
+            .collect();
+        if !inc.is_empty() {
+            update.insert("$inc", inc);
+        }
+    }
+    if update.is_empty() {
+        return;
+    }
+    if let Err(e) = backlog_coll(db).update_one(doc! { "user_id": user_id }, update).upsert(true).await {
+        error!("Error restoring DND backlog of {}: {}", user_id, e);
+    }
+}
+
+/// Deliver the summary to every user whose DND window has ended and who has a
+/// session on this instance. Taking the backlog is the claim: of several instances
+/// only one gets it, and offline users keep theirs until they connect.
+async fn deliver_summaries(data: &AppState) {
+    let user_ids: Vec<String> = match backlog_coll(&data.mongodb).distinct("user_id", doc! {}).await {
+        Ok(ids) => ids.iter().filter_map(|v| v.as_str().map(str::to_string)).collect(),
+        Err(e) => {
+            error!("Error fetching DND backlog: {}", e);
+            return;
+        }
+    };
+    if user_ids.is_empty() {
+        return;
+    }
+    let states = match load_states(&data.mongodb, &user_ids).await {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Error loading DND settings: {}", e);
+            return;
+        }
+    };
+    let now = Utc::now();
+    for user_id in user_ids {
+        if states.get(&user_id).is_some_and(|(s, working)| s.in_dnd(*working, now)) {
+            continue;
+        }
+        if !data.chat_server.send(IsOnline { user_id: user_id.clone() }).await.unwrap_or(false) {
+            continue;
+        }
+        let backlog = match backlog_coll(&data.mongodb).find_one_and_delete(doc! { "user_id": &user_id }).await {
+            Ok(Some(b)) => b,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Error taking DND backlog of {}: {}", user_id, e);
+                continue;
+            }
+        };
+        let notifications: Vec<serde_json::Value> = backlog
+            .get_array("notifications")
+            .map(|n| n.iter().filter_map(|p| p.as_str()).filter_map(|p| serde_json::from_str(p).ok()).collect())
+            .unwrap_or_default();
+        let missed_messages: Vec<serde_json::Value> = backlog
+            .get_document("missed_messages")
+            .map(|m| {
+                m.iter()
+                    .map(|(chat_id, n)| serde_json::json!({ "chat_id": chat_id, "count": n.as_i32().unwrap_or(0) }))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let payload = serde_json::json!({
+            "type": "dnd_summary",
+            "notifications": notifications,
+            "missed_messages": missed_messages,
+        });
+        let pushed = data
+            .chat_server
+            .send(PushIfOnline { user_id: user_id.clone(), payload: payload.to_string() })
+            .await
+            .unwrap_or(false);
+        // The user disconnected since the check; keep the summary for later.
+        if !pushed {
+            restore_backlog(&data.mongodb, &user_id, &backlog).await;
+        }
+    }
+}
+
+pub fn spawn_dnd_summaries(data: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(SUMMARY_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            deliver_summaries(&data).await;
+        }
+    });
+}
+
+async fn find_settings(db: &MongoDB, user_id: &str) -> mongodb::error::Result<DndSettings> {
+    let saved = settings_coll(db).find_one(doc! { "user_id": user_id }).await?;
+    Ok(saved.unwrap_or_else(|| DndSettings::new(user_id)))
+}
+
+/// The user's own time zone, as set with their DND settings. Users who never set
+/// one get the zone of one of their teams, and UTC when no team has one either.
+pub(crate) async fn user_timezone(db: &MongoDB, user_id: &str) -> Tz {
+    match own_or_team_timezone(db, user_id).await {
+        Ok(tz) => tz.unwrap_or(Tz::UTC),
+        Err(e) => {
+            error!("Error fetching time zone of {}: {}", user_id, e);
+            Tz::UTC
+        }
+    }
+}
+
+async fn own_or_team_timezone(db: &MongoDB, user_id: &str) -> mongodb::error::Result<Option<Tz>> {
+    // Read raw: the typed settings default the zone to UTC when it was never stored.
+    let own = db.db.collection::<Document>("dnd_settings").find_one(doc! { "user_id": user_id }).await?;
+    if let Some(tz) = own.as_ref().and_then(|s| s.get_str("timezone").ok()).and_then(|tz| tz.parse::<Tz>().ok()) {
+        return Ok(Some(tz));
+    }
+    let team_ids = db.db.collection::<Document>("user_teams").distinct("team_id", doc! { "user_id": user_id }).await?;
+    let team = db
+        .db
+        .collection::<Document>("teams")
+        .find_one(doc! { "team_id": { "$in": team_ids }, "timezone": { "$type": "string" } })
+        .sort(doc! { "team_id": 1 })
+        .await?;
+    Ok(team.and_then(|t| t.get_str("timezone").ok().and_then(|tz| tz.parse::<Tz>().ok())))
+}
+
+/// GET /users/me/dnd
+pub async fn get_dnd_settings(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    match find_settings(&data.mongodb, auth.user_id()).await {
+        Ok(s) => HttpResponse::Ok().json(s),
+        Err(e) => {
+            error!("Error fetching DND settings: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching DND settings")
+        }
+    }
+}
+
+/// PUT /users/me/dnd
+pub async fn update_dnd_settings(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    payload: web::Json<UpdateDndRequest>,
+) -> impl Responder {
+    let req = payload.into_inner();
+    if let Some(Err(msg)) = req.timezone.as_deref().map(parse_timezone) {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    let (start, end) = if req.mode == DndMode::Custom {
+        match (parse_time(req.start.as_deref()), parse_time(req.end.as_deref())) {
+            (Some(s), Some(e)) if s != e => (req.start, req.end),
+            _ => return HttpResponse::BadRequest().body("custom mode needs different start and end times as HH:MM"),
+        }
+    } else {
+        (None, None)
+    };
+    let mode = match to_bson(&req.mode) {
+        Ok(m) => m,
+        Err(e) => {
+            error!("Error serializing DND mode: {}", e);
+            return HttpResponse::InternalServerError().body("Error updating DND settings");
+        }
+    };
+    let mut set = doc! { "mode": mode, "start": start, "end": end };
+    // Left unset unless given, so `user_timezone` can fall back to the team's zone.
+    if let Some(timezone) = req.timezone {
+        set.insert("timezone", timezone);
+    }
+    let update = doc! { "$set": set };
+    match settings_coll(&data.mongodb)
+        .find_one_and_update(doc! { "user_id": auth.user_id() }, update)
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .await
+    {
+        Ok(Some(s)) => HttpResponse::Ok().json(s),
+        Ok(None) => HttpResponse::InternalServerError().body("Error updating DND settings"),
+        Err(e) => {
+            error!("Error updating DND settings: {}", e);
+            HttpResponse::InternalServerError().body("Error updating DND settings")
+        }
+    }
+}
+
+/// PUT /chats/{chat_id}/mute
+/// `{"until": null}` mutes until unmuted.
+pub async fn mute_chat(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    chat_id: web::Path<String>,
+    payload: web::Json<MuteChatRequest>,
+) -> impl Responder {
+    let chat_id = chat_id.into_inner();
+    if payload.until.is_some_and(|u| u <= Utc::now()) {
+        return HttpResponse::BadRequest().body("until must be in the future");
+    }
+    let chats = data.mongodb.db.collection::<Chat>("chats");
+    match chats.find_one(doc! { "_id": &chat_id, "participants": auth.user_id() }).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::Forbidden().error("not_chat_participant"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    }
+
+    let mute = ChatMute { chat_id: chat_id.clone(), until: payload.until };
+    let mute = match to_bson(&mute) {
+        Ok(m) => m,
+        Err(e) => {
+            error!("Error serializing chat mute: {}", e);
+            return HttpResponse::InternalServerError().body("Error muting chat");
+        }
+    };
+    let coll = settings_coll(&data.mongodb);
+    let filter = doc! { "user_id": auth.user_id() };
+    let result = async {
+        coll.update_one(filter.clone(), doc! { "$pull": { "muted_chats": { "chat_id": &chat_id } } })
+            .upsert(true)
+            .await?;
+        coll.find_one_and_update(filter.clone(), doc! { "$push": { "muted_chats": mute } })
+            .return_document(ReturnDocument::After)
+            .await
+    }
+    .await;
+    match result {
+        Ok(Some(s)) => HttpResponse::Ok().json(s),
+        Ok(None) => HttpResponse::InternalServerError().body("Error muting chat"),
+        Err(e) => {
+            error!("Error muting chat: {}", e);
+            HttpResponse::InternalServerError().body("Error muting chat")
+        }
+    }
+}
+
+/// DELETE /chats/{chat_id}/mute
+pub async fn unmute_chat(auth: AuthContext, data: web::Data<AppState>, chat_id: web::Path<String>) -> impl Responder {
+    match settings_coll(&data.mongodb)
+        .find_one_and_update(
+            doc! { "user_id": auth.user_id() },
+            doc! { "$pull": { "muted_chats": { "chat_id": &*chat_id } } },
+        )
+        .return_document(ReturnDocument::After)
+        .await
+    {
+        Ok(Some(s)) => HttpResponse::Ok().json(s),
+        Ok(None) => HttpResponse::Ok().json(DndSettings::new(auth.user_id())),
+        Err(e) => {
+            error!("Error unmuting chat: {}", e);
+            HttpResponse::InternalServerError().body("Error unmuting chat")
+        }
+    }
+}
@@ -0,0 +1,407 @@
+// src/doc_collab.rs
+//
+// Collaborative editing of knowledge base documents. Every document being edited
+// gets an in-memory Yjs (yrs) room; clients exchange binary updates (base64 over the
+// existing WebSocket) and the merged state is snapshotted to MongoDB periodically.
+// A room stays in memory until its last change is saved, and while a join is still
+// loading it, so a snapshot read from MongoDB is never older than a live room.
+// Anyone who can read a document may edit it; that is rechecked on updates, so
+// losing access also ends a live editing session.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Utc;
+use log::{error, info};
+use mongodb::bson::{doc, spec::BinarySubtype, Binary, Document};
+use yrs::updates::decoder::Decode;
+use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact, Update};
+
+use crate::chat_db::MongoDB;
+use crate::chat_server::{SignalMessage, WsMessage};
+use crate::knowledge_base;
+use crate::sync::Op;
+
+/// How often dirty rooms are written back to MongoDB.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10);
+/// Name of the shared text type holding the document body.
+const CONTENT_FIELD: &str = "content";
+/// How long a member's access check covers their updates.
+const ACCESS_RECHECK: Duration = Duration::from_secs(30);
+
+struct Room {
+    doc: Doc,
+    members: HashMap<String, Vec<Recipient<WsMessage>>>,
+    /// When each member's access to the document was last confirmed
+    checked: HashMap<String, Instant>,
+    /// Bumped by every update; the room is clean when it equals `saved_revision`.
+    revision: u64,
+    saved_revision: u64,
+    saving: bool,
+}
+
+impl Room {
+    fn new(doc: Doc) -> Self {
+        Room { doc, members: HashMap::new(), checked: HashMap::new(), revision: 0, saved_revision: 0, saving: false }
+    }
+
+    fn dirty(&self) -> bool {
+        self.revision != self.saved_revision
+    }
+}
+
+pub struct DocServer {
+    rooms: HashMap<String, Room>,
+    /// Joins still loading each document; its room is kept until they finish.
+    loading: HashMap<String, usize>,
+    db: Arc<MongoDB>,
+}
+
+impl DocServer {
+    pub fn new(db: Arc<MongoDB>) -> Self {
+        DocServer { rooms: HashMap::new(), loading: HashMap::new(), db }
+    }
+
+    fn send(addr: &Recipient<WsMessage>, payload: serde_json::Value) {
+        addr.do_send(WsMessage::Signal(SignalMessage { payload: payload.to_string() }));
+    }
+
+    fn send_error(addr: &Recipient<WsMessage>, doc_id: &str, error: &str) {
+        Self::send(addr, serde_json::json!({ "type": "doc_error", "doc_id": doc_id, "error": error }));
+    }
+
+    /// Persist every dirty room that is not already being saved. Rooms are dropped
+    /// once they have no members, nothing left to save and no join loading them.
+    fn flush(&mut self, ctx: &mut Context<Self>) {
+        let loading = &self.loading;
+        self.rooms.retain(|id, r| !r.members.is_empty() || r.dirty() || r.saving || loading.contains_key(id));
+
+        let mut snapshots = Vec::new();
+        for (doc_id, room) in self.rooms.iter_mut().filter(|(_, r)| r.dirty() && !r.saving) {
+            let txn = room.doc.transact();
+            let update = txn.encode_state_as_update_v1(&StateVector::default());
+            let text = room.doc.get_or_insert_text(CONTENT_FIELD).get_string(&txn);
+            drop(txn);
+            snapshots.push((doc_id.clone(), room.revision, update, text));
+            room.saving = true;
+        }
+        if snapshots.is_empty() {
+            return;
+        }
+        let db = self.db.clone();
+        ctx.spawn(
+            async move {
+                let mut saved = Vec::with_capacity(snapshots.len());
+                for (doc_id, revision, update, text) in snapshots {
+                    let result = save_snapshot(&db, &doc_id, update, &text).await;
+                    if let Err(e) = &result {
+                        error!("Error saving snapshot for document {}: {}", doc_id, e);
+                    }
+                    saved.push((doc_id, revision, result.is_ok()));
+                }
+                saved
+            }
+            .into_actor(self)
+            .map(|saved, act, _| {
+                // A failed write leaves the room dirty for the next flush.
+                for (doc_id, revision, ok) in saved {
+                    if let Some(room) = act.rooms.get_mut(&doc_id) {
+                        room.saving = false;
+                        if ok {
+                            room.saved_revision = room.saved_revision.max(revision);
+                        }
+                    }
+                }
+            }),
+        );
+    }
+
+    /// Applies a member's update and relays it to the rest of the room.
+    fn apply_update(&mut self, msg: DocUpdate) {
+        let room = match self.rooms.get_mut(&msg.doc_id) {
+            Some(r) if r.members.get(&msg.user_id).is_some_and(|a| a.contains(&msg.addr)) => r,
+            _ => return Self::send_error(&msg.addr, &msg.doc_id, "Join the document first"),
+        };
+        let applied = BASE64
+            .decode(&msg.update)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| Update::decode_v1(&bytes).map_err(|e| e.to_string()))
+            .and_then(|update| room.doc.transact_mut().apply_update(update).map_err(|e| e.to_string()));
+        if let Err(e) = applied {
+            return Self::send_error(&msg.addr, &msg.doc_id, &e);
+        }
+        room.revision += 1;
+
+        let payload = serde_json::json!({
+            "type": "doc_update",
+            "doc_id": msg.doc_id,
+            "user_id": msg.user_id,
+            "update": msg.update,
+        });
+        for addr in room.members.values().flatten().filter(|a| **a != msg.addr) {
+            Self::send(addr, payload.clone());
+        }
+    }
+
+    /// Removes a user who can no longer read the document from its room.
+    fn evict(&mut self, doc_id: &str, user_id: &str) {
+        if let Some(room) = self.rooms.get_mut(doc_id) {
+            room.checked.remove(user_id);
+            for addr in room.members.remove(user_id).unwrap_or_default() {
+                Self::send_error(&addr, doc_id, "Document not found");
+            }
+        }
+    }
+}
+
+async fn save_snapshot(db: &MongoDB, doc_id: &str, update: Vec<u8>, text: &str) -> mongodb::error::Result<()> {
+    let now = Utc::now();
+    let snapshots = db.db.collection::<Document>("kb_doc_snapshots");
+    let state = Binary { subtype: BinarySubtype::Generic, bytes: update };
+    snapshots
+        .update_one(
+            doc! { "doc_id": doc_id },
+            doc! { "$set": { "doc_id": doc_id, "state": state, "updated_at": now.to_rfc3339() } },
+        )
+        .upsert(true)
+        .await?;
+    // Keep the plain-text copy used by the REST endpoints in step with the CRDT.
+    let kb = db.db.collection::<knowledge_base::Document>("knowledge_base");
+    let saved = kb
+        .find_one_and_update(
+            doc! { "_id": doc_id },
+            doc! { "$set": { "content": text, "updated_at": now.to_rfc3339() } },
+        )
+        .await?;
+    if let Some(saved) = saved {
+        knowledge_base::record_doc_change(db, &saved, Op::Upsert).await;
+    }
+    Ok(())
+}
+
+/// Build the server-side Doc from the last snapshot, or seed it from the stored content.
+async fn load_doc(db: &MongoDB, doc_id: &str) -> Result<Doc, String> {
+    let snapshots = db.db.collection::<Document>("kb_doc_snapshots");
+    let doc = Doc::new();
+    let text = doc.get_or_insert_text(CONTENT_FIELD);
+
+    if let Some(snap) = snapshots.find_one(doc! { "doc_id": doc_id }).await.map_err(|e| e.to_string())? {
+        let bytes = snap.get_binary_generic("state").map_err(|e| e.to_string())?;
+        let update = Update::decode_v1(bytes).map_err(|e| e.to_string())?;
+        doc.transact_mut().apply_update(update).map_err(|e| e.to_string())?;
+        return Ok(doc);
+    }
+
+    let kb = db.db.collection::<knowledge_base::Document>("knowledge_base");
+    match kb.find_one(doc! { "_id": doc_id }).await.map_err(|e| e.to_string())? {
+        Some(existing) => {
+            let mut txn = doc.transact_mut();
+            text.insert(&mut txn, 0, &existing.content);
+        }
+        None => return Err("Document not found".to_string()),
+    }
+    Ok(doc)
+}
+
+impl Actor for DocServer {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(SNAPSHOT_INTERVAL, |act, ctx| act.flush(ctx));
+    }
+}
+
+/// A session wants to edit `doc_id`. `state_vector` is the client's base64 state vector
+/// (empty for a fresh client); the reply contains everything the client is missing, which
+/// is how offline edits are merged on reconnect.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct JoinDoc {
+    pub doc_id: String,
+    pub user_id: String,
+    pub state_vector: Option<String>,
+    pub addr: Recipient<WsMessage>,
+}
+
+/// Incremental update produced by a client.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct DocUpdate {
+    pub doc_id: String,
+    pub user_id: String,
+    pub update: String,
+    pub addr: Recipient<WsMessage>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct LeaveDoc {
+    /// None leaves every room, used when the socket closes.
+    pub doc_id: Option<String>,
+    pub user_id: String,
+    pub addr: Recipient<WsMessage>,
+}
+
+impl DocServer {
+    fn join_loaded(&mut self, msg: JoinDoc) {
+        let room = match self.rooms.get_mut(&msg.doc_id) {
+            Some(r) => r,
+            None => return,
+        };
+        let client_sv = msg
+            .state_vector
+            .as_deref()
+            .and_then(|sv| BASE64.decode(sv).ok())
+            .and_then(|bytes| StateVector::decode_v1(&bytes).ok())
+            .unwrap_or_default();
+        let (diff, server_sv) = {
+            let txn = room.doc.transact();
+            (txn.encode_state_as_update_v1(&client_sv), txn.state_vector())
+        };
+        let members = room.members.entry(msg.user_id.clone()).or_default();
+        if !members.contains(&msg.addr) {
+            members.push(msg.addr.clone());
+        }
+        room.checked.insert(msg.user_id.clone(), Instant::now());
+        info!("User {} joined document {}", msg.user_id, msg.doc_id);
+        Self::send(
+            &msg.addr,
+            serde_json::json!({
+                "type": "doc_sync",
+                "doc_id": msg.doc_id,
+                "update": BASE64.encode(diff),
+                "state_vector": BASE64.encode(yrs::updates::encoder::Encode::encode_v1(&server_sv)),
+            }),
+        );
+    }
+}
+
+impl Handler<JoinDoc> for DocServer {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, msg: JoinDoc, _: &mut Context<Self>) -> Self::Result {
+        let db = self.db.clone();
+        let doc_id = msg.doc_id.clone();
+        let user_id = msg.user_id.clone();
+        let loaded = self.rooms.contains_key(&msg.doc_id);
+        *self.loading.entry(msg.doc_id.clone()).or_default() += 1;
+        Box::pin(
+            async move {
+                // Same rule as the REST endpoints: team members who can read the document.
+                if !knowledge_base::can_access(&db, &doc_id, &user_id).await? {
+                    return Err("Document not found".to_string());
+                }
+                if loaded {
+                    return Ok(None);
+                }
+                load_doc(&db, &doc_id).await.map(Some)
+            }
+                .into_actor(self)
+                .map(move |res, act, _| {
+                    if let Some(n) = act.loading.get_mut(&msg.doc_id) {
+                        *n -= 1;
+                        if *n == 0 {
+                            act.loading.remove(&msg.doc_id);
+                        }
+                    }
+                    match res {
+                        Ok(doc) => {
+                            // Another join may have loaded the room while we were waiting.
+                            if let Some(doc) = doc {
+                                act.rooms.entry(msg.doc_id.clone()).or_insert_with(|| Room::new(doc));
+                            }
+                            act.join_loaded(msg);
+                        }
+                        Err(e) => {
+                            error!("Error loading document {}: {}", msg.doc_id, e);
+                            Self::send_error(&msg.addr, &msg.doc_id, &e);
+                        }
+                    }
+                }),
+        )
+    }
+}
+
+impl Handler<DocUpdate> for DocServer {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, msg: DocUpdate, _: &mut Context<Self>) -> Self::Result {
+        let fresh = self
+            .rooms
+            .get(&msg.doc_id)
+            .and_then(|r| r.checked.get(&msg.user_id))
+            .is_some_and(|at| at.elapsed() < ACCESS_RECHECK);
+        if fresh {
+            self.apply_update(msg);
+            return Box::pin(fut::ready(()));
+        }
+        // Updates are CRDT operations, so applying this one after later ones is fine.
+        let db = self.db.clone();
+        let doc_id = msg.doc_id.clone();
+        let user_id = msg.user_id.clone();
+        Box::pin(
+            async move { knowledge_base::can_access(&db, &doc_id, &user_id).await }
+                .into_actor(self)
+                .map(move |res, act, _| match res {
+                    Ok(true) => {
+                        if let Some(room) = act.rooms.get_mut(&msg.doc_id) {
+                            room.checked.insert(msg.user_id.clone(), Instant::now());
+                        }
+                        act.apply_update(msg);
+                    }
+                    Ok(false) => act.evict(&msg.doc_id, &msg.user_id),
+                    Err(e) => {
+                        error!("Error checking access to document {}: {}", msg.doc_id, e);
+                        Self::send_error(&msg.addr, &msg.doc_id, "Update not applied, please retry");
+                    }
+                }),
+        )
+    }
+}
+
+impl Handler<LeaveDoc> for DocServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: LeaveDoc, _: &mut Context<Self>) {
+        for (doc_id, room) in self.rooms.iter_mut() {
+            if msg.doc_id.as_ref().is_some_and(|d| d != doc_id) {
+                continue;
+            }
+            if let Some(addrs) = room.members.get_mut(&msg.user_id) {
+                addrs.retain(|a| a != &msg.addr);
+                if addrs.is_empty() {
+                    room.members.remove(&msg.user_id);
+                    room.checked.remove(&msg.user_id);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DocRoomStats {
+    pub doc_id: String,
+    pub members: usize,
+}
+
+/// Snapshot of the open editing rooms, for admin inspection.
+#[derive(Message)]
+#[rtype(result = "Vec<DocRoomStats>")]
+pub struct GetDocRooms;
+
+impl Handler<GetDocRooms> for DocServer {
+    type Result = MessageResult<GetDocRooms>;
+
+    fn handle(&mut self, _: GetDocRooms, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(
+            self.rooms
+                .iter()
+                .map(|(doc_id, room)| DocRoomStats { doc_id: doc_id.clone(), members: room.members.len() })
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,615 @@
+// src/email_ingest.rs
+//
+// Email-to-ticket channel. A project may configure an inbound channel; the mail
+// provider forwards every received message, already parsed to JSON, to the
+// channel's secret webhook URL. New conversations become tickets on the channel's
+// board, replies (matched through In-Reply-To / References) become comments on the
+// ticket they answer. Each Message-ID is processed once per channel, so provider
+// retries are harmless.
+//
+// The From header is only believed when the provider reports that the message passed
+// DKIM or SPF. Otherwise the sender is recorded as the requester but the ticket or
+// comment is filed by the channel's creator, as for senders outside the team.
+
+use std::sync::OnceLock;
+
+use actix_web::{web, HttpResponse, Responder};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use futures_util::io::AsyncWriteExt;
+use log::{error, info, warn};
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::activity::{record_activity, ActivityEvent};
+use crate::api::V1_PREFIX;
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::find_board;
+use crate::chat_db::MongoDB;
+use crate::encryption::{lookup_hash, EncryptedString};
+use crate::response::{ok, ok_message};
+use crate::ticket::{Ticket, TicketComment};
+use crate::ticket_assignment::auto_assignee;
+use crate::tenancy::{ProjectScope, Repo};
+use crate::ticket_events::{commit, CommitError, TicketChange, MAX_ATTEMPTS};
+
+/// Longest subject kept as a ticket title.
+const MAX_TITLE_CHARS: usize = 200;
+/// Longest body kept as a description or comment.
+const MAX_BODY_CHARS: usize = 64 * 1024;
+const MAX_ATTACHMENTS: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailChannel {
+    pub channel_id: String,
+    pub team_id: String,
+    pub project_id: String,
+    /// Board new tickets are created on
+    pub board_id: String,
+    /// Secret part of the webhook URL
+    pub token: EncryptedString,
+    /// `lookup_hash` of the token, for finding the channel by its webhook URL
+    #[serde(default)]
+    pub token_hash: String,
+    /// Reporter and comment author when the sender is not a team member
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmailChannelView {
+    pub channel_id: String,
+    pub team_id: String,
+    pub project_id: String,
+    pub board_id: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    /// Where the mail provider should POST inbound messages
+    pub webhook_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfigureChannelRequest {
+    pub board_id: String,
+    /// Issue a new webhook token, invalidating the old URL
+    #[serde(default)]
+    pub rotate_token: bool,
+}
+
+/// A message as forwarded by the mail provider.
+#[derive(Debug, Deserialize)]
+pub struct InboundEmail {
+    pub message_id: String,
+    pub in_reply_to: Option<String>,
+    /// Raw References header: whitespace-separated Message-IDs
+    pub references: Option<String>,
+    /// "Name <address>" or a bare address
+    pub from: String,
+    /// The provider's SPF verdict for the message, e.g. "pass"
+    pub spf: Option<String>,
+    /// The provider's DKIM verdict for the From domain, e.g. "pass"
+    pub dkim: Option<String>,
+    #[serde(default)]
+    pub subject: String,
+    pub text: Option<String>,
+    pub html: Option<String>,
+    #[serde(default)]
+    pub attachments: Vec<InboundAttachment>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InboundAttachment {
+    pub filename: String,
+    pub content_type: Option<String>,
+    /// Base64-encoded file content
+    pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IngestResult {
+    pub ticket_id: String,
+    /// Set when the message was threaded into an existing ticket
+    pub comment_id: Option<String>,
+    pub duplicate: bool,
+}
+
+fn channels(db: &MongoDB) -> mongodb::Collection<EmailChannel> {
+    db.db.collection::<EmailChannel>("email_channels")
+}
+
+fn inbound(db: &MongoDB) -> mongodb::Collection<Document> {
+    db.db.collection::<Document>("inbound_emails")
+}
+
+fn view(data: &AppState, channel: EmailChannel) -> EmailChannelView {
+    EmailChannelView {
+        webhook_url: format!("{}{}/inbound/email/{}", data.config.app_base_url, V1_PREFIX, channel.token.expose()),
+        channel_id: channel.channel_id,
+        team_id: channel.team_id,
+        project_id: channel.project_id,
+        board_id: channel.board_id,
+        created_by: channel.created_by,
+        created_at: channel.created_at,
+    }
+}
+
+async fn can_manage(auth: &AuthContext, team_id: &str, project_id: &str) -> bool {
+    auth.is_team_admin(team_id).await || auth.is_project_owner(project_id).await
+}
+
+fn normalize_message_id(id: &str) -> String {
+    id.trim().trim_start_matches('<').trim_end_matches('>').to_string()
+}
+
+/// Lower-cased address from a From header.
+fn sender_address(from: &str) -> Option<String> {
+    let addr = match (from.rfind('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &from[start + 1..end],
+        _ => from,
+    };
+    let addr = addr.trim().to_lowercase();
+    addr.contains('@').then_some(addr)
+}
+
+fn truncate(s: &str, max: usize) -> String {
+    s.chars().take(max).collect()
+}
+
+/// Plain-text body; HTML-only messages have their tags stripped.
+fn body_text(email: &InboundEmail) -> String {
+    if let Some(text) = email.text.as_deref().filter(|t| !t.trim().is_empty()) {
+        return text.trim().to_string();
+    }
+    let html = email.html.as_deref().unwrap_or_default();
+    static TAGS: OnceLock<Regex> = OnceLock::new();
+    let tags = TAGS.get_or_init(|| Regex::new(r"(?s)<(script|style)[^>]*>.*?</(script|style)>|<[^>]*>").expect("valid tag regex"));
+    tags.replace_all(html, " ")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A reply without the quoted history the mail client appended.
+fn strip_quoted(body: &str) -> String {
+    body.lines()
+        .filter(|l| !l.trim_start().starts_with('>'))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Whether the provider vouches for the From address.
+fn sender_verified(email: &InboundEmail) -> bool {
+    let pass = |verdict: &Option<String>| verdict.as_deref().is_some_and(|v| v.trim().eq_ignore_ascii_case("pass"));
+    pass(&email.dkim) || pass(&email.spf)
+}
+
+/// The team member with this address, if any.
+async fn member_by_email(data: &AppState, team_id: &str, email: &str) -> Option<String> {
+    let user = data
+        .mongodb
+        .db
+        .collection::<Document>("users")
+        .find_one(doc! { "email": email })
+        .await
+        .ok()
+        .flatten()?;
+    let user_id = user.get_object_id("_id").ok()?.to_hex();
+    data.mongodb.check_user_team(&user_id, team_id).await.unwrap_or(false).then_some(user_id)
+}
+
+fn attachment_path(id: &ObjectId) -> String {
+    format!("{}/attachments/{}", V1_PREFIX, id.to_hex())
+}
+
+/// Removes stored attachments of a message that could not be filed.
+async fn discard_attachments(data: &AppState, ids: &[ObjectId]) {
+    let bucket = data.mongodb.db.gridfs_bucket(None);
+    for id in ids {
+        if let Err(e) = bucket.delete(Bson::ObjectId(*id)).await {
+            warn!("Error removing email attachment {}: {}", id.to_hex(), e);
+        }
+    }
+}
+
+/// Store attachments in GridFS under the project; returns their file ids. Nothing is
+/// left behind when one of them fails.
+async fn store_attachments(
+    data: &AppState,
+    channel: &EmailChannel,
+    attachments: &[InboundAttachment],
+) -> Result<Vec<ObjectId>, String> {
+    let mut ids = Vec::with_capacity(attachments.len());
+    for a in attachments.iter().take(MAX_ATTACHMENTS) {
+        match store_attachment(data, channel, a).await {
+            Ok(Some(id)) => ids.push(id),
+            Ok(None) => {}
+            Err(e) => {
+                discard_attachments(data, &ids).await;
+                return Err(e);
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// Stores one attachment; None when it is skipped as unreadable or too large.
+async fn store_attachment(data: &AppState, channel: &EmailChannel, a: &InboundAttachment) -> Result<Option<ObjectId>, String> {
+    let Ok(bytes) = BASE64.decode(a.content.as_bytes()) else {
+        warn!("Skipping email attachment \"{}\": content is not valid base64", a.filename);
+        return Ok(None);
+    };
+    if bytes.len() > data.config.upload_limit {
+        warn!("Skipping oversized email attachment \"{}\" ({} bytes)", a.filename, bytes.len());
+        return Ok(None);
+    }
+    let content_type = a.content_type.clone().unwrap_or_else(|| "application/octet-stream".to_string());
+    let mut upload = data
+        .mongodb
+        .db
+        .gridfs_bucket(None)
+        .open_upload_stream(&a.filename)
+        .metadata(doc! { "project_id": &channel.project_id, "content_type": &content_type })
+        .await
+        .map_err(|e| e.to_string())?;
+    let id = upload.id().as_object_id().ok_or("GridFS returned a non-ObjectId id")?;
+    if let Err(e) = upload.write_all(&bytes).await {
+        let _ = upload.abort().await;
+        return Err(e.to_string());
+    }
+    upload.close().await.map_err(|e| e.to_string())?;
+    Ok(Some(id))
+}
+
+/// The ticket an earlier message of this thread was filed under, if it still exists.
+async fn thread_ticket(data: &AppState, scope: &ProjectScope, channel: &EmailChannel, email: &InboundEmail) -> mongodb::error::Result<Option<Ticket>> {
+    let mut ids: Vec<String> = email
+        .references
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(normalize_message_id)
+        .collect();
+    ids.extend(email.in_reply_to.as_deref().map(normalize_message_id));
+    ids.retain(|id| !id.is_empty());
+    if ids.is_empty() {
+        return Ok(None);
+    }
+    let filter = doc! {
+        "channel_id": &channel.channel_id,
+        "message_id": { "$in": ids },
+        "ticket_id": { "$type": "string" },
+    };
+    let Some(earlier) = inbound(&data.mongodb).find_one(filter).await? else {
+        return Ok(None);
+    };
+    let ticket_id = earlier.get_str("ticket_id").unwrap_or_default();
+    Repo::<Ticket>::new(&data.mongodb, scope).find_one(doc! { "ticket_id": ticket_id }).await
+}
+
+async fn create_ticket(
+    data: &AppState,
+    scope: &ProjectScope,
+    channel: &EmailChannel,
+    email: &InboundEmail,
+    sender: &str,
+    author: &str,
+    attachments: Vec<String>,
+) -> Result<Ticket, String> {
+    let subject = email.subject.trim();
+    let title = if subject.is_empty() { format!("Email from {}", sender) } else { truncate(subject, MAX_TITLE_CHARS) };
+    let body = truncate(&body_text(email), MAX_BODY_CHARS);
+    let assignee = auto_assignee(data, scope, &channel.board_id, &[]).await;
+
+    let ticket = Ticket {
+        id: None,
+        ticket_id: Uuid::new_v4().to_string(),
+        board_id: channel.board_id.clone(),
+        project_id: channel.project_id.clone(),
+        title,
+        description: (!body.is_empty()).then_some(body),
+        status: "To Do".to_string(),
+        priority: None,
+        reporter: author.to_string(),
+        requester_email: Some(sender.to_string()),
+        assignee,
+        due_date: None,
+        ticket_type: None,
+        sprint: None,
+        rank: None,
+        labels: None,
+        fix_version: None,
+        estimate_hours: None,
+        blocked_by: Vec::new(),
+        attachments: (!attachments.is_empty()).then_some(attachments),
+        comments: Some(vec![]),
+        references: Vec::new(),
+        created_at: Utc::now(),
+        resolution: None,
+        resolved_at: None,
+        resolved_by: None,
+        vote_count: 0,
+        version: 0,
+    };
+    match commit(&data.mongodb, None, vec![TicketChange::Created { ticket: Box::new(ticket) }], author).await {
+        Ok(Some(ticket)) => {
+            record_activity(&data.mongodb, ActivityEvent::new(
+                &channel.team_id,
+                Some(&channel.project_id),
+                author,
+                "ticket_created",
+                &ticket.ticket_id,
+                format!("created ticket \"{}\" from an email by {}", ticket.title, sender),
+            )).await;
+            Ok(ticket)
+        }
+        Ok(None) | Err(CommitError::Conflict) => Err("Ticket already exists".to_string()),
+        Err(CommitError::Invalid(msg)) => Err(msg),
+        Err(CommitError::Db(e)) => Err(e.to_string()),
+    }
+}
+
+async fn add_reply(
+    data: &AppState,
+    scope: &ProjectScope,
+    ticket: Ticket,
+    email: &InboundEmail,
+    sender: &str,
+    author: &str,
+    attachments: Vec<String>,
+) -> Result<TicketComment, String> {
+    let body = truncate(&strip_quoted(&body_text(email)), MAX_BODY_CHARS);
+    let comment = TicketComment {
+        comment_id: Uuid::new_v4().to_string(),
+        author_id: author.to_string(),
+        content: format!("Email from {}:\n\n{}", sender, body),
+        timestamp: Utc::now(),
+        reactions: Vec::new(),
+        resolved: false,
+        resolved_by: None,
+    };
+
+    let tickets_coll = Repo::<Ticket>::new(&data.mongodb, scope);
+    let filter = doc! { "ticket_id": &ticket.ticket_id };
+    let mut current = ticket;
+    for _ in 0..MAX_ATTEMPTS {
+        let mut changes = vec![TicketChange::Commented { comment: comment.clone() }];
+        if !attachments.is_empty() {
+            let mut all = current.attachments.clone().unwrap_or_default();
+            all.extend(attachments.iter().cloned());
+            changes.extend(TicketChange::field(&current, "attachments", &all));
+        }
+        match commit(&data.mongodb, Some(&current), changes, author).await {
+            Ok(_) => return Ok(comment),
+            Err(CommitError::Conflict) => {}
+            Err(CommitError::Invalid(msg)) => return Err(msg),
+            Err(CommitError::Db(e)) => return Err(e.to_string()),
+        }
+        current = match tickets_coll.find_one(filter.clone()).await {
+            Ok(Some(t)) => t,
+            Ok(None) => return Err("Ticket was deleted".to_string()),
+            Err(e) => return Err(e.to_string()),
+        };
+    }
+    Err("Ticket was modified concurrently".to_string())
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(&*e.kind, ErrorKind::Write(WriteFailure::WriteError(w)) if w.code == 11000)
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/email-channel
+pub async fn get_email_channel(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (team_id, project_id) = path.into_inner();
+    if !can_manage(&auth, &team_id, &project_id).await {
+        return HttpResponse::Forbidden().body("Only team admins and project owners can manage the email channel");
+    }
+    match channels(&data.mongodb).find_one(doc! { "team_id": &team_id, "project_id": &project_id }).await {
+        Ok(Some(channel)) => ok(view(&data, channel)),
+        Ok(None) => HttpResponse::NotFound().body("No email channel configured"),
+        Err(e) => {
+            error!("Error fetching email channel: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching email channel")
+        }
+    }
+}
+
+/// PUT /teams/{team_id}/projects/{project_id}/email-channel
+/// Creates the channel, or changes its board (and optionally its token).
+pub async fn configure_email_channel(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<ConfigureChannelRequest>,
+) -> impl Responder {
+    let (team_id, project_id) = path.into_inner();
+    if !can_manage(&auth, &team_id, &project_id).await {
+        return HttpResponse::Forbidden().body("Only team admins and project owners can manage the email channel");
+    }
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    if let Err(resp) = find_board(&data, &scope, &payload.board_id).await {
+        return resp;
+    }
+
+    let coll = channels(&data.mongodb);
+    let filter = doc! { "team_id": &team_id, "project_id": &project_id };
+    let existing = match coll.find_one(filter.clone()).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching email channel: {}", e);
+            return HttpResponse::InternalServerError().body("Error saving email channel");
+        }
+    };
+    let channel = match existing {
+        Some(mut c) => {
+            c.board_id = payload.board_id.clone();
+            if payload.rotate_token {
+                c.token = EncryptedString::new(Uuid::new_v4().simple().to_string());
+            }
+            // Channels saved before tokens were hashed get their hash here.
+            c.token_hash = lookup_hash(c.token.expose());
+            c
+        }
+        None => {
+            let token = Uuid::new_v4().simple().to_string();
+            EmailChannel {
+                channel_id: Uuid::new_v4().to_string(),
+                team_id,
+                project_id,
+                board_id: payload.board_id.clone(),
+                token_hash: lookup_hash(&token),
+                token: EncryptedString::new(token),
+                created_by: auth.user_id().to_string(),
+                created_at: Utc::now(),
+            }
+        }
+    };
+    match coll.replace_one(filter, &channel).upsert(true).await {
+        Ok(_) => ok(view(&data, channel)),
+        Err(e) => {
+            error!("Error saving email channel: {}", e);
+            HttpResponse::InternalServerError().body("Error saving email channel")
+        }
+    }
+}
+
+/// DELETE /teams/{team_id}/projects/{project_id}/email-channel
+pub async fn delete_email_channel(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (team_id, project_id) = path.into_inner();
+    if !can_manage(&auth, &team_id, &project_id).await {
+        return HttpResponse::Forbidden().body("Only team admins and project owners can manage the email channel");
+    }
+    match channels(&data.mongodb).delete_one(doc! { "team_id": &team_id, "project_id": &project_id }).await {
+        Ok(res) if res.deleted_count == 1 => ok_message("Email channel removed"),
+        Ok(_) => HttpResponse::NotFound().body("No email channel configured"),
+        Err(e) => {
+            error!("Error deleting email channel: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting email channel")
+        }
+    }
+}
+
+/// POST /inbound/email/{token}
+/// Called by the mail provider; the token in the path is the only credential.
+pub async fn receive_email(
+    data: web::Data<AppState>,
+    token: web::Path<String>,
+    payload: web::Json<InboundEmail>,
+) -> impl Responder {
+    // Tokens of channels saved before encryption are still matched in plaintext.
+    let filter = doc! { "$or": [{ "token_hash": lookup_hash(&token) }, { "token": token.as_str() }] };
+    let channel = match channels(&data.mongodb).find_one(filter).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return HttpResponse::NotFound().body("Unknown email channel"),
+        Err(e) => {
+            error!("Error fetching email channel: {}", e);
+            return HttpResponse::InternalServerError().body("Error processing email");
+        }
+    };
+    // The team comes from the channel's project, not from the channel itself.
+    let scope = match ProjectScope::of_project(&data.mongodb, &channel.project_id).await {
+        Some(scope) if scope.team_id() == channel.team_id => scope,
+        _ => return HttpResponse::NotFound().body("Unknown email channel"),
+    };
+    let email = payload.into_inner();
+    let message_id = normalize_message_id(&email.message_id);
+    if message_id.is_empty() {
+        return HttpResponse::BadRequest().body("message_id is required");
+    }
+    let Some(sender) = sender_address(&email.from) else {
+        return HttpResponse::BadRequest().body("from must contain an email address");
+    };
+
+    // Claim the Message-ID first; the unique index turns provider retries into no-ops.
+    let claim = doc! {
+        "channel_id": &channel.channel_id,
+        "message_id": &message_id,
+        "from": &sender,
+        "received_at": Utc::now().to_rfc3339(),
+    };
+    if let Err(e) = inbound(&data.mongodb).insert_one(claim).await {
+        if !is_duplicate_key(&e) {
+            error!("Error recording inbound email: {}", e);
+            return HttpResponse::InternalServerError().body("Error processing email");
+        }
+        let earlier = inbound(&data.mongodb)
+            .find_one(doc! { "channel_id": &channel.channel_id, "message_id": &message_id })
+            .await
+            .ok()
+            .flatten();
+        return ok(IngestResult {
+            ticket_id: earlier.as_ref().and_then(|d| d.get_str("ticket_id").ok()).unwrap_or_default().to_string(),
+            comment_id: earlier.as_ref().and_then(|d| d.get_str("comment_id").ok()).map(String::from),
+            duplicate: true,
+        });
+    }
+    let claim_filter = doc! { "channel_id": &channel.channel_id, "message_id": &message_id };
+
+    let member = if sender_verified(&email) { member_by_email(&data, &channel.team_id, &sender).await } else { None };
+    let author = member.unwrap_or_else(|| channel.created_by.clone());
+    let stored = match store_attachments(&data, &channel, &email.attachments).await {
+        Ok(ids) => ids,
+        Err(msg) => {
+            let _ = inbound(&data.mongodb).delete_one(claim_filter).await;
+            error!("Error storing attachments of inbound email {}: {}", message_id, msg);
+            return HttpResponse::InternalServerError().body("Error processing email");
+        }
+    };
+    let result = async {
+        let attachments = stored.iter().map(attachment_path).collect();
+        match thread_ticket(&data, &scope, &channel, &email).await.map_err(|e| e.to_string())? {
+            Some(ticket) => {
+                let ticket_id = ticket.ticket_id.clone();
+                let comment = add_reply(&data, &scope, ticket, &email, &sender, &author, attachments).await?;
+                Ok(IngestResult { ticket_id, comment_id: Some(comment.comment_id), duplicate: false })
+            }
+            None => {
+                let ticket = create_ticket(&data, &scope, &channel, &email, &sender, &author, attachments).await?;
+                Ok::<_, String>(IngestResult { ticket_id: ticket.ticket_id, comment_id: None, duplicate: false })
+            }
+        }
+    }
+    .await;
+
+    match result {
+        Ok(result) => {
+            let update = doc! { "$set": { "ticket_id": &result.ticket_id, "comment_id": &result.comment_id } };
+            if let Err(e) = inbound(&data.mongodb).update_one(claim_filter, update).await {
+                warn!("Error linking inbound email {} to its ticket: {}", message_id, e);
+            }
+            info!("Inbound email {} filed under ticket {}", message_id, result.ticket_id);
+            ok(result)
+        }
+        Err(msg) => {
+            // Release the claim so the provider's retry can try again, and drop the
+            // attachments it will upload again.
+            let _ = inbound(&data.mongodb).delete_one(claim_filter).await;
+            discard_attachments(&data, &stored).await;
+            error!("Error processing inbound email {}: {}", message_id, msg);
+            HttpResponse::InternalServerError().body("Error processing email")
+        }
+    }
+}
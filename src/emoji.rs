@@ -0,0 +1,115 @@
+// src/emoji.rs
+//
+// Server-side expansion of `:shortcode:` emoji in chat messages, so every client
+// (and every notification, email or export built from the stored text) shows the
+// same thing. Unknown shortcodes, which include team custom emoji (custom_emoji.rs),
+// and anything inside `code` spans are left as typed.
+
+/// Supported shortcodes, without the surrounding colons.
+const SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("alarm_clock", "⏰"),
+    ("angry", "😠"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("calendar", "📅"),
+    ("clap", "👏"),
+    ("coffee", "☕"),
+    ("confused", "😕"),
+    ("construction", "🚧"),
+    ("cry", "😢"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("heart", "❤️"),
+    ("heavy_check_mark", "✔️"),
+    ("hourglass", "⌛"),
+    ("hugs", "🤗"),
+    ("joy", "😂"),
+    ("laughing", "😆"),
+    ("lock", "🔒"),
+    ("mag", "🔍"),
+    ("memo", "📝"),
+    ("muscle", "💪"),
+    ("no_entry", "⛔"),
+    ("ok_hand", "👌"),
+    ("party", "🥳"),
+    ("pensive", "😔"),
+    ("pin", "📌"),
+    ("pray", "🙏"),
+    ("question", "❓"),
+    ("raised_hands", "🙌"),
+    ("rocket", "🚀"),
+    ("rotating_light", "🚨"),
+    ("scream", "😱"),
+    ("see_no_evil", "🙈"),
+    ("shrug", "🤷"),
+    ("slightly_smiling_face", "🙂"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("sob", "😭"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("sunglasses", "😎"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("x", "❌"),
+    ("zap", "⚡"),
+];
+
+fn lookup(code: &str) -> Option<&'static str> {
+    SHORTCODES.iter().find(|(name, _)| *name == code).map(|(_, emoji)| *emoji)
+}
+
+pub fn is_builtin_shortcode(code: &str) -> bool {
+    lookup(code).is_some()
+}
+
+/// Expand known `:shortcodes:` outside of backtick code spans.
+pub fn expand_shortcodes(text: &str) -> String {
+    if !text.contains(':') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    // Alternate segments between backticks are code and kept verbatim.
+    for (n, segment) in text.split('`').enumerate() {
+        if n > 0 {
+            out.push('`');
+        }
+        if n % 2 == 1 {
+            out.push_str(segment);
+        } else {
+            expand_segment(segment, &mut out);
+        }
+    }
+    out
+}
+
+fn expand_segment(segment: &str, out: &mut String) {
+    let mut rest = segment;
+    while let Some(start) = rest.find(':') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '+' || c == '-'));
+        match end.filter(|&e| e > 0 && after[e..].starts_with(':')).and_then(|e| lookup(&after[..e]).map(|emoji| (e, emoji))) {
+            Some((e, emoji)) => {
+                out.push_str(emoji);
+                rest = &after[e + 1..];
+            }
+            None => {
+                out.push(':');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+}
@@ -0,0 +1,256 @@
+// src/encryption.rs
+//
+// Application-level encryption for sensitive stored values. Fields typed as
+// `EncryptedString` are sealed with AES-256-GCM when serialized and opened again
+// when deserialized, so models use them like plain strings while MongoDB only sees
+// ciphertext.
+//
+// Keys come from ENCRYPTION_KEYS as comma-separated `key_id:base64-key` pairs; the
+// first one encrypts, all of them decrypt. To rotate, put a new key first, keep the
+// old ones, run POST /admin/encryption/rotate, then drop the old keys. Values stored
+// before encryption was enabled are read as plaintext and sealed on the next write
+// or rotation; rotation also writes the lookup hash of values found by hash, and
+// moves email addresses out of old invitations' `invitee_id` into `invitee_email`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use mongodb::bson::{doc, Bson, Document};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::admin::is_platform_admin;
+use crate::app_state::AppState;
+use crate::response::CodedError;
+
+const PREFIX: &str = "enc:";
+
+/// Stored fields holding `EncryptedString`s, as (collection, field, field holding
+/// the value's `lookup_hash`).
+pub const ENCRYPTED_FIELDS: [(&str, &str, Option<&str>); 4] = [
+    ("email_channels", "token", Some("token_hash")),
+    ("sso_connections", "client_secret", None),
+    ("notification_channels", "url", None),
+    ("team_invitations", "invitee_email", None),
+];
+
+struct Keyring {
+    active: String,
+    keys: HashMap<String, LessSafeKey>,
+    rng: SystemRandom,
+}
+
+static KEYRING: OnceLock<Option<Keyring>> = OnceLock::new();
+
+fn parse_keys(spec: &str) -> Result<Keyring, String> {
+    let mut active = None;
+    let mut keys = HashMap::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (id, key) = entry.split_once(':').ok_or_else(|| format!("Key entry '{}' must be key_id:base64-key", entry))?;
+        if id.is_empty() || id.contains(':') {
+            return Err(format!("Invalid key id '{}'", id));
+        }
+        let bytes = BASE64.decode(key.trim()).map_err(|_| format!("Key '{}' is not valid base64", id))?;
+        let unbound = UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| format!("Key '{}' must be 32 bytes", id))?;
+        if keys.insert(id.to_string(), LessSafeKey::new(unbound)).is_some() {
+            return Err(format!("Duplicate key id '{}'", id));
+        }
+        active.get_or_insert_with(|| id.to_string());
+    }
+    let active = active.ok_or("ENCRYPTION_KEYS contains no keys")?;
+    Ok(Keyring { active, keys, rng: SystemRandom::new() })
+}
+
+/// Load the keyring from the configured key list. Without keys, values are stored
+/// in plaintext.
+pub fn init(spec: Option<&str>) -> Result<(), String> {
+    let keyring = spec.map(parse_keys).transpose()?;
+    match &keyring {
+        Some(k) => info!("Encryption at rest enabled with key '{}' ({} keys loaded)", k.active, k.keys.len()),
+        None => warn!("ENCRYPTION_KEYS is not set; sensitive fields are stored unencrypted"),
+    }
+    KEYRING.set(keyring).map_err(|_| "Encryption keys already initialised".to_string())
+}
+
+fn keyring() -> Option<&'static Keyring> {
+    KEYRING.get().and_then(Option::as_ref)
+}
+
+fn seal(plain: &str) -> Result<String, String> {
+    let Some(ring) = keyring() else {
+        return Ok(plain.to_string());
+    };
+    let key = &ring.keys[&ring.active];
+    let mut nonce = [0u8; NONCE_LEN];
+    ring.rng.fill(&mut nonce).map_err(|_| "Could not generate a nonce".to_string())?;
+    let mut data = plain.as_bytes().to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(ring.active.as_bytes()), &mut data)
+        .map_err(|_| "Encryption failed".to_string())?;
+    let mut out = nonce.to_vec();
+    out.extend(data);
+    Ok(format!("{}{}:{}", PREFIX, ring.active, BASE64.encode(out)))
+}
+
+fn open(stored: &str) -> Result<String, String> {
+    let Some(sealed) = stored.strip_prefix(PREFIX) else {
+        // Written before encryption was enabled.
+        return Ok(stored.to_string());
+    };
+    let (key_id, payload) = sealed.split_once(':').ok_or("Malformed encrypted value")?;
+    let key = keyring()
+        .and_then(|r| r.keys.get(key_id))
+        .ok_or_else(|| format!("Encryption key '{}' is not configured", key_id))?;
+    let mut data = BASE64.decode(payload).map_err(|_| "Malformed encrypted value".to_string())?;
+    if data.len() < NONCE_LEN {
+        return Err("Malformed encrypted value".to_string());
+    }
+    let mut sealed = data.split_off(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(&data).map_err(|_| "Malformed encrypted value".to_string())?;
+    let plain = key
+        .open_in_place(nonce, Aad::from(key_id.as_bytes()), &mut sealed)
+        .map_err(|_| format!("Could not decrypt value with key '{}'", key_id))?;
+    String::from_utf8(plain.to_vec()).map_err(|_| "Decrypted value is not UTF-8".to_string())
+}
+
+/// Whether a stored value is plaintext or sealed with a key other than the active one.
+fn needs_rotation(stored: &str) -> bool {
+    match keyring() {
+        Some(ring) => !stored.starts_with(&format!("{}{}:", PREFIX, ring.active)),
+        None => false,
+    }
+}
+
+/// Hex SHA-256 of a secret, for looking up records by a value that is stored encrypted.
+pub fn lookup_hash(secret: &str) -> String {
+    digest(&SHA256, secret.as_bytes()).as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A string encrypted at rest. Holds the plaintext in memory; `Debug` never prints it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptedString(String);
+
+impl EncryptedString {
+    pub fn new(plain: impl Into<String>) -> Self {
+        EncryptedString(plain.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for EncryptedString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptedString(..)")
+    }
+}
+
+impl Serialize for EncryptedString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let sealed = seal(&self.0).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&sealed)
+    }
+}
+
+impl<'de> Deserialize<'de> for EncryptedString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = String::deserialize(deserializer)?;
+        open(&stored).map(EncryptedString).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct RotationReport {
+    pub rewritten: u64,
+    pub failed: u64,
+}
+
+/// Invitations made before invitees were resolved to accounts hold the address in
+/// `invitee_id`; it moves, sealed, to `invitee_email` and `invitee_id` keeps its hash.
+async fn seal_invitation_emails(data: &AppState, report: &mut RotationReport) -> mongodb::error::Result<()> {
+    let coll = data.mongodb.db.collection::<Document>("team_invitations");
+    let filter = doc! { "invitee_id": { "$regex": "@" }, "invitee_email": { "$exists": false } };
+    let mut cursor = coll.find(filter).await?;
+    while let Some(d) = cursor.next().await {
+        let d = d?;
+        let (Some(id), Ok(email)) = (d.get("_id").cloned(), d.get_str("invitee_id")) else {
+            continue;
+        };
+        match seal(email) {
+            Ok(value) => {
+                let update = doc! { "$set": { "invitee_email": value, "invitee_id": lookup_hash(email) } };
+                coll.update_one(doc! { "_id": id, "invitee_id": email }, update).await?;
+                report.rewritten += 1;
+            }
+            Err(e) => {
+                error!("Cannot encrypt the email of invitation {}: {}", id, e);
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Re-seal every stored value that is plaintext or uses an old key.
+async fn rotate(data: &AppState) -> mongodb::error::Result<RotationReport> {
+    let mut report = RotationReport::default();
+    seal_invitation_emails(data, &mut report).await?;
+    for (collection, field, hash_field) in ENCRYPTED_FIELDS {
+        let coll = data.mongodb.db.collection::<Document>(collection);
+        let mut cursor = coll.find(doc! { field: { "$type": "string" } }).await?;
+        while let Some(d) = cursor.next().await {
+            let d = d?;
+            let (Some(id), Ok(stored)) = (d.get("_id").cloned(), d.get_str(field)) else {
+                continue;
+            };
+            if !needs_rotation(stored) {
+                continue;
+            }
+            let resealed = open(stored).and_then(|plain| Ok((seal(&plain)?, lookup_hash(&plain))));
+            match resealed {
+                Ok((value, hash)) => {
+                    let mut set = doc! { field: Bson::String(value) };
+                    // Plaintext values were also matched directly; sealed ones only by hash.
+                    if let Some(hash_field) = hash_field {
+                        set.insert(hash_field, hash);
+                    }
+                    coll.update_one(doc! { "_id": id, field: stored }, doc! { "$set": set }).await?;
+                    report.rewritten += 1;
+                }
+                Err(e) => {
+                    error!("Cannot re-encrypt {}.{} of {}: {}", collection, field, id, e);
+                    report.failed += 1;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// POST /admin/encryption/rotate
+pub async fn rotate_encryption(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    if keyring().is_none() {
+        return HttpResponse::BadRequest().body("Encryption at rest is not enabled");
+    }
+    match rotate(&data).await {
+        Ok(report) => {
+            info!("Encryption rotation: {} values rewritten, {} failed", report.rewritten, report.failed);
+            HttpResponse::Ok().json(report)
+        }
+        Err(e) => {
+            error!("Error rotating encryption keys: {}", e);
+            HttpResponse::InternalServerError().body("Error rotating encryption keys")
+        }
+    }
+}
@@ -0,0 +1,509 @@
+// src/escalation.rs
+//
+// Deadline escalation policies. Team admins define rules ("overdue High tickets
+// unassigned for 24h"); a background job evaluates them and, for every matching
+// ticket, notifies the project owners and/or assignee and can bump the priority.
+// Each rule fires at most once per ticket; what fired is kept in `escalations`.
+
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use futures_util::StreamExt;
+use log::{error, info};
+use mongodb::bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::activity::{record_activity, ActivityEvent};
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::do_not_disturb::notify_user;
+use crate::mailer::send_email;
+use crate::response::CodedError;
+use crate::tenancy::{ProjectsScope, Repo};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+use crate::ticket_events::{commit, TicketChange, TicketEvent};
+
+const EVALUATION_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Upper bound on unassigned_for_hours; a year.
+const MAX_UNASSIGNED_HOURS: i64 = 24 * 365;
+const DEFAULT_LOG_LIMIT: i64 = 100;
+const MAX_LOG_LIMIT: i64 = 500;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EscalationConditions {
+    /// Only tickets with one of these priorities; empty matches any
+    #[serde(default)]
+    pub priorities: Vec<String>,
+    /// Only open tickets in one of these statuses; empty matches any open status
+    #[serde(default)]
+    pub statuses: Vec<String>,
+    /// Only tickets past their due date
+    #[serde(default)]
+    pub overdue: bool,
+    /// Only tickets that have had no assignee for at least this many hours
+    pub unassigned_for_hours: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EscalationActions {
+    #[serde(default)]
+    pub notify_project_owner: bool,
+    #[serde(default)]
+    pub notify_assignee: bool,
+    /// Raise the priority one step (Low → Medium → High → Critical)
+    #[serde(default)]
+    pub bump_priority: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EscalationRule {
+    pub rule_id: String,
+    pub team_id: String,
+    /// Restrict the rule to one project; all of the team's projects otherwise
+    pub project_id: Option<String>,
+    pub name: String,
+    pub enabled: bool,
+    pub conditions: EscalationConditions,
+    pub actions: EscalationActions,
+    pub created_by: String,
+    pub created_at: BsonDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Escalation {
+    pub escalation_id: String,
+    pub rule_id: String,
+    pub rule_name: String,
+    pub team_id: String,
+    pub project_id: String,
+    pub ticket_id: String,
+    pub ticket_title: String,
+    /// e.g. "notified_project_owner", "notified_assignee", "priority_bumped"
+    pub actions: Vec<String>,
+    pub fired_at: BsonDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRuleRequest {
+    pub name: String,
+    pub project_id: Option<String>,
+    pub enabled: Option<bool>,
+    pub conditions: EscalationConditions,
+    pub actions: EscalationActions,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateRuleRequest {
+    pub name: Option<String>,
+    pub enabled: Option<bool>,
+    pub conditions: Option<EscalationConditions>,
+    pub actions: Option<EscalationActions>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EscalationLogQuery {
+    pub rule_id: Option<String>,
+    pub ticket_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+fn rules_coll(data: &AppState) -> mongodb::Collection<EscalationRule> {
+    data.mongodb.db.collection::<EscalationRule>("escalation_rules")
+}
+
+fn log_coll(data: &AppState) -> mongodb::Collection<Escalation> {
+    data.mongodb.db.collection::<Escalation>("escalations")
+}
+
+fn validate_rule(conditions: &EscalationConditions, actions: &EscalationActions) -> Result<(), &'static str> {
+    if conditions.priorities.is_empty() && conditions.statuses.is_empty() && !conditions.overdue
+        && conditions.unassigned_for_hours.is_none()
+    {
+        return Err("A rule needs at least one condition");
+    }
+    if matches!(conditions.unassigned_for_hours, Some(h) if !(0..=MAX_UNASSIGNED_HOURS).contains(&h)) {
+        return Err("unassigned_for_hours must be between 0 and 8760");
+    }
+    if !actions.notify_project_owner && !actions.notify_assignee && !actions.bump_priority {
+        return Err("A rule needs at least one action");
+    }
+    Ok(())
+}
+
+/// The next priority up, or None when it cannot be raised further.
+fn bumped_priority(priority: Option<&str>) -> Option<&'static str> {
+    match priority.map(str::to_lowercase).as_deref() {
+        None | Some("low") => Some("Medium"),
+        Some("medium") | Some("normal") => Some("High"),
+        Some("high") => Some("Critical"),
+        _ => None,
+    }
+}
+
+/// GET /teams/{team_id}/escalations/rules
+pub async fn list_rules(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Unauthorized().body("Only team admins can manage escalation rules");
+    }
+    match rules_coll(&data).find(doc! { "team_id": &*team_id }).sort(doc! { "created_at": 1 }).await {
+        Ok(mut cursor) => {
+            let mut rules = Vec::new();
+            while let Some(Ok(r)) = cursor.next().await {
+                rules.push(r);
+            }
+            HttpResponse::Ok().json(rules)
+        }
+        Err(e) => {
+            error!("Error fetching escalation rules: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching escalation rules")
+        }
+    }
+}
+
+/// POST /teams/{team_id}/escalations/rules
+pub async fn create_rule(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<CreateRuleRequest>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Unauthorized().body("Only team admins can manage escalation rules");
+    }
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().body("Rule name is required");
+    }
+    if let Err(msg) = validate_rule(&payload.conditions, &payload.actions) {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    if let Some(project_id) = &payload.project_id {
+        if auth.project_scope(&team_id, project_id).await.is_err() {
+            return HttpResponse::BadRequest().body("project_id must be a project of this team");
+        }
+    }
+
+    let rule = EscalationRule {
+        rule_id: Uuid::new_v4().to_string(),
+        team_id: team_id.into_inner(),
+        project_id: payload.project_id.clone(),
+        name: name.to_string(),
+        enabled: payload.enabled.unwrap_or(true),
+        conditions: payload.conditions.clone(),
+        actions: payload.actions.clone(),
+        created_by: auth.user_id().to_string(),
+        created_at: BsonDateTime::now(),
+    };
+    match rules_coll(&data).insert_one(&rule).await {
+        Ok(_) => HttpResponse::Ok().json(rule),
+        Err(e) => {
+            error!("Error creating escalation rule: {}", e);
+            HttpResponse::InternalServerError().body("Error creating escalation rule")
+        }
+    }
+}
+
+/// PUT /teams/{team_id}/escalations/rules/{rule_id}
+pub async fn update_rule(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<UpdateRuleRequest>,
+) -> impl Responder {
+    let (team_id, rule_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Unauthorized().body("Only team admins can manage escalation rules");
+    }
+    let filter = doc! { "rule_id": &rule_id, "team_id": &team_id };
+    let mut rule = match rules_coll(&data).find_one(filter.clone()).await {
+        Ok(Some(r)) => r,
+        Ok(None) => return HttpResponse::NotFound().error("rule_not_found"),
+        Err(e) => {
+            error!("Error fetching escalation rule: {}", e);
+            return HttpResponse::InternalServerError().body("Error updating escalation rule");
+        }
+    };
+    if let Some(name) = payload.name.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+        rule.name = name.to_string();
+    }
+    if let Some(enabled) = payload.enabled {
+        rule.enabled = enabled;
+    }
+    if let Some(conditions) = &payload.conditions {
+        rule.conditions = conditions.clone();
+    }
+    if let Some(actions) = &payload.actions {
+        rule.actions = actions.clone();
+    }
+    if let Err(msg) = validate_rule(&rule.conditions, &rule.actions) {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    match rules_coll(&data).replace_one(filter, &rule).await {
+        Ok(_) => HttpResponse::Ok().json(rule),
+        Err(e) => {
+            error!("Error updating escalation rule: {}", e);
+            HttpResponse::InternalServerError().body("Error updating escalation rule")
+        }
+    }
+}
+
+/// DELETE /teams/{team_id}/escalations/rules/{rule_id}
+pub async fn delete_rule(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (team_id, rule_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Unauthorized().body("Only team admins can manage escalation rules");
+    }
+    match rules_coll(&data).delete_one(doc! { "rule_id": &rule_id, "team_id": &team_id }).await {
+        Ok(res) if res.deleted_count == 0 => HttpResponse::NotFound().error("rule_not_found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Error deleting escalation rule: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting escalation rule")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/escalations?rule_id=&ticket_id=&limit=
+///
+/// Admins see every escalation of the team, other members those of their projects.
+pub async fn list_escalations(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    query: web::Query<EscalationLogQuery>,
+) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    let is_admin = auth.is_team_admin(&team_id).await;
+    let mut filter = doc! { "team_id": &*team_id };
+    if let Some(rule_id) = &query.rule_id {
+        filter.insert("rule_id", rule_id);
+    }
+    if let Some(ticket_id) = &query.ticket_id {
+        filter.insert("ticket_id", ticket_id);
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LOG_LIMIT).clamp(1, MAX_LOG_LIMIT);
+
+    let mut cursor = match log_coll(&data).find(filter).sort(doc! { "fired_at": -1 }).limit(limit).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching escalations: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching escalations");
+        }
+    };
+    let mut out = Vec::new();
+    while let Some(Ok(entry)) = cursor.next().await {
+        if is_admin || auth.is_project_member(&entry.project_id).await {
+            out.push(entry);
+        }
+    }
+    HttpResponse::Ok().json(out)
+}
+
+/// When the ticket last lost (or was created without) its assignee.
+async fn unassigned_since(data: &AppState, ticket: &Ticket) -> chrono::DateTime<Utc> {
+    data.mongodb.db.collection::<TicketEvent>("ticket_events")
+        .find_one(doc! { "ticket_id": &ticket.ticket_id, "change.type": "field_changed", "change.field": "assignee" })
+        .sort(doc! { "seq": -1 })
+        .await
+        .ok()
+        .flatten()
+        .map_or(ticket.created_at, |e| e.at)
+}
+
+async fn project_owner_ids(data: &AppState, project_id: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    if let Ok(mut cursor) = data.mongodb.db.collection::<Document>("project_memberships")
+        .find(doc! { "project_id": project_id, "role": "owner" })
+        .await
+    {
+        while let Some(Ok(m)) = cursor.next().await {
+            if let Ok(uid) = m.get_str("user_id") {
+                out.push(uid.to_string());
+            }
+        }
+    }
+    out
+}
+
+async fn notify(data: &AppState, user_id: &str, subject: &str, text: &str, payload: &serde_json::Value) {
+    notify_user(data, user_id, payload.to_string()).await;
+    let email = match ObjectId::parse_str(user_id) {
+        Ok(oid) => data.mongodb.db.collection::<Document>("users")
+            .find_one(doc! { "_id": oid })
+            .await
+            .ok()
+            .flatten()
+            .and_then(|u| u.get_str("email").ok().map(String::from)),
+        Err(_) => None,
+    };
+    if let Some(email) = email {
+        let _ = send_email(data, &email, subject, text).await;
+    }
+}
+
+/// Tickets currently matching the rule, before the once-per-ticket check.
+async fn matching_tickets(data: &AppState, rule: &EscalationRule) -> mongodb::error::Result<Vec<Ticket>> {
+    let c = &rule.conditions;
+    let mut scope = ProjectsScope::of_team(&data.mongodb, &rule.team_id).await?;
+    if let Some(p) = &rule.project_id {
+        scope.retain(|id| id == p);
+    }
+    let mut filter = doc! { "status": { "$nin": CLOSED_STATUSES.to_vec() } };
+    if !c.statuses.is_empty() {
+        filter.insert("status", doc! { "$in": &c.statuses, "$nin": CLOSED_STATUSES.to_vec() });
+    }
+    if !c.priorities.is_empty() {
+        filter.insert("priority", doc! { "$in": &c.priorities });
+    }
+    if c.unassigned_for_hours.is_some() {
+        filter.insert("assignee", doc! { "$in": [null, ""] });
+    }
+
+    let now = Utc::now();
+    let mut out = Vec::new();
+    let mut cursor = Repo::<Ticket>::across(&data.mongodb, &scope).find(filter).await?;
+    while let Some(t) = cursor.next().await {
+        let t = t?;
+        if c.overdue && t.due_date.is_none_or(|d| d >= now) {
+            continue;
+        }
+        if let Some(hours) = c.unassigned_for_hours {
+            // Rules stored before the bound was enforced may still hold larger values;
+            // such a ticket never qualifies rather than overflowing the date.
+            let since = unassigned_since(data, &t).await;
+            let due = chrono::TimeDelta::try_hours(hours).and_then(|d| since.checked_add_signed(d));
+            if due.is_none_or(|due| due > now) {
+                continue;
+            }
+        }
+        out.push(t);
+    }
+    Ok(out)
+}
+
+async fn fire(data: &AppState, rule: &EscalationRule, ticket: &Ticket) {
+    let mut actions = Vec::new();
+    if rule.actions.notify_project_owner {
+        actions.push("notified_project_owner".to_string());
+    }
+    if rule.actions.notify_assignee && ticket.assignee.is_some() {
+        actions.push("notified_assignee".to_string());
+    }
+    let bump = if rule.actions.bump_priority { bumped_priority(ticket.priority.as_deref()) } else { None };
+    if bump.is_some() {
+        actions.push("priority_bumped".to_string());
+    }
+
+    // Claim the (rule, ticket) pair first: the unique index makes sure only one
+    // instance acts on it, and the rule never fires for the ticket again.
+    let entry = Escalation {
+        escalation_id: Uuid::new_v4().to_string(),
+        rule_id: rule.rule_id.clone(),
+        rule_name: rule.name.clone(),
+        team_id: rule.team_id.clone(),
+        project_id: ticket.project_id.clone(),
+        ticket_id: ticket.ticket_id.clone(),
+        ticket_title: ticket.title.clone(),
+        actions,
+        fired_at: BsonDateTime::now(),
+    };
+    if log_coll(data).insert_one(&entry).await.is_err() {
+        return;
+    }
+
+    let actor = format!("escalation:{}", rule.rule_id);
+    if let Some(priority) = bump {
+        if let Some(change) = TicketChange::field(ticket, "priority", &Some(priority)) {
+            if let Err(e) = commit(&data.mongodb, Some(ticket), vec![change], &actor).await {
+                error!("Escalation {} could not bump priority of {}: {:?}", rule.rule_id, ticket.ticket_id, e);
+            }
+        }
+    }
+
+    let subject = format!("Escalation: {}", ticket.title);
+    let text = format!(
+        "The escalation rule \"{}\" fired for ticket \"{}\" (status {}, priority {}).\n",
+        rule.name,
+        ticket.title,
+        ticket.status,
+        bump.or(ticket.priority.as_deref()).unwrap_or("none"),
+    );
+    let payload = serde_json::json!({
+        "type": "ticket_escalated",
+        "rule_id": rule.rule_id,
+        "ticket_id": ticket.ticket_id,
+        "project_id": ticket.project_id,
+        "title": ticket.title,
+    });
+    let mut recipients = Vec::new();
+    if rule.actions.notify_project_owner {
+        recipients.extend(project_owner_ids(data, &ticket.project_id).await);
+    }
+    if rule.actions.notify_assignee {
+        recipients.extend(ticket.assignee.clone());
+    }
+    recipients.sort();
+    recipients.dedup();
+    for user_id in &recipients {
+        notify(data, user_id, &subject, &text, &payload).await;
+    }
+
+    record_activity(&data.mongodb, ActivityEvent::new(
+        &rule.team_id, Some(&ticket.project_id), &actor, "ticket_escalated", &ticket.ticket_id,
+        format!("escalated ticket \"{}\" by rule \"{}\"", ticket.title, rule.name),
+    )).await;
+}
+
+/// Evaluate every enabled rule once.
+async fn evaluate_rules(data: &AppState) {
+    let mut cursor = match rules_coll(data).find(doc! { "enabled": true }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching escalation rules: {}", e);
+            return;
+        }
+    };
+    while let Some(Ok(rule)) = cursor.next().await {
+        let tickets = match matching_tickets(data, &rule).await {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Error evaluating escalation rule {}: {}", rule.rule_id, e);
+                continue;
+            }
+        };
+        let fired = log_coll(data)
+            .distinct("ticket_id", doc! { "rule_id": &rule.rule_id })
+            .await
+            .unwrap_or_default();
+        let mut count = 0;
+        for ticket in tickets {
+            if fired.iter().any(|id| id.as_str() == Some(ticket.ticket_id.as_str())) {
+                continue;
+            }
+            fire(data, &rule, &ticket).await;
+            count += 1;
+        }
+        if count > 0 {
+            info!("Escalation rule {} fired for {} ticket(s)", rule.rule_id, count);
+        }
+    }
+}
+
+/// Start the background job that evaluates escalation rules.
+pub fn spawn_escalations(data: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(EVALUATION_INTERVAL);
+        loop {
+            interval.tick().await;
+            evaluate_rules(&data).await;
+        }
+    });
+}
@@ -0,0 +1,418 @@
+// src/estimation_poker.rs
+//
+// Planning poker on a ticket. Sessions are ephemeral and live in the ChatServer:
+// the facilitator starts one, project members join and submit hidden votes, the
+// facilitator reveals them and records the agreed value as the ticket's estimate.
+// Every step is pushed to the participants over the WebSocket:
+//
+//   {"type": "poker_start" | "poker_join" | "poker_reveal" | "poker_leave", "ticket_id": ...}
+//   {"type": "poker_vote", "ticket_id": ..., "value": 5}
+//   {"type": "poker_accept", "ticket_id": ..., "value": 5}   // value defaults to the consensus
+//
+// `GET .../tickets/{ticket_id}/poker` returns the same state for clients that
+// reconnect or missed events.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::error;
+use mongodb::bson::doc;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::chat_server::{ChatServer, SignalMessage, WsMessage};
+use crate::response::{CodedError, ok};
+use crate::tenancy::{ProjectScope, Repo};
+use crate::ticket::Ticket;
+use crate::ticket_events::{commit, CommitError, TicketChange, MAX_ATTEMPTS};
+
+/// Sessions nobody has touched for this long are dropped.
+const SESSION_TTL: Duration = Duration::from_secs(2 * 60 * 60);
+
+pub struct PokerSession {
+    ticket_id: String,
+    project_id: String,
+    facilitator_id: String,
+    participants: Vec<String>,
+    votes: HashMap<String, f64>,
+    revealed: bool,
+    started_at: DateTime<Utc>,
+    last_activity: Instant,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PokerSummary {
+    pub min: f64,
+    pub max: f64,
+    pub average: f64,
+    pub median: f64,
+    /// Set when every vote is the same
+    pub consensus: Option<f64>,
+}
+
+/// A session as seen by one participant.
+#[derive(Debug, Serialize)]
+pub struct PokerState {
+    pub ticket_id: String,
+    pub project_id: String,
+    pub facilitator_id: String,
+    pub participants: Vec<String>,
+    /// Who has voted; the values stay hidden until the reveal
+    pub voted: Vec<String>,
+    pub revealed: bool,
+    pub my_vote: Option<f64>,
+    /// Every vote by user, once revealed
+    pub votes: Option<HashMap<String, f64>>,
+    pub summary: Option<PokerSummary>,
+    pub started_at: DateTime<Utc>,
+}
+
+impl PokerSession {
+    fn new(ticket_id: &str, project_id: String, facilitator_id: &str) -> Self {
+        PokerSession {
+            ticket_id: ticket_id.to_string(),
+            project_id,
+            facilitator_id: facilitator_id.to_string(),
+            participants: vec![facilitator_id.to_string()],
+            votes: HashMap::new(),
+            revealed: false,
+            started_at: Utc::now(),
+            last_activity: Instant::now(),
+        }
+    }
+
+    fn summary(&self) -> Option<PokerSummary> {
+        let mut values: Vec<f64> = self.votes.values().copied().collect();
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(|a, b| a.total_cmp(b));
+        let n = values.len();
+        let median = if n % 2 == 1 { values[n / 2] } else { (values[n / 2 - 1] + values[n / 2]) / 2.0 };
+        Some(PokerSummary {
+            min: values[0],
+            max: values[n - 1],
+            average: values.iter().sum::<f64>() / n as f64,
+            median,
+            consensus: (values[0] == values[n - 1]).then_some(values[0]),
+        })
+    }
+
+    fn state(&self, user_id: &str) -> PokerState {
+        PokerState {
+            ticket_id: self.ticket_id.clone(),
+            project_id: self.project_id.clone(),
+            facilitator_id: self.facilitator_id.clone(),
+            participants: self.participants.clone(),
+            voted: self.participants.iter().filter(|u| self.votes.contains_key(*u)).cloned().collect(),
+            revealed: self.revealed,
+            my_vote: self.votes.get(user_id).copied(),
+            votes: self.revealed.then(|| self.votes.clone()),
+            summary: if self.revealed { self.summary() } else { None },
+            started_at: self.started_at,
+        }
+    }
+}
+
+pub enum PokerAction {
+    Start,
+    Join,
+    Vote(Option<f64>),
+    Reveal,
+    /// Record the given value, or the consensus when None
+    Accept(Option<f64>),
+    Leave,
+}
+
+impl PokerAction {
+    /// Reads the action of a `poker_*` WebSocket message; None for anything else.
+    pub fn from_ws(kind: &str, msg: &Value) -> Option<PokerAction> {
+        let value = msg.get("value").and_then(Value::as_f64);
+        Some(match kind {
+            "poker_start" => PokerAction::Start,
+            "poker_join" => PokerAction::Join,
+            "poker_vote" => PokerAction::Vote(value),
+            "poker_reveal" => PokerAction::Reveal,
+            "poker_accept" => PokerAction::Accept(value),
+            "poker_leave" => PokerAction::Leave,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct PokerCommand {
+    pub ticket_id: String,
+    pub user_id: String,
+    pub action: PokerAction,
+    pub addr: Recipient<WsMessage>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Option<PokerState>")]
+pub struct GetPokerState {
+    pub ticket_id: String,
+    pub user_id: String,
+}
+
+fn reply_error(addr: &Recipient<WsMessage>, ticket_id: &str, error: &str) {
+    let payload = json!({ "type": "poker_error", "ticket_id": ticket_id, "error": error });
+    addr.do_send(WsMessage::Signal(SignalMessage { payload: payload.to_string() }));
+}
+
+fn valid_estimate(value: Option<f64>) -> Result<f64, &'static str> {
+    value
+        .filter(|v| v.is_finite() && *v >= 0.0)
+        .ok_or("value must be a non-negative number")
+}
+
+/// The ticket's project, provided `user_id` is a member of it.
+async fn member_project(db: &MongoDB, ticket_id: &str, user_id: &str) -> Result<String, String> {
+    let scope = match ProjectScope::of_ticket(db, ticket_id).await {
+        Ok(Some(s)) => s,
+        Ok(None) => return Err("Ticket not found".to_string()),
+        Err(e) => {
+            error!("Error fetching ticket: {}", e);
+            return Err("Error fetching ticket".to_string());
+        }
+    };
+    match db.check_project_membership(user_id, scope.project_id()).await {
+        Ok(true) => Ok(scope.project_id().to_string()),
+        Ok(false) => Err("Not a member of this project".to_string()),
+        Err(e) => {
+            error!("Error checking project membership: {}", e);
+            Err("Error checking project membership".to_string())
+        }
+    }
+}
+
+async fn record_estimate(db: &MongoDB, ticket_id: &str, project_id: &str, value: f64, actor_id: &str) -> Result<(), String> {
+    let scope = ProjectScope::of_project(db, project_id).await.ok_or("Project not found")?;
+    let tickets = Repo::<Ticket>::new(db, &scope);
+    for _ in 0..MAX_ATTEMPTS {
+        let ticket = match tickets.find_one(doc! { "ticket_id": ticket_id }).await {
+            Ok(Some(t)) => t,
+            Ok(None) => return Err("Ticket not found".to_string()),
+            Err(e) => {
+                error!("Error fetching ticket: {}", e);
+                return Err("Error fetching ticket".to_string());
+            }
+        };
+        let changes = TicketChange::field(&ticket, "estimate_hours", &Some(value)).into_iter().collect();
+        match commit(db, Some(&ticket), changes, actor_id).await {
+            Ok(_) => return Ok(()),
+            Err(CommitError::Conflict) => continue,
+            Err(CommitError::Invalid(msg)) => return Err(msg),
+            Err(CommitError::Db(e)) => {
+                error!("Error recording estimate: {}", e);
+                return Err("Error recording estimate".to_string());
+            }
+        }
+    }
+    Err("Ticket was modified concurrently, please retry".to_string())
+}
+
+impl ChatServer {
+    /// Push each participant their own view of the session.
+    fn poker_broadcast(&self, ticket_id: &str, event: &str) {
+        if let Some(session) = self.poker.get(ticket_id) {
+            for user_id in &session.participants {
+                let payload = json!({
+                    "type": "poker_update",
+                    "event": event,
+                    "ticket_id": ticket_id,
+                    "state": session.state(user_id),
+                });
+                self.push(user_id, &payload.to_string());
+            }
+        }
+    }
+
+    fn poker_end(&mut self, ticket_id: &str, payload: Value) {
+        if let Some(session) = self.poker.remove(ticket_id) {
+            for user_id in &session.participants {
+                self.push(user_id, &payload.to_string());
+            }
+        }
+    }
+
+    /// Start or join, once project membership has been checked. The facilitator
+    /// starting again opens a new round.
+    fn poker_enter(&mut self, msg: &PokerCommand, project_id: String) -> Result<(), &'static str> {
+        let start = matches!(msg.action, PokerAction::Start);
+        if !self.poker.contains_key(&msg.ticket_id) {
+            if !start {
+                return Err("No estimation session for this ticket");
+            }
+            self.poker.insert(msg.ticket_id.clone(), PokerSession::new(&msg.ticket_id, project_id, &msg.user_id));
+            self.poker_broadcast(&msg.ticket_id, "started");
+            return Ok(());
+        }
+        let session = self.poker.get_mut(&msg.ticket_id).expect("checked above");
+        session.last_activity = Instant::now();
+        let event = if start && session.facilitator_id == msg.user_id {
+            session.votes.clear();
+            session.revealed = false;
+            "round_started"
+        } else {
+            if !session.participants.contains(&msg.user_id) {
+                session.participants.push(msg.user_id.clone());
+            }
+            "joined"
+        };
+        self.poker_broadcast(&msg.ticket_id, event);
+        Ok(())
+    }
+
+    fn poker_step(&mut self, msg: &PokerCommand) -> Result<(), &'static str> {
+        let session = self.poker.get_mut(&msg.ticket_id).ok_or("No estimation session for this ticket")?;
+        if !session.participants.contains(&msg.user_id) {
+            return Err("Join the session first");
+        }
+        let is_facilitator = session.facilitator_id == msg.user_id;
+        session.last_activity = Instant::now();
+        let event = match &msg.action {
+            PokerAction::Vote(value) => {
+                if session.revealed {
+                    return Err("Votes are revealed; wait for a new round");
+                }
+                session.votes.insert(msg.user_id.clone(), valid_estimate(*value)?);
+                "voted"
+            }
+            PokerAction::Reveal if !is_facilitator => return Err("Only the facilitator can reveal votes"),
+            PokerAction::Reveal => {
+                session.revealed = true;
+                "revealed"
+            }
+            PokerAction::Leave if is_facilitator => {
+                self.poker_end(&msg.ticket_id, json!({ "type": "poker_ended", "ticket_id": msg.ticket_id }));
+                return Ok(());
+            }
+            PokerAction::Leave => {
+                session.participants.retain(|u| u != &msg.user_id);
+                session.votes.remove(&msg.user_id);
+                "left"
+            }
+            PokerAction::Start | PokerAction::Join | PokerAction::Accept(_) => return Ok(()),
+        };
+        self.poker_broadcast(&msg.ticket_id, event);
+        Ok(())
+    }
+
+    /// The project and value to record when the facilitator accepts.
+    fn poker_outcome(&self, msg: &PokerCommand, value: Option<f64>) -> Result<(String, f64), &'static str> {
+        let session = self.poker.get(&msg.ticket_id).ok_or("No estimation session for this ticket")?;
+        if session.facilitator_id != msg.user_id {
+            return Err("Only the facilitator can record the estimate");
+        }
+        if !session.revealed {
+            return Err("Reveal the votes first");
+        }
+        let value = match value {
+            Some(v) => Some(v),
+            None => session.summary().and_then(|s| s.consensus),
+        };
+        if value.is_none() {
+            return Err("No consensus; pass the agreed value");
+        }
+        Ok((session.project_id.clone(), valid_estimate(value)?))
+    }
+}
+
+impl Handler<PokerCommand> for ChatServer {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, msg: PokerCommand, _: &mut Context<Self>) -> Self::Result {
+        self.poker.retain(|_, s| s.last_activity.elapsed() < SESSION_TTL);
+        let db = self.db.clone();
+        match msg.action {
+            PokerAction::Start | PokerAction::Join => Box::pin(
+                async move {
+                    let project = member_project(&db, &msg.ticket_id, &msg.user_id).await;
+                    (msg, project)
+                }
+                .into_actor(self)
+                .map(|(msg, project), act, _| {
+                    let result = project.and_then(|p| act.poker_enter(&msg, p).map_err(str::to_string));
+                    if let Err(e) = result {
+                        reply_error(&msg.addr, &msg.ticket_id, &e);
+                    }
+                }),
+            ),
+            PokerAction::Accept(value) => {
+                let (project_id, value) = match self.poker_outcome(&msg, value) {
+                    Ok(outcome) => outcome,
+                    Err(e) => {
+                        reply_error(&msg.addr, &msg.ticket_id, e);
+                        return Box::pin(fut::ready(()));
+                    }
+                };
+                Box::pin(
+                    async move {
+                        let result = record_estimate(&db, &msg.ticket_id, &project_id, value, &msg.user_id).await;
+                        (msg, result)
+                    }
+                    .into_actor(self)
+                    .map(move |(msg, result), act, _| match result {
+                        Ok(()) => act.poker_end(
+                            &msg.ticket_id,
+                            json!({ "type": "poker_recorded", "ticket_id": msg.ticket_id, "estimate_hours": value }),
+                        ),
+                        Err(e) => reply_error(&msg.addr, &msg.ticket_id, &e),
+                    }),
+                )
+            }
+            _ => {
+                if let Err(e) = self.poker_step(&msg) {
+                    reply_error(&msg.addr, &msg.ticket_id, e);
+                }
+                Box::pin(fut::ready(()))
+            }
+        }
+    }
+}
+
+impl Handler<GetPokerState> for ChatServer {
+    type Result = MessageResult<GetPokerState>;
+
+    fn handle(&mut self, msg: GetPokerState, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(
+            self.poker
+                .get(&msg.ticket_id)
+                .filter(|s| s.last_activity.elapsed() < SESSION_TTL)
+                .map(|s| s.state(&msg.user_id)),
+        )
+    }
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/poker
+pub async fn get_poker_session(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>, // (team_id, project_id, ticket_id)
+) -> impl Responder {
+    let (team_id, project_id, ticket_id) = path.into_inner();
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    if !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
+
+    let request = GetPokerState { ticket_id, user_id: auth.user_id().to_string() };
+    match data.chat_server.send(request).await {
+        Ok(Some(state)) if state.project_id == project_id => ok(state),
+        Ok(_) => HttpResponse::NotFound().body("No estimation session for this ticket"),
+        Err(e) => {
+            error!("Error reading estimation session: {}", e);
+            HttpResponse::InternalServerError().body("Error reading estimation session")
+        }
+    }
+}
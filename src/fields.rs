@@ -0,0 +1,65 @@
+// src/fields.rs
+//
+// `?fields=a,b,c` support for read endpoints. Responses are trimmed on the server
+// before they are sent, so list views can skip heavy embedded data such as ticket
+// comments and attachments. Nested fields can be named with dots (`comments.content`).
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[derive(Debug, Deserialize)]
+pub struct FieldsQuery {
+    pub fields: Option<String>,
+}
+
+/// A parsed field selection; `None` from [`FieldSet::parse`] means "everything".
+#[derive(Debug, Default)]
+pub struct FieldSet {
+    children: BTreeMap<String, FieldSet>,
+}
+
+impl FieldSet {
+    /// Parses a comma-separated list. `always` is included whenever a selection is made,
+    /// so clients can still key the results (e.g. `ticket_id`).
+    pub fn parse(fields: Option<&str>, always: &[&str]) -> Option<FieldSet> {
+        let fields = fields.map(str::trim).filter(|f| !f.is_empty())?;
+        let mut set = FieldSet::default();
+        for path in fields.split(',').map(str::trim).filter(|p| !p.is_empty()).chain(always.iter().copied()) {
+            let mut node = &mut set;
+            for part in path.split('.') {
+                node = node.children.entry(part.to_string()).or_default();
+            }
+        }
+        Some(set)
+    }
+
+    fn apply(&self, value: Value) -> Value {
+        if self.children.is_empty() {
+            return value;
+        }
+        match value {
+            Value::Object(map) => {
+                let mut out = Map::new();
+                for (key, v) in map {
+                    if let Some(child) = self.children.get(&key) {
+                        out.insert(key, child.apply(v));
+                    }
+                }
+                Value::Object(out)
+            }
+            Value::Array(items) => Value::Array(items.into_iter().map(|v| self.apply(v)).collect()),
+            other => other,
+        }
+    }
+}
+
+/// Serializes `value` and keeps only the selected fields. Arrays are filtered per element.
+pub fn select<T: Serialize>(value: &T, fields: &Option<FieldSet>) -> Value {
+    let value = serde_json::to_value(value).unwrap_or(Value::Null);
+    match fields {
+        Some(set) => set.apply(value),
+        None => value,
+    }
+}
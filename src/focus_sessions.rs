@@ -0,0 +1,348 @@
+// src/focus_sessions.rs
+//
+// Focus sessions: a block of time a user spends on one ticket. Starting a session
+// puts a busy block on the user's calendar and the user in DND until the planned
+// end; ending it, or reaching the planned end, trims the block to the time actually
+// spent, restores the user's earlier DND and logs the time in the ticket's worklog.
+// The time is logged before the session is marked ended, so a failed end is retried
+// by the expiry job. A user has at most one session running, enforced by a unique
+// index. Weekly stats sum the ended sessions per week of the user's time zone.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use futures_util::StreamExt;
+use log::{error, info};
+use mongodb::bson::{self, doc};
+use mongodb::error::{ErrorKind, WriteFailure};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::calendar::CalendarEvent;
+use crate::chat_db::MongoDB;
+use crate::do_not_disturb::{dnd_until, set_dnd_until, user_timezone};
+use crate::sync::{record_change, Entity, Op, Scope};
+use crate::ticket_worklog::{log_focus_work, member_ticket, WorklogEntry};
+use crate::user_status::broadcast_presence;
+
+/// How often the job ends sessions that reached their planned end.
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_MINUTES: i64 = 50;
+const MIN_MINUTES: i64 = 5;
+const MAX_MINUTES: i64 = 240;
+const MAX_STATS_WEEKS: i64 = 52;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusSession {
+    pub session_id: String,
+    pub user_id: String,
+    pub ticket_id: String,
+    pub project_id: String,
+    pub ticket_title: String,
+    /// The busy block on the user's calendar
+    pub event_id: String,
+    pub started_at: DateTime<Utc>,
+    pub planned_end: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    /// Logged to the ticket when the session ends
+    pub minutes: Option<i64>,
+    /// The user's DND `until` before the session, restored when it ends
+    #[serde(default)]
+    pub previous_dnd_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartFocusRequest {
+    pub ticket_id: String,
+    /// Planned length; 50 minutes by default
+    pub minutes: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FocusStatsQuery {
+    /// Weeks back including the current one; 4 by default
+    pub weeks: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TicketFocus {
+    pub ticket_id: String,
+    pub ticket_title: String,
+    pub minutes: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FocusWeek {
+    /// Monday of the week in the user's time zone
+    pub week_start: NaiveDate,
+    pub sessions: usize,
+    pub minutes: i64,
+    /// Most focused first
+    pub tickets: Vec<TicketFocus>,
+}
+
+fn sessions_coll(db: &MongoDB) -> mongodb::Collection<FocusSession> {
+    db.db.collection::<FocusSession>("focus_sessions")
+}
+
+async fn active_session(db: &MongoDB, user_id: &str) -> mongodb::error::Result<Option<FocusSession>> {
+    sessions_coll(db).find_one(doc! { "user_id": user_id, "ended_at": null }).await
+}
+
+/// Ends the session at `ended_at`: logs the time, trims the busy block and restores
+/// the user's earlier DND. Returns None when the session had already ended.
+async fn finish(data: &AppState, session: FocusSession, ended_at: DateTime<Utc>) -> mongodb::error::Result<Option<FocusSession>> {
+    let mut minutes = ((ended_at - session.started_at).num_seconds() + 30).max(0) / 60;
+    let mut ended_at = ended_at;
+    if minutes > 0 {
+        let entry = WorklogEntry {
+            entry_id: Uuid::new_v4().to_string(),
+            ticket_id: session.ticket_id.clone(),
+            user_id: session.user_id.clone(),
+            started_at: session.started_at,
+            ended_at,
+            minutes,
+            focus_session_id: Some(session.session_id.clone()),
+        };
+        // An earlier attempt may have logged the session already; its end time wins.
+        let logged = log_focus_work(&data.mongodb, &entry).await?;
+        (ended_at, minutes) = (logged.ended_at, logged.minutes);
+    }
+    let ended = sessions_coll(&data.mongodb)
+        .update_one(
+            doc! { "session_id": &session.session_id, "ended_at": null },
+            doc! { "$set": { "ended_at": bson::to_bson(&ended_at)?, "minutes": minutes } },
+        )
+        .await?;
+    if ended.modified_count == 0 {
+        return Ok(None);
+    }
+
+    let events = data.mongodb.db.collection::<CalendarEvent>("calendar_events");
+    events
+        .update_one(doc! { "event_id": &session.event_id }, doc! { "$set": { "end": bson::to_bson(&ended_at)? } })
+        .await?;
+    record_change(&data.mongodb, Entity::Event, &session.event_id, Op::Upsert, Scope::Users(std::slice::from_ref(&session.user_id))).await;
+    set_dnd_until(&data.mongodb, &session.user_id, session.previous_dnd_until.filter(|u| *u > Utc::now())).await?;
+    broadcast_presence(data, &session.user_id).await;
+    info!("Focus session {} on ticket {} ended after {} minutes", session.session_id, session.ticket_id, minutes);
+    Ok(Some(FocusSession { ended_at: Some(ended_at), minutes: Some(minutes), ..session }))
+}
+
+/// GET /users/me/focus
+/// The running session; null when there is none.
+pub async fn get_focus_session(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    match active_session(&data.mongodb, auth.user_id()).await {
+        Ok(s) => HttpResponse::Ok().json(s),
+        Err(e) => {
+            error!("Error fetching focus session: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching focus session")
+        }
+    }
+}
+
+/// POST /users/me/focus
+pub async fn start_focus_session(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    payload: web::Json<StartFocusRequest>,
+) -> impl Responder {
+    let user_id = auth.user_id().to_string();
+    let minutes = payload.minutes.unwrap_or(DEFAULT_MINUTES);
+    if !(MIN_MINUTES..=MAX_MINUTES).contains(&minutes) {
+        return HttpResponse::BadRequest().body(format!("minutes must be between {} and {}", MIN_MINUTES, MAX_MINUTES));
+    }
+    let (ticket, team_id) = match member_ticket(&auth, &data, &payload.ticket_id).await {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+    let previous_dnd_until = match dnd_until(&data.mongodb, &user_id).await {
+        Ok(until) => until,
+        Err(e) => {
+            error!("Error fetching DND settings: {}", e);
+            return HttpResponse::InternalServerError().body("Error starting focus session");
+        }
+    };
+
+    let now = Utc::now();
+    let planned_end = now + chrono::Duration::minutes(minutes);
+    let block = CalendarEvent {
+        event_id: Uuid::new_v4().to_string(),
+        user_id: user_id.clone(),
+        title: format!("Focus: {}", ticket.title),
+        start: now,
+        end: planned_end,
+        participants: vec![user_id.clone()],
+        created_at: now,
+        team_id: Some(team_id),
+        meeting_notes: Some(false),
+        notes_doc_id: None,
+        meeting_url: None,
+        meeting_provider: None,
+        focus: true,
+    };
+    let session = FocusSession {
+        session_id: Uuid::new_v4().to_string(),
+        user_id: user_id.clone(),
+        ticket_id: ticket.ticket_id,
+        project_id: ticket.project_id,
+        ticket_title: ticket.title,
+        event_id: block.event_id.clone(),
+        started_at: now,
+        planned_end,
+        ended_at: None,
+        minutes: None,
+        previous_dnd_until,
+    };
+    match sessions_coll(&data.mongodb).insert_one(&session).await {
+        Ok(_) => {}
+        Err(e) if matches!(&*e.kind, ErrorKind::Write(WriteFailure::WriteError(w)) if w.code == 11000) => {
+            return HttpResponse::Conflict().body("A focus session is already running");
+        }
+        Err(e) => {
+            error!("Error creating focus session: {}", e);
+            return HttpResponse::InternalServerError().body("Error starting focus session");
+        }
+    }
+    if let Err(e) = data.mongodb.db.collection::<CalendarEvent>("calendar_events").insert_one(&block).await {
+        error!("Error creating focus block: {}", e);
+        if let Err(e) = sessions_coll(&data.mongodb).delete_one(doc! { "session_id": &session.session_id }).await {
+            error!("Error removing focus session {}: {}", session.session_id, e);
+        }
+        return HttpResponse::InternalServerError().body("Error starting focus session");
+    }
+    record_change(&data.mongodb, Entity::Event, &block.event_id, Op::Upsert, Scope::Users(std::slice::from_ref(&user_id))).await;
+    // A longer DND that was already set is kept.
+    let dnd_end = previous_dnd_until.map_or(planned_end, |u| u.max(planned_end));
+    if let Err(e) = set_dnd_until(&data.mongodb, &user_id, Some(dnd_end)).await {
+        error!("Error setting DND for focus session: {}", e);
+    }
+    broadcast_presence(&data, &user_id).await;
+    HttpResponse::Ok().json(session)
+}
+
+/// POST /users/me/focus/end
+pub async fn end_focus_session(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    let session = match active_session(&data.mongodb, auth.user_id()).await {
+        Ok(Some(s)) => s,
+        Ok(None) => return HttpResponse::NotFound().body("No focus session is running"),
+        Err(e) => {
+            error!("Error fetching focus session: {}", e);
+            return HttpResponse::InternalServerError().body("Error ending focus session");
+        }
+    };
+    let ended_at = Utc::now().min(session.planned_end);
+    match finish(&data, session, ended_at).await {
+        Ok(Some(s)) => HttpResponse::Ok().json(s),
+        Ok(None) => HttpResponse::NotFound().body("No focus session is running"),
+        Err(e) => {
+            error!("Error ending focus session: {}", e);
+            HttpResponse::InternalServerError().body("Error ending focus session")
+        }
+    }
+}
+
+/// GET /users/me/focus/stats?weeks=
+pub async fn get_focus_stats(auth: AuthContext, data: web::Data<AppState>, query: web::Query<FocusStatsQuery>) -> impl Responder {
+    let weeks = query.weeks.unwrap_or(4);
+    if !(1..=MAX_STATS_WEEKS).contains(&weeks) {
+        return HttpResponse::BadRequest().body(format!("weeks must be between 1 and {}", MAX_STATS_WEEKS));
+    }
+    let tz = user_timezone(&data.mongodb, auth.user_id()).await;
+    let today = Utc::now().with_timezone(&tz).date_naive();
+    let this_week = today - chrono::Duration::days(today.weekday().num_days_from_monday() as i64);
+    let first_week = this_week - chrono::Duration::weeks(weeks - 1);
+    let Some(since) = first_week.and_hms_opt(0, 0, 0).and_then(|t| t.and_local_timezone(tz).earliest()) else {
+        return HttpResponse::InternalServerError().body("Error fetching focus stats");
+    };
+
+    let filter = doc! {
+        "user_id": auth.user_id(),
+        "ended_at": { "$ne": null },
+        "started_at": { "$gte": bson::to_bson(&since.with_timezone(&Utc)).unwrap_or_default() },
+    };
+    let mut cursor = match sessions_coll(&data.mongodb).find(filter).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching focus sessions: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching focus stats");
+        }
+    };
+    let mut by_week: BTreeMap<NaiveDate, (usize, BTreeMap<String, TicketFocus>)> = BTreeMap::new();
+    let mut week = first_week;
+    while week <= this_week {
+        by_week.insert(week, Default::default());
+        week += chrono::Duration::weeks(1);
+    }
+    while let Some(session) = cursor.next().await {
+        let session = match session {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Error reading focus session: {}", e);
+                continue;
+            }
+        };
+        let day = session.started_at.with_timezone(&tz).date_naive();
+        let monday = day - chrono::Duration::days(day.weekday().num_days_from_monday() as i64);
+        let Some((count, tickets)) = by_week.get_mut(&monday) else {
+            continue;
+        };
+        *count += 1;
+        let entry = tickets.entry(session.ticket_id.clone()).or_insert_with(|| TicketFocus {
+            ticket_id: session.ticket_id.clone(),
+            ticket_title: session.ticket_title.clone(),
+            minutes: 0,
+        });
+        entry.minutes += session.minutes.unwrap_or(0);
+    }
+
+    let stats: Vec<FocusWeek> = by_week
+        .into_iter()
+        .map(|(week_start, (sessions, tickets))| {
+            let mut tickets: Vec<TicketFocus> = tickets.into_values().collect();
+            tickets.sort_by_key(|t| std::cmp::Reverse(t.minutes));
+            FocusWeek { week_start, sessions, minutes: tickets.iter().map(|t| t.minutes).sum(), tickets }
+        })
+        .collect();
+    HttpResponse::Ok().json(stats)
+}
+
+/// Ends every session that reached its planned end.
+async fn end_expired(data: &AppState) {
+    let filter = doc! { "ended_at": null, "planned_end": { "$lte": bson::to_bson(&Utc::now()).unwrap_or_default() } };
+    let mut cursor = match sessions_coll(&data.mongodb).find(filter).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching expired focus sessions: {}", e);
+            return;
+        }
+    };
+    while let Some(session) = cursor.next().await {
+        let session = match session {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Error reading focus session: {}", e);
+                continue;
+            }
+        };
+        let (session_id, planned_end) = (session.session_id.clone(), session.planned_end);
+        if let Err(e) = finish(data, session, planned_end).await {
+            error!("Error ending focus session {}: {}", session_id, e);
+        }
+    }
+}
+
+/// Start the background job that ends focus sessions at their planned end.
+pub fn spawn_focus_expiry(state: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(EXPIRY_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            end_expired(&state).await;
+        }
+    });
+}
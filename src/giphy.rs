@@ -0,0 +1,90 @@
+// src/giphy.rs
+//
+// The `/giphy <search>` command. Only available when GIPHY_API_KEY is set; the first
+// matching GIF is posted by the sender as a linked attachment, so it is not copied
+// into GridFS.
+
+use std::time::Duration;
+
+use log::warn;
+use serde::Deserialize;
+
+use crate::app_state::AppState;
+use crate::chat_attachments::ChatAttachment;
+use crate::chat_server::{CreateMessage, MessageResponse};
+
+const GIPHY_SEARCH_URL: &str = "https://api.giphy.com/v1/gifs/search";
+const GIPHY_TIMEOUT: Duration = Duration::from_secs(5);
+pub const GIPHY_USAGE: &str = "/giphy <search> — post the first matching GIF";
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    data: Vec<Gif>,
+}
+
+#[derive(Deserialize)]
+struct Gif {
+    id: String,
+    images: GifImages,
+}
+
+#[derive(Deserialize)]
+struct GifImages {
+    original: GifImage,
+}
+
+#[derive(Deserialize)]
+struct GifImage {
+    url: String,
+    size: Option<String>,
+}
+
+pub fn giphy_enabled(data: &AppState) -> bool {
+    data.config.giphy_api_key.is_some()
+}
+
+/// The first GIF matching `query`, as a linked attachment; None when nothing matches.
+async fn search(data: &AppState, query: &str) -> Result<Option<ChatAttachment>, String> {
+    let api_key = data.config.giphy_api_key.as_deref().ok_or("/giphy is not enabled")?;
+    let resp = data.http_client.get(GIPHY_SEARCH_URL)
+        .timeout(GIPHY_TIMEOUT)
+        .query(&[("api_key", api_key), ("q", query), ("limit", "1"), ("rating", "g")])
+        .send()
+        .await
+        .map_err(|e| {
+            warn!("Giphy unreachable: {}", e);
+            "Giphy did not respond".to_string()
+        })?;
+    if !resp.status().is_success() {
+        warn!("Giphy search answered {}", resp.status());
+        return Err("Giphy search failed".to_string());
+    }
+    let found: SearchResponse = resp.json().await.map_err(|_| "Giphy sent an invalid reply".to_string())?;
+    Ok(found.data.into_iter().next().map(|gif| ChatAttachment {
+        attachment_id: format!("giphy:{}", gif.id),
+        filename: format!("{}.gif", query),
+        content_type: "image/gif".to_string(),
+        size: gif.images.original.size.and_then(|s| s.parse().ok()).unwrap_or(0),
+        thumbnail_id: None,
+        url: Some(gif.images.original.url),
+    }))
+}
+
+/// `/giphy <search>`; posts the GIF in the chat on the sender's behalf.
+pub(crate) async fn giphy_command(data: &AppState, user_id: &str, chat_id: &str, args: &str) -> Result<MessageResponse, String> {
+    let query = args.trim();
+    if query.is_empty() {
+        return Err(format!("Usage: {}", GIPHY_USAGE));
+    }
+    let gif = search(data, query).await?.ok_or_else(|| format!("No GIFs found for \"{}\"", query))?;
+    let create = CreateMessage {
+        user_id: user_id.to_string(),
+        chat_id: chat_id.to_string(),
+        content: query.to_string(),
+        attachments: Some(vec![gif]),
+    };
+    match data.chat_server.send(create).await {
+        Ok(Ok(msg)) => Ok(msg),
+        _ => Err("Failed to post the GIF".to_string()),
+    }
+}
@@ -0,0 +1,307 @@
+// src/holidays.rs
+//
+// Team holiday calendars. Team admins add custom days off or import a country's
+// public holidays for a year; together with weekends they make up the team's
+// non-working days, which capacity planning leaves out. `non_working_days` and
+// `is_working_day` are the helpers for anything else that counts working time.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Datelike, NaiveDate, Utc, Weekday};
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::response::{CodedError, ok};
+
+const PUBLIC_HOLIDAYS_URL: &str = "https://date.nager.at/api/v3/PublicHolidays";
+const IMPORT_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_NAME_LEN: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HolidaySource {
+    Custom,
+    Imported,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Holiday {
+    pub holiday_id: String,
+    pub team_id: String,
+    /// Stored as "YYYY-MM-DD", so ranges can be queried as strings
+    pub date: NaiveDate,
+    pub name: String,
+    pub source: HolidaySource,
+    /// ISO country code of imported holidays
+    pub country: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HolidayQuery {
+    pub year: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateHolidayRequest {
+    pub date: NaiveDate,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateHolidayRequest {
+    pub date: Option<NaiveDate>,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportHolidaysRequest {
+    /// ISO 3166-1 alpha-2, e.g. "DE"
+    pub country: String,
+    pub year: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResult {
+    pub imported: usize,
+    /// Dates the team already had a holiday on
+    pub skipped: usize,
+}
+
+#[derive(Deserialize)]
+struct PublicHoliday {
+    date: NaiveDate,
+    name: String,
+    /// False for holidays observed only in some regions
+    #[serde(default)]
+    global: bool,
+}
+
+fn holidays_coll(db: &MongoDB) -> mongodb::Collection<Holiday> {
+    db.db.collection::<Holiday>("team_holidays")
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(&*e.kind, mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(w)) if w.code == 11000)
+}
+
+/// The team's holidays between `start` and `end`, both included.
+pub async fn non_working_days(db: &MongoDB, team_id: &str, start: NaiveDate, end: NaiveDate) -> mongodb::error::Result<HashSet<NaiveDate>> {
+    let filter = doc! {
+        "team_id": team_id,
+        "date": { "$gte": start.to_string(), "$lte": end.to_string() },
+    };
+    let mut cursor = holidays_coll(db).find(filter).await?;
+    let mut days = HashSet::new();
+    while let Some(h) = cursor.next().await {
+        days.insert(h?.date);
+    }
+    Ok(days)
+}
+
+/// Neither a weekend nor one of `holidays`.
+pub fn is_working_day(date: NaiveDate, holidays: &HashSet<NaiveDate>) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !holidays.contains(&date)
+}
+
+/// GET /teams/{team_id}/holidays?year=
+pub async fn list_holidays(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    query: web::Query<HolidayQuery>,
+) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    let mut filter = doc! { "team_id": &*team_id };
+    if let Some(year) = query.year {
+        filter.insert("date", doc! { "$gte": format!("{:04}-01-01", year), "$lte": format!("{:04}-12-31", year) });
+    }
+    let mut cursor = match holidays_coll(&data.mongodb).find(filter).sort(doc! { "date": 1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching holidays: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching holidays");
+        }
+    };
+    let mut holidays = Vec::new();
+    while let Some(h) = cursor.next().await {
+        match h {
+            Ok(h) => holidays.push(h),
+            Err(e) => error!("Error reading holiday: {}", e),
+        }
+    }
+    ok(holidays)
+}
+
+/// POST /teams/{team_id}/holidays
+pub async fn create_holiday(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<CreateHolidayRequest>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage holidays");
+    }
+    let name = payload.name.trim();
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return HttpResponse::BadRequest().body(format!("Name must be 1-{} characters", MAX_NAME_LEN));
+    }
+    let holiday = Holiday {
+        holiday_id: Uuid::new_v4().to_string(),
+        team_id: team_id.into_inner(),
+        date: payload.date,
+        name: name.to_string(),
+        source: HolidaySource::Custom,
+        country: None,
+        created_by: auth.user_id().to_string(),
+        created_at: Utc::now(),
+    };
+    match holidays_coll(&data.mongodb).insert_one(&holiday).await {
+        Ok(_) => HttpResponse::Created().json(holiday),
+        Err(e) if is_duplicate_key(&e) => HttpResponse::Conflict().body(format!("{} is already a holiday", holiday.date)),
+        Err(e) => {
+            error!("Error saving holiday: {}", e);
+            HttpResponse::InternalServerError().body("Error saving holiday")
+        }
+    }
+}
+
+/// PUT /teams/{team_id}/holidays/{holiday_id}
+pub async fn update_holiday(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<UpdateHolidayRequest>,
+) -> impl Responder {
+    let (team_id, holiday_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage holidays");
+    }
+    let filter = doc! { "holiday_id": &holiday_id, "team_id": &team_id };
+    let mut holiday = match holidays_coll(&data.mongodb).find_one(filter.clone()).await {
+        Ok(Some(h)) => h,
+        Ok(None) => return HttpResponse::NotFound().body("Holiday not found"),
+        Err(e) => {
+            error!("Error fetching holiday: {}", e);
+            return HttpResponse::InternalServerError().body("Error updating holiday");
+        }
+    };
+    if let Some(name) = payload.name.as_deref().map(str::trim) {
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return HttpResponse::BadRequest().body(format!("Name must be 1-{} characters", MAX_NAME_LEN));
+        }
+        holiday.name = name.to_string();
+    }
+    if let Some(date) = payload.date {
+        holiday.date = date;
+    }
+    match holidays_coll(&data.mongodb).replace_one(filter, &holiday).await {
+        Ok(_) => ok(holiday),
+        Err(e) if is_duplicate_key(&e) => HttpResponse::Conflict().body(format!("{} is already a holiday", holiday.date)),
+        Err(e) => {
+            error!("Error updating holiday: {}", e);
+            HttpResponse::InternalServerError().body("Error updating holiday")
+        }
+    }
+}
+
+/// DELETE /teams/{team_id}/holidays/{holiday_id}
+pub async fn delete_holiday(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (team_id, holiday_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage holidays");
+    }
+    match holidays_coll(&data.mongodb).delete_one(doc! { "holiday_id": &holiday_id, "team_id": &team_id }).await {
+        Ok(res) if res.deleted_count == 0 => HttpResponse::NotFound().body("Holiday not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Error deleting holiday: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting holiday")
+        }
+    }
+}
+
+/// POST /teams/{team_id}/holidays/import
+/// Adds the country's nationwide public holidays of the year; dates the team
+/// already has a holiday on are kept as they are.
+pub async fn import_holidays(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<ImportHolidaysRequest>,
+) -> impl Responder {
+    let team_id = team_id.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage holidays");
+    }
+    let country = payload.country.trim().to_ascii_uppercase();
+    if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+        return HttpResponse::BadRequest().body("country must be a two-letter ISO code");
+    }
+    let this_year = Utc::now().year();
+    if !(this_year - 1..=this_year + 5).contains(&payload.year) {
+        return HttpResponse::BadRequest().body(format!("year must be between {} and {}", this_year - 1, this_year + 5));
+    }
+
+    let url = format!("{}/{}/{}", PUBLIC_HOLIDAYS_URL, payload.year, country);
+    let resp = match data.http_client.get(&url).timeout(IMPORT_TIMEOUT).send().await {
+        Ok(r) => r,
+        Err(e) => {
+            warn!("Public holiday service unreachable: {}", e);
+            return HttpResponse::BadGateway().body("Public holiday service did not respond");
+        }
+    };
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return HttpResponse::BadRequest().body(format!("No public holidays known for {}", country));
+    }
+    if !resp.status().is_success() {
+        warn!("Public holiday service answered {}", resp.status());
+        return HttpResponse::BadGateway().body("Public holiday service failed");
+    }
+    // An unknown country code is answered with an empty body rather than a 404.
+    let found: Vec<PublicHoliday> = match resp.text().await.map(|body| serde_json::from_str(&body)) {
+        Ok(Ok(found)) => found,
+        Ok(Err(_)) => return HttpResponse::BadRequest().body(format!("No public holidays known for {}", country)),
+        Err(_) => return HttpResponse::BadGateway().body("Public holiday service sent an invalid reply"),
+    };
+
+    let mut result = ImportResult { imported: 0, skipped: 0 };
+    for public in found.into_iter().filter(|h| h.global) {
+        let holiday = Holiday {
+            holiday_id: Uuid::new_v4().to_string(),
+            team_id: team_id.clone(),
+            date: public.date,
+            name: public.name,
+            source: HolidaySource::Imported,
+            country: Some(country.clone()),
+            created_by: auth.user_id().to_string(),
+            created_at: Utc::now(),
+        };
+        match holidays_coll(&data.mongodb).insert_one(&holiday).await {
+            Ok(_) => result.imported += 1,
+            Err(e) if is_duplicate_key(&e) => result.skipped += 1,
+            Err(e) => {
+                error!("Error importing holiday: {}", e);
+                return HttpResponse::InternalServerError().body("Error importing holidays");
+            }
+        }
+    }
+    info!("User {} imported {} {} holidays for {} into team {}", auth.user_id(), result.imported, country, payload.year, team_id);
+    ok(result)
+}
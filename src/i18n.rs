@@ -0,0 +1,225 @@
+// src/i18n.rs
+//
+// Localized user-facing strings. The catalog maps a stable code to its text in each
+// supported language; English is the fallback for anything missing.
+//
+// Handlers answer errors by code through `response::CodedError`, which writes the
+// English text and tags the response with the code and its arguments.
+// `localize_layer` negotiates the language from `Accept-Language` and rewrites tagged
+// responses: the body becomes the translation, or, for clients that ask for JSON
+// only (`Accept: application/json` without text/plain or a wildcard), an `ApiError`
+// carrying the code as well. Untagged responses pass through unchanged. Digest
+// emails use the language saved in the user's notification preferences.
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::response::{ApiError, ErrorCode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+}
+
+impl Lang {
+    pub const SUPPORTED: [Lang; 2] = [Lang::En, Lang::De];
+
+    pub fn tag(self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::De => "de",
+        }
+    }
+
+    /// Matches the primary subtag, so "de-AT" is German.
+    pub fn parse(tag: &str) -> Option<Lang> {
+        let primary = tag.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+        Lang::SUPPORTED.into_iter().find(|l| l.tag() == primary)
+    }
+
+    /// The supported language the client prefers most, by `Accept-Language` q-values.
+    pub fn negotiate(accept_language: Option<&str>) -> Lang {
+        let mut best = (Lang::default(), 0.0_f32);
+        for range in accept_language.unwrap_or("").split(',') {
+            let mut parts = range.split(';');
+            let Some(lang) = parts.next().and_then(Lang::parse) else {
+                continue;
+            };
+            let q = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if q > best.1 {
+                best = (lang, q);
+            }
+        }
+        best.0
+    }
+}
+
+/// (code, English, German)
+const CATALOG: [(&str, &str, &str); 36] = [
+    ("not_team_member", "Not a member of this team", "Kein Mitglied dieses Teams"),
+    ("not_project_member", "Not a member of this project", "Kein Mitglied dieses Projekts"),
+    ("not_project_or_board_member", "Not a member of this project or board", "Kein Mitglied dieses Projekts oder Boards"),
+    ("not_chat_participant", "You are not a participant of this chat.", "Sie nehmen an diesem Chat nicht teil."),
+    ("admin_required", "Admin access required", "Administratorrechte erforderlich"),
+    ("unauthorized", "Unauthorized", "Nicht angemeldet"),
+    ("user_id_missing", "User ID missing", "Benutzer-ID fehlt"),
+    ("project_not_found", "Project not found", "Projekt nicht gefunden"),
+    ("ticket_not_found", "Ticket not found", "Ticket nicht gefunden"),
+    ("board_not_found", "Board not found", "Board nicht gefunden"),
+    ("team_not_found", "Team not found", "Team nicht gefunden"),
+    ("user_not_found", "User not found", "Benutzer nicht gefunden"),
+    ("document_not_found", "Document not found", "Dokument nicht gefunden"),
+    ("task_not_found", "Task not found", "Aufgabe nicht gefunden"),
+    ("rule_not_found", "Rule not found", "Regel nicht gefunden"),
+    ("whiteboard_not_found", "Whiteboard not found", "Whiteboard nicht gefunden"),
+    ("no_fields_to_update", "No fields to update", "Keine Felder zum Aktualisieren"),
+    (
+        "ticket_modified_concurrently",
+        "Ticket was modified concurrently, please retry",
+        "Das Ticket wurde gleichzeitig geändert, bitte erneut versuchen",
+    ),
+    ("error_fetching_ticket", "Error fetching ticket", "Fehler beim Laden des Tickets"),
+    ("error_fetching_tickets", "Error fetching tickets", "Fehler beim Laden der Tickets"),
+    ("impersonation_minutes", "minutes must be between 1 and {}", "minutes muss zwischen 1 und {} liegen"),
+    ("digest.subject.daily", "Your daily Taskline digest", "Ihre tägliche Taskline-Zusammenfassung"),
+    ("digest.subject.weekly", "Your weekly Taskline digest", "Ihre wöchentliche Taskline-Zusammenfassung"),
+    ("digest.heading", "Here is what happened since {}.", "Das ist seit {} passiert."),
+    ("digest.assigned", "Newly assigned to you", "Neu Ihnen zugewiesen"),
+    ("digest.mentions", "Mentions", "Erwähnungen"),
+    ("digest.upcoming", "Due soon", "Bald fällig"),
+    ("digest.sprints", "Sprint progress", "Sprint-Fortschritt"),
+    ("digest.more", "... and {} more", "... und {} weitere"),
+    ("digest.unsubscribe", "To stop receiving these emails, visit {}", "Um diese E-Mails abzubestellen, besuchen Sie {}"),
+    ("digest.mention_ticket", "On \"{}\": {}", "Zu \"{}\": {}"),
+    ("digest.mention_chat", "In chat: {}", "Im Chat: {}"),
+    ("digest.due", "{} (due {})", "{} (fällig am {})"),
+    ("digest.overdue", "{} (overdue since {})", "{} (überfällig seit {})"),
+    ("digest.sprint", "{} sprint {}: {}/{} done", "{} Sprint {}: {}/{} erledigt"),
+    ("digest.burndown", "Burndown chart: {}", "Burndown-Diagramm: {}"),
+];
+
+/// The text of `code` in `lang`; the code itself when it is not in the catalog.
+pub fn text(code: &'static str, lang: Lang) -> &'static str {
+    match CATALOG.iter().find(|(c, _, _)| *c == code) {
+        Some((_, en, de)) => match lang {
+            Lang::En => en,
+            Lang::De => de,
+        },
+        None => code,
+    }
+}
+
+/// `text(code, lang)` with each `{}` replaced by the next of `args`.
+pub fn format(code: &'static str, lang: Lang, args: &[&str]) -> String {
+    let mut args = args.iter();
+    let mut parts = text(code, lang).split("{}");
+    let mut out = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        out.push_str(args.next().copied().unwrap_or_default());
+        out.push_str(part);
+    }
+    out
+}
+
+fn wants_json(req: &actix_web::HttpRequest) -> bool {
+    let accept = req.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok()).unwrap_or("");
+    accept.contains("application/json") && !accept.contains("text/plain") && !accept.contains("*/*")
+}
+
+/// Middleware applying the negotiated language to error responses; see the top of
+/// the file.
+pub async fn localize_layer(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, Error> {
+    let lang = Lang::negotiate(req.headers().get(header::ACCEPT_LANGUAGE).and_then(|v| v.to_str().ok()));
+    let res = next.call(req).await?.map_into_boxed_body();
+    let Some(ErrorCode { code, args }) = res.response().extensions().get::<ErrorCode>().cloned() else {
+        return Ok(res);
+    };
+
+    let (req, res) = res.into_parts();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let message = format(code, lang, &args);
+    let mut localized = if wants_json(&req) {
+        HttpResponse::build(res.status()).json(ApiError { error: code, message })
+    } else {
+        HttpResponse::build(res.status()).content_type("text/plain; charset=utf-8").body(message)
+    };
+    for (name, value) in res.headers() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            localized.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    localized.headers_mut().insert(header::CONTENT_LANGUAGE, HeaderValue::from_static(lang.tag()));
+    Ok(ServiceResponse::new(req, localized))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{web, App};
+
+    use crate::response::CodedError;
+
+    #[test]
+    fn negotiation_follows_q_values() {
+        assert_eq!(Lang::negotiate(None), Lang::En);
+        assert_eq!(Lang::negotiate(Some("de-AT")), Lang::De);
+        assert_eq!(Lang::negotiate(Some("fr, de;q=0.8, en;q=0.5")), Lang::De);
+        assert_eq!(Lang::negotiate(Some("de;q=0.3, en-GB;q=0.9")), Lang::En);
+        assert_eq!(Lang::negotiate(Some("fr, es;q=0.9")), Lang::En);
+    }
+
+    #[test]
+    fn codes_format_with_their_arguments() {
+        assert_eq!(format("impersonation_minutes", Lang::De, &["60"]), "minutes muss zwischen 1 und 60 liegen");
+        assert_eq!(format("digest.sprint", Lang::En, &["Core", "3", "4", "9"]), "Core sprint 3: 4/9 done");
+        // Missing arguments leave their placeholder empty; unknown codes are returned as is.
+        assert_eq!(format("digest.due", Lang::En, &["Ship"]), "Ship (due )");
+        assert_eq!(text("no_such_code", Lang::De), "no_such_code");
+    }
+
+    #[test]
+    fn translations_take_the_same_arguments() {
+        for (i, (code, en, de)) in CATALOG.iter().enumerate() {
+            assert!(CATALOG[..i].iter().all(|(c, _, _)| c != code), "{} listed twice", code);
+            assert_eq!(en.matches("{}").count(), de.matches("{}").count(), "{}", code);
+        }
+    }
+
+    #[actix_web::test]
+    async fn coded_errors_are_translated_by_code() {
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(localize_layer))
+                .route("/coded", web::get().to(|| async { HttpResponse::BadRequest().error_with("impersonation_minutes", &["60"]) }))
+                .route("/plain", web::get().to(|| async { HttpResponse::BadRequest().body("Not a member of this team") })),
+        )
+        .await;
+
+        let german = TestRequest::get().uri("/coded").insert_header((header::ACCEPT_LANGUAGE, "de-DE, en;q=0.5")).to_request();
+        let res = call_service(&app, german).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.headers().get(header::CONTENT_LANGUAGE).unwrap(), "de");
+        assert_eq!(read_body(res).await, "minutes muss zwischen 1 und 60 liegen");
+
+        let json = TestRequest::get().uri("/coded").insert_header((header::ACCEPT, "application/json")).to_request();
+        let body: serde_json::Value = serde_json::from_slice(&read_body(call_service(&app, json).await).await).unwrap();
+        assert_eq!(body, serde_json::json!({ "error": "impersonation_minutes", "message": "minutes must be between 1 and 60" }));
+
+        // Untagged bodies are left alone, even when they read like catalog text.
+        let plain = TestRequest::get().uri("/plain").insert_header((header::ACCEPT_LANGUAGE, "de")).to_request();
+        assert_eq!(read_body(call_service(&app, plain).await).await, "Not a member of this team");
+    }
+}
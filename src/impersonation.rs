@@ -0,0 +1,209 @@
+// src/impersonation.rs
+//
+// Support impersonation. A platform admin can mint a short-lived token that acts as
+// another user; the token carries an `impersonated_by` claim, every request made with
+// it is written to `impersonation_audit` (as is every WebSocket message sent on a
+// connection opened with it), and responses carry `X-Impersonated-By` so clients can
+// show a banner. Impersonation tokens never pass the admin check, cannot create bots
+// or bot keys, and other admins cannot be impersonated.
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use log::{error, info};
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::admin::is_platform_admin;
+use crate::app_state::AppState;
+use crate::auth::create_impersonation_jwt;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::response::CodedError;
+
+pub const IMPERSONATION_HEADER: &str = "x-impersonated-by";
+const DEFAULT_MINUTES: i64 = 15;
+const MAX_MINUTES: i64 = 60;
+const AUDIT_PAGE: i64 = 200;
+
+/// Present in the request extensions when the caller is using an impersonation token.
+#[derive(Debug, Clone)]
+pub struct Impersonation {
+    pub admin_id: String,
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImpersonationAudit {
+    pub admin_id: String,
+    pub user_id: String,
+    /// "start" when the token is issued, "request" for each call made with it, "ws"
+    /// for each WebSocket message
+    pub action: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub status: Option<u16>,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImpersonateRequest {
+    /// Why support needs to act as this user; stored in the audit log
+    pub reason: String,
+    pub minutes: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImpersonationToken {
+    pub token: String,
+    pub user_id: String,
+    pub impersonated_by: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub admin_id: Option<String>,
+    pub user_id: Option<String>,
+}
+
+fn audit_log(db: &MongoDB) -> mongodb::Collection<ImpersonationAudit> {
+    db.db.collection::<ImpersonationAudit>("impersonation_audit")
+}
+
+/// Record a request made under impersonation. Called by the auth middleware once the
+/// handler has answered.
+pub async fn audit_request(db: &MongoDB, imp: &Impersonation, method: &str, path: &str, status: u16) {
+    let entry = ImpersonationAudit {
+        admin_id: imp.admin_id.clone(),
+        user_id: imp.user_id.clone(),
+        action: "request".to_string(),
+        reason: None,
+        method: Some(method.to_string()),
+        path: Some(path.to_string()),
+        status: Some(status),
+        at: Utc::now(),
+    };
+    if let Err(e) = audit_log(db).insert_one(&entry).await {
+        error!("Error writing impersonation audit for {} as {}: {}", imp.admin_id, imp.user_id, e);
+    }
+}
+
+/// Record a WebSocket message sent under impersonation; `path` names the message type
+/// and its target, e.g. `doc_update doc-1`.
+pub async fn audit_ws_message(db: &MongoDB, imp: &Impersonation, path: &str) {
+    let entry = ImpersonationAudit {
+        admin_id: imp.admin_id.clone(),
+        user_id: imp.user_id.clone(),
+        action: "ws".to_string(),
+        reason: None,
+        method: None,
+        path: Some(path.to_string()),
+        status: None,
+        at: Utc::now(),
+    };
+    if let Err(e) = audit_log(db).insert_one(&entry).await {
+        error!("Error writing impersonation audit for {} as {}: {}", imp.admin_id, imp.user_id, e);
+    }
+}
+
+/// POST /admin/impersonate/{user_id}
+pub async fn impersonate_user(
+    req: HttpRequest,
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    user_id: web::Path<String>,
+    payload: web::Json<ImpersonateRequest>,
+) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    let admin_id = auth.user_id().to_string();
+    let user_id = user_id.into_inner();
+    let reason = payload.reason.trim().to_string();
+    if reason.is_empty() {
+        return HttpResponse::BadRequest().body("A reason is required");
+    }
+    let minutes = payload.minutes.unwrap_or(DEFAULT_MINUTES);
+    if !(1..=MAX_MINUTES).contains(&minutes) {
+        return HttpResponse::BadRequest().error_with("impersonation_minutes", &[&MAX_MINUTES.to_string()]);
+    }
+    if user_id == admin_id || data.config.admin_user_ids.contains(&user_id) {
+        return HttpResponse::Forbidden().body("Admins cannot be impersonated");
+    }
+
+    let oid = match ObjectId::parse_str(&user_id) {
+        Ok(oid) => oid,
+        Err(_) => return HttpResponse::NotFound().error("user_not_found"),
+    };
+    let user = match data.mongodb.db.collection::<Document>("users").find_one(doc! { "_id": oid }).await {
+        Ok(Some(u)) => u,
+        Ok(None) => return HttpResponse::NotFound().error("user_not_found"),
+        Err(e) => {
+            error!("Error fetching user: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching user");
+        }
+    };
+    let team_id = user.get_str("team_id").unwrap_or("");
+
+    // Audit first: no token is handed out unless the start is on record.
+    let entry = ImpersonationAudit {
+        admin_id: admin_id.clone(),
+        user_id: user_id.clone(),
+        action: "start".to_string(),
+        reason: Some(reason),
+        method: None,
+        path: None,
+        status: None,
+        at: Utc::now(),
+    };
+    if let Err(e) = audit_log(&data.mongodb).insert_one(&entry).await {
+        error!("Error writing impersonation audit: {}", e);
+        return HttpResponse::InternalServerError().body("Error starting impersonation");
+    }
+
+    let expires_at = Utc::now() + chrono::Duration::minutes(minutes);
+    let token = create_impersonation_jwt(&user_id, team_id, &admin_id, expires_at, &data.config.jwt_secret);
+    info!("Admin {} started impersonating {} for {} minutes", admin_id, user_id, minutes);
+    HttpResponse::Ok().json(ImpersonationToken { token, user_id, impersonated_by: admin_id, expires_at })
+}
+
+/// GET /admin/impersonation/audit?admin_id=&user_id=
+/// Most recent entries first.
+pub async fn list_impersonation_audit(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<AuditQuery>,
+) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    let mut filter = doc! {};
+    if let Some(admin_id) = &query.admin_id {
+        filter.insert("admin_id", admin_id);
+    }
+    if let Some(user_id) = &query.user_id {
+        filter.insert("user_id", user_id);
+    }
+    let entries: Result<Vec<ImpersonationAudit>, _> = match audit_log(&data.mongodb)
+        .find(filter)
+        .sort(doc! { "at": -1 })
+        .limit(AUDIT_PAGE)
+        .await
+    {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    };
+    match entries {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => {
+            error!("Error fetching impersonation audit: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching impersonation audit")
+        }
+    }
+}
@@ -0,0 +1,263 @@
+// src/integrity.rs
+//
+// Referential integrity checks. Deleting a team, project, board or user does not
+// cascade everywhere, so rows can be left pointing at data that is gone: memberships
+// of deleted users, boards of deleted projects, tickets of deleted boards, chats
+// listing unknown participants. `scan` reports them; `repair` also removes them where
+// that is safe. Projects of deleted teams and messages of deleted chats are only
+// reported: the first need a decision about their content, the second may be under
+// legal hold. Used by /admin/integrity and by `taskline-admin verify`, so this module
+// only depends on the database.
+
+use futures_util::StreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use serde::Serialize;
+
+use crate::chat_db::MongoDB;
+
+/// Sample ids kept per check.
+const SAMPLE: usize = 5;
+/// Ids removed per `delete_many`.
+const BATCH: usize = 500;
+
+enum Target {
+    /// Documents of `child` whose `field` matches no `parent_field` in `parent`.
+    Reference { child: &'static str, field: &'static str, parent: &'static str, parent_field: &'static str },
+    /// Chat participants that are not users.
+    ChatParticipants,
+}
+
+enum Fix {
+    ReportOnly,
+    Delete,
+    /// Tickets go with their history, votes and watches.
+    DeleteTickets,
+    PullParticipants,
+}
+
+struct Check {
+    name: &'static str,
+    target: Target,
+    fix: Fix,
+}
+
+/// In repair order: boards of deleted projects are removed before the ticket check
+/// runs, so their tickets are found and removed in the same pass.
+const CHECKS: [Check; 9] = [
+    Check {
+        name: "user_teams.team_id -> teams",
+        target: Target::Reference { child: "user_teams", field: "team_id", parent: "teams", parent_field: "team_id" },
+        fix: Fix::Delete,
+    },
+    Check {
+        name: "user_teams.user_id -> users",
+        target: Target::Reference { child: "user_teams", field: "user_id", parent: "users", parent_field: "_id" },
+        fix: Fix::Delete,
+    },
+    Check {
+        name: "projects.team_id -> teams",
+        target: Target::Reference { child: "projects", field: "team_id", parent: "teams", parent_field: "team_id" },
+        fix: Fix::ReportOnly,
+    },
+    Check {
+        name: "project_memberships.project_id -> projects",
+        target: Target::Reference { child: "project_memberships", field: "project_id", parent: "projects", parent_field: "project_id" },
+        fix: Fix::Delete,
+    },
+    Check {
+        name: "project_memberships.user_id -> users",
+        target: Target::Reference { child: "project_memberships", field: "user_id", parent: "users", parent_field: "_id" },
+        fix: Fix::Delete,
+    },
+    Check {
+        name: "boards.project_id -> projects",
+        target: Target::Reference { child: "boards", field: "project_id", parent: "projects", parent_field: "project_id" },
+        fix: Fix::Delete,
+    },
+    Check {
+        name: "tickets.board_id -> boards",
+        target: Target::Reference { child: "tickets", field: "board_id", parent: "boards", parent_field: "board_id" },
+        fix: Fix::DeleteTickets,
+    },
+    Check {
+        name: "messages.id_chat -> chats",
+        target: Target::Reference { child: "messages", field: "id_chat", parent: "chats", parent_field: "_id" },
+        fix: Fix::ReportOnly,
+    },
+    Check { name: "chats.participants -> users", target: Target::ChatParticipants, fix: Fix::PullParticipants },
+];
+
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    /// Documents with a broken reference
+    pub count: u64,
+    /// A few of their `_id`s
+    pub sample: Vec<String>,
+    /// Whether `repair` fixes this check
+    pub repairable: bool,
+    /// Documents deleted or updated; always 0 in a dry run
+    pub repaired: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct IntegrityReport {
+    pub dry_run: bool,
+    pub findings: Vec<Finding>,
+    /// `(ticket_id, project_id)` of deleted tickets, for the caller to publish to sync
+    #[serde(skip)]
+    pub deleted_tickets: Vec<(String, String)>,
+}
+
+impl IntegrityReport {
+    /// Broken references left after the run.
+    #[allow(dead_code)] // called by taskline-admin only
+    pub fn remaining(&self) -> u64 {
+        self.findings.iter().map(|f| f.count.saturating_sub(f.repaired)).sum()
+    }
+}
+
+/// Users are keyed by ObjectId but referenced by its hex string.
+fn lookup(field: &str, parent: &str, parent_field: &str) -> Document {
+    if parent == "users" {
+        doc! { "$lookup": {
+            "from": parent,
+            "let": { "ref": format!("${}", field) },
+            "pipeline": [
+                { "$match": { "$expr": { "$eq": [{ "$toString": "$_id" }, "$$ref"] } } },
+                { "$project": { "_id": 1 } },
+            ],
+            "as": "_parent",
+        } }
+    } else {
+        doc! { "$lookup": { "from": parent, "localField": field, "foreignField": parent_field, "as": "_parent" } }
+    }
+}
+
+/// Pipeline over `collection(target)` yielding one document per broken row: `{_id}` for
+/// references, `{_id: chat id, unknown: [user ids]}` for chats.
+fn pipeline(target: &Target) -> Vec<Document> {
+    match target {
+        Target::Reference { field, parent, parent_field, .. } => vec![
+            lookup(field, parent, parent_field),
+            doc! { "$match": { "_parent": { "$size": 0 } } },
+            doc! { "$project": { "_id": 1 } },
+        ],
+        Target::ChatParticipants => vec![
+            doc! { "$project": { "participants": 1 } },
+            doc! { "$unwind": "$participants" },
+            doc! { "$match": { "participants": { "$not": { "$regex": "^bot:" } } } },
+            lookup("participants", "users", "_id"),
+            doc! { "$match": { "_parent": { "$size": 0 } } },
+            doc! { "$group": { "_id": "$_id", "unknown": { "$addToSet": "$participants" } } },
+        ],
+    }
+}
+
+fn collection(target: &Target) -> &'static str {
+    match target {
+        Target::Reference { child, .. } => child,
+        Target::ChatParticipants => "chats",
+    }
+}
+
+fn id_string(id: &Bson) -> String {
+    match id {
+        Bson::ObjectId(oid) => oid.to_hex(),
+        Bson::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+async fn broken(db: &MongoDB, target: &Target) -> mongodb::error::Result<Vec<Document>> {
+    let mut cursor = db.db.collection::<Document>(collection(target)).aggregate(pipeline(target)).allow_disk_use(true).await?;
+    let mut rows = Vec::new();
+    while let Some(row) = cursor.next().await {
+        rows.push(row?);
+    }
+    Ok(rows)
+}
+
+async fn delete_rows(db: &MongoDB, collection: &str, ids: &[Bson]) -> mongodb::error::Result<u64> {
+    let mut deleted = 0;
+    for chunk in ids.chunks(BATCH) {
+        deleted += db.db.collection::<Document>(collection).delete_many(doc! { "_id": { "$in": chunk } }).await?.deleted_count;
+    }
+    Ok(deleted)
+}
+
+/// Deletes the tickets with `_id`s in `ids` and everything keyed by their ticket id.
+/// Returns the number of tickets deleted and their `(ticket_id, project_id)`.
+async fn delete_tickets(db: &MongoDB, ids: &[Bson]) -> mongodb::error::Result<(u64, Vec<(String, String)>)> {
+    let tickets = db.db.collection::<Document>("tickets");
+    let mut deleted = 0;
+    let mut removed = Vec::new();
+    for chunk in ids.chunks(BATCH) {
+        let mut cursor = tickets
+            .find(doc! { "_id": { "$in": chunk } })
+            .projection(doc! { "ticket_id": 1, "project_id": 1 })
+            .await?;
+        let mut ticket_ids = Vec::new();
+        while let Some(t) = cursor.next().await {
+            let t = t?;
+            if let Ok(ticket_id) = t.get_str("ticket_id") {
+                ticket_ids.push(ticket_id.to_string());
+                removed.push((ticket_id.to_string(), t.get_str("project_id").unwrap_or_default().to_string()));
+            }
+        }
+        for coll in ["ticket_events", "ticket_votes", "ticket_watchers"] {
+            db.db.collection::<Document>(coll).delete_many(doc! { "ticket_id": { "$in": &ticket_ids } }).await?;
+        }
+        deleted += tickets.delete_many(doc! { "_id": { "$in": chunk } }).await?.deleted_count;
+    }
+    Ok((deleted, removed))
+}
+
+/// Runs every check; with `repair`, fixes what can be fixed as it goes.
+async fn run(db: &MongoDB, repair: bool) -> mongodb::error::Result<IntegrityReport> {
+    let mut report = IntegrityReport { dry_run: !repair, ..Default::default() };
+    for check in &CHECKS {
+        let rows = broken(db, &check.target).await?;
+        let ids: Vec<Bson> = rows.iter().filter_map(|r| r.get("_id").cloned()).collect();
+        let mut finding = Finding {
+            check: check.name,
+            count: rows.len() as u64,
+            sample: ids.iter().take(SAMPLE).map(id_string).collect(),
+            repairable: !matches!(check.fix, Fix::ReportOnly),
+            repaired: 0,
+        };
+        if repair && !rows.is_empty() {
+            finding.repaired = match check.fix {
+                Fix::ReportOnly => 0,
+                Fix::Delete => delete_rows(db, collection(&check.target), &ids).await?,
+                Fix::DeleteTickets => {
+                    let (deleted, removed) = delete_tickets(db, &ids).await?;
+                    report.deleted_tickets.extend(removed);
+                    deleted
+                }
+                Fix::PullParticipants => {
+                    let chats = db.db.collection::<Document>("chats");
+                    let mut updated = 0;
+                    for row in &rows {
+                        let unknown = row.get_array("unknown").cloned().unwrap_or_default();
+                        let pull = doc! { "$pull": { "participants": { "$in": &unknown }, "admins": { "$in": &unknown } } };
+                        updated += chats.update_one(doc! { "_id": row.get("_id").cloned() }, pull).await?.modified_count;
+                    }
+                    updated
+                }
+            };
+        }
+        report.findings.push(finding);
+    }
+    Ok(report)
+}
+
+/// Reports broken references without changing anything.
+pub async fn scan(db: &MongoDB) -> mongodb::error::Result<IntegrityReport> {
+    run(db, false).await
+}
+
+/// Removes the broken references that can be removed and reports the rest.
+pub async fn repair(db: &MongoDB) -> mongodb::error::Result<IntegrityReport> {
+    run(db, true).await
+}
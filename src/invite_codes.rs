@@ -0,0 +1,178 @@
+// src/invite_codes.rs
+//
+// Invite codes for closed signups. With SIGNUP_INVITE_ONLY set, signup needs a code
+// created by a platform admin; each code may be limited in uses and time, and
+// counts how often it was redeemed.
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::admin::is_platform_admin;
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::response::CodedError;
+
+const MAX_CODE_LEN: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InviteCode {
+    pub code: String,
+    pub note: Option<String>,
+    /// Unlimited when unset
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInviteCodeRequest {
+    /// Generated when omitted
+    pub code: Option<String>,
+    pub note: Option<String>,
+    pub max_uses: Option<u32>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn codes(db: &MongoDB) -> mongodb::Collection<InviteCode> {
+    db.db.collection::<InviteCode>("invite_codes")
+}
+
+fn normalize(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+/// Takes one use of the code. Returns the reason when the code cannot be used.
+pub(crate) async fn redeem(db: &MongoDB, code: &str) -> Result<(), &'static str> {
+    let code = normalize(code);
+    let invalid = "Invalid or expired invite code";
+    let found = match codes(db).find_one(doc! { "code": &code }).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return Err(invalid),
+        Err(e) => {
+            error!("Error fetching invite code: {}", e);
+            return Err("Error checking invite code");
+        }
+    };
+    if found.expires_at.is_some_and(|t| t <= Utc::now()) {
+        return Err(invalid);
+    }
+    // The use limit is part of the filter, so concurrent signups cannot exceed it.
+    let mut filter = doc! { "code": &code };
+    if let Some(max) = found.max_uses {
+        filter.insert("uses", doc! { "$lt": max as i64 });
+    }
+    let update = doc! { "$inc": { "uses": 1 }, "$set": { "last_used_at": Utc::now().to_rfc3339() } };
+    match codes(db).update_one(filter, update).await {
+        Ok(r) if r.modified_count == 1 => Ok(()),
+        Ok(_) => Err("This invite code has been used up"),
+        Err(e) => {
+            error!("Error redeeming invite code: {}", e);
+            Err("Error checking invite code")
+        }
+    }
+}
+
+/// Gives back a use taken by `redeem` when the signup failed afterwards.
+pub(crate) async fn release(db: &MongoDB, code: &str) {
+    let filter = doc! { "code": normalize(code), "uses": { "$gt": 0 } };
+    if let Err(e) = codes(db).update_one(filter, doc! { "$inc": { "uses": -1 } }).await {
+        error!("Error releasing invite code: {}", e);
+    }
+}
+
+/// GET /admin/invite-codes
+pub async fn list_invite_codes(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    let mut cursor = match codes(&data.mongodb).find(doc! {}).sort(doc! { "created_at": -1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching invite codes: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching invite codes");
+        }
+    };
+    let mut out = Vec::new();
+    while let Ok(true) = cursor.advance().await {
+        if let Ok(c) = cursor.deserialize_current() {
+            out.push(c);
+        }
+    }
+    HttpResponse::Ok().json(out)
+}
+
+/// POST /admin/invite-codes
+pub async fn create_invite_code(
+    req: HttpRequest,
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    payload: web::Json<CreateInviteCodeRequest>,
+) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    let body = payload.into_inner();
+    let code = match body.code.as_deref().map(normalize) {
+        Some(c) if c.is_empty() || c.len() > MAX_CODE_LEN || !c.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-') => {
+            return HttpResponse::BadRequest().body(format!("code must be 1-{} letters, digits or dashes", MAX_CODE_LEN));
+        }
+        Some(c) => c,
+        None => {
+            let raw = Uuid::new_v4().simple().to_string().to_uppercase();
+            format!("{}-{}", &raw[..4], &raw[4..8])
+        }
+    };
+    if body.max_uses == Some(0) {
+        return HttpResponse::BadRequest().body("max_uses must be at least 1");
+    }
+    if body.expires_at.is_some_and(|t| t <= Utc::now()) {
+        return HttpResponse::BadRequest().body("expires_at must be in the future");
+    }
+    let invite = InviteCode {
+        code,
+        note: body.note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()),
+        max_uses: body.max_uses,
+        uses: 0,
+        expires_at: body.expires_at,
+        created_by: auth.user_id().to_string(),
+        created_at: Utc::now(),
+        last_used_at: None,
+    };
+    match codes(&data.mongodb).insert_one(&invite).await {
+        Ok(_) => {
+            info!("Admin {} created invite code {}", invite.created_by, invite.code);
+            HttpResponse::Created().json(invite)
+        }
+        Err(e) if matches!(&*e.kind, mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(w)) if w.code == 11000) => {
+            HttpResponse::Conflict().body("This invite code already exists")
+        }
+        Err(e) => {
+            error!("Error creating invite code: {}", e);
+            HttpResponse::InternalServerError().body("Error creating invite code")
+        }
+    }
+}
+
+/// DELETE /admin/invite-codes/{code}
+/// Accounts created with the code are kept.
+pub async fn delete_invite_code(req: HttpRequest, data: web::Data<AppState>, code: web::Path<String>) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    match codes(&data.mongodb).delete_one(doc! { "code": normalize(&code) }).await {
+        Ok(r) if r.deleted_count == 0 => HttpResponse::NotFound().body("Invite code not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Error deleting invite code: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting invite code")
+        }
+    }
+}
@@ -6,12 +6,33 @@ use futures::stream::StreamExt;
 use mongodb::bson::{doc, Uuid};
 use serde::{Deserialize, Serialize};
 
+use crate::activity::{record_activity, ActivityEvent};
+use crate::ai_endpoints::{cached_embeddings, embed, rank, remove_embedding};
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::fields::{select, FieldSet, FieldsQuery};
+use crate::response::CodedError;
+use crate::sync::{record_change, Entity, Op, Scope};
+use crate::team_management::resolve_user_id;
 use crate::AppState;
 
 /* -------------------------------------------------------------------------- */
 /* Models                                                                     */
 /* -------------------------------------------------------------------------- */
 
+/// Who besides the author can read and edit a document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// The author only
+    Private,
+    /// The author and the users in `shared_with`
+    Members,
+    /// Every member of the team
+    #[default]
+    Team,
+}
+
 /// Internal model – stored exactly as it lives in MongoDB.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Document {
@@ -24,6 +45,81 @@ pub struct Document {
     pub content: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Absent on documents created before sharing existed
+    #[serde(default)]
+    pub author_id: Option<String>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// Readers of a `members` document besides the author
+    #[serde(default)]
+    pub shared_with: Vec<String>,
+}
+
+impl Document {
+    /// Whether a member of the document's team may read (and edit) it.
+    pub fn readable_by(&self, user_id: &str) -> bool {
+        match self.visibility {
+            Visibility::Team => true,
+            Visibility::Private => self.author_id.as_deref() == Some(user_id),
+            Visibility::Members => {
+                self.author_id.as_deref() == Some(user_id) || self.shared_with.iter().any(|u| u == user_id)
+            }
+        }
+    }
+
+    /// Users who can see a restricted document; None for team documents.
+    fn audience(&self) -> Option<Vec<String>> {
+        if self.visibility == Visibility::Team {
+            return None;
+        }
+        let mut users: Vec<String> = self.author_id.iter().cloned().collect();
+        if self.visibility == Visibility::Members {
+            for u in &self.shared_with {
+                if !users.contains(u) {
+                    users.push(u.clone());
+                }
+            }
+        }
+        Some(users)
+    }
+}
+
+/// Mongo filter for the documents of `team_id` that `user_id` may read.
+fn readable_filter(team_id: &str, user_id: &str) -> mongodb::bson::Document {
+    let mut filter = readable_by_filter(user_id);
+    filter.insert("team_id", team_id);
+    filter
+}
+
+/// Mongo filter for documents `user_id` may read, in whichever of their teams.
+pub(crate) fn readable_by_filter(user_id: &str) -> mongodb::bson::Document {
+    doc! {
+        "$or": [
+            { "visibility": { "$exists": false } },
+            { "visibility": "team" },
+            { "author_id": user_id },
+            { "visibility": "members", "shared_with": user_id },
+        ],
+    }
+}
+
+/// Whether `user_id` may open the document, e.g. for live editing.
+pub async fn can_access(db: &MongoDB, doc_id: &str, user_id: &str) -> Result<bool, String> {
+    let collection = db.db.collection::<Document>("knowledge_base");
+    let d = match collection.find_one(doc! { "_id": doc_id }).await.map_err(|e| e.to_string())? {
+        Some(d) => d,
+        None => return Ok(false),
+    };
+    let member = db.check_user_team(user_id, &d.team_id).await.map_err(|e| e.to_string())?;
+    Ok(member && d.readable_by(user_id))
+}
+
+/// Append a sync entry visible to exactly the document's readers.
+pub async fn record_doc_change(db: &MongoDB, d: &Document, op: Op) {
+    match d.audience() {
+        None => record_change(db, Entity::Doc, &d.id, op, Scope::Team(&d.team_id)).await,
+        Some(users) => record_change(db, Entity::Doc, &d.id, op, Scope::Users(&users)).await,
+    }
 }
 
 /// What we expose to the frontend.
@@ -35,6 +131,9 @@ pub struct PublicDocument {
     pub content: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub author_id: Option<String>,
+    pub visibility: Visibility,
+    pub shared_with: Vec<String>,
 }
 
 impl From<Document> for PublicDocument {
@@ -46,6 +145,9 @@ impl From<Document> for PublicDocument {
             content: d.content,
             created_at: d.created_at,
             updated_at: d.updated_at,
+            author_id: d.author_id,
+            visibility: d.visibility,
+            shared_with: d.shared_with,
         }
     }
 }
@@ -57,6 +159,18 @@ pub struct CreateDocumentRequest {
     pub team_id: String,
     pub title: String,
     pub content: String,
+    /// Defaults to `team`
+    pub visibility: Option<Visibility>,
+    /// User ids, emails or usernames; only used for `members`
+    pub shared_with: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShareDocumentRequest {
+    pub visibility: Visibility,
+    /// User ids, emails or usernames; only used for `members`
+    #[serde(default)]
+    pub shared_with: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -65,15 +179,138 @@ pub struct UpdateDocumentRequest {
     pub content: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SemanticSearchQuery {
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SemanticSearchHit {
+    pub document: PublicDocument,
+    /// Cosine similarity to the query, 0-1
+    pub score: f32,
+}
+
+/* -------------------------------------------------------------------------- */
+/* Embedding index                                                            */
+/* -------------------------------------------------------------------------- */
+
+const EMBEDDING_KIND: &str = "kb_doc";
+/// Characters of a document that go into its embedding.
+const EMBEDDING_TEXT_CHARS: usize = 8_000;
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+const MAX_SEARCH_LIMIT: usize = 50;
+
+fn embedding_text(d: &Document) -> String {
+    format!("{}\n\n{}", d.title, d.content).chars().take(EMBEDDING_TEXT_CHARS).collect()
+}
+
+fn embedding_key(d: &Document) -> (String, String) {
+    (d.id.clone(), embedding_text(d))
+}
+
+/// The team's documents readable by `user_id`, ranked by similarity to `query`,
+/// best match first.
+pub async fn rank_documents(
+    data: &AppState,
+    team_id: &str,
+    user_id: &str,
+    query: &[f32],
+    limit: usize,
+) -> Result<Vec<SemanticSearchHit>, String> {
+    let collection = data.mongodb.db.collection::<Document>("knowledge_base");
+    let mut cursor = collection
+        .find(readable_filter(team_id, user_id))
+        .await
+        .map_err(|e| format!("Fetch failed: {e}"))?;
+    let mut docs = Vec::new();
+    while let Some(Ok(d)) = cursor.next().await {
+        docs.push(d);
+    }
+    let ranked = rank(data, EMBEDDING_KIND, query, docs, embedding_key).await?;
+    Ok(ranked
+        .into_iter()
+        .take(limit)
+        .map(|(d, score)| SemanticSearchHit { document: PublicDocument::from(d), score })
+        .collect())
+}
+
+/// (Re)computes a document's embedding in the background after a write; search
+/// embeds anything still missing, so a failure here only costs latency later.
+fn index_document(data: &web::Data<AppState>, d: &Document) {
+    let data = data.clone();
+    let item = embedding_key(d);
+    actix_web::rt::spawn(async move {
+        if let Err(e) = cached_embeddings(&data, EMBEDDING_KIND, vec![item]).await {
+            log::warn!("Failed to index knowledge base document: {}", e);
+        }
+    });
+}
+
 /* -------------------------------------------------------------------------- */
 /* Handlers                                                                   */
 /* -------------------------------------------------------------------------- */
 
+/// Caller must be on the document's team and able to read it; unreadable
+/// documents are reported as missing.
+async fn readable_document(auth: &AuthContext, data: &AppState, id: &str) -> Result<Document, HttpResponse> {
+    let collection = data.mongodb.db.collection::<Document>("knowledge_base");
+    let d = match collection.find_one(doc! { "_id": id }).await {
+        Ok(Some(d)) => d,
+        Ok(None) => return Err(HttpResponse::NotFound().error("document_not_found")),
+        Err(e) => return Err(HttpResponse::InternalServerError().body(format!("Fetch failed: {e}"))),
+    };
+    if !auth.is_team_member(&d.team_id).await {
+        return Err(HttpResponse::Unauthorized().error("not_team_member"));
+    }
+    if !d.readable_by(auth.user_id()) {
+        return Err(HttpResponse::NotFound().error("document_not_found"));
+    }
+    Ok(d)
+}
+
+/// Resolves `shared_with` to team members' ids, dropping the author.
+async fn resolve_members(
+    data: &AppState,
+    team_id: &str,
+    author_id: &str,
+    references: &[String],
+) -> Result<Vec<String>, HttpResponse> {
+    let mut members = Vec::new();
+    for reference in references {
+        let user_id = match resolve_user_id(&data.mongodb, reference).await {
+            Some(id) => id,
+            None => return Err(HttpResponse::BadRequest().body(format!("Unknown user: {reference}"))),
+        };
+        if !data.mongodb.check_user_team(&user_id, team_id).await.unwrap_or(false) {
+            return Err(HttpResponse::BadRequest().body(format!("{reference} is not a member of this team")));
+        }
+        if user_id != author_id && !members.contains(&user_id) {
+            members.push(user_id);
+        }
+    }
+    Ok(members)
+}
+
 /// POST /knowledge_base
 pub async fn create_document(
+    auth: AuthContext,
     data: web::Data<AppState>,
     req: web::Json<CreateDocumentRequest>,
 ) -> impl Responder {
+    if !auth.is_team_member(&req.team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    let actor = auth.user_id().to_string();
+    let visibility = req.visibility.unwrap_or_default();
+    let shared_with = match (visibility, &req.shared_with) {
+        (Visibility::Members, Some(refs)) => match resolve_members(&data, &req.team_id, &actor, refs).await {
+            Ok(members) => members,
+            Err(resp) => return resp,
+        },
+        _ => Vec::new(),
+    };
     let collection = data.mongodb.db.collection::<Document>("knowledge_base");
 
     let now = Utc::now();
@@ -84,10 +321,24 @@ pub async fn create_document(
         content: req.content.clone(),
         created_at: now,
         updated_at: now,
+        author_id: Some(actor.clone()),
+        visibility,
+        shared_with,
     };
 
     match collection.insert_one(&new_doc).await {
-        Ok(_) => HttpResponse::Ok().json(PublicDocument::from(new_doc)),
+        Ok(_) => {
+            // Restricted documents stay out of the team's activity feed.
+            if new_doc.visibility == Visibility::Team {
+                record_activity(&data.mongodb, ActivityEvent::new(
+                    &new_doc.team_id, None, &actor, "doc_created", &new_doc.id,
+                    format!("created document \"{}\"", new_doc.title),
+                )).await;
+            }
+            record_doc_change(&data.mongodb, &new_doc, Op::Upsert).await;
+            index_document(&data, &new_doc);
+            HttpResponse::Ok().json(PublicDocument::from(new_doc))
+        }
         Err(e) => HttpResponse::InternalServerError()
             .body(format!("Failed to save document: {e}")),
     }
@@ -95,13 +346,19 @@ pub async fn create_document(
 
 /// GET /knowledge_base/{team_id}
 pub async fn get_team_documents(
+    auth: AuthContext,
     data: web::Data<AppState>,
     team_id: web::Path<String>,
+    query: web::Query<FieldsQuery>,
 ) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
     let collection = data.mongodb.db.collection::<Document>("knowledge_base");
+    let fields = FieldSet::parse(query.fields.as_deref(), &["id"]);
 
     match collection
-        .find(doc! { "team_id": team_id.as_str() })
+        .find(readable_filter(&team_id, auth.user_id()))
         .await
     {
         Ok(mut cursor) => {
@@ -111,7 +368,7 @@ pub async fn get_team_documents(
                     docs.push(PublicDocument::from(d));
                 }
             }
-            HttpResponse::Ok().json(docs)
+            HttpResponse::Ok().json(select(&docs, &fields))
         }
         Err(e) => HttpResponse::InternalServerError()
             .body(format!("Fetch failed: {e}")),
@@ -120,25 +377,26 @@ pub async fn get_team_documents(
 
 /// GET /knowledge_base/doc/{id}
 pub async fn get_document(
+    auth: AuthContext,
     data: web::Data<AppState>,
     id: web::Path<String>,
 ) -> impl Responder {
-    let collection = data.mongodb.db.collection::<Document>("knowledge_base");
-
-    match collection.find_one(doc! { "_id": id.as_str() }).await {
-        Ok(Some(doc)) => HttpResponse::Ok().json(PublicDocument::from(doc)),
-        Ok(None)      => HttpResponse::NotFound().body("Document not found"),
-        Err(e)        => HttpResponse::InternalServerError()
-            .body(format!("Fetch failed: {e}")),
+    match readable_document(&auth, &data, &id).await {
+        Ok(doc) => HttpResponse::Ok().json(PublicDocument::from(doc)),
+        Err(resp) => resp,
     }
 }
 
 /// PUT /knowledge_base/doc/{id}
 pub async fn update_document(
+    auth: AuthContext,
     data: web::Data<AppState>,
     id: web::Path<String>,
     payload: web::Json<UpdateDocumentRequest>,
 ) -> impl Responder {
+    if let Err(resp) = readable_document(&auth, &data, &id).await {
+        return resp;
+    }
     let collection = data.mongodb.db.collection::<Document>("knowledge_base");
 
     /* ------- build the $set object -------- */
@@ -152,7 +410,7 @@ pub async fn update_document(
     /* ------- 1) perform the update -------- */
     match collection.update_one(filter.clone(), update).await {
         Ok(res) if res.matched_count == 0 => {
-            return HttpResponse::NotFound().body("Document not found")
+            return HttpResponse::NotFound().error("document_not_found")
         }
         Ok(_) => { /* fall‑through */ }
         Err(e) => {
@@ -163,7 +421,17 @@ pub async fn update_document(
 
     /* ------- 2) fetch the updated doc ----- */
     match collection.find_one(filter).await {
-        Ok(Some(doc)) => HttpResponse::Ok().json(PublicDocument::from(doc)),
+        Ok(Some(doc)) => {
+            if doc.visibility == Visibility::Team {
+                record_activity(&data.mongodb, ActivityEvent::new(
+                    &doc.team_id, None, auth.user_id(), "doc_edited", &doc.id,
+                    format!("edited document \"{}\"", doc.title),
+                )).await;
+            }
+            record_doc_change(&data.mongodb, &doc, Op::Upsert).await;
+            index_document(&data, &doc);
+            HttpResponse::Ok().json(PublicDocument::from(doc))
+        }
         Ok(None)      => HttpResponse::InternalServerError()
             .body("Document updated but could not be re‑fetched"),
         Err(e)        => HttpResponse::InternalServerError()
@@ -171,20 +439,116 @@ pub async fn update_document(
     }
 }
 
+/// PUT /knowledge_base/{doc_id}/sharing
+/// Only the author may change who can see a document; documents created before
+/// sharing existed are claimed by the team admin who first restricts them.
+pub async fn share_document(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    payload: web::Json<ShareDocumentRequest>,
+) -> impl Responder {
+    let existing = match readable_document(&auth, &data, &id).await {
+        Ok(d) => d,
+        Err(resp) => return resp,
+    };
+    let author_id = match &existing.author_id {
+        Some(author) if author == auth.user_id() => author.clone(),
+        None if auth.is_team_admin(&existing.team_id).await => auth.user_id().to_string(),
+        _ => return HttpResponse::Forbidden().body("Only the document's author can change its sharing"),
+    };
+    let shared_with = if payload.visibility == Visibility::Members {
+        match resolve_members(&data, &existing.team_id, &author_id, &payload.shared_with).await {
+            Ok(members) => members,
+            Err(resp) => return resp,
+        }
+    } else {
+        Vec::new()
+    };
+
+    let collection = data.mongodb.db.collection::<Document>("knowledge_base");
+    let update = doc! { "$set": {
+        "author_id": &author_id,
+        "visibility": mongodb::bson::to_bson(&payload.visibility).unwrap_or_default(),
+        "shared_with": &shared_with,
+    } };
+    let updated = match collection
+        .find_one_and_update(doc! { "_id": id.as_str() }, update)
+        .return_document(mongodb::options::ReturnDocument::After)
+        .await
+    {
+        Ok(Some(d)) => d,
+        Ok(None) => return HttpResponse::NotFound().error("document_not_found"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Update failed: {e}")),
+    };
+
+    // When the audience narrows, every team member drops their synced copy; the
+    // upsert that follows is the latest entry for everyone who kept access.
+    let narrowed = match (existing.audience(), updated.audience()) {
+        (_, None) => false,
+        (None, Some(_)) => true,
+        (Some(before), Some(after)) => before.iter().any(|u| !after.contains(u)),
+    };
+    if narrowed {
+        record_change(&data.mongodb, Entity::Doc, &existing.id, Op::Delete, Scope::Team(&existing.team_id)).await;
+    }
+    record_doc_change(&data.mongodb, &updated, Op::Upsert).await;
+    HttpResponse::Ok().json(PublicDocument::from(updated))
+}
+
 /// DELETE /knowledge_base/doc/{id}
+/// Restricted documents can only be deleted by their author.
 pub async fn delete_document(
+    auth: AuthContext,
     data: web::Data<AppState>,
     id: web::Path<String>,
 ) -> impl Responder {
+    match readable_document(&auth, &data, &id).await {
+        Ok(d) if d.visibility != Visibility::Team && d.author_id.as_deref() != Some(auth.user_id()) => {
+            return HttpResponse::Forbidden().body("Only the document's author can delete it");
+        }
+        Ok(_) => {}
+        Err(resp) => return resp,
+    }
     let collection = data.mongodb.db.collection::<Document>("knowledge_base");
 
     match collection
-        .delete_one(doc! { "_id": id.as_str() })
+        .find_one_and_delete(doc! { "_id": id.as_str() })
          .await
     {
-        Ok(res) if res.deleted_count == 1 => HttpResponse::NoContent().finish(),
-        Ok(_)  => HttpResponse::NotFound().body("Document not found"),
+        Ok(Some(deleted)) => {
+            record_doc_change(&data.mongodb, &deleted, Op::Delete).await;
+            remove_embedding(&data, EMBEDDING_KIND, &deleted.id).await;
+            HttpResponse::NoContent().finish()
+        }
+        Ok(None) => HttpResponse::NotFound().error("document_not_found"),
         Err(e) => HttpResponse::InternalServerError()
             .body(format!("Delete failed: {e}")),
     }
 }
+
+/// GET /knowledge_base/{team_id}/semantic_search?q=
+pub async fn semantic_search(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    query: web::Query<SemanticSearchQuery>,
+) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    let q = query.q.trim();
+    if q.is_empty() {
+        return HttpResponse::BadRequest().body("q is required");
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT);
+
+    let query_vector = match embed(&data, &[q.to_string()]).await {
+        Ok(mut v) => v.remove(0),
+        Err(e) => return HttpResponse::BadGateway().body(e),
+    };
+    match rank_documents(&data, &team_id, auth.user_id(), &query_vector, limit).await {
+        Ok(hits) => HttpResponse::Ok().json(hits),
+        Err(e) => HttpResponse::BadGateway().body(e),
+    }
+}
@@ -0,0 +1,268 @@
+// src/legal_hold.rs
+//
+// Legal holds on top of retention. A team admin can place a chat, or a member, of
+// the team under hold; while it is active the retention job keeps every message of
+// that chat, or sent by that user, and a held chat cannot be deleted. Placing and
+// releasing a hold are written to `legal_hold_audit`, which is never purged.
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::{error, info};
+use mongodb::bson::{doc, Document};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::response::ok;
+
+const AUDIT_PAGE: i64 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldTarget {
+    Chat,
+    User,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegalHold {
+    pub hold_id: String,
+    pub team_id: String,
+    pub target_type: HoldTarget,
+    /// Chat id or user id, depending on `target_type`
+    pub target_id: String,
+    pub reason: String,
+    pub placed_by: String,
+    pub placed_at: DateTime<Utc>,
+    /// Active until released
+    pub released_by: Option<String>,
+    pub released_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LegalHoldAudit {
+    pub team_id: String,
+    pub hold_id: String,
+    /// "placed" or "released"
+    pub action: String,
+    pub target_type: HoldTarget,
+    pub target_id: String,
+    pub reason: Option<String>,
+    pub actor_id: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlaceHoldRequest {
+    pub target_type: HoldTarget,
+    pub target_id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReleaseHoldQuery {
+    pub reason: Option<String>,
+}
+
+/// Chats and users of a team currently under hold.
+#[derive(Debug, Default)]
+pub struct ActiveHolds {
+    pub chat_ids: Vec<String>,
+    pub user_ids: Vec<String>,
+}
+
+fn holds_coll(db: &MongoDB) -> mongodb::Collection<LegalHold> {
+    db.db.collection::<LegalHold>("legal_holds")
+}
+
+pub async fn active_holds(db: &MongoDB, team_id: &str) -> mongodb::error::Result<ActiveHolds> {
+    let mut holds = ActiveHolds::default();
+    let mut cursor = holds_coll(db).find(doc! { "team_id": team_id, "released_at": null }).await?;
+    while let Some(hold) = cursor.next().await {
+        let hold = hold?;
+        match hold.target_type {
+            HoldTarget::Chat => holds.chat_ids.push(hold.target_id),
+            HoldTarget::User => holds.user_ids.push(hold.target_id),
+        }
+    }
+    Ok(holds)
+}
+
+/// Whether the chat, or any of `participants`, is under an active hold in any team.
+pub async fn chat_under_hold(db: &MongoDB, chat_id: &str, participants: &[String]) -> mongodb::error::Result<bool> {
+    let filter = doc! {
+        "released_at": null,
+        "$or": [
+            { "target_type": "chat", "target_id": chat_id },
+            { "target_type": "user", "target_id": { "$in": participants } },
+        ],
+    };
+    Ok(holds_coll(db).find_one(filter).await?.is_some())
+}
+
+async fn audit(db: &MongoDB, hold: &LegalHold, action: &str, reason: Option<String>, actor_id: &str) {
+    let entry = LegalHoldAudit {
+        team_id: hold.team_id.clone(),
+        hold_id: hold.hold_id.clone(),
+        action: action.to_string(),
+        target_type: hold.target_type,
+        target_id: hold.target_id.clone(),
+        reason,
+        actor_id: actor_id.to_string(),
+        at: Utc::now(),
+    };
+    if let Err(e) = db.db.collection::<LegalHoldAudit>("legal_hold_audit").insert_one(&entry).await {
+        error!("Error writing legal hold audit for {}: {}", hold.hold_id, e);
+    }
+}
+
+/// GET /teams/{team_id}/retention/holds
+/// Active holds, newest first.
+pub async fn list_holds(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage legal holds");
+    }
+    let filter = doc! { "team_id": &*team_id, "released_at": null };
+    let mut cursor = match holds_coll(&data.mongodb).find(filter).sort(doc! { "placed_at": -1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching legal holds: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching legal holds");
+        }
+    };
+    let mut holds = Vec::new();
+    while let Some(h) = cursor.next().await {
+        match h {
+            Ok(h) => holds.push(h),
+            Err(e) => error!("Error reading legal hold: {}", e),
+        }
+    }
+    ok(holds)
+}
+
+/// POST /teams/{team_id}/retention/holds
+/// The chat must belong to the team, the user must be a member of it.
+pub async fn place_hold(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<PlaceHoldRequest>,
+) -> impl Responder {
+    let team_id = team_id.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage legal holds");
+    }
+    let req = payload.into_inner();
+    if req.reason.trim().is_empty() {
+        return HttpResponse::BadRequest().body("A reason is required");
+    }
+    let db = &data.mongodb.db;
+    let target = match req.target_type {
+        HoldTarget::Chat => {
+            db.collection::<Document>("chats").find_one(doc! { "_id": &req.target_id, "team_id": &team_id }).await
+        }
+        HoldTarget::User => {
+            db.collection::<Document>("user_teams").find_one(doc! { "team_id": &team_id, "user_id": &req.target_id }).await
+        }
+    };
+    match target {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().body("No such chat or member in this team"),
+        Err(e) => {
+            error!("Error checking legal hold target: {}", e);
+            return HttpResponse::InternalServerError().body("Error placing legal hold");
+        }
+    }
+    let existing = doc! {
+        "team_id": &team_id,
+        "target_type": if req.target_type == HoldTarget::Chat { "chat" } else { "user" },
+        "target_id": &req.target_id,
+        "released_at": null,
+    };
+    match holds_coll(&data.mongodb).find_one(existing).await {
+        Ok(Some(_)) => return HttpResponse::Conflict().body("Already under an active legal hold"),
+        Ok(None) => {}
+        Err(e) => {
+            error!("Error checking legal holds: {}", e);
+            return HttpResponse::InternalServerError().body("Error placing legal hold");
+        }
+    }
+
+    let hold = LegalHold {
+        hold_id: Uuid::new_v4().to_string(),
+        team_id,
+        target_type: req.target_type,
+        target_id: req.target_id,
+        reason: req.reason.trim().to_string(),
+        placed_by: auth.user_id().to_string(),
+        placed_at: Utc::now(),
+        released_by: None,
+        released_at: None,
+    };
+    if let Err(e) = holds_coll(&data.mongodb).insert_one(&hold).await {
+        error!("Error saving legal hold: {}", e);
+        return HttpResponse::InternalServerError().body("Error placing legal hold");
+    }
+    audit(&data.mongodb, &hold, "placed", Some(hold.reason.clone()), auth.user_id()).await;
+    info!("User {} placed legal hold {} on {:?} {}", auth.user_id(), hold.hold_id, hold.target_type, hold.target_id);
+    HttpResponse::Created().json(hold)
+}
+
+/// DELETE /teams/{team_id}/retention/holds/{hold_id}?reason=
+/// Releases the hold; it stays on record with who released it.
+pub async fn release_hold(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    query: web::Query<ReleaseHoldQuery>,
+) -> impl Responder {
+    let (team_id, hold_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage legal holds");
+    }
+    let update = doc! { "$set": { "released_by": auth.user_id(), "released_at": Utc::now().to_rfc3339() } };
+    let released = holds_coll(&data.mongodb)
+        .find_one_and_update(doc! { "hold_id": &hold_id, "team_id": &team_id, "released_at": null }, update)
+        .return_document(ReturnDocument::After)
+        .await;
+    match released {
+        Ok(Some(hold)) => {
+            audit(&data.mongodb, &hold, "released", query.into_inner().reason, auth.user_id()).await;
+            info!("User {} released legal hold {}", auth.user_id(), hold_id);
+            ok(hold)
+        }
+        Ok(None) => HttpResponse::NotFound().body("No active legal hold with that id"),
+        Err(e) => {
+            error!("Error releasing legal hold: {}", e);
+            HttpResponse::InternalServerError().body("Error releasing legal hold")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/retention/holds/audit
+/// Most recent hold changes first.
+pub async fn list_hold_audit(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage legal holds");
+    }
+    let audit = data.mongodb.db.collection::<LegalHoldAudit>("legal_hold_audit");
+    let mut cursor = match audit.find(doc! { "team_id": &*team_id }).sort(doc! { "at": -1 }).limit(AUDIT_PAGE).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching legal hold audit: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching legal hold audit");
+        }
+    };
+    let mut entries = Vec::new();
+    while let Some(entry) = cursor.next().await {
+        match entry {
+            Ok(e) => entries.push(e),
+            Err(e) => error!("Error reading legal hold audit: {}", e),
+        }
+    }
+    ok(entries)
+}
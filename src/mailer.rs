@@ -0,0 +1,39 @@
+// src/mailer.rs
+
+use log::{error, info};
+use serde::Serialize;
+
+use crate::app_state::AppState;
+
+#[derive(Debug, Serialize)]
+struct OutgoingEmail<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    text: &'a str,
+}
+
+/// Send a plain-text email through the configured HTTP mail relay.
+/// Without EMAIL_API_ENDPOINT the message is only logged, which keeps local setups working.
+pub async fn send_email(data: &AppState, to: &str, subject: &str, text: &str) -> Result<(), String> {
+    let endpoint = match &data.config.email_api_endpoint {
+        Some(e) => e,
+        None => {
+            info!("Email (not sent, no relay configured) to {}: {}\n{}", to, subject, text);
+            return Ok(());
+        }
+    };
+
+    let email = OutgoingEmail { from: &data.config.email_from, to, subject, text };
+    match data.http_client.post(endpoint).json(&email).send().await {
+        Ok(resp) if resp.status().is_success() => Ok(()),
+        Ok(resp) => {
+            error!("Mail relay rejected email to {}: {}", to, resp.status());
+            Err(format!("Mail relay error: {}", resp.status()))
+        }
+        Err(e) => {
+            error!("Mail relay unreachable: {}", e);
+            Err(format!("Mail relay unreachable: {}", e))
+        }
+    }
+}
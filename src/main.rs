@@ -1,71 +1,138 @@
 // src/main.rs
 
+mod activity;
+mod admin;
+mod analytics_export;
+mod api;
+mod api_logs;
 mod auth;
+mod auth_context;
 mod team_management;
+mod team_branding;
+mod team_feedback;
+mod team_home;
+mod team_time;
 mod app_state;
 mod config;
+mod db_pool;
+mod cors;
 mod chat_server;
 mod chat_db;
 mod models;
 mod web_socket_server;
+mod personal_tasks;
+mod portfolio;
 mod project;
+mod quick_search;
+mod release;
+mod reminders;
+mod response;
+mod retention;
+mod role_claims;
+mod saved_reports;
+mod scheduled_messages;
+mod sprint_planning;
+mod stale_tickets;
+mod story_map;
+mod status;
+mod sync;
 mod chat;
+mod chat_export;
+mod chat_attachments;
+mod custom_emoji;
+mod holidays;
 mod knowledge_base;
 mod user_management;
+mod user_status;
+mod ui_preferences;
 mod board;
+mod board_automation;
+mod board_live;
+mod board_transfer;
+mod bots;
 mod ticket;
+mod ticket_assignment;
+mod ticket_comments;
+mod ticket_events;
+mod ticket_merge;
+mod ticket_move;
+mod ticket_references;
+mod ticket_render;
+mod ticket_votes;
+mod ticket_watchers;
+mod ticket_worklog;
 mod calendar;
+mod mailer;
+mod notification_channels;
+mod meeting_links;
+mod meeting_notes;
+mod calls;
+mod capacity;
+mod charts;
 mod ai_endpoints;
 mod dashboard_data;
+mod dashboard_layouts;
+mod dashboard_report;
+mod definition_of_done;
+mod dependency_graph;
+mod digest;
+mod escalation;
+mod estimation_poker;
+mod do_not_disturb;
+mod doc_collab;
+mod email_ingest;
+mod emoji;
+mod encryption;
+mod whiteboard;
+mod fields;
+mod focus_sessions;
+mod giphy;
+mod legal_hold;
+mod member_import;
+mod migrations;
+mod message_translation;
+mod out_of_office;
+mod outbound;
+mod i18n;
+mod impersonation;
+mod integrity;
+mod invite_codes;
+mod scim;
+mod security_policy;
+mod sso;
+mod tenancy;
+#[cfg(test)]
+mod policy_tests;
 
 use std::env;
+use std::rc::Rc;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::future::Future;
 use std::pin::Pin;
 
 use actix::Actor;
-use actix_cors::Cors;
-use actix_web::{body::{BoxBody, MessageBody}, dev::{Service, ServiceRequest, ServiceResponse, Transform}, http, middleware::Logger, web, App, Error, HttpMessage, HttpResponse, HttpServer};
+use actix_web::{body::{BoxBody, MessageBody}, dev::{Service, ServiceRequest, ServiceResponse, Transform}, http, middleware::{from_fn, Compress, Logger}, web, App, Error, HttpMessage, HttpResponse, HttpServer};
 use env_logger::Env;
 use futures::future::{ok, Ready};
 use jsonwebtoken::{decode, DecodingKey, Validation};
+use ring::constant_time;
 
-use crate::user_management::{get_working_hours, set_working_hours};
-use crate::calendar::{create_event, get_user_events};
-use crate::auth::{login, signup, Claims};
-use crate::team_management::{
-    create_team, get_team_members, get_user_teams, invite_user,
-    get_team, update_team, delete_team, remove_team_member,
-    accept_invitation, decline_invitation, delete_invitations, get_pending_invitations,
-};
-use crate::project::{
-    create_project, list_projects, get_project, update_project, delete_project,add_user_to_project
-};
+use crate::auth::{Claims, CSRF_HEADER, SESSION_COOKIE};
 use crate::app_state::AppState;
-use crate::chat::{
-    get_user_chats, create_chat, search_chats, delete_chat,
-    get_single_chat, update_chat, create_message, get_messages,
-};
-use crate::user_management::{find_user_email, get_user_by_id};
-use crate::web_socket_server::ws_index;
-use crate::board::{
-    list_boards, create_board, update_board, delete_board, add_user_to_board,
-};
-use crate::ticket::{
-    create_ticket, list_tickets, get_ticket, update_ticket, delete_ticket,
-};
-use crate::knowledge_base::{
-    create_document, delete_document, get_team_documents, update_document,
-};
-use crate::dashboard_data::{get_dashboard_data, upsert_dashboard_data};
+use crate::auth_context::AuthContext;
+use crate::admin::metrics;
+use crate::impersonation::{Impersonation, IMPERSONATION_HEADER};
+use crate::role_claims::RolesStale;
+use crate::security_policy::TokenIssuedAt;
+use crate::status::{get_status, readyz};
 
 #[derive(Debug)]
 pub struct Authentication;
 
 impl<S, B> Transform<S, ServiceRequest> for Authentication
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: MessageBody + 'static,
 {
@@ -76,17 +143,17 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ok(AuthMiddleware { service })
+        ok(AuthMiddleware { service: Rc::new(service) })
     }
 }
 
 pub struct AuthMiddleware<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: MessageBody + 'static,
 {
@@ -98,44 +165,157 @@ where
         self.service.poll_ready(cx)
     }
 
-    fn call(&self, mut req: ServiceRequest) -> Self::Future {
-        if let Some(auth_header) = req.headers().get(http::header::AUTHORIZATION) {
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // /scim checks its own bearer token, which is not a JWT. /metrics takes either an
+        // admin's JWT or the scraper token, so a bearer that is not a JWT is left to it.
+        let own_token = req.path().starts_with("/scim/");
+        let metrics = req.path() == "/metrics";
+        if let Some(auth_header) = req.headers().get(http::header::AUTHORIZATION).filter(|_| !own_token) {
             if let Ok(auth_str) = auth_header.to_str() {
                 if auth_str.starts_with("Bearer ") {
                     let token = auth_str.trim_start_matches("Bearer ").trim().to_string();
                     match verify_token(&token) {
-                        Ok(user_id) => {
-                            req.extensions_mut().insert(user_id);
+                        Ok(claims) => {
+                            authenticate(&req, claims);
                         }
+                        Err(_) if metrics => {}
                         Err(e) => {
-                            let (req_parts, _payload) = req.into_parts();
-                            let resp = HttpResponse::Unauthorized()
-                                .body(format!("Invalid token: {}", e))
-                                .map_into_boxed_body();
-                            let srv_resp = ServiceResponse::new(req_parts, resp);
-                            return Box::pin(async move { Ok(srv_resp) });
+                            return reject(req, HttpResponse::Unauthorized().body(format!("Invalid token: {}", e)));
                         }
                     }
                 }
             }
         }
 
-        let fut = self.service.call(req);
+        // Cookie sessions: only consulted when no bearer token was supplied.
+        let cookie_sessions = req
+            .app_data::<web::Data<AppState>>()
+            .is_some_and(|d| d.config.cookie_sessions);
+        if cookie_sessions && req.extensions().get::<String>().is_none() {
+            if let Some(cookie) = req.cookie(SESSION_COOKIE) {
+                match verify_token(cookie.value()) {
+                    Ok(claims) => {
+                        let safe_method = matches!(
+                            *req.method(),
+                            http::Method::GET | http::Method::HEAD | http::Method::OPTIONS
+                        );
+                        let csrf_valid = match (req.headers().get(CSRF_HEADER), &claims.csrf) {
+                            (Some(header), Some(expected)) => {
+                                constant_time::verify_slices_are_equal(header.as_bytes(), expected.as_bytes()).is_ok()
+                            }
+                            _ => false,
+                        };
+                        if !safe_method && !csrf_valid {
+                            return reject(req, HttpResponse::Forbidden().body("Missing or invalid CSRF token"));
+                        }
+                        authenticate(&req, claims);
+                    }
+                    Err(e) => {
+                        return reject(req, HttpResponse::Unauthorized().body(format!("Invalid session: {}", e)));
+                    }
+                }
+            }
+        }
+
+        let impersonation = req.extensions().get::<Impersonation>().cloned().and_then(|imp| {
+            let data = req.app_data::<web::Data<AppState>>()?.clone();
+            Some((imp, data, req.method().to_string(), req.path().to_string()))
+        });
+        // Every authenticated request is checked for revoked sessions and against the
+        // security policies of the teams it concerns.
+        let policy_check = {
+            let ext = req.extensions();
+            match (ext.get::<String>(), ext.get::<TokenIssuedAt>(), req.app_data::<web::Data<AppState>>()) {
+                (Some(user_id), Some(issued), Some(data)) => Some((user_id.clone(), issued.0, data.clone())),
+                _ => None,
+            }
+        };
+        let roles_stale = req.extensions().get::<RolesStale>().is_some();
+        let service = self.service.clone();
         Box::pin(async move {
-            let res = fut.await?;
+            if let Some((user_id, issued_at, data)) = policy_check {
+                if role_claims::is_revoked(&user_id, issued_at) {
+                    return reject(req, HttpResponse::Unauthorized().body("Session has been revoked")).await;
+                }
+                if let Err(resp) = security_policy::enforce(&data, req.request(), &user_id, issued_at).await {
+                    return reject(req, resp).await;
+                }
+            }
+            let mut res = service.call(req).await?;
+            if let Some((imp, data, method, path)) = impersonation {
+                impersonation::audit_request(&data.mongodb, &imp, &method, &path, res.status().as_u16()).await;
+                if let Ok(value) = http::header::HeaderValue::from_str(&imp.admin_id) {
+                    res.headers_mut().insert(http::header::HeaderName::from_static(IMPERSONATION_HEADER), value);
+                }
+            }
+            if roles_stale {
+                res.headers_mut().insert(
+                    http::header::HeaderName::from_static(role_claims::REFRESH_HEADER),
+                    http::header::HeaderValue::from_static("roles"),
+                );
+            }
             Ok(res.map_into_boxed_body())
         })
     }
 }
 
-fn verify_token(token: &str) -> Result<String, String> {
+/// Attach the caller to the request: the raw user id plus the AuthContext extractor,
+/// the token's issue time, and the acting admin when the token is an impersonation token.
+fn authenticate(req: &ServiceRequest, claims: Claims) {
+    // Tokens without `iat` were issued for 24 hours.
+    let issued_at = if claims.iat > 0 { claims.iat as i64 } else { claims.exp as i64 - 24 * 3600 };
+    req.extensions_mut().insert(TokenIssuedAt(issued_at));
+    req.extensions_mut().insert(claims.clone());
+    let user_id = claims.sub;
+    if let Some(admin_id) = claims.impersonated_by {
+        req.extensions_mut().insert(Impersonation { admin_id, user_id: user_id.clone() });
+    }
+    if let Some(data) = req.app_data::<web::Data<AppState>>() {
+        // Claimed roles stand in for membership queries only while they are current.
+        let roles = claims.roles.filter(|roles| {
+            let current = role_claims::is_current(&user_id, roles);
+            if !current {
+                req.extensions_mut().insert(RolesStale);
+            }
+            current
+        });
+        req.extensions_mut().insert(AuthContext::new(user_id.clone(), data.mongodb.clone(), roles));
+    }
+    req.extensions_mut().insert(user_id);
+}
+
+fn reject(
+    req: ServiceRequest,
+    resp: HttpResponse,
+) -> Pin<Box<dyn Future<Output = Result<ServiceResponse<BoxBody>, Error>>>> {
+    let (req_parts, _payload) = req.into_parts();
+    let srv_resp = ServiceResponse::new(req_parts, resp.map_into_boxed_body());
+    Box::pin(async move { Ok(srv_resp) })
+}
+
+/// Tokens signed with a secret listed in JWT_PREVIOUS_SECRETS (comma-separated) stay
+/// valid until they expire, so rotating JWT_SECRET does not log everyone out.
+fn verify_token(token: &str) -> Result<Claims, String> {
     let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "secret".to_string());
-    match decode::<Claims>(
+    let result = decode::<Claims>(
         token,
         &DecodingKey::from_secret(secret.as_ref()),
         &Validation::default(),
-    ) {
-        Ok(token_data) => Ok(token_data.claims.sub),
+    );
+    let result = match result {
+        Err(e) if matches!(e.kind(), jsonwebtoken::errors::ErrorKind::InvalidSignature) => {
+            let previous = env::var("JWT_PREVIOUS_SECRETS").unwrap_or_default();
+            previous
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .find_map(|old| decode::<Claims>(token, &DecodingKey::from_secret(old.as_ref()), &Validation::default()).ok())
+                .ok_or(e)
+        }
+        other => other,
+    };
+    match result {
+        Ok(token_data) => Ok(token_data.claims),
         Err(e) => Err(format!("Token decode error: {}", e)),
     }
 }
@@ -145,142 +325,116 @@ async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
+    status::mark_started();
     let config = config::Config::from_env();
-    let mongodb = Arc::new(chat_db::MongoDB::init(&config.mongo_uri, &config.database_name).await);
+    encryption::init(config.encryption_keys.as_deref())
+        .unwrap_or_else(|e| panic!("Invalid ENCRYPTION_KEYS: {}", e));
+    let mongodb = Arc::new(chat_db::MongoDB::init(&config).await);
+    if let Err(e) = mongodb.ensure_indexes().await {
+        log::error!("Failed to create indexes: {}", e);
+    }
+    match migrations::pending(&mongodb).await {
+        Ok(pending) if !pending.is_empty() => {
+            let ids: Vec<&str> = pending.iter().map(|m| m.id).collect();
+            log::warn!("Pending data migrations: {}; run `taskline-admin migrate`", ids.join(", "));
+        }
+        Ok(_) => {}
+        Err(e) => log::error!("Failed to check migrations: {}", e),
+    }
     let chat_server = chat_server::ChatServer::new(mongodb.clone()).start();
+    board_live::init(chat_server.clone());
+    let doc_server = doc_collab::DocServer::new(mongodb.clone()).start();
+    let whiteboard_server = whiteboard::WhiteboardServer::new(mongodb.clone()).start();
 
-    let frontend_origin = env::var("FRONTEND_ORIGIN")
-        .unwrap_or_else(|_| "http://localhost:3000".to_string());
+    let api_logger = if config.api_logging {
+        Some(api_logs::start(mongodb.clone(), config.api_log_max_bytes).await)
+    } else {
+        None
+    };
+
+    let cors_rules = cors::parse_origins(&config.cors_origins)
+        .unwrap_or_else(|e| panic!("Invalid CORS configuration: {}", e));
+
+    let app_state = AppState {
+        chat_server,
+        doc_server,
+        whiteboard_server,
+        mongodb,
+        config: config.clone(),
+        http_client: Default::default(),
+        api_logger,
+    };
+    notification_channels::init(app_state.clone());
+    ticket_watchers::init(app_state.clone());
+    board_automation::init(app_state.clone());
+    personal_tasks::spawn_reminders(app_state.clone());
+    escalation::spawn_escalations(app_state.clone());
+    retention::spawn_retention(app_state.clone());
+    do_not_disturb::spawn_dnd_summaries(app_state.clone());
+    scheduled_messages::spawn_scheduler(app_state.clone());
+    role_claims::spawn_version_sync(app_state.clone());
+    saved_reports::spawn_report_schedules(app_state.clone());
+    stale_tickets::spawn_stale_nudges(app_state.clone());
+    meeting_notes::spawn_meeting_notes(app_state.clone());
+    focus_sessions::spawn_focus_expiry(app_state.clone());
+    user_status::spawn_presence_updates(app_state.clone());
+    if config.weekly_reports {
+        dashboard_report::spawn_weekly_reports(app_state.clone());
+    }
+    if config.digest_emails {
+        digest::spawn_digests(app_state.clone());
+    }
 
     println!("Server running at http://0.0.0.0:8080");
-    println!("Allowed CORS Origin: {}", frontend_origin);
+    if config.cors_permissive {
+        println!("CORS permissive mode enabled - do not use in production");
+    } else {
+        println!("Allowed CORS Origins: {}", config.cors_origins);
+    }
 
     HttpServer::new(move || {
-        let cors = Cors::default()
-            .allowed_origin(&frontend_origin)
-            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"])
-            .allowed_headers(vec![
-                http::header::CONTENT_TYPE,
-                http::header::ACCEPT,
-                http::header::AUTHORIZATION,
-            ])
-            .supports_credentials()
-            .max_age(3600);
+        let cors = cors::build_cors(&cors_rules, config.cors_permissive);
 
         App::new()
             .wrap(Logger::default())
+            // inside Compress, which would hide error bodies
+            .wrap(from_fn(i18n::localize_layer))
+            // gzip/brotli/zstd per Accept-Encoding; skipped for WebSocket upgrades
+            .wrap(Compress::default())
             .wrap(cors)
             .wrap(Authentication)
-            .app_data(web::Data::new(AppState {
-                chat_server: chat_server.clone(),
-                mongodb: mongodb.clone(),
-                config: config.clone(),
-                http_client: Default::default(),
-            }))
-            // auth
-            .service(
-                web::scope("/auth")
-                    .route("/signup", web::post().to(signup))
-                    .route("/login", web::post().to(login))
-            )
-            // teams & related
-            .service(
-                web::scope("/teams")
-                    .route("/user_teams/{user_id}", web::get().to(get_user_teams))
-                    .route("/user_invitations/{user_id}", web::get().to(get_pending_invitations))
-                    .route("", web::post().to(create_team))
-                    .service(
-                        web::scope("/{team_id}")
-                            .route("", web::get().to(get_team))
-                            .route("", web::put().to(update_team))
-                            .route("", web::delete().to(delete_team))
-                            .service(
-                                web::scope("/members")
-                                    .route("", web::get().to(get_team_members))
-                                    .route("", web::post().to(invite_user))
-                                    .route("", web::delete().to(remove_team_member))
-                            )
-                            .service(
-                                web::scope("/invitations")
-                                    .route("/accept", web::post().to(accept_invitation))
-                                    .route("/decline", web::post().to(decline_invitation))
-                                    .route("", web::delete().to(delete_invitations))
-                            )
-                            .service(
-                                web::scope("/projects")
-                                    .route("", web::post().to(create_project))
-                                    .route("", web::get().to(list_projects))
-                                    .route("/{project_id}", web::get().to(get_project))
-                                    .route("/{project_id}", web::put().to(update_project))
-                                    .route("/{project_id}", web::delete().to(delete_project))
-                                    .route("/{project_id}/members", web::post().to(add_user_to_project))
-                                    .service(
-                                        web::scope("/{project_id}/boards")
-                                            .route("", web::get().to(list_boards))
-                                            .route("", web::post().to(create_board))
-                                            .route("/{board_id}", web::put().to(update_board))
-                                            .route("/{board_id}", web::delete().to(delete_board))
-                                            .route("/{board_id}/members", web::post().to(add_user_to_board))
-                                    )
-                                    .service(
-                                        web::scope("/{project_id}/tickets")
-                                            .route("", web::get().to(list_tickets))
-                                            .route("", web::post().to(create_ticket))
-                                            .route("/{ticket_id}", web::get().to(get_ticket))
-                                            .route("/{ticket_id}", web::put().to(update_ticket))
-                                            .route("/{ticket_id}", web::delete().to(delete_ticket))
-                                    )
-                            )
-                    )
-            )
-            //TEAM-DATA
-            .service(
-                web::scope("/team-data")
-                    .route("/{team_id}", web::get().to(get_dashboard_data))
-                    .route("/{team_id}", web::put().to(upsert_dashboard_data))
-            )
-            // chats & messages
+            .wrap(from_fn(api_logs::log_layer))
+            .app_data(web::JsonConfig::default().limit(config.json_limit_default))
+            .app_data(web::Data::new(app_state.clone()))
+            // infrastructure endpoints are not versioned
+            .route("/metrics", web::get().to(metrics))
+            .route("/status", web::get().to(get_status))
+            .route("/readyz", web::get().to(readyz))
             .service(
-                web::scope("/chats")
-                    .route("/{user_id}", web::get().to(get_user_chats))
-                    .route("", web::post().to(create_chat))
-                    .route("/search/{user_id}", web::get().to(search_chats))
-                    .route("/{chat_id}", web::patch().to(update_chat))
-                    .route("/{chat_id}", web::delete().to(delete_chat))
-                    .route("/get/{chat_id}", web::get().to(get_single_chat))
+                web::scope("/scim/v2")
+                    .route("/ServiceProviderConfig", web::get().to(scim::service_provider_config))
+                    .route("/Users", web::get().to(scim::list_users))
+                    .route("/Users", web::post().to(scim::create_user))
+                    .route("/Users/{id}", web::get().to(scim::get_user))
+                    .route("/Users/{id}", web::put().to(scim::replace_user_handler))
+                    .route("/Users/{id}", web::patch().to(scim::patch_user))
+                    .route("/Users/{id}", web::delete().to(scim::delete_user))
+                    .route("/Groups", web::get().to(scim::list_groups))
+                    .route("/Groups", web::post().to(scim::create_group))
+                    .route("/Groups/{id}", web::get().to(scim::get_group))
+                    .route("/Groups/{id}", web::put().to(scim::replace_group))
+                    .route("/Groups/{id}", web::patch().to(scim::patch_group))
             )
             .service(
-                web::scope("/messages")
-                    .route("/{chat_id}", web::get().to(get_messages))
-                    .route("/{chat_id}", web::post().to(create_message))
-            )
-
-            // users
-            .service(
-                web::scope("/users")
-                    .route("/find_user_email", web::get().to(find_user_email))
-                    .route("/get/{id}", web::get().to(get_user_by_id))
-                    .route("/working-hours", web::get().to(get_working_hours))
-                    .route("/working-hours", web::post().to(set_working_hours))
+                web::scope(api::V1_PREFIX)
+                    .wrap(from_fn(api::v1_layer))
+                    .configure(|cfg| api::v1::configure(cfg, &config))
             )
-
-            // websocket
-            .service(web::resource("/ws").route(web::get().to(ws_index)))
-
-            // calendar
-            .service(
-                web::scope("/calendar")
-                    .route("/events", web::post().to(create_event))
-                    .route("/events/{user_id}", web::get().to(get_user_events))
-            )
-
-            // knowledge base
+            // deprecated: pre-versioning paths, kept as aliases of v1
             .service(
-                web::scope("/knowledge_base")
-                    .route("", web::post().to(create_document))
-                    .route("/{team_id}", web::get().to(get_team_documents))
-                    .route("/{doc_id}", web::put().to(update_document))
-                    .route("/{doc_id}", web::delete().to(delete_document))
+                web::scope("")
+                    .wrap(from_fn(api::legacy_layer))
+                    .configure(|cfg| api::v1::configure(cfg, &config))
             )
     })
         .bind(("0.0.0.0", 8080))?
@@ -0,0 +1,153 @@
+// src/meeting_links.rs
+//
+// Video meeting links for calendar events. MEETING_PROVIDER picks where rooms are
+// created:
+//
+// - `zoom`: a scheduled meeting through a Zoom server-to-server OAuth app
+// - `google_meet`: a Meet space through the Meet REST API, as the account whose
+//   refresh token is configured
+// - `jitsi`: no API call, the room URL is JITSI_URL_PATTERN with `{room}` replaced
+//
+// When the Zoom or Google call fails and a Jitsi pattern is configured, the event
+// gets a Jitsi room instead, so creating the event never fails because of the
+// provider. Without any provider events have no link.
+
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::warn;
+use serde::Deserialize;
+
+use crate::app_state::AppState;
+
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(10);
+const ZOOM_TOKEN_URL: &str = "https://zoom.us/oauth/token";
+const ZOOM_MEETINGS_URL: &str = "https://api.zoom.us/v2/users/me/meetings";
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const MEET_SPACES_URL: &str = "https://meet.googleapis.com/v2/spaces";
+
+/// A provisioned room.
+#[derive(Debug, Clone)]
+pub struct MeetingLink {
+    pub url: String,
+    /// `zoom`, `google_meet` or `jitsi`
+    pub provider: &'static str,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct ZoomMeeting {
+    join_url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MeetSpace {
+    meeting_uri: String,
+}
+
+fn jitsi(data: &AppState, room: &str) -> Option<MeetingLink> {
+    let pattern = data.config.jitsi_url_pattern.as_deref()?;
+    Some(MeetingLink { url: pattern.replace("{room}", room), provider: "jitsi" })
+}
+
+async fn zoom(data: &AppState, title: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<String, String> {
+    let config = &data.config;
+    let (Some(account_id), Some(client_id), Some(secret)) =
+        (&config.zoom_account_id, &config.zoom_client_id, &config.zoom_client_secret)
+    else {
+        return Err("Zoom credentials are not configured".to_string());
+    };
+    let token: TokenResponse = data.http_client.post(ZOOM_TOKEN_URL)
+        .timeout(PROVIDER_TIMEOUT)
+        .basic_auth(client_id, Some(secret))
+        .query(&[("grant_type", "account_credentials"), ("account_id", account_id.as_str())])
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Zoom token request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Zoom sent an invalid token: {}", e))?;
+    let body = serde_json::json!({
+        "topic": title,
+        "type": 2,
+        "start_time": start.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "duration": (end - start).num_minutes().max(1),
+        "timezone": "UTC",
+    });
+    let meeting: ZoomMeeting = data.http_client.post(ZOOM_MEETINGS_URL)
+        .timeout(PROVIDER_TIMEOUT)
+        .bearer_auth(&token.access_token)
+        .json(&body)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Zoom meeting request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Zoom sent an invalid meeting: {}", e))?;
+    Ok(meeting.join_url)
+}
+
+async fn google_meet(data: &AppState) -> Result<String, String> {
+    let config = &data.config;
+    let (Some(client_id), Some(secret), Some(refresh_token)) = (
+        &config.google_meet_client_id,
+        &config.google_meet_client_secret,
+        &config.google_meet_refresh_token,
+    ) else {
+        return Err("Google Meet credentials are not configured".to_string());
+    };
+    let form = [
+        ("grant_type", "refresh_token"),
+        ("client_id", client_id.as_str()),
+        ("client_secret", secret.as_str()),
+        ("refresh_token", refresh_token.as_str()),
+    ];
+    let token: TokenResponse = data.http_client.post(GOOGLE_TOKEN_URL)
+        .timeout(PROVIDER_TIMEOUT)
+        .form(&form)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Google token request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Google sent an invalid token: {}", e))?;
+    let space: MeetSpace = data.http_client.post(MEET_SPACES_URL)
+        .timeout(PROVIDER_TIMEOUT)
+        .bearer_auth(&token.access_token)
+        .json(&serde_json::json!({}))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Meet space request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Google sent an invalid space: {}", e))?;
+    Ok(space.meeting_uri)
+}
+
+/// A room for the event `event_id`; None when no provider is configured or the
+/// provider failed without a Jitsi fallback.
+pub async fn provision(data: &AppState, event_id: &str, title: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<MeetingLink> {
+    let provider = data.config.meeting_provider.as_deref()?;
+    let created = match provider {
+        "zoom" => zoom(data, title, start, end).await.map(|url| MeetingLink { url, provider: "zoom" }),
+        "google_meet" => google_meet(data).await.map(|url| MeetingLink { url, provider: "google_meet" }),
+        "jitsi" => return jitsi(data, event_id),
+        other => Err(format!("Unknown meeting provider '{}'", other)),
+    };
+    match created {
+        Ok(link) => Some(link),
+        Err(e) => {
+            warn!("Could not create a meeting for event {}: {}", event_id, e);
+            jitsi(data, event_id)
+        }
+    }
+}
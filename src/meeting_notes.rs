@@ -0,0 +1,283 @@
+// src/meeting_notes.rs
+//
+// Notes documents for calendar events. When an event ends, a knowledge base
+// document is created from the team's agenda template, listing the attendees and
+// shared with them, and the organizer is asked to fill it in. Teams opt in with
+// their meeting notes settings; an event can override the team's choice either way.
+//
+// Only events that ended within the last day are picked up, so enabling the
+// setting does not create notes for a team's whole calendar history.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use futures_util::StreamExt;
+use log::{error, info};
+use mongodb::bson::{self, doc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::calendar::CalendarEvent;
+use crate::chat_db::MongoDB;
+use crate::do_not_disturb::notify_user;
+use crate::knowledge_base::{record_doc_change, Document, Visibility};
+use crate::out_of_office::usernames;
+use crate::response::{CodedError, ok};
+use crate::sync::{record_change, Entity, Op, Scope};
+
+/// How often the job looks for events that have ended.
+const NOTES_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const LOOKBACK_HOURS: i64 = 24;
+const MAX_TEMPLATE_LEN: usize = 10_000;
+const DEFAULT_AGENDA: &str = "## Agenda\n\n## Notes\n\n## Action items\n";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeetingNotesSettings {
+    /// Create notes for the team's events unless the event says otherwise
+    pub enabled: bool,
+    /// Markdown placed below the attendee list
+    pub agenda_template: String,
+}
+
+impl Default for MeetingNotesSettings {
+    fn default() -> Self {
+        MeetingNotesSettings { enabled: false, agenda_template: DEFAULT_AGENDA.to_string() }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventNotesRequest {
+    /// null goes back to the team's setting
+    pub enabled: Option<bool>,
+}
+
+fn settings_coll(db: &MongoDB) -> mongodb::Collection<MeetingNotesSettings> {
+    db.db.collection::<MeetingNotesSettings>("meeting_notes_settings")
+}
+
+fn events_coll(db: &MongoDB) -> mongodb::Collection<CalendarEvent> {
+    db.db.collection::<CalendarEvent>("calendar_events")
+}
+
+async fn load_settings(db: &MongoDB, team_id: &str) -> mongodb::error::Result<MeetingNotesSettings> {
+    Ok(settings_coll(db).find_one(doc! { "team_id": team_id }).await?.unwrap_or_default())
+}
+
+/// The organizer and the participants.
+fn attendees(event: &CalendarEvent) -> Vec<String> {
+    let mut users = vec![event.user_id.clone()];
+    for p in &event.participants {
+        if !users.contains(p) {
+            users.push(p.clone());
+        }
+    }
+    users
+}
+
+/// GET /teams/{team_id}/meeting-notes
+pub async fn get_meeting_notes_settings(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    match load_settings(&data.mongodb, &team_id).await {
+        Ok(s) => ok(s),
+        Err(e) => {
+            error!("Error fetching meeting notes settings: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching settings")
+        }
+    }
+}
+
+/// PUT /teams/{team_id}/meeting-notes
+pub async fn update_meeting_notes_settings(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<MeetingNotesSettings>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can change these settings");
+    }
+    let settings = payload.into_inner();
+    if settings.agenda_template.len() > MAX_TEMPLATE_LEN {
+        return HttpResponse::BadRequest().body(format!("Agenda template is longer than {} bytes", MAX_TEMPLATE_LEN));
+    }
+    let fields = match bson::to_document(&settings) {
+        Ok(d) => d,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error saving settings: {}", e)),
+    };
+    match settings_coll(&data.mongodb).update_one(doc! { "team_id": &*team_id }, doc! { "$set": fields }).upsert(true).await {
+        Ok(_) => ok(settings),
+        Err(e) => {
+            error!("Error saving meeting notes settings: {}", e);
+            HttpResponse::InternalServerError().body("Error saving settings")
+        }
+    }
+}
+
+/// PUT /calendar/events/{event_id}/meeting-notes
+/// Organizer only; has no effect once the notes exist.
+pub async fn set_event_meeting_notes(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    event_id: web::Path<String>,
+    payload: web::Json<EventNotesRequest>,
+) -> impl Responder {
+    let event = match events_coll(&data.mongodb).find_one(doc! { "event_id": &*event_id }).await {
+        Ok(Some(e)) => e,
+        Ok(None) => return HttpResponse::NotFound().body("Event not found"),
+        Err(e) => {
+            error!("Error fetching event: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching event");
+        }
+    };
+    if event.user_id != auth.user_id() {
+        return HttpResponse::Forbidden().body("Only the organizer can change this event");
+    }
+    let update = doc! { "$set": { "meeting_notes": payload.enabled } };
+    match events_coll(&data.mongodb).update_one(doc! { "event_id": &event.event_id }, update).await {
+        Ok(_) => {
+            record_change(&data.mongodb, Entity::Event, &event.event_id, Op::Upsert, Scope::Users(&attendees(&event))).await;
+            HttpResponse::Ok().json(CalendarEvent { meeting_notes: payload.enabled, ..event })
+        }
+        Err(e) => {
+            error!("Error updating event: {}", e);
+            HttpResponse::InternalServerError().body("Error updating event")
+        }
+    }
+}
+
+/// Creates the notes document of an ended event and tells the organizer.
+async fn create_notes(data: &AppState, event: &CalendarEvent, team_id: &str, settings: &MeetingNotesSettings) -> mongodb::error::Result<()> {
+    let doc_id = Uuid::new_v4().to_string();
+    // Claim the event first so a notes document is only ever created once.
+    let claim = events_coll(&data.mongodb)
+        .update_one(
+            doc! { "event_id": &event.event_id, "notes_doc_id": null },
+            doc! { "$set": { "notes_doc_id": &doc_id } },
+        )
+        .await?;
+    if claim.modified_count == 0 {
+        return Ok(());
+    }
+
+    let audience = attendees(event);
+    let names: HashMap<String, String> = match usernames(&data.mongodb, &audience).await {
+        Ok(names) => names.into_iter().collect(),
+        Err(e) => {
+            error!("Error fetching attendee names: {}", e);
+            HashMap::new()
+        }
+    };
+    let mut content = format!(
+        "# {}\n\n{} – {}\n\n## Attendees\n",
+        event.title,
+        event.start.format("%Y-%m-%d %H:%M UTC"),
+        event.end.format("%H:%M UTC"),
+    );
+    for user_id in &audience {
+        content.push_str(&format!("- {}\n", names.get(user_id).unwrap_or(user_id)));
+    }
+    content.push('\n');
+    content.push_str(&settings.agenda_template);
+
+    let now = Utc::now();
+    let notes = Document {
+        id: doc_id,
+        team_id: team_id.to_string(),
+        title: format!("Meeting notes: {} ({})", event.title, event.start.format("%Y-%m-%d")),
+        content,
+        created_at: now,
+        updated_at: now,
+        author_id: Some(event.user_id.clone()),
+        visibility: Visibility::Members,
+        shared_with: audience.iter().filter(|u| **u != event.user_id).cloned().collect(),
+    };
+    if let Err(e) = data.mongodb.db.collection::<Document>("knowledge_base").insert_one(&notes).await {
+        // Release the claim so the next run tries again.
+        let release = doc! { "$set": { "notes_doc_id": null } };
+        if let Err(e) = events_coll(&data.mongodb).update_one(doc! { "event_id": &event.event_id }, release).await {
+            error!("Error releasing meeting notes claim of event {}: {}", event.event_id, e);
+        }
+        return Err(e);
+    }
+    record_doc_change(&data.mongodb, &notes, Op::Upsert).await;
+    record_change(&data.mongodb, Entity::Event, &event.event_id, Op::Upsert, Scope::Users(&audience)).await;
+
+    let payload = serde_json::json!({
+        "type": "meeting_notes",
+        "event_id": event.event_id,
+        "title": event.title,
+        "doc_id": notes.id,
+        "doc_title": notes.title,
+    });
+    notify_user(data, &event.user_id, payload.to_string()).await;
+    info!("Created meeting notes {} for event {}", notes.id, event.event_id);
+    Ok(())
+}
+
+async fn create_due_notes(data: &AppState) {
+    let now = Utc::now();
+    let since = now - chrono::Duration::hours(LOOKBACK_HOURS);
+    // Compared in the form serde stores the event's `end` in.
+    let filter = doc! {
+        "end": { "$gte": bson::to_bson(&since).unwrap_or_default(), "$lte": bson::to_bson(&now).unwrap_or_default() },
+        "notes_doc_id": null,
+        "meeting_notes": { "$ne": false },
+        "team_id": { "$type": "string" },
+    };
+    let mut cursor = match events_coll(&data.mongodb).find(filter).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching ended events: {}", e);
+            return;
+        }
+    };
+    let mut teams: HashMap<String, MeetingNotesSettings> = HashMap::new();
+    while let Some(event) = cursor.next().await {
+        let event = match event {
+            Ok(e) => e,
+            Err(e) => {
+                error!("Error reading calendar event: {}", e);
+                continue;
+            }
+        };
+        let Some(team_id) = event.team_id.clone() else {
+            continue;
+        };
+        if !teams.contains_key(&team_id) {
+            match load_settings(&data.mongodb, &team_id).await {
+                Ok(s) => {
+                    teams.insert(team_id.clone(), s);
+                }
+                Err(e) => {
+                    error!("Error fetching meeting notes settings of team {}: {}", team_id, e);
+                    continue;
+                }
+            }
+        }
+        let settings = &teams[&team_id];
+        if !event.meeting_notes.unwrap_or(settings.enabled) {
+            continue;
+        }
+        if let Err(e) = create_notes(data, &event, &team_id, settings).await {
+            error!("Error creating meeting notes for event {}: {}", event.event_id, e);
+        }
+    }
+}
+
+/// Start the background job that creates notes for ended events.
+pub fn spawn_meeting_notes(state: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(NOTES_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            create_due_notes(&state).await;
+        }
+    });
+}
@@ -0,0 +1,303 @@
+// src/member_import.rs
+//
+// Bulk onboarding from a CSV with an `email` column and an optional `name` column.
+// Rows for existing accounts become team invitations. Unknown emails either fail
+// (`mode=invite`) or get an account created on the spot (`mode=provision`), which
+// joins the team right away but cannot log in until its owner sets a password
+// through the emailed setup link. Every row gets its own result. Uploads carrying an
+// `Idempotency-Key` header are recorded, and repeating the key returns the recorded
+// report instead of importing again.
+
+use std::collections::HashSet;
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use mongodb::bson::{doc, to_bson, DateTime as BsonDateTime, Document};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::activity::{record_activity, ActivityEvent};
+use crate::app_state::AppState;
+use crate::auth::send_password_setup;
+use crate::auth_context::AuthContext;
+use crate::response::CodedError;
+use crate::team_management::{TeamInvitation, UserTeam};
+
+const MAX_ROWS: usize = 500;
+const IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+const MAX_KEY_LEN: usize = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    #[default]
+    Invite,
+    Provision,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub mode: ImportMode,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportRow {
+    email: String,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowStatus {
+    Invited,
+    Provisioned,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RowResult {
+    /// 1-based line in the CSV, header excluded
+    pub row: usize,
+    pub email: String,
+    pub status: RowStatus,
+    pub user_id: Option<String>,
+    /// Why the row was skipped or failed
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub mode: ImportMode,
+    pub invited: usize,
+    pub provisioned: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub rows: Vec<RowResult>,
+    pub imported_at: DateTime<Utc>,
+}
+
+pub(crate) fn valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !email.contains(char::is_whitespace),
+        None => false,
+    }
+}
+
+/// A username derived from `source` (a name or email) that nobody uses yet.
+pub(crate) async fn free_username(data: &AppState, source: &str) -> mongodb::error::Result<String> {
+    let source = source.split('@').next().unwrap_or("");
+    let mut base: String = source
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '.' })
+        .collect();
+    base = base.split('.').filter(|s| !s.is_empty()).collect::<Vec<_>>().join(".");
+    if base.is_empty() {
+        base = "user".to_string();
+    }
+    let users = data.mongodb.db.collection::<Document>("users");
+    let mut candidate = base.clone();
+    let mut n = 1;
+    while users.find_one(doc! { "username": &candidate }).await?.is_some() {
+        n += 1;
+        candidate = format!("{}{}", base, n);
+    }
+    Ok(candidate)
+}
+
+async fn import_row(
+    data: &AppState,
+    team_id: &str,
+    team_name: &str,
+    inviter: &str,
+    mode: ImportMode,
+    row: &ImportRow,
+) -> mongodb::error::Result<(RowStatus, Option<String>, Option<String>)> {
+    let users = data.mongodb.db.collection::<Document>("users");
+    let user_teams = data.mongodb.db.collection::<UserTeam>("user_teams");
+    let invitations = data.mongodb.db.collection::<TeamInvitation>("team_invitations");
+
+    let existing = users
+        .find_one(doc! { "email": { "$regex": format!("^{}$", regex::escape(&row.email)), "$options": "i" } })
+        .await?;
+    if let Some(user) = existing {
+        let Ok(oid) = user.get_object_id("_id") else {
+            return Ok((RowStatus::Failed, None, Some("Account has no id".to_string())));
+        };
+        let user_id = oid.to_hex();
+        if user_teams.find_one(doc! { "team_id": team_id, "user_id": &user_id }).await?.is_some() {
+            return Ok((RowStatus::Skipped, Some(user_id), Some("Already a member of the team".to_string())));
+        }
+        let pending = doc! { "team_id": team_id, "invitee_id": &user_id, "status": "pending" };
+        if invitations.find_one(pending).await?.is_some() {
+            return Ok((RowStatus::Skipped, Some(user_id), Some("An invitation is already pending".to_string())));
+        }
+        invitations
+            .insert_one(TeamInvitation {
+                invitation_id: Uuid::new_v4().to_string(),
+                team_id: team_id.to_string(),
+                invitee_id: user_id.clone(),
+                inviter_id: inviter.to_string(),
+                status: "pending".to_string(),
+                sent_at: Utc::now(),
+                responded_at: None,
+                invitee_email: None,
+            })
+            .await?;
+        return Ok((RowStatus::Invited, Some(user_id), None));
+    }
+
+    if mode == ImportMode::Invite {
+        return Ok((RowStatus::Failed, None, Some("No account with this email".to_string())));
+    }
+    // No usable password until the owner picks one through the setup link.
+    let user = doc! {
+        "username": free_username(data, row.name.as_deref().filter(|n| !n.trim().is_empty()).unwrap_or(&row.email)).await?,
+        "email": &row.email,
+        "password": "",
+        "must_reset_password": true,
+        "team_id": team_id,
+        "email_verified": false,
+        "created_at": BsonDateTime::now(),
+        "provisioned_by": inviter,
+    };
+    let user_id = match users.insert_one(user).await?.inserted_id.as_object_id() {
+        Some(oid) => oid.to_hex(),
+        None => return Ok((RowStatus::Failed, None, Some("Account could not be created".to_string()))),
+    };
+    user_teams
+        .insert_one(UserTeam {
+            user_id: user_id.clone(),
+            team_id: team_id.to_string(),
+            role: "member".to_string(),
+            joined_at: Utc::now(),
+        })
+        .await?;
+    record_activity(&data.mongodb, ActivityEvent::new(
+        team_id, None, inviter, "member_provisioned", &user_id, format!("added {} to the team", row.email),
+    )).await;
+    if let Err(e) = send_password_setup(data, &user_id, &row.email, team_name).await {
+        error!("Error sending password setup email to {}: {}", row.email, e);
+        return Ok((RowStatus::Provisioned, Some(user_id), Some("Account created, but the setup email could not be sent".to_string())));
+    }
+    Ok((RowStatus::Provisioned, Some(user_id), None))
+}
+
+/// POST /teams/{team_id}/members/import?mode=invite|provision
+/// Body: CSV with an `email` header and optionally `name`.
+pub async fn import_members(
+    req: HttpRequest,
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    query: web::Query<ImportQuery>,
+    body: web::Bytes,
+) -> impl Responder {
+    let team_id = team_id.into_inner();
+    let current_user = auth.user_id().to_string();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Unauthorized().body("Only team admins can import members");
+    }
+    let key = match req.headers().get(IDEMPOTENCY_HEADER).map(|v| v.to_str()) {
+        Some(Ok(k)) if !k.trim().is_empty() && k.len() <= MAX_KEY_LEN => Some(k.trim().to_string()),
+        Some(_) => return HttpResponse::BadRequest().body(format!("{} must be 1-{} visible characters", IDEMPOTENCY_HEADER, MAX_KEY_LEN)),
+        None => None,
+    };
+
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(&body[..]);
+    let rows = match reader.deserialize::<ImportRow>().collect::<Result<Vec<_>, _>>() {
+        Ok(rows) => rows,
+        Err(e) => return HttpResponse::BadRequest().body(format!("Invalid CSV: {}", e)),
+    };
+    if rows.is_empty() {
+        return HttpResponse::BadRequest().body("The CSV has no rows");
+    }
+    if rows.len() > MAX_ROWS {
+        return HttpResponse::BadRequest().body(format!("At most {} rows can be imported at once", MAX_ROWS));
+    }
+
+    let team_name = match data.mongodb.db.collection::<Document>("teams").find_one(doc! { "team_id": &team_id }).await {
+        Ok(Some(t)) => t.get_str("name").unwrap_or("your team").to_string(),
+        Ok(None) => return HttpResponse::NotFound().error("team_not_found"),
+        Err(e) => {
+            error!("Error fetching team: {}", e);
+            return HttpResponse::InternalServerError().body("Error importing members");
+        }
+    };
+
+    // Claim the key before importing, so a concurrent retry cannot import twice.
+    let imports = data.mongodb.db.collection::<Document>("member_imports");
+    if let Some(key) = &key {
+        let claim = doc! { "team_id": &team_id, "key": key, "created_by": &current_user, "created_at": BsonDateTime::now() };
+        if let Err(e) = imports.insert_one(claim).await {
+            if !matches!(&*e.kind, mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(w)) if w.code == 11000) {
+                error!("Error recording member import: {}", e);
+                return HttpResponse::InternalServerError().body("Error importing members");
+            }
+            return match imports.find_one(doc! { "team_id": &team_id, "key": key }).await {
+                Ok(Some(previous)) => match previous.get("report").cloned().map(mongodb::bson::from_bson::<ImportReport>) {
+                    Some(Ok(report)) => HttpResponse::Ok().insert_header(("Idempotent-Replayed", "true")).json(report),
+                    _ => HttpResponse::Conflict().body("An import with this key is still running"),
+                },
+                Ok(None) => HttpResponse::Conflict().body("An import with this key is still running"),
+                Err(e) => {
+                    error!("Error fetching member import: {}", e);
+                    HttpResponse::InternalServerError().body("Error importing members")
+                }
+            };
+        }
+    }
+
+    let mode = query.mode;
+    let mut seen = HashSet::new();
+    let mut results = Vec::with_capacity(rows.len());
+    for (i, mut row) in rows.into_iter().enumerate() {
+        row.email = row.email.to_lowercase();
+        let (status, user_id, message) = if !valid_email(&row.email) {
+            (RowStatus::Failed, None, Some("Invalid email address".to_string()))
+        } else if !seen.insert(row.email.clone()) {
+            (RowStatus::Skipped, None, Some("Duplicate of an earlier row".to_string()))
+        } else {
+            match import_row(&data, &team_id, &team_name, &current_user, mode, &row).await {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    error!("Error importing {} into team {}: {}", row.email, team_id, e);
+                    (RowStatus::Failed, None, Some("Database error".to_string()))
+                }
+            }
+        };
+        results.push(RowResult { row: i + 1, email: row.email, status, user_id, message });
+    }
+
+    let count = |s: RowStatus| results.iter().filter(|r| r.status == s).count();
+    let report = ImportReport {
+        mode,
+        invited: count(RowStatus::Invited),
+        provisioned: count(RowStatus::Provisioned),
+        skipped: count(RowStatus::Skipped),
+        failed: count(RowStatus::Failed),
+        rows: results,
+        imported_at: Utc::now(),
+    };
+    if let Some(key) = &key {
+        match to_bson(&report) {
+            Ok(r) => {
+                let update = doc! { "$set": { "report": r } };
+                if let Err(e) = imports.update_one(doc! { "team_id": &team_id, "key": key }, update).await {
+                    error!("Error saving member import report: {}", e);
+                }
+            }
+            Err(e) => error!("Error serializing member import report: {}", e),
+        }
+    }
+    info!(
+        "User {} imported members into team {}: {} invited, {} provisioned, {} skipped, {} failed",
+        current_user, team_id, report.invited, report.provisioned, report.skipped, report.failed
+    );
+    HttpResponse::Ok().json(report)
+}
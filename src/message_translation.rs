@@ -0,0 +1,131 @@
+// src/message_translation.rs
+//
+// On-demand translation of chat messages through the configured AI service.
+// Messages are immutable once sent, so each (message, language) pair is
+// translated at most once and served from `message_translations` afterwards.
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::error;
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+
+use crate::ai_endpoints::ai_endpoint;
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat::{Chat, DBMessage};
+use crate::response::CodedError;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageTranslation {
+    pub message_id: String,
+    pub id_chat: String,
+    pub lang: String,
+    /// Language the AI service detected for the original, when it reports one
+    pub source_lang: Option<String>,
+    pub content: String,
+    /// `created_at` of the original message, so retention can purge both together
+    pub message_created_at: DateTime<Utc>,
+    pub translated_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct TranslateQuery {
+    pub to: String,
+}
+
+#[derive(Serialize)]
+struct TranslateRequest<'a> {
+    text: &'a str,
+    target: &'a str,
+}
+
+#[derive(Deserialize)]
+struct TranslateResponse {
+    translated_text: String,
+    source_lang: Option<String>,
+}
+
+/// Normalise a BCP 47 style tag ("PT-br" -> "pt-br"); None when it can't be one.
+fn parse_lang(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_ascii_lowercase();
+    let mut parts = tag.split('-');
+    let primary = parts.next()?;
+    let valid = (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && parts.all(|p| (1..=8).contains(&p.len()) && p.chars().all(|c| c.is_ascii_alphanumeric()));
+    valid.then_some(tag)
+}
+
+async fn translate(data: &AppState, text: &str, target: &str) -> Result<TranslateResponse, String> {
+    let url = format!("{}/translate", ai_endpoint(data));
+    let resp = data.http_client.post(&url)
+        .json(&TranslateRequest { text, target })
+        .send()
+        .await
+        .map_err(|e| format!("AI service unreachable: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("AI service error: {}", resp.status()));
+    }
+    resp.json::<TranslateResponse>().await.map_err(|e| format!("AI response parse error: {}", e))
+}
+
+// ----------------------------------------------------------------------
+// POST /messages/{chat_id}/{message_id}/translate?to=xx
+//    Participants only. Cached translations are returned without calling the AI service.
+// ----------------------------------------------------------------------
+pub async fn translate_message(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    query: web::Query<TranslateQuery>,
+) -> impl Responder {
+    let (chat_id, message_id) = path.into_inner();
+    let Some(lang) = parse_lang(&query.to) else {
+        return HttpResponse::BadRequest().body("`to` must be a language code such as \"de\" or \"pt-BR\"");
+    };
+
+    let chats = data.mongodb.db.collection::<Chat>("chats");
+    match chats.find_one(doc! { "_id": &chat_id, "participants": auth.user_id() }).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::Forbidden().error("not_chat_participant"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    }
+    let messages = data.mongodb.db.collection::<DBMessage>("messages");
+    let message = match messages.find_one(doc! { "_id": &message_id, "id_chat": &chat_id }).await {
+        Ok(Some(m)) => m,
+        Ok(None) => return HttpResponse::NotFound().body("Message not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    };
+
+    let translations = data.mongodb.db.collection::<MessageTranslation>("message_translations");
+    match translations.find_one(doc! { "message_id": &message_id, "lang": &lang }).await {
+        Ok(Some(cached)) => return HttpResponse::Ok().json(cached),
+        Ok(None) => {}
+        Err(e) => error!("Error reading translation cache: {}", e),
+    }
+    if message.content.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Message has no text to translate");
+    }
+
+    let translated = match translate(&data, &message.content, &lang).await {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::BadGateway().body(e),
+    };
+    let translation = MessageTranslation {
+        message_id,
+        id_chat: chat_id,
+        lang,
+        source_lang: translated.source_lang,
+        content: translated.translated_text,
+        message_created_at: message.created_at,
+        translated_at: Utc::now(),
+    };
+    // A concurrent request may have cached it first; the unique index keeps one copy.
+    if let Err(e) = translations.insert_one(&translation).await {
+        if !matches!(&*e.kind, mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(w)) if w.code == 11000) {
+            error!("Error caching translation: {}", e);
+        }
+    }
+    HttpResponse::Ok().json(translation)
+}
@@ -0,0 +1,96 @@
+// src/migrations.rs
+//
+// One-off data migrations, applied in order by `taskline-admin migrate` and recorded
+// in the `migrations` collection so each runs once. The server does not run them; it
+// only warns at startup when some are pending. Migrations must be safe to re-run in
+// case one is interrupted before it is recorded.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use mongodb::bson::{doc, DateTime as BsonDateTime, Document};
+
+use crate::chat_db::MongoDB;
+
+type MigrationFn = for<'a> fn(&'a MongoDB) -> Pin<Box<dyn Future<Output = mongodb::error::Result<u64>> + Send + 'a>>;
+
+pub struct Migration {
+    pub id: &'static str,
+    #[allow(dead_code)] // read by taskline-admin only
+    pub description: &'static str,
+    /// Returns the number of documents changed
+    run: MigrationFn,
+}
+
+pub static MIGRATIONS: [Migration; 2] = [
+    Migration {
+        id: "0001_ticket_counters",
+        description: "Backfill version and vote_count on tickets created before they existed",
+        run: |db| Box::pin(ticket_counters(db)),
+    },
+    Migration {
+        id: "0002_message_attachments",
+        description: "Convert string message attachments to attachment lists",
+        run: |db| Box::pin(message_attachments(db)),
+    },
+];
+
+async fn ticket_counters(db: &MongoDB) -> mongodb::error::Result<u64> {
+    let tickets = db.db.collection::<Document>("tickets");
+    let versions = tickets
+        .update_many(doc! { "version": { "$exists": false } }, doc! { "$set": { "version": 0_i64 } })
+        .await?;
+    let votes = tickets
+        .update_many(doc! { "vote_count": { "$exists": false } }, doc! { "$set": { "vote_count": 0_i64 } })
+        .await?;
+    Ok(versions.modified_count + votes.modified_count)
+}
+
+/// Messages used to carry `attachments` as a single string (a link); they now hold a
+/// list of attachment descriptors. Old values become one linked attachment.
+async fn message_attachments(db: &MongoDB) -> mongodb::error::Result<u64> {
+    let messages = db.db.collection::<Document>("messages");
+    let cleared = messages
+        .update_many(doc! { "attachments": "" }, doc! { "$set": { "attachments": null } })
+        .await?;
+    let linked = doc! {
+        "attachment_id": "",
+        "filename": "$attachments",
+        "content_type": "application/octet-stream",
+        "size": 0_i64,
+        "thumbnail_id": null,
+        "url": "$attachments",
+    };
+    let converted = messages
+        .update_many(
+            doc! { "attachments": { "$type": "string" } },
+            vec![doc! { "$set": { "attachments": [linked] } }],
+        )
+        .await?;
+    Ok(cleared.modified_count + converted.modified_count)
+}
+
+/// Migrations not yet recorded as applied, in order.
+pub async fn pending(db: &MongoDB) -> mongodb::error::Result<Vec<&'static Migration>> {
+    let applied = db.db.collection::<Document>("migrations").distinct("_id", doc! {}).await?;
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|id| id.as_str() == Some(m.id)))
+        .collect())
+}
+
+/// Runs one migration and records it. Returns the number of documents changed.
+#[allow(dead_code)] // called by taskline-admin only
+pub async fn apply(db: &MongoDB, migration: &Migration) -> mongodb::error::Result<u64> {
+    let changed = (migration.run)(db).await?;
+    db.db
+        .collection::<Document>("migrations")
+        .insert_one(doc! {
+            "_id": migration.id,
+            "description": migration.description,
+            "changed": changed as i64,
+            "applied_at": BsonDateTime::now(),
+        })
+        .await?;
+    Ok(changed)
+}
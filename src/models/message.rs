@@ -7,6 +7,7 @@ use mongodb::bson::{doc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use crate::app_state::AppState;
+use crate::response::CodedError;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Message {
@@ -38,7 +39,7 @@ pub async fn send_message(
     let current_user = if let Some(user) = req.extensions().get::<String>() {
         user.clone()
     } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
+        return HttpResponse::Unauthorized().error("unauthorized");
     };
     let chat_users_collection = data.mongodb.db.collection::<mongodb::bson::Document>("chat_users");
     let filter = doc! { "chat_id": chat_id.to_string(), "user_id": current_user.clone() };
@@ -72,7 +73,7 @@ pub async fn fetch_messages(
     let current_user = if let Some(user) = req.extensions().get::<String>() {
         user.clone()
     } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
+        return HttpResponse::Unauthorized().error("unauthorized");
     };
     let chat_users_collection = data.mongodb.db.collection::<mongodb::bson::Document>("chat_users");
     let filter = doc! { "chat_id": chat_id.to_string(), "user_id": current_user };
@@ -7,6 +7,7 @@ use mongodb::options::FindOptions;
 
 use crate::app_state::AppState;
 use crate::models::task::{Task, CreateTaskRequest, UpdateTaskRequest};
+use crate::response::CodedError;
 
 pub async fn create_task(data: web::Data<AppState>, req: web::Json<CreateTaskRequest>) -> impl Responder {
     let tasks_coll = data.mongodb.db.collection::<Task>("tasks");
@@ -73,7 +74,7 @@ pub async fn update_task(
     }
 
     if update_doc.is_empty() {
-        return HttpResponse::BadRequest().body("No fields to update");
+        return HttpResponse::BadRequest().error("no_fields_to_update");
     }
 
     update_doc.insert("updated_at", BsonDateTime::from_millis(Utc::now().timestamp_millis()));
@@ -81,7 +82,7 @@ pub async fn update_task(
     match tasks_coll.update_one(doc! {"_id": task_id_bson}, doc!{"$set": update_doc}).await {
         Ok(res) => {
             if res.matched_count == 0 {
-                HttpResponse::NotFound().body("Task not found")
+                HttpResponse::NotFound().error("task_not_found")
             } else {
                 HttpResponse::Ok().body("Task updated")
             }
@@ -0,0 +1,472 @@
+// src/notification_channels.rs
+//
+// Outgoing notification channels. Team admins register Discord, Microsoft Teams or
+// generic webhooks; every event recorded in the team's activity feed is sent to the
+// channels whose routing rules match it. A rule matches on the event kind (exact, a
+// `ticket_*` prefix or `*`) and optionally a project; a channel without rules gets
+// every event.
+//
+// The payload comes from the channel's template, in which `{{name}}` is replaced by
+// the event's fields: event_id, team_id, project_id, actor_id, actor_name, kind,
+// target_id, summary and created_at. For Discord and Teams the template is the
+// message text, wrapped in the envelope the service expects. For webhooks it is the
+// whole body, and with a JSON content type the values are escaped for use inside a
+// JSON string; without a template webhooks get the event as JSON.
+//
+// Delivery happens in the background, once, and never affects the request that
+// recorded the event; the outcome of the last attempt is kept on the channel.
+//
+// Channel URLs carry the webhook's secret, so they are stored encrypted and only
+// shown with their path masked. They must be public https URLs (see outbound.rs).
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::{error, warn};
+use mongodb::bson::{self, doc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::activity::ActivityEvent;
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::encryption::EncryptedString;
+use crate::out_of_office::usernames;
+use crate::outbound;
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_CHANNELS: u64 = 20;
+const MAX_TEMPLATE_LEN: usize = 8 * 1024;
+const DEFAULT_MESSAGE: &str = "{{actor_name}} {{summary}}";
+
+static STATE: OnceLock<AppState> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelKind {
+    Discord,
+    MsTeams,
+    Webhook,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingRule {
+    /// Event kinds: exact, `prefix_*` or `*`
+    pub kinds: Vec<String>,
+    /// Only events of this project
+    #[serde(default)]
+    pub project_id: Option<String>,
+}
+
+impl RoutingRule {
+    fn matches(&self, event: &ActivityEvent) -> bool {
+        let kind_ok = self.kinds.iter().any(|k| match k.strip_suffix('*') {
+            Some(prefix) => event.kind.starts_with(prefix),
+            None => *k == event.kind,
+        });
+        kind_ok && self.project_id.as_ref().is_none_or(|p| event.project_id.as_ref() == Some(p))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationChannel {
+    pub channel_id: String,
+    pub team_id: String,
+    pub name: String,
+    pub kind: ChannelKind,
+    pub url: EncryptedString,
+    pub template: Option<String>,
+    /// Content type of webhook bodies; application/json by default
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<RoutingRule>,
+    pub enabled: bool,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_delivery_at: Option<DateTime<Utc>>,
+    /// Error of the last delivery; None when it succeeded
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+impl NotificationChannel {
+    fn wants(&self, event: &ActivityEvent) -> bool {
+        self.enabled && (self.rules.is_empty() || self.rules.iter().any(|r| r.matches(event)))
+    }
+
+    fn is_json(&self) -> bool {
+        self.content_type.as_deref().is_none_or(|ct| ct.starts_with("application/json"))
+    }
+}
+
+/// A channel as shown to admins; only the scheme and host of the URL are returned.
+#[derive(Debug, Serialize)]
+pub struct ChannelView {
+    pub channel_id: String,
+    pub team_id: String,
+    pub name: String,
+    pub kind: ChannelKind,
+    pub url: String,
+    pub template: Option<String>,
+    pub content_type: Option<String>,
+    pub rules: Vec<RoutingRule>,
+    pub enabled: bool,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub last_delivery_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl From<NotificationChannel> for ChannelView {
+    fn from(c: NotificationChannel) -> Self {
+        let url = match reqwest::Url::parse(c.url.expose()) {
+            Ok(u) => format!("{}://{}/…", u.scheme(), u.host_str().unwrap_or_default()),
+            Err(_) => "…".to_string(),
+        };
+        ChannelView {
+            channel_id: c.channel_id,
+            team_id: c.team_id,
+            name: c.name,
+            kind: c.kind,
+            url,
+            template: c.template,
+            content_type: c.content_type,
+            rules: c.rules,
+            enabled: c.enabled,
+            created_by: c.created_by,
+            created_at: c.created_at,
+            last_delivery_at: c.last_delivery_at,
+            last_error: c.last_error,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChannelRequest {
+    pub name: String,
+    pub kind: ChannelKind,
+    /// Required on create; omit on update to keep the current URL
+    pub url: Option<String>,
+    pub template: Option<String>,
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<RoutingRule>,
+    pub enabled: Option<bool>,
+}
+
+/// Called once at startup; deliveries use the app's database and HTTP client.
+pub fn init(data: AppState) {
+    let _ = STATE.set(data);
+}
+
+fn channels_coll(db: &MongoDB) -> mongodb::Collection<NotificationChannel> {
+    db.db.collection::<NotificationChannel>("notification_channels")
+}
+
+async fn validate(req: &ChannelRequest) -> Result<(), String> {
+    if req.name.trim().is_empty() {
+        return Err("Channel name must not be empty".to_string());
+    }
+    if let Some(url) = &req.url {
+        outbound::check_url(url).await?;
+    }
+    if req.template.as_ref().is_some_and(|t| t.len() > MAX_TEMPLATE_LEN) {
+        return Err(format!("template is longer than {} bytes", MAX_TEMPLATE_LEN));
+    }
+    if req.rules.iter().any(|r| r.kinds.is_empty() || r.kinds.iter().any(|k| k.trim().is_empty())) {
+        return Err("Every rule needs at least one event kind".to_string());
+    }
+    Ok(())
+}
+
+/// The template variables of an event.
+fn variables(event: &ActivityEvent, actor_name: &str) -> Vec<(&'static str, String)> {
+    vec![
+        ("event_id", event.event_id.clone()),
+        ("team_id", event.team_id.clone()),
+        ("project_id", event.project_id.clone().unwrap_or_default()),
+        ("actor_id", event.actor_id.clone()),
+        ("actor_name", actor_name.to_string()),
+        ("kind", event.kind.clone()),
+        ("target_id", event.target_id.clone()),
+        ("summary", event.summary.clone()),
+        ("created_at", event.created_at.try_to_rfc3339_string().unwrap_or_default()),
+    ]
+}
+
+/// Replaces each `{{ name }}`; unknown names render empty.
+fn render(template: &str, vars: &[(&'static str, String)], escape_json: bool) -> String {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    let re = PLACEHOLDER.get_or_init(|| Regex::new(r"\{\{\s*([a-z_]+)\s*\}\}").expect("valid placeholder regex"));
+    re.replace_all(template, |caps: &regex::Captures| {
+        let value = vars.iter().find(|(name, _)| *name == &caps[1]).map_or("", |(_, v)| v.as_str());
+        if escape_json {
+            let quoted = Value::String(value.to_string()).to_string();
+            quoted[1..quoted.len() - 1].to_string()
+        } else {
+            value.to_string()
+        }
+    })
+    .into_owned()
+}
+
+/// Content type and body sent to the channel.
+fn payload(channel: &NotificationChannel, event: &ActivityEvent, actor_name: &str) -> (String, String) {
+    let vars = variables(event, actor_name);
+    let message = || render(channel.template.as_deref().unwrap_or(DEFAULT_MESSAGE), &vars, false);
+    match channel.kind {
+        ChannelKind::Discord => ("application/json".to_string(), json!({ "content": message() }).to_string()),
+        ChannelKind::MsTeams => ("application/json".to_string(), json!({ "text": message() }).to_string()),
+        ChannelKind::Webhook => {
+            let content_type = channel.content_type.clone().unwrap_or_else(|| "application/json".to_string());
+            let body = match &channel.template {
+                Some(t) => render(t, &vars, channel.is_json()),
+                None => Value::Object(vars.into_iter().map(|(k, v)| (k.to_string(), Value::String(v))).collect()).to_string(),
+            };
+            (content_type, body)
+        }
+    }
+}
+
+async fn deliver(channel: &NotificationChannel, event: &ActivityEvent, actor_name: &str) -> Result<(), String> {
+    let (content_type, body) = payload(channel, event, actor_name);
+    let (client, url) = outbound::client_for(channel.url.expose()).await?;
+    let resp = client
+        .post(url)
+        .timeout(DELIVERY_TIMEOUT)
+        .header(reqwest::header::CONTENT_TYPE, content_type)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| format!("Unreachable: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("Answered {}", resp.status()));
+    }
+    Ok(())
+}
+
+async fn record_outcome(db: &MongoDB, channel_id: &str, outcome: &Result<(), String>) {
+    let now = bson::to_bson(&Utc::now()).unwrap_or_default();
+    let error = outcome.as_ref().err().cloned();
+    let update = doc! { "$set": { "last_delivery_at": now, "last_error": error } };
+    if let Err(e) = channels_coll(db).update_one(doc! { "channel_id": channel_id }, update).await {
+        error!("Error recording delivery to channel {}: {}", channel_id, e);
+    }
+}
+
+async fn actor_name(db: &MongoDB, actor_id: &str) -> String {
+    match usernames(db, &[actor_id.to_string()]).await {
+        Ok(names) => names.into_iter().next().map_or_else(|| actor_id.to_string(), |(_, name)| name),
+        Err(e) => {
+            error!("Error fetching username: {}", e);
+            actor_id.to_string()
+        }
+    }
+}
+
+/// Sends an activity event to the team's matching channels in the background.
+pub(crate) fn dispatch(event: &ActivityEvent) {
+    let Some(data) = STATE.get().cloned() else {
+        return;
+    };
+    let event = event.clone();
+    actix_web::rt::spawn(async move {
+        let db = &data.mongodb;
+        let mut cursor = match channels_coll(db).find(doc! { "team_id": &event.team_id, "enabled": true }).await {
+            Ok(c) => c,
+            Err(e) => {
+                error!("Error fetching notification channels: {}", e);
+                return;
+            }
+        };
+        let mut targets = Vec::new();
+        while let Some(channel) = cursor.next().await {
+            match channel {
+                Ok(c) if c.wants(&event) => targets.push(c),
+                Ok(_) => {}
+                Err(e) => error!("Error reading notification channel: {}", e),
+            }
+        }
+        if targets.is_empty() {
+            return;
+        }
+        let name = actor_name(db, &event.actor_id).await;
+        for channel in targets {
+            let outcome = deliver(&channel, &event, &name).await;
+            if let Err(e) = &outcome {
+                warn!("Notification channel {} failed: {}", channel.channel_id, e);
+            }
+            record_outcome(db, &channel.channel_id, &outcome).await;
+        }
+    });
+}
+
+/// GET /teams/{team_id}/notification-channels
+pub async fn list_channels(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage notification channels");
+    }
+    let mut cursor = match channels_coll(&data.mongodb).find(doc! { "team_id": &*team_id }).sort(doc! { "created_at": 1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching notification channels: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching channels");
+        }
+    };
+    let mut channels = Vec::new();
+    while let Some(c) = cursor.next().await {
+        match c {
+            Ok(c) => channels.push(ChannelView::from(c)),
+            Err(e) => error!("Error reading notification channel: {}", e),
+        }
+    }
+    HttpResponse::Ok().json(channels)
+}
+
+/// POST /teams/{team_id}/notification-channels
+pub async fn create_channel(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<ChannelRequest>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage notification channels");
+    }
+    if let Err(msg) = validate(&payload).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    let Some(url) = payload.url.clone() else {
+        return HttpResponse::BadRequest().body("url is required");
+    };
+    match channels_coll(&data.mongodb).count_documents(doc! { "team_id": &*team_id }).await {
+        Ok(n) if n >= MAX_CHANNELS => {
+            return HttpResponse::BadRequest().body(format!("A team can have at most {} notification channels", MAX_CHANNELS));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("Error counting notification channels: {}", e);
+            return HttpResponse::InternalServerError().body("Error creating channel");
+        }
+    }
+    let req = payload.into_inner();
+    let channel = NotificationChannel {
+        channel_id: Uuid::new_v4().to_string(),
+        team_id: team_id.into_inner(),
+        name: req.name.trim().to_string(),
+        kind: req.kind,
+        url: EncryptedString::new(url.trim()),
+        template: req.template,
+        content_type: req.content_type,
+        rules: req.rules,
+        enabled: req.enabled.unwrap_or(true),
+        created_by: auth.user_id().to_string(),
+        created_at: Utc::now(),
+        last_delivery_at: None,
+        last_error: None,
+    };
+    match channels_coll(&data.mongodb).insert_one(&channel).await {
+        Ok(_) => HttpResponse::Ok().json(ChannelView::from(channel)),
+        Err(e) => {
+            error!("Error creating notification channel: {}", e);
+            HttpResponse::InternalServerError().body("Error creating channel")
+        }
+    }
+}
+
+/// PUT /teams/{team_id}/notification-channels/{channel_id}
+/// Replaces the channel's settings; delivery status is kept.
+pub async fn update_channel(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<ChannelRequest>,
+) -> impl Responder {
+    let (team_id, channel_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage notification channels");
+    }
+    if let Err(msg) = validate(&payload).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    let req = payload.into_inner();
+    let (kind, rules) = match (bson::to_bson(&req.kind), bson::to_bson(&req.rules)) {
+        (Ok(k), Ok(r)) => (k, r),
+        (Err(e), _) | (_, Err(e)) => return HttpResponse::InternalServerError().body(format!("Error saving channel: {}", e)),
+    };
+    let mut set = doc! {
+        "name": req.name.trim(),
+        "kind": kind,
+        "template": req.template,
+        "content_type": req.content_type,
+        "rules": rules,
+        "enabled": req.enabled.unwrap_or(true),
+    };
+    if let Some(url) = req.url {
+        match bson::to_bson(&EncryptedString::new(url.trim())) {
+            Ok(sealed) => set.insert("url", sealed),
+            Err(e) => return HttpResponse::InternalServerError().body(format!("Error saving channel: {}", e)),
+        };
+    }
+    let update = doc! { "$set": set };
+    match channels_coll(&data.mongodb)
+        .find_one_and_update(doc! { "channel_id": &channel_id, "team_id": &team_id }, update)
+        .return_document(mongodb::options::ReturnDocument::After)
+        .await
+    {
+        Ok(Some(c)) => HttpResponse::Ok().json(ChannelView::from(c)),
+        Ok(None) => HttpResponse::NotFound().body("Channel not found"),
+        Err(e) => {
+            error!("Error updating notification channel: {}", e);
+            HttpResponse::InternalServerError().body("Error updating channel")
+        }
+    }
+}
+
+/// DELETE /teams/{team_id}/notification-channels/{channel_id}
+pub async fn delete_channel(auth: AuthContext, data: web::Data<AppState>, path: web::Path<(String, String)>) -> impl Responder {
+    let (team_id, channel_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage notification channels");
+    }
+    match channels_coll(&data.mongodb).delete_one(doc! { "channel_id": &channel_id, "team_id": &team_id }).await {
+        Ok(r) if r.deleted_count == 1 => HttpResponse::Ok().body("Channel deleted"),
+        Ok(_) => HttpResponse::NotFound().body("Channel not found"),
+        Err(e) => {
+            error!("Error deleting notification channel: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting channel")
+        }
+    }
+}
+
+/// POST /teams/{team_id}/notification-channels/{channel_id}/test
+/// Sends a sample event right away, ignoring the rules, and reports the outcome.
+pub async fn test_channel(auth: AuthContext, data: web::Data<AppState>, path: web::Path<(String, String)>) -> impl Responder {
+    let (team_id, channel_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage notification channels");
+    }
+    let channel = match channels_coll(&data.mongodb).find_one(doc! { "channel_id": &channel_id, "team_id": &team_id }).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return HttpResponse::NotFound().body("Channel not found"),
+        Err(e) => {
+            error!("Error fetching notification channel: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching channel");
+        }
+    };
+    let event = ActivityEvent::new(&team_id, None, auth.user_id(), "channel_test", &channel_id, "sent a test notification");
+    let name = actor_name(&data.mongodb, auth.user_id()).await;
+    let outcome = deliver(&channel, &event, &name).await;
+    record_outcome(&data.mongodb, &channel_id, &outcome).await;
+    match outcome {
+        Ok(()) => HttpResponse::Ok().json(json!({ "delivered": true })),
+        Err(e) => HttpResponse::Ok().json(json!({ "delivered": false, "error": e })),
+    }
+}
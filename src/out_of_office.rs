@@ -0,0 +1,267 @@
+// src/out_of_office.rs
+//
+// Out-of-office periods. While a user is away, tickets newly assigned to them go to
+// their delegate instead (`redirect`) or are assigned anyway with a warning to the
+// assigner (`flag`), and an @mention in a chat gets an automatic reply from them,
+// at most once per chat every few hours. Team dashboards list who is away in the
+// current week.
+
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::error;
+use mongodb::bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document};
+use mongodb::options::ReturnDocument;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::do_not_disturb::user_timezone;
+
+/// Minimum time between two automatic replies of one user in the same chat.
+const AUTO_REPLY_COOLDOWN: Duration = Duration::from_secs(4 * 3600);
+const MAX_MESSAGE_LEN: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnAssignment {
+    /// Assign to the delegate instead
+    Redirect,
+    /// Assign anyway and warn the assigner
+    #[default]
+    Flag,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutOfOffice {
+    pub user_id: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Teammate who covers for the user
+    pub delegate: Option<String>,
+    #[serde(default)]
+    pub on_assignment: OnAssignment,
+    /// Appended to the automatic chat reply
+    pub message: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl OutOfOffice {
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.start <= now && now < self.end
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OutOfOfficeRequest {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub delegate: Option<String>,
+    #[serde(default)]
+    pub on_assignment: OnAssignment,
+    pub message: Option<String>,
+}
+
+fn ooo_coll(db: &MongoDB) -> mongodb::Collection<OutOfOffice> {
+    db.db.collection::<OutOfOffice>("out_of_office")
+}
+
+/// The user's out-of-office period if it covers `now`.
+async fn active_absence(db: &MongoDB, user_id: &str, now: DateTime<Utc>) -> mongodb::error::Result<Option<OutOfOffice>> {
+    Ok(ooo_coll(db).find_one(doc! { "user_id": user_id }).await?.filter(|o| o.is_active(now)))
+}
+
+/// Who a new assignment to `assignee` in the team should go to, plus a note for the
+/// assigner when `assignee` is out of office. A delegate who left the team or is away
+/// themselves is skipped and the assignment flagged instead.
+pub(crate) async fn route_assignment(data: &AppState, team_id: &str, assignee: &str) -> (String, Option<String>) {
+    let now = Utc::now();
+    let absence = match active_absence(&data.mongodb, assignee, now).await {
+        Ok(Some(a)) => a,
+        Ok(None) => return (assignee.to_string(), None),
+        Err(e) => {
+            error!("Error checking out-of-office of {}: {}", assignee, e);
+            return (assignee.to_string(), None);
+        }
+    };
+    let until = absence.end.format("%Y-%m-%d");
+    if let (OnAssignment::Redirect, Some(delegate)) = (absence.on_assignment, &absence.delegate) {
+        let on_team = data.mongodb.check_user_team(delegate, team_id).await.unwrap_or(false);
+        let away = !matches!(active_absence(&data.mongodb, delegate, now).await, Ok(None));
+        if on_team && !away {
+            return (delegate.clone(), Some(format!("The assignee is out of office until {}; assigned to their delegate instead", until)));
+        }
+    }
+    (assignee.to_string(), Some(format!("The assignee is out of office until {}", until)))
+}
+
+pub(crate) async fn usernames(db: &MongoDB, user_ids: &[String]) -> mongodb::error::Result<Vec<(String, String)>> {
+    let oids: Vec<ObjectId> = user_ids.iter().filter_map(|id| ObjectId::parse_str(id).ok()).collect();
+    let mut cursor = db.db.collection::<Document>("users").find(doc! { "_id": { "$in": oids } }).await?;
+    let mut out = Vec::new();
+    while cursor.advance().await? {
+        let user = cursor.deserialize_current()?;
+        if let (Ok(oid), Ok(name)) = (user.get_object_id("_id"), user.get_str("username")) {
+            out.push((oid.to_hex(), name.to_string()));
+        }
+    }
+    Ok(out)
+}
+
+/// Automatic replies to a chat message: one `(user_id, text)` per participant who
+/// is @mentioned, out of office, and has not replied in the chat recently.
+pub(crate) async fn auto_replies(db: &MongoDB, chat_id: &str, participants: &[String], sender_id: &str, content: &str) -> Vec<(String, String)> {
+    if !content.contains('@') {
+        return Vec::new();
+    }
+    let others: Vec<String> = participants.iter().filter(|p| *p != sender_id).cloned().collect();
+    let names = match usernames(db, &others).await {
+        Ok(n) => n,
+        Err(e) => {
+            error!("Error fetching chat participants: {}", e);
+            return Vec::new();
+        }
+    };
+    let now = Utc::now();
+    let mut replies = Vec::new();
+    for (user_id, name) in names {
+        let Ok(pattern) = Regex::new(&format!(r"(?i)@{}\b", regex::escape(&name))) else { continue };
+        if !pattern.is_match(content) {
+            continue;
+        }
+        let absence = match active_absence(db, &user_id, now).await {
+            Ok(Some(a)) => a,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Error checking out-of-office of {}: {}", user_id, e);
+                continue;
+            }
+        };
+        // Claim the reply first so concurrent mentions only answer once.
+        let field = format!("last_replies.{}", chat_id);
+        let cutoff = BsonDateTime::from_millis(now.timestamp_millis() - AUTO_REPLY_COOLDOWN.as_millis() as i64);
+        let claimed = db
+            .db
+            .collection::<Document>("out_of_office")
+            .update_one(
+                doc! { "user_id": &user_id, field.clone(): { "$not": { "$gt": cutoff } } },
+                doc! { "$set": { field: BsonDateTime::from_millis(now.timestamp_millis()) } },
+            )
+            .await;
+        match claimed {
+            Ok(r) if r.modified_count == 1 => {}
+            Ok(_) => continue,
+            Err(e) => {
+                error!("Error recording automatic reply of {}: {}", user_id, e);
+                continue;
+            }
+        }
+        let tz = user_timezone(db, &user_id).await;
+        let mut text = format!("I'm out of office until {}.", absence.end.with_timezone(&tz).format("%b %-d, %H:%M %Z"));
+        if let Some(message) = &absence.message {
+            text.push(' ');
+            text.push_str(message);
+        }
+        if let Some(delegate) = &absence.delegate {
+            if let Some((_, name)) = usernames(db, std::slice::from_ref(delegate)).await.ok().and_then(|n| n.into_iter().next()) {
+                text.push_str(&format!(" Please contact @{} in the meantime.", name));
+            }
+        }
+        replies.push((user_id, text));
+    }
+    replies
+}
+
+/// GET /users/me/out-of-office
+pub async fn get_out_of_office(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    match ooo_coll(&data.mongodb).find_one(doc! { "user_id": auth.user_id() }).await {
+        Ok(Some(o)) => HttpResponse::Ok().json(o),
+        Ok(None) => HttpResponse::NotFound().body("No out-of-office period set"),
+        Err(e) => {
+            error!("Error fetching out-of-office: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching out-of-office")
+        }
+    }
+}
+
+/// PUT /users/me/out-of-office
+/// Replaces the user's out-of-office period.
+pub async fn set_out_of_office(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    payload: web::Json<OutOfOfficeRequest>,
+) -> impl Responder {
+    let req = payload.into_inner();
+    let user_id = auth.user_id();
+    if req.end <= req.start {
+        return HttpResponse::BadRequest().body("end must be after start");
+    }
+    if req.end <= Utc::now() {
+        return HttpResponse::BadRequest().body("end must be in the future");
+    }
+    let message = req.message.map(|m| m.trim().to_string()).filter(|m| !m.is_empty());
+    if message.as_ref().is_some_and(|m| m.chars().count() > MAX_MESSAGE_LEN) {
+        return HttpResponse::BadRequest().body(format!("message may be at most {} characters", MAX_MESSAGE_LEN));
+    }
+    if let Some(delegate) = &req.delegate {
+        if delegate == user_id {
+            return HttpResponse::BadRequest().body("You cannot delegate to yourself");
+        }
+        let shared = match (data.mongodb.user_team_ids(user_id).await, data.mongodb.user_team_ids(delegate).await) {
+            (Ok(mine), Ok(theirs)) => mine.iter().any(|t| theirs.contains(t)),
+            (Err(e), _) | (_, Err(e)) => {
+                error!("Error fetching teams: {}", e);
+                return HttpResponse::InternalServerError().body("Error saving out-of-office");
+            }
+        };
+        if !shared {
+            return HttpResponse::BadRequest().body("The delegate must share a team with you");
+        }
+    } else if req.on_assignment == OnAssignment::Redirect {
+        return HttpResponse::BadRequest().body("redirect needs a delegate");
+    }
+
+    let on_assignment = match req.on_assignment {
+        OnAssignment::Redirect => "redirect",
+        OnAssignment::Flag => "flag",
+    };
+    let update = doc! {
+        "$set": {
+            "start": req.start.to_rfc3339(),
+            "end": req.end.to_rfc3339(),
+            "delegate": req.delegate,
+            "on_assignment": on_assignment,
+            "message": message,
+            "updated_at": Utc::now().to_rfc3339(),
+            "last_replies": {},
+        }
+    };
+    match ooo_coll(&data.mongodb)
+        .find_one_and_update(doc! { "user_id": user_id }, update)
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .await
+    {
+        Ok(Some(o)) => HttpResponse::Ok().json(o),
+        Ok(None) => HttpResponse::InternalServerError().body("Error saving out-of-office"),
+        Err(e) => {
+            error!("Error saving out-of-office: {}", e);
+            HttpResponse::InternalServerError().body("Error saving out-of-office")
+        }
+    }
+}
+
+/// DELETE /users/me/out-of-office
+pub async fn clear_out_of_office(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    match ooo_coll(&data.mongodb).delete_one(doc! { "user_id": auth.user_id() }).await {
+        Ok(r) if r.deleted_count == 0 => HttpResponse::NotFound().body("No out-of-office period set"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Error clearing out-of-office: {}", e);
+            HttpResponse::InternalServerError().body("Error clearing out-of-office")
+        }
+    }
+}
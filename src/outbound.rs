@@ -0,0 +1,98 @@
+// src/outbound.rs
+//
+// Requests to URLs chosen by users: notification channels, bot webhooks and report
+// deliveries. Such a URL must be https and its host must resolve to public addresses
+// only, so it cannot reach the server's own network or a cloud metadata endpoint.
+// URLs are checked when they are saved and again before every request, since DNS
+// answers change; the client used for the request connects to exactly the addresses
+// that were checked and does not follow redirects, which could lead anywhere.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use reqwest::redirect::Policy;
+use reqwest::{Client, Url};
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        // Shared address space (carrier-grade NAT)
+        || (a == 100 && (64..128).contains(&b))
+        // Benchmarking
+        || (a == 198 && (b == 18 || b == 19))
+        // Reserved
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_v4(v4);
+    }
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local, fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // Link-local, fe80::/10
+        || (first & 0xffc0) == 0xfe80
+        // Documentation, 2001:db8::/32
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_public_v4(v4),
+        IpAddr::V6(v6) => is_public_v6(v6),
+    }
+}
+
+/// Parses a user-supplied URL and resolves its host, refusing anything that is not
+/// https or reaches a non-public address.
+async fn resolve(raw: &str) -> Result<(Url, Vec<SocketAddr>), String> {
+    let url = Url::parse(raw.trim()).map_err(|_| "url is not a valid URL".to_string())?;
+    if url.scheme() != "https" {
+        return Err("url must be an https URL".to_string());
+    }
+    let port = url.port_or_known_default().unwrap_or(443);
+    let host = url.host_str().filter(|h| !h.is_empty()).ok_or("url has no host")?;
+    // IPv6 literals come bracketed.
+    let literal = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().ok();
+    let addrs: Vec<SocketAddr> = match literal {
+        Some(ip) => vec![SocketAddr::new(ip, port)],
+        None => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|_| format!("Cannot resolve {}", host))?
+            .collect(),
+    };
+    if addrs.is_empty() {
+        return Err(format!("Cannot resolve {}", host));
+    }
+    if addrs.iter().any(|a| !is_public(a.ip())) {
+        return Err(format!("{} is not a public address", host));
+    }
+    Ok((url, addrs))
+}
+
+/// Checks a user-supplied URL before it is saved.
+pub(crate) async fn check_url(raw: &str) -> Result<(), String> {
+    resolve(raw).await.map(|_| ())
+}
+
+/// A client for one request to a user-supplied URL, pinned to the addresses that
+/// were just checked, and the parsed URL to send it to.
+pub(crate) async fn client_for(raw: &str) -> Result<(Client, Url), String> {
+    let (url, addrs) = resolve(raw).await?;
+    let mut builder = Client::builder().redirect(Policy::none());
+    if let Some(domain) = url.domain() {
+        builder = builder.resolve_to_addrs(domain, &addrs);
+    }
+    let client = builder.build().map_err(|e| format!("Cannot create HTTP client: {}", e))?;
+    Ok((client, url))
+}
@@ -0,0 +1,407 @@
+// src/personal_tasks.rs
+//
+// Personal tasks: a per-user inbox that lives outside teams and projects. Tasks can
+// carry a due date and a reminder, and can later be promoted into a project ticket.
+
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, SecondsFormat, Timelike, Utc};
+use futures_util::StreamExt;
+use log::{error, info};
+use mongodb::bson::{doc, Bson};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::activity::{record_activity, ActivityEvent};
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::Board;
+use crate::do_not_disturb::try_notify_user;
+use crate::response::CodedError;
+use crate::tenancy::{ProjectsScope, Repo};
+use crate::ticket::Ticket;
+use crate::ticket_events::{commit, TicketChange};
+
+/// How often due reminders are delivered.
+const REMINDER_INTERVAL: Duration = Duration::from_secs(60);
+/// How long a claimed reminder is left to its instance before another retries it.
+const REMINDER_LEASE_MINUTES: i64 = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PersonalTask {
+    pub task_id: String,
+    pub owner_id: String,
+    pub title: String,
+    pub notes: Option<String>,
+    pub due_date: Option<DateTime<Utc>>,
+    /// Stored with whole seconds so that string comparison in queries is chronological
+    pub remind_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub reminder_sent: bool,
+    #[serde(default)]
+    pub done: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePersonalTaskRequest {
+    pub title: String,
+    pub notes: Option<String>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub remind_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePersonalTaskRequest {
+    pub title: Option<String>,
+    pub notes: Option<String>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub remind_at: Option<DateTime<Utc>>,
+    pub done: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PersonalTaskQuery {
+    #[serde(default)]
+    pub include_done: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConvertTaskRequest {
+    pub team_id: String,
+    pub project_id: String,
+    pub board_id: String,
+    pub status: Option<String>,
+    pub priority: Option<String>,
+}
+
+fn whole_seconds(t: DateTime<Utc>) -> DateTime<Utc> {
+    t.with_nanosecond(0).unwrap_or(t)
+}
+
+fn tasks_coll(data: &AppState) -> mongodb::Collection<PersonalTask> {
+    data.mongodb.db.collection::<PersonalTask>("personal_tasks")
+}
+
+async fn owned_tasks(data: &AppState, owner_id: &str, include_done: bool) -> mongodb::error::Result<Vec<PersonalTask>> {
+    let mut filter = doc! { "owner_id": owner_id };
+    if !include_done {
+        filter.insert("done", false);
+    }
+    let mut cursor = tasks_coll(data).find(filter).sort(doc! { "created_at": -1 }).await?;
+    let mut tasks = Vec::new();
+    while let Some(task) = cursor.next().await {
+        tasks.push(task?);
+    }
+    Ok(tasks)
+}
+
+/// POST /users/me/tasks
+pub async fn create_personal_task(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    payload: web::Json<CreatePersonalTaskRequest>,
+) -> impl Responder {
+    let current_user = auth.user_id().to_string();
+    if payload.title.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Title must not be empty");
+    }
+
+    let now = Utc::now();
+    let task = PersonalTask {
+        task_id: Uuid::new_v4().to_string(),
+        owner_id: current_user,
+        title: payload.title.clone(),
+        notes: payload.notes.clone(),
+        due_date: payload.due_date,
+        remind_at: payload.remind_at.map(whole_seconds),
+        reminder_sent: false,
+        done: false,
+        created_at: now,
+        updated_at: now,
+    };
+    match tasks_coll(&data).insert_one(&task).await {
+        Ok(_) => HttpResponse::Ok().json(task),
+        Err(e) => {
+            error!("Error creating personal task: {}", e);
+            HttpResponse::InternalServerError().body("Error creating task")
+        }
+    }
+}
+
+/// GET /users/me/tasks
+pub async fn list_personal_tasks(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    query: web::Query<PersonalTaskQuery>,
+) -> impl Responder {
+    let current_user = auth.user_id().to_string();
+    match owned_tasks(&data, &current_user, query.include_done).await {
+        Ok(tasks) => HttpResponse::Ok().json(tasks),
+        Err(e) => {
+            error!("Error listing personal tasks: {}", e);
+            HttpResponse::InternalServerError().body("Error listing tasks")
+        }
+    }
+}
+
+/// PUT /users/me/tasks/{task_id}
+pub async fn update_personal_task(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    task_id: web::Path<String>,
+    payload: web::Json<UpdatePersonalTaskRequest>,
+) -> impl Responder {
+    let current_user = auth.user_id().to_string();
+
+    let mut update_doc = doc! {};
+    if let Some(title) = &payload.title {
+        if title.trim().is_empty() {
+            return HttpResponse::BadRequest().body("Title must not be empty");
+        }
+        update_doc.insert("title", title);
+    }
+    if let Some(notes) = &payload.notes { update_doc.insert("notes", notes); }
+    if let Some(due_date) = &payload.due_date {
+        update_doc.insert("due_date", mongodb::bson::to_bson(due_date).unwrap_or(Bson::Null));
+    }
+    if let Some(remind_at) = payload.remind_at {
+        update_doc.insert("remind_at", mongodb::bson::to_bson(&whole_seconds(remind_at)).unwrap_or(Bson::Null));
+        // A new reminder time re-arms the reminder.
+        update_doc.insert("reminder_sent", false);
+        update_doc.insert("reminder_claimed_until", Bson::Null);
+    }
+    if let Some(done) = payload.done { update_doc.insert("done", done); }
+    if update_doc.is_empty() {
+        return HttpResponse::BadRequest().error("no_fields_to_update");
+    }
+    update_doc.insert("updated_at", mongodb::bson::to_bson(&Utc::now()).unwrap_or(Bson::Null));
+
+    let filter = doc! { "task_id": &*task_id, "owner_id": &current_user };
+    match tasks_coll(&data).find_one_and_update(filter, doc! { "$set": update_doc })
+        .return_document(mongodb::options::ReturnDocument::After)
+        .await
+    {
+        Ok(Some(task)) => HttpResponse::Ok().json(task),
+        Ok(None) => HttpResponse::NotFound().error("task_not_found"),
+        Err(e) => {
+            error!("Error updating personal task: {}", e);
+            HttpResponse::InternalServerError().body("Error updating task")
+        }
+    }
+}
+
+/// DELETE /users/me/tasks/{task_id}
+pub async fn delete_personal_task(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    task_id: web::Path<String>,
+) -> impl Responder {
+    let current_user = auth.user_id().to_string();
+    match tasks_coll(&data).delete_one(doc! { "task_id": &*task_id, "owner_id": &current_user }).await {
+        Ok(res) if res.deleted_count == 1 => HttpResponse::Ok().body("Task deleted"),
+        Ok(_) => HttpResponse::NotFound().error("task_not_found"),
+        Err(e) => {
+            error!("Error deleting personal task: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting task")
+        }
+    }
+}
+
+/// POST /users/me/tasks/{task_id}/convert
+/// Promotes a personal task into a ticket assigned to its owner; the task is removed.
+pub async fn convert_personal_task(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    task_id: web::Path<String>,
+    payload: web::Json<ConvertTaskRequest>,
+) -> impl Responder {
+    let current_user = auth.user_id().to_string();
+
+    // Same checks as creating a ticket directly.
+    let scope = match auth.project_scope(&payload.team_id, &payload.project_id).await {
+        Ok(scope) => scope,
+        Err(resp) => return resp,
+    };
+    if !auth.is_project_member(&payload.project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
+    let board_ok = Repo::<Board>::new(&data.mongodb, &scope)
+        .find_one(doc! { "board_id": &payload.board_id })
+        .await
+        .ok()
+        .flatten()
+        .is_some();
+    if !board_ok {
+        return HttpResponse::NotFound().error("board_not_found");
+    }
+
+    // Taking the task out first means a repeated request cannot create a second
+    // ticket, and its reminder no longer fires; it is put back if the ticket fails.
+    let task = match tasks_coll(&data).find_one_and_delete(doc! { "task_id": &*task_id, "owner_id": &current_user }).await {
+        Ok(Some(t)) => t,
+        Ok(None) => return HttpResponse::NotFound().error("task_not_found"),
+        Err(e) => {
+            error!("Error fetching personal task: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching task");
+        }
+    };
+
+    let ticket = Ticket {
+        id: None,
+        ticket_id: Uuid::new_v4().to_string(),
+        board_id: payload.board_id.clone(),
+        project_id: payload.project_id.clone(),
+        title: task.title.clone(),
+        description: task.notes.clone(),
+        status: payload.status.clone().unwrap_or_else(|| "To Do".to_string()),
+        priority: payload.priority.clone(),
+        reporter: current_user.clone(),
+        requester_email: None,
+        assignee: Some(current_user.clone()),
+        due_date: task.due_date,
+        ticket_type: Some("Task".to_string()),
+        sprint: None,
+        rank: None,
+        labels: None,
+        fix_version: None,
+        estimate_hours: None,
+        blocked_by: Vec::new(),
+        attachments: None,
+        comments: Some(vec![]),
+        references: Vec::new(),
+        created_at: Utc::now(),
+        resolution: None,
+        resolved_at: None,
+        resolved_by: None,
+        vote_count: 0,
+        version: 0,
+    };
+    let ticket = match commit(&data.mongodb, None, vec![TicketChange::Created { ticket: Box::new(ticket) }], &current_user).await {
+        Ok(Some(t)) => t,
+        Ok(None) | Err(_) => {
+            error!("Error inserting ticket for personal task {}", task.task_id);
+            if let Err(e) = tasks_coll(&data).insert_one(&task).await {
+                error!("Error restoring personal task {}: {}", task.task_id, e);
+            }
+            return HttpResponse::InternalServerError().body("Error inserting ticket");
+        }
+    };
+
+    info!("Personal task {} converted to ticket {}", task.task_id, ticket.ticket_id);
+    record_activity(&data.mongodb, ActivityEvent::new(
+        &payload.team_id,
+        Some(&payload.project_id),
+        &current_user,
+        "ticket_created",
+        &ticket.ticket_id,
+        format!("created ticket \"{}\"", ticket.title),
+    )).await;
+    HttpResponse::Ok().json(ticket)
+}
+
+#[derive(Debug, Serialize)]
+pub struct MyWork {
+    /// Open tickets assigned to the caller, across all teams
+    pub tickets: Vec<Ticket>,
+    pub personal_tasks: Vec<PersonalTask>,
+}
+
+/// GET /users/me/work
+pub async fn get_my_work(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    let current_user = auth.user_id().to_string();
+
+    let filter = doc! {
+        "assignee": &current_user,
+        "status": { "$nin": ["Done", "done", "Closed", "closed", "Resolved", "resolved"] },
+    };
+    let scope = match ProjectsScope::of_user(&data.mongodb, &current_user).await {
+        Ok(scope) => scope,
+        Err(e) => {
+            error!("Error fetching projects: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_tickets");
+        }
+    };
+    let mut tickets = Vec::new();
+    match Repo::<Ticket>::across(&data.mongodb, &scope).find(filter).await {
+        Ok(mut cursor) => {
+            while let Some(res) = cursor.next().await {
+                match res {
+                    Ok(t) => tickets.push(t),
+                    Err(e) => error!("Error reading ticket: {}", e),
+                }
+            }
+        }
+        Err(e) => {
+            error!("Error fetching assigned tickets: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_tickets");
+        }
+    }
+    let personal_tasks = match owned_tasks(&data, &current_user, false).await {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Error listing personal tasks: {}", e);
+            return HttpResponse::InternalServerError().body("Error listing tasks");
+        }
+    };
+
+    HttpResponse::Ok().json(MyWork { tickets, personal_tasks })
+}
+
+/// Push every due, unsent reminder to its owner. Each one is claimed with a lease so
+/// a second instance skips it; it is only marked sent once delivered.
+async fn deliver_reminders(data: &AppState) {
+    let now = Utc::now();
+    let lease = (now + chrono::Duration::minutes(REMINDER_LEASE_MINUTES)).to_rfc3339_opts(SecondsFormat::Secs, true);
+    let now = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+    loop {
+        let filter = doc! {
+            "reminder_sent": false,
+            "done": false,
+            "remind_at": { "$lte": &now },
+            "reminder_claimed_until": { "$not": { "$gt": &now } },
+        };
+        let claimed = tasks_coll(data)
+            .find_one_and_update(filter, doc! { "$set": { "reminder_claimed_until": &lease } })
+            .sort(doc! { "remind_at": 1 })
+            .await;
+        let task = match claimed {
+            Ok(Some(t)) => t,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Error claiming due reminders: {}", e);
+                return;
+            }
+        };
+        let payload = serde_json::json!({
+            "type": "task_reminder",
+            "task_id": task.task_id,
+            "title": task.title,
+            "due_date": task.due_date,
+        });
+        // On failure the claim runs out and the reminder is retried.
+        if let Err(e) = try_notify_user(data, &task.owner_id, payload.to_string()).await {
+            error!("Error delivering reminder of task {}: {}", task.task_id, e);
+            continue;
+        }
+        if let Err(e) = tasks_coll(data)
+            .update_one(doc! { "task_id": &task.task_id }, doc! { "$set": { "reminder_sent": true } })
+            .await
+        {
+            error!("Error marking reminder sent: {}", e);
+        }
+    }
+}
+
+/// Start the background job that delivers personal task reminders.
+pub fn spawn_reminders(data: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(REMINDER_INTERVAL);
+        loop {
+            interval.tick().await;
+            deliver_reminders(&data).await;
+        }
+    });
+}
@@ -0,0 +1,867 @@
+// src/policy_tests.rs
+//
+// Authorization matrix for the v1 API. Every route is listed once with the access
+// rule its handler enforces (through AuthContext, or the raw user id for the older
+// handlers), and the tests check the rules from the outside:
+//
+// - the table covers exactly the routes api::v1::configure registers;
+// - projects, boards and tickets are only queried through tenancy::Repo, which adds
+//   the caller's scope;
+// - an anonymous caller is turned away from every route that is not public;
+// - tokens that are expired or signed with another key are refused everywhere;
+// - each persona below is let in or kept out of every route according to its
+//   rule. These tests need a disposable MongoDB in TEST_MONGODB_URI and are
+//   ignored by default (`cargo test -- --ignored`). The fixture is reseeded before
+//   each request, so a persona deleting the team does not affect the next one;
+// - a team's security policy also holds its members on routes naming no team.
+//
+// Path parameters other than the fixture's team, project, board, chat and user ids
+// point at nothing, so routes on tickets, comments, calls etc. exercise the checks
+// that run before the lookup: a denied persona may see 404 instead of 401/403.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use actix::Actor;
+use actix_web::http::{header::ContentType, Method, StatusCode};
+use actix_web::test::{call_service, init_service, TestRequest};
+use actix_web::{web, App};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use mongodb::bson::{doc, oid::ObjectId, to_bson, DateTime as BsonDateTime, Document};
+use serde_json::json;
+
+use crate::api::{self, V1_PREFIX};
+use crate::app_state::AppState;
+use crate::chat_db::MongoDB;
+use crate::chat_server::ChatServer;
+use crate::config::Config;
+use crate::doc_collab::DocServer;
+use crate::whiteboard::WhiteboardServer;
+use crate::Authentication;
+
+use Access::*;
+
+const GET: Method = Method::GET;
+const POST: Method = Method::POST;
+const PUT: Method = Method::PUT;
+const PATCH: Method = Method::PATCH;
+const DELETE: Method = Method::DELETE;
+
+const TEAM_ID: &str = "policy-team";
+const PROJECT_ID: &str = "policy-project";
+const BOARD_ID: &str = "policy-board";
+const CHAT_ID: &str = "policy-chat";
+
+/// Who may call a route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    /// No authentication; tokens, keys or signatures in the request are checked instead
+    Public,
+    /// Any authenticated user; the handler scopes the data to the caller
+    User,
+    /// Only the user named by the path
+    SelfOnly,
+    /// Users sharing a team with the user named by the path
+    Teammate,
+    TeamMember,
+    TeamAdmin,
+    /// The team's creator
+    TeamOwner,
+    ProjectMember,
+    ProjectOwner,
+    /// Project members who administer the target team
+    ProjectMemberAndTeamAdmin,
+    /// Project members and the board's participants
+    BoardEditor,
+    TeamAdminOrProjectOwner,
+    ChatParticipant,
+    ChatAdmin,
+    /// Users listed in ADMIN_USER_IDS, which the tests leave empty
+    PlatformAdmin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Persona {
+    /// Authenticated, but shares nothing with the fixture
+    Outsider,
+    /// Team member outside the project and the chat
+    Member,
+    /// Team member, project member and chat participant
+    Viewer,
+    /// Team admin outside the project and the chat
+    Admin,
+    /// Team creator, project owner, board creator and chat admin
+    Owner,
+}
+
+const PERSONAS: [Persona; 5] = [Persona::Outsider, Persona::Member, Persona::Viewer, Persona::Admin, Persona::Owner];
+
+impl Persona {
+    fn user_id(self) -> &'static str {
+        match self {
+            Persona::Outsider => "65f0000000000000000000a1",
+            Persona::Member => "65f0000000000000000000a2",
+            Persona::Viewer => "65f0000000000000000000a3",
+            Persona::Admin => "65f0000000000000000000a4",
+            Persona::Owner => "65f0000000000000000000a5",
+        }
+    }
+}
+
+impl Access {
+    fn allows(self, persona: Persona) -> bool {
+        use Persona::*;
+        match self {
+            Public | User => true,
+            SelfOnly | TeamOwner | ProjectOwner | ProjectMemberAndTeamAdmin | ChatAdmin => persona == Owner,
+            Teammate | TeamMember => persona != Outsider,
+            TeamAdmin | TeamAdminOrProjectOwner => matches!(persona, Admin | Owner),
+            ProjectMember | BoardEditor | ChatParticipant => matches!(persona, Viewer | Owner),
+            PlatformAdmin => false,
+        }
+    }
+}
+
+struct Route {
+    method: Method,
+    /// Registered path, optionally followed by the query string its extractor requires
+    path: &'static str,
+    access: Access,
+    /// JSON body; `{me}` and the fixture placeholders are filled in per request
+    body: Option<&'static str>,
+}
+
+fn r(method: Method, path: &'static str, access: Access, body: Option<&'static str>) -> Route {
+    Route { method, path, access, body }
+}
+
+fn routes() -> Vec<Route> {
+    vec![
+        // /auth
+        r(POST, "/auth/signup", Public, Some(r#"{"username": "policy", "password": "correct horse", "email": "policy@example.com"}"#)),
+        r(POST, "/auth/login", Public, Some(r#"{"username": "policy", "password": "correct horse"}"#)),
+        r(POST, "/auth/logout", Public, None),
+        r(POST, "/auth/refresh", User, None),
+        r(GET, "/auth/verify/{token}", Public, None),
+        r(GET, "/auth/devices/confirm/{token}", Public, None),
+        r(POST, "/auth/devices/confirm/{token}", Public, None),
+        r(POST, "/auth/resend-verification", Public, Some(r#"{"email": "policy@example.com"}"#)),
+        r(POST, "/auth/password-setup/{token}", Public, Some(r#"{"password": "correct horse battery"}"#)),
+        r(POST, "/auth/sso/start", Public, Some(r#"{"email": "policy@example.com"}"#)),
+        r(POST, "/auth/sso/callback", Public, Some(r#"{"code": "x", "state": "x"}"#)),
+        // /teams
+        r(GET, "/teams/user_teams/{user_id}", SelfOnly, None),
+        r(GET, "/teams/user_invitations/{user_id}", SelfOnly, None),
+        r(POST, "/teams", User, Some(r#"{"name": "Policy", "description": "x"}"#)),
+        r(GET, "/teams/{team_id}", TeamMember, None),
+        r(PUT, "/teams/{team_id}", TeamOwner, Some(r#"{"name": "Policy"}"#)),
+        r(DELETE, "/teams/{team_id}", TeamOwner, None),
+        r(GET, "/teams/{team_id}/members", TeamMember, None),
+        r(POST, "/teams/{team_id}/members", TeamAdmin, Some(r#"{"invitee_id": "missing"}"#)),
+        r(DELETE, "/teams/{team_id}/members", TeamAdmin, Some(r#"{"team_id": "{team_id}", "user_id": "missing"}"#)),
+        r(POST, "/teams/{team_id}/members/import", TeamAdmin, None),
+        r(POST, "/teams/{team_id}/invitations/accept", User, Some(r#"{"invitation_id": "missing"}"#)),
+        r(POST, "/teams/{team_id}/invitations/decline", User, Some(r#"{"invitation_id": "missing"}"#)),
+        r(DELETE, "/teams/{team_id}/invitations", TeamAdmin, Some(r#"{"team_id": "{team_id}", "invitation_ids": []}"#)),
+        r(POST, "/teams/{team_id}/invite_links", TeamAdmin, Some(r#"{}"#)),
+        r(GET, "/teams/{team_id}/activity", TeamMember, None),
+        r(GET, "/teams/{team_id}/capacity", TeamMember, None),
+        r(GET, "/teams/{team_id}/holidays", TeamMember, None),
+        r(POST, "/teams/{team_id}/holidays", TeamAdmin, Some(r#"{"date": "2030-01-01", "name": "Holiday"}"#)),
+        r(POST, "/teams/{team_id}/holidays/import", TeamAdmin, Some(r#"{"country": "US", "year": 2030}"#)),
+        r(PUT, "/teams/{team_id}/holidays/{holiday_id}", TeamAdmin, Some(r#"{}"#)),
+        r(DELETE, "/teams/{team_id}/holidays/{holiday_id}", TeamAdmin, None),
+        r(GET, "/teams/{team_id}/security-policy", TeamMember, None),
+        r(PUT, "/teams/{team_id}/security-policy", TeamAdmin, Some(r#"{}"#)),
+        r(DELETE, "/teams/{team_id}/security-policy", TeamAdmin, None),
+        r(GET, "/teams/{team_id}/retention", TeamAdmin, None),
+        r(PUT, "/teams/{team_id}/retention", TeamAdmin, Some(r#"{}"#)),
+        r(GET, "/teams/{team_id}/retention/preview", TeamAdmin, None),
+        r(GET, "/teams/{team_id}/retention/holds", TeamAdmin, None),
+        r(POST, "/teams/{team_id}/retention/holds", TeamAdmin, Some(r#"{"target_type": "user", "target_id": "{me}", "reason": "x"}"#)),
+        r(GET, "/teams/{team_id}/retention/holds/audit", TeamAdmin, None),
+        r(DELETE, "/teams/{team_id}/retention/holds/{hold_id}", TeamAdmin, None),
+        r(GET, "/teams/{team_id}/emoji", TeamMember, None),
+        r(POST, "/teams/{team_id}/emoji", TeamMember, None),
+        r(GET, "/teams/{team_id}/emoji/{name}", TeamMember, None),
+        r(DELETE, "/teams/{team_id}/emoji/{name}", TeamAdmin, None),
+        r(GET, "/teams/{team_id}/bots", TeamMember, None),
+        r(POST, "/teams/{team_id}/bots", TeamAdmin, Some(r#"{"name": "Bot"}"#)),
+        r(PUT, "/teams/{team_id}/bots/{bot_id}", TeamAdmin, Some(r#"{"name": "Bot"}"#)),
+        r(DELETE, "/teams/{team_id}/bots/{bot_id}", TeamAdmin, None),
+        r(POST, "/teams/{team_id}/bots/{bot_id}/key", TeamAdmin, None),
+        r(GET, "/teams/{team_id}/dashboard/layout", TeamMember, None),
+        r(PUT, "/teams/{team_id}/dashboard/layout", TeamMember, Some(r#"{"widgets": []}"#)),
+        r(GET, "/teams/{team_id}/dashboard/data", TeamMember, None),
+        r(GET, "/teams/{team_id}/analytics/export?dataset=tickets", TeamAdmin, None),
+        r(GET, "/teams/{team_id}/dependencies", TeamMember, None),
+        r(GET, "/teams/{team_id}/feedback", TeamAdmin, None),
+        r(POST, "/teams/{team_id}/feedback", TeamMember, Some(r#"{"category": "suggestion", "text": "x"}"#)),
+        r(GET, "/teams/{team_id}/feedback/mine", TeamMember, None),
+        r(POST, "/teams/{team_id}/feedback/{feedback_id}/ticket", TeamAdmin, Some(r#"{"project_id": "{project_id}", "board_id": "{board_id}"}"#)),
+        r(GET, "/teams/{team_id}/home", TeamMember, None),
+        r(PUT, "/teams/{team_id}/home", TeamAdmin, Some(r#"{"items": []}"#)),
+        r(GET, "/teams/{team_id}/home/config", TeamAdmin, None),
+        r(GET, "/teams/{team_id}/meeting-notes", TeamMember, None),
+        r(GET, "/teams/{team_id}/notification-channels", TeamAdmin, None),
+        r(POST, "/teams/{team_id}/notification-channels", TeamAdmin, Some(r#"{"name": "Channel", "kind": "webhook"}"#)),
+        r(PUT, "/teams/{team_id}/notification-channels/{channel_id}", TeamAdmin, Some(r#"{"name": "Channel", "kind": "webhook"}"#)),
+        r(DELETE, "/teams/{team_id}/notification-channels/{channel_id}", TeamAdmin, None),
+        r(POST, "/teams/{team_id}/notification-channels/{channel_id}/test", TeamAdmin, None),
+        r(GET, "/teams/{team_id}/reports", TeamAdmin, None),
+        r(POST, "/teams/{team_id}/reports", TeamAdmin, Some(r#"{"definition": {"name": "Report", "source": "tickets", "aggregations": ["count"]}}"#)),
+        r(POST, "/teams/{team_id}/reports/preview", TeamAdmin, Some(r#"{"name": "Report", "source": "tickets", "aggregations": ["count"]}"#)),
+        r(GET, "/teams/{team_id}/reports/{report_id}", TeamAdmin, None),
+        r(PUT, "/teams/{team_id}/reports/{report_id}", TeamAdmin, Some(r#"{"definition": {"name": "Report", "source": "tickets", "aggregations": ["count"]}}"#)),
+        r(DELETE, "/teams/{team_id}/reports/{report_id}", TeamAdmin, None),
+        r(GET, "/teams/{team_id}/reports/{report_id}/results", TeamAdmin, None),
+        r(PUT, "/teams/{team_id}/meeting-notes", TeamAdmin, Some(r#"{"enabled": false, "agenda_template": ""}"#)),
+        r(GET, "/teams/{team_id}/escalations", TeamMember, None),
+        r(GET, "/teams/{team_id}/escalations/rules", TeamAdmin, None),
+        r(POST, "/teams/{team_id}/escalations/rules", TeamAdmin, Some(r#"{"name": "Rule", "conditions": {}, "actions": {}}"#)),
+        r(PUT, "/teams/{team_id}/escalations/rules/{rule_id}", TeamAdmin, Some(r#"{}"#)),
+        r(DELETE, "/teams/{team_id}/escalations/rules/{rule_id}", TeamAdmin, None),
+        r(PUT, "/teams/{team_id}/branding", TeamAdmin, Some(r#"{}"#)),
+        r(POST, "/teams/{team_id}/branding/logo", TeamAdmin, None),
+        r(GET, "/teams/{team_id}/branding/logo", Public, None),
+        r(POST, "/teams/{team_id}/projects", TeamMember, Some(r#"{"name": "Project"}"#)),
+        r(GET, "/teams/{team_id}/projects", TeamMember, None),
+        r(GET, "/teams/{team_id}/projects/{project_id}", TeamMember, None),
+        r(PUT, "/teams/{team_id}/projects/{project_id}", ProjectOwner, Some(r#"{}"#)),
+        r(DELETE, "/teams/{team_id}/projects/{project_id}", ProjectOwner, None),
+        r(POST, "/teams/{team_id}/projects/{project_id}/members", ProjectOwner, Some(r#"{"user_id": "missing", "role": "member"}"#)),
+        r(GET, "/teams/{team_id}/projects/{project_id}/boards", BoardEditor, None),
+        r(POST, "/teams/{team_id}/projects/{project_id}/boards", ProjectMember, Some(r#"{"name": "Board", "board_type": "kanban"}"#)),
+        r(POST, "/teams/{team_id}/projects/{project_id}/boards/import", ProjectMember, None),
+        r(GET, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/export", BoardEditor, None),
+        r(PUT, "/teams/{team_id}/projects/{project_id}/boards/{board_id}", BoardEditor, Some(r#"{"name": "Board", "board_type": "kanban"}"#)),
+        r(DELETE, "/teams/{team_id}/projects/{project_id}/boards/{board_id}", ProjectOwner, None),
+        r(POST, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/members", BoardEditor, Some(r#"{"user_id": "missing"}"#)),
+        r(GET, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/settings", BoardEditor, None),
+        r(PUT, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/settings", BoardEditor, Some(r#"{}"#)),
+        r(GET, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/definition-of-done", BoardEditor, None),
+        r(PUT, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/definition-of-done", ProjectOwner, Some(r#"{}"#)),
+        r(GET, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/definition-of-done/overrides", ProjectMember, None),
+        r(GET, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/charts/burndown.png", BoardEditor, None),
+        r(GET, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/charts/velocity.png", BoardEditor, None),
+        r(GET, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/automations", BoardEditor, None),
+        r(POST, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/automations", BoardEditor, Some(r#"{"name": "Rule", "trigger": {"type": "ticket_created"}, "actions": []}"#)),
+        r(PUT, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/automations/{rule_id}", BoardEditor, Some(r#"{}"#)),
+        r(DELETE, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/automations/{rule_id}", BoardEditor, None),
+        r(POST, "/teams/{team_id}/projects/{project_id}/boards/{board_id}/automations/{rule_id}/test", BoardEditor, Some(r#"{}"#)),
+        r(GET, "/teams/{team_id}/projects/{project_id}/whiteboards", ProjectMember, None),
+        r(POST, "/teams/{team_id}/projects/{project_id}/whiteboards", ProjectMember, Some(r#"{"name": "Whiteboard"}"#)),
+        r(GET, "/teams/{team_id}/projects/{project_id}/whiteboards/{whiteboard_id}", ProjectMember, None),
+        r(PUT, "/teams/{team_id}/projects/{project_id}/whiteboards/{whiteboard_id}", ProjectMember, Some(r#"{"name": "Whiteboard"}"#)),
+        r(DELETE, "/teams/{team_id}/projects/{project_id}/whiteboards/{whiteboard_id}", ProjectMember, None),
+        r(GET, "/teams/{team_id}/projects/{project_id}/email-channel", TeamAdminOrProjectOwner, None),
+        r(PUT, "/teams/{team_id}/projects/{project_id}/email-channel", TeamAdminOrProjectOwner, Some(r#"{"board_id": "{board_id}"}"#)),
+        r(DELETE, "/teams/{team_id}/projects/{project_id}/email-channel", TeamAdminOrProjectOwner, None),
+        r(GET, "/teams/{team_id}/projects/{project_id}/releases", ProjectMember, None),
+        r(POST, "/teams/{team_id}/projects/{project_id}/releases", ProjectMember, Some(r#"{"name": "Release"}"#)),
+        r(GET, "/teams/{team_id}/projects/{project_id}/releases/{release_id}", ProjectMember, None),
+        r(PUT, "/teams/{team_id}/projects/{project_id}/releases/{release_id}", ProjectMember, Some(r#"{}"#)),
+        r(GET, "/teams/{team_id}/projects/{project_id}/story-map", ProjectMember, None),
+        r(PUT, "/teams/{team_id}/projects/{project_id}/story-map", ProjectMember, Some(r#"{"activities": []}"#)),
+        r(GET, "/teams/{team_id}/projects/{project_id}/tickets?board_id={board_id}", ProjectMember, None),
+        r(POST, "/teams/{team_id}/projects/{project_id}/tickets", ProjectMember, Some(r#"{"board_id": "{board_id}", "title": "Ticket"}"#)),
+        r(GET, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}", ProjectMember, None),
+        r(PUT, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}", ProjectMember, Some(r#"{}"#)),
+        r(DELETE, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}", ProjectMember, None),
+        r(POST, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/reopen", ProjectMember, Some(r#"{"reason": "x"}"#)),
+        r(GET, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/poker", ProjectMember, None),
+        r(POST, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/votes", ProjectMember, None),
+        r(DELETE, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/votes", ProjectMember, None),
+        r(PUT, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/watch", ProjectMember, Some(r#"{}"#)),
+        r(DELETE, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/watch", TeamMember, None),
+        r(GET, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/watchers", ProjectMember, None),
+        r(GET, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/comments", ProjectMember, None),
+        r(POST, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/comments", ProjectMember, Some(r#"{"content": "x"}"#)),
+        r(POST, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/comments/{comment_id}/reactions", ProjectMember, Some(r#"{"emoji": "+1"}"#)),
+        r(PUT, "/teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/comments/{comment_id}/resolved", ProjectMember, Some(r#"{"resolved": true}"#)),
+        // /admin
+        r(GET, "/admin/ws", PlatformAdmin, None),
+        r(POST, "/admin/ws/disconnect/{user_id}", PlatformAdmin, None),
+        r(POST, "/admin/encryption/rotate", PlatformAdmin, None),
+        r(POST, "/admin/impersonate/{user_id}", PlatformAdmin, Some(r#"{"reason": "x"}"#)),
+        r(GET, "/admin/impersonation/audit", PlatformAdmin, None),
+        r(GET, "/admin/api-usage", PlatformAdmin, None),
+        r(GET, "/admin/integrity", PlatformAdmin, None),
+        r(POST, "/admin/integrity/repair", PlatformAdmin, None),
+        r(GET, "/admin/invite-codes", PlatformAdmin, None),
+        r(POST, "/admin/invite-codes", PlatformAdmin, Some(r#"{}"#)),
+        r(DELETE, "/admin/invite-codes/{code}", PlatformAdmin, None),
+        r(GET, "/admin/sso-connections", PlatformAdmin, None),
+        r(POST, "/admin/sso-connections", PlatformAdmin, Some(r#"{"name": "SSO", "domains": ["example.com"], "issuer": "https://idp.example.com", "client_id": "x"}"#)),
+        r(PUT, "/admin/sso-connections/{connection_id}", PlatformAdmin, Some(r#"{"name": "SSO", "domains": ["example.com"], "issuer": "https://idp.example.com", "client_id": "x"}"#)),
+        r(DELETE, "/admin/sso-connections/{connection_id}", PlatformAdmin, None),
+        r(POST, "/admin/sso-connections/{connection_id}/scim-token", PlatformAdmin, None),
+        r(DELETE, "/admin/sso-connections/{connection_id}/scim-token", PlatformAdmin, None),
+        // /sync
+        r(GET, "/sync", User, None),
+        // /digest
+        r(GET, "/digest/unsubscribe/{token}", Public, None),
+        // /bot-api
+        r(POST, "/bot-api/chats/{chat_id}/messages", Public, Some(r#"{"content": "x"}"#)),
+        // /inbound
+        r(POST, "/inbound/email/{token}", Public, Some(r#"{"message_id": "x", "from": "someone@example.com"}"#)),
+        // /invite
+        r(GET, "/invite/{token}", Public, None),
+        r(POST, "/invite/{token}/join", User, None),
+        // /projects
+        r(GET, "/projects/{project_id}/activity", TeamMember, None),
+        r(POST, "/projects/{project_id}/duplicate", ProjectMemberAndTeamAdmin, Some(r#"{}"#)),
+        r(GET, "/projects/{project_id}/insights/stale", ProjectMember, None),
+        r(GET, "/projects/{project_id}/insights/stale/settings", ProjectMember, None),
+        r(PUT, "/projects/{project_id}/insights/stale/settings", TeamAdminOrProjectOwner, Some(r#"{"stale_days": 14, "unassigned_days": 7, "weekly_nudge": false}"#)),
+        // /ai
+        r(POST, "/ai/tickets/find_duplicates", ProjectMember, Some(r#"{"team_id": "{team_id}", "project_id": "{project_id}", "title": "Ticket"}"#)),
+        r(POST, "/ai/teams/{team_id}/assistant", TeamMember, Some(r#"{"question": "x"}"#)),
+        // /boards
+        r(POST, "/boards/{board_id}/sprints/{sprint_id}/plan", ProjectMember, Some(r#"{"ticket_ids": ["missing"]}"#)),
+        // /tickets
+        r(GET, "/tickets/voted", User, None),
+        r(GET, "/tickets/{ticket_id}/references", TeamMember, None),
+        r(GET, "/tickets/{ticket_id}/history", ProjectMember, None),
+        r(GET, "/tickets/{ticket_id}/render", ProjectMember, None),
+        r(GET, "/tickets/{ticket_id}/worklog", ProjectMember, None),
+        r(POST, "/tickets/{ticket_id}/move", ProjectMember, Some(r#"{"project_id": "{project_id}", "board_id": "{board_id}"}"#)),
+        // /dashboard
+        r(GET, "/dashboard/widgets", Public, None),
+        // /portfolio
+        r(GET, "/portfolio/dashboard", User, None),
+        // /quick_search
+        r(GET, "/quick_search?q=x", User, None),
+        // /team-data
+        r(GET, "/team-data/{team_id}", TeamMember, None),
+        r(GET, "/team-data/{team_id}/report", TeamMember, None),
+        r(GET, "/team-data/{team_id}/charts/budget.png", TeamMember, None),
+        r(PUT, "/team-data/{team_id}", TeamAdmin, Some(r#"{"budgetInput": {"totalAnnualBudget": 0.0, "monthlyDrains": []}}"#)),
+        // /chats
+        r(GET, "/chats/{user_id}", SelfOnly, None),
+        r(POST, "/chats", TeamMember, Some(r#"{"team_id": "{team_id}", "participants": ["{me}"], "message": ""}"#)),
+        r(GET, "/chats/search/{user_id}", SelfOnly, None),
+        r(PATCH, "/chats/{chat_id}", ChatParticipant, Some(r#"{"participants": ["{me}"]}"#)),
+        r(DELETE, "/chats/{chat_id}", ChatParticipant, None),
+        r(GET, "/chats/get/{chat_id}", ChatParticipant, None),
+        r(POST, "/chats/{chat_id}/pins/{message_id}", ChatParticipant, None),
+        r(DELETE, "/chats/{chat_id}/pins/{message_id}", ChatParticipant, None),
+        r(PUT, "/chats/{chat_id}/announcement", ChatAdmin, Some(r#"{}"#)),
+        r(GET, "/chats/{chat_id}/export", ChatParticipant, None),
+        r(PUT, "/chats/{chat_id}/mute", ChatParticipant, Some(r#"{}"#)),
+        r(DELETE, "/chats/{chat_id}/mute", User, None),
+        r(GET, "/chats/{chat_id}/bots", ChatParticipant, None),
+        r(POST, "/chats/{chat_id}/bots/{bot_id}", ChatAdmin, None),
+        r(DELETE, "/chats/{chat_id}/bots/{bot_id}", ChatAdmin, None),
+        r(POST, "/chats/{chat_id}/calls", ChatParticipant, Some(r#"{}"#)),
+        r(GET, "/chats/{chat_id}/calls/{call_id}", ChatParticipant, None),
+        r(POST, "/chats/{chat_id}/calls/{call_id}/join", ChatParticipant, None),
+        r(POST, "/chats/{chat_id}/calls/{call_id}/leave", ChatParticipant, None),
+        r(POST, "/chats/{chat_id}/calls/{call_id}/end", ChatParticipant, None),
+        // /messages
+        r(GET, "/messages/{chat_id}", ChatParticipant, None),
+        r(GET, "/messages/{chat_id}/search?q=x", ChatParticipant, None),
+        r(POST, "/messages/{chat_id}", ChatParticipant, Some(r#"{"sender_id": "{me}", "content": "x"}"#)),
+        r(POST, "/messages/{chat_id}/attachments", ChatParticipant, None),
+        r(GET, "/messages/{chat_id}/scheduled", User, None),
+        r(POST, "/messages/{chat_id}/scheduled", ChatParticipant, Some(r#"{"content": "x", "send_at": "2030-01-01T00:00:00Z"}"#)),
+        r(DELETE, "/messages/{chat_id}/scheduled/{scheduled_id}", User, None),
+        r(POST, "/messages/{chat_id}/{message_id}/translate?to=fr", ChatParticipant, None),
+        // /attachments
+        r(GET, "/attachments/{attachment_id}", User, None),
+        // /users
+        r(GET, "/users/find_user_email?query=x", User, None),
+        r(GET, "/users/get/{id}", User, None),
+        r(GET, "/users/working-hours", User, None),
+        r(POST, "/users/working-hours", User, Some(r#"{"start": "09:00", "end": "17:00"}"#)),
+        r(GET, "/users/me/notification-preferences", User, None),
+        r(PUT, "/users/me/notification-preferences", User, Some(r#"{}"#)),
+        r(GET, "/users/me/ui-preferences", User, None),
+        r(PUT, "/users/me/ui-preferences", User, Some(r#"{}"#)),
+        r(GET, "/users/me/focus", User, None),
+        r(POST, "/users/me/focus", User, Some(r#"{"ticket_id": "missing"}"#)),
+        r(POST, "/users/me/focus/end", User, None),
+        r(GET, "/users/me/focus/stats", User, None),
+        r(GET, "/users/me/dnd", User, None),
+        r(PUT, "/users/me/dnd", User, Some(r#"{"mode": "off"}"#)),
+        r(GET, "/users/me/scheduled-messages", User, None),
+        r(GET, "/users/me/reminders", User, None),
+        r(POST, "/users/me/reminders", User, Some(r#"{"text": "x"}"#)),
+        r(DELETE, "/users/me/reminders/{reminder_id}", User, None),
+        r(GET, "/users/me/work", User, None),
+        r(GET, "/users/me/tasks", User, None),
+        r(POST, "/users/me/tasks", User, Some(r#"{"title": "Task"}"#)),
+        r(PUT, "/users/me/tasks/{task_id}", User, Some(r#"{}"#)),
+        r(DELETE, "/users/me/tasks/{task_id}", User, None),
+        r(POST, "/users/me/tasks/{task_id}/convert", ProjectMember, Some(r#"{"team_id": "{team_id}", "project_id": "{project_id}", "board_id": "{board_id}"}"#)),
+        r(PUT, "/users/me/status", User, Some(r#"{}"#)),
+        r(DELETE, "/users/me/status", User, None),
+        r(GET, "/users/me/out-of-office", User, None),
+        r(PUT, "/users/me/out-of-office", User, Some(r#"{"start": "2030-01-01T00:00:00Z", "end": "2030-01-02T00:00:00Z"}"#)),
+        r(DELETE, "/users/me/out-of-office", User, None),
+        r(GET, "/users/me/devices", User, None),
+        r(DELETE, "/users/me/devices/{device_id}", User, None),
+        r(GET, "/users/{id}/status", Teammate, None),
+        // /ws
+        r(GET, "/ws", User, None),
+        // /calendar
+        r(POST, "/calendar/events", TeamMember, Some(r#"{"title": "Event", "start": "2030-01-01T09:00:00Z", "end": "2030-01-01T10:00:00Z", "participants": ["{me}"], "team_id": "{team_id}"}"#)),
+        r(GET, "/calendar/events/{user_id}", Teammate, None),
+        r(PUT, "/calendar/events/{event_id}/meeting-notes", User, Some(r#"{}"#)),
+        r(GET, "/calendar/tickets/{user_id}", Teammate, None),
+        // /knowledge_base
+        r(POST, "/knowledge_base", TeamMember, Some(r#"{"team_id": "{team_id}", "title": "Doc", "content": "x"}"#)),
+        r(GET, "/knowledge_base/{team_id}", TeamMember, None),
+        r(GET, "/knowledge_base/{team_id}/semantic_search?q=x", TeamMember, None),
+        r(PUT, "/knowledge_base/{doc_id}", User, Some(r#"{}"#)),
+        r(PUT, "/knowledge_base/{doc_id}/sharing", User, Some(r#"{"visibility": "team"}"#)),
+        r(DELETE, "/knowledge_base/{doc_id}", User, None),
+    ]
+}
+
+/// Fills in the fixture ids; `{user_id}` and `{id}` name the owner persona and any
+/// other path parameter names nothing.
+fn fill_path(template: &str, me: &str) -> String {
+    let mut path = fill(template, me)
+        .replace("{user_id}", Persona::Owner.user_id())
+        .replace("{id}", Persona::Owner.user_id());
+    while let (Some(start), Some(end)) = (path.find('{'), path.find('}')) {
+        path.replace_range(start..=end, "missing");
+    }
+    path
+}
+
+fn fill(template: &str, me: &str) -> String {
+    template
+        .replace("{team_id}", TEAM_ID)
+        .replace("{project_id}", PROJECT_ID)
+        .replace("{board_id}", BOARD_ID)
+        .replace("{chat_id}", CHAT_ID)
+        .replace("{me}", me)
+}
+
+/// A session token for `user_id` signed with `secret`, expiring `valid_for` from now.
+fn signed_token(user_id: &str, secret: &str, valid_for: Duration) -> String {
+    let now = Utc::now();
+    let claims = json!({
+        "sub": user_id,
+        "team_id": "",
+        "exp": (now + valid_for).timestamp(),
+        "iat": now.timestamp(),
+    });
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref())).unwrap()
+}
+
+fn token(user_id: &str) -> String {
+    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "secret".to_string());
+    signed_token(user_id, &secret, Duration::hours(1))
+}
+
+fn request(route: &Route, persona: Option<Persona>) -> TestRequest {
+    let bearer = persona.map(|p| token(p.user_id()));
+    request_with(route, persona, bearer)
+}
+
+fn request_with(route: &Route, persona: Option<Persona>, bearer: Option<String>) -> TestRequest {
+    let me = persona.map_or("anonymous", Persona::user_id);
+    let mut req = TestRequest::default()
+        .method(route.method.clone())
+        .uri(&format!("{}{}", V1_PREFIX, fill_path(route.path, me)));
+    if let Some(bearer) = bearer {
+        req = req.insert_header(("Authorization", format!("Bearer {}", bearer)));
+    }
+    if let Some(body) = route.body {
+        req = req.insert_header(ContentType::json()).set_payload(fill(body, me));
+    }
+    req
+}
+
+async fn state(mongo_uri: &str, database: &str) -> AppState {
+    for (key, default) in [("MONGO_URI", mongo_uri), ("JWT_SECRET", "secret"), ("AI_AWS_ENDPOINT", "http://localhost:9001")] {
+        if std::env::var(key).is_err() {
+            std::env::set_var(key, default);
+        }
+    }
+    let mut config = Config::from_env();
+    config.mongo_uri = mongo_uri.to_string();
+    config.database_name = database.to_string();
+    config.admin_user_ids = Vec::new();
+    config.cookie_sessions = false;
+    let mongodb = Arc::new(MongoDB::init(&config).await);
+    AppState {
+        chat_server: ChatServer::new(mongodb.clone()).start(),
+        doc_server: DocServer::new(mongodb.clone()).start(),
+        whiteboard_server: WhiteboardServer::new(mongodb.clone()).start(),
+        mongodb,
+        config,
+        http_client: Default::default(),
+        api_logger: None,
+    }
+}
+
+/// One team with a project, a board and a chat, and a user per persona. Written as
+/// raw documents so the fixture keeps to the fields every model has always had.
+async fn seed(db: &MongoDB) {
+    use Persona::*;
+    db.db.drop().await.expect("Failed to reset the test database");
+    let now = to_bson(&Utc::now()).unwrap();
+    let owner = Owner.user_id();
+    let insert = |collection: &str, docs: Vec<Document>| {
+        let collection = db.db.collection::<Document>(collection);
+        async move { collection.insert_many(docs).await.expect("Failed to seed the test database") }
+    };
+
+    let users = PERSONAS
+        .iter()
+        .map(|p| {
+            doc! {
+                "_id": ObjectId::parse_str(p.user_id()).unwrap(),
+                "username": format!("{:?}", p).to_lowercase(),
+                "email": format!("{:?}@policy.test", p).to_lowercase(),
+                "email_verified": true,
+            }
+        })
+        .collect();
+    insert("users", users).await;
+    insert(
+        "teams",
+        vec![doc! {
+            "team_id": TEAM_ID,
+            "name": "Policy",
+            "owner_id": owner,
+            "description": null,
+            "created_at": now.clone(),
+        }],
+    )
+    .await;
+    let memberships = [(Member, "member"), (Viewer, "member"), (Admin, "admin"), (Owner, "admin")]
+        .map(|(p, role)| doc! { "user_id": p.user_id(), "team_id": TEAM_ID, "role": role, "joined_at": now.clone() });
+    insert("user_teams", memberships.to_vec()).await;
+    insert(
+        "projects",
+        vec![doc! {
+            "project_id": PROJECT_ID,
+            "team_id": TEAM_ID,
+            "name": "Policy",
+            "description": null,
+            "created_at": now.clone(),
+            "created_by": owner,
+        }],
+    )
+    .await;
+    let members = [(Owner, "owner"), (Viewer, "member")]
+        .map(|(p, role)| doc! { "project_id": PROJECT_ID, "user_id": p.user_id(), "role": role, "joined_at": now.clone() });
+    insert("project_memberships", members.to_vec()).await;
+    insert(
+        "boards",
+        vec![doc! {
+            "board_id": BOARD_ID,
+            "project_id": PROJECT_ID,
+            "name": "Policy",
+            "board_type": "kanban",
+            "description": null,
+            "sprint_length": null,
+            "created_at": now.clone(),
+            "created_by": owner,
+            "participants": [owner],
+        }],
+    )
+    .await;
+    insert(
+        "chats",
+        vec![doc! {
+            "_id": CHAT_ID,
+            "participants": [owner, Viewer.user_id()],
+            "is_group": false,
+            "group_name": null,
+            "created_at": BsonDateTime::now(),
+            "last_message_at": BsonDateTime::now(),
+            "admins": [owner],
+            "team_id": TEAM_ID,
+        }],
+    )
+    .await;
+}
+
+/// (method, path) of every route api::v1::configure registers, read from its source.
+fn registered_routes() -> Vec<(String, String)> {
+    let src = include_str!("api/v1.rs");
+    let src = src[src.find("pub fn configure").expect("configure in api/v1.rs")..]
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n");
+    // Scopes apply until the parenthesis they were opened in closes.
+    let mut scopes: Vec<(i32, &str)> = Vec::new();
+    let mut depth = 0;
+    let mut found = Vec::new();
+    let mut rest = src.as_str();
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("web::scope(\"") {
+            let (name, after) = after.split_once("\")").expect("scope path");
+            scopes.push((depth, name));
+            rest = after;
+            continue;
+        }
+        let registration = rest.strip_prefix(".route(\"").or_else(|| rest.strip_prefix("web::resource(\""));
+        if let Some(after) = registration {
+            let (path, after) = after.split_once('"').expect("route path");
+            let (method, after) = after.split_once("web::").and_then(|(_, a)| a.split_once("()")).expect("route method");
+            let (_, after) = after.split_once(".to(").and_then(|(_, a)| a.split_once(')')).expect("route handler");
+            let prefix: String = scopes.iter().map(|(_, s)| *s).collect();
+            found.push((method.to_uppercase(), format!("{}{}", prefix, path)));
+            // The registration's own parenthesis is still open.
+            depth += 1;
+            rest = after;
+            continue;
+        }
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                while scopes.last().is_some_and(|(d, _)| *d > depth) {
+                    scopes.pop();
+                }
+            }
+            _ => {}
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    found
+}
+
+fn key(route: &Route) -> (String, String) {
+    let path = route.path.split('?').next().unwrap_or(route.path);
+    (route.method.as_str().to_string(), path.to_string())
+}
+
+/// Nothing listens here: a handler reaching the database before rejecting the
+/// caller fails with a 500 instead of hanging.
+async fn offline_state() -> web::Data<AppState> {
+    web::Data::new(state("mongodb://127.0.0.1:9/?serverSelectionTimeoutMS=200", "taskline_policy").await)
+}
+
+/// Sends every non-public route with `bearer` and lists the ones not answered 401/403.
+async fn served_with(bearer: impl Fn() -> Option<String>) -> Vec<String> {
+    let data = offline_state().await;
+    let config = data.config.clone();
+    let app = init_service(
+        App::new()
+            .wrap(Authentication)
+            .app_data(data.clone())
+            .service(web::scope(V1_PREFIX).configure(|cfg| api::v1::configure(cfg, &config))),
+    )
+    .await;
+    let mut served = Vec::new();
+    for route in routes().iter().filter(|r| r.access != Public) {
+        let status = call_service(&app, request_with(route, None, bearer()).to_request()).await.status();
+        if !matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+            served.push(format!("{} {}: {}", route.method, route.path, status));
+        }
+    }
+    served
+}
+
+#[test]
+fn registered_routes_carry_their_scopes() {
+    let registered = registered_routes();
+    for expected in [
+        ("POST", "/auth/login"),
+        ("GET", "/teams/{team_id}/projects/{project_id}/boards"),
+        ("GET", "/messages/{chat_id}"),
+    ] {
+        assert!(
+            registered.iter().any(|(m, p)| (m.as_str(), p.as_str()) == expected),
+            "{:?} not found among the registered routes",
+            expected
+        );
+    }
+    assert!(registered.iter().all(|(_, p)| p.starts_with('/')), "route without its scope");
+}
+
+#[test]
+fn every_route_has_a_policy() {
+    let table: Vec<_> = routes().iter().map(key).collect();
+    let listed: HashSet<_> = table.iter().cloned().collect();
+    assert_eq!(listed.len(), table.len(), "routes listed twice in the policy table");
+
+    let registered: HashSet<_> = registered_routes().into_iter().collect();
+    let mut missing: Vec<_> = registered.difference(&listed).collect();
+    let mut stale: Vec<_> = listed.difference(&registered).collect();
+    missing.sort();
+    stale.sort();
+    assert!(missing.is_empty(), "routes without a policy: {:?}", missing);
+    assert!(stale.is_empty(), "policies for routes that are not registered: {:?}", stale);
+}
+
+/// Flags a tenant collection named in `collection(...)` or passed on to a helper as an
+/// argument. integrity.rs and migrations.rs are shared with taskline-admin, which has
+/// no tenancy layer, and work on every tenant's documents anyway.
+#[test]
+fn tenant_collections_are_only_queried_through_repo() {
+    const EXEMPT: [&str; 5] = ["tenancy.rs", "integrity.rs", "migrations.rs", "policy_tests.rs", "taskline-admin.rs"];
+    const NEEDLES: [&str; 6] = ["\"tickets\"", "\"boards\"", "\"projects\"", "Ticket::COLLECTION", "Board::COLLECTION", "Project::COLLECTION"];
+    let mut dirs = vec![std::path::PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src"))];
+    let mut raw = Vec::new();
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir).expect("source directory") {
+            let path = entry.expect("directory entry").path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string();
+            if !name.ends_with(".rs") || EXEMPT.contains(&name.as_str()) {
+                continue;
+            }
+            let src = std::fs::read_to_string(&path).expect("source file");
+            for needle in NEEDLES {
+                for (at, _) in src.match_indices(needle) {
+                    let (before, after) = (&src[..at], &src[at + needle.len()..]);
+                    let turbofish = before.rsplit_once("collection::<").map(|(_, t)| t);
+                    let opened = before.ends_with("collection(")
+                        || turbofish.is_some_and(|t| t.ends_with(">(") && !t.contains(char::is_whitespace));
+                    let argument = before.ends_with(", ") && (after.starts_with(',') || after.starts_with(')'));
+                    if opened || argument {
+                        raw.push(format!("{}:{}", path.display(), before.matches('\n').count() + 1));
+                    }
+                }
+            }
+        }
+    }
+    raw.sort();
+    assert!(raw.is_empty(), "tenant collections used without a Repo: {:?}", raw);
+}
+
+#[test]
+fn fixture_ids_fill_the_path() {
+    let path = fill_path("/teams/{team_id}/projects/{project_id}/boards/{board_id}/tickets/{ticket_id}", "me");
+    assert_eq!(path, "/teams/policy-team/projects/policy-project/boards/policy-board/tickets/missing");
+    assert_eq!(fill_path("/users/{user_id}", "me"), format!("/users/{}", Persona::Owner.user_id()));
+}
+
+#[actix_web::test]
+async fn anonymous_requests_are_rejected() {
+    let served = served_with(|| None).await;
+    assert!(served.is_empty(), "anonymous requests served:\n{}", served.join("\n"));
+}
+
+#[actix_web::test]
+async fn tokens_signed_with_another_key_are_rejected() {
+    let forged = || Some(signed_token(Persona::Owner.user_id(), "not-the-jwt-secret", Duration::hours(1)));
+    let served = served_with(forged).await;
+    assert!(served.is_empty(), "forged tokens accepted:\n{}", served.join("\n"));
+}
+
+#[actix_web::test]
+async fn expired_tokens_are_rejected() {
+    let expired = || {
+        let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "secret".to_string());
+        Some(signed_token(Persona::Owner.user_id(), &secret, Duration::hours(-2)))
+    };
+    let served = served_with(expired).await;
+    assert!(served.is_empty(), "expired tokens accepted:\n{}", served.join("\n"));
+}
+
+/// A second team of the viewer's whose policy only admits one network. Its id is
+/// its own, as policies are cached per team for the whole test process.
+#[actix_web::test]
+#[ignore = "needs TEST_MONGODB_URI"]
+async fn team_policies_hold_on_routes_naming_no_team() {
+    let uri = std::env::var("TEST_MONGODB_URI").expect("TEST_MONGODB_URI must name a disposable MongoDB");
+    let data = web::Data::new(state(&uri, "taskline_policy_security").await);
+    let config = data.config.clone();
+    let app = init_service(
+        App::new()
+            .wrap(Authentication)
+            .app_data(data.clone())
+            .service(web::scope(V1_PREFIX).configure(|cfg| api::v1::configure(cfg, &config))),
+    )
+    .await;
+    seed(&data.mongodb).await;
+    let db = &data.mongodb.db;
+    let joined_at = to_bson(&Utc::now()).unwrap();
+    db.collection::<Document>("user_teams")
+        .insert_one(doc! { "user_id": Persona::Viewer.user_id(), "team_id": "policy-locked-team", "role": "member", "joined_at": joined_at })
+        .await
+        .unwrap();
+    db.collection::<Document>("team_security_policies")
+        .insert_one(doc! { "team_id": "policy-locked-team", "allowed_cidrs": ["203.0.113.0/24"], "reauth_hours": null, "device_confirmation": false })
+        .await
+        .unwrap();
+
+    for path in ["/messages/{chat_id}", "/knowledge_base/{team_id}"] {
+        let route = r(GET, path, User, None);
+        let elsewhere = request(&route, Some(Persona::Viewer)).peer_addr("198.51.100.7:4000".parse().unwrap());
+        let status = call_service(&app, elsewhere.to_request()).await.status();
+        assert_eq!(status, StatusCode::FORBIDDEN, "{} from outside the allowed network", path);
+        let inside = request(&route, Some(Persona::Viewer)).peer_addr("203.0.113.7:4000".parse().unwrap());
+        let status = call_service(&app, inside.to_request()).await.status();
+        assert_eq!(status, StatusCode::OK, "{} from the allowed network", path);
+    }
+    db.drop().await.ok();
+}
+
+/// Checks every route for one persona against a freshly seeded database of its own,
+/// so the persona tests can run in parallel.
+async fn check_persona(persona: Persona) {
+    let uri = std::env::var("TEST_MONGODB_URI").expect("TEST_MONGODB_URI must name a disposable MongoDB");
+    let database = format!("taskline_policy_{:?}", persona).to_lowercase();
+    let data = web::Data::new(state(&uri, &database).await);
+    let config = data.config.clone();
+    let app = init_service(
+        App::new()
+            .wrap(Authentication)
+            .app_data(data.clone())
+            .service(web::scope(V1_PREFIX).configure(|cfg| api::v1::configure(cfg, &config))),
+    )
+    .await;
+
+    let mut failures = Vec::new();
+    for route in routes().iter().filter(|r| r.access != Public) {
+        seed(&data.mongodb).await;
+        let status = call_service(&app, request(route, Some(persona)).to_request()).await.status();
+        let denied = matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN);
+        let expected = if route.access.allows(persona) {
+            !denied
+        } else {
+            denied || status == StatusCode::NOT_FOUND
+        };
+        if !expected {
+            failures.push(format!("{} {} ({:?}): {}", route.method, route.path, route.access, status));
+        }
+    }
+    data.mongodb.db.drop().await.ok();
+    assert!(failures.is_empty(), "{:?} mismatches:\n{}", persona, failures.join("\n"));
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_MONGODB_URI"]
+async fn outsider_policy() {
+    check_persona(Persona::Outsider).await;
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_MONGODB_URI"]
+async fn member_policy() {
+    check_persona(Persona::Member).await;
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_MONGODB_URI"]
+async fn viewer_policy() {
+    check_persona(Persona::Viewer).await;
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_MONGODB_URI"]
+async fn admin_policy() {
+    check_persona(Persona::Admin).await;
+}
+
+#[actix_web::test]
+#[ignore = "needs TEST_MONGODB_URI"]
+async fn owner_policy() {
+    check_persona(Persona::Owner).await;
+}
@@ -0,0 +1,240 @@
+// src/portfolio.rs
+//
+// Cross-team portfolio view for people who work in several teams. KPIs are
+// computed with one aggregation pipeline per collection (tickets, team budgets,
+// releases) over every team the caller belongs to, then split per team with
+// links for drilling down into the regular team endpoints. Tickets and releases
+// only count from projects the caller is a member of.
+
+use std::collections::HashMap;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Datelike, Duration, Utc};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{doc, Bson, DateTime as BsonDateTime, Document};
+use serde::Serialize;
+
+use crate::api::V1_PREFIX;
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::response::ok;
+use crate::tenancy::{ProjectsScope, Repo};
+use crate::team_management::Team;
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+
+/// Releases due within this window count as upcoming milestones.
+const MILESTONE_WINDOW_DAYS: i64 = 30;
+const MAX_MILESTONES: i64 = 20;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Kpis {
+    pub open_tickets: i64,
+    /// Open tickets whose due date is before today (UTC)
+    pub overdue_tickets: i64,
+    pub budget_planned: f64,
+    /// Budget drained up to and including the current month
+    pub budget_spent: f64,
+    pub budget_burn_percent: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamLinks {
+    pub team: String,
+    pub dashboard: String,
+    pub projects: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamPortfolio {
+    pub team_id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub kpis: Kpis,
+    pub upcoming_milestones: usize,
+    pub links: TeamLinks,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Milestone {
+    pub release_id: String,
+    pub name: String,
+    pub release_date: DateTime<Utc>,
+    pub team_id: String,
+    pub project_id: String,
+    pub link: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PortfolioDashboard {
+    pub totals: Kpis,
+    pub teams: Vec<TeamPortfolio>,
+    pub upcoming_milestones: Vec<Milestone>,
+}
+
+/// Stored dates are RFC 3339 strings or BSON dates; anything else becomes null.
+fn as_date(field: &str) -> Document {
+    doc! { "$convert": { "input": field, "to": "date", "onError": Bson::Null, "onNull": Bson::Null } }
+}
+
+fn burn_percent(planned: f64, spent: f64) -> f64 {
+    if planned > 0.0 { (spent / planned * 1000.0).round() / 10.0 } else { 0.0 }
+}
+
+async fn collect(mut cursor: mongodb::Cursor<Document>) -> mongodb::error::Result<Vec<Document>> {
+    let mut docs = Vec::new();
+    while let Some(d) = cursor.next().await {
+        docs.push(d?);
+    }
+    Ok(docs)
+}
+
+/// Open and overdue ticket counts per project.
+async fn ticket_counts(db: &MongoDB, scope: &ProjectsScope) -> mongodb::error::Result<Vec<Document>> {
+    let today = Utc::now().date_naive().and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc();
+    let is_open = doc! { "$not": [{ "$in": ["$status", CLOSED_STATUSES.to_vec()] }] };
+    let pipeline = vec![
+        doc! { "$addFields": { "due": as_date("$due_date") } },
+        doc! { "$group": {
+            "_id": "$project_id",
+            "open": { "$sum": { "$cond": [is_open.clone(), 1, 0] } },
+            "overdue": { "$sum": { "$cond": [
+                { "$and": [
+                    is_open,
+                    { "$ne": ["$due", Bson::Null] },
+                    { "$lt": ["$due", BsonDateTime::from_chrono(today)] },
+                ] },
+                1,
+                0,
+            ] } },
+        } },
+    ];
+    collect(Repo::<Ticket>::across(db, scope).aggregate(pipeline).await?).await
+}
+
+/// Planned and spent-to-date budget per team, from the team dashboards' budget input.
+async fn budgets(db: &MongoDB, team_ids: &[String]) -> mongodb::error::Result<Vec<Document>> {
+    let months = Utc::now().month() as i32;
+    let pipeline = vec![
+        doc! { "$match": { "teamId": { "$in": team_ids } } },
+        doc! { "$project": {
+            "teamId": 1,
+            "planned": { "$ifNull": ["$budgetInput.totalAnnualBudget", 0.0] },
+            "spent": { "$sum": { "$slice": [{ "$ifNull": ["$budgetInput.monthlyDrains", []] }, months] } },
+        } },
+    ];
+    collect(db.db.collection::<Document>("dashboard_data").aggregate(pipeline).await?).await
+}
+
+/// Unshipped releases due in the milestone window, soonest first.
+async fn milestones(db: &MongoDB, project_ids: &[String]) -> mongodb::error::Result<Vec<Document>> {
+    let now = Utc::now();
+    let until = now + Duration::days(MILESTONE_WINDOW_DAYS);
+    let pipeline = vec![
+        doc! { "$match": { "project_id": { "$in": project_ids }, "status": { "$ne": "shipped" } } },
+        doc! { "$addFields": { "date": as_date("$release_date") } },
+        doc! { "$match": { "date": {
+            "$gte": BsonDateTime::from_chrono(now),
+            "$lte": BsonDateTime::from_chrono(until),
+        } } },
+        doc! { "$sort": { "date": 1 } },
+        doc! { "$limit": MAX_MILESTONES },
+        doc! { "$project": { "_id": 0, "release_id": 1, "name": 1, "project_id": 1, "date": 1 } },
+    ];
+    collect(db.db.collection::<Document>("releases").aggregate(pipeline).await?).await
+}
+
+fn number(d: &Document, key: &str) -> f64 {
+    match d.get(key) {
+        Some(Bson::Double(v)) => *v,
+        Some(Bson::Int32(v)) => *v as f64,
+        Some(Bson::Int64(v)) => *v as f64,
+        _ => 0.0,
+    }
+}
+
+async fn build(db: &MongoDB, user_id: &str) -> mongodb::error::Result<PortfolioDashboard> {
+    let team_ids = db.user_team_ids(user_id).await?;
+
+    let mut names = HashMap::new();
+    let mut cursor = db.db.collection::<Team>("teams").find(doc! { "team_id": { "$in": &team_ids } }).await?;
+    while let Some(team) = cursor.next().await {
+        let team = team?;
+        names.insert(team.team_id, team.name);
+    }
+
+    let member_of = db.user_project_ids(user_id).await?;
+    let mut scope = ProjectsScope::of_user(db, user_id).await?;
+    scope.retain(|p| member_of.contains(p));
+    let project_team = scope.teams(db).await?;
+
+    let mut kpis: HashMap<String, Kpis> = team_ids.iter().map(|t| (t.clone(), Kpis::default())).collect();
+    for row in ticket_counts(db, &scope).await? {
+        let team = row.get_str("_id").ok().and_then(|p| project_team.get(p));
+        if let Some(k) = team.and_then(|t| kpis.get_mut(t)) {
+            k.open_tickets += number(&row, "open") as i64;
+            k.overdue_tickets += number(&row, "overdue") as i64;
+        }
+    }
+    for row in budgets(db, &team_ids).await? {
+        if let Some(k) = row.get_str("teamId").ok().and_then(|t| kpis.get_mut(t)) {
+            k.budget_planned = number(&row, "planned");
+            k.budget_spent = number(&row, "spent");
+            k.budget_burn_percent = burn_percent(k.budget_planned, k.budget_spent);
+        }
+    }
+
+    let mut upcoming = Vec::new();
+    for row in milestones(db, scope.project_ids()).await? {
+        let project_id = row.get_str("project_id").unwrap_or_default().to_string();
+        let (Some(team_id), Ok(date)) = (project_team.get(&project_id), row.get_datetime("date")) else {
+            continue;
+        };
+        let release_id = row.get_str("release_id").unwrap_or_default().to_string();
+        upcoming.push(Milestone {
+            link: format!("{}/teams/{}/projects/{}/releases/{}", V1_PREFIX, team_id, project_id, release_id),
+            release_id,
+            name: row.get_str("name").unwrap_or_default().to_string(),
+            release_date: date.to_chrono(),
+            team_id: team_id.clone(),
+            project_id,
+        });
+    }
+
+    let mut totals = Kpis::default();
+    let mut teams = Vec::with_capacity(team_ids.len());
+    for team_id in team_ids {
+        let k = kpis.remove(&team_id).unwrap_or_default();
+        totals.open_tickets += k.open_tickets;
+        totals.overdue_tickets += k.overdue_tickets;
+        totals.budget_planned += k.budget_planned;
+        totals.budget_spent += k.budget_spent;
+        teams.push(TeamPortfolio {
+            name: names.remove(&team_id).unwrap_or_default(),
+            kpis: k,
+            upcoming_milestones: upcoming.iter().filter(|m| m.team_id == team_id).count(),
+            links: TeamLinks {
+                team: format!("{}/teams/{}", V1_PREFIX, team_id),
+                dashboard: format!("{}/team-data/{}", V1_PREFIX, team_id),
+                projects: format!("{}/teams/{}/projects", V1_PREFIX, team_id),
+            },
+            team_id,
+        });
+    }
+    totals.budget_burn_percent = burn_percent(totals.budget_planned, totals.budget_spent);
+    teams.sort_by_key(|t| t.name.to_lowercase());
+
+    Ok(PortfolioDashboard { totals, teams, upcoming_milestones: upcoming })
+}
+
+/// GET /portfolio/dashboard
+pub async fn get_portfolio_dashboard(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    match build(&data.mongodb, auth.user_id()).await {
+        Ok(dashboard) => ok(dashboard),
+        Err(e) => {
+            error!("Error computing portfolio dashboard: {}", e);
+            HttpResponse::InternalServerError().body("Error computing portfolio dashboard")
+        }
+    }
+}
@@ -1,6 +1,6 @@
 // src/project.rs
 
-use actix_web::{web, HttpResponse, Responder, HttpRequest, HttpMessage};
+use actix_web::{web, HttpResponse, Responder};
 use chrono::Utc;
 use futures_util::StreamExt;
 use mongodb::bson::{doc, to_document};
@@ -8,7 +8,12 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use log::{debug, error, info};
 
+use crate::activity::{record_activity, ActivityEvent};
 use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::response::{CodedError, ok, ok_message};
+use crate::role_claims;
+use crate::tenancy::{project_team_id, Repo};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Project {
@@ -49,7 +54,7 @@ pub struct AssignUserRequest {
 /// POST /teams/{team_id}/projects
 /// Creates a new project within a team.
 pub async fn create_project(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     team_id: web::Path<String>,
     project_info: web::Json<CreateProjectRequest>,
@@ -58,38 +63,27 @@ pub async fn create_project(
         "Received create_project request for team_id: {} with payload: {:?}",
         team_id, project_info
     );
-    let current_user = if let Some(uid) = req.extensions().get::<String>() {
-        uid.clone()
-    } else {
-        error!("Unauthorized in create_project");
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
 
     // 1) Verify team membership
-    let user_teams = data.mongodb.db.collection::<mongodb::bson::Document>("user_teams");
-    let team_filter = doc! { "team_id": &*team_id, "user_id": &current_user };
-    match user_teams.find_one(team_filter).await {
-        Ok(Some(_)) => {}
-        Ok(None) => {
+    let scope = match auth.team_scope(&team_id).await {
+        Ok(s) => s,
+        Err(resp) => {
             error!("User {} not in team {}", current_user, team_id);
-            return HttpResponse::Unauthorized().body("Not a member of the team");
-        }
-        Err(e) => {
-            error!("Error checking membership: {}", e);
-            return HttpResponse::InternalServerError().body("Error checking membership");
+            return resp;
         }
-    }
+    };
 
     // 2) Insert project
     let new_project = Project {
         project_id: Uuid::new_v4().to_string(),
-        team_id: team_id.into_inner(),
+        team_id: scope.team_id().to_string(),
         name: project_info.name.clone(),
         description: project_info.description.clone(),
         created_at: Utc::now(),
         created_by: current_user.clone(),
     };
-    let projects_coll = data.mongodb.db.collection::<Project>("projects");
+    let projects_coll = Repo::<Project>::new(&data.mongodb, &scope);
     if let Err(e) = projects_coll.insert_one(&new_project).await {
         error!("Error creating project: {}", e);
         return HttpResponse::InternalServerError().body("Error creating project");
@@ -115,39 +109,37 @@ pub async fn create_project(
         error!("Error inserting membership: {}", e);
         return HttpResponse::InternalServerError().body("Error adding membership");
     }
+    role_claims::bump(&data.mongodb, std::slice::from_ref(&current_user)).await;
+
+    record_activity(&data.mongodb, ActivityEvent::new(
+        &new_project.team_id,
+        Some(&new_project.project_id),
+        &current_user,
+        "project_created",
+        &new_project.project_id,
+        format!("created project \"{}\"", new_project.name),
+    )).await;
 
-    HttpResponse::Ok().json(new_project)
+    ok(new_project)
 }
 
 /// GET /teams/{team_id}/projects
 pub async fn list_projects(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     team_id: web::Path<String>,
 ) -> impl Responder {
     let team_id = team_id.into_inner();
-    let current_user = if let Some(uid) = req.extensions().get::<String>() {
-        uid.clone()
-    } else {
-        error!("Unauthorized in list_projects");
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
 
     // Verify team membership
-    let user_teams = data.mongodb.db.collection::<mongodb::bson::Document>("user_teams");
-    if user_teams
-        .find_one(doc! { "team_id": &team_id, "user_id": &current_user })
-        .await
-        .ok()
-        .flatten()
-        .is_none()
-    {
-        return HttpResponse::Unauthorized().body("Not a member of the team");
-    }
+    let scope = match auth.team_scope(&team_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
 
     // Fetch and return
-    let projects_coll = data.mongodb.db.collection::<Project>("projects");
-    let mut cursor = match projects_coll.find(doc! { "team_id": &team_id }).await {
+    let projects_coll = Repo::<Project>::new(&data.mongodb, &scope);
+    let mut cursor = match projects_coll.find(doc! {}).await {
         Ok(c) => c,
         Err(e) => {
             error!("Error fetching projects: {}", e);
@@ -164,43 +156,31 @@ pub async fn list_projects(
             }
         }
     }
-    HttpResponse::Ok().json(projects)
+    ok(projects)
 }
 
 /// GET /teams/{team_id}/projects/{project_id}
 pub async fn get_project(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     params: web::Path<(String, String)>,
 ) -> impl Responder {
     let (team_id, project_id) = params.into_inner();
-    let current_user = if let Some(uid) = req.extensions().get::<String>() {
-        uid.clone()
-    } else {
-        error!("Unauthorized in get_project");
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
 
     // Verify team membership
-    let user_teams = data.mongodb.db.collection::<mongodb::bson::Document>("user_teams");
-    if user_teams
-        .find_one(doc! { "team_id": &team_id, "user_id": &current_user })
-        .await
-        .ok()
-        .flatten()
-        .is_none()
-    {
-        return HttpResponse::Unauthorized().body("Not a member of the team");
-    }
+    let scope = match auth.team_scope(&team_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
 
     // Fetch project
-    let projects_coll = data.mongodb.db.collection::<Project>("projects");
+    let projects_coll = Repo::<Project>::new(&data.mongodb, &scope);
     match projects_coll
-        .find_one(doc! { "team_id": &team_id, "project_id": &project_id })
+        .find_one(doc! { "project_id": &project_id })
         .await
     {
-        Ok(Some(proj)) => HttpResponse::Ok().json(proj),
-        Ok(None) => HttpResponse::NotFound().body("Project not found"),
+        Ok(Some(proj)) => ok(proj),
+        Ok(None) => HttpResponse::NotFound().error("project_not_found"),
         Err(e) => {
             error!("Error fetching project: {}", e);
             HttpResponse::InternalServerError().body("Error fetching project")
@@ -210,17 +190,16 @@ pub async fn get_project(
 
 /// PUT /teams/{team_id}/projects/{project_id}
 pub async fn update_project(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     params: web::Path<(String, String)>,
     update_info: web::Json<UpdateProjectRequest>,
 ) -> impl Responder {
     let (team_id, project_id) = params.into_inner();
-    let current_user = if let Some(uid) = req.extensions().get::<String>() {
-        uid.clone()
-    } else {
-        error!("Unauthorized in update_project");
-        return HttpResponse::Unauthorized().body("Unauthorized");
+    let current_user = auth.user_id().to_string();
+    let scope = match auth.team_scope(&team_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
     };
 
     // Verify project ownership
@@ -247,20 +226,20 @@ pub async fn update_project(
         set_doc.insert("description", desc.clone());
     }
     if set_doc.is_empty() {
-        return HttpResponse::BadRequest().body("No fields to update");
+        return HttpResponse::BadRequest().error("no_fields_to_update");
     }
 
-    let projects_coll = data.mongodb.db.collection::<Project>("projects");
+    let projects_coll = Repo::<Project>::new(&data.mongodb, &scope);
     match projects_coll
         .update_one(
-            doc! { "team_id": &team_id, "project_id": &project_id },
+            doc! { "project_id": &project_id },
             doc! { "$set": set_doc },
             
         )
         .await
     {
-        Ok(res) if res.matched_count == 1 => HttpResponse::Ok().body("Project updated"),
-        Ok(_) => HttpResponse::NotFound().body("Project not found"),
+        Ok(res) if res.matched_count == 1 => ok_message("Project updated"),
+        Ok(_) => HttpResponse::NotFound().error("project_not_found"),
         Err(e) => {
             error!("Error updating project: {}", e);
             HttpResponse::InternalServerError().body("Error updating project")
@@ -270,16 +249,15 @@ pub async fn update_project(
 
 /// DELETE /teams/{team_id}/projects/{project_id}
 pub async fn delete_project(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     params: web::Path<(String, String)>,
 ) -> impl Responder {
     let (team_id, project_id) = params.into_inner();
-    let current_user = if let Some(uid) = req.extensions().get::<String>() {
-        uid.clone()
-    } else {
-        error!("Unauthorized in delete_project");
-        return HttpResponse::Unauthorized().body("Unauthorized");
+    let current_user = auth.user_id().to_string();
+    let scope = match auth.team_scope(&team_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
     };
 
     // Verify project ownership
@@ -302,13 +280,13 @@ pub async fn delete_project(
     }
 
     // Delete
-    let projects_coll = data.mongodb.db.collection::<Project>("projects");
+    let projects_coll = Repo::<Project>::new(&data.mongodb, &scope);
     match projects_coll
-        .delete_one(doc! { "team_id": &team_id, "project_id": &project_id })
+        .delete_one(doc! { "project_id": &project_id })
         .await
     {
-        Ok(res) if res.deleted_count == 1 => HttpResponse::Ok().body("Project deleted"),
-        Ok(_) => HttpResponse::NotFound().body("Project not found"),
+        Ok(res) if res.deleted_count == 1 => ok_message("Project deleted"),
+        Ok(_) => HttpResponse::NotFound().error("project_not_found"),
         Err(e) => {
             error!("Error deleting project: {}", e);
             HttpResponse::InternalServerError().body("Error deleting project")
@@ -318,17 +296,13 @@ pub async fn delete_project(
 
 /// POST /teams/{team_id}/projects/{project_id}/members
 pub async fn add_user_to_project(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     path: web::Path<(String, String)>,
     payload: web::Json<AssignUserRequest>,
 ) -> impl Responder {
     let (team_id, project_id) = path.into_inner();
-    let current_user = if let Some(uid) = req.extensions().get::<String>() {
-        uid.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
 
     // 1) Only project owner may add
     let proj_members = data.mongodb.db.collection::<mongodb::bson::Document>("project_memberships");
@@ -389,7 +363,256 @@ pub async fn add_user_to_project(
         error!("DB error: {}", e);
         return HttpResponse::InternalServerError().body("Error adding user");
     }
+    role_claims::bump(&data.mongodb, std::slice::from_ref(&payload.user_id)).await;
 
     info!("Added {} to project {}", payload.user_id, project_id);
-    HttpResponse::Ok().body("User added to project")
+    ok_message("User added to project")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DuplicateProjectRequest {
+    /// Defaults to "<original name> (copy)"
+    pub name: Option<String>,
+    /// Team to create the copy in; defaults to the source project's team
+    pub target_team_id: Option<String>,
+    #[serde(default)]
+    pub include_open_tickets: bool,
+}
+
+/// POST /projects/{project_id}/duplicate
+/// Clones the project, its boards (with columns) and optionally its open tickets
+/// into a new project. The caller must administer the target team.
+pub async fn duplicate_project(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    project_id: web::Path<String>,
+    payload: web::Json<DuplicateProjectRequest>,
+) -> impl Responder {
+    use crate::board::Board;
+    use crate::ticket::Ticket;
+    use crate::sync::{record_change, Entity, Op, Scope};
+    use crate::ticket_events::{created_event, TicketEvent};
+
+    let project_id = project_id.into_inner();
+    let current_user = auth.user_id().to_string();
+
+    let db = &data.mongodb.db;
+    let Some(source_team_id) = project_team_id(&data.mongodb, &project_id).await else {
+        return HttpResponse::NotFound().error("project_not_found");
+    };
+
+    // 1) Caller must belong to the source project and administer the target team
+    if !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
+    let source_scope = match auth.project_scope(&source_team_id, &project_id).await {
+        Ok(scope) => scope,
+        Err(resp) => return resp,
+    };
+    let source = match Repo::<Project>::new(&data.mongodb, &source_scope.team()).find_one(doc! { "project_id": &project_id }).await {
+        Ok(Some(p)) => p,
+        Ok(None) => return HttpResponse::NotFound().error("project_not_found"),
+        Err(e) => {
+            error!("Error fetching project: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching project");
+        }
+    };
+    let target_team_id = payload.target_team_id.clone().unwrap_or_else(|| source.team_id.clone());
+    if !auth.is_team_admin(&target_team_id).await {
+        return HttpResponse::Unauthorized().body("Only admins of the target team can duplicate into it");
+    }
+    let target_team = match auth.team_scope(&target_team_id).await {
+        Ok(scope) => scope,
+        Err(resp) => return resp,
+    };
+
+    // 2) Load what is being copied
+    let boards: Vec<Board> = match Repo::<Board>::new(&data.mongodb, &source_scope).find(doc! {}).await {
+        Ok(mut cursor) => {
+            let mut out = Vec::new();
+            while let Some(b) = cursor.next().await {
+                match b {
+                    Ok(b) => out.push(b),
+                    Err(e) => {
+                        error!("Error reading board: {}", e);
+                        return HttpResponse::InternalServerError().body("Error fetching boards");
+                    }
+                }
+            }
+            out
+        }
+        Err(e) => {
+            error!("Error fetching boards: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching boards");
+        }
+    };
+    let mut tickets: Vec<Ticket> = Vec::new();
+    if payload.include_open_tickets {
+        let filter = doc! {
+            "status": { "$nin": ["Done", "done", "Closed", "closed", "Resolved", "resolved"] },
+        };
+        match Repo::<Ticket>::new(&data.mongodb, &source_scope).find(filter).await {
+            Ok(mut cursor) => {
+                while let Some(t) = cursor.next().await {
+                    match t {
+                        Ok(t) => tickets.push(t),
+                        Err(e) => {
+                            error!("Error reading ticket: {}", e);
+                            return HttpResponse::InternalServerError().error("error_fetching_tickets");
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Error fetching tickets: {}", e);
+                return HttpResponse::InternalServerError().error("error_fetching_tickets");
+            }
+        }
+    }
+
+    // 3) Build the copies with fresh ids
+    let now = Utc::now();
+    let new_project = Project {
+        project_id: Uuid::new_v4().to_string(),
+        team_id: target_team_id.clone(),
+        name: payload.name.clone().unwrap_or_else(|| format!("{} (copy)", source.name)),
+        description: source.description.clone(),
+        created_at: now,
+        created_by: current_user.clone(),
+    };
+    let mut board_ids = std::collections::HashMap::new();
+    let new_boards: Vec<Board> = boards
+        .into_iter()
+        .map(|b| {
+            let new_id = Uuid::new_v4().to_string();
+            board_ids.insert(b.board_id, new_id.clone());
+            Board {
+                board_id: new_id,
+                project_id: new_project.project_id.clone(),
+                name: b.name,
+                board_type: b.board_type,
+                description: b.description,
+                sprint_length: b.sprint_length,
+                created_at: now,
+                created_by: current_user.clone(),
+                participants: vec![current_user.clone()],
+                columns: b.columns,
+                // Label mappings name people, so they only carry over within the team.
+                auto_assign: b.auto_assign.filter(|_| target_team_id == source.team_id),
+                definition_of_done: b.definition_of_done,
+            }
+        })
+        .collect();
+
+    // Assignees only carry over when they are members of the target team.
+    let same_team = target_team_id == source.team_id;
+    let mut target_members = std::collections::HashSet::new();
+    if !same_team {
+        let members = match db.collection::<mongodb::bson::Document>("user_teams").distinct("user_id", doc! { "team_id": &target_team_id }).await {
+            Ok(ids) => ids,
+            Err(e) => {
+                error!("Error fetching target team members: {}", e);
+                return HttpResponse::InternalServerError().body("Error fetching team members");
+            }
+        };
+        target_members.extend(members.iter().filter_map(|v| v.as_str().map(str::to_string)));
+    }
+    let new_tickets: Vec<Ticket> = tickets
+        .into_iter()
+        .filter_map(|t| {
+            let board_id = board_ids.get(&t.board_id)?.clone();
+            Some(Ticket {
+                id: None,
+                ticket_id: Uuid::new_v4().to_string(),
+                board_id,
+                project_id: new_project.project_id.clone(),
+                title: t.title,
+                description: t.description,
+                status: t.status,
+                priority: t.priority,
+                reporter: current_user.clone(),
+                requester_email: t.requester_email,
+                assignee: t.assignee.filter(|a| same_team || target_members.contains(a)),
+                due_date: t.due_date,
+                ticket_type: t.ticket_type,
+                sprint: t.sprint,
+                rank: t.rank,
+                labels: t.labels,
+                // Releases belong to the source project
+                fix_version: None,
+                estimate_hours: t.estimate_hours,
+                // Blockers point at tickets of the source project
+                blocked_by: Vec::new(),
+                attachments: t.attachments,
+                comments: Some(vec![]),
+                references: if same_team { t.references } else { Vec::new() },
+                created_at: now,
+                resolution: None,
+                resolved_at: None,
+                resolved_by: None,
+                vote_count: 0,
+                version: 1,
+            })
+        })
+        .collect();
+    let Some(target_scope) = target_team.new_project(&new_project) else {
+        return HttpResponse::InternalServerError().body("Error duplicating project");
+    };
+    let membership = ProjectMembership {
+        project_id: new_project.project_id.clone(),
+        user_id: current_user.clone(),
+        role: "owner".to_string(),
+        joined_at: now,
+    };
+
+    // 4) Write everything in one transaction
+    let mut session = match data.mongodb.client.start_session().await {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Error starting session: {}", e);
+            return HttpResponse::InternalServerError().body("Error duplicating project");
+        }
+    };
+    let result: mongodb::error::Result<()> = async {
+        session.start_transaction().await?;
+        Repo::<Project>::new(&data.mongodb, &target_team).insert_one_in(&new_project, &mut session).await?;
+        db.collection::<ProjectMembership>("project_memberships")
+            .insert_one(&membership)
+            .session(&mut session)
+            .await?;
+        if !new_boards.is_empty() {
+            Repo::<Board>::new(&data.mongodb, &target_scope).insert_many_in(&new_boards, &mut session).await?;
+        }
+        if !new_tickets.is_empty() {
+            Repo::<Ticket>::new(&data.mongodb, &target_scope).insert_many_in(&new_tickets, &mut session).await?;
+            let events: Vec<TicketEvent> = new_tickets.iter().map(|t| created_event(t, &current_user)).collect();
+            db.collection::<TicketEvent>("ticket_events").insert_many(&events).session(&mut session).await?;
+        }
+        session.commit_transaction().await
+    }
+    .await;
+    if let Err(e) = result {
+        error!("Error duplicating project {}: {}", project_id, e);
+        let _ = session.abort_transaction().await;
+        return HttpResponse::InternalServerError().body("Error duplicating project");
+    }
+    role_claims::bump(&data.mongodb, std::slice::from_ref(&current_user)).await;
+
+    for t in &new_tickets {
+        record_change(&data.mongodb, Entity::Ticket, &t.ticket_id, Op::Upsert, Scope::Project(&new_project.project_id)).await;
+    }
+    info!(
+        "Project {} duplicated into {} ({} boards, {} tickets)",
+        project_id, new_project.project_id, new_boards.len(), new_tickets.len()
+    );
+    record_activity(&data.mongodb, ActivityEvent::new(
+        &new_project.team_id,
+        Some(&new_project.project_id),
+        &current_user,
+        "project_created",
+        &new_project.project_id,
+        format!("duplicated project \"{}\" as \"{}\"", source.name, new_project.name),
+    )).await;
+
+    ok(new_project)
 }
@@ -0,0 +1,286 @@
+// src/quick_search.rs
+//
+// Command-palette search: `GET /quick_search?q=` returns a short mixed list of
+// tickets, projects, boards, knowledge base documents, group chats and people across
+// the caller's teams. Names are matched by prefix, case-insensitively, through
+// indexes with a case-insensitive collation (`PREFIX_INDEXES` in chat_db.rs, created
+// at startup), so each lookup is a bounded index range scan. Tickets also match by id
+// prefix, and `ticket:<id>` searches ids only.
+//
+// All lookups run concurrently under a fixed time budget; a category that does not
+// answer in time is left out and the response is marked partial.
+
+use std::cmp::Reverse;
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use futures_util::StreamExt;
+use log::{error, warn};
+use mongodb::action::Find;
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::Board;
+use crate::chat_db::{prefix_collation, MongoDB};
+use crate::knowledge_base::readable_by_filter;
+use crate::project::Project;
+use crate::tenancy::{ProjectsScope, Repo};
+use crate::ticket::Ticket;
+
+const BUDGET: Duration = Duration::from_millis(300);
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 25;
+const MAX_QUERY_LEN: usize = 100;
+/// Id prefixes shorter than this match too many tickets to be useful.
+const MIN_ID_PREFIX: usize = 4;
+
+#[derive(Debug, Deserialize)]
+pub struct QuickSearchQuery {
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HitKind {
+    Ticket,
+    Project,
+    Board,
+    Doc,
+    Chat,
+    Person,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuickSearchHit {
+    pub kind: HitKind,
+    pub id: String,
+    pub title: String,
+    /// Status for tickets, email for people
+    pub subtitle: Option<String>,
+    pub team_id: Option<String>,
+    pub project_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuickSearchResults {
+    pub query: String,
+    pub hits: Vec<QuickSearchHit>,
+    /// Some categories ran out of time or failed and are missing
+    pub partial: bool,
+}
+
+/// Teams and projects the caller can search in.
+struct SearchScope {
+    team_ids: Vec<String>,
+    projects: ProjectsScope,
+}
+
+async fn search_scope(db: &MongoDB, user_id: &str) -> mongodb::error::Result<SearchScope> {
+    let team_ids = db.user_team_ids(user_id).await?;
+    let member_of = db.user_project_ids(user_id).await?;
+    // Memberships can outlive a team membership; only projects of current teams count.
+    let mut projects = ProjectsScope::of_user(db, user_id).await?;
+    projects.retain(|p| member_of.contains(p));
+    Ok(SearchScope { team_ids, projects })
+}
+
+/// Range matching every string that starts with `prefix` under the prefix collation.
+fn prefix_range(prefix: &str) -> Document {
+    doc! { "$gte": prefix, "$lt": format!("{}{}", prefix, char::MAX) }
+}
+
+async fn find(db: &MongoDB, collection: &str, filter: Document, limit: usize, collated: bool) -> mongodb::error::Result<Vec<Document>> {
+    run(db.db.collection::<Document>(collection).find(filter), limit, collated).await
+}
+
+async fn run(find: Find<'_, Document>, limit: usize, collated: bool) -> mongodb::error::Result<Vec<Document>> {
+    let find = find.limit(limit as i64).max_time(BUDGET);
+    let mut cursor = if collated { find.collation(prefix_collation()).await? } else { find.await? };
+    let mut docs = Vec::new();
+    while let Some(d) = cursor.next().await {
+        docs.push(d?);
+    }
+    Ok(docs)
+}
+
+fn text(d: &Document, key: &str) -> Option<String> {
+    d.get_str(key).ok().map(str::to_string)
+}
+
+async fn tickets(db: &MongoDB, scope: &SearchScope, q: &str, ids_only: bool, limit: usize) -> mongodb::error::Result<Vec<QuickSearchHit>> {
+    let repo = Repo::<Ticket>::across(db, &scope.projects);
+    let mut docs = Vec::new();
+    let id_like = q.len() >= MIN_ID_PREFIX && q.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+    if id_like {
+        let filter = doc! { "ticket_id": prefix_range(&q.to_ascii_lowercase()) };
+        docs = run(repo.find_docs(filter), limit, false).await?;
+    }
+    if !ids_only && docs.len() < limit {
+        let filter = doc! { "title": prefix_range(q) };
+        docs.extend(run(repo.find_docs(filter), limit - docs.len(), true).await?);
+    }
+    Ok(docs
+        .iter()
+        .map(|d| QuickSearchHit {
+            kind: HitKind::Ticket,
+            id: text(d, "ticket_id").unwrap_or_default(),
+            title: text(d, "title").unwrap_or_default(),
+            subtitle: text(d, "status"),
+            team_id: None,
+            project_id: text(d, "project_id"),
+        })
+        .collect())
+}
+
+async fn projects(db: &MongoDB, scope: &SearchScope, q: &str, limit: usize) -> mongodb::error::Result<Vec<QuickSearchHit>> {
+    let filter = doc! { "team_id": { "$in": &scope.team_ids }, "name": prefix_range(q) };
+    Ok(run(Repo::<Project>::across(db, &scope.projects).find_docs(filter), limit, true)
+        .await?
+        .iter()
+        .map(|d| QuickSearchHit {
+            kind: HitKind::Project,
+            id: text(d, "project_id").unwrap_or_default(),
+            title: text(d, "name").unwrap_or_default(),
+            subtitle: None,
+            team_id: text(d, "team_id"),
+            project_id: text(d, "project_id"),
+        })
+        .collect())
+}
+
+async fn boards(db: &MongoDB, scope: &SearchScope, q: &str, limit: usize) -> mongodb::error::Result<Vec<QuickSearchHit>> {
+    let filter = doc! { "name": prefix_range(q) };
+    Ok(run(Repo::<Board>::across(db, &scope.projects).find_docs(filter), limit, true)
+        .await?
+        .iter()
+        .map(|d| QuickSearchHit {
+            kind: HitKind::Board,
+            id: text(d, "board_id").unwrap_or_default(),
+            title: text(d, "name").unwrap_or_default(),
+            subtitle: text(d, "board_type"),
+            team_id: None,
+            project_id: text(d, "project_id"),
+        })
+        .collect())
+}
+
+async fn docs(db: &MongoDB, scope: &SearchScope, user_id: &str, q: &str, limit: usize) -> mongodb::error::Result<Vec<QuickSearchHit>> {
+    let mut filter = readable_by_filter(user_id);
+    filter.insert("team_id", doc! { "$in": &scope.team_ids });
+    filter.insert("title", prefix_range(q));
+    Ok(find(db, "knowledge_base", filter, limit, true)
+        .await?
+        .iter()
+        .map(|d| QuickSearchHit {
+            kind: HitKind::Doc,
+            id: text(d, "_id").unwrap_or_default(),
+            title: text(d, "title").unwrap_or_default(),
+            subtitle: None,
+            team_id: text(d, "team_id"),
+            project_id: None,
+        })
+        .collect())
+}
+
+async fn chats(db: &MongoDB, user_id: &str, q: &str, limit: usize) -> mongodb::error::Result<Vec<QuickSearchHit>> {
+    let filter = doc! { "participants": user_id, "group_name": prefix_range(q) };
+    Ok(find(db, "chats", filter, limit, true)
+        .await?
+        .iter()
+        .map(|d| QuickSearchHit {
+            kind: HitKind::Chat,
+            id: text(d, "_id").unwrap_or_default(),
+            title: text(d, "group_name").unwrap_or_default(),
+            subtitle: None,
+            team_id: text(d, "team_id"),
+            project_id: None,
+        })
+        .collect())
+}
+
+async fn people(db: &MongoDB, scope: &SearchScope, q: &str, limit: usize) -> mongodb::error::Result<Vec<QuickSearchHit>> {
+    let teammates = db.db.collection::<Document>("user_teams").distinct("user_id", doc! { "team_id": { "$in": &scope.team_ids } }).await?;
+    let oids: Vec<ObjectId> = teammates.iter().filter_map(|v| v.as_str().and_then(|id| ObjectId::parse_str(id).ok())).collect();
+    let filter = doc! { "_id": { "$in": oids }, "$or": [{ "username": prefix_range(q) }, { "email": prefix_range(q) }] };
+    Ok(find(db, "users", filter, limit, true)
+        .await?
+        .iter()
+        .filter_map(|d| {
+            let id = d.get_object_id("_id").ok()?.to_hex();
+            let email = text(d, "email");
+            Some(QuickSearchHit {
+                kind: HitKind::Person,
+                title: text(d, "username").or_else(|| email.clone()).unwrap_or_else(|| id.clone()),
+                id,
+                subtitle: email,
+                team_id: None,
+                project_id: None,
+            })
+        })
+        .collect())
+}
+
+/// Runs one lookup within the budget; None when it timed out or failed.
+async fn within_budget(
+    category: &str,
+    lookup: impl std::future::Future<Output = mongodb::error::Result<Vec<QuickSearchHit>>>,
+) -> Option<Vec<QuickSearchHit>> {
+    match actix_web::rt::time::timeout(BUDGET, lookup).await {
+        Ok(Ok(hits)) => Some(hits),
+        Ok(Err(e)) => {
+            error!("Quick search of {} failed: {}", category, e);
+            None
+        }
+        Err(_) => {
+            warn!("Quick search of {} ran out of time", category);
+            None
+        }
+    }
+}
+
+/// GET /quick_search?q=&limit=
+pub async fn quick_search(auth: AuthContext, data: web::Data<AppState>, query: web::Query<QuickSearchQuery>) -> impl Responder {
+    let raw = query.q.trim();
+    if raw.is_empty() || raw.chars().count() > MAX_QUERY_LEN {
+        return HttpResponse::BadRequest().body(format!("q must be between 1 and {} characters", MAX_QUERY_LEN));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let (q, ids_only) = match raw.strip_prefix("ticket:") {
+        Some(id) => (id.trim(), true),
+        None => (raw, false),
+    };
+    let db = &data.mongodb;
+    let user_id = auth.user_id();
+
+    let scope = match actix_web::rt::time::timeout(BUDGET, search_scope(db, user_id)).await {
+        Ok(Ok(s)) => s,
+        Ok(Err(e)) => {
+            error!("Error resolving quick search scope: {}", e);
+            return HttpResponse::InternalServerError().body("Error searching");
+        }
+        Err(_) => return HttpResponse::ServiceUnavailable().body("Search timed out"),
+    };
+
+    let mut results = vec![within_budget("tickets", tickets(db, &scope, q, ids_only, limit)).await];
+    if !ids_only {
+        let (p, b, d, c, u) = futures_util::join!(
+            within_budget("projects", projects(db, &scope, q, limit)),
+            within_budget("boards", boards(db, &scope, q, limit)),
+            within_budget("documents", docs(db, &scope, user_id, q, limit)),
+            within_budget("chats", chats(db, user_id, q, limit)),
+            within_budget("people", people(db, &scope, q, limit)),
+        );
+        results.extend([p, b, d, c, u]);
+    }
+    let partial = results.iter().any(Option::is_none);
+
+    // Exact names first, then shorter names, then by kind.
+    let needle = q.to_lowercase();
+    let mut hits: Vec<QuickSearchHit> = results.into_iter().flatten().flatten().collect();
+    hits.sort_by_key(|h| (Reverse(h.title.to_lowercase() == needle), h.title.chars().count(), h.kind));
+    hits.truncate(limit);
+    HttpResponse::Ok().json(QuickSearchResults { query: raw.to_string(), hits, partial })
+}
@@ -0,0 +1,333 @@
+// src/release.rs
+//
+// Project-scoped releases ("fix versions"). Tickets point at a release through
+// `Ticket.fix_version`; marking a release shipped generates release notes from its
+// tickets, grouped by ticket type.
+
+use std::collections::BTreeMap;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::{error, info};
+use mongodb::bson::{doc, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::activity::{record_activity, ActivityEvent};
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::response::CodedError;
+use crate::tenancy::{ProjectScope, Repo};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+
+pub const STATUSES: [&str; 3] = ["planned", "in_progress", "shipped"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Release {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub release_id: String,
+    pub project_id: String,
+    pub name: String,
+    pub release_date: Option<DateTime<Utc>>,
+    /// "planned", "in_progress" or "shipped"
+    pub status: String,
+    /// Generated when the release is shipped
+    pub release_notes: Option<String>,
+    pub shipped_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateReleaseRequest {
+    pub name: String,
+    pub release_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateReleaseRequest {
+    pub name: Option<String>,
+    pub release_date: Option<DateTime<Utc>>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReleaseProgress {
+    pub total: usize,
+    pub done: usize,
+    /// 0-100, rounded down; 0 for an empty release
+    pub percent: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReleaseSummary {
+    #[serde(flatten)]
+    pub release: Release,
+    pub progress: ReleaseProgress,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReleaseDetail {
+    #[serde(flatten)]
+    pub release: Release,
+    pub progress: ReleaseProgress,
+    pub tickets: Vec<Ticket>,
+}
+
+fn is_done(ticket: &Ticket) -> bool {
+    CLOSED_STATUSES.contains(&ticket.status.as_str())
+}
+
+fn progress(tickets: &[Ticket]) -> ReleaseProgress {
+    let total = tickets.len();
+    let done = tickets.iter().filter(|t| is_done(t)).count();
+    let percent = (done * 100).checked_div(total).unwrap_or(0) as u32;
+    ReleaseProgress { total, done, percent }
+}
+
+/// Markdown notes for the completed tickets of a release, one section per ticket type.
+fn release_notes(release: &Release, tickets: &[Ticket]) -> String {
+    let mut groups: BTreeMap<&str, Vec<&Ticket>> = BTreeMap::new();
+    for t in tickets.iter().filter(|t| is_done(t)) {
+        groups.entry(t.ticket_type.as_deref().unwrap_or("Other")).or_default().push(t);
+    }
+    let mut out = format!("# {}\n", release.name);
+    if let Some(date) = release.shipped_at.or(release.release_date) {
+        out.push_str(&format!("\nReleased {}\n", date.format("%Y-%m-%d")));
+    }
+    if groups.is_empty() {
+        out.push_str("\nNo completed tickets.\n");
+    }
+    for (ticket_type, tickets) in groups {
+        out.push_str(&format!("\n## {}\n\n", ticket_type));
+        for t in tickets {
+            out.push_str(&format!("- {}\n", t.title));
+        }
+    }
+    out
+}
+
+async fn check_access(auth: &AuthContext, team_id: &str, project_id: &str) -> Result<ProjectScope, HttpResponse> {
+    let scope = auth.project_scope(team_id, project_id).await?;
+    if !auth.is_project_member(project_id).await {
+        return Err(HttpResponse::Unauthorized().error("not_project_member"));
+    }
+    Ok(scope)
+}
+
+async fn release_tickets(data: &AppState, scope: &ProjectScope, release_id: &str) -> mongodb::error::Result<Vec<Ticket>> {
+    let mut cursor = Repo::<Ticket>::new(&data.mongodb, scope)
+        .find(doc! { "fix_version": release_id })
+        .sort(doc! { "created_at": 1 })
+        .await?;
+    let mut tickets = Vec::new();
+    while let Some(t) = cursor.next().await {
+        tickets.push(t?);
+    }
+    Ok(tickets)
+}
+
+/// Whether `release_id` is a release of `project_id`; used to validate `fix_version`.
+pub async fn release_in_project(data: &AppState, project_id: &str, release_id: &str) -> bool {
+    data.mongodb
+        .db
+        .collection::<Release>("releases")
+        .find_one(doc! { "release_id": release_id, "project_id": project_id })
+        .await
+        .ok()
+        .flatten()
+        .is_some()
+}
+
+/// POST /teams/{team_id}/projects/{project_id}/releases
+pub async fn create_release(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<CreateReleaseRequest>,
+) -> impl Responder {
+    let (team_id, project_id) = path.into_inner();
+    if let Err(resp) = check_access(&auth, &team_id, &project_id).await {
+        return resp;
+    }
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().body("Release name is required");
+    }
+
+    let coll = data.mongodb.db.collection::<Release>("releases");
+    match coll.find_one(doc! { "project_id": &project_id, "name": name }).await {
+        Ok(Some(_)) => return HttpResponse::Conflict().body("A release with this name already exists"),
+        Ok(None) => {}
+        Err(e) => {
+            error!("Error checking releases: {}", e);
+            return HttpResponse::InternalServerError().body("Error creating release");
+        }
+    }
+
+    let release = Release {
+        id: None,
+        release_id: Uuid::new_v4().to_string(),
+        project_id: project_id.clone(),
+        name: name.to_string(),
+        release_date: payload.release_date,
+        status: "planned".to_string(),
+        release_notes: None,
+        shipped_at: None,
+        created_at: Utc::now(),
+    };
+    match coll.insert_one(&release).await {
+        Ok(_) => {
+            record_activity(&data.mongodb, ActivityEvent::new(
+                &team_id, Some(&project_id), auth.user_id(), "release_created", &release.release_id,
+                format!("created release \"{}\"", release.name),
+            )).await;
+            HttpResponse::Ok().json(release)
+        }
+        Err(e) => {
+            error!("Error inserting release: {}", e);
+            HttpResponse::InternalServerError().body("Error creating release")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/releases
+pub async fn list_releases(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (team_id, project_id) = path.into_inner();
+    let scope = match check_access(&auth, &team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+
+    let mut cursor = match data
+        .mongodb
+        .db
+        .collection::<Release>("releases")
+        .find(doc! { "project_id": &project_id })
+        .sort(doc! { "release_date": 1, "created_at": 1 })
+        .await
+    {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching releases: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching releases");
+        }
+    };
+    let mut out = Vec::new();
+    while let Some(Ok(release)) = cursor.next().await {
+        let tickets = match release_tickets(&data, &scope, &release.release_id).await {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Error fetching release tickets: {}", e);
+                return HttpResponse::InternalServerError().body("Error fetching releases");
+            }
+        };
+        out.push(ReleaseSummary { progress: progress(&tickets), release });
+    }
+    HttpResponse::Ok().json(out)
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/releases/{release_id}
+pub async fn get_release(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (team_id, project_id, release_id) = path.into_inner();
+    let scope = match check_access(&auth, &team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+
+    let coll = data.mongodb.db.collection::<Release>("releases");
+    let release = match coll.find_one(doc! { "release_id": &release_id, "project_id": &project_id }).await {
+        Ok(Some(r)) => r,
+        Ok(None) => return HttpResponse::NotFound().body("Release not found"),
+        Err(e) => {
+            error!("Error fetching release: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching release");
+        }
+    };
+    match release_tickets(&data, &scope, &release_id).await {
+        Ok(tickets) => HttpResponse::Ok().json(ReleaseDetail { progress: progress(&tickets), release, tickets }),
+        Err(e) => {
+            error!("Error fetching release tickets: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching release")
+        }
+    }
+}
+
+/// PUT /teams/{team_id}/projects/{project_id}/releases/{release_id}
+///
+/// Moving a release to "shipped" stamps `shipped_at` and (re)generates its notes.
+pub async fn update_release(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    payload: web::Json<UpdateReleaseRequest>,
+) -> impl Responder {
+    let (team_id, project_id, release_id) = path.into_inner();
+    let scope = match check_access(&auth, &team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    if let Some(status) = &payload.status {
+        if !STATUSES.contains(&status.as_str()) {
+            return HttpResponse::BadRequest().body(format!("status must be one of {}", STATUSES.join(", ")));
+        }
+    }
+
+    let coll = data.mongodb.db.collection::<Release>("releases");
+    let filter = doc! { "release_id": &release_id, "project_id": &project_id };
+    let mut release = match coll.find_one(filter.clone()).await {
+        Ok(Some(r)) => r,
+        Ok(None) => return HttpResponse::NotFound().body("Release not found"),
+        Err(e) => {
+            error!("Error fetching release: {}", e);
+            return HttpResponse::InternalServerError().body("Error updating release");
+        }
+    };
+
+    if let Some(name) = payload.name.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+        release.name = name.to_string();
+    }
+    if payload.release_date.is_some() {
+        release.release_date = payload.release_date;
+    }
+    let shipping = payload.status.as_deref() == Some("shipped") && release.status != "shipped";
+    if let Some(status) = &payload.status {
+        release.status = status.clone();
+    }
+    if release.status != "shipped" {
+        release.shipped_at = None;
+    }
+    if shipping {
+        release.shipped_at = Some(Utc::now());
+        match release_tickets(&data, &scope, &release_id).await {
+            Ok(tickets) => release.release_notes = Some(release_notes(&release, &tickets)),
+            Err(e) => {
+                error!("Error fetching release tickets: {}", e);
+                return HttpResponse::InternalServerError().body("Error generating release notes");
+            }
+        }
+    }
+
+    release.id = None;
+    if let Err(e) = coll.replace_one(filter, &release).await {
+        error!("Error updating release: {}", e);
+        return HttpResponse::InternalServerError().body("Error updating release");
+    }
+    if shipping {
+        info!("Release {} of project {} shipped", release.name, project_id);
+        record_activity(&data.mongodb, ActivityEvent::new(
+            &team_id, Some(&project_id), auth.user_id(), "release_shipped", &release.release_id,
+            format!("shipped release \"{}\"", release.name),
+        )).await;
+    }
+    HttpResponse::Ok().json(release)
+}
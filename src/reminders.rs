@@ -0,0 +1,409 @@
+// src/reminders.rs
+//
+// Personal reminders, set with `/remind me in 2h to …` in any chat or through the
+// REST API. Times like "at 15:00" or "tomorrow" are read in the user's own time zone
+// (the one in their DND settings). Due reminders are picked up by the message
+// scheduler and pushed through `notify_user`, so a reminder that falls in the user's
+// DND window arrives with their summary instead. A claimed reminder is only marked
+// sent once delivered; one stuck in "sending" is picked up again after a lease.
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, NaiveDate, NaiveTime, SecondsFormat, SubsecRound, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::doc;
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::do_not_disturb::{try_notify_user, user_timezone};
+use crate::team_time::parse_timezone;
+
+/// How far ahead a reminder may be set.
+const MAX_REMINDER_DAYS: i64 = 365;
+const MAX_TEXT_LEN: usize = 1000;
+/// Time of day used when only a day is given ("tomorrow", "on 2026-05-01").
+const DEFAULT_HOUR: u32 = 9;
+/// How long a claimed reminder is left to its instance before another retries it.
+const DELIVERY_LEASE_MINUTES: i64 = 5;
+pub const REMIND_USAGE: &str =
+    "/remind me in 2h to <text> — also \"at 15:00\", \"tomorrow at 9am\" or \"on 2026-05-01 at 9:00\"";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reminder {
+    pub reminder_id: String,
+    pub user_id: String,
+    pub text: String,
+    /// Chat the reminder was set from with `/remind`, if any
+    pub chat_id: Option<String>,
+    /// Whole seconds, so stored values compare correctly as strings
+    pub remind_at: DateTime<Utc>,
+    /// "pending", "sending", "sent" or "cancelled"
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateReminderRequest {
+    pub text: String,
+    /// Natural form such as "in 2h" or "tomorrow at 9am"; alternative to `remind_at`
+    pub when: Option<String>,
+    pub remind_at: Option<DateTime<Utc>>,
+    /// Zone for `when`; defaults to the user's own
+    pub timezone: Option<String>,
+}
+
+fn reminders_coll(data: &AppState) -> mongodb::Collection<Reminder> {
+    data.mongodb.db.collection::<Reminder>("reminders")
+}
+
+fn unit_minutes(unit: &str) -> Option<i64> {
+    match unit {
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(1),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(60),
+        "d" | "day" | "days" => Some(60 * 24),
+        "w" | "wk" | "week" | "weeks" => Some(60 * 24 * 7),
+        _ => None,
+    }
+}
+
+/// Minutes in a single word like "2h" or "1h30m".
+fn compact_minutes(word: &str) -> Option<i64> {
+    let mut total = 0i64;
+    let mut rest = word;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let end = rest[digits..].find(|c: char| c.is_ascii_digit()).map_or(rest.len(), |p| p + digits);
+        if digits == 0 || end == digits {
+            return None;
+        }
+        let n: i64 = rest[..digits].parse().ok()?;
+        total = total.checked_add(n.checked_mul(unit_minutes(&rest[digits..end])?)?)?;
+        rest = &rest[end..];
+    }
+    (total > 0).then_some(total)
+}
+
+/// A duration such as "2h", "1h30m", "90 minutes", "an hour" or "1 day 3 hours",
+/// advancing `i` past the words it used.
+fn parse_duration(words: &[String], i: &mut usize) -> Option<chrono::Duration> {
+    let mut minutes = 0i64;
+    while let Some(word) = words.get(*i) {
+        let count = match word.as_str() {
+            "a" | "an" => Some(1),
+            w => w.parse::<i64>().ok(),
+        };
+        let step = match count {
+            Some(n) => match words.get(*i + 1).and_then(|u| unit_minutes(u)) {
+                Some(unit) => n.checked_mul(unit).map(|m| (m, 2)),
+                None => None,
+            },
+            None => compact_minutes(word).map(|m| (m, 1)),
+        };
+        let Some((m, used)) = step else { break };
+        minutes = minutes.checked_add(m)?;
+        *i += used;
+    }
+    // Capped so absurd values are rejected as too far ahead rather than overflowing.
+    (minutes > 0).then(|| chrono::Duration::minutes(minutes.min((MAX_REMINDER_DAYS + 1) * 24 * 60)))
+}
+
+/// A time of day such as "15:00", "9am", "9:30 pm" or "noon".
+fn parse_clock(words: &[String], i: &mut usize) -> Option<NaiveTime> {
+    let word = words.get(*i)?;
+    if word == "noon" {
+        *i += 1;
+        return NaiveTime::from_hms_opt(12, 0, 0);
+    }
+    let (clock, mut pm, mut used) = match (word.strip_suffix("am"), word.strip_suffix("pm")) {
+        (Some(c), _) => (c, Some(false), 1),
+        (_, Some(c)) => (c, Some(true), 1),
+        _ => (word.as_str(), None, 1),
+    };
+    if pm.is_none() {
+        match words.get(*i + 1).map(String::as_str) {
+            Some("am") => (pm, used) = (Some(false), 2),
+            Some("pm") => (pm, used) = (Some(true), 2),
+            _ => {}
+        }
+    }
+    let (hour, minute) = match clock.split_once(':') {
+        Some((h, m)) if m.len() == 2 => (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?),
+        Some(_) => return None,
+        None => (clock.parse::<u32>().ok()?, 0),
+    };
+    let hour = match pm {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(pm) => hour % 12 + if pm { 12 } else { 0 },
+        None => hour,
+    };
+    let time = NaiveTime::from_hms_opt(hour, minute, 0)?;
+    *i += used;
+    Some(time)
+}
+
+/// "at <time>" following a day, or the default time of day when there is none.
+fn parse_optional_clock(words: &[String], i: &mut usize) -> Result<NaiveTime, String> {
+    if words.get(*i).map(String::as_str) != Some("at") {
+        return Ok(NaiveTime::from_hms_opt(DEFAULT_HOUR, 0, 0).unwrap());
+    }
+    *i += 1;
+    parse_clock(words, i).ok_or_else(|| "Couldn't read the time, e.g. \"15:00\" or \"9am\"".to_string())
+}
+
+fn at_local(date: NaiveDate, time: NaiveTime, tz: Tz) -> Result<DateTime<Utc>, String> {
+    tz.from_local_datetime(&date.and_time(time))
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .ok_or_else(|| format!("{} {} does not exist in {}", date, time.format("%H:%M"), tz))
+}
+
+/// Read a point in time from `words[i..]`, advancing `i` past it: "in 2h", "at 15:00"
+/// (tomorrow if already past), "today at 5pm", "tomorrow [at 9am]" or
+/// "on 2026-05-01 [at 9:00]".
+fn parse_when(words: &[String], i: &mut usize, now: DateTime<Utc>, tz: Tz) -> Result<DateTime<Utc>, String> {
+    let usage = || format!("Usage: {}", REMIND_USAGE);
+    let keyword = words.get(*i).ok_or_else(usage)?.clone();
+    *i += 1;
+    let today = now.with_timezone(&tz).date_naive();
+    match keyword.as_str() {
+        "in" => parse_duration(words, i)
+            .map(|d| now + d)
+            .ok_or_else(|| "Couldn't read the duration, e.g. \"2h\", \"1h30m\" or \"90 minutes\"".to_string()),
+        "at" => {
+            let time = parse_clock(words, i).ok_or_else(|| "Couldn't read the time, e.g. \"15:00\" or \"9am\"".to_string())?;
+            let at = at_local(today, time, tz)?;
+            if at > now {
+                Ok(at)
+            } else {
+                at_local(today + chrono::Duration::days(1), time, tz)
+            }
+        }
+        "today" => at_local(today, parse_optional_clock(words, i)?, tz),
+        "tomorrow" => at_local(today + chrono::Duration::days(1), parse_optional_clock(words, i)?, tz),
+        "on" => {
+            let date = words
+                .get(*i)
+                .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+                .ok_or_else(|| "Couldn't read the date, use YYYY-MM-DD".to_string())?;
+            *i += 1;
+            at_local(date, parse_optional_clock(words, i)?, tz)
+        }
+        _ => Err(usage()),
+    }
+}
+
+fn check_time(remind_at: DateTime<Utc>, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    if remind_at <= now {
+        return Err("That time has already passed".to_string());
+    }
+    if remind_at > now + chrono::Duration::days(MAX_REMINDER_DAYS) {
+        return Err(format!("Reminders can be set at most {} days ahead", MAX_REMINDER_DAYS));
+    }
+    Ok(remind_at.trunc_subsecs(0))
+}
+
+fn check_text(text: &str) -> Result<(), String> {
+    if text.trim().is_empty() {
+        return Err("What should the reminder say? e.g. \"/remind me in 2h to call Sam\"".to_string());
+    }
+    if text.chars().count() > MAX_TEXT_LEN {
+        return Err(format!("Reminder text is limited to {} characters", MAX_TEXT_LEN));
+    }
+    Ok(())
+}
+
+/// Parse the arguments of `/remind`: "me <when> [to] <text>".
+fn parse_command(args: &str, now: DateTime<Utc>, tz: Tz) -> Result<(DateTime<Utc>, String), String> {
+    let original: Vec<&str> = args.split_whitespace().collect();
+    let words: Vec<String> = original.iter().map(|w| w.to_lowercase()).collect();
+    let mut i = 0;
+    if words.first().map(String::as_str) == Some("me") {
+        i += 1;
+    }
+    let remind_at = check_time(parse_when(&words, &mut i, now, tz)?, now)?;
+    if words.get(i).map(String::as_str) == Some("to") {
+        i += 1;
+    }
+    let text = original[i..].join(" ");
+    check_text(&text)?;
+    Ok((remind_at, text))
+}
+
+async fn store(data: &AppState, reminder: &Reminder) -> Result<(), String> {
+    reminders_coll(data).insert_one(reminder).await.map(|_| ()).map_err(|e| {
+        error!("Error saving reminder: {}", e);
+        "Error saving reminder".to_string()
+    })
+}
+
+/// `/remind me …`; returns the confirmation for the sender.
+pub(crate) async fn remind_command(data: &AppState, user_id: &str, chat_id: &str, args: &str) -> Result<String, String> {
+    let tz = user_timezone(&data.mongodb, user_id).await;
+    let now = Utc::now();
+    let (remind_at, text) = parse_command(args, now, tz)?;
+    let reminder = Reminder {
+        reminder_id: Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        text,
+        chat_id: Some(chat_id.to_string()),
+        remind_at,
+        status: "pending".to_string(),
+        created_at: now,
+    };
+    store(data, &reminder).await?;
+    let local = remind_at.with_timezone(&tz);
+    Ok(format!("I'll remind you on {} ({}): {}", local.format("%a %b %-d at %H:%M"), tz, reminder.text))
+}
+
+/// Push every due reminder to its owner. Run by the message scheduler.
+pub(crate) async fn deliver_due_reminders(data: &AppState) {
+    let now = Utc::now();
+    let lease = (now + chrono::Duration::minutes(DELIVERY_LEASE_MINUTES)).to_rfc3339_opts(SecondsFormat::Secs, true);
+    let now = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+    loop {
+        // Claimed one at a time so a second instance never delivers the same reminder.
+        let claimed = reminders_coll(data)
+            .find_one_and_update(
+                doc! { "$or": [
+                    { "status": "pending", "remind_at": { "$lte": &now } },
+                    { "status": "sending", "claimed_until": { "$lte": &now } },
+                ] },
+                doc! { "$set": { "status": "sending", "claimed_until": &lease } },
+            )
+            .sort(doc! { "remind_at": 1 })
+            .await;
+        let reminder = match claimed {
+            Ok(Some(r)) => r,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Error claiming due reminders: {}", e);
+                return;
+            }
+        };
+        let payload = serde_json::json!({
+            "type": "reminder",
+            "reminder_id": reminder.reminder_id,
+            "text": reminder.text,
+            "chat_id": reminder.chat_id,
+            "remind_at": reminder.remind_at,
+        });
+        // On failure the reminder stays "sending" and is retried once the lease ends.
+        if let Err(e) = try_notify_user(data, &reminder.user_id, payload.to_string()).await {
+            error!("Error delivering reminder {}: {}", reminder.reminder_id, e);
+            continue;
+        }
+        if let Err(e) = reminders_coll(data)
+            .update_one(
+                doc! { "reminder_id": &reminder.reminder_id, "status": "sending" },
+                doc! { "$set": { "status": "sent" }, "$unset": { "claimed_until": "" } },
+            )
+            .await
+        {
+            error!("Error marking reminder {} sent: {}", reminder.reminder_id, e);
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// POST /users/me/reminders => set a reminder, by `when` or `remind_at`
+// ----------------------------------------------------------------------
+pub async fn create_reminder(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    payload: web::Json<CreateReminderRequest>,
+) -> impl Responder {
+    let req = payload.into_inner();
+    if let Err(msg) = check_text(&req.text) {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    let now = Utc::now();
+    let remind_at = match (req.when, req.remind_at) {
+        (Some(when), None) => {
+            let tz = match req.timezone {
+                Some(name) => match parse_timezone(&name) {
+                    Ok(tz) => tz,
+                    Err(msg) => return HttpResponse::BadRequest().body(msg),
+                },
+                None => user_timezone(&data.mongodb, auth.user_id()).await,
+            };
+            let words: Vec<String> = when.split_whitespace().map(str::to_lowercase).collect();
+            let mut i = 0;
+            match parse_when(&words, &mut i, now, tz) {
+                Ok(_) if i < words.len() => {
+                    return HttpResponse::BadRequest().body(format!("Unexpected \"{}\" in `when`", words[i..].join(" ")))
+                }
+                Ok(at) => at,
+                Err(msg) => return HttpResponse::BadRequest().body(msg),
+            }
+        }
+        (None, Some(at)) => at,
+        _ => return HttpResponse::BadRequest().body("Provide exactly one of `when` or `remind_at`"),
+    };
+    let remind_at = match check_time(remind_at, now) {
+        Ok(at) => at,
+        Err(msg) => return HttpResponse::BadRequest().body(msg),
+    };
+
+    let reminder = Reminder {
+        reminder_id: Uuid::new_v4().to_string(),
+        user_id: auth.user_id().to_string(),
+        text: req.text.trim().to_string(),
+        chat_id: None,
+        remind_at,
+        status: "pending".to_string(),
+        created_at: now,
+    };
+    match store(&data, &reminder).await {
+        Ok(()) => HttpResponse::Created().json(reminder),
+        Err(msg) => HttpResponse::InternalServerError().body(msg),
+    }
+}
+
+// ----------------------------------------------------------------------
+// GET /users/me/reminders => the caller's pending reminders, soonest first
+// ----------------------------------------------------------------------
+pub async fn list_reminders(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    let filter = doc! { "user_id": auth.user_id(), "status": "pending" };
+    let mut cursor = match reminders_coll(&data).find(filter).sort(doc! { "remind_at": 1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching reminders: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching reminders");
+        }
+    };
+    let mut reminders = Vec::new();
+    while let Some(r) = cursor.next().await {
+        match r {
+            Ok(r) => reminders.push(r),
+            Err(e) => error!("Error reading reminder: {}", e),
+        }
+    }
+    HttpResponse::Ok().json(reminders)
+}
+
+// ----------------------------------------------------------------------
+// DELETE /users/me/reminders/{reminder_id} => cancel a pending reminder
+// ----------------------------------------------------------------------
+pub async fn cancel_reminder(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    reminder_id: web::Path<String>,
+) -> impl Responder {
+    let filter = doc! { "reminder_id": &*reminder_id, "user_id": auth.user_id(), "status": "pending" };
+    match reminders_coll(&data)
+        .find_one_and_update(filter, doc! { "$set": { "status": "cancelled" } })
+        .return_document(ReturnDocument::After)
+        .await
+    {
+        Ok(Some(r)) => HttpResponse::Ok().json(r),
+        Ok(None) => HttpResponse::NotFound().body("No pending reminder with that id"),
+        Err(e) => {
+            error!("Error cancelling reminder: {}", e);
+            HttpResponse::InternalServerError().body("Error cancelling reminder")
+        }
+    }
+}
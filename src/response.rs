@@ -0,0 +1,103 @@
+// src/response.rs
+//
+// Shared JSON envelope for success responses:
+//
+//     { "data": <payload or null>, "message": <string or null>, "meta": <object or null> }
+//
+// Error responses are plain text. Those built with `CodedError` name a catalog code
+// (see i18n.rs), which localizes them and answers clients accepting only JSON with
+// an `ApiError`. Large exports can instead be streamed as newline-delimited JSON,
+// one object per line, built with `ndjson_line`.
+
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{web, HttpResponse, HttpResponseBuilder};
+use log::error;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Serialize)]
+pub struct ApiResponse<T: Serialize> {
+    pub data: Option<T>,
+    /// Human-readable outcome, e.g. "Team updated"
+    pub message: Option<String>,
+    /// Extra information about `data`, e.g. pagination totals
+    pub meta: Option<Value>,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    pub fn new(data: T) -> Self {
+        ApiResponse { data: Some(data), message: None, meta: None }
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn with_meta(mut self, meta: Value) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+}
+
+/// Error body for clients that accept only JSON, see `crate::i18n`.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    /// Stable code, e.g. "ticket_not_found"
+    pub error: &'static str,
+    /// `error` in the negotiated language
+    pub message: String,
+}
+
+/// Response extension naming the catalog message of an error response.
+#[derive(Debug, Clone)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    /// Values for the message's `{}` placeholders
+    pub args: Vec<String>,
+}
+
+/// Error responses by catalog code, e.g.
+/// `HttpResponse::NotFound().error("ticket_not_found")`. The body is the English
+/// text; i18n.rs translates it for the client.
+pub trait CodedError {
+    fn error(&mut self, code: &'static str) -> HttpResponse {
+        self.error_with(code, &[])
+    }
+
+    /// With `args` filling the message's placeholders in order.
+    fn error_with(&mut self, code: &'static str, args: &[&str]) -> HttpResponse;
+}
+
+impl CodedError for HttpResponseBuilder {
+    fn error_with(&mut self, code: &'static str, args: &[&str]) -> HttpResponse {
+        let mut resp = self
+            .content_type("text/plain; charset=utf-8")
+            .body(crate::i18n::format(code, crate::i18n::Lang::En, args));
+        resp.extensions_mut().insert(ErrorCode { code, args: args.iter().map(|a| a.to_string()).collect() });
+        resp
+    }
+}
+
+/// 200 with `data`.
+pub fn ok<T: Serialize>(data: T) -> HttpResponse {
+    HttpResponse::Ok().json(ApiResponse::new(data))
+}
+
+/// Content type of newline-delimited JSON responses.
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// `value` as one NDJSON line, ready to be sent as a chunk of a streamed body.
+pub fn ndjson_line<T: Serialize>(value: &T) -> Result<web::Bytes, actix_web::Error> {
+    let mut line = serde_json::to_vec(value).map_err(|e| {
+        error!("Error serializing NDJSON line: {}", e);
+        ErrorInternalServerError("Error serializing response")
+    })?;
+    line.push(b'\n');
+    Ok(web::Bytes::from(line))
+}
+
+/// 200 with only a message, for actions that have nothing to return.
+pub fn ok_message(message: impl Into<String>) -> HttpResponse {
+    HttpResponse::Ok().json(ApiResponse::<()> { data: None, message: Some(message.into()), meta: None })
+}
@@ -0,0 +1,420 @@
+// src/retention.rs
+//
+// Per-team data retention. Team admins set how long chat messages, closed tickets
+// and the collaborative edit history of knowledge base documents are kept; a daily
+// job purges whatever has aged out. Every category is off until a number of days is
+// set. The preview endpoint runs the same selection without deleting anything.
+// Messages of chats and users under legal hold (see legal_hold.rs) are never purged.
+// Purged messages and tickets take their translations, embeddings and stored files
+// with them; a file still referenced by something that survives is kept.
+//
+// Knowledge base documents have no revision list: their history is the CRDT state
+// kept for live editing. Expiring it leaves the document text untouched; the next
+// editing session starts a fresh history from that text.
+
+use std::collections::HashMap;
+use std::time::Duration as StdDuration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Utc};
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use mongodb::bson::{doc, oid::ObjectId, Bson, DateTime as BsonDateTime, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::legal_hold::active_holds;
+use crate::response::ok;
+use crate::sync::{record_change, Entity, Op, Scope};
+use crate::tenancy::{AllTenants, ProjectsScope, Repo};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+use crate::ticket_watchers::remove_watches;
+
+const RUN_INTERVAL: StdDuration = StdDuration::from_secs(24 * 60 * 60);
+const MIN_DAYS: u32 = 1;
+const MAX_DAYS: u32 = 3650;
+const PURGE_BATCH: i64 = 500;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub team_id: String,
+    /// Delete messages of the team's chats older than this many days
+    pub chat_message_days: Option<u32>,
+    /// Purge closed tickets, with their history and votes, untouched for this many days
+    pub closed_ticket_days: Option<u32>,
+    /// Drop the edit history of knowledge base documents idle for this many days
+    pub kb_history_days: Option<u32>,
+    pub updated_by: Option<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePolicyRequest {
+    pub chat_message_days: Option<u32>,
+    pub closed_ticket_days: Option<u32>,
+    pub kb_history_days: Option<u32>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PurgeReport {
+    pub dry_run: bool,
+    pub chat_messages: u64,
+    pub closed_tickets: u64,
+    pub kb_histories: u64,
+}
+
+/// Matches documents whose `field` (an RFC 3339 string or BSON date) is before `cutoff`.
+/// Unparseable dates never match.
+fn older_than(field: &str, cutoff: DateTime<Utc>) -> Document {
+    let date = doc! { "$convert": { "input": field, "to": "date", "onError": Bson::Null, "onNull": Bson::Null } };
+    doc! { "$expr": { "$and": [
+        { "$ne": [date.clone(), Bson::Null] },
+        { "$lt": [date, BsonDateTime::from_chrono(cutoff)] },
+    ] } }
+}
+
+fn cutoff(days: u32) -> DateTime<Utc> {
+    Utc::now() - Duration::days(days as i64)
+}
+
+async fn find_policy(db: &MongoDB, team_id: &str) -> mongodb::error::Result<RetentionPolicy> {
+    let policies = db.db.collection::<RetentionPolicy>("retention_policies");
+    let saved = policies.find_one(doc! { "team_id": team_id }).await?;
+    Ok(saved.unwrap_or_else(|| RetentionPolicy { team_id: team_id.to_string(), ..Default::default() }))
+}
+
+async fn purge_messages(db: &MongoDB, team_id: &str, days: u32, dry_run: bool) -> mongodb::error::Result<u64> {
+    let chat_ids = db.db.collection::<Document>("chats").distinct("_id", doc! { "team_id": team_id }).await?;
+    if chat_ids.is_empty() {
+        return Ok(0);
+    }
+    let holds = active_holds(db, team_id).await?;
+    let mut filter = older_than("$created_at", cutoff(days));
+    filter.insert("id_chat", doc! { "$in": chat_ids.clone(), "$nin": &holds.chat_ids });
+    filter.insert("sender_id", doc! { "$nin": &holds.user_ids });
+    let messages = db.db.collection::<Document>("messages");
+    if dry_run {
+        return messages.count_documents(filter).await;
+    }
+
+    let mut participants: HashMap<String, Vec<String>> = HashMap::new();
+    let mut chats = db
+        .db
+        .collection::<Document>("chats")
+        .find(doc! { "_id": { "$in": chat_ids } })
+        .projection(doc! { "participants": 1 })
+        .await?;
+    while let Some(chat) = chats.next().await {
+        let chat = chat?;
+        if let Ok(id) = chat.get_object_id("_id") {
+            let users = chat.get_array("participants").map(|a| a.iter().filter_map(|u| u.as_str().map(String::from)).collect());
+            participants.insert(id.to_hex(), users.unwrap_or_default());
+        }
+    }
+
+    let mut deleted = 0;
+    loop {
+        let batch: Vec<Document> = messages
+            .find(filter.clone())
+            .projection(doc! { "id_chat": 1, "attachments": 1 })
+            .limit(PURGE_BATCH)
+            .await?
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<_, _>>()?;
+        let ids: Vec<ObjectId> = batch.iter().filter_map(|m| m.get_object_id("_id").ok()).collect();
+        if ids.is_empty() {
+            break;
+        }
+        let removed = messages.delete_many(doc! { "_id": { "$in": &ids } }).await?.deleted_count;
+        let hex_ids: Vec<String> = ids.iter().map(|id| id.to_hex()).collect();
+        db.db.collection::<Document>("message_translations").delete_many(doc! { "message_id": { "$in": &hex_ids } }).await?;
+        db.db
+            .collection::<Document>("embeddings")
+            .delete_many(doc! { "kind": "message", "entity_id": { "$in": &hex_ids } })
+            .await?;
+
+        let mut files = Vec::new();
+        for message in &batch {
+            let Ok(attachments) = message.get_array("attachments") else { continue };
+            for a in attachments.iter().filter_map(Bson::as_document) {
+                files.extend(["attachment_id", "thumbnail_id"].iter().filter_map(|f| a.get_str(f).ok()).map(String::from));
+            }
+        }
+        for file in files {
+            let in_use = doc! { "$or": [{ "attachments.attachment_id": &file }, { "attachments.thumbnail_id": &file }] };
+            let in_use = db.db.collection::<Document>("messages").count_documents(in_use).limit(1).await? > 0;
+            drop_file_unless(db, &file, in_use).await;
+        }
+
+        for (message, id) in batch.iter().zip(&hex_ids) {
+            let users = message.get_str("id_chat").ok().and_then(|c| participants.get(c));
+            record_change(db, Entity::Message, id, Op::Delete, Scope::Users(users.map_or(&[], |u| u.as_slice()))).await;
+        }
+        deleted += removed;
+        if removed == 0 || (batch.len() as i64) < PURGE_BATCH {
+            break;
+        }
+    }
+    Ok(deleted)
+}
+
+/// Removes the GridFS file `file_id` unless something still refers to it. Ids that
+/// are not GridFS files (e.g. GIF links) are ignored.
+async fn drop_file_unless(db: &MongoDB, file_id: &str, in_use: bool) {
+    if in_use {
+        return;
+    }
+    let Ok(oid) = ObjectId::parse_str(file_id) else {
+        return;
+    };
+    if let Err(e) = db.db.gridfs_bucket(None).delete(Bson::ObjectId(oid)).await {
+        warn!("Error removing stored file {}: {}", file_id, e);
+    }
+}
+
+/// Closed tickets of the team whose last change (or creation) is before the cutoff,
+/// as (ticket_id, project_id).
+async fn expired_tickets(db: &MongoDB, scope: &ProjectsScope, days: u32) -> mongodb::error::Result<Vec<(String, String)>> {
+    let limit = cutoff(days);
+    let mut filter = older_than("$created_at", limit);
+    filter.insert("status", doc! { "$in": CLOSED_STATUSES.to_vec() });
+    let mut candidates = HashMap::new();
+    let mut cursor = Repo::<Ticket>::across(db, scope).find_docs(filter).await?;
+    while let Some(t) = cursor.next().await {
+        let t = t?;
+        if let (Ok(id), Ok(project)) = (t.get_str("ticket_id"), t.get_str("project_id")) {
+            candidates.insert(id.to_string(), project.to_string());
+        }
+    }
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Anything changed since the cutoff, e.g. a late comment, keeps the ticket.
+    let ids: Vec<&String> = candidates.keys().collect();
+    let mut recent = db
+        .db
+        .collection::<Document>("ticket_events")
+        .aggregate(vec![
+            doc! { "$match": { "ticket_id": { "$in": ids } } },
+            doc! { "$addFields": { "at_date": { "$convert": { "input": "$at", "to": "date", "onError": Bson::Null, "onNull": Bson::Null } } } },
+            doc! { "$match": { "at_date": { "$gte": BsonDateTime::from_chrono(limit) } } },
+            doc! { "$group": { "_id": "$ticket_id" } },
+        ])
+        .await?;
+    while let Some(row) = recent.next().await {
+        if let Ok(id) = row?.get_str("_id") {
+            candidates.remove(id);
+        }
+    }
+    Ok(candidates.into_iter().collect())
+}
+
+async fn purge_tickets(db: &MongoDB, team_id: &str, days: u32, dry_run: bool) -> mongodb::error::Result<u64> {
+    let scope = ProjectsScope::of_team(db, team_id).await?;
+    let expired = expired_tickets(db, &scope, days).await?;
+    if dry_run || expired.is_empty() {
+        return Ok(expired.len() as u64);
+    }
+    // One at a time, so a ticket reopened since the selection is neither deleted nor
+    // stripped of its history.
+    let tickets = Repo::<Ticket>::across(db, &scope);
+    let mut purged = Vec::new();
+    let mut files = Vec::new();
+    for (ticket_id, project_id) in &expired {
+        let filter = doc! { "ticket_id": ticket_id, "status": { "$in": CLOSED_STATUSES.to_vec() } };
+        let Some(ticket) = tickets.find_one(filter.clone()).await? else { continue };
+        if tickets.delete_one(filter).await?.deleted_count == 0 {
+            continue;
+        }
+        for path in ticket.attachments.iter().flatten() {
+            if let Some(file) = path.rsplit_once("/attachments/").map(|(_, id)| id) {
+                files.push((file.to_string(), path.clone()));
+            }
+        }
+        purged.push((ticket_id, project_id));
+    }
+    if purged.is_empty() {
+        return Ok(0);
+    }
+
+    let ids: Vec<&String> = purged.iter().map(|(id, _)| *id).collect();
+    db.db.collection::<Document>("ticket_events").delete_many(doc! { "ticket_id": { "$in": &ids } }).await?;
+    db.db.collection::<Document>("ticket_votes").delete_many(doc! { "ticket_id": { "$in": &ids } }).await?;
+    db.db
+        .collection::<Document>("embeddings")
+        .delete_many(doc! { "kind": "ticket", "entity_id": { "$in": &ids } })
+        .await?;
+    remove_watches(db, &ids).await?;
+    // Duplicated projects share attachments with their source, in whatever team.
+    let tickets = Repo::<Ticket>::all_tenants(db, AllTenants::maintenance());
+    for (file, path) in files {
+        let in_use = tickets.count_documents(doc! { "attachments": path }).await? > 0;
+        drop_file_unless(db, &file, in_use).await;
+    }
+    for (ticket_id, project_id) in &purged {
+        record_change(db, Entity::Ticket, ticket_id, Op::Delete, Scope::Project(project_id)).await;
+    }
+    Ok(purged.len() as u64)
+}
+
+async fn purge_kb_history(db: &MongoDB, team_id: &str, days: u32, dry_run: bool) -> mongodb::error::Result<u64> {
+    let doc_ids = db.db.collection::<Document>("knowledge_base").distinct("_id", doc! { "team_id": team_id }).await?;
+    if doc_ids.is_empty() {
+        return Ok(0);
+    }
+    let mut filter = older_than("$updated_at", cutoff(days));
+    filter.insert("doc_id", doc! { "$in": doc_ids });
+    let snapshots = db.db.collection::<Document>("kb_doc_snapshots");
+    if dry_run {
+        snapshots.count_documents(filter).await
+    } else {
+        Ok(snapshots.delete_many(filter).await?.deleted_count)
+    }
+}
+
+/// Apply (or, with `dry_run`, measure) one team's policy.
+async fn enforce(db: &MongoDB, policy: &RetentionPolicy, dry_run: bool) -> mongodb::error::Result<PurgeReport> {
+    let mut report = PurgeReport { dry_run, ..Default::default() };
+    if let Some(days) = policy.chat_message_days {
+        report.chat_messages = purge_messages(db, &policy.team_id, days, dry_run).await?;
+    }
+    if let Some(days) = policy.closed_ticket_days {
+        report.closed_tickets = purge_tickets(db, &policy.team_id, days, dry_run).await?;
+    }
+    if let Some(days) = policy.kb_history_days {
+        report.kb_histories = purge_kb_history(db, &policy.team_id, days, dry_run).await?;
+    }
+    Ok(report)
+}
+
+async fn run_policies(data: &AppState) {
+    let policies = data.mongodb.db.collection::<RetentionPolicy>("retention_policies");
+    let mut cursor = match policies.find(doc! {}).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error loading retention policies: {}", e);
+            return;
+        }
+    };
+    while let Some(policy) = cursor.next().await {
+        let policy = match policy {
+            Ok(p) => p,
+            Err(e) => {
+                error!("Error reading retention policy: {}", e);
+                continue;
+            }
+        };
+        match enforce(&data.mongodb, &policy, false).await {
+            Ok(report) => {
+                info!(
+                    "Retention for team {}: {} messages, {} tickets, {} document histories purged",
+                    policy.team_id, report.chat_messages, report.closed_tickets, report.kb_histories
+                );
+                let _ = policies
+                    .update_one(doc! { "team_id": &policy.team_id }, doc! { "$set": { "last_run_at": Utc::now().to_rfc3339() } })
+                    .await;
+            }
+            Err(e) => error!("Error enforcing retention for team {}: {}", policy.team_id, e),
+        }
+    }
+}
+
+/// Start the daily retention job.
+pub fn spawn_retention(data: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(RUN_INTERVAL);
+        loop {
+            interval.tick().await;
+            run_policies(&data).await;
+        }
+    });
+}
+
+/// GET /teams/{team_id}/retention
+pub async fn get_retention_policy(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage retention");
+    }
+    match find_policy(&data.mongodb, &team_id).await {
+        Ok(policy) => ok(policy),
+        Err(e) => {
+            error!("Error fetching retention policy: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching retention policy")
+        }
+    }
+}
+
+/// PUT /teams/{team_id}/retention
+/// Omitted or null fields turn that category off.
+pub async fn update_retention_policy(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<UpdatePolicyRequest>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage retention");
+    }
+    let days = [payload.chat_message_days, payload.closed_ticket_days, payload.kb_history_days];
+    if days.iter().flatten().any(|d| !(MIN_DAYS..=MAX_DAYS).contains(d)) {
+        return HttpResponse::BadRequest().body(format!("Retention must be between {} and {} days", MIN_DAYS, MAX_DAYS));
+    }
+
+    let previous = match find_policy(&data.mongodb, &team_id).await {
+        Ok(p) => p,
+        Err(e) => {
+            error!("Error fetching retention policy: {}", e);
+            return HttpResponse::InternalServerError().body("Error saving retention policy");
+        }
+    };
+    let policy = RetentionPolicy {
+        team_id: team_id.into_inner(),
+        chat_message_days: payload.chat_message_days,
+        closed_ticket_days: payload.closed_ticket_days,
+        kb_history_days: payload.kb_history_days,
+        updated_by: Some(auth.user_id().to_string()),
+        updated_at: Some(Utc::now()),
+        last_run_at: previous.last_run_at,
+    };
+    let policies = data.mongodb.db.collection::<RetentionPolicy>("retention_policies");
+    match policies.replace_one(doc! { "team_id": &policy.team_id }, &policy).upsert(true).await {
+        Ok(_) => ok(policy),
+        Err(e) => {
+            error!("Error saving retention policy: {}", e);
+            HttpResponse::InternalServerError().body("Error saving retention policy")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/retention/preview
+/// What the next run would purge under the saved policy; nothing is deleted.
+pub async fn preview_retention(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage retention");
+    }
+    let report = match find_policy(&data.mongodb, &team_id).await {
+        Ok(policy) => enforce(&data.mongodb, &policy, true).await,
+        Err(e) => Err(e),
+    };
+    match report {
+        Ok(report) => ok(report),
+        Err(e) => {
+            error!("Error computing retention preview: {}", e);
+            HttpResponse::InternalServerError().body("Error computing retention preview")
+        }
+    }
+}
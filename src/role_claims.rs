@@ -0,0 +1,214 @@
+// src/role_claims.rs
+//
+// Team and project roles carried in the JWT (with JWT_ROLE_CLAIMS=true), so the
+// AuthContext can answer membership checks on hot paths without querying
+// `user_teams` and `project_memberships`. The roles are a snapshot and are trusted
+// only while they are fresh: they expire ROLES_TTL_MINUTES after they were read, and
+// they carry the user's role version at that time.
+//
+// Every change to a user's memberships bumps their version in `role_versions`
+// (`bump`). Each instance keeps the current versions in memory, updated by its own
+// bumps and by polling the collection every VERSION_POLL_INTERVAL. Roles that are
+// expired or older than the known version are ignored, the request falls back to
+// the database, and the response carries `X-Token-Refresh: roles`; clients then call
+// POST /auth/refresh for a token with current roles.
+//
+// The same records revoke sessions: `revoke_sessions` (used when an account is
+// deactivated) stores the time before which the user's tokens are no longer
+// accepted, and the Authentication middleware rejects older tokens once the
+// instance has seen it. Revoking a trusted device bumps the version as well, which
+// drops the device from every instance's cache (see security_policy.rs).
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{self, doc, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::chat_db::MongoDB;
+
+pub const ROLES_TTL_MINUTES: i64 = 5;
+/// Users with more memberships than this get no role claims, so tokens stay within
+/// cookie size limits.
+const MAX_CLAIMED_ROLES: usize = 40;
+const VERSION_POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// Response header asking the client to refresh its token
+pub const REFRESH_HEADER: &str = "x-token-refresh";
+
+static VERSIONS: OnceLock<RwLock<HashMap<String, i64>>> = OnceLock::new();
+/// user id -> tokens issued at or before this time (seconds) are revoked
+static REVOKED: OnceLock<RwLock<HashMap<String, i64>>> = OnceLock::new();
+/// Set once the versions were loaded; until then claimed roles are not trusted.
+static SYNCED: AtomicBool = AtomicBool::new(false);
+
+/// Request extension: the token carried roles that are no longer current.
+pub struct RolesStale;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoleClaims {
+    /// team_id -> role ("admin"/"member")
+    #[serde(default)]
+    pub teams: HashMap<String, String>,
+    /// project_id -> role ("owner"/"member"/...)
+    #[serde(default)]
+    pub projects: HashMap<String, String>,
+    /// The user's role version when the roles were read
+    pub ver: i64,
+    /// Expiry of the roles in seconds since the epoch; the token may live longer
+    pub exp: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RoleVersion {
+    user_id: String,
+    version: i64,
+    updated_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    revoked_before: Option<i64>,
+}
+
+fn versions() -> &'static RwLock<HashMap<String, i64>> {
+    VERSIONS.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn revoked() -> &'static RwLock<HashMap<String, i64>> {
+    REVOKED.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn remember(v: RoleVersion) {
+    if let Some(at) = v.revoked_before {
+        let mut map = revoked().write().unwrap();
+        let known = map.entry(v.user_id.clone()).or_insert(at);
+        *known = (*known).max(at);
+    }
+    let mut map = versions().write().unwrap();
+    let known = map.entry(v.user_id).or_insert(0);
+    *known = (*known).max(v.version);
+}
+
+fn versions_coll(db: &MongoDB) -> mongodb::Collection<RoleVersion> {
+    db.db.collection::<RoleVersion>("role_versions")
+}
+
+/// The user's role version as far as this instance knows.
+pub(crate) fn known_version(user_id: &str) -> i64 {
+    versions().read().unwrap().get(user_id).copied().unwrap_or(0)
+}
+
+/// Whether claimed roles can stand in for the membership collections.
+pub(crate) fn is_current(user_id: &str, roles: &RoleClaims) -> bool {
+    SYNCED.load(Ordering::Acquire)
+        && roles.exp > Utc::now().timestamp()
+        && known_version(user_id) <= roles.ver
+}
+
+/// id -> role of the user's memberships in `collection`.
+async fn memberships(db: &MongoDB, collection: &str, key: &str, user_id: &str) -> mongodb::error::Result<HashMap<String, String>> {
+    let mut cursor = db.db.collection::<Document>(collection).find(doc! { "user_id": user_id }).await?;
+    let mut roles = HashMap::new();
+    while let Some(m) = cursor.next().await {
+        let m = m?;
+        if let Ok(id) = m.get_str(key) {
+            roles.insert(id.to_string(), m.get_str("role").unwrap_or("member").to_string());
+        }
+    }
+    Ok(roles)
+}
+
+/// The user's current roles for a new token, or None when they have too many.
+pub(crate) async fn load(db: &MongoDB, user_id: &str) -> mongodb::error::Result<Option<RoleClaims>> {
+    // The version is read first, so a change made meanwhile makes the snapshot stale.
+    let ver = versions_coll(db).find_one(doc! { "user_id": user_id }).await?.map_or(0, |v| v.version);
+    let teams = memberships(db, "user_teams", "team_id", user_id).await?;
+    let projects = memberships(db, "project_memberships", "project_id", user_id).await?;
+    if teams.len() + projects.len() > MAX_CLAIMED_ROLES {
+        return Ok(None);
+    }
+    let exp = (Utc::now() + chrono::Duration::minutes(ROLES_TTL_MINUTES)).timestamp();
+    Ok(Some(RoleClaims { teams, projects, ver, exp }))
+}
+
+/// Marks the roles in the users' tokens as stale; call after changing their team or
+/// project memberships.
+pub(crate) async fn bump(db: &MongoDB, user_ids: &[String]) {
+    let now = bson::to_bson(&Utc::now()).unwrap_or_default();
+    for user_id in user_ids {
+        let updated = versions_coll(db)
+            .find_one_and_update(doc! { "user_id": user_id }, doc! { "$inc": { "version": 1 }, "$set": { "updated_at": &now } })
+            .upsert(true)
+            .return_document(mongodb::options::ReturnDocument::After)
+            .await;
+        match updated {
+            Ok(Some(v)) => remember(v),
+            Ok(None) => {}
+            Err(e) => error!("Error bumping role version of {}: {}", user_id, e),
+        }
+    }
+}
+
+/// Ends every session the user has: tokens issued until now stop being accepted, on
+/// other instances within VERSION_POLL_INTERVAL.
+pub(crate) async fn revoke_sessions(db: &MongoDB, user_id: &str) -> mongodb::error::Result<()> {
+    let now = Utc::now();
+    let update = doc! {
+        "$inc": { "version": 1 },
+        "$set": { "updated_at": bson::to_bson(&now).unwrap_or_default(), "revoked_before": now.timestamp() },
+    };
+    let updated = versions_coll(db)
+        .find_one_and_update(doc! { "user_id": user_id }, update)
+        .upsert(true)
+        .return_document(mongodb::options::ReturnDocument::After)
+        .await?;
+    if let Some(v) = updated {
+        remember(v);
+    }
+    Ok(())
+}
+
+/// Whether a token the user was issued at `issued_at` (seconds) has been revoked.
+pub(crate) fn is_revoked(user_id: &str, issued_at: i64) -> bool {
+    revoked().read().unwrap().get(user_id).is_some_and(|before| issued_at <= *before)
+}
+
+/// Loads versions changed since `since` into memory.
+async fn sync_versions(db: &MongoDB, since: Option<DateTime<Utc>>) -> mongodb::error::Result<()> {
+    let filter = match since {
+        Some(t) => doc! { "updated_at": { "$gte": bson::to_bson(&t).unwrap_or_default() } },
+        None => doc! {},
+    };
+    let mut cursor = versions_coll(db).find(filter).await?;
+    let mut changed = Vec::new();
+    while let Some(v) = cursor.next().await {
+        changed.push(v?);
+    }
+    for v in changed {
+        remember(v);
+    }
+    Ok(())
+}
+
+/// Start the background job that keeps the in-memory role versions current.
+pub fn spawn_version_sync(data: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(VERSION_POLL_INTERVAL);
+        let mut since: Option<DateTime<Utc>> = None;
+        loop {
+            interval.tick().await;
+            // Overlap the polls so a bump written during the last one is not missed.
+            let started = Utc::now() - chrono::Duration::seconds(VERSION_POLL_INTERVAL.as_secs() as i64);
+            match sync_versions(&data.mongodb, since).await {
+                Ok(()) => {
+                    since = Some(started);
+                    SYNCED.store(true, Ordering::Release);
+                }
+                Err(e) => error!("Error syncing role versions: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,871 @@
+// src/saved_reports.rs
+//
+// Custom reports. A report is a definition over the team's tickets or worklog
+// entries: filters, an optional group-by and one or more aggregations (count, sum of
+// estimates, average cycle time, sum of logged time). Team admins save definitions
+// per team, can preview a definition before saving it, and can put a saved report on
+// a daily or weekly schedule; the results are then emailed as text and/or POSTed as
+// JSON to a webhook.
+//
+// Cycle time runs from the first status change in the ticket's event log to
+// `resolved_at`; tickets without one (created before the log existed, or closed on
+// creation) count from `created_at`. Only closed tickets have a cycle time.
+//
+// A run reads at most MAX_TICKETS tickets; larger results are marked truncated.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Datelike, NaiveTime, SecondsFormat, SubsecRound, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use futures_util::StreamExt;
+use log::{error, info};
+use mongodb::action::Find;
+use mongodb::bson::{self, doc, Document};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::Board;
+use crate::chat_db::MongoDB;
+use crate::mailer::send_email;
+use crate::member_import::valid_email;
+use crate::out_of_office::usernames;
+use crate::outbound;
+use crate::project::Project;
+use crate::team_time::{team_timezone, timestamp};
+use crate::tenancy::{ProjectsScope, Repo};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+use crate::ticket_worklog::WorklogEntry;
+
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// How long a claimed run keeps other instances away before it is retried.
+const RUN_LEASE_MINUTES: i64 = 30;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_TICKETS: usize = 20_000;
+const MAX_REPORTS: u64 = 100;
+const MAX_NAME_LEN: usize = 120;
+const MAX_RECIPIENTS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportSource {
+    Tickets,
+    Worklogs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    Status,
+    Priority,
+    TicketType,
+    Assignee,
+    Reporter,
+    Project,
+    Board,
+    Sprint,
+    Label,
+    Release,
+    /// Who logged the time; worklogs only
+    User,
+    /// ISO week of `created_at` for tickets, of `ended_at` for worklogs, in the team's time zone
+    Week,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregation {
+    Count,
+    /// Tickets only
+    SumEstimate,
+    /// Tickets only
+    AvgCycleTime,
+    SumLogged,
+}
+
+/// Empty lists do not filter.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportFilters {
+    pub project_ids: Vec<String>,
+    pub board_ids: Vec<String>,
+    pub statuses: Vec<String>,
+    pub priorities: Vec<String>,
+    pub ticket_types: Vec<String>,
+    pub assignees: Vec<String>,
+    /// Tickets with any of these labels
+    pub labels: Vec<String>,
+    pub sprints: Vec<i32>,
+    /// Only closed (true) or open (false) tickets
+    pub closed: Option<bool>,
+    /// Who logged the time; worklogs only
+    pub user_ids: Vec<String>,
+    /// `created_at` range for tickets, `ended_at` range for worklogs
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportDefinition {
+    pub name: String,
+    pub source: ReportSource,
+    #[serde(default)]
+    pub filters: ReportFilters,
+    #[serde(default)]
+    pub group_by: Option<GroupBy>,
+    pub aggregations: Vec<Aggregation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Frequency {
+    Daily,
+    Weekly,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportSchedule {
+    pub frequency: Frequency,
+    /// Day of weekly reports, e.g. "Mon"
+    #[serde(default)]
+    pub weekday: Option<Weekday>,
+    /// Hour of the day in the team's time zone
+    pub hour: u32,
+    #[serde(default)]
+    pub emails: Vec<String>,
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedReport {
+    pub report_id: String,
+    pub team_id: String,
+    #[serde(flatten)]
+    pub definition: ReportDefinition,
+    pub schedule: Option<ReportSchedule>,
+    /// Whole seconds, so stored values compare correctly as strings
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// Outcome of the last scheduled delivery
+    pub last_error: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveReportRequest {
+    #[serde(flatten)]
+    pub definition: ReportDefinition,
+    pub schedule: Option<ReportSchedule>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReportRow {
+    /// Id of the group (user, project, board or release id, or the value itself);
+    /// None for tickets without a value
+    pub key: Option<String>,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sum_estimate_hours: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_cycle_time_hours: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sum_logged_hours: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportResult {
+    pub report_id: Option<String>,
+    pub name: String,
+    pub source: ReportSource,
+    pub group_by: Option<GroupBy>,
+    /// One row per group, largest first; empty without a group-by
+    pub rows: Vec<ReportRow>,
+    pub total: ReportRow,
+    /// Tickets or worklog entries the report covers
+    pub matched: usize,
+    /// More than MAX_TICKETS tickets matched and the rest were left out
+    pub truncated: bool,
+    pub generated_at: DateTime<Utc>,
+}
+
+fn reports_coll(db: &MongoDB) -> mongodb::Collection<SavedReport> {
+    db.db.collection::<SavedReport>("saved_reports")
+}
+
+async fn validate(db: &MongoDB, team_id: &str, def: &ReportDefinition, schedule: Option<&ReportSchedule>) -> Result<(), String> {
+    let name = def.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(format!("name must be between 1 and {} characters", MAX_NAME_LEN));
+    }
+    if def.aggregations.is_empty() {
+        return Err("At least one aggregation is required".to_string());
+    }
+    let unique: HashSet<_> = def.aggregations.iter().collect();
+    if unique.len() != def.aggregations.len() {
+        return Err("Aggregations must not repeat".to_string());
+    }
+    let f = &def.filters;
+    match def.source {
+        ReportSource::Tickets => {
+            if def.group_by == Some(GroupBy::User) {
+                return Err("Grouping by user is only available for worklogs; use assignee or reporter".to_string());
+            }
+            if !f.user_ids.is_empty() {
+                return Err("The user_ids filter is only available for worklogs".to_string());
+            }
+        }
+        ReportSource::Worklogs => {
+            if def.aggregations.iter().any(|a| matches!(a, Aggregation::SumEstimate | Aggregation::AvgCycleTime)) {
+                return Err("sum_estimate and avg_cycle_time are only available for tickets".to_string());
+            }
+        }
+    }
+    if let (Some(from), Some(to)) = (f.from, f.to) {
+        if from >= to {
+            return Err("from must be before to".to_string());
+        }
+    }
+    if !f.project_ids.is_empty() {
+        let team_projects = ProjectsScope::of_team(db, team_id).await.map_err(|e| {
+            error!("Error validating report: {}", e);
+            "Error validating report".to_string()
+        })?;
+        if let Some(p) = f.project_ids.iter().find(|p| !team_projects.project_ids().contains(p)) {
+            return Err(format!("Project {} is not a project of this team", p));
+        }
+    }
+    if let Some(s) = schedule {
+        if s.hour > 23 {
+            return Err("hour must be between 0 and 23".to_string());
+        }
+        if s.frequency == Frequency::Weekly && s.weekday.is_none() {
+            return Err("Weekly reports need a weekday".to_string());
+        }
+        if s.emails.is_empty() && s.webhook_url.is_none() {
+            return Err("A schedule needs at least one email address or a webhook".to_string());
+        }
+        if s.emails.len() > MAX_RECIPIENTS {
+            return Err(format!("A schedule can have at most {} email addresses", MAX_RECIPIENTS));
+        }
+        if let Some(e) = s.emails.iter().find(|e| !valid_email(e)) {
+            return Err(format!("{} is not a valid email address", e));
+        }
+        if let Some(url) = &s.webhook_url {
+            outbound::check_url(url).await.map_err(|e| format!("webhook_url: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+fn ticket_filter(def: &ReportDefinition) -> Document {
+    let f = &def.filters;
+    let mut filter = doc! {};
+    let lists = [
+        ("board_id", &f.board_ids),
+        ("status", &f.statuses),
+        ("priority", &f.priorities),
+        ("ticket_type", &f.ticket_types),
+        ("assignee", &f.assignees),
+        ("labels", &f.labels),
+    ];
+    for (field, values) in lists {
+        if !values.is_empty() {
+            filter.insert(field, doc! { "$in": values });
+        }
+    }
+    if !f.sprints.is_empty() {
+        filter.insert("sprint", doc! { "$in": &f.sprints });
+    }
+    match f.closed {
+        Some(true) => {
+            filter.insert("status", doc! { "$in": CLOSED_STATUSES.to_vec() });
+        }
+        // Combined with a status list, the list is narrowed to open statuses.
+        Some(false) => {
+            let open: Vec<&String> = f.statuses.iter().filter(|s| !CLOSED_STATUSES.contains(&s.as_str())).collect();
+            let status = if f.statuses.is_empty() { doc! { "$nin": CLOSED_STATUSES.to_vec() } } else { doc! { "$in": open } };
+            filter.insert("status", status);
+        }
+        None => {}
+    }
+    if def.source == ReportSource::Tickets {
+        if let Some(range) = date_range(f) {
+            filter.insert("created_at", range);
+        }
+    }
+    filter
+}
+
+fn date_range(f: &ReportFilters) -> Option<Document> {
+    let mut range = Document::new();
+    if let Some(from) = f.from {
+        range.insert("$gte", bson::to_bson(&from).unwrap_or_default());
+    }
+    if let Some(to) = f.to {
+        range.insert("$lt", bson::to_bson(&to).unwrap_or_default());
+    }
+    (!range.is_empty()).then_some(range)
+}
+
+/// When work on each ticket started: its first status change.
+async fn work_started(db: &MongoDB, ticket_ids: Vec<&String>) -> mongodb::error::Result<HashMap<String, DateTime<Utc>>> {
+    let pipeline = vec![
+        doc! { "$match": { "ticket_id": { "$in": ticket_ids }, "change.type": "field_changed", "change.field": "status" } },
+        doc! { "$group": { "_id": "$ticket_id", "at": { "$min": "$at" } } },
+    ];
+    let mut cursor = db.db.collection::<Document>("ticket_events").aggregate(pipeline).await?;
+    let mut started = HashMap::new();
+    while let Some(d) = cursor.next().await {
+        let d = d?;
+        if let (Ok(id), Some(at)) = (d.get_str("_id"), d.get("at").and_then(timestamp)) {
+            started.insert(id.to_string(), at);
+        }
+    }
+    Ok(started)
+}
+
+/// Accumulates one group.
+#[derive(Default)]
+struct Totals {
+    count: i64,
+    estimate: f64,
+    cycle_hours: f64,
+    cycled: i64,
+    logged_minutes: i64,
+}
+
+impl Totals {
+    fn row(&self, key: Option<String>, label: String, aggregations: &[Aggregation]) -> ReportRow {
+        let mut row = ReportRow { key, label, ..Default::default() };
+        for a in aggregations {
+            match a {
+                Aggregation::Count => row.count = Some(self.count),
+                Aggregation::SumEstimate => row.sum_estimate_hours = Some(self.estimate),
+                Aggregation::AvgCycleTime => {
+                    row.avg_cycle_time_hours = Some(if self.cycled == 0 { 0.0 } else { self.cycle_hours / self.cycled as f64 })
+                }
+                Aggregation::SumLogged => row.sum_logged_hours = Some(self.logged_minutes as f64 / 60.0),
+            }
+        }
+        row
+    }
+}
+
+/// Display names for group keys.
+struct Names {
+    users: HashMap<String, String>,
+    projects: HashMap<String, String>,
+    boards: HashMap<String, String>,
+    releases: HashMap<String, String>,
+}
+
+async fn name_map(find: Find<'_, Document>, id: &str) -> mongodb::error::Result<HashMap<String, String>> {
+    let mut cursor = find.projection(doc! { id: 1, "name": 1 }).await?;
+    let mut map = HashMap::new();
+    while let Some(d) = cursor.next().await {
+        let d = d?;
+        if let (Ok(k), Ok(v)) = (d.get_str(id), d.get_str("name")) {
+            map.insert(k.to_string(), v.to_string());
+        }
+    }
+    Ok(map)
+}
+
+async fn names(db: &MongoDB, scope: &ProjectsScope, group_by: GroupBy, keys: Vec<String>) -> mongodb::error::Result<Names> {
+    let mut names = Names { users: HashMap::new(), projects: HashMap::new(), boards: HashMap::new(), releases: HashMap::new() };
+    let (projects, boards) = (Repo::<Project>::across(db, scope), Repo::<Board>::across(db, scope));
+    let releases = db.db.collection::<Document>("releases");
+    match group_by {
+        GroupBy::Assignee | GroupBy::Reporter | GroupBy::User => names.users = usernames(db, &keys).await?.into_iter().collect(),
+        GroupBy::Project => names.projects = name_map(projects.find_docs(doc! { "project_id": { "$in": keys } }), "project_id").await?,
+        GroupBy::Board => names.boards = name_map(boards.find_docs(doc! { "board_id": { "$in": keys } }), "board_id").await?,
+        GroupBy::Release => names.releases = name_map(releases.find(doc! { "release_id": { "$in": keys } }), "release_id").await?,
+        _ => {}
+    }
+    Ok(names)
+}
+
+impl Names {
+    fn label(&self, key: &Option<String>) -> String {
+        let Some(key) = key else {
+            return "(none)".to_string();
+        };
+        [&self.users, &self.projects, &self.boards, &self.releases]
+            .iter()
+            .find_map(|m| m.get(key))
+            .cloned()
+            .unwrap_or_else(|| key.clone())
+    }
+}
+
+fn iso_week(at: DateTime<Utc>, tz: Tz) -> String {
+    let week = at.with_timezone(&tz).iso_week();
+    format!("{}-W{:02}", week.year(), week.week())
+}
+
+/// Group keys of a ticket; labels give one key per label.
+fn ticket_keys(t: &Ticket, group_by: GroupBy, tz: Tz) -> Vec<Option<String>> {
+    let one = |v: Option<&String>| vec![v.cloned()];
+    match group_by {
+        GroupBy::Status => vec![Some(t.status.clone())],
+        GroupBy::Priority => one(t.priority.as_ref()),
+        GroupBy::TicketType => one(t.ticket_type.as_ref()),
+        GroupBy::Assignee => one(t.assignee.as_ref()),
+        GroupBy::Reporter => vec![Some(t.reporter.clone()).filter(|r| !r.is_empty())],
+        GroupBy::Project => vec![Some(t.project_id.clone())],
+        GroupBy::Board => vec![Some(t.board_id.clone())],
+        GroupBy::Sprint => vec![t.sprint.map(|s| s.to_string())],
+        GroupBy::Label => match t.labels.as_ref().filter(|l| !l.is_empty()) {
+            Some(labels) => labels.iter().cloned().map(Some).collect::<HashSet<_>>().into_iter().collect(),
+            None => vec![None],
+        },
+        GroupBy::Release => one(t.fix_version.as_ref()),
+        GroupBy::Week => vec![Some(iso_week(t.created_at, tz))],
+        GroupBy::User => vec![None],
+    }
+}
+
+/// Runs a definition (already validated) over the team's data.
+pub(crate) async fn run_report(db: &MongoDB, team_id: &str, report_id: Option<String>, def: &ReportDefinition) -> mongodb::error::Result<ReportResult> {
+    let mut scope = ProjectsScope::of_team(db, team_id).await?;
+    if !def.filters.project_ids.is_empty() {
+        scope.retain(|p| def.filters.project_ids.contains(p));
+    }
+    let mut cursor = Repo::<Ticket>::across(db, &scope)
+        .find(ticket_filter(def))
+        .limit(MAX_TICKETS as i64 + 1)
+        .await?;
+    let mut tickets = Vec::new();
+    while let Some(t) = cursor.next().await {
+        tickets.push(t?);
+    }
+    let truncated = tickets.len() > MAX_TICKETS;
+    tickets.truncate(MAX_TICKETS);
+    let tz = team_timezone(db, team_id).await;
+    let wants = |a: Aggregation| def.aggregations.contains(&a);
+
+    let mut logged: HashMap<String, i64> = HashMap::new();
+    let mut entries: Vec<WorklogEntry> = Vec::new();
+    if def.source == ReportSource::Worklogs || wants(Aggregation::SumLogged) {
+        let mut filter = doc! { "ticket_id": { "$in": tickets.iter().map(|t| &t.ticket_id).collect::<Vec<_>>() } };
+        if def.source == ReportSource::Worklogs {
+            if !def.filters.user_ids.is_empty() {
+                filter.insert("user_id", doc! { "$in": &def.filters.user_ids });
+            }
+            if let Some(range) = date_range(&def.filters) {
+                filter.insert("ended_at", range);
+            }
+        }
+        let mut cursor = db.db.collection::<WorklogEntry>("ticket_worklog").find(filter).await?;
+        while let Some(e) = cursor.next().await {
+            let e = e?;
+            *logged.entry(e.ticket_id.clone()).or_default() += e.minutes;
+            entries.push(e);
+        }
+    }
+    let mut started = HashMap::new();
+    if wants(Aggregation::AvgCycleTime) {
+        let closed = tickets.iter().filter(|t| t.resolved_at.is_some()).map(|t| &t.ticket_id).collect();
+        started = work_started(db, closed).await?;
+    }
+
+    let mut total = Totals::default();
+    let mut groups: BTreeMap<Option<String>, Totals> = BTreeMap::new();
+    let matched = match def.source {
+        ReportSource::Tickets => {
+            for t in &tickets {
+                let cycle = t.resolved_at.filter(|_| CLOSED_STATUSES.contains(&t.status.as_str())).map(|resolved| {
+                    let start = started.get(&t.ticket_id).copied().unwrap_or(t.created_at);
+                    (resolved - start).num_minutes().max(0) as f64 / 60.0
+                });
+                let add = |g: &mut Totals| {
+                    g.count += 1;
+                    g.estimate += t.estimate_hours.unwrap_or(0.0);
+                    g.logged_minutes += logged.get(&t.ticket_id).copied().unwrap_or(0);
+                    if let Some(hours) = cycle {
+                        g.cycle_hours += hours;
+                        g.cycled += 1;
+                    }
+                };
+                add(&mut total);
+                if let Some(group_by) = def.group_by {
+                    for key in ticket_keys(t, group_by, tz) {
+                        add(groups.entry(key).or_default());
+                    }
+                }
+            }
+            tickets.len()
+        }
+        ReportSource::Worklogs => {
+            let by_id: HashMap<&str, &Ticket> = tickets.iter().map(|t| (t.ticket_id.as_str(), t)).collect();
+            for e in &entries {
+                let add = |g: &mut Totals| {
+                    g.count += 1;
+                    g.logged_minutes += e.minutes;
+                };
+                add(&mut total);
+                let keys = match (def.group_by, by_id.get(e.ticket_id.as_str())) {
+                    (None, _) => Vec::new(),
+                    (Some(GroupBy::User), _) => vec![Some(e.user_id.clone())],
+                    (Some(GroupBy::Week), _) => vec![Some(iso_week(e.ended_at, tz))],
+                    (Some(group_by), Some(t)) => ticket_keys(t, group_by, tz),
+                    (Some(_), None) => vec![None],
+                };
+                for key in keys {
+                    add(groups.entry(key).or_default());
+                }
+            }
+            entries.len()
+        }
+    };
+
+    let rows = match def.group_by {
+        Some(group_by) => {
+            let names = names(db, &scope, group_by, groups.keys().flatten().cloned().collect()).await?;
+            let mut rows: Vec<(i64, ReportRow)> = groups
+                .into_iter()
+                .map(|(key, g)| (g.count, g.row(key.clone(), names.label(&key), &def.aggregations)))
+                .collect();
+            rows.sort_by_key(|(count, _)| std::cmp::Reverse(*count));
+            rows.into_iter().map(|(_, row)| row).collect()
+        }
+        None => Vec::new(),
+    };
+    Ok(ReportResult {
+        report_id,
+        name: def.name.trim().to_string(),
+        source: def.source,
+        group_by: def.group_by,
+        rows,
+        total: total.row(None, "Total".to_string(), &def.aggregations),
+        matched,
+        truncated,
+        generated_at: Utc::now(),
+    })
+}
+
+/// The first scheduled time after `after`.
+fn next_run(schedule: &ReportSchedule, tz: Tz, after: DateTime<Utc>) -> DateTime<Utc> {
+    let today = after.with_timezone(&tz).date_naive();
+    let time = NaiveTime::from_hms_opt(schedule.hour, 0, 0).unwrap_or(NaiveTime::MIN);
+    (0..=8)
+        .map(|d| today + chrono::Duration::days(d))
+        .filter(|date| schedule.frequency == Frequency::Daily || Some(date.weekday()) == schedule.weekday)
+        .filter_map(|date| tz.from_local_datetime(&date.and_time(time)).earliest())
+        .map(|t| t.with_timezone(&Utc))
+        .find(|t| *t > after)
+        .unwrap_or(after + chrono::Duration::days(1))
+        .trunc_subsecs(0)
+}
+
+fn fmt_hours(h: f64) -> String {
+    format!("{:.1}h", h)
+}
+
+fn render_text(result: &ReportResult) -> String {
+    let line = |row: &ReportRow| {
+        let mut parts = Vec::new();
+        if let Some(c) = row.count {
+            parts.push(format!("count {}", c));
+        }
+        if let Some(h) = row.sum_estimate_hours {
+            parts.push(format!("estimated {}", fmt_hours(h)));
+        }
+        if let Some(h) = row.avg_cycle_time_hours {
+            parts.push(format!("avg cycle time {}", fmt_hours(h)));
+        }
+        if let Some(h) = row.sum_logged_hours {
+            parts.push(format!("logged {}", fmt_hours(h)));
+        }
+        format!("  {}: {}\n", row.label, parts.join(", "))
+    };
+    let mut out = format!("{}\n\n", result.name);
+    for row in &result.rows {
+        out.push_str(&line(row));
+    }
+    out.push_str(&line(&result.total));
+    if result.truncated {
+        out.push_str(&format!("\nOnly the first {} tickets are included.\n", MAX_TICKETS));
+    }
+    out
+}
+
+/// Sends a result to the schedule's recipients; the error of the first failure.
+async fn deliver(data: &AppState, schedule: &ReportSchedule, result: &ReportResult) -> Result<(), String> {
+    let mut outcome = Ok(());
+    let subject = format!("Report: {}", result.name);
+    let text = render_text(result);
+    for to in &schedule.emails {
+        if let Err(e) = send_email(data, to, &subject, &text).await {
+            outcome = outcome.and(Err(e));
+        }
+    }
+    if let Some(url) = &schedule.webhook_url {
+        let sent = match outbound::client_for(url).await {
+            Ok((client, url)) => client
+                .post(url)
+                .timeout(WEBHOOK_TIMEOUT)
+                .json(result)
+                .send()
+                .await
+                .map_err(|e| format!("Webhook unreachable: {}", e))
+                .and_then(|r| if r.status().is_success() { Ok(()) } else { Err(format!("Webhook answered {}", r.status())) }),
+            Err(e) => Err(format!("Webhook refused: {}", e)),
+        };
+        outcome = outcome.and(sent);
+    }
+    outcome
+}
+
+/// Claim and deliver every scheduled report whose time has come.
+async fn run_due(data: &AppState) {
+    let db = &data.mongodb;
+    loop {
+        let now = Utc::now().trunc_subsecs(0);
+        // The lease keeps a second instance from sending the same report.
+        let lease = (now + chrono::Duration::minutes(RUN_LEASE_MINUTES)).to_rfc3339_opts(SecondsFormat::Secs, true);
+        let claimed = reports_coll(db)
+            .find_one_and_update(
+                doc! { "next_run_at": { "$lte": now.to_rfc3339_opts(SecondsFormat::Secs, true) } },
+                doc! { "$set": { "next_run_at": lease } },
+            )
+            .sort(doc! { "next_run_at": 1 })
+            .return_document(ReturnDocument::Before)
+            .await;
+        let report = match claimed {
+            Ok(Some(r)) => r,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Error claiming scheduled reports: {}", e);
+                return;
+            }
+        };
+        let Some(schedule) = &report.schedule else { continue };
+        let outcome = match run_report(db, &report.team_id, Some(report.report_id.clone()), &report.definition).await {
+            Ok(result) => deliver(data, schedule, &result).await,
+            Err(e) => {
+                error!("Error running report {}: {}", report.report_id, e);
+                Err("Error running report".to_string())
+            }
+        };
+        let tz = team_timezone(db, &report.team_id).await;
+        let update = doc! { "$set": {
+            "next_run_at": bson::to_bson(&next_run(schedule, tz, now)).unwrap_or_default(),
+            "last_run_at": bson::to_bson(&now).unwrap_or_default(),
+            "last_error": outcome.as_ref().err(),
+        } };
+        if let Err(e) = reports_coll(db).update_one(doc! { "report_id": &report.report_id }, update).await {
+            error!("Error updating report {}: {}", report.report_id, e);
+        }
+        info!("Scheduled report {} delivered: {:?}", report.report_id, outcome);
+    }
+}
+
+/// Start the background job that delivers scheduled reports.
+pub fn spawn_report_schedules(data: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(SCHEDULE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            run_due(&data).await;
+        }
+    });
+}
+
+/// POST /teams/{team_id}/reports/preview
+/// Team admins only; validates a definition and runs it without saving.
+pub async fn preview_report(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<ReportDefinition>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage reports");
+    }
+    if let Err(msg) = validate(&data.mongodb, &team_id, &payload, None).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    match run_report(&data.mongodb, &team_id, None, &payload).await {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(e) => {
+            error!("Error previewing report: {}", e);
+            HttpResponse::InternalServerError().body("Error running report")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/reports
+pub async fn list_reports(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage reports");
+    }
+    let mut cursor = match reports_coll(&data.mongodb).find(doc! { "team_id": &*team_id }).sort(doc! { "name": 1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching reports: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching reports");
+        }
+    };
+    let mut reports = Vec::new();
+    while let Some(r) = cursor.next().await {
+        match r {
+            Ok(r) => reports.push(r),
+            Err(e) => error!("Error reading report: {}", e),
+        }
+    }
+    HttpResponse::Ok().json(reports)
+}
+
+/// POST /teams/{team_id}/reports
+pub async fn create_report(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<SaveReportRequest>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage reports");
+    }
+    let req = payload.into_inner();
+    if let Err(msg) = validate(&data.mongodb, &team_id, &req.definition, req.schedule.as_ref()).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    match reports_coll(&data.mongodb).count_documents(doc! { "team_id": &*team_id }).await {
+        Ok(n) if n >= MAX_REPORTS => {
+            return HttpResponse::BadRequest().body(format!("A team can have at most {} reports", MAX_REPORTS));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("Error counting reports: {}", e);
+            return HttpResponse::InternalServerError().body("Error creating report");
+        }
+    }
+    let now = Utc::now();
+    let tz = team_timezone(&data.mongodb, &team_id).await;
+    let mut definition = req.definition;
+    definition.name = definition.name.trim().to_string();
+    let report = SavedReport {
+        report_id: Uuid::new_v4().to_string(),
+        team_id: team_id.to_string(),
+        definition,
+        next_run_at: req.schedule.as_ref().map(|s| next_run(s, tz, now)),
+        schedule: req.schedule,
+        last_run_at: None,
+        last_error: None,
+        created_by: auth.user_id().to_string(),
+        created_at: now,
+        updated_at: now,
+    };
+    match reports_coll(&data.mongodb).insert_one(&report).await {
+        Ok(_) => HttpResponse::Created().json(report),
+        Err(e) => {
+            error!("Error creating report: {}", e);
+            HttpResponse::InternalServerError().body("Error creating report")
+        }
+    }
+}
+
+async fn find_report(data: &AppState, team_id: &str, report_id: &str) -> Result<SavedReport, HttpResponse> {
+    match reports_coll(&data.mongodb).find_one(doc! { "report_id": report_id, "team_id": team_id }).await {
+        Ok(Some(r)) => Ok(r),
+        Ok(None) => Err(HttpResponse::NotFound().body("Report not found")),
+        Err(e) => {
+            error!("Error fetching report: {}", e);
+            Err(HttpResponse::InternalServerError().body("Error fetching report"))
+        }
+    }
+}
+
+/// GET /teams/{team_id}/reports/{report_id}
+pub async fn get_report(auth: AuthContext, data: web::Data<AppState>, path: web::Path<(String, String)>) -> impl Responder {
+    let (team_id, report_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage reports");
+    }
+    match find_report(&data, &team_id, &report_id).await {
+        Ok(r) => HttpResponse::Ok().json(r),
+        Err(resp) => resp,
+    }
+}
+
+/// PUT /teams/{team_id}/reports/{report_id}
+/// Replaces the definition and schedule; the next run is recomputed.
+pub async fn update_report(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<SaveReportRequest>,
+) -> impl Responder {
+    let (team_id, report_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage reports");
+    }
+    let mut report = match find_report(&data, &team_id, &report_id).await {
+        Ok(r) => r,
+        Err(resp) => return resp,
+    };
+    let req = payload.into_inner();
+    if let Err(msg) = validate(&data.mongodb, &team_id, &req.definition, req.schedule.as_ref()).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    let now = Utc::now();
+    let tz = team_timezone(&data.mongodb, &team_id).await;
+    report.definition = req.definition;
+    report.definition.name = report.definition.name.trim().to_string();
+    report.next_run_at = req.schedule.as_ref().map(|s| next_run(s, tz, now));
+    report.schedule = req.schedule;
+    report.updated_at = now;
+    match reports_coll(&data.mongodb).replace_one(doc! { "report_id": &report_id, "team_id": &team_id }, &report).await {
+        Ok(res) if res.matched_count == 1 => HttpResponse::Ok().json(report),
+        Ok(_) => HttpResponse::NotFound().body("Report not found"),
+        Err(e) => {
+            error!("Error updating report: {}", e);
+            HttpResponse::InternalServerError().body("Error updating report")
+        }
+    }
+}
+
+/// DELETE /teams/{team_id}/reports/{report_id}
+pub async fn delete_report(auth: AuthContext, data: web::Data<AppState>, path: web::Path<(String, String)>) -> impl Responder {
+    let (team_id, report_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage reports");
+    }
+    match reports_coll(&data.mongodb).delete_one(doc! { "report_id": &report_id, "team_id": &team_id }).await {
+        Ok(res) if res.deleted_count == 1 => HttpResponse::NoContent().finish(),
+        Ok(_) => HttpResponse::NotFound().body("Report not found"),
+        Err(e) => {
+            error!("Error deleting report: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting report")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/reports/{report_id}/results
+pub async fn get_report_results(auth: AuthContext, data: web::Data<AppState>, path: web::Path<(String, String)>) -> impl Responder {
+    let (team_id, report_id) = path.into_inner();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage reports");
+    }
+    let report = match find_report(&data, &team_id, &report_id).await {
+        Ok(r) => r,
+        Err(resp) => return resp,
+    };
+    match run_report(&data.mongodb, &team_id, Some(report_id), &report.definition).await {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(e) => {
+            error!("Error running report: {}", e);
+            HttpResponse::InternalServerError().body("Error running report")
+        }
+    }
+}
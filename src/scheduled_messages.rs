@@ -0,0 +1,225 @@
+// src/scheduled_messages.rs
+//
+// Chat messages scheduled for later. A pending entry is delivered through the
+// ChatServer once `send_at` has passed, exactly as if the sender had posted it then;
+// the sender must still be a participant at that point or the entry is marked failed.
+// The same job delivers due reminders (see reminders.rs).
+
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, SecondsFormat, SubsecRound, Utc};
+use futures_util::StreamExt;
+use log::{error, warn};
+use mongodb::bson::doc;
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat::Chat;
+use crate::chat_server::CreateMessage;
+use crate::reminders::deliver_due_reminders;
+use crate::response::CodedError;
+
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(15);
+/// How far ahead a message may be scheduled.
+const MAX_SCHEDULE_DAYS: i64 = 365;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledMessage {
+    pub scheduled_id: String,
+    pub chat_id: String,
+    pub sender_id: String,
+    pub content: String,
+    /// Whole seconds, so stored values compare correctly as strings
+    pub send_at: DateTime<Utc>,
+    /// "pending", "sending", "sent", "failed" or "cancelled"
+    pub status: String,
+    pub message_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduleMessageRequest {
+    pub content: String,
+    pub send_at: DateTime<Utc>,
+}
+
+fn scheduled_coll(data: &AppState) -> mongodb::Collection<ScheduledMessage> {
+    data.mongodb.db.collection::<ScheduledMessage>("scheduled_messages")
+}
+
+async fn collect(
+    data: &AppState,
+    filter: mongodb::bson::Document,
+) -> mongodb::error::Result<Vec<ScheduledMessage>> {
+    let mut cursor = scheduled_coll(data).find(filter).sort(doc! { "send_at": 1 }).await?;
+    let mut out = Vec::new();
+    while let Some(m) = cursor.next().await {
+        out.push(m?);
+    }
+    Ok(out)
+}
+
+/// Claim and deliver every pending message whose time has come.
+async fn deliver_due(data: &AppState) {
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    loop {
+        // Claiming one at a time keeps a second instance from sending the same message.
+        let claimed = scheduled_coll(data)
+            .find_one_and_update(
+                doc! { "status": "pending", "send_at": { "$lte": &now } },
+                doc! { "$set": { "status": "sending" } },
+            )
+            .sort(doc! { "send_at": 1 })
+            .return_document(ReturnDocument::After)
+            .await;
+        let scheduled = match claimed {
+            Ok(Some(m)) => m,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Error claiming scheduled messages: {}", e);
+                return;
+            }
+        };
+        let create = CreateMessage {
+            user_id: scheduled.sender_id.clone(),
+            chat_id: scheduled.chat_id.clone(),
+            content: scheduled.content.clone(),
+            attachments: None,
+        };
+        let update = match data.chat_server.send(create).await {
+            Ok(Ok(msg)) => doc! { "$set": { "status": "sent", "message_id": msg.id } },
+            Ok(Err(_)) | Err(_) => {
+                warn!("Scheduled message {} could not be delivered", scheduled.scheduled_id);
+                doc! { "$set": { "status": "failed" } }
+            }
+        };
+        if let Err(e) = scheduled_coll(data)
+            .update_one(doc! { "scheduled_id": &scheduled.scheduled_id }, update)
+            .await
+        {
+            error!("Error updating scheduled message {}: {}", scheduled.scheduled_id, e);
+        }
+    }
+}
+
+pub fn spawn_scheduler(data: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(SCHEDULER_INTERVAL);
+        loop {
+            interval.tick().await;
+            deliver_due(&data).await;
+            deliver_due_reminders(&data).await;
+        }
+    });
+}
+
+// ----------------------------------------------------------------------
+// POST /messages/{chat_id}/scheduled => schedule a message for later
+// ----------------------------------------------------------------------
+pub async fn schedule_message(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    chat_id: web::Path<String>,
+    payload: web::Json<ScheduleMessageRequest>,
+) -> impl Responder {
+    let chat_id = chat_id.into_inner();
+    let req = payload.into_inner();
+    if req.content.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Message content must not be empty");
+    }
+    let now = Utc::now();
+    if req.send_at <= now {
+        return HttpResponse::BadRequest().body("send_at must be in the future");
+    }
+    if req.send_at > now + chrono::Duration::days(MAX_SCHEDULE_DAYS) {
+        return HttpResponse::BadRequest().body(format!("send_at must be within {} days", MAX_SCHEDULE_DAYS));
+    }
+    let chats = data.mongodb.db.collection::<Chat>("chats");
+    match chats.find_one(doc! { "_id": &chat_id, "participants": auth.user_id() }).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::Forbidden().error("not_chat_participant"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    }
+
+    let scheduled = ScheduledMessage {
+        scheduled_id: Uuid::new_v4().to_string(),
+        chat_id,
+        sender_id: auth.user_id().to_string(),
+        content: req.content,
+        send_at: req.send_at.trunc_subsecs(0),
+        status: "pending".to_string(),
+        message_id: None,
+        created_at: now,
+    };
+    match scheduled_coll(&data).insert_one(&scheduled).await {
+        Ok(_) => HttpResponse::Created().json(scheduled),
+        Err(e) => {
+            error!("Error scheduling message: {}", e);
+            HttpResponse::InternalServerError().body("Error scheduling message")
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// GET /messages/{chat_id}/scheduled => the caller's pending messages in a chat
+// ----------------------------------------------------------------------
+pub async fn list_scheduled_messages(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    chat_id: web::Path<String>,
+) -> impl Responder {
+    let filter = doc! { "chat_id": &*chat_id, "sender_id": auth.user_id(), "status": "pending" };
+    match collect(&data, filter).await {
+        Ok(list) => HttpResponse::Ok().json(list),
+        Err(e) => {
+            error!("Error fetching scheduled messages: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching scheduled messages")
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// GET /users/me/scheduled-messages => the caller's pending messages in every chat
+// ----------------------------------------------------------------------
+pub async fn list_my_scheduled_messages(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    match collect(&data, doc! { "sender_id": auth.user_id(), "status": "pending" }).await {
+        Ok(list) => HttpResponse::Ok().json(list),
+        Err(e) => {
+            error!("Error fetching scheduled messages: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching scheduled messages")
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// DELETE /messages/{chat_id}/scheduled/{scheduled_id} => cancel a pending message
+// ----------------------------------------------------------------------
+pub async fn cancel_scheduled_message(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (chat_id, scheduled_id) = path.into_inner();
+    let filter = doc! {
+        "scheduled_id": &scheduled_id,
+        "chat_id": &chat_id,
+        "sender_id": auth.user_id(),
+        "status": "pending",
+    };
+    match scheduled_coll(&data)
+        .find_one_and_update(filter, doc! { "$set": { "status": "cancelled" } })
+        .return_document(ReturnDocument::After)
+        .await
+    {
+        Ok(Some(m)) => HttpResponse::Ok().json(m),
+        Ok(None) => HttpResponse::NotFound().body("No pending scheduled message with that id"),
+        Err(e) => {
+            error!("Error cancelling scheduled message: {}", e);
+            HttpResponse::InternalServerError().body("Error cancelling scheduled message")
+        }
+    }
+}
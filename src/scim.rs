@@ -0,0 +1,929 @@
+// src/scim.rs
+//
+// SCIM 2.0 provisioning for identity providers. Each organization's provider
+// authenticates with the SCIM token of its SSO connection (issued under
+// /admin/sso-connections/{id}/scim-token) and sees only its own part of the data:
+// accounts whose email is in the connection's domains and teams created with that
+// token. The supported subset:
+//
+// - Users map to accounts: create, read, list with `eq` filters, replace, PATCH
+//   and deactivation (`active: false` or DELETE). Deactivating removes the user from
+//   all teams and revokes their sessions. New accounts get a password setup email.
+// - Groups map to teams: create, read, list, rename and change members. A team made
+//   through SCIM gets its first member as owner and admin. The owner cannot be
+//   removed through SCIM, and teams cannot be deleted through it.
+//
+// Responses and errors use the SCIM JSON format rather than the API's envelope.
+
+use std::sync::OnceLock;
+
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use log::{error, info};
+use mongodb::bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth::send_password_setup;
+use crate::chat_server::ForceDisconnect;
+use crate::encryption::lookup_hash;
+use crate::role_claims;
+use crate::team_management::UserTeam;
+
+const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+const LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const CONTENT_TYPE: &str = "application/scim+json";
+const BASE_PATH: &str = "/scim/v2";
+const MAX_COUNT: u64 = 200;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListQuery {
+    pub filter: Option<String>,
+    pub start_index: Option<u64>,
+    pub count: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimName {
+    given_name: Option<String>,
+    family_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScimEmail {
+    value: String,
+    #[serde(default)]
+    primary: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimUser {
+    user_name: String,
+    external_id: Option<String>,
+    #[serde(default)]
+    name: Option<ScimName>,
+    display_name: Option<String>,
+    #[serde(default)]
+    emails: Vec<ScimEmail>,
+    #[serde(default, deserialize_with = "lenient_bool")]
+    active: Option<bool>,
+}
+
+impl ScimUser {
+    /// The primary email, else the first one, else a userName that looks like an email.
+    fn email(&self) -> Option<String> {
+        self.emails
+            .iter()
+            .find(|e| e.primary)
+            .or_else(|| self.emails.first())
+            .map(|e| e.value.trim().to_lowercase())
+            .or_else(|| self.user_name.contains('@').then(|| self.user_name.trim().to_lowercase()))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MemberRef {
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimGroup {
+    display_name: String,
+    external_id: Option<String>,
+    #[serde(default)]
+    members: Vec<MemberRef>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PatchRequest {
+    #[serde(rename = "Operations")]
+    operations: Vec<PatchOp>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PatchOp {
+    op: String,
+    path: Option<String>,
+    value: Option<Value>,
+}
+
+/// Some identity providers send booleans as "True"/"False". Other strings are
+/// refused rather than read as false, which would deprovision the user.
+fn lenient_bool<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<bool>, D::Error> {
+    match Option::<Value>::deserialize(d)? {
+        Some(Value::Bool(b)) => Ok(Some(b)),
+        Some(Value::String(s)) if s.eq_ignore_ascii_case("true") => Ok(Some(true)),
+        Some(Value::String(s)) if s.eq_ignore_ascii_case("false") => Ok(Some(false)),
+        Some(Value::String(s)) => Err(serde::de::Error::custom(format!("\"{}\" is not a boolean", s))),
+        _ => Ok(None),
+    }
+}
+
+fn scim_response(status: StatusCode, body: Value) -> HttpResponse {
+    HttpResponse::build(status).content_type(CONTENT_TYPE).body(body.to_string())
+}
+
+fn scim_error(status: StatusCode, detail: &str, scim_type: Option<&str>) -> HttpResponse {
+    let mut body = json!({ "schemas": [ERROR_SCHEMA], "status": status.as_u16().to_string(), "detail": detail });
+    if let Some(t) = scim_type {
+        body["scimType"] = json!(t);
+    }
+    scim_response(status, body)
+}
+
+fn db_error(e: mongodb::error::Error) -> HttpResponse {
+    error!("SCIM database error: {}", e);
+    scim_error(StatusCode::INTERNAL_SERVER_ERROR, "Internal error", None)
+}
+
+/// The organization a provisioning token belongs to.
+struct ScimOrg {
+    connection_id: String,
+    /// Lowercase email domains of the organization's accounts
+    domains: Vec<String>,
+}
+
+impl ScimOrg {
+    fn owns_email(&self, email: &str) -> bool {
+        email.rsplit_once('@').is_some_and(|(_, domain)| self.domains.iter().any(|d| d.eq_ignore_ascii_case(domain)))
+    }
+
+    /// Accounts of the organization.
+    fn users(&self) -> Document {
+        let domains: Vec<String> = self.domains.iter().map(|d| regex::escape(d)).collect();
+        doc! { "email": { "$regex": format!("@({})$", domains.join("|")), "$options": "i" } }
+    }
+
+    /// Teams created with the organization's token.
+    fn teams(&self) -> Document {
+        doc! { "scim_connection_id": &self.connection_id }
+    }
+}
+
+/// The organization whose SCIM token the request carries.
+async fn authorize(req: &HttpRequest, data: &AppState) -> Result<ScimOrg, HttpResponse> {
+    let invalid = || scim_error(StatusCode::UNAUTHORIZED, "Invalid provisioning token", None);
+    let presented = req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .ok_or_else(invalid)?;
+    let connection = data
+        .mongodb
+        .db
+        .collection::<Document>("sso_connections")
+        .find_one(doc! { "scim_token_hash": lookup_hash(presented) })
+        .await
+        .map_err(db_error)?
+        .ok_or_else(invalid)?;
+    let domains: Vec<String> = connection
+        .get_array("domains")
+        .map(|ds| ds.iter().filter_map(|d| d.as_str()).map(str::to_lowercase).collect())
+        .unwrap_or_default();
+    if domains.is_empty() {
+        return Err(invalid());
+    }
+    Ok(ScimOrg { connection_id: connection.get_str("connection_id").unwrap_or_default().to_string(), domains })
+}
+
+fn parse_body<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, HttpResponse> {
+    serde_json::from_slice(body).map_err(|e| scim_error(StatusCode::BAD_REQUEST, &format!("Invalid request: {}", e), Some("invalidSyntax")))
+}
+
+/// Turns `attr eq "value"` into a Mongo filter using `fields` (SCIM name → stored field).
+fn parse_filter(filter: Option<&str>, fields: &[(&str, &str)]) -> Result<Document, HttpResponse> {
+    let Some(filter) = filter.map(str::trim).filter(|f| !f.is_empty()) else {
+        return Ok(Document::new());
+    };
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| Regex::new(r#"^([A-Za-z.]+)\s+(?i:eq)\s+"((?:[^"\\]|\\.)*)"$"#).expect("valid filter regex"));
+    let invalid = || scim_error(StatusCode::BAD_REQUEST, "Only `attribute eq \"value\"` filters are supported", Some("invalidFilter"));
+    let caps = pattern.captures(filter).ok_or_else(invalid)?;
+    let field = fields
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(&caps[1]))
+        .map(|(_, field)| *field)
+        .ok_or_else(invalid)?;
+    let value = caps[2].replace("\\\"", "\"");
+    Ok(doc! { field: { "$regex": format!("^{}$", regex::escape(&value)), "$options": "i" } })
+}
+
+fn user_resource(user: &Document) -> Value {
+    let id = user.get_object_id("_id").map(|o| o.to_hex()).unwrap_or_default();
+    let opt = |field: &str| user.get_str(field).ok().map(str::to_string);
+    let created = user.get_datetime("created_at").ok().and_then(|d| d.try_to_rfc3339_string().ok());
+    json!({
+        "schemas": [USER_SCHEMA],
+        "id": id,
+        "externalId": opt("scim_external_id"),
+        "userName": opt("username"),
+        "displayName": opt("display_name").or_else(|| opt("username")),
+        "name": { "givenName": opt("given_name"), "familyName": opt("family_name") },
+        "emails": opt("email").map(|e| vec![json!({ "value": e, "primary": true, "type": "work" })]).unwrap_or_default(),
+        "active": user.get_bool("active").unwrap_or(true),
+        "meta": { "resourceType": "User", "created": created, "location": format!("{}/Users/{}", BASE_PATH, id) },
+    })
+}
+
+async fn group_resource(data: &AppState, team: &Document) -> mongodb::error::Result<Value> {
+    let team_id = team.get_str("team_id").unwrap_or_default();
+    let member_ids = data.mongodb.db.collection::<Document>("user_teams").distinct("user_id", doc! { "team_id": team_id }).await?;
+    let oids: Vec<ObjectId> = member_ids.iter().filter_map(|v| v.as_str()).filter_map(|id| ObjectId::parse_str(id).ok()).collect();
+    let mut cursor = data.mongodb.db.collection::<Document>("users").find(doc! { "_id": { "$in": oids } }).await?;
+    let mut members = Vec::new();
+    while cursor.advance().await? {
+        let user = cursor.deserialize_current()?;
+        if let Ok(oid) = user.get_object_id("_id") {
+            members.push(json!({ "value": oid.to_hex(), "display": user.get_str("username").ok(), "$ref": format!("{}/Users/{}", BASE_PATH, oid.to_hex()) }));
+        }
+    }
+    Ok(json!({
+        "schemas": [GROUP_SCHEMA],
+        "id": team_id,
+        "externalId": team.get_str("scim_external_id").ok(),
+        "displayName": team.get_str("name").ok(),
+        "members": members,
+        "meta": { "resourceType": "Group", "location": format!("{}/Groups/{}", BASE_PATH, team_id) },
+    }))
+}
+
+fn list_response(total: u64, start_index: u64, resources: Vec<Value>) -> HttpResponse {
+    scim_response(StatusCode::OK, json!({
+        "schemas": [LIST_SCHEMA],
+        "totalResults": total,
+        "startIndex": start_index,
+        "itemsPerPage": resources.len(),
+        "Resources": resources,
+    }))
+}
+
+fn users(data: &AppState) -> mongodb::Collection<Document> {
+    data.mongodb.db.collection::<Document>("users")
+}
+
+fn teams(data: &AppState) -> mongodb::Collection<Document> {
+    data.mongodb.db.collection::<Document>("teams")
+}
+
+async fn find_user(data: &AppState, org: &ScimOrg, id: &str) -> Result<Document, HttpResponse> {
+    let not_found = || scim_error(StatusCode::NOT_FOUND, "User not found", None);
+    let oid = ObjectId::parse_str(id).map_err(|_| not_found())?;
+    let mut filter = org.users();
+    filter.insert("_id", oid);
+    users(data).find_one(filter).await.map_err(db_error)?.ok_or_else(not_found)
+}
+
+async fn find_team(data: &AppState, org: &ScimOrg, id: &str) -> Result<Document, HttpResponse> {
+    let mut filter = org.teams();
+    filter.insert("team_id", id);
+    match teams(data).find_one(filter).await {
+        Ok(Some(t)) => Ok(t),
+        Ok(None) => Err(scim_error(StatusCode::NOT_FOUND, "Group not found", None)),
+        Err(e) => Err(db_error(e)),
+    }
+}
+
+/// Rejects a userName or email already used by another account.
+async fn check_unique(data: &AppState, username: &str, email: &str, except: Option<ObjectId>) -> Result<(), HttpResponse> {
+    let ci = |v: &str| doc! { "$regex": format!("^{}$", regex::escape(v)), "$options": "i" };
+    let mut filter = doc! { "$or": [{ "username": ci(username) }, { "email": ci(email) }] };
+    if let Some(oid) = except {
+        filter.insert("_id", doc! { "$ne": oid });
+    }
+    match users(data).find_one(filter).await {
+        Ok(Some(_)) => Err(scim_error(StatusCode::CONFLICT, "userName or email is already taken", Some("uniqueness"))),
+        Ok(None) => Ok(()),
+        Err(e) => Err(db_error(e)),
+    }
+}
+
+/// Removes a deactivated user from every team and closes their sessions.
+async fn deprovision(data: &AppState, user_id: &str) -> mongodb::error::Result<()> {
+    data.mongodb.db.collection::<Document>("user_teams").delete_many(doc! { "user_id": user_id }).await?;
+    role_claims::revoke_sessions(&data.mongodb, user_id).await?;
+    data.chat_server.do_send(ForceDisconnect { user_id: user_id.to_string() });
+    info!("SCIM deactivated user {}", user_id);
+    Ok(())
+}
+
+fn foreign_email() -> HttpResponse {
+    scim_error(StatusCode::BAD_REQUEST, "The email must be in one of the organization's domains", Some("invalidValue"))
+}
+
+/// Applies a full user representation to an existing account.
+async fn replace_user(data: &AppState, org: &ScimOrg, existing: Document, input: ScimUser) -> HttpResponse {
+    let Ok(oid) = existing.get_object_id("_id") else {
+        return scim_error(StatusCode::INTERNAL_SERVER_ERROR, "User has no id", None);
+    };
+    let Some(email) = input.email() else {
+        return scim_error(StatusCode::BAD_REQUEST, "An email is required", Some("invalidValue"));
+    };
+    if !org.owns_email(&email) {
+        return foreign_email();
+    }
+    let username = input.user_name.trim().to_string();
+    if let Err(resp) = check_unique(data, &username, &email, Some(oid)).await {
+        return resp;
+    }
+    let name = input.name.unwrap_or_default();
+    let active = input.active.unwrap_or(true);
+    let update = doc! { "$set": {
+        "username": &username,
+        "email": &email,
+        "scim_external_id": input.external_id,
+        "given_name": name.given_name,
+        "family_name": name.family_name,
+        "display_name": input.display_name,
+        "active": active,
+        "updated_at": BsonDateTime::now(),
+    } };
+    if let Err(e) = users(data).update_one(doc! { "_id": oid }, update).await {
+        return db_error(e);
+    }
+    if !active && existing.get_bool("active").unwrap_or(true) {
+        if let Err(e) = deprovision(data, &oid.to_hex()).await {
+            return db_error(e);
+        }
+    }
+    match find_user(data, org, &oid.to_hex()).await {
+        Ok(user) => scim_response(StatusCode::OK, user_resource(&user)),
+        Err(resp) => resp,
+    }
+}
+
+/// Applies PATCH operations to a resource's JSON form. Paths may be nested
+/// (`name.givenName`); a value filter like `emails[type eq "work"].value` addresses
+/// the primary entry.
+fn apply_patch(resource: &mut Value, ops: Vec<PatchOp>) -> Result<(), HttpResponse> {
+    for op in ops {
+        let remove = match op.op.to_lowercase().as_str() {
+            "add" | "replace" => false,
+            "remove" => true,
+            other => return Err(scim_error(StatusCode::BAD_REQUEST, &format!("Unsupported op \"{}\"", other), Some("invalidSyntax"))),
+        };
+        let value = if remove { Value::Null } else { op.value.unwrap_or(Value::Null) };
+        let Some(path) = op.path.filter(|p| !p.is_empty()) else {
+            match value {
+                Value::Object(map) => {
+                    for (k, v) in map {
+                        set_path(resource, &k, v);
+                    }
+                    continue;
+                }
+                _ => return Err(scim_error(StatusCode::BAD_REQUEST, "An op without a path needs an object value", Some("invalidValue"))),
+            }
+        };
+        set_path(resource, &path, value);
+    }
+    Ok(())
+}
+
+fn set_path(resource: &mut Value, path: &str, value: Value) {
+    // `emails[...].value` and `emails[...]` replace the address list with one primary entry.
+    if let Some((attr, rest)) = path.split_once('[') {
+        let sub = rest.split_once("].").map(|(_, s)| s);
+        if attr.eq_ignore_ascii_case("emails") && sub.is_none_or(|s| s == "value") {
+            let address = match value {
+                Value::Object(o) => o.get("value").cloned().unwrap_or(Value::Null),
+                v => v,
+            };
+            resource["emails"] = if address.is_null() { json!([]) } else { json!([{ "value": address, "primary": true }]) };
+        }
+        return;
+    }
+    let mut target = resource;
+    let parts: Vec<&str> = path.split('.').collect();
+    for part in &parts[..parts.len() - 1] {
+        let key = find_key(target, part);
+        if !target[&key].is_object() {
+            target[&key] = Value::Object(Map::new());
+        }
+        target = &mut target[&key];
+    }
+    let last = parts[parts.len() - 1];
+    let key = find_key(target, last);
+    target[&key] = value;
+}
+
+/// Attribute names are case-insensitive in SCIM; reuse the existing spelling.
+fn find_key(target: &Value, name: &str) -> String {
+    target
+        .as_object()
+        .and_then(|o| o.keys().find(|k| k.eq_ignore_ascii_case(name)).cloned())
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// GET /scim/v2/ServiceProviderConfig
+pub async fn service_provider_config(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Err(resp) = authorize(&req, &data).await {
+        return resp;
+    }
+    scim_response(StatusCode::OK, json!({
+        "schemas": ["urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig"],
+        "patch": { "supported": true },
+        "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+        "filter": { "supported": true, "maxResults": MAX_COUNT },
+        "changePassword": { "supported": false },
+        "sort": { "supported": false },
+        "etag": { "supported": false },
+        "authenticationSchemes": [{ "type": "oauthbearertoken", "name": "Bearer token", "description": "The SSO connection's SCIM token" }],
+    }))
+}
+
+/// GET /scim/v2/Users?filter=userName eq "..."&startIndex=&count=
+pub async fn list_users(req: HttpRequest, data: web::Data<AppState>, query: web::Query<ListQuery>) -> impl Responder {
+    let org = match authorize(&req, &data).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    let fields = [("userName", "username"), ("externalId", "scim_external_id"), ("emails.value", "email"), ("emails", "email")];
+    let filter = match parse_filter(query.filter.as_deref(), &fields) {
+        Ok(f) => doc! { "$and": [f, org.users()] },
+        Err(resp) => return resp,
+    };
+    let start = query.start_index.unwrap_or(1).max(1);
+    let count = query.count.unwrap_or(100).min(MAX_COUNT);
+    let total = match users(&data).count_documents(filter.clone()).await {
+        Ok(n) => n,
+        Err(e) => return db_error(e),
+    };
+    let mut cursor = match users(&data).find(filter).sort(doc! { "_id": 1 }).skip(start - 1).limit(count as i64).await {
+        Ok(c) => c,
+        Err(e) => return db_error(e),
+    };
+    let mut resources = Vec::new();
+    while let Ok(true) = cursor.advance().await {
+        if let Ok(user) = cursor.deserialize_current() {
+            resources.push(user_resource(&user));
+        }
+    }
+    list_response(total, start, resources)
+}
+
+/// GET /scim/v2/Users/{id}
+pub async fn get_user(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>) -> impl Responder {
+    let org = match authorize(&req, &data).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    match find_user(&data, &org, &id).await {
+        Ok(user) => scim_response(StatusCode::OK, user_resource(&user)),
+        Err(resp) => resp,
+    }
+}
+
+/// POST /scim/v2/Users
+pub async fn create_user(req: HttpRequest, data: web::Data<AppState>, body: web::Bytes) -> impl Responder {
+    let org = match authorize(&req, &data).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    let input: ScimUser = match parse_body(&body) {
+        Ok(u) => u,
+        Err(resp) => return resp,
+    };
+    let username = input.user_name.trim().to_string();
+    if username.is_empty() {
+        return scim_error(StatusCode::BAD_REQUEST, "userName is required", Some("invalidValue"));
+    }
+    let Some(email) = input.email() else {
+        return scim_error(StatusCode::BAD_REQUEST, "An email is required", Some("invalidValue"));
+    };
+    if !org.owns_email(&email) {
+        return foreign_email();
+    }
+    if let Err(resp) = check_unique(&data, &username, &email, None).await {
+        return resp;
+    }
+    let name = input.name.unwrap_or_default();
+    // No usable password until the user sets one through the emailed link.
+    let user = doc! {
+        "username": &username,
+        "email": &email,
+        "password": "",
+        "must_reset_password": true,
+        "team_id": "",
+        "email_verified": false,
+        "created_at": BsonDateTime::now(),
+        "scim_external_id": input.external_id,
+        "given_name": name.given_name,
+        "family_name": name.family_name,
+        "display_name": input.display_name,
+        "active": input.active.unwrap_or(true),
+    };
+    let oid = match users(&data).insert_one(user).await {
+        Ok(res) => match res.inserted_id.as_object_id() {
+            Some(oid) => oid,
+            None => return scim_error(StatusCode::INTERNAL_SERVER_ERROR, "User has no id", None),
+        },
+        Err(e) => return db_error(e),
+    };
+    if input.active.unwrap_or(true) {
+        if let Err(e) = send_password_setup(&data, &oid.to_hex(), &email, "your organization").await {
+            error!("Error sending password setup email to {}: {}", email, e);
+        }
+    }
+    info!("SCIM provisioned user {} ({})", oid.to_hex(), username);
+    match find_user(&data, &org, &oid.to_hex()).await {
+        Ok(user) => scim_response(StatusCode::CREATED, user_resource(&user)),
+        Err(resp) => resp,
+    }
+}
+
+/// PUT /scim/v2/Users/{id}
+pub async fn replace_user_handler(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>, body: web::Bytes) -> impl Responder {
+    let org = match authorize(&req, &data).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    let input: ScimUser = match parse_body(&body) {
+        Ok(u) => u,
+        Err(resp) => return resp,
+    };
+    match find_user(&data, &org, &id).await {
+        Ok(existing) => replace_user(&data, &org, existing, input).await,
+        Err(resp) => resp,
+    }
+}
+
+/// PATCH /scim/v2/Users/{id}
+pub async fn patch_user(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>, body: web::Bytes) -> impl Responder {
+    let org = match authorize(&req, &data).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    let patch: PatchRequest = match parse_body(&body) {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+    let existing = match find_user(&data, &org, &id).await {
+        Ok(u) => u,
+        Err(resp) => return resp,
+    };
+    let mut resource = user_resource(&existing);
+    if let Err(resp) = apply_patch(&mut resource, patch.operations) {
+        return resp;
+    }
+    let input: ScimUser = match serde_json::from_value(resource) {
+        Ok(u) => u,
+        Err(e) => return scim_error(StatusCode::BAD_REQUEST, &format!("Invalid result: {}", e), Some("invalidValue")),
+    };
+    replace_user(&data, &org, existing, input).await
+}
+
+/// DELETE /scim/v2/Users/{id}
+/// Deactivates the account; its data is kept.
+pub async fn delete_user(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>) -> impl Responder {
+    let org = match authorize(&req, &data).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    let user = match find_user(&data, &org, &id).await {
+        Ok(u) => u,
+        Err(resp) => return resp,
+    };
+    let Ok(oid) = user.get_object_id("_id") else {
+        return scim_error(StatusCode::INTERNAL_SERVER_ERROR, "User has no id", None);
+    };
+    let update = doc! { "$set": { "active": false, "updated_at": BsonDateTime::now() } };
+    if let Err(e) = users(&data).update_one(doc! { "_id": oid }, update).await {
+        return db_error(e);
+    }
+    if let Err(e) = deprovision(&data, &oid.to_hex()).await {
+        return db_error(e);
+    }
+    HttpResponse::NoContent().finish()
+}
+
+/// Adds and removes team members; unknown and inactive users are rejected, and so is
+/// removing the owner. A team without owner gets its first member as owner.
+async fn change_members(data: &AppState, org: &ScimOrg, team: &Document, add: &[String], remove: &[String]) -> Result<(), HttpResponse> {
+    let team_id = team.get_str("team_id").unwrap_or_default();
+    let user_teams = data.mongodb.db.collection::<UserTeam>("user_teams");
+    let owner = team.get_str("owner_id").unwrap_or_default();
+    if !owner.is_empty() && remove.iter().any(|r| r == owner) {
+        return Err(scim_error(
+            StatusCode::BAD_REQUEST,
+            &format!("Member {} owns the team and cannot be removed; transfer ownership first", owner),
+            Some("mutability"),
+        ));
+    }
+    let mut has_owner = !owner.is_empty();
+    for user_id in add {
+        let user = find_user(data, org, user_id).await.map_err(|_| {
+            scim_error(StatusCode::BAD_REQUEST, &format!("Unknown member {}", user_id), Some("invalidValue"))
+        })?;
+        if !user.get_bool("active").unwrap_or(true) {
+            return Err(scim_error(StatusCode::BAD_REQUEST, &format!("Member {} is deactivated", user_id), Some("invalidValue")));
+        }
+        if user_teams.find_one(doc! { "team_id": team_id, "user_id": user_id }).await.map_err(db_error)?.is_some() {
+            continue;
+        }
+        let role = if has_owner { "member" } else { "admin" };
+        let membership = UserTeam { user_id: user_id.clone(), team_id: team_id.to_string(), role: role.to_string(), joined_at: Utc::now() };
+        user_teams.insert_one(membership).await.map_err(db_error)?;
+        role_claims::bump(&data.mongodb, std::slice::from_ref(user_id)).await;
+        if !has_owner {
+            teams(data).update_one(doc! { "team_id": team_id }, doc! { "$set": { "owner_id": user_id } }).await.map_err(db_error)?;
+            has_owner = true;
+        }
+    }
+    if !remove.is_empty() {
+        user_teams.delete_many(doc! { "team_id": team_id, "user_id": { "$in": remove } }).await.map_err(db_error)?;
+        role_claims::bump(&data.mongodb, remove).await;
+    }
+    Ok(())
+}
+
+async fn current_member_ids(data: &AppState, team_id: &str) -> Result<Vec<String>, HttpResponse> {
+    let ids = data.mongodb.db.collection::<Document>("user_teams").distinct("user_id", doc! { "team_id": team_id }).await.map_err(db_error)?;
+    Ok(ids.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+}
+
+async fn group_response(data: &AppState, org: &ScimOrg, team_id: &str, status: StatusCode) -> HttpResponse {
+    let team = match find_team(data, org, team_id).await {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    match group_resource(data, &team).await {
+        Ok(group) => scim_response(status, group),
+        Err(e) => db_error(e),
+    }
+}
+
+/// GET /scim/v2/Groups?filter=displayName eq "..."
+pub async fn list_groups(req: HttpRequest, data: web::Data<AppState>, query: web::Query<ListQuery>) -> impl Responder {
+    let org = match authorize(&req, &data).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    let filter = match parse_filter(query.filter.as_deref(), &[("displayName", "name"), ("externalId", "scim_external_id")]) {
+        Ok(f) => doc! { "$and": [f, org.teams()] },
+        Err(resp) => return resp,
+    };
+    let start = query.start_index.unwrap_or(1).max(1);
+    let count = query.count.unwrap_or(100).min(MAX_COUNT);
+    let total = match teams(&data).count_documents(filter.clone()).await {
+        Ok(n) => n,
+        Err(e) => return db_error(e),
+    };
+    let mut cursor = match teams(&data).find(filter).sort(doc! { "team_id": 1 }).skip(start - 1).limit(count as i64).await {
+        Ok(c) => c,
+        Err(e) => return db_error(e),
+    };
+    let mut resources = Vec::new();
+    while let Ok(true) = cursor.advance().await {
+        let Ok(team) = cursor.deserialize_current() else { continue };
+        match group_resource(&data, &team).await {
+            Ok(group) => resources.push(group),
+            Err(e) => return db_error(e),
+        }
+    }
+    list_response(total, start, resources)
+}
+
+/// GET /scim/v2/Groups/{id}
+pub async fn get_group(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>) -> impl Responder {
+    let org = match authorize(&req, &data).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    group_response(&data, &org, &id, StatusCode::OK).await
+}
+
+/// POST /scim/v2/Groups
+pub async fn create_group(req: HttpRequest, data: web::Data<AppState>, body: web::Bytes) -> impl Responder {
+    let org = match authorize(&req, &data).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    let input: ScimGroup = match parse_body(&body) {
+        Ok(g) => g,
+        Err(resp) => return resp,
+    };
+    let name = input.display_name.trim();
+    if name.is_empty() {
+        return scim_error(StatusCode::BAD_REQUEST, "displayName is required", Some("invalidValue"));
+    }
+    let team_id = Uuid::new_v4().to_string();
+    let team = doc! {
+        "team_id": &team_id,
+        "name": name,
+        "owner_id": "",
+        "description": None::<String>,
+        "created_at": Utc::now().to_rfc3339(),
+        "scim_external_id": input.external_id,
+        "scim_connection_id": &org.connection_id,
+    };
+    if let Err(e) = teams(&data).insert_one(&team).await {
+        return db_error(e);
+    }
+    let add: Vec<String> = input.members.into_iter().map(|m| m.value).collect();
+    if let Err(resp) = change_members(&data, &org, &team, &add, &[]).await {
+        // A member was refused: take the new team back out instead of leaving it ownerless.
+        let memberships = data.mongodb.db.collection::<Document>("user_teams");
+        if let Err(e) = memberships.delete_many(doc! { "team_id": &team_id }).await {
+            error!("Error removing memberships of refused SCIM team {}: {}", team_id, e);
+        }
+        if let Err(e) = teams(&data).delete_one(doc! { "team_id": &team_id }).await {
+            error!("Error removing refused SCIM team {}: {}", team_id, e);
+        }
+        role_claims::bump(&data.mongodb, &add).await;
+        return resp;
+    }
+    info!("SCIM created team {} ({})", team_id, name);
+    group_response(&data, &org, &team_id, StatusCode::CREATED).await
+}
+
+/// PUT /scim/v2/Groups/{id}
+pub async fn replace_group(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>, body: web::Bytes) -> impl Responder {
+    let org = match authorize(&req, &data).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    let input: ScimGroup = match parse_body(&body) {
+        Ok(g) => g,
+        Err(resp) => return resp,
+    };
+    let team = match find_team(&data, &org, &id).await {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    let name = input.display_name.trim();
+    if name.is_empty() {
+        return scim_error(StatusCode::BAD_REQUEST, "displayName is required", Some("invalidValue"));
+    }
+    let update = doc! { "$set": { "name": name, "scim_external_id": input.external_id } };
+    if let Err(e) = teams(&data).update_one(doc! { "team_id": &*id }, update).await {
+        return db_error(e);
+    }
+    let wanted: Vec<String> = input.members.into_iter().map(|m| m.value).collect();
+    let current = match current_member_ids(&data, &id).await {
+        Ok(c) => c,
+        Err(resp) => return resp,
+    };
+    let remove: Vec<String> = current.iter().filter(|c| !wanted.contains(c)).cloned().collect();
+    if let Err(resp) = change_members(&data, &org, &team, &wanted, &remove).await {
+        return resp;
+    }
+    group_response(&data, &org, &id, StatusCode::OK).await
+}
+
+/// PATCH /scim/v2/Groups/{id}
+/// Supports renaming and `members` add/remove/replace, including
+/// `members[value eq "..."]` removals.
+pub async fn patch_group(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>, body: web::Bytes) -> impl Responder {
+    let org = match authorize(&req, &data).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    let patch: PatchRequest = match parse_body(&body) {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+    let team = match find_team(&data, &org, &id).await {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    static MEMBER_FILTER: OnceLock<Regex> = OnceLock::new();
+    let member_filter = MEMBER_FILTER.get_or_init(|| Regex::new(r#"^members\[value eq "([^"]+)"\]$"#).expect("valid member filter regex"));
+    let refs = |v: &Option<Value>| -> Vec<String> {
+        match v {
+            Some(Value::Array(items)) => items.iter().filter_map(|i| i.get("value").and_then(Value::as_str).map(str::to_string)).collect(),
+            Some(Value::Object(o)) => o.get("value").and_then(Value::as_str).map(str::to_string).into_iter().collect(),
+            _ => Vec::new(),
+        }
+    };
+    let (mut add, mut remove) = (Vec::new(), Vec::new());
+    let mut rename = None;
+    for op in &patch.operations {
+        let kind = op.op.to_lowercase();
+        let path = op.path.as_deref().unwrap_or("");
+        match (kind.as_str(), path) {
+            ("add", p) if p.eq_ignore_ascii_case("members") => add.extend(refs(&op.value)),
+            ("remove", p) if p.eq_ignore_ascii_case("members") => {
+                let listed = refs(&op.value);
+                if listed.is_empty() {
+                    match current_member_ids(&data, &id).await {
+                        Ok(all) => remove.extend(all),
+                        Err(resp) => return resp,
+                    }
+                } else {
+                    remove.extend(listed);
+                }
+            }
+            ("remove", p) if member_filter.is_match(p) => {
+                remove.extend(member_filter.captures(p).map(|c| c[1].to_string()));
+            }
+            ("replace", p) if p.eq_ignore_ascii_case("members") => {
+                let wanted = refs(&op.value);
+                match current_member_ids(&data, &id).await {
+                    Ok(all) => remove.extend(all.into_iter().filter(|m| !wanted.contains(m))),
+                    Err(resp) => return resp,
+                }
+                add.extend(wanted);
+            }
+            ("replace" | "add", p) if p.eq_ignore_ascii_case("displayName") => {
+                rename = op.value.as_ref().and_then(Value::as_str).map(str::to_string);
+            }
+            ("replace" | "add", "") => {
+                rename = op.value.as_ref().and_then(|v| v.get("displayName")).and_then(Value::as_str).map(str::to_string);
+            }
+            _ => {
+                return scim_error(StatusCode::BAD_REQUEST, &format!("Unsupported operation {} {}", op.op, path), Some("invalidPath"));
+            }
+        }
+    }
+    if let Some(name) = rename.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()) {
+        if let Err(e) = teams(&data).update_one(doc! { "team_id": &*id }, doc! { "$set": { "name": name } }).await {
+            return db_error(e);
+        }
+    }
+    add.retain(|a| !remove.contains(a));
+    if let Err(resp) = change_members(&data, &org, &team, &add, &remove).await {
+        return resp;
+    }
+    group_response(&data, &org, &id, StatusCode::OK).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: [(&str, &str); 2] = [("userName", "username"), ("emails.value", "email")];
+
+    fn patch(op: &str, path: Option<&str>, value: Value) -> PatchOp {
+        PatchOp { op: op.to_string(), path: path.map(str::to_string), value: Some(value) }
+    }
+
+    #[test]
+    fn filters_match_one_attribute_exactly() {
+        let filter = parse_filter(Some(r#"UserName eq "a.b+c@example.com""#), &FIELDS).unwrap();
+        assert_eq!(filter, doc! { "username": { "$regex": r"^a\.b\+c@example\.com$", "$options": "i" } });
+        let quoted = parse_filter(Some(r#"emails.value EQ "say \"hi\"""#), &FIELDS).unwrap();
+        assert_eq!(quoted, doc! { "email": { "$regex": r#"^say "hi"$"#, "$options": "i" } });
+        assert_eq!(parse_filter(Some("  "), &FIELDS).unwrap(), Document::new());
+        assert_eq!(parse_filter(None, &FIELDS).unwrap(), Document::new());
+    }
+
+    #[test]
+    fn other_filters_are_refused() {
+        for filter in [r#"userName co "a""#, r#"displayName eq "a""#, r#"userName eq "a" or userName eq "b""#, "userName eq a"] {
+            let refused = parse_filter(Some(filter), &FIELDS).unwrap_err();
+            assert_eq!(refused.status(), StatusCode::BAD_REQUEST, "{}", filter);
+        }
+    }
+
+    #[test]
+    fn patches_set_nested_paths_case_insensitively() {
+        let mut user = json!({ "userName": "ann", "name": { "givenName": "Ann" }, "active": true });
+        let ops = vec![
+            patch("Replace", Some("name.GIVENNAME"), json!("Anna")),
+            patch("add", Some("name.familyName"), json!("Lee")),
+            patch("replace", None, json!({ "Active": false })),
+        ];
+        apply_patch(&mut user, ops).unwrap();
+        assert_eq!(user, json!({ "userName": "ann", "name": { "givenName": "Anna", "familyName": "Lee" }, "active": false }));
+    }
+
+    #[test]
+    fn patches_replace_the_primary_email() {
+        let mut user = json!({ "emails": [{ "value": "old@example.com", "primary": true }, { "value": "other@example.com" }] });
+        apply_patch(&mut user, vec![patch("replace", Some(r#"emails[type eq "work"].value"#), json!("new@example.com"))]).unwrap();
+        assert_eq!(user["emails"], json!([{ "value": "new@example.com", "primary": true }]));
+        apply_patch(&mut user, vec![PatchOp { op: "remove".to_string(), path: Some("emails[]".to_string()), value: None }]).unwrap();
+        assert_eq!(user["emails"], json!([]));
+    }
+
+    #[test]
+    fn bad_patches_are_refused() {
+        let mut user = json!({});
+        let unsupported = apply_patch(&mut user, vec![patch("move", Some("userName"), json!("x"))]).unwrap_err();
+        assert_eq!(unsupported.status(), StatusCode::BAD_REQUEST);
+        let pathless = apply_patch(&mut user, vec![patch("replace", None, json!("x"))]).unwrap_err();
+        assert_eq!(pathless.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn active_accepts_string_booleans_only() {
+        let active = |body: Value| serde_json::from_value::<ScimUser>(json!({ "userName": "ann", "active": body })).map(|u| u.active);
+        assert_eq!(active(json!(false)).unwrap(), Some(false));
+        assert_eq!(active(json!("True")).unwrap(), Some(true));
+        assert_eq!(active(json!("FALSE")).unwrap(), Some(false));
+        assert_eq!(active(Value::Null).unwrap(), None);
+        assert!(active(json!("no")).is_err());
+    }
+}
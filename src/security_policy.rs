@@ -0,0 +1,639 @@
+// src/security_policy.rs
+//
+// Optional per-team security policies, enforced by the Authentication middleware on
+// the team's routes: those naming the team (`/teams/{team_id}/...`) and those naming
+// one of its projects, boards or tickets. Routes naming no team (chats, messages,
+// attachments, the knowledge base, calendar, sync, search, the WebSocket...) are
+// held to the policies of all of the caller's teams, so the strictest applies.
+//
+// - `allowed_cidrs`: requests must come from one of these networks
+// - `reauth_hours`: tokens older than this are refused and the user must log in again
+// - `device_confirmation`: a device the user has not used before is blocked until
+//   they open the confirmation link emailed to them
+//
+// Policies are cached per team for a minute, so changes apply within that time.
+// Devices are identified by a random key the server hands out on first contact, in
+// the `X-Device-Id` response header and the `taskline_device` cookie; clients send
+// it back the same way. A key counts once it is confirmed from the emailed link, so
+// copying someone's headers does not make a trusted device. Confirmed devices are
+// cached for TRUSTED_DEVICE_TTL, or until the user's role version moves on (see
+// role_claims.rs), which revoking a device does. Client addresses come
+// from the connection, or from `X-Forwarded-For` when the connection is one of
+// TRUSTED_PROXIES (see client_ip).
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use mongodb::bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::dashboard_report::html_escape;
+use crate::encryption::lookup_hash;
+use crate::mailer::send_email;
+use crate::role_claims;
+use crate::tenancy::{project_team_id, ProjectScope};
+
+pub const DEVICE_HEADER: &str = "X-Device-Id";
+const DEVICE_COOKIE: &str = "taskline_device";
+const POLICY_CACHE_TTL: Duration = Duration::from_secs(60);
+const TRUSTED_DEVICE_TTL: Duration = Duration::from_secs(10 * 60);
+/// A new confirmation email is sent at most this often per device.
+const CONFIRMATION_RESEND_MINUTES: i64 = 15;
+const CONFIRMATION_VALID_HOURS: i64 = 24;
+const MAX_CIDRS: usize = 100;
+const MAX_REAUTH_HOURS: u32 = 24 * 30;
+/// Unconfirmed devices a user can start within the resend window; more are refused
+/// without handing out a key.
+const MAX_PENDING_DEVICES: u64 = 5;
+
+/// team id -> when the policy was read, and the policy if the team has one
+type PolicyCache = HashMap<String, (Instant, Option<Arc<TeamPolicy>>)>;
+
+static POLICIES: OnceLock<Mutex<PolicyCache>> = OnceLock::new();
+/// device hash -> when the device was found confirmed, and the user's role version then
+static TRUSTED: OnceLock<Mutex<HashMap<String, (Instant, i64)>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityPolicy {
+    pub team_id: String,
+    /// Networks in CIDR notation, e.g. `203.0.113.0/24`; empty allows any address
+    #[serde(default)]
+    pub allowed_cidrs: Vec<String>,
+    /// Maximum token age in hours
+    pub reauth_hours: Option<u32>,
+    #[serde(default)]
+    pub device_confirmation: bool,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SecurityPolicyRequest {
+    #[serde(default)]
+    pub allowed_cidrs: Vec<String>,
+    pub reauth_hours: Option<u32>,
+    #[serde(default)]
+    pub device_confirmation: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrustedDevice {
+    pub device_id: String,
+    pub user_id: String,
+    /// `lookup_hash` of the user id and the device key handed to the client
+    pub device_hash: String,
+    /// User-Agent of the first request
+    pub label: String,
+    pub confirmed: bool,
+    /// Emailed confirmation token; removed once confirmed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// When the confirmation was last emailed
+    pub requested_at: BsonDateTime,
+    pub confirmed_at: Option<BsonDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceView {
+    pub device_id: String,
+    pub label: String,
+    pub confirmed: bool,
+    pub requested_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+/// When the request's token was issued; set by the middleware on authentication.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenIssuedAt(pub i64);
+
+#[derive(Debug, Clone, Copy)]
+struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    fn parse(s: &str) -> Option<Cidr> {
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((a, p)) => (a.parse::<IpAddr>().ok()?, p.parse::<u8>().ok()?),
+            None => {
+                let a = s.trim().parse::<IpAddr>().ok()?;
+                (a, if a.is_ipv4() { 32 } else { 128 })
+            }
+        };
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        (prefix <= max).then_some(Cidr { network: addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A team's policy with its networks parsed.
+#[derive(Debug)]
+struct TeamPolicy {
+    /// Empty allows any address
+    allowed: Vec<Cidr>,
+    reauth_hours: Option<u32>,
+    device_confirmation: bool,
+}
+
+fn policies(db: &MongoDB) -> mongodb::Collection<SecurityPolicy> {
+    db.db.collection::<SecurityPolicy>("team_security_policies")
+}
+
+fn devices(db: &MongoDB) -> mongodb::Collection<TrustedDevice> {
+    db.db.collection::<TrustedDevice>("trusted_devices")
+}
+
+fn invalidate_policy(team_id: &str) {
+    POLICIES.get_or_init(Default::default).lock().unwrap().remove(team_id);
+}
+
+async fn team_policy(db: &MongoDB, team_id: &str) -> mongodb::error::Result<Option<Arc<TeamPolicy>>> {
+    let cache = POLICIES.get_or_init(Default::default);
+    if let Some((at, policy)) = cache.lock().unwrap().get(team_id) {
+        if at.elapsed() < POLICY_CACHE_TTL {
+            return Ok(policy.clone());
+        }
+    }
+    let policy = policies(db).find_one(doc! { "team_id": team_id }).await?.map(|p| {
+        Arc::new(TeamPolicy {
+            allowed: p.allowed_cidrs.iter().filter_map(|c| Cidr::parse(c)).collect(),
+            reauth_hours: p.reauth_hours,
+            device_confirmation: p.device_confirmation,
+        })
+    });
+    cache.lock().unwrap().insert(team_id.to_string(), (Instant::now(), policy.clone()));
+    Ok(policy)
+}
+
+/// The team a request is about, read from its path: team routes name it, project,
+/// board and ticket routes name something it owns.
+async fn request_team(db: &MongoDB, path: &str) -> mongodb::error::Result<Option<String>> {
+    let path = path.strip_prefix(crate::api::V1_PREFIX).unwrap_or(path);
+    let mut segments = path.trim_start_matches('/').split('/');
+    let (Some(kind), Some(id)) = (segments.next(), segments.next()) else {
+        return Ok(None);
+    };
+    let scope = match (kind, id) {
+        ("teams" | "team-data", team_id) => return Ok(Some(team_id.to_string())),
+        ("ai", "teams") => return Ok(segments.next().map(str::to_string)),
+        ("projects", project_id) => return Ok(project_team_id(db, project_id).await),
+        ("boards", board_id) => ProjectScope::of_board(db, board_id).await?,
+        ("tickets", ticket_id) => ProjectScope::of_ticket(db, ticket_id).await?,
+        _ => None,
+    };
+    Ok(scope.map(|s| s.team_id().to_string()))
+}
+
+/// The caller's address. A connection from one of TRUSTED_PROXIES is taken to carry
+/// the client address in `X-Forwarded-For`: every proxy appends the address it was
+/// reached from, so the list is read from the right and the first hop that is not
+/// itself a trusted proxy is the client. Entries further left come from the client
+/// and are ignored.
+pub(crate) fn client_ip(req: &HttpRequest, data: &AppState) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let proxies: Vec<Cidr> = data.config.trusted_proxies.iter().filter_map(|p| Cidr::parse(p)).collect();
+    let trusted = |ip: IpAddr| proxies.iter().any(|c| c.contains(ip));
+    let hops: Vec<&str> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .map(str::trim)
+        .collect();
+    let mut client = peer;
+    for hop in hops.iter().rev() {
+        if !trusted(client) {
+            break;
+        }
+        let hop = hop.trim_start_matches('[').split(']').next().unwrap_or(hop);
+        match hop.parse::<IpAddr>().ok().or_else(|| hop.parse::<SocketAddr>().ok().map(|a| a.ip())) {
+            Some(ip) => client = ip,
+            None => break,
+        }
+    }
+    Some(client)
+}
+
+fn device_label(req: &HttpRequest) -> String {
+    let agent = req.headers().get("User-Agent").and_then(|h| h.to_str().ok()).map(str::trim).filter(|v| !v.is_empty());
+    agent.unwrap_or("unknown device").chars().take(200).collect()
+}
+
+/// The device key the client sent back, if any.
+fn device_key(req: &HttpRequest) -> Option<String> {
+    let header = req.headers().get(DEVICE_HEADER).and_then(|h| h.to_str().ok()).map(str::trim);
+    header
+        .filter(|k| !k.is_empty())
+        .map(str::to_string)
+        .or_else(|| req.cookie(DEVICE_COOKIE).map(|c| c.value().to_string()))
+}
+
+/// Requests that must work from any network and device: the emailed confirmation
+/// link is how a new device gets trusted.
+fn exempt(path: &str) -> bool {
+    let path = path.strip_prefix(crate::api::V1_PREFIX).unwrap_or(path);
+    path.trim_start_matches('/').starts_with("auth/devices/confirm/")
+}
+
+/// Teams whose policies a request is held to: the team its path names when the
+/// caller belongs to it, or every team of the caller when the path names none.
+async fn policy_teams(db: &MongoDB, auth: &AuthContext, path: &str) -> mongodb::error::Result<Vec<String>> {
+    match request_team(db, path).await? {
+        Some(team_id) if auth.is_team_member(&team_id).await => Ok(vec![team_id]),
+        // Callers outside the team are left to the route's own checks.
+        Some(_) => Ok(Vec::new()),
+        None => auth.team_ids().await,
+    }
+}
+
+/// Checks a request against the policies of the teams it concerns.
+pub(crate) async fn enforce(data: &AppState, req: &HttpRequest, user_id: &str, issued_at: i64) -> Result<(), HttpResponse> {
+    let internal = |e: mongodb::error::Error| {
+        error!("Error fetching the security policy for {} {}: {}", user_id, req.path(), e);
+        HttpResponse::InternalServerError().body("Error checking security policy")
+    };
+    let auth = req.extensions().get::<AuthContext>().cloned();
+    let Some(auth) = auth.filter(|_| !exempt(req.path())) else {
+        return Ok(());
+    };
+    let mut device_confirmation = false;
+    for team_id in policy_teams(&data.mongodb, &auth, req.path()).await.map_err(internal)? {
+        let Some(policy) = team_policy(&data.mongodb, &team_id).await.map_err(internal)? else {
+            continue;
+        };
+        if !policy.allowed.is_empty() && !client_ip(req, data).is_some_and(|ip| policy.allowed.iter().any(|c| c.contains(ip))) {
+            return Err(HttpResponse::Forbidden().body("Access from this network is not allowed by your team's security policy"));
+        }
+        if let Some(hours) = policy.reauth_hours {
+            if Utc::now().timestamp() - issued_at > hours as i64 * 3600 {
+                return Err(HttpResponse::Unauthorized().body("Your team requires you to log in again"));
+            }
+        }
+        device_confirmation |= policy.device_confirmation;
+    }
+    if device_confirmation {
+        check_device(data, req, user_id).await?;
+    }
+    Ok(())
+}
+
+/// 403 for an unconfirmed device, handing out `new_key` when one was issued.
+fn blocked(data: &AppState, new_key: Option<&str>) -> HttpResponse {
+    let mut resp = HttpResponse::Forbidden();
+    if let Some(key) = new_key {
+        let cookie = Cookie::build(DEVICE_COOKIE, key.to_string())
+            .path("/")
+            .http_only(true)
+            .secure(data.config.cookie_secure)
+            .same_site(crate::auth::same_site(&data.config))
+            .max_age(CookieDuration::days(365))
+            .finish();
+        resp.insert_header((DEVICE_HEADER, key)).cookie(cookie);
+    }
+    resp.body("New device: confirm it with the link sent to your email")
+}
+
+async fn check_device(data: &AppState, req: &HttpRequest, user_id: &str) -> Result<(), HttpResponse> {
+    let internal = |e: mongodb::error::Error| {
+        error!("Error checking device of {}: {}", user_id, e);
+        HttpResponse::InternalServerError().body("Error checking security policy")
+    };
+    let resend_after = BsonDateTime::from_millis(Utc::now().timestamp_millis() - CONFIRMATION_RESEND_MINUTES * 60_000);
+    let label = device_label(req);
+    if let Some(key) = device_key(req) {
+        let device_hash = lookup_hash(&format!("{}:{}", user_id, key));
+        let trusted = TRUSTED.get_or_init(Default::default);
+        let version = role_claims::known_version(user_id);
+        let cached = trusted.lock().unwrap().get(&device_hash).copied();
+        if cached.is_some_and(|(at, seen)| at.elapsed() < TRUSTED_DEVICE_TTL && seen >= version) {
+            return Ok(());
+        }
+        match devices(&data.mongodb).find_one(doc! { "device_hash": &device_hash }).await.map_err(internal)? {
+            Some(device) if device.confirmed => {
+                trusted.lock().unwrap().insert(device_hash, (Instant::now(), version));
+                return Ok(());
+            }
+            Some(device) => {
+                // Pending: email a fresh link once the last one is old enough.
+                if device.requested_at > resend_after {
+                    return Err(blocked(data, None));
+                }
+                let token = Uuid::new_v4().simple().to_string();
+                let update = doc! { "$set": { "token": &token, "requested_at": BsonDateTime::now() } };
+                devices(&data.mongodb)
+                    .update_one(doc! { "device_hash": &device_hash, "confirmed": false }, update)
+                    .await
+                    .map_err(internal)?;
+                email_confirmation(data, user_id, &device.label, &token).await.map_err(internal)?;
+                return Err(blocked(data, None));
+            }
+            // Unknown or revoked: the device starts over with a new key.
+            None => {}
+        }
+    }
+
+    let pending = doc! { "user_id": user_id, "confirmed": false, "requested_at": { "$gt": resend_after } };
+    let recent = devices(&data.mongodb).count_documents(pending).await.map_err(internal)?;
+    if recent >= MAX_PENDING_DEVICES {
+        return Err(blocked(data, None));
+    }
+    let key = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let token = Uuid::new_v4().simple().to_string();
+    let device = TrustedDevice {
+        device_id: Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        device_hash: lookup_hash(&format!("{}:{}", user_id, key)),
+        label,
+        confirmed: false,
+        token: Some(token.clone()),
+        requested_at: BsonDateTime::now(),
+        confirmed_at: None,
+    };
+    devices(&data.mongodb).insert_one(&device).await.map_err(internal)?;
+    // One email per resend window; further devices get theirs when they come back.
+    if recent == 0 {
+        email_confirmation(data, user_id, &device.label, &token).await.map_err(internal)?;
+    }
+    Err(blocked(data, Some(&key)))
+}
+
+async fn email_confirmation(data: &AppState, user_id: &str, label: &str, token: &str) -> mongodb::error::Result<()> {
+    let email = match ObjectId::parse_str(user_id) {
+        Ok(oid) => data
+            .mongodb
+            .db
+            .collection::<Document>("users")
+            .find_one(doc! { "_id": oid })
+            .await?
+            .and_then(|u| u.get_str("email").ok().map(str::to_string)),
+        Err(_) => None,
+    };
+    if let Some(email) = email {
+        let link = format!("{}{}/auth/devices/confirm/{}", data.config.app_base_url.trim_end_matches('/'), crate::api::V1_PREFIX, token);
+        let body = format!(
+            "Your Taskline account was used from a new device:\n{}\n\nIf this was you, confirm the device by opening:\n{}\n\nThe link is valid for {} hours. If it was not you, change your password.\n",
+            label, link, CONFIRMATION_VALID_HOURS
+        );
+        if let Err(e) = send_email(data, &email, "Confirm your new device", &body).await {
+            error!("Error sending device confirmation to {}: {}", email, e);
+        }
+    }
+    Ok(())
+}
+
+/// The pending device a confirmation token belongs to, while the link is valid.
+async fn pending_device(data: &AppState, token: &str) -> Result<TrustedDevice, HttpResponse> {
+    let device = match devices(&data.mongodb).find_one(doc! { "token": token }).await {
+        Ok(Some(d)) => d,
+        Ok(None) => return Err(HttpResponse::NotFound().body("Invalid or already used confirmation link")),
+        Err(e) => {
+            error!("Error fetching device: {}", e);
+            return Err(HttpResponse::InternalServerError().body("Error confirming device"));
+        }
+    };
+    let valid_after = Utc::now().timestamp_millis() - CONFIRMATION_VALID_HOURS * 3_600_000;
+    if device.requested_at.timestamp_millis() < valid_after {
+        return Err(HttpResponse::Gone().body("Confirmation link has expired"));
+    }
+    Ok(device)
+}
+
+/// GET /auth/devices/confirm/{token}
+/// The emailed link. Only shows the device and a button that POSTs the confirmation,
+/// so mail scanners following the link confirm nothing.
+pub async fn confirm_device_page(data: web::Data<AppState>, token: web::Path<String>) -> impl Responder {
+    let device = match pending_device(&data, &token).await {
+        Ok(d) => d,
+        Err(resp) => return resp,
+    };
+    let page = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Confirm your device</title></head><body>\
+         <p>Confirm the new device <strong>{}</strong>?</p>\
+         <form method=\"post\"><button type=\"submit\">Confirm device</button></form></body></html>",
+        html_escape(&device.label)
+    );
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header(("Cache-Control", "no-store"))
+        .insert_header(("Referrer-Policy", "no-referrer"))
+        .body(page)
+}
+
+/// POST /auth/devices/confirm/{token}
+pub async fn confirm_device(data: web::Data<AppState>, token: web::Path<String>) -> impl Responder {
+    let device = match pending_device(&data, &token).await {
+        Ok(d) => d,
+        Err(resp) => return resp,
+    };
+    let update = doc! { "$set": { "confirmed": true, "confirmed_at": BsonDateTime::now() }, "$unset": { "token": "" } };
+    match devices(&data.mongodb).update_one(doc! { "token": &*token }, update).await {
+        Ok(r) if r.modified_count == 0 => HttpResponse::NotFound().body("Invalid or already used confirmation link"),
+        Ok(_) => {
+            info!("User {} confirmed device {}", device.user_id, device.device_id);
+            HttpResponse::Ok().body("Device confirmed")
+        }
+        Err(e) => {
+            error!("Error confirming device: {}", e);
+            HttpResponse::InternalServerError().body("Error confirming device")
+        }
+    }
+}
+
+/// GET /users/me/devices
+pub async fn list_devices(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    let mut cursor = match devices(&data.mongodb).find(doc! { "user_id": auth.user_id() }).sort(doc! { "requested_at": -1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching devices: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching devices");
+        }
+    };
+    let mut out = Vec::new();
+    while let Ok(true) = cursor.advance().await {
+        if let Ok(d) = cursor.deserialize_current() {
+            out.push(DeviceView {
+                device_id: d.device_id,
+                label: d.label,
+                confirmed: d.confirmed,
+                requested_at: d.requested_at.to_chrono(),
+                confirmed_at: d.confirmed_at.map(|t| t.to_chrono()),
+            });
+        }
+    }
+    HttpResponse::Ok().json(out)
+}
+
+/// DELETE /users/me/devices/{device_id}
+/// The device has to be confirmed again on its next use. Other instances stop
+/// trusting it once they see the bumped role version.
+pub async fn revoke_device(auth: AuthContext, data: web::Data<AppState>, device_id: web::Path<String>) -> impl Responder {
+    match devices(&data.mongodb).delete_one(doc! { "device_id": &*device_id, "user_id": auth.user_id() }).await {
+        Ok(r) if r.deleted_count == 0 => HttpResponse::NotFound().body("Device not found"),
+        Ok(_) => {
+            role_claims::bump(&data.mongodb, &[auth.user_id().to_string()]).await;
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => {
+            error!("Error revoking device: {}", e);
+            HttpResponse::InternalServerError().body("Error revoking device")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/security-policy
+pub async fn get_security_policy(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().body("You are not a member of this team");
+    }
+    match policies(&data.mongodb).find_one(doc! { "team_id": &*team_id }).await {
+        Ok(Some(p)) => HttpResponse::Ok().json(p),
+        Ok(None) => HttpResponse::NotFound().body("The team has no security policy"),
+        Err(e) => {
+            error!("Error fetching security policy: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching security policy")
+        }
+    }
+}
+
+/// PUT /teams/{team_id}/security-policy
+/// Refuses an allow-list that would lock out the admin making the change.
+pub async fn set_security_policy(
+    req: HttpRequest,
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<SecurityPolicyRequest>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Unauthorized().body("Only team admins can change the security policy");
+    }
+    let body = payload.into_inner();
+    if body.allowed_cidrs.len() > MAX_CIDRS {
+        return HttpResponse::BadRequest().body(format!("At most {} networks can be allowed", MAX_CIDRS));
+    }
+    let mut cidrs = Vec::with_capacity(body.allowed_cidrs.len());
+    for entry in &body.allowed_cidrs {
+        match Cidr::parse(entry) {
+            Some(c) => cidrs.push(c),
+            None => return HttpResponse::BadRequest().body(format!("Invalid network \"{}\"", entry)),
+        }
+    }
+    if !cidrs.is_empty() && !client_ip(&req, &data).is_some_and(|ip| cidrs.iter().any(|c| c.contains(ip))) {
+        return HttpResponse::BadRequest().body("The allowed networks must include your current address");
+    }
+    if body.reauth_hours.is_some_and(|h| h == 0 || h > MAX_REAUTH_HOURS) {
+        return HttpResponse::BadRequest().body(format!("reauth_hours must be between 1 and {}", MAX_REAUTH_HOURS));
+    }
+
+    let policy = SecurityPolicy {
+        team_id: team_id.to_string(),
+        allowed_cidrs: body.allowed_cidrs.iter().map(|c| c.trim().to_string()).collect(),
+        reauth_hours: body.reauth_hours,
+        device_confirmation: body.device_confirmation,
+        updated_by: auth.user_id().to_string(),
+        updated_at: Utc::now(),
+    };
+    match policies(&data.mongodb).replace_one(doc! { "team_id": &*team_id }, &policy).upsert(true).await {
+        Ok(_) => {
+            invalidate_policy(&team_id);
+            info!("User {} updated the security policy of team {}", auth.user_id(), team_id);
+            HttpResponse::Ok().json(policy)
+        }
+        Err(e) => {
+            error!("Error saving security policy: {}", e);
+            HttpResponse::InternalServerError().body("Error saving security policy")
+        }
+    }
+}
+
+/// DELETE /teams/{team_id}/security-policy
+pub async fn delete_security_policy(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Unauthorized().body("Only team admins can change the security policy");
+    }
+    match policies(&data.mongodb).delete_one(doc! { "team_id": &*team_id }).await {
+        Ok(r) if r.deleted_count == 0 => HttpResponse::NotFound().body("The team has no security policy"),
+        Ok(_) => {
+            invalidate_policy(&team_id);
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => {
+            error!("Error deleting security policy: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting security policy")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn networks_contain_their_addresses() {
+        let net = Cidr::parse(" 203.0.113.0/24 ").unwrap();
+        assert!(net.contains(ip("203.0.113.0")));
+        assert!(net.contains(ip("203.0.113.255")));
+        assert!(!net.contains(ip("203.0.114.1")));
+        // IPv4 clients reaching a dual-stack listener arrive mapped into IPv6.
+        assert!(net.contains(ip("::ffff:203.0.113.9")));
+        assert!(!net.contains(ip("2001:db8::1")));
+
+        let v6 = Cidr::parse("2001:db8::/32").unwrap();
+        assert!(v6.contains(ip("2001:db8:ffff::1")));
+        assert!(!v6.contains(ip("2001:db9::1")));
+        assert!(!v6.contains(ip("203.0.113.1")));
+    }
+
+    #[test]
+    fn bare_addresses_and_zero_prefixes() {
+        let host = Cidr::parse("198.51.100.7").unwrap();
+        assert!(host.contains(ip("198.51.100.7")));
+        assert!(!host.contains(ip("198.51.100.8")));
+        let any = Cidr::parse("0.0.0.0/0").unwrap();
+        assert!(any.contains(ip("198.51.100.8")));
+        let any6 = Cidr::parse("::/0").unwrap();
+        assert!(any6.contains(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn malformed_networks_are_dropped() {
+        for s in ["", "203.0.113.0/33", "2001:db8::/129", "203.0.113/24", "example.com", "203.0.113.0/-1"] {
+            assert!(Cidr::parse(s).is_none(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn only_device_confirmation_is_exempt() {
+        assert!(exempt(&format!("{}/auth/devices/confirm/abc", crate::api::V1_PREFIX)));
+        assert!(!exempt(&format!("{}/auth/refresh", crate::api::V1_PREFIX)));
+        assert!(!exempt(&format!("{}/messages/abc", crate::api::V1_PREFIX)));
+    }
+}
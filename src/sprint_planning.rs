@@ -0,0 +1,261 @@
+// src/sprint_planning.rs
+//
+// Server-side sprint planning check. Given the tickets a team wants to pull into a
+// sprint, report whether the estimates fit the team's capacity (from members'
+// working hours), which tickets are still blocked, and who would be overloaded.
+// Nothing is written; the client assigns the sprint afterwards as usual.
+
+use std::collections::{HashMap, HashSet};
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::NaiveTime;
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::response::CodedError;
+use crate::tenancy::Repo;
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+
+/// Used for members who have not set their working hours.
+const DEFAULT_DAILY_HOURS: f64 = 8.0;
+/// Two working weeks.
+const DEFAULT_SPRINT_DAYS: u32 = 10;
+
+#[derive(Debug, Deserialize)]
+pub struct PlanSprintRequest {
+    pub ticket_ids: Vec<String>,
+    /// Working days in the sprint
+    pub sprint_days: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlanWarning {
+    /// "over_capacity", "unestimated", "blocked", "assignee_overloaded", "not_found"
+    pub kind: &'static str,
+    pub ticket_id: Option<String>,
+    pub user_id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AssigneeLoad {
+    pub user_id: String,
+    pub estimate_hours: f64,
+    pub capacity_hours: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SprintPlan {
+    pub sprint_id: i32,
+    pub total_estimate_hours: f64,
+    pub capacity_hours: f64,
+    pub assignees: Vec<AssigneeLoad>,
+    pub warnings: Vec<PlanWarning>,
+}
+
+/// Parsed "HH:MM" working hours; None when unset or not a forward range.
+pub(crate) fn working_window(start: Option<&str>, end: Option<&str>) -> Option<(NaiveTime, NaiveTime)> {
+    let start = NaiveTime::parse_from_str(start?, "%H:%M").ok()?;
+    let end = NaiveTime::parse_from_str(end?, "%H:%M").ok()?;
+    (end > start).then_some((start, end))
+}
+
+/// Hours per day from "HH:MM" working hours.
+fn daily_hours(start: Option<&str>, end: Option<&str>) -> Option<f64> {
+    let (start, end) = working_window(start, end)?;
+    Some((end - start).num_minutes() as f64 / 60.0)
+}
+
+/// Daily hours for every member of the team.
+async fn member_hours(data: &AppState, team_id: &str) -> mongodb::error::Result<HashMap<String, f64>> {
+    let mut members = Vec::new();
+    let mut cursor = data.mongodb.db.collection::<Document>("user_teams").find(doc! { "team_id": team_id }).await?;
+    while let Some(m) = cursor.next().await {
+        if let Ok(uid) = m?.get_str("user_id") {
+            members.push(uid.to_string());
+        }
+    }
+
+    let oids: Vec<ObjectId> = members.iter().filter_map(|m| ObjectId::parse_str(m).ok()).collect();
+    let mut hours = HashMap::new();
+    let mut cursor = data.mongodb.db.collection::<Document>("users").find(doc! { "_id": { "$in": oids } }).await?;
+    while let Some(user) = cursor.next().await {
+        let user = user?;
+        if let Ok(oid) = user.get_object_id("_id") {
+            let h = daily_hours(user.get_str("working_hours_start").ok(), user.get_str("working_hours_end").ok());
+            hours.insert(oid.to_hex(), h.unwrap_or(DEFAULT_DAILY_HOURS));
+        }
+    }
+    for m in members {
+        hours.entry(m).or_insert(DEFAULT_DAILY_HOURS);
+    }
+    Ok(hours)
+}
+
+async fn load_tickets(tickets: Repo<'_, Ticket>, filter: Document) -> mongodb::error::Result<Vec<Ticket>> {
+    let mut cursor = tickets.find(filter).await?;
+    let mut out = Vec::new();
+    while let Some(t) = cursor.next().await {
+        out.push(t?);
+    }
+    Ok(out)
+}
+
+fn is_closed(status: &str) -> bool {
+    CLOSED_STATUSES.contains(&status)
+}
+
+/// POST /boards/{board_id}/sprints/{sprint_id}/plan
+pub async fn plan_sprint(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, i32)>,
+    payload: web::Json<PlanSprintRequest>,
+) -> impl Responder {
+    let (board_id, sprint_id) = path.into_inner();
+    if payload.ticket_ids.is_empty() {
+        return HttpResponse::BadRequest().body("ticket_ids must not be empty");
+    }
+    let sprint_days = payload.sprint_days.unwrap_or(DEFAULT_SPRINT_DAYS);
+    if sprint_days == 0 {
+        return HttpResponse::BadRequest().body("sprint_days must be positive");
+    }
+
+    let scope = match auth.board_scope(&board_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let team_id = scope.team_id().to_string();
+    if !auth.is_project_member(scope.project_id()).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
+
+    let (tickets, hours) = match (
+        load_tickets(Repo::new(&data.mongodb, &scope), doc! { "board_id": &board_id, "ticket_id": { "$in": &payload.ticket_ids } }).await,
+        member_hours(&data, &team_id).await,
+    ) {
+        (Ok(t), Ok(h)) => (t, h),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Error loading sprint plan data: {}", e);
+            return HttpResponse::InternalServerError().body("Error planning sprint");
+        }
+    };
+
+    let mut warnings = Vec::new();
+    let found: HashSet<&str> = tickets.iter().map(|t| t.ticket_id.as_str()).collect();
+    for id in &payload.ticket_ids {
+        if !found.contains(id.as_str()) {
+            warnings.push(PlanWarning {
+                kind: "not_found",
+                ticket_id: Some(id.clone()),
+                user_id: None,
+                message: "Ticket is not on this board".to_string(),
+            });
+        }
+    }
+
+    // Blockers: a dependency is resolved when it is closed or planned into the same sprint.
+    let blocker_ids: Vec<&String> = tickets.iter().flat_map(|t| &t.blocked_by).collect();
+    let blockers: HashMap<String, Ticket> = if blocker_ids.is_empty() {
+        HashMap::new()
+    } else {
+        match load_tickets(Repo::new(&data.mongodb, &scope), doc! { "ticket_id": { "$in": blocker_ids } }).await {
+            Ok(b) => b.into_iter().map(|t| (t.ticket_id.clone(), t)).collect(),
+            Err(e) => {
+                error!("Error loading blockers: {}", e);
+                return HttpResponse::InternalServerError().body("Error planning sprint");
+            }
+        }
+    };
+    for t in &tickets {
+        if t.status.eq_ignore_ascii_case("blocked") {
+            warnings.push(PlanWarning {
+                kind: "blocked",
+                ticket_id: Some(t.ticket_id.clone()),
+                user_id: None,
+                message: format!("\"{}\" is marked Blocked", t.title),
+            });
+        }
+        for b in &t.blocked_by {
+            let open = match blockers.get(b) {
+                Some(blocker) => !is_closed(&blocker.status) && !found.contains(b.as_str()),
+                // A deleted blocker no longer blocks anything.
+                None => false,
+            };
+            if open {
+                warnings.push(PlanWarning {
+                    kind: "blocked",
+                    ticket_id: Some(t.ticket_id.clone()),
+                    user_id: None,
+                    message: format!(
+                        "\"{}\" is blocked by unresolved \"{}\" which is not in this sprint",
+                        t.title, blockers[b].title
+                    ),
+                });
+            }
+        }
+    }
+
+    // Capacity and per-assignee load.
+    let capacity_hours: f64 = hours.values().sum::<f64>() * sprint_days as f64;
+    let mut total_estimate_hours = 0.0;
+    let mut load: HashMap<String, f64> = HashMap::new();
+    for t in &tickets {
+        match t.estimate_hours {
+            Some(est) => {
+                total_estimate_hours += est;
+                if let Some(a) = &t.assignee {
+                    *load.entry(a.clone()).or_default() += est;
+                }
+            }
+            None => warnings.push(PlanWarning {
+                kind: "unestimated",
+                ticket_id: Some(t.ticket_id.clone()),
+                user_id: None,
+                message: format!("\"{}\" has no estimate", t.title),
+            }),
+        }
+    }
+    if total_estimate_hours > capacity_hours {
+        warnings.push(PlanWarning {
+            kind: "over_capacity",
+            ticket_id: None,
+            user_id: None,
+            message: format!(
+                "Planned {:.1}h exceeds team capacity of {:.1}h",
+                total_estimate_hours, capacity_hours
+            ),
+        });
+    }
+
+    let mut assignees: Vec<AssigneeLoad> = load
+        .into_iter()
+        .map(|(user_id, estimate_hours)| {
+            let capacity = hours.get(&user_id).copied().unwrap_or(DEFAULT_DAILY_HOURS) * sprint_days as f64;
+            AssigneeLoad { user_id, estimate_hours, capacity_hours: capacity }
+        })
+        .collect();
+    assignees.sort_by(|a, b| a.user_id.cmp(&b.user_id));
+    for a in &assignees {
+        if a.estimate_hours > a.capacity_hours {
+            warnings.push(PlanWarning {
+                kind: "assignee_overloaded",
+                ticket_id: None,
+                user_id: Some(a.user_id.clone()),
+                message: format!("Assigned {:.1}h against {:.1}h available", a.estimate_hours, a.capacity_hours),
+            });
+        }
+    }
+
+    HttpResponse::Ok().json(SprintPlan {
+        sprint_id,
+        total_estimate_hours,
+        capacity_hours,
+        assignees,
+        warnings,
+    })
+}
@@ -0,0 +1,720 @@
+// src/sso.rs
+//
+// Single sign-on through OpenID Connect. A connection belongs to the organization
+// owning a set of email domains and points at its identity provider's issuer, whose
+// discovery document supplies the endpoints and signing keys. Platform admins manage
+// connections under /admin/sso-connections.
+//
+// Login is the authorization code flow with PKCE: `/auth/sso/start` returns the
+// provider URL for an email address, the provider sends the browser back to
+// `{APP_BASE_URL}/sso/callback`, and the frontend posts the code and state to
+// `/auth/sso/callback`, which answers like a password login. The start also sets an
+// HttpOnly cookie that the callback must present, so a callback link made by
+// someone else cannot log the browser into their account. Unknown users are
+// created on the fly when the connection allows it, and a claim (e.g. `groups`) can
+// map provider groups to team memberships. With `enforce` set, password login and
+// signup are refused for the connection's domains. SAML is not supported.
+//
+// A connection can also be given a SCIM token (see scim.rs), which provisions only
+// the users of its domains and the teams made with that token.
+
+use std::time::Duration as StdDuration;
+
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use log::{error, info, warn};
+use mongodb::bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::admin::is_platform_admin;
+use crate::app_state::AppState;
+use crate::auth::{same_site, session_response};
+use crate::chat_db::MongoDB;
+use crate::encryption::{lookup_hash, EncryptedString};
+use crate::member_import::free_username;
+use crate::response::CodedError;
+use crate::role_claims;
+use crate::team_management::UserTeam;
+
+/// How long a started login may take before the callback is rejected.
+const LOGIN_TTL_MINUTES: i64 = 10;
+/// Ties a pending login to the browser that started it.
+const SSO_COOKIE: &str = "taskline_sso";
+const PROVIDER_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+const SIGNING_ALGORITHMS: [Algorithm; 6] =
+    [Algorithm::RS256, Algorithm::RS384, Algorithm::RS512, Algorithm::PS256, Algorithm::ES256, Algorithm::ES384];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamMapping {
+    /// Value of the connection's team claim, e.g. a group name
+    pub claim_value: String,
+    pub team_id: String,
+    /// "admin" or "member"
+    pub role: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SsoConnection {
+    pub connection_id: String,
+    pub name: String,
+    /// Lowercase email domains signing in through this connection
+    pub domains: Vec<String>,
+    /// OIDC issuer URL; `/.well-known/openid-configuration` is fetched from it
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: EncryptedString,
+    /// Refuse password login and signup for the domains
+    pub enforce: bool,
+    /// Create accounts for unknown users on their first login
+    pub jit_provisioning: bool,
+    /// ID token claim holding the user's groups
+    pub team_claim: Option<String>,
+    #[serde(default)]
+    pub team_mappings: Vec<TeamMapping>,
+    /// `lookup_hash` of the connection's SCIM provisioning token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scim_token_hash: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A connection as shown to admins; the client secret is never returned.
+#[derive(Debug, Serialize)]
+pub struct SsoConnectionView {
+    pub connection_id: String,
+    pub name: String,
+    pub domains: Vec<String>,
+    pub issuer: String,
+    pub client_id: String,
+    pub enforce: bool,
+    pub jit_provisioning: bool,
+    pub team_claim: Option<String>,
+    pub team_mappings: Vec<TeamMapping>,
+    pub scim_enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<SsoConnection> for SsoConnectionView {
+    fn from(c: SsoConnection) -> Self {
+        SsoConnectionView {
+            connection_id: c.connection_id,
+            name: c.name,
+            domains: c.domains,
+            issuer: c.issuer,
+            client_id: c.client_id,
+            enforce: c.enforce,
+            jit_provisioning: c.jit_provisioning,
+            team_claim: c.team_claim,
+            team_mappings: c.team_mappings,
+            scim_enabled: c.scim_token_hash.is_some(),
+            created_at: c.created_at,
+            updated_at: c.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SsoConnectionRequest {
+    pub name: String,
+    pub domains: Vec<String>,
+    pub issuer: String,
+    pub client_id: String,
+    /// Required on create; omit on update to keep the current secret
+    pub client_secret: Option<String>,
+    #[serde(default)]
+    pub enforce: bool,
+    #[serde(default)]
+    pub jit_provisioning: bool,
+    pub team_claim: Option<String>,
+    #[serde(default)]
+    pub team_mappings: Vec<TeamMapping>,
+}
+
+/// A login between `/auth/sso/start` and the callback.
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingLogin {
+    state: String,
+    /// `lookup_hash` of the key in the browser's SSO_COOKIE
+    #[serde(default)]
+    browser_hash: String,
+    connection_id: String,
+    nonce: String,
+    code_verifier: String,
+    expires_at: BsonDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CallbackRequest {
+    pub code: String,
+    pub state: String,
+}
+
+fn connections(db: &MongoDB) -> mongodb::Collection<SsoConnection> {
+    db.db.collection::<SsoConnection>("sso_connections")
+}
+
+fn email_domain(email: &str) -> Option<String> {
+    email.rsplit_once('@').map(|(_, d)| d.trim().to_lowercase()).filter(|d| !d.is_empty())
+}
+
+async fn connection_for_email(db: &MongoDB, email: &str) -> mongodb::error::Result<Option<SsoConnection>> {
+    match email_domain(email) {
+        Some(domain) => connections(db).find_one(doc! { "domains": domain }).await,
+        None => Ok(None),
+    }
+}
+
+/// Whether password login and signup are disabled for the email's domain.
+pub(crate) async fn password_login_disabled(db: &MongoDB, email: &str) -> bool {
+    match connection_for_email(db, email).await {
+        Ok(c) => c.is_some_and(|c| c.enforce),
+        Err(e) => {
+            error!("Error fetching SSO connection: {}", e);
+            false
+        }
+    }
+}
+
+fn redirect_uri(data: &AppState) -> String {
+    format!("{}/sso/callback", data.config.app_base_url.trim_end_matches('/'))
+}
+
+async fn discover(data: &AppState, issuer: &str) -> Result<Discovery, String> {
+    let url = format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/'));
+    let discovery: Discovery = data.http_client.get(&url).timeout(PROVIDER_TIMEOUT).send().await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("discovery request failed: {}", e))?
+        .json().await
+        .map_err(|e| format!("invalid discovery document: {}", e))?;
+    if discovery.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+        return Err(format!("discovery document is for issuer {}", discovery.issuer));
+    }
+    Ok(discovery)
+}
+
+/// Checks the ID token's signature against the provider's keys, plus issuer,
+/// audience, expiry and nonce, and returns its claims.
+async fn verify_id_token(
+    data: &AppState,
+    discovery: &Discovery,
+    connection: &SsoConnection,
+    id_token: &str,
+    nonce: &str,
+) -> Result<Map<String, Value>, String> {
+    let header = decode_header(id_token).map_err(|e| format!("malformed ID token: {}", e))?;
+    if !SIGNING_ALGORITHMS.contains(&header.alg) {
+        return Err(format!("unsupported signing algorithm {:?}", header.alg));
+    }
+    let keys: JwkSet = data.http_client.get(&discovery.jwks_uri).timeout(PROVIDER_TIMEOUT).send().await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("key request failed: {}", e))?
+        .json().await
+        .map_err(|e| format!("invalid key set: {}", e))?;
+    let jwk = match &header.kid {
+        Some(kid) => keys.find(kid),
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }
+    .ok_or("no matching signing key")?;
+    let key = DecodingKey::from_jwk(jwk).map_err(|e| format!("unusable signing key: {}", e))?;
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[&connection.client_id]);
+    validation.set_issuer(&[&discovery.issuer]);
+    let claims = decode::<Map<String, Value>>(id_token, &key, &validation)
+        .map_err(|e| format!("invalid ID token: {}", e))?
+        .claims;
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+        return Err("nonce mismatch".to_string());
+    }
+    Ok(claims)
+}
+
+/// The claim's values; providers send groups as a list or a single string.
+fn claim_values(claims: &Map<String, Value>, claim: &str) -> Vec<String> {
+    match claims.get(claim) {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Adds the user to the teams their claims map to. Memberships are only added, never
+/// removed or downgraded, so roles changed in the app stick.
+async fn apply_team_mappings(data: &AppState, connection: &SsoConnection, user_id: &str, claims: &Map<String, Value>) -> mongodb::error::Result<Vec<String>> {
+    let Some(claim) = &connection.team_claim else {
+        return Ok(Vec::new());
+    };
+    let values = claim_values(claims, claim);
+    let user_teams = data.mongodb.db.collection::<UserTeam>("user_teams");
+    let mut joined = Vec::new();
+    for mapping in connection.team_mappings.iter().filter(|m| values.contains(&m.claim_value)) {
+        let filter = doc! { "team_id": &mapping.team_id, "user_id": user_id };
+        if user_teams.find_one(filter).await?.is_none() {
+            user_teams
+                .insert_one(UserTeam {
+                    user_id: user_id.to_string(),
+                    team_id: mapping.team_id.clone(),
+                    role: mapping.role.clone(),
+                    joined_at: Utc::now(),
+                })
+                .await?;
+            joined.push(mapping.team_id.clone());
+        }
+    }
+    if !joined.is_empty() {
+        role_claims::bump(&data.mongodb, &[user_id.to_string()]).await;
+    }
+    Ok(joined)
+}
+
+/// POST /auth/sso/start
+/// Body: `{ "email": "..." }`. Returns the provider URL to send the browser to.
+pub async fn start_sso(data: web::Data<AppState>, payload: web::Json<StartRequest>) -> impl Responder {
+    let connection = match connection_for_email(&data.mongodb, &payload.email).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return HttpResponse::NotFound().body("No single sign-on is configured for this email domain"),
+        Err(e) => {
+            error!("Error fetching SSO connection: {}", e);
+            return HttpResponse::InternalServerError().body("Error starting single sign-on");
+        }
+    };
+    let discovery = match discover(&data, &connection.issuer).await {
+        Ok(d) => d,
+        Err(e) => {
+            warn!("SSO connection {}: {}", connection.connection_id, e);
+            return HttpResponse::BadGateway().body("The identity provider is unavailable");
+        }
+    };
+
+    let browser_key = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let login = PendingLogin {
+        state: Uuid::new_v4().simple().to_string(),
+        browser_hash: lookup_hash(&browser_key),
+        connection_id: connection.connection_id.clone(),
+        nonce: Uuid::new_v4().simple().to_string(),
+        code_verifier: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+        expires_at: BsonDateTime::from_millis((Utc::now() + Duration::minutes(LOGIN_TTL_MINUTES)).timestamp_millis()),
+    };
+    let challenge = URL_SAFE_NO_PAD.encode(digest(&SHA256, login.code_verifier.as_bytes()).as_ref());
+    let url = reqwest::Url::parse_with_params(&discovery.authorization_endpoint, &[
+        ("response_type", "code"),
+        ("client_id", connection.client_id.as_str()),
+        ("redirect_uri", redirect_uri(&data).as_str()),
+        ("scope", "openid email profile"),
+        ("state", login.state.as_str()),
+        ("nonce", login.nonce.as_str()),
+        ("code_challenge", challenge.as_str()),
+        ("code_challenge_method", "S256"),
+        ("login_hint", payload.email.trim()),
+    ]);
+    let url = match url {
+        Ok(u) => u,
+        Err(e) => {
+            warn!("SSO connection {} has an invalid authorization endpoint: {}", connection.connection_id, e);
+            return HttpResponse::BadGateway().body("The identity provider is misconfigured");
+        }
+    };
+
+    let logins = data.mongodb.db.collection::<PendingLogin>("sso_logins");
+    let _ = logins.delete_many(doc! { "expires_at": { "$lt": BsonDateTime::now() } }).await;
+    if let Err(e) = logins.insert_one(&login).await {
+        error!("Error saving SSO login: {}", e);
+        return HttpResponse::InternalServerError().body("Error starting single sign-on");
+    }
+    let cookie = Cookie::build(SSO_COOKIE, browser_key)
+        .path("/")
+        .http_only(true)
+        .secure(data.config.cookie_secure)
+        .same_site(same_site(&data.config))
+        .max_age(CookieDuration::minutes(LOGIN_TTL_MINUTES))
+        .finish();
+    HttpResponse::Ok().cookie(cookie).json(serde_json::json!({ "authorization_url": url.to_string() }))
+}
+
+/// POST /auth/sso/callback
+/// Body: `{ "code": "...", "state": "..." }` as received by the frontend callback page.
+/// Only completes in the browser holding the cookie set by the start.
+pub async fn sso_callback(req: HttpRequest, data: web::Data<AppState>, payload: web::Json<CallbackRequest>) -> impl Responder {
+    let Some(browser_key) = req.cookie(SSO_COOKIE).map(|c| c.value().to_string()) else {
+        return HttpResponse::BadRequest().body("Login expired or unknown; start again");
+    };
+    // Consumed on first use, so a code/state pair cannot be replayed.
+    let pending = doc! { "state": &payload.state, "browser_hash": lookup_hash(&browser_key) };
+    let login = match data.mongodb.db.collection::<PendingLogin>("sso_logins").find_one_and_delete(pending).await {
+        Ok(Some(l)) if l.expires_at > BsonDateTime::now() => l,
+        Ok(_) => return HttpResponse::BadRequest().body("Login expired or unknown; start again"),
+        Err(e) => {
+            error!("Error fetching SSO login: {}", e);
+            return HttpResponse::InternalServerError().body("Error completing single sign-on");
+        }
+    };
+    let connection = match connections(&data.mongodb).find_one(doc! { "connection_id": &login.connection_id }).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return HttpResponse::BadRequest().body("Single sign-on was removed for this domain"),
+        Err(e) => {
+            error!("Error fetching SSO connection: {}", e);
+            return HttpResponse::InternalServerError().body("Error completing single sign-on");
+        }
+    };
+
+    let verified = async {
+        let discovery = discover(&data, &connection.issuer).await?;
+        let redirect = redirect_uri(&data);
+        let form = [
+            ("grant_type", "authorization_code"),
+            ("code", payload.code.as_str()),
+            ("redirect_uri", redirect.as_str()),
+            ("client_id", connection.client_id.as_str()),
+            ("client_secret", connection.client_secret.expose()),
+            ("code_verifier", login.code_verifier.as_str()),
+        ];
+        let tokens: TokenResponse = data.http_client.post(&discovery.token_endpoint).timeout(PROVIDER_TIMEOUT).form(&form).send().await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("token request failed: {}", e))?
+            .json().await
+            .map_err(|e| format!("invalid token response: {}", e))?;
+        verify_id_token(&data, &discovery, &connection, &tokens.id_token, &login.nonce).await
+    }
+    .await;
+    let claims = match verified {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("SSO login through {} failed: {}", connection.connection_id, e);
+            return HttpResponse::Unauthorized().body("Single sign-on failed");
+        }
+    };
+
+    let Some(email) = claims.get("email").and_then(Value::as_str).map(|e| e.trim().to_lowercase()) else {
+        return HttpResponse::Unauthorized().body("The identity provider did not share an email address");
+    };
+    if claims.get("email_verified").and_then(Value::as_bool) == Some(false) {
+        return HttpResponse::Unauthorized().body("The identity provider has not verified this email address");
+    }
+    // A provider may only sign in addresses of the domains it was configured for.
+    if !email_domain(&email).is_some_and(|d| connection.domains.contains(&d)) {
+        return HttpResponse::Forbidden().body("This email domain does not belong to the connection");
+    }
+    let subject = claims.get("sub").and_then(Value::as_str).unwrap_or_default().to_string();
+
+    let users = data.mongodb.db.collection::<Document>("users");
+    let existing = users
+        .find_one(doc! { "email": { "$regex": format!("^{}$", regex::escape(&email)), "$options": "i" } })
+        .await;
+    let (user_id, team_id) = match existing {
+        Ok(Some(user)) => {
+            if let Ok(false) = user.get_bool("active") {
+                return HttpResponse::Forbidden().body("Account deactivated");
+            }
+            let Ok(oid) = user.get_object_id("_id") else {
+                return HttpResponse::InternalServerError().error("user_id_missing");
+            };
+            let link = doc! { "$set": {
+                "sso_connection_id": &connection.connection_id,
+                "sso_subject": &subject,
+                "email_verified": true,
+            } };
+            if let Err(e) = users.update_one(doc! { "_id": oid }, link).await {
+                error!("Error linking user {} to SSO: {}", oid.to_hex(), e);
+            }
+            (oid.to_hex(), user.get_str("team_id").unwrap_or("").to_string())
+        }
+        Ok(None) if connection.jit_provisioning => {
+            let hint = claims.get("preferred_username").or_else(|| claims.get("name")).and_then(Value::as_str).unwrap_or(&email);
+            let username = match free_username(&data, hint).await {
+                Ok(u) => u,
+                Err(e) => {
+                    error!("Error choosing a username: {}", e);
+                    return HttpResponse::InternalServerError().body("Error creating user");
+                }
+            };
+            // SSO-only account: the empty password never matches a bcrypt check.
+            let user = doc! {
+                "username": &username,
+                "email": &email,
+                "password": "",
+                "team_id": "",
+                "email_verified": true,
+                "created_at": BsonDateTime::now(),
+                "sso_connection_id": &connection.connection_id,
+                "sso_subject": &subject,
+            };
+            match users.insert_one(user).await {
+                Ok(res) => match res.inserted_id.as_object_id() {
+                    Some(oid) => {
+                        info!("Created user {} ({}) through SSO connection {}", oid.to_hex(), username, connection.connection_id);
+                        (oid.to_hex(), String::new())
+                    }
+                    None => return HttpResponse::InternalServerError().body("Error creating user"),
+                },
+                Err(e) => {
+                    error!("Error creating SSO user: {}", e);
+                    return HttpResponse::InternalServerError().body("Error creating user");
+                }
+            }
+        }
+        Ok(None) => return HttpResponse::Forbidden().body("No account exists for this email; ask an admin to invite you"),
+        Err(e) => {
+            error!("Error fetching user: {}", e);
+            return HttpResponse::InternalServerError().body("Error completing single sign-on");
+        }
+    };
+
+    let team_id = match apply_team_mappings(&data, &connection, &user_id, &claims).await {
+        Ok(joined) if team_id.is_empty() && !joined.is_empty() => {
+            let oid = ObjectId::parse_str(&user_id).ok();
+            if let Err(e) = users.update_one(doc! { "_id": oid }, doc! { "$set": { "team_id": &joined[0] } }).await {
+                error!("Error setting team of {}: {}", user_id, e);
+            }
+            joined[0].clone()
+        }
+        Ok(_) => team_id,
+        Err(e) => {
+            error!("Error applying SSO team mappings for {}: {}", user_id, e);
+            team_id
+        }
+    };
+    let mut resp = session_response(&data, &user_id, &team_id).await;
+    let _ = resp.add_removal_cookie(&Cookie::build(SSO_COOKIE, "").path("/").finish());
+    resp
+}
+
+fn validate(req: &SsoConnectionRequest) -> Result<Vec<String>, String> {
+    if req.name.trim().is_empty() {
+        return Err("name is required".to_string());
+    }
+    let domains: Vec<String> = req.domains.iter().map(|d| d.trim().trim_start_matches('@').to_lowercase()).filter(|d| !d.is_empty()).collect();
+    if domains.is_empty() || domains.iter().any(|d| !d.contains('.') || d.contains(char::is_whitespace)) {
+        return Err("domains must be a non-empty list of email domains".to_string());
+    }
+    if !req.issuer.starts_with("https://") {
+        return Err("issuer must be an https URL".to_string());
+    }
+    if req.client_id.trim().is_empty() {
+        return Err("client_id is required".to_string());
+    }
+    if req.team_mappings.iter().any(|m| m.role != "admin" && m.role != "member") {
+        return Err("team mapping roles must be admin or member".to_string());
+    }
+    if !req.team_mappings.is_empty() && req.team_claim.is_none() {
+        return Err("team_mappings need a team_claim".to_string());
+    }
+    Ok(domains)
+}
+
+async fn check_teams_exist(db: &MongoDB, mappings: &[TeamMapping]) -> Result<(), HttpResponse> {
+    let teams = db.db.collection::<Document>("teams");
+    for mapping in mappings {
+        match teams.find_one(doc! { "team_id": &mapping.team_id }).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(HttpResponse::BadRequest().body(format!("Team {} not found", mapping.team_id))),
+            Err(e) => {
+                error!("Error fetching team: {}", e);
+                return Err(HttpResponse::InternalServerError().body("Error saving SSO connection"));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(&*e.kind, mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(w)) if w.code == 11000)
+}
+
+/// GET /admin/sso-connections
+pub async fn list_sso_connections(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    let mut cursor = match connections(&data.mongodb).find(doc! {}).sort(doc! { "name": 1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching SSO connections: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching SSO connections");
+        }
+    };
+    let mut out = Vec::new();
+    while let Ok(true) = cursor.advance().await {
+        match cursor.deserialize_current() {
+            Ok(c) => out.push(SsoConnectionView::from(c)),
+            Err(e) => error!("Skipping unreadable SSO connection: {}", e),
+        }
+    }
+    HttpResponse::Ok().json(out)
+}
+
+/// POST /admin/sso-connections
+pub async fn create_sso_connection(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    payload: web::Json<SsoConnectionRequest>,
+) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    let body = payload.into_inner();
+    let domains = match validate(&body) {
+        Ok(d) => d,
+        Err(msg) => return HttpResponse::BadRequest().body(msg),
+    };
+    let Some(secret) = body.client_secret.filter(|s| !s.is_empty()) else {
+        return HttpResponse::BadRequest().body("client_secret is required");
+    };
+    if let Err(resp) = check_teams_exist(&data.mongodb, &body.team_mappings).await {
+        return resp;
+    }
+    let now = Utc::now();
+    let connection = SsoConnection {
+        connection_id: Uuid::new_v4().to_string(),
+        name: body.name.trim().to_string(),
+        domains,
+        issuer: body.issuer.trim_end_matches('/').to_string(),
+        client_id: body.client_id.trim().to_string(),
+        client_secret: EncryptedString::new(secret),
+        enforce: body.enforce,
+        jit_provisioning: body.jit_provisioning,
+        team_claim: body.team_claim,
+        team_mappings: body.team_mappings,
+        scim_token_hash: None,
+        created_at: now,
+        updated_at: now,
+    };
+    match connections(&data.mongodb).insert_one(&connection).await {
+        Ok(_) => HttpResponse::Created().json(SsoConnectionView::from(connection)),
+        Err(e) if is_duplicate_key(&e) => HttpResponse::Conflict().body("A domain already belongs to another SSO connection"),
+        Err(e) => {
+            error!("Error creating SSO connection: {}", e);
+            HttpResponse::InternalServerError().body("Error saving SSO connection")
+        }
+    }
+}
+
+/// PUT /admin/sso-connections/{connection_id}
+pub async fn update_sso_connection(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    connection_id: web::Path<String>,
+    payload: web::Json<SsoConnectionRequest>,
+) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    let body = payload.into_inner();
+    let domains = match validate(&body) {
+        Ok(d) => d,
+        Err(msg) => return HttpResponse::BadRequest().body(msg),
+    };
+    if let Err(resp) = check_teams_exist(&data.mongodb, &body.team_mappings).await {
+        return resp;
+    }
+    let existing = match connections(&data.mongodb).find_one(doc! { "connection_id": &*connection_id }).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return HttpResponse::NotFound().body("SSO connection not found"),
+        Err(e) => {
+            error!("Error fetching SSO connection: {}", e);
+            return HttpResponse::InternalServerError().body("Error saving SSO connection");
+        }
+    };
+    let connection = SsoConnection {
+        name: body.name.trim().to_string(),
+        domains,
+        issuer: body.issuer.trim_end_matches('/').to_string(),
+        client_id: body.client_id.trim().to_string(),
+        client_secret: body.client_secret.filter(|s| !s.is_empty()).map(EncryptedString::new).unwrap_or(existing.client_secret),
+        enforce: body.enforce,
+        jit_provisioning: body.jit_provisioning,
+        team_claim: body.team_claim,
+        team_mappings: body.team_mappings,
+        updated_at: Utc::now(),
+        ..existing
+    };
+    match connections(&data.mongodb).replace_one(doc! { "connection_id": &*connection_id }, &connection).await {
+        Ok(_) => HttpResponse::Ok().json(SsoConnectionView::from(connection)),
+        Err(e) if is_duplicate_key(&e) => HttpResponse::Conflict().body("A domain already belongs to another SSO connection"),
+        Err(e) => {
+            error!("Error updating SSO connection: {}", e);
+            HttpResponse::InternalServerError().body("Error saving SSO connection")
+        }
+    }
+}
+
+/// DELETE /admin/sso-connections/{connection_id}
+/// Users keep their accounts.
+pub async fn delete_sso_connection(req: HttpRequest, data: web::Data<AppState>, connection_id: web::Path<String>) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    match connections(&data.mongodb).delete_one(doc! { "connection_id": &*connection_id }).await {
+        Ok(r) if r.deleted_count == 0 => HttpResponse::NotFound().body("SSO connection not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Error deleting SSO connection: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting SSO connection")
+        }
+    }
+}
+
+/// POST /admin/sso-connections/{connection_id}/scim-token
+/// Issues the connection's SCIM token, replacing any earlier one. The token is shown
+/// only in this response.
+pub async fn rotate_scim_token(req: HttpRequest, data: web::Data<AppState>, connection_id: web::Path<String>) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let update = doc! { "$set": { "scim_token_hash": lookup_hash(&token), "updated_at": mongodb::bson::to_bson(&Utc::now()).unwrap_or_default() } };
+    match connections(&data.mongodb).update_one(doc! { "connection_id": &*connection_id }, update).await {
+        Ok(r) if r.matched_count == 0 => HttpResponse::NotFound().body("SSO connection not found"),
+        Ok(_) => {
+            info!("Issued a SCIM token for SSO connection {}", connection_id);
+            HttpResponse::Created().json(serde_json::json!({ "token": token }))
+        }
+        Err(e) => {
+            error!("Error issuing SCIM token: {}", e);
+            HttpResponse::InternalServerError().body("Error issuing SCIM token")
+        }
+    }
+}
+
+/// DELETE /admin/sso-connections/{connection_id}/scim-token
+/// Turns SCIM provisioning off for the connection.
+pub async fn delete_scim_token(req: HttpRequest, data: web::Data<AppState>, connection_id: web::Path<String>) -> impl Responder {
+    if !is_platform_admin(&req, &data) {
+        return HttpResponse::Forbidden().error("admin_required");
+    }
+    let update = doc! { "$unset": { "scim_token_hash": "" } };
+    match connections(&data.mongodb).update_one(doc! { "connection_id": &*connection_id }, update).await {
+        Ok(r) if r.matched_count == 0 => HttpResponse::NotFound().body("SSO connection not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Error deleting SCIM token: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting SCIM token")
+        }
+    }
+}
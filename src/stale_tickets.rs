@@ -0,0 +1,352 @@
+// src/stale_tickets.rs
+//
+// Stale ticket insights for a project: open tickets nobody has touched for a while,
+// open tickets that have been waiting for an assignee, and open tickets past their
+// due date. Thresholds are kept per project and can be overridden per request. A
+// weekly job nudges assignees about their stale and overdue tickets, and the
+// project owners about unassigned ones.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::{error, info};
+use mongodb::bson::{self, doc, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::do_not_disturb::notify_user;
+use crate::project::Project;
+use crate::response::{CodedError, ok};
+use crate::team_time::timestamp;
+use crate::tenancy::{project_team_id, AllTenants, ProjectScope, Repo};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+
+/// How often the nudge job looks for projects that are due one.
+const NUDGE_CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+const NUDGE_PERIOD_DAYS: i64 = 7;
+const MAX_THRESHOLD_DAYS: i64 = 365;
+/// Tickets listed per user in a nudge; the rest are only counted.
+const NUDGE_TICKETS: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StaleSettings {
+    /// Open tickets without any change for this many days are stale
+    pub stale_days: i64,
+    /// Open tickets without an assignee this many days after creation
+    pub unassigned_days: i64,
+    pub weekly_nudge: bool,
+}
+
+impl Default for StaleSettings {
+    fn default() -> Self {
+        StaleSettings { stale_days: 14, unassigned_days: 7, weekly_nudge: true }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StaleQuery {
+    pub stale_days: Option<i64>,
+    pub unassigned_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StaleTicket {
+    pub ticket_id: String,
+    pub board_id: String,
+    pub title: String,
+    pub status: String,
+    pub assignee: Option<String>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub last_updated_at: DateTime<Utc>,
+    /// Days since the last change
+    pub idle_days: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StaleReport {
+    pub stale_days: i64,
+    pub unassigned_days: i64,
+    /// Least recently updated first
+    pub stale: Vec<StaleTicket>,
+    /// Oldest first
+    pub unassigned: Vec<StaleTicket>,
+    /// Longest overdue first
+    pub overdue: Vec<StaleTicket>,
+}
+
+fn settings_coll(db: &MongoDB) -> mongodb::Collection<StaleSettings> {
+    db.db.collection::<StaleSettings>("stale_settings")
+}
+
+fn valid_days(days: i64) -> bool {
+    (1..=MAX_THRESHOLD_DAYS).contains(&days)
+}
+
+async fn load_settings(db: &MongoDB, project_id: &str) -> mongodb::error::Result<StaleSettings> {
+    Ok(settings_coll(db).find_one(doc! { "project_id": project_id }).await?.unwrap_or_default())
+}
+
+/// Time of the last event of each ticket; legacy tickets without events are missing.
+async fn last_updates(db: &MongoDB, ticket_ids: &[&str]) -> mongodb::error::Result<HashMap<String, DateTime<Utc>>> {
+    let pipeline = vec![
+        doc! { "$match": { "ticket_id": { "$in": ticket_ids } } },
+        doc! { "$sort": { "ticket_id": 1, "seq": -1 } },
+        doc! { "$group": { "_id": "$ticket_id", "at": { "$first": "$at" } } },
+    ];
+    let mut cursor = db.db.collection::<Document>("ticket_events").aggregate(pipeline).await?;
+    let mut out = HashMap::new();
+    while let Some(d) = cursor.next().await {
+        let d = d?;
+        if let (Ok(id), Some(at)) = (d.get_str("_id"), d.get("at").and_then(timestamp)) {
+            out.insert(id.to_string(), at);
+        }
+    }
+    Ok(out)
+}
+
+async fn build_report(db: &MongoDB, scope: &ProjectScope, stale_days: i64, unassigned_days: i64) -> mongodb::error::Result<StaleReport> {
+    let filter = doc! { "status": { "$nin": CLOSED_STATUSES.to_vec() } };
+    let mut cursor = Repo::<Ticket>::new(db, scope).find(filter).await?;
+    let mut tickets = Vec::new();
+    while let Some(t) = cursor.next().await {
+        tickets.push(t?);
+    }
+    let ids: Vec<&str> = tickets.iter().map(|t| t.ticket_id.as_str()).collect();
+    let updates = last_updates(db, &ids).await?;
+
+    let now = Utc::now();
+    let mut report = StaleReport { stale_days, unassigned_days, stale: Vec::new(), unassigned: Vec::new(), overdue: Vec::new() };
+    for t in tickets {
+        let last_updated_at = updates.get(&t.ticket_id).copied().unwrap_or(t.created_at);
+        let entry = || StaleTicket {
+            ticket_id: t.ticket_id.clone(),
+            board_id: t.board_id.clone(),
+            title: t.title.clone(),
+            status: t.status.clone(),
+            assignee: t.assignee.clone(),
+            due_date: t.due_date,
+            created_at: t.created_at,
+            last_updated_at,
+            idle_days: (now - last_updated_at).num_days(),
+        };
+        if (now - last_updated_at).num_days() >= stale_days {
+            report.stale.push(entry());
+        }
+        if t.assignee.as_deref().is_none_or(str::is_empty) && (now - t.created_at).num_days() >= unassigned_days {
+            report.unassigned.push(entry());
+        }
+        if t.due_date.is_some_and(|d| d < now) {
+            report.overdue.push(entry());
+        }
+    }
+    report.stale.sort_by_key(|t| t.last_updated_at);
+    report.unassigned.sort_by_key(|t| t.created_at);
+    report.overdue.sort_by_key(|t| t.due_date);
+    Ok(report)
+}
+
+/// Project members may read; project owners and team admins may change settings.
+async fn check_access(auth: &AuthContext, data: &AppState, project_id: &str, manage: bool) -> Result<ProjectScope, HttpResponse> {
+    let team_id = match project_team_id(&data.mongodb, project_id).await {
+        Some(t) => t,
+        None => return Err(HttpResponse::NotFound().error("project_not_found")),
+    };
+    let scope = match auth.project_scope(&team_id, project_id).await {
+        Ok(scope) if auth.is_project_member(project_id).await => scope,
+        _ => return Err(HttpResponse::Unauthorized().error("not_project_member")),
+    };
+    if manage && !auth.is_project_owner(project_id).await && !auth.is_team_admin(&team_id).await {
+        return Err(HttpResponse::Forbidden().body("Only project owners and team admins can change these settings"));
+    }
+    Ok(scope)
+}
+
+/// GET /projects/{project_id}/insights/stale?stale_days=&unassigned_days=
+/// Thresholds default to the project's settings.
+pub async fn get_stale_tickets(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    project_id: web::Path<String>,
+    query: web::Query<StaleQuery>,
+) -> impl Responder {
+    let scope = match check_access(&auth, &data, &project_id, false).await {
+        Ok(scope) => scope,
+        Err(resp) => return resp,
+    };
+    let settings = match load_settings(&data.mongodb, &project_id).await {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Error fetching stale settings: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching stale tickets");
+        }
+    };
+    let stale_days = query.stale_days.unwrap_or(settings.stale_days);
+    let unassigned_days = query.unassigned_days.unwrap_or(settings.unassigned_days);
+    if !valid_days(stale_days) || !valid_days(unassigned_days) {
+        return HttpResponse::BadRequest().body(format!("Thresholds must be between 1 and {} days", MAX_THRESHOLD_DAYS));
+    }
+    match build_report(&data.mongodb, &scope, stale_days, unassigned_days).await {
+        Ok(report) => ok(report),
+        Err(e) => {
+            error!("Error building stale ticket report: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching stale tickets")
+        }
+    }
+}
+
+/// GET /projects/{project_id}/insights/stale/settings
+pub async fn get_stale_settings(auth: AuthContext, data: web::Data<AppState>, project_id: web::Path<String>) -> impl Responder {
+    if let Err(resp) = check_access(&auth, &data, &project_id, false).await {
+        return resp;
+    }
+    match load_settings(&data.mongodb, &project_id).await {
+        Ok(s) => ok(s),
+        Err(e) => {
+            error!("Error fetching stale settings: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching settings")
+        }
+    }
+}
+
+/// PUT /projects/{project_id}/insights/stale/settings
+pub async fn update_stale_settings(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    project_id: web::Path<String>,
+    payload: web::Json<StaleSettings>,
+) -> impl Responder {
+    if let Err(resp) = check_access(&auth, &data, &project_id, true).await {
+        return resp;
+    }
+    let settings = payload.into_inner();
+    if !valid_days(settings.stale_days) || !valid_days(settings.unassigned_days) {
+        return HttpResponse::BadRequest().body(format!("Thresholds must be between 1 and {} days", MAX_THRESHOLD_DAYS));
+    }
+    let fields = match bson::to_document(&settings) {
+        Ok(d) => d,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error saving settings: {}", e)),
+    };
+    let update = doc! { "$set": fields };
+    match settings_coll(&data.mongodb).update_one(doc! { "project_id": &*project_id }, update).upsert(true).await {
+        Ok(_) => ok(settings),
+        Err(e) => {
+            error!("Error saving stale settings: {}", e);
+            HttpResponse::InternalServerError().body("Error saving settings")
+        }
+    }
+}
+
+fn nudge_entry(t: &StaleTicket, reason: &str) -> serde_json::Value {
+    serde_json::json!({ "ticket_id": t.ticket_id, "title": t.title, "reason": reason })
+}
+
+async fn owner_ids(db: &MongoDB, project_id: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    if let Ok(mut cursor) = db.db.collection::<Document>("project_memberships")
+        .find(doc! { "project_id": project_id, "role": "owner" })
+        .await
+    {
+        while let Some(Ok(m)) = cursor.next().await {
+            if let Ok(uid) = m.get_str("user_id") {
+                out.push(uid.to_string());
+            }
+        }
+    }
+    out
+}
+
+/// Nudge one project: assignees about their stale and overdue tickets, owners about
+/// the unassigned ones.
+async fn nudge_project(state: &AppState, project_id: &str, project_name: &str, settings: &StaleSettings) -> mongodb::error::Result<()> {
+    let Some(scope) = ProjectScope::of_project(&state.mongodb, project_id).await else {
+        return Ok(());
+    };
+    let report = build_report(&state.mongodb, &scope, settings.stale_days, settings.unassigned_days).await?;
+    let mut per_user: HashMap<&str, Vec<serde_json::Value>> = HashMap::new();
+    for (tickets, reason) in [(&report.overdue, "overdue"), (&report.stale, "stale")] {
+        for t in tickets {
+            if let Some(assignee) = t.assignee.as_deref().filter(|a| !a.is_empty()) {
+                let entries = per_user.entry(assignee).or_default();
+                if !entries.iter().any(|e| e["ticket_id"] == t.ticket_id.as_str()) {
+                    entries.push(nudge_entry(t, reason));
+                }
+            }
+        }
+    }
+    for (user_id, entries) in per_user {
+        let payload = serde_json::json!({
+            "type": "stale_tickets",
+            "project_id": project_id,
+            "project_name": project_name,
+            "count": entries.len(),
+            "tickets": &entries[..entries.len().min(NUDGE_TICKETS)],
+        });
+        notify_user(state, user_id, payload.to_string()).await;
+    }
+    if !report.unassigned.is_empty() {
+        let payload = serde_json::json!({
+            "type": "unassigned_tickets",
+            "project_id": project_id,
+            "project_name": project_name,
+            "count": report.unassigned.len(),
+            "tickets": report.unassigned.iter().take(NUDGE_TICKETS).map(|t| nudge_entry(t, "unassigned")).collect::<Vec<_>>(),
+        });
+        for owner in owner_ids(&state.mongodb, project_id).await {
+            notify_user(state, &owner, payload.to_string()).await;
+        }
+    }
+    Ok(())
+}
+
+/// Nudge every project with nudges on that has not had one in the last week.
+async fn send_due_nudges(state: &AppState) {
+    let cutoff = Utc::now() - chrono::Duration::days(NUDGE_PERIOD_DAYS);
+    let mut projects = match Repo::<Project>::all_tenants(&state.mongodb, AllTenants::maintenance()).find_docs(doc! {}).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error listing projects for stale nudges: {}", e);
+            return;
+        }
+    };
+    let raw = state.mongodb.db.collection::<Document>("stale_settings");
+    while let Some(Ok(project)) = projects.next().await {
+        let Ok(project_id) = project.get_str("project_id") else { continue };
+        let stored = raw.find_one(doc! { "project_id": project_id }).await.ok().flatten();
+        let last = stored.as_ref().and_then(|d| d.get_datetime("last_nudge_at").ok()).map(|t| t.to_chrono());
+        if last.is_some_and(|t| t > cutoff) {
+            continue;
+        }
+        let settings: StaleSettings = stored.and_then(|d| bson::from_document(d).ok()).unwrap_or_default();
+        if !settings.weekly_nudge {
+            continue;
+        }
+        let name = project.get_str("name").unwrap_or(project_id);
+        if let Err(e) = nudge_project(state, project_id, name, &settings).await {
+            error!("Error sending stale nudges for project {}: {}", project_id, e);
+            continue;
+        }
+        let stamp = doc! { "$set": { "last_nudge_at": bson::DateTime::now() } };
+        if let Err(e) = raw.update_one(doc! { "project_id": project_id }, stamp).upsert(true).await {
+            error!("Error recording stale nudge for project {}: {}", project_id, e);
+        }
+        info!("Sent stale ticket nudges for project {}", project_id);
+    }
+}
+
+/// Start the background job that sends the weekly stale ticket nudges.
+pub fn spawn_stale_nudges(state: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(NUDGE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            send_due_nudges(&state).await;
+        }
+    });
+}
@@ -0,0 +1,166 @@
+// src/status.rs
+//
+// Public, unauthenticated status endpoint for embedding in a status page. It only
+// reports aggregate health; results are cached briefly and callers are rate limited
+// per IP so it cannot be used to hammer the database or upstream services.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use mongodb::bson::doc;
+use serde::Serialize;
+
+use crate::app_state::AppState;
+use crate::db_pool::{pool_stats, PoolStats};
+
+/// How long a computed status is reused.
+const CACHE_TTL: Duration = Duration::from_secs(15);
+/// Per-IP budget within RATE_WINDOW.
+const RATE_LIMIT: u32 = 30;
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// Upper bound for each dependency probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+static STARTED_AT: OnceLock<Instant> = OnceLock::new();
+static CACHE: Mutex<Option<(Instant, StatusReport)>> = Mutex::new(None);
+static RATE: OnceLock<Mutex<HashMap<IpAddr, (Instant, u32)>>> = OnceLock::new();
+
+/// Record the process start time; call once during startup.
+pub fn mark_started() {
+    STARTED_AT.get_or_init(Instant::now);
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SubsystemStatus {
+    /// "ok", "degraded" or "not_configured"
+    pub database: &'static str,
+    pub ai: &'static str,
+    pub email: &'static str,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct StatusReport {
+    /// "operational", "degraded" or "outage"
+    pub status: &'static str,
+    pub version: &'static str,
+    pub uptime_secs: u64,
+    pub subsystems: SubsystemStatus,
+    pub checked_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Fixed-window limiter; returns false once the caller has used up its budget.
+fn allow(ip: IpAddr) -> bool {
+    let now = Instant::now();
+    let mut map = RATE.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+    if map.len() > 10_000 {
+        map.retain(|_, (start, _)| now.duration_since(*start) < RATE_WINDOW);
+    }
+    let entry = map.entry(ip).or_insert((now, 0));
+    if now.duration_since(entry.0) >= RATE_WINDOW {
+        *entry = (now, 0);
+    }
+    entry.1 += 1;
+    entry.1 <= RATE_LIMIT
+}
+
+/// Any HTTP answer counts as reachable; only transport errors and timeouts do not.
+async fn probe(data: &AppState, url: &str) -> &'static str {
+    match data.http_client.get(url).timeout(PROBE_TIMEOUT).send().await {
+        Ok(_) => "ok",
+        Err(_) => "degraded",
+    }
+}
+
+async fn compute(data: &AppState) -> StatusReport {
+    let database = match tokio::time::timeout(PROBE_TIMEOUT, data.mongodb.db.run_command(doc! { "ping": 1 })).await {
+        Ok(Ok(_)) => "ok",
+        _ => "degraded",
+    };
+    let ai_endpoint = if data.config.ai_use_local {
+        &data.config.ai_local_endpoint
+    } else {
+        &data.config.ai_aws_endpoint
+    };
+    let ai = probe(data, ai_endpoint).await;
+    let email = match &data.config.email_api_endpoint {
+        Some(endpoint) => probe(data, endpoint).await,
+        None => "not_configured",
+    };
+
+    let status = if database != "ok" {
+        "outage"
+    } else if ai != "ok" || email == "degraded" {
+        "degraded"
+    } else {
+        "operational"
+    };
+    StatusReport {
+        status,
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_secs: STARTED_AT.get().map_or(0, |t| t.elapsed().as_secs()),
+        subsystems: SubsystemStatus { database, ai, email },
+        checked_at: chrono::Utc::now(),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    /// "ok" or "degraded"
+    pub database: &'static str,
+    pub pool: PoolStats,
+}
+
+/// GET /readyz — for load balancers and orchestrators. Pings the database on every
+/// call (bounded by PROBE_TIMEOUT) and reports the connection pool counters; 503 when
+/// the database cannot be reached.
+pub async fn readyz(data: web::Data<AppState>) -> impl Responder {
+    let database = match tokio::time::timeout(PROBE_TIMEOUT, data.mongodb.db.run_command(doc! { "ping": 1 })).await {
+        Ok(Ok(_)) => "ok",
+        _ => "degraded",
+    };
+    let report = Readiness { ready: database == "ok", database, pool: pool_stats() };
+    let mut resp = if report.ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    resp.insert_header(("Cache-Control", "no-store")).json(report)
+}
+
+/// GET /status
+pub async fn get_status(req: HttpRequest, data: web::Data<AppState>) -> impl Responder {
+    if let Some(ip) = req.peer_addr().map(|a| a.ip()) {
+        if !allow(ip) {
+            return HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", RATE_WINDOW.as_secs().to_string()))
+                .body("Too many requests");
+        }
+    }
+
+    let cached = CACHE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .filter(|(at, _)| at.elapsed() < CACHE_TTL)
+        .map(|(_, report)| report.clone());
+    let report = match cached {
+        Some(r) => r,
+        None => {
+            let r = compute(&data).await;
+            *CACHE.lock().unwrap() = Some((Instant::now(), r.clone()));
+            r
+        }
+    };
+
+    let mut resp = if report.status == "outage" {
+        HttpResponse::ServiceUnavailable()
+    } else {
+        HttpResponse::Ok()
+    };
+    resp.insert_header(("Cache-Control", format!("public, max-age={}", CACHE_TTL.as_secs())))
+        .json(report)
+}
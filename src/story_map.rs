@@ -0,0 +1,360 @@
+// src/story_map.rs
+//
+// Story map per project: a backbone of activities, each optionally backed by an epic
+// (a ticket of type "Epic"), split into steps that hold the stories. The arrangement
+// of activities, steps and the order of tickets within a step is stored in
+// `story_maps`; the release row of a story is its `fix_version`, so moving a story
+// between releases is an ordinary ticket update. GET returns the whole map resolved
+// against current tickets and releases, plus the open tickets not placed yet.
+
+use std::collections::{HashMap, HashSet};
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::release::Release;
+use crate::response::CodedError;
+use crate::tenancy::{ProjectScope, Repo};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+
+const MAX_ACTIVITIES: usize = 100;
+const MAX_STEPS: usize = 100;
+const MAX_TITLE_LEN: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoryStep {
+    pub step_id: String,
+    pub title: String,
+    /// Stories in display order
+    pub ticket_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoryActivity {
+    pub activity_id: String,
+    pub title: String,
+    /// Epic ticket the activity stands for
+    pub epic_id: Option<String>,
+    pub steps: Vec<StoryStep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoryMap {
+    pub project_id: String,
+    pub activities: Vec<StoryActivity>,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StepRequest {
+    /// Kept when given, generated otherwise
+    pub step_id: Option<String>,
+    pub title: String,
+    #[serde(default)]
+    pub ticket_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActivityRequest {
+    pub activity_id: Option<String>,
+    pub title: String,
+    pub epic_id: Option<String>,
+    #[serde(default)]
+    pub steps: Vec<StepRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateStoryMapRequest {
+    pub activities: Vec<ActivityRequest>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StoryCard {
+    pub ticket_id: String,
+    pub title: String,
+    pub status: String,
+    pub ticket_type: Option<String>,
+    pub assignee: Option<String>,
+    pub estimate_hours: Option<f64>,
+    pub done: bool,
+}
+
+impl From<&Ticket> for StoryCard {
+    fn from(t: &Ticket) -> Self {
+        StoryCard {
+            ticket_id: t.ticket_id.clone(),
+            title: t.title.clone(),
+            status: t.status.clone(),
+            ticket_type: t.ticket_type.clone(),
+            assignee: t.assignee.clone(),
+            estimate_hours: t.estimate_hours,
+            done: CLOSED_STATUSES.contains(&t.status.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReleaseRow {
+    pub release_id: String,
+    pub name: String,
+    pub release_date: Option<DateTime<Utc>>,
+    pub status: String,
+}
+
+/// The stories of one step in one release; `release_id` None is the unscheduled row.
+#[derive(Debug, Serialize)]
+pub struct StoryCell {
+    pub release_id: Option<String>,
+    pub stories: Vec<StoryCard>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StepView {
+    pub step_id: String,
+    pub title: String,
+    /// One cell per release row, in row order, then the unscheduled cell
+    pub cells: Vec<StoryCell>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActivityView {
+    pub activity_id: String,
+    pub title: String,
+    pub epic: Option<StoryCard>,
+    pub steps: Vec<StepView>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StoryMapView {
+    pub project_id: String,
+    /// Release rows, by release date; undated releases last
+    pub releases: Vec<ReleaseRow>,
+    pub activities: Vec<ActivityView>,
+    /// Open tickets that are neither placed on the map nor epics
+    pub unmapped: Vec<StoryCard>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+fn is_epic(ticket: &Ticket) -> bool {
+    ticket.ticket_type.as_deref().is_some_and(|t| t.eq_ignore_ascii_case("epic"))
+}
+
+async fn check_access(auth: &AuthContext, team_id: &str, project_id: &str) -> Result<ProjectScope, HttpResponse> {
+    let scope = auth.project_scope(team_id, project_id).await?;
+    if !auth.is_project_member(project_id).await {
+        return Err(HttpResponse::Unauthorized().error("not_project_member"));
+    }
+    Ok(scope)
+}
+
+async fn project_tickets(data: &AppState, scope: &ProjectScope) -> mongodb::error::Result<Vec<Ticket>> {
+    let mut cursor = Repo::<Ticket>::new(&data.mongodb, scope)
+        .find(doc! {})
+        .sort(doc! { "created_at": 1 })
+        .await?;
+    let mut tickets = Vec::new();
+    while let Some(t) = cursor.next().await {
+        tickets.push(t?);
+    }
+    Ok(tickets)
+}
+
+async fn project_releases(data: &AppState, project_id: &str) -> mongodb::error::Result<Vec<Release>> {
+    let mut cursor = data.mongodb.db.collection::<Release>("releases").find(doc! { "project_id": project_id }).await?;
+    let mut releases = Vec::new();
+    while let Some(r) = cursor.next().await {
+        releases.push(r?);
+    }
+    releases.sort_by(|a, b| match (a.release_date, b.release_date) {
+        (Some(x), Some(y)) => x.cmp(&y),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.created_at.cmp(&b.created_at),
+    });
+    Ok(releases)
+}
+
+/// Resolves the stored arrangement against the project's current tickets; tickets
+/// deleted or moved away since are left out.
+fn build_view(project_id: &str, map: Option<StoryMap>, tickets: &[Ticket], releases: Vec<Release>) -> StoryMapView {
+    let by_id: HashMap<&str, &Ticket> = tickets.iter().map(|t| (t.ticket_id.as_str(), t)).collect();
+    let rows: Vec<Option<String>> = releases.iter().map(|r| Some(r.release_id.clone())).chain(std::iter::once(None)).collect();
+    let mut placed: HashSet<&str> = HashSet::new();
+    let (activities, updated_at) = match map {
+        Some(m) => (m.activities, Some(m.updated_at)),
+        None => (Vec::new(), None),
+    };
+
+    let activities = activities
+        .into_iter()
+        .map(|a| {
+            let epic = a.epic_id.as_deref().and_then(|id| by_id.get(id)).map(|t| {
+                placed.insert(t.ticket_id.as_str());
+                StoryCard::from(*t)
+            });
+            let steps = a
+                .steps
+                .into_iter()
+                .map(|s| {
+                    let stories: Vec<&Ticket> = s.ticket_ids.iter().filter_map(|id| by_id.get(id.as_str()).copied()).collect();
+                    placed.extend(stories.iter().map(|t| t.ticket_id.as_str()));
+                    let cells = rows
+                        .iter()
+                        .map(|row| {
+                            // Tickets pointing at a release that no longer exists are unscheduled.
+                            let in_row = |t: &Ticket| match row {
+                                Some(id) => t.fix_version.as_ref() == Some(id),
+                                None => !t.fix_version.as_ref().is_some_and(|v| rows.contains(&Some(v.clone()))),
+                            };
+                            StoryCell { release_id: row.clone(), stories: stories.iter().filter(|t| in_row(t)).map(|t| StoryCard::from(*t)).collect() }
+                        })
+                        .collect();
+                    StepView { step_id: s.step_id, title: s.title, cells }
+                })
+                .collect();
+            ActivityView { activity_id: a.activity_id, title: a.title, epic, steps }
+        })
+        .collect();
+
+    let unmapped = tickets
+        .iter()
+        .filter(|t| !placed.contains(t.ticket_id.as_str()) && !is_epic(t) && !CLOSED_STATUSES.contains(&t.status.as_str()))
+        .map(StoryCard::from)
+        .collect();
+    let releases = releases
+        .into_iter()
+        .map(|r| ReleaseRow { release_id: r.release_id, name: r.name, release_date: r.release_date, status: r.status })
+        .collect();
+    StoryMapView { project_id: project_id.to_string(), releases, activities, unmapped, updated_at }
+}
+
+fn check_title(title: &str, what: &str) -> Result<String, String> {
+    let title = title.trim();
+    if title.is_empty() || title.chars().count() > MAX_TITLE_LEN {
+        return Err(format!("{} titles must be between 1 and {} characters", what, MAX_TITLE_LEN));
+    }
+    Ok(title.to_string())
+}
+
+/// Checks the request against the project's tickets and turns it into the stored form.
+fn validate(req: UpdateStoryMapRequest, tickets: &[Ticket]) -> Result<Vec<StoryActivity>, String> {
+    if req.activities.len() > MAX_ACTIVITIES {
+        return Err(format!("A story map can have at most {} activities", MAX_ACTIVITIES));
+    }
+    let by_id: HashMap<&str, &Ticket> = tickets.iter().map(|t| (t.ticket_id.as_str(), t)).collect();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut ids: HashSet<String> = HashSet::new();
+    let mut fresh_id = |given: Option<String>| -> Result<String, String> {
+        let id = given.filter(|id| !id.trim().is_empty()).unwrap_or_else(|| Uuid::new_v4().to_string());
+        if !ids.insert(id.clone()) {
+            return Err(format!("Duplicate activity or step id {}", id));
+        }
+        Ok(id)
+    };
+
+    let mut activities = Vec::with_capacity(req.activities.len());
+    for a in req.activities {
+        let title = check_title(&a.title, "Activity")?;
+        if a.steps.len() > MAX_STEPS {
+            return Err(format!("An activity can have at most {} steps", MAX_STEPS));
+        }
+        if let Some(epic_id) = &a.epic_id {
+            match by_id.get(epic_id.as_str()) {
+                Some(t) if is_epic(t) => {}
+                Some(_) => return Err(format!("Ticket {} is not an epic", epic_id)),
+                None => return Err(format!("Epic {} is not a ticket of this project", epic_id)),
+            }
+            if !seen.insert(epic_id.clone()) {
+                return Err(format!("Ticket {} is placed more than once", epic_id));
+            }
+        }
+        let mut steps = Vec::with_capacity(a.steps.len());
+        for s in a.steps {
+            let step_title = check_title(&s.title, "Step")?;
+            for id in &s.ticket_ids {
+                if !by_id.contains_key(id.as_str()) {
+                    return Err(format!("Ticket {} is not a ticket of this project", id));
+                }
+                if !seen.insert(id.clone()) {
+                    return Err(format!("Ticket {} is placed more than once", id));
+                }
+            }
+            steps.push(StoryStep { step_id: fresh_id(s.step_id)?, title: step_title, ticket_ids: s.ticket_ids });
+        }
+        activities.push(StoryActivity { activity_id: fresh_id(a.activity_id)?, title, epic_id: a.epic_id, steps });
+    }
+    Ok(activities)
+}
+
+async fn load_view(data: &AppState, scope: &ProjectScope, project_id: &str) -> Result<StoryMapView, HttpResponse> {
+    let map = data.mongodb.db.collection::<StoryMap>("story_maps").find_one(doc! { "project_id": project_id }).await;
+    match (map, project_tickets(data, scope).await, project_releases(data, project_id).await) {
+        (Ok(map), Ok(tickets), Ok(releases)) => Ok(build_view(project_id, map, &tickets, releases)),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            error!("Error fetching story map: {}", e);
+            Err(HttpResponse::InternalServerError().body("Error fetching story map"))
+        }
+    }
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/story-map
+pub async fn get_story_map(auth: AuthContext, data: web::Data<AppState>, path: web::Path<(String, String)>) -> impl Responder {
+    let (team_id, project_id) = path.into_inner();
+    let scope = match check_access(&auth, &team_id, &project_id).await {
+        Ok(scope) => scope,
+        Err(resp) => return resp,
+    };
+    match load_view(&data, &scope, &project_id).await {
+        Ok(view) => HttpResponse::Ok().json(view),
+        Err(resp) => resp,
+    }
+}
+
+/// PUT /teams/{team_id}/projects/{project_id}/story-map
+/// Replaces the arrangement and returns the resolved map.
+pub async fn update_story_map(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<UpdateStoryMapRequest>,
+) -> impl Responder {
+    let (team_id, project_id) = path.into_inner();
+    let scope = match check_access(&auth, &team_id, &project_id).await {
+        Ok(scope) => scope,
+        Err(resp) => return resp,
+    };
+    let tickets = match project_tickets(&data, &scope).await {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Error fetching tickets: {}", e);
+            return HttpResponse::InternalServerError().body("Error saving story map");
+        }
+    };
+    let activities = match validate(payload.into_inner(), &tickets) {
+        Ok(a) => a,
+        Err(msg) => return HttpResponse::BadRequest().body(msg),
+    };
+
+    let map = StoryMap { project_id: project_id.clone(), activities, updated_by: auth.user_id().to_string(), updated_at: Utc::now() };
+    if let Err(e) = data.mongodb.db
+        .collection::<StoryMap>("story_maps")
+        .replace_one(doc! { "project_id": &project_id }, &map)
+        .upsert(true)
+        .await
+    {
+        error!("Error saving story map: {}", e);
+        return HttpResponse::InternalServerError().body("Error saving story map");
+    }
+    match load_view(&data, &scope, &project_id).await {
+        Ok(view) => HttpResponse::Ok().json(view),
+        Err(resp) => resp,
+    }
+}
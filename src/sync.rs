@@ -0,0 +1,414 @@
+// src/sync.rs
+//
+// Incremental sync for offline-first clients. Every mutation of a ticket, chat,
+// message, KB document or calendar event appends an entry to `sync_log` under a
+// global sequence number. `GET /sync?since=<cursor>` returns the entries the caller
+// can see after the cursor, together with the current state of each upserted
+// entity, and the cursor to pass next time.
+//
+// Clients bootstrap with the regular list endpoints after calling `GET /sync` without
+// `since`, which only returns the current cursor. With `format=ndjson` the changes are
+// streamed one per line and entity states are loaded in batches, not all at once.
+
+use std::collections::{HashMap, HashSet};
+
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{web, HttpResponse, Responder};
+use futures_util::{future, stream, StreamExt};
+use log::error;
+use mongodb::bson::{doc, DateTime as BsonDateTime, Document};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::response::{ndjson_line, NDJSON};
+use crate::tenancy::{ProjectsScope, Repo};
+use crate::ticket::Ticket;
+
+/// Entries younger than this are held back, so a writer that took its sequence
+/// number earlier but committed later is not skipped by a client's cursor.
+const SETTLE_MILLIS: i64 = 2_000;
+const DEFAULT_LIMIT: i64 = 500;
+const MAX_LIMIT: i64 = 2_000;
+/// Entries whose entities are loaded together when streaming NDJSON.
+const STREAM_BATCH: usize = 100;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Entity {
+    Ticket,
+    Chat,
+    Message,
+    Doc,
+    Event,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Op {
+    Upsert,
+    Delete,
+}
+
+/// Who can see a change.
+pub enum Scope<'a> {
+    Team(&'a str),
+    Project(&'a str),
+    Users(&'a [String]),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SyncEntry {
+    seq: i64,
+    entity: Entity,
+    entity_id: String,
+    op: Op,
+    team_id: Option<String>,
+    project_id: Option<String>,
+    #[serde(default)]
+    user_ids: Vec<String>,
+    at: BsonDateTime,
+}
+
+async fn next_seq(db: &MongoDB) -> mongodb::error::Result<i64> {
+    let counter = db
+        .db
+        .collection::<Document>("counters")
+        .find_one_and_update(doc! { "_id": "sync_log" }, doc! { "$inc": { "seq": 1_i64 } })
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .await?;
+    Ok(counter.and_then(|c| c.get_i64("seq").ok()).unwrap_or(0))
+}
+
+/// Append a change to the log. Failures are logged, never surfaced to the caller.
+pub async fn record_change(db: &MongoDB, entity: Entity, entity_id: &str, op: Op, scope: Scope<'_>) {
+    let seq = match next_seq(db).await {
+        Ok(seq) => seq,
+        Err(e) => {
+            error!("Error allocating sync sequence: {}", e);
+            return;
+        }
+    };
+    let (team_id, project_id, user_ids) = match scope {
+        Scope::Team(t) => (Some(t.to_string()), None, Vec::new()),
+        Scope::Project(p) => (None, Some(p.to_string()), Vec::new()),
+        Scope::Users(u) => (None, None, u.to_vec()),
+    };
+    let entry = SyncEntry {
+        seq,
+        entity,
+        entity_id: entity_id.to_string(),
+        op,
+        team_id,
+        project_id,
+        user_ids,
+        at: BsonDateTime::now(),
+    };
+    if let Err(e) = db.db.collection::<SyncEntry>("sync_log").insert_one(&entry).await {
+        error!("Error recording sync entry for {:?} {}: {}", entity, entity_id, e);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncQuery {
+    pub since: Option<i64>,
+    pub limit: Option<i64>,
+    /// "ndjson" streams a `SyncHeader` line followed by one change per line
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncChange {
+    pub seq: i64,
+    pub entity: Entity,
+    pub id: String,
+    pub op: Op,
+    /// Current state for upserts
+    pub data: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncResponse {
+    pub cursor: i64,
+    /// More changes are available right away; call again with `cursor`
+    pub has_more: bool,
+    pub changes: Vec<SyncChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncHeader {
+    pub cursor: i64,
+    pub has_more: bool,
+}
+
+async fn user_scope_ids(db: &MongoDB, coll: &str, key: &str, user_id: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    if let Ok(mut cursor) = db.db.collection::<Document>(coll).find(doc! { "user_id": user_id }).await {
+        while let Some(Ok(d)) = cursor.next().await {
+            if let Ok(id) = d.get_str(key) {
+                out.push(id.to_string());
+            }
+        }
+    }
+    out
+}
+
+/// Current documents for `ids` that `user_id` can still read, keyed by id, serialized
+/// as the REST endpoints would. `teams` are the caller's teams.
+async fn load_entities(
+    db: &MongoDB,
+    entity: Entity,
+    ids: Vec<String>,
+    user_id: &str,
+    teams: &[String],
+) -> mongodb::error::Result<HashMap<String, serde_json::Value>> {
+    async fn fetch<T>(
+        db: &MongoDB,
+        coll: &str,
+        key: &str,
+        ids: Vec<String>,
+        scope: Document,
+        id_of: impl Fn(&T) -> String,
+        view: impl Fn(T) -> serde_json::Value,
+    ) -> mongodb::error::Result<HashMap<String, serde_json::Value>>
+    where
+        T: serde::de::DeserializeOwned + Send + Sync,
+    {
+        let mut out = HashMap::new();
+        let mut filter = doc! { key: { "$in": ids } };
+        filter.extend(scope);
+        let mut cursor = db.db.collection::<T>(coll).find(filter).await?;
+        while let Some(item) = cursor.next().await {
+            let item = item?;
+            out.insert(id_of(&item), view(item));
+        }
+        Ok(out)
+    }
+    match entity {
+        Entity::Ticket => {
+            let scope = ProjectsScope::of_user(db, user_id).await?;
+            let mut out = HashMap::new();
+            let mut cursor = Repo::<Ticket>::across(db, &scope).find(doc! { "ticket_id": { "$in": ids } }).await?;
+            while let Some(t) = cursor.next().await {
+                let t = t?;
+                out.insert(t.ticket_id.clone(), to_json(t));
+            }
+            Ok(out)
+        }
+        Entity::Chat => {
+            let scope = doc! { "participants": user_id };
+            fetch::<crate::chat::Chat>(db, "chats", "_id", ids, scope, |c| c.id_chat.clone(), to_json).await
+        }
+        Entity::Message => {
+            let mut messages = fetch::<Document>(
+                db,
+                "messages",
+                "_id",
+                ids,
+                doc! {},
+                |m| m.get_str("_id").unwrap_or_default().to_string(),
+                to_json,
+            )
+            .await?;
+            // Only messages of chats the caller still takes part in.
+            let chat_of = |m: &serde_json::Value| m.get("id_chat").and_then(|c| c.as_str()).map(str::to_string);
+            let chat_ids: Vec<String> = messages.values().filter_map(chat_of).collect();
+            let joined = db
+                .db
+                .collection::<Document>("chats")
+                .distinct("_id", doc! { "_id": { "$in": chat_ids }, "participants": user_id })
+                .await?;
+            let joined: HashSet<&str> = joined.iter().filter_map(|c| c.as_str()).collect();
+            messages.retain(|_, m| chat_of(m).is_some_and(|c| joined.contains(c.as_str())));
+            Ok(messages)
+        }
+        Entity::Doc => {
+            let mut scope = crate::knowledge_base::readable_by_filter(user_id);
+            scope.insert("team_id", doc! { "$in": teams });
+            fetch::<crate::knowledge_base::Document>(db, "knowledge_base", "_id", ids, scope, |d| d.id.clone(), |d| {
+                to_json(crate::knowledge_base::PublicDocument::from(d))
+            })
+            .await
+        }
+        Entity::Event => {
+            // Events the caller organizes or attends, in teams they still belong to.
+            let scope = doc! { "$and": [
+                { "$or": [{ "user_id": user_id }, { "participants": user_id }] },
+                { "$or": [{ "team_id": { "$in": teams } }, { "team_id": null }] },
+            ] };
+            fetch::<crate::calendar::CalendarEvent>(
+                db,
+                "calendar_events",
+                "event_id",
+                ids,
+                scope,
+                |e| e.event_id.clone(),
+                to_json,
+            )
+            .await
+        }
+    }
+}
+
+fn to_json<T: Serialize>(value: T) -> serde_json::Value {
+    serde_json::to_value(value).unwrap_or(serde_json::Value::Null)
+}
+
+/// Changes for `entries`, with the current state of each upserted entity. An entry
+/// may predate a change of who can see the entity, so access is checked again here.
+async fn resolve(
+    db: &MongoDB,
+    user_id: &str,
+    teams: &[String],
+    entries: Vec<SyncEntry>,
+) -> mongodb::error::Result<Vec<SyncChange>> {
+    let mut states: HashMap<Entity, HashMap<String, serde_json::Value>> = HashMap::new();
+    for entity in [Entity::Ticket, Entity::Chat, Entity::Message, Entity::Doc, Entity::Event] {
+        let ids: Vec<String> = entries
+            .iter()
+            .filter(|e| e.entity == entity && e.op == Op::Upsert)
+            .map(|e| e.entity_id.clone())
+            .collect();
+        if !ids.is_empty() {
+            states.insert(entity, load_entities(db, entity, ids, user_id, teams).await?);
+        }
+    }
+
+    Ok(entries
+        .into_iter()
+        .map(|e| {
+            let data = match e.op {
+                Op::Upsert => states.get_mut(&e.entity).and_then(|m| m.remove(&e.entity_id)),
+                Op::Delete => None,
+            };
+            SyncChange {
+                seq: e.seq,
+                entity: e.entity,
+                // Gone or no longer readable since the entry was written: report it as deleted.
+                op: if data.is_some() { Op::Upsert } else { Op::Delete },
+                id: e.entity_id,
+                data,
+            }
+        })
+        .collect())
+}
+
+/// GET /sync?since=<cursor>&limit=<n>&format=json|ndjson
+pub async fn get_changes(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    query: web::Query<SyncQuery>,
+) -> impl Responder {
+    let ndjson = match query.format.as_deref() {
+        None | Some("json") => false,
+        Some("ndjson") => true,
+        Some(_) => return HttpResponse::BadRequest().body("format must be json or ndjson"),
+    };
+    let db = &data.mongodb;
+    let log = db.db.collection::<SyncEntry>("sync_log");
+
+    let since = match query.since {
+        Some(s) => s,
+        None => {
+            // No cursor yet: hand out the current position to start from.
+            let cursor = match log.find_one(doc! {}).sort(doc! { "seq": -1 }).await {
+                Ok(last) => last.map_or(0, |e| e.seq),
+                Err(e) => {
+                    error!("Error reading sync log: {}", e);
+                    return HttpResponse::InternalServerError().body("Error reading changes");
+                }
+            };
+            if ndjson {
+                return match ndjson_line(&SyncHeader { cursor, has_more: false }) {
+                    Ok(line) => HttpResponse::Ok().content_type(NDJSON).body(line),
+                    Err(e) => e.error_response(),
+                };
+            }
+            return HttpResponse::Ok().json(SyncResponse { cursor, has_more: false, changes: Vec::new() });
+        }
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let user_id = auth.user_id();
+    let teams = user_scope_ids(db, "user_teams", "team_id", user_id).await;
+    let projects = user_scope_ids(db, "project_memberships", "project_id", user_id).await;
+    let settled = BsonDateTime::from_millis(BsonDateTime::now().timestamp_millis() - SETTLE_MILLIS);
+    let filter = doc! {
+        "seq": { "$gt": since },
+        "at": { "$lte": settled },
+        "$or": [
+            { "team_id": { "$in": &teams } },
+            { "project_id": { "$in": &projects } },
+            { "user_ids": user_id },
+        ],
+    };
+
+    let mut entries = Vec::new();
+    match log.find(filter).sort(doc! { "seq": 1 }).limit(limit + 1).await {
+        Ok(mut cursor) => {
+            while let Some(entry) = cursor.next().await {
+                match entry {
+                    Ok(e) => entries.push(e),
+                    Err(e) => {
+                        error!("Error reading sync log: {}", e);
+                        return HttpResponse::InternalServerError().body("Error reading changes");
+                    }
+                }
+            }
+        }
+        Err(e) => {
+            error!("Error reading sync log: {}", e);
+            return HttpResponse::InternalServerError().body("Error reading changes");
+        }
+    }
+    let has_more = entries.len() as i64 > limit;
+    entries.truncate(limit as usize);
+    let cursor = entries.last().map_or(since, |e| e.seq);
+
+    // Only the latest change per entity matters to the client.
+    let mut seen = HashSet::new();
+    let mut latest: Vec<SyncEntry> = entries
+        .into_iter()
+        .rev()
+        .filter(|e| seen.insert((e.entity, e.entity_id.clone())))
+        .collect();
+    latest.reverse();
+
+    if ndjson {
+        // Entity states are loaded one batch at a time while the response is sent.
+        let head = ndjson_line(&SyncHeader { cursor, has_more });
+        let mut batches = Vec::new();
+        let mut latest = latest.into_iter().peekable();
+        while latest.peek().is_some() {
+            batches.push(latest.by_ref().take(STREAM_BATCH).collect::<Vec<_>>());
+        }
+        let user_id = user_id.to_string();
+        let changes = stream::iter(batches)
+            .then(move |batch| {
+                let (data, user_id, teams) = (data.clone(), user_id.clone(), teams.clone());
+                async move { resolve(&data.mongodb, &user_id, &teams, batch).await }
+            })
+            .flat_map(|res| {
+                let lines = match res {
+                    Ok(changes) => changes.iter().map(ndjson_line).collect(),
+                    Err(e) => {
+                        error!("Error loading entities for sync: {}", e);
+                        vec![Err(ErrorInternalServerError("Error reading changes"))]
+                    }
+                };
+                stream::iter(lines)
+            });
+        return HttpResponse::Ok().content_type(NDJSON).streaming(stream::once(future::ready(head)).chain(changes));
+    }
+
+    match resolve(db, user_id, &teams, latest).await {
+        Ok(changes) => HttpResponse::Ok().json(SyncResponse { cursor, has_more, changes }),
+        Err(e) => {
+            error!("Error loading entities for sync: {}", e);
+            HttpResponse::InternalServerError().body("Error reading changes")
+        }
+    }
+}
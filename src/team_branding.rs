@@ -0,0 +1,252 @@
+// src/team_branding.rs
+
+use std::sync::OnceLock;
+
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse, Responder};
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use futures_util::StreamExt;
+use image::ImageFormat;
+use log::{error, info};
+use mongodb::bson::{doc, oid::ObjectId, to_bson, Bson, Document};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::response::CodedError;
+
+/// Largest logo accepted, in bytes.
+const MAX_LOGO_BYTES: usize = 2 * 1024 * 1024;
+/// Longest "about" page accepted, in characters.
+const MAX_ABOUT_CHARS: usize = 20_000;
+/// Content types a logo is served with; anything else stored earlier is served as a download.
+const LOGO_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// Team-level branding, embedded in the team document.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TeamBranding {
+    /// GridFS id (hex) of the uploaded logo
+    pub logo_id: Option<String>,
+    /// "#RRGGBB"
+    pub accent_color: Option<String>,
+    /// Markdown "about" page
+    pub about: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateBrandingRequest {
+    pub accent_color: Option<String>,
+    pub about: Option<String>,
+}
+
+/// Content type of a logo, judged by decoding the bytes rather than trusting the
+/// declared type (which may be e.g. SVG with scripts).
+fn logo_content_type(bytes: &[u8]) -> Option<&'static str> {
+    let (format, content_type) = match image::guess_format(bytes).ok()? {
+        ImageFormat::Png => (ImageFormat::Png, "image/png"),
+        ImageFormat::Jpeg => (ImageFormat::Jpeg, "image/jpeg"),
+        ImageFormat::Gif => (ImageFormat::Gif, "image/gif"),
+        ImageFormat::WebP => (ImageFormat::WebP, "image/webp"),
+        _ => return None,
+    };
+    image::load_from_memory_with_format(bytes, format).ok()?;
+    Some(content_type)
+}
+
+async fn require_admin(data: &AppState, team_id: &str, user_id: &str) -> Result<(), HttpResponse> {
+    let user_teams = data.mongodb.db.collection::<Document>("user_teams");
+    match user_teams
+        .find_one(doc! { "team_id": team_id, "user_id": user_id, "role": "admin" })
+        .await
+    {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(HttpResponse::Unauthorized().body("Only team admins can change branding")),
+        Err(e) => Err(HttpResponse::InternalServerError().body(format!("Error verifying admin status: {}", e))),
+    }
+}
+
+async fn current_branding(data: &AppState, team_id: &str) -> Result<TeamBranding, HttpResponse> {
+    let teams = data.mongodb.db.collection::<Document>("teams");
+    match teams.find_one(doc! { "team_id": team_id }).await {
+        Ok(Some(team)) => Ok(team
+            .get_document("branding")
+            .ok()
+            .and_then(|b| mongodb::bson::from_document(b.clone()).ok())
+            .unwrap_or_default()),
+        Ok(None) => Err(HttpResponse::NotFound().error("team_not_found")),
+        Err(e) => Err(HttpResponse::InternalServerError().body(format!("Error fetching team: {}", e))),
+    }
+}
+
+async fn save_branding(data: &AppState, team_id: &str, branding: &TeamBranding) -> Result<(), HttpResponse> {
+    let teams = data.mongodb.db.collection::<Document>("teams");
+    let value = to_bson(branding).map_err(|e| HttpResponse::InternalServerError().body(format!("Serialize error: {}", e)))?;
+    match teams
+        .update_one(doc! { "team_id": team_id }, doc! { "$set": { "branding": value } })
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(HttpResponse::InternalServerError().body(format!("Error updating team: {}", e))),
+    }
+}
+
+/// PUT /teams/{team_id}/branding
+pub async fn update_branding(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<UpdateBrandingRequest>,
+) -> impl Responder {
+    let team_id = team_id.into_inner();
+    let current_user = auth.user_id().to_string();
+    if let Err(resp) = require_admin(&data, &team_id, &current_user).await {
+        return resp;
+    }
+
+    let mut branding = match current_branding(&data, &team_id).await {
+        Ok(b) => b,
+        Err(resp) => return resp,
+    };
+
+    if let Some(color) = &payload.accent_color {
+        static HEX: OnceLock<Regex> = OnceLock::new();
+        let hex = HEX.get_or_init(|| Regex::new(r"^#[0-9a-fA-F]{6}$").expect("valid color regex"));
+        if color.is_empty() {
+            branding.accent_color = None;
+        } else if hex.is_match(color) {
+            branding.accent_color = Some(color.to_lowercase());
+        } else {
+            return HttpResponse::BadRequest().body("accent_color must look like #RRGGBB");
+        }
+    }
+    if let Some(about) = &payload.about {
+        if about.chars().count() > MAX_ABOUT_CHARS {
+            return HttpResponse::BadRequest().body("About page is too long");
+        }
+        branding.about = if about.trim().is_empty() { None } else { Some(about.clone()) };
+    }
+
+    match save_branding(&data, &team_id, &branding).await {
+        Ok(()) => HttpResponse::Ok().json(branding),
+        Err(resp) => resp,
+    }
+}
+
+/// POST /teams/{team_id}/branding/logo
+/// Multipart upload; the first file part becomes the team logo.
+pub async fn upload_logo(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    mut payload: Multipart,
+) -> impl Responder {
+    let team_id = team_id.into_inner();
+    let current_user = auth.user_id().to_string();
+    if let Err(resp) = require_admin(&data, &team_id, &current_user).await {
+        return resp;
+    }
+    let mut branding = match current_branding(&data, &team_id).await {
+        Ok(b) => b,
+        Err(resp) => return resp,
+    };
+
+    let mut field = match payload.next().await {
+        Some(Ok(f)) => f,
+        Some(Err(e)) => return HttpResponse::BadRequest().body(format!("Malformed multipart: {}", e)),
+        None => return HttpResponse::BadRequest().body("No logo was uploaded"),
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = match chunk {
+            Ok(c) => c,
+            Err(e) => return HttpResponse::BadRequest().body(format!("Upload interrupted: {}", e)),
+        };
+        if bytes.len() + chunk.len() > MAX_LOGO_BYTES {
+            return HttpResponse::PayloadTooLarge().body("Logo exceeds the size limit");
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    let content_type = match logo_content_type(&bytes) {
+        Some(t) => t,
+        None => return HttpResponse::BadRequest().body("Logo must be a PNG, JPEG, GIF or WebP image"),
+    };
+
+    let bucket = data.mongodb.db.gridfs_bucket(None);
+    let mut upload = match bucket
+        .open_upload_stream(format!("team_logo_{}", team_id))
+        .metadata(doc! { "team_id": &team_id, "content_type": content_type })
+        .await
+    {
+        Ok(u) => u,
+        Err(e) => {
+            error!("Error opening logo upload: {}", e);
+            return HttpResponse::InternalServerError().body("Error storing logo");
+        }
+    };
+    if let Err(e) = upload.write_all(&bytes).await {
+        error!("Error writing logo: {}", e);
+        return HttpResponse::InternalServerError().body("Error storing logo");
+    }
+    if let Err(e) = upload.close().await {
+        error!("Error finalizing logo: {}", e);
+        return HttpResponse::InternalServerError().body("Error storing logo");
+    }
+    let new_logo = match upload.id().as_object_id() {
+        Some(id) => id,
+        None => return HttpResponse::InternalServerError().body("Error storing logo"),
+    };
+
+    // Drop the previous logo once the new one is referenced.
+    let old_logo = branding.logo_id.replace(new_logo.to_hex());
+    if let Err(resp) = save_branding(&data, &team_id, &branding).await {
+        return resp;
+    }
+    if let Some(old) = old_logo.and_then(|id| ObjectId::parse_str(id).ok()) {
+        let _ = bucket.delete(Bson::ObjectId(old)).await;
+    }
+
+    info!("Logo updated for team {}", team_id);
+    HttpResponse::Ok().json(branding)
+}
+
+/// GET /teams/{team_id}/branding/logo
+/// Public so invite pages can show the logo before login.
+pub async fn get_logo(
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+) -> impl Responder {
+    let branding = match current_branding(&data, &team_id).await {
+        Ok(b) => b,
+        Err(resp) => return resp,
+    };
+    let oid = match branding.logo_id.and_then(|id| ObjectId::parse_str(id).ok()) {
+        Some(oid) => oid,
+        None => return HttpResponse::NotFound().body("Team has no logo"),
+    };
+
+    let bucket = data.mongodb.db.gridfs_bucket(None);
+    let content_type = match bucket.find_one(doc! { "_id": oid }).await {
+        Ok(Some(file)) => file
+            .metadata
+            .as_ref()
+            .and_then(|m| m.get_str("content_type").ok())
+            .filter(|t| LOGO_TYPES.contains(t))
+            .unwrap_or("application/octet-stream")
+            .to_string(),
+        Ok(None) => return HttpResponse::NotFound().body("Team has no logo"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error fetching logo: {}", e)),
+    };
+    let mut stream = match bucket.open_download_stream(Bson::ObjectId(oid)).await {
+        Ok(s) => s,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error fetching logo: {}", e)),
+    };
+    let mut bytes = Vec::new();
+    if let Err(e) = stream.read_to_end(&mut bytes).await {
+        return HttpResponse::InternalServerError().body(format!("Error fetching logo: {}", e));
+    }
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .body(bytes)
+}
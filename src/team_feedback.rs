@@ -0,0 +1,301 @@
+// src/team_feedback.rs
+//
+// Anonymous feedback box per team. Members post suggestions and concerns while
+// signed in, but the author is only stored as a keyed hash of team and user, so
+// admins, and anyone with database access but not the server secret, cannot tell who
+// wrote what. The hash still lets authors list their own items and caps how much
+// one member can post per day. Timestamps are kept to the day, as exact times would
+// point back at whoever was online. Admins can turn an item into a ticket; the
+// ticket is reported by the admin. For the same reason items get a random `_id`
+// rather than an ObjectId, whose embedded timestamp is exact to the second, and
+// posting is kept out of the API usage logs.
+
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::{error, info};
+use mongodb::bson::{self, doc, Document};
+use ring::{hkdf, hmac};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::activity::{record_activity, ActivityEvent};
+use crate::api_logs::Unlogged;
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::Board;
+use crate::chat_db::MongoDB;
+use crate::response::CodedError;
+use crate::tenancy::Repo;
+use crate::ticket::Ticket;
+use crate::ticket_events::{commit, TicketChange};
+
+const MAX_TEXT_LEN: usize = 4000;
+/// Items one member may post per team and day.
+const MAX_PER_DAY: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackCategory {
+    Suggestion,
+    Concern,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Feedback {
+    /// Same UUID as `feedback_id`; items posted before it was set have an ObjectId
+    #[serde(rename = "_id", skip_deserializing)]
+    pub id: String,
+    pub feedback_id: String,
+    pub team_id: String,
+    /// Keyed hash of team and author, see `author_hash`
+    pub author_hash: String,
+    pub category: FeedbackCategory,
+    pub text: String,
+    /// Start of the UTC day the item was posted
+    pub created_at: DateTime<Utc>,
+    /// Ticket the item was turned into
+    pub ticket_id: Option<String>,
+}
+
+/// What admins and authors see: no trace of the author.
+#[derive(Debug, Serialize)]
+pub struct FeedbackView {
+    pub feedback_id: String,
+    pub category: FeedbackCategory,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+    pub ticket_id: Option<String>,
+}
+
+impl From<Feedback> for FeedbackView {
+    fn from(f: Feedback) -> Self {
+        FeedbackView { feedback_id: f.feedback_id, category: f.category, text: f.text, created_at: f.created_at, ticket_id: f.ticket_id }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubmitFeedbackRequest {
+    pub category: FeedbackCategory,
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConvertFeedbackRequest {
+    pub project_id: String,
+    pub board_id: String,
+    /// Defaults to the first line of the feedback
+    pub title: Option<String>,
+    pub status: Option<String>,
+    pub priority: Option<String>,
+}
+
+fn feedback_coll(db: &MongoDB) -> mongodb::Collection<Feedback> {
+    db.db.collection::<Feedback>("team_feedback")
+}
+
+/// HMAC of team and user under a key derived from the JWT secret: stable per team,
+/// so the same member cannot be followed across teams either.
+fn author_hash(data: &AppState, team_id: &str, user_id: &str) -> String {
+    let key: hmac::Key = hkdf::Salt::new(hkdf::HKDF_SHA256, &[])
+        .extract(data.config.jwt_secret.as_bytes())
+        .expand(&[b"team-feedback"], hmac::HMAC_SHA256)
+        .expect("an HMAC key is a valid HKDF length")
+        .into();
+    let tag = hmac::sign(&key, format!("feedback:{}:{}", team_id, user_id).as_bytes());
+    tag.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn start_of_day(t: DateTime<Utc>) -> DateTime<Utc> {
+    t.date_naive().and_hms_opt(0, 0, 0).map_or(t, |d| d.and_utc())
+}
+
+async fn list(db: &MongoDB, filter: Document) -> mongodb::error::Result<Vec<FeedbackView>> {
+    let mut cursor = feedback_coll(db).find(filter).sort(doc! { "created_at": -1 }).await?;
+    let mut items = Vec::new();
+    while let Some(f) = cursor.next().await {
+        items.push(FeedbackView::from(f?));
+    }
+    Ok(items)
+}
+
+/// POST /teams/{team_id}/feedback
+pub async fn submit_feedback(
+    req: HttpRequest,
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<SubmitFeedbackRequest>,
+) -> impl Responder {
+    req.extensions_mut().insert(Unlogged);
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    let text = payload.text.trim();
+    if text.is_empty() || text.chars().count() > MAX_TEXT_LEN {
+        return HttpResponse::BadRequest().body(format!("text must be between 1 and {} characters", MAX_TEXT_LEN));
+    }
+    let author = author_hash(&data, &team_id, auth.user_id());
+    let today = start_of_day(Utc::now());
+    let posted_today = doc! { "author_hash": &author, "created_at": bson::to_bson(&today).unwrap_or_default() };
+    match feedback_coll(&data.mongodb).count_documents(posted_today).await {
+        Ok(n) if n >= MAX_PER_DAY => {
+            return HttpResponse::TooManyRequests().body(format!("At most {} items can be posted per day", MAX_PER_DAY));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("Error counting feedback: {}", e);
+            return HttpResponse::InternalServerError().body("Error saving feedback");
+        }
+    }
+
+    let feedback_id = Uuid::new_v4().to_string();
+    let item = Feedback {
+        id: feedback_id.clone(),
+        feedback_id,
+        team_id: team_id.into_inner(),
+        author_hash: author,
+        category: payload.category,
+        text: text.to_string(),
+        created_at: today,
+        ticket_id: None,
+    };
+    match feedback_coll(&data.mongodb).insert_one(&item).await {
+        Ok(_) => HttpResponse::Ok().json(FeedbackView::from(item)),
+        Err(e) => {
+            error!("Error saving feedback: {}", e);
+            HttpResponse::InternalServerError().body("Error saving feedback")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/feedback
+/// Team admins only.
+pub async fn list_feedback(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can read feedback");
+    }
+    match list(&data.mongodb, doc! { "team_id": &*team_id }).await {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(e) => {
+            error!("Error fetching feedback: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching feedback")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/feedback/mine
+pub async fn list_my_feedback(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    let author = author_hash(&data, &team_id, auth.user_id());
+    match list(&data.mongodb, doc! { "team_id": &*team_id, "author_hash": author }).await {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(e) => {
+            error!("Error fetching feedback: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching feedback")
+        }
+    }
+}
+
+/// POST /teams/{team_id}/feedback/{feedback_id}/ticket
+/// Team admins only; the item keeps a link to the ticket.
+pub async fn convert_feedback(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<ConvertFeedbackRequest>,
+) -> impl Responder {
+    let (team_id, feedback_id) = path.into_inner();
+    let current_user = auth.user_id().to_string();
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can convert feedback");
+    }
+    let scope = match auth.project_scope(&team_id, &payload.project_id).await {
+        Ok(scope) => scope,
+        Err(resp) => return resp,
+    };
+    let board_ok = Repo::<Board>::new(&data.mongodb, &scope)
+        .find_one(doc! { "board_id": &payload.board_id })
+        .await
+        .ok()
+        .flatten()
+        .is_some();
+    if !board_ok {
+        return HttpResponse::NotFound().error("board_not_found");
+    }
+    let item = match feedback_coll(&data.mongodb).find_one(doc! { "feedback_id": &feedback_id, "team_id": &team_id }).await {
+        Ok(Some(f)) => f,
+        Ok(None) => return HttpResponse::NotFound().body("Feedback not found"),
+        Err(e) => {
+            error!("Error fetching feedback: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching feedback");
+        }
+    };
+    if item.ticket_id.is_some() {
+        return HttpResponse::BadRequest().body("Feedback was already turned into a ticket");
+    }
+
+    let title = payload
+        .title
+        .clone()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| item.text.lines().next().unwrap_or_default().chars().take(120).collect());
+    let ticket = Ticket {
+        id: None,
+        ticket_id: Uuid::new_v4().to_string(),
+        board_id: payload.board_id.clone(),
+        project_id: payload.project_id.clone(),
+        title,
+        description: Some(item.text.clone()),
+        status: payload.status.clone().unwrap_or_else(|| "To Do".to_string()),
+        priority: payload.priority.clone(),
+        reporter: current_user.clone(),
+        requester_email: None,
+        assignee: None,
+        due_date: None,
+        ticket_type: Some(match item.category {
+            FeedbackCategory::Suggestion => "Suggestion".to_string(),
+            FeedbackCategory::Concern => "Concern".to_string(),
+        }),
+        sprint: None,
+        rank: None,
+        labels: Some(vec!["feedback".to_string()]),
+        fix_version: None,
+        estimate_hours: None,
+        blocked_by: Vec::new(),
+        attachments: None,
+        comments: Some(vec![]),
+        references: Vec::new(),
+        created_at: Utc::now(),
+        resolution: None,
+        resolved_at: None,
+        resolved_by: None,
+        vote_count: 0,
+        version: 0,
+    };
+    let ticket = match commit(&data.mongodb, None, vec![TicketChange::Created { ticket: Box::new(ticket) }], &current_user).await {
+        Ok(Some(t)) => t,
+        Ok(None) | Err(_) => {
+            error!("Error inserting ticket for feedback {}", feedback_id);
+            return HttpResponse::InternalServerError().body("Error inserting ticket");
+        }
+    };
+    let link = doc! { "$set": { "ticket_id": &ticket.ticket_id } };
+    if let Err(e) = feedback_coll(&data.mongodb).update_one(doc! { "feedback_id": &feedback_id }, link).await {
+        error!("Error linking feedback {} to its ticket: {}", feedback_id, e);
+    }
+
+    info!("Feedback {} converted to ticket {}", feedback_id, ticket.ticket_id);
+    record_activity(&data.mongodb, ActivityEvent::new(
+        &team_id,
+        Some(&payload.project_id),
+        &current_user,
+        "ticket_created",
+        &ticket.ticket_id,
+        format!("created ticket \"{}\" from team feedback", ticket.title),
+    )).await;
+    HttpResponse::Ok().json(ticket)
+}
@@ -0,0 +1,316 @@
+// src/team_home.rs
+//
+// Team home page: an ordered list of pins chosen by team admins. A pin is a knowledge
+// base document, a board, an external link or a milestone: either a given release,
+// or whichever unshipped release of the team's projects is due next. GET
+// /teams/{team_id}/home resolves the pins for the caller in one call and silently
+// drops those they may not see: documents they cannot read, boards and releases of
+// projects they are not in, and pins whose target was deleted.
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{self, doc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::Board;
+use crate::knowledge_base::Document;
+use crate::project::Project;
+use crate::release::Release;
+use crate::tenancy::{project_team_id, Repo, TeamScope};
+
+const MAX_PINS: usize = 50;
+const MAX_LABEL_LEN: usize = 120;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Pin {
+    Doc { doc_id: String },
+    Board { board_id: String },
+    Link { title: String, url: String },
+    /// A release, or the next one due when `release_id` is None
+    Milestone { release_id: Option<String> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinnedItem {
+    pub pin_id: String,
+    /// Shown instead of the target's own title
+    pub label: Option<String>,
+    #[serde(flatten)]
+    pub pin: Pin,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamHome {
+    pub team_id: String,
+    pub items: Vec<PinnedItem>,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PinRequest {
+    pub label: Option<String>,
+    #[serde(flatten)]
+    pub pin: Pin,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateTeamHomeRequest {
+    pub items: Vec<PinRequest>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Resource {
+    Doc { doc_id: String, title: String, updated_at: DateTime<Utc>, author_id: Option<String> },
+    Board { board_id: String, project_id: String, name: String, board_type: String },
+    Link { title: String, url: String },
+    Milestone { release_id: String, project_id: String, name: String, release_date: Option<DateTime<Utc>>, status: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResolvedPin {
+    pub pin_id: String,
+    pub label: Option<String>,
+    #[serde(flatten)]
+    pub resource: Resource,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamHomeView {
+    pub team_id: String,
+    pub items: Vec<ResolvedPin>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+fn homes_coll(data: &AppState) -> mongodb::Collection<TeamHome> {
+    data.mongodb.db.collection::<TeamHome>("team_homes")
+}
+
+async fn find_release(data: &AppState, release_id: &str) -> mongodb::error::Result<Option<Release>> {
+    data.mongodb.db.collection::<Release>("releases").find_one(doc! { "release_id": release_id }).await
+}
+
+/// Projects of the team the caller is a member of.
+async fn member_projects(auth: &AuthContext, data: &AppState, team: &TeamScope) -> mongodb::error::Result<Vec<String>> {
+    let mut cursor = Repo::<Project>::new(&data.mongodb, team).find(doc! {}).await?;
+    let mut ids = Vec::new();
+    while let Some(p) = cursor.next().await {
+        let p = p?;
+        if auth.is_project_member(&p.project_id).await {
+            ids.push(p.project_id);
+        }
+    }
+    Ok(ids)
+}
+
+/// The pin's resource as the caller may see it; None when hidden or gone.
+async fn resolve(auth: &AuthContext, data: &AppState, team: &TeamScope, pin: &Pin) -> mongodb::error::Result<Option<Resource>> {
+    let (user_id, team_id) = (auth.user_id(), team.team_id());
+    Ok(match pin {
+        Pin::Doc { doc_id } => data.mongodb.db
+            .collection::<Document>("knowledge_base")
+            .find_one(doc! { "_id": doc_id, "team_id": team_id })
+            .await?
+            .filter(|d| d.readable_by(user_id))
+            .map(|d| Resource::Doc { doc_id: d.id, title: d.title, updated_at: d.updated_at, author_id: d.author_id }),
+        Pin::Board { board_id } => {
+            let projects = team.projects(&data.mongodb).await?;
+            let Some(board) = Repo::<Board>::across(&data.mongodb, &projects).find_one(doc! { "board_id": board_id }).await? else {
+                return Ok(None);
+            };
+            let visible = board.participants.iter().any(|p| p == user_id) || auth.is_project_member(&board.project_id).await;
+            visible.then_some(Resource::Board {
+                board_id: board.board_id,
+                project_id: board.project_id,
+                name: board.name,
+                board_type: board.board_type,
+            })
+        }
+        Pin::Link { title, url } => Some(Resource::Link { title: title.clone(), url: url.clone() }),
+        Pin::Milestone { release_id: Some(release_id) } => {
+            let Some(release) = find_release(data, release_id).await? else {
+                return Ok(None);
+            };
+            let visible = project_team_id(&data.mongodb, &release.project_id).await.as_deref() == Some(team_id)
+                && auth.is_project_member(&release.project_id).await;
+            visible.then(|| milestone(release))
+        }
+        Pin::Milestone { release_id: None } => {
+            let projects = member_projects(auth, data, team).await?;
+            let filter = doc! {
+                "project_id": { "$in": projects },
+                "status": { "$ne": "shipped" },
+                "release_date": { "$gte": bson::to_bson(&Utc::now()).unwrap_or_default() },
+            };
+            data.mongodb.db
+                .collection::<Release>("releases")
+                .find_one(filter)
+                .sort(doc! { "release_date": 1 })
+                .await?
+                .map(milestone)
+        }
+    })
+}
+
+fn milestone(r: Release) -> Resource {
+    Resource::Milestone { release_id: r.release_id, project_id: r.project_id, name: r.name, release_date: r.release_date, status: r.status }
+}
+
+async fn build_view(auth: &AuthContext, data: &AppState, team: &TeamScope, home: Option<TeamHome>) -> mongodb::error::Result<TeamHomeView> {
+    let (items, updated_at) = match home {
+        Some(h) => (h.items, Some(h.updated_at)),
+        None => (Vec::new(), None),
+    };
+    let mut resolved = Vec::with_capacity(items.len());
+    for item in items {
+        if let Some(resource) = resolve(auth, data, team, &item.pin).await? {
+            resolved.push(ResolvedPin { pin_id: item.pin_id, label: item.label, resource });
+        }
+    }
+    Ok(TeamHomeView { team_id: team.team_id().to_string(), items: resolved, updated_at })
+}
+
+/// Checks that every pin points at something of the team.
+async fn validate(data: &AppState, team: &TeamScope, items: &[PinRequest]) -> Result<(), String> {
+    let team_id = team.team_id();
+    if items.len() > MAX_PINS {
+        return Err(format!("The team home can have at most {} pins", MAX_PINS));
+    }
+    let db_error = |e: mongodb::error::Error| {
+        error!("Error validating team home: {}", e);
+        "Error validating pins".to_string()
+    };
+    for item in items {
+        if item.label.as_ref().is_some_and(|l| l.chars().count() > MAX_LABEL_LEN) {
+            return Err(format!("Labels can be at most {} characters", MAX_LABEL_LEN));
+        }
+        match &item.pin {
+            Pin::Doc { doc_id } => {
+                let found = data.mongodb.db
+                    .collection::<Document>("knowledge_base")
+                    .find_one(doc! { "_id": doc_id, "team_id": team_id })
+                    .await
+                    .map_err(db_error)?;
+                if found.is_none() {
+                    return Err(format!("Document {} is not a document of this team", doc_id));
+                }
+            }
+            Pin::Board { board_id } => {
+                let projects = team.projects(&data.mongodb).await.map_err(db_error)?;
+                let board = Repo::<Board>::across(&data.mongodb, &projects)
+                    .find_one(doc! { "board_id": board_id })
+                    .await
+                    .map_err(db_error)?;
+                if board.is_none() {
+                    return Err(format!("Board {} is not a board of this team", board_id));
+                }
+            }
+            Pin::Link { title, url } => {
+                if title.trim().is_empty() {
+                    return Err("Links need a title".to_string());
+                }
+                if !(url.starts_with("https://") || url.starts_with("http://")) {
+                    return Err("Links must be http(s) URLs".to_string());
+                }
+            }
+            Pin::Milestone { release_id: Some(release_id) } => {
+                let in_team = match find_release(data, release_id).await.map_err(db_error)? {
+                    Some(r) => project_team_id(&data.mongodb, &r.project_id).await.as_deref() == Some(team_id),
+                    None => false,
+                };
+                if !in_team {
+                    return Err(format!("Release {} is not a release of this team", release_id));
+                }
+            }
+            Pin::Milestone { release_id: None } => {}
+        }
+    }
+    Ok(())
+}
+
+/// GET /teams/{team_id}/home
+pub async fn get_team_home(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    let team = match auth.team_scope(&team_id).await {
+        Ok(team) => team,
+        Err(resp) => return resp,
+    };
+    let home = match homes_coll(&data).find_one(doc! { "team_id": &*team_id }).await {
+        Ok(h) => h,
+        Err(e) => {
+            error!("Error fetching team home: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching team home");
+        }
+    };
+    match build_view(&auth, &data, &team, home).await {
+        Ok(view) => HttpResponse::Ok().json(view),
+        Err(e) => {
+            error!("Error resolving team home: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching team home")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/home/config
+/// Team admins only; the stored pins, including ones the admin cannot see.
+pub async fn get_team_home_config(auth: AuthContext, data: web::Data<AppState>, team_id: web::Path<String>) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage the team home");
+    }
+    match homes_coll(&data).find_one(doc! { "team_id": &*team_id }).await {
+        Ok(Some(home)) => HttpResponse::Ok().json(home.items),
+        Ok(None) => HttpResponse::Ok().json(Vec::<PinnedItem>::new()),
+        Err(e) => {
+            error!("Error fetching team home: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching team home")
+        }
+    }
+}
+
+/// PUT /teams/{team_id}/home
+/// Team admins only; replaces the pins, in the given order, and returns the home.
+pub async fn update_team_home(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<UpdateTeamHomeRequest>,
+) -> impl Responder {
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Forbidden().body("Only team admins can manage the team home");
+    }
+    let team = match auth.team_scope(&team_id).await {
+        Ok(team) => team,
+        Err(resp) => return resp,
+    };
+    if let Err(msg) = validate(&data, &team, &payload.items).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    let items = payload
+        .into_inner()
+        .items
+        .into_iter()
+        .map(|p| PinnedItem {
+            pin_id: Uuid::new_v4().to_string(),
+            label: p.label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty()),
+            pin: p.pin,
+        })
+        .collect();
+    let home = TeamHome { team_id: team_id.to_string(), items, updated_by: auth.user_id().to_string(), updated_at: Utc::now() };
+    if let Err(e) = homes_coll(&data).replace_one(doc! { "team_id": &*team_id }, &home).upsert(true).await {
+        error!("Error saving team home: {}", e);
+        return HttpResponse::InternalServerError().body("Error saving team home");
+    }
+    match build_view(&auth, &data, &team, Some(home)).await {
+        Ok(view) => HttpResponse::Ok().json(view),
+        Err(e) => {
+            error!("Error resolving team home: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching team home")
+        }
+    }
+}
@@ -1,14 +1,25 @@
 // File: team-management.rs
-use actix_web::{web, HttpResponse, Responder, HttpRequest, HttpMessage};
+use actix_web::{web, HttpResponse, Responder, HttpRequest};
 use futures_util::StreamExt;
-use mongodb::bson::{doc, to_document, DateTime as BsonDateTime, oid::ObjectId};
+use mongodb::bson::{doc, to_bson, to_document, DateTime as BsonDateTime, oid::ObjectId};
+use mongodb::error::{ErrorKind, WriteFailure};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 use chrono::Utc;
 use log::{debug, error, info};
 
+use crate::activity::{record_activity, ActivityEvent};
 use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::do_not_disturb::notify_user;
+use crate::encryption::EncryptedString;
 use crate::models::Chat;
+use crate::response::{ApiResponse, CodedError, ok, ok_message};
+use crate::role_claims;
+use crate::team_branding::TeamBranding;
+use crate::team_time::parse_timezone;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Team {
@@ -17,6 +28,11 @@ pub struct Team {
     pub owner_id: String,
     pub description: Option<String>,
     pub created_at: chrono::DateTime<Utc>,
+    #[serde(default)]
+    pub branding: Option<TeamBranding>,
+    /// IANA time zone used for dashboards and due dates; UTC when unset
+    #[serde(default)]
+    pub timezone: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -39,6 +55,10 @@ pub struct TeamInvitation {
     pub status: String,       // "pending", "accepted", or "declined"
     pub sent_at: chrono::DateTime<Utc>,
     pub responded_at: Option<chrono::DateTime<Utc>>,
+    // Address of an old invitation that held it in invitee_id, moved here by
+    // encryption rotation (invitee_id then holds its hash).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invitee_email: Option<EncryptedString>,
 }
 
 pub type TeamMember = UserTeam;
@@ -67,12 +87,14 @@ pub struct InvitationDisplay {
     pub team_id: String,
     pub team_name: String,
     pub inviter_username: String,
+    pub branding: Option<TeamBranding>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CreateTeamRequest {
     pub name: String,
     pub description: String,
+    pub timezone: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -89,6 +111,7 @@ pub struct RespondInvitationRequest {
 pub struct UpdateTeamRequest {
     pub name: String,
     pub new_owner_id: Option<String>,
+    pub timezone: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -107,16 +130,11 @@ pub struct DeleteInvitationsRequest {
 /// The endpoint verifies that the JWT user matches the requested user.
 /// It then filters for invitations where invitee_id equals the user’s hex string.
 pub async fn get_pending_invitations(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     user_id: web::Path<String>,
 ) -> impl Responder {
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.trim().to_string()
-    } else {
-        error!("No user found in request extensions for get_pending_invitations");
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().trim().to_string();
 
     let requested_user = user_id.trim().to_string();
     debug!("Token user id: '{}' | Requested user id: '{}'", current_user, requested_user);
@@ -147,7 +165,10 @@ pub async fn get_pending_invitations(
                 // Look up team info.
                 let team_filter = doc! { "team_id": &inv.team_id };
                 let team_doc = teams_collection.find_one(team_filter).await.ok().flatten();
-                let team_name = team_doc.map(|t| t.name).unwrap_or_else(|| "Unknown Team".into());
+                let (team_name, branding) = match team_doc {
+                    Some(t) => (t.name, t.branding),
+                    None => ("Unknown Team".into(), None),
+                };
 
                 // Look up inviter info.
                 let inviter_obj_id = ObjectId::parse_str(&inv.inviter_id).ok();
@@ -167,6 +188,7 @@ pub async fn get_pending_invitations(
                     team_id: inv.team_id,
                     team_name,
                     inviter_username,
+                    branding,
                 });
             },
             Err(err) => {
@@ -176,19 +198,15 @@ pub async fn get_pending_invitations(
         }
     }
 
-    HttpResponse::Ok().json(displays)
+    ok(displays)
 }
 
 pub async fn get_user_teams(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     user_id: web::Path<String>,
 ) -> impl Responder {
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
 
     if current_user != *user_id {
         return HttpResponse::Unauthorized().body("Cannot access other user's teams");
@@ -218,7 +236,7 @@ pub async fn get_user_teams(
         }
     }
 
-    HttpResponse::Ok().json(user_teams)
+    ok(user_teams)
 }
 
 pub async fn get_user_chats(
@@ -249,21 +267,21 @@ pub async fn get_user_chats(
         }
     }
 
-    HttpResponse::Ok().json(chats)
+    ok(chats)
 }
 
 pub async fn create_team(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     team_info: web::Json<CreateTeamRequest>,
 ) -> impl Responder {
     debug!("create_team endpoint called with payload: {:?}", team_info);
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.clone()
-    } else {
-        error!("Unauthorized: No authenticated user found in request extensions");
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
+    if let Some(tz) = &team_info.timezone {
+        if let Err(msg) = parse_timezone(tz) {
+            return HttpResponse::BadRequest().body(msg);
+        }
+    }
 
     let teams_collection = data.mongodb.db.collection::<Team>("teams");
     let user_teams_collection = data.mongodb.db.collection::<UserTeam>("user_teams");
@@ -275,6 +293,8 @@ pub async fn create_team(
         owner_id: current_user.clone(),
         description: Some(team_info.description.clone()),
         created_at: Utc::now(),
+        branding: None,
+        timezone: team_info.timezone.clone(),
     };
 
     debug!("Creating team with new_team: {:?}", new_team);
@@ -290,6 +310,7 @@ pub async fn create_team(
             debug!("Inserting user_team membership: {:?}", user_team);
             match user_teams_collection.insert_one(&user_team).await {
                 Ok(_) => {
+                    role_claims::bump(&data.mongodb, std::slice::from_ref(&current_user)).await;
                     let users_collection = data.mongodb.db.collection::<mongodb::bson::Document>("users");
                     if let Ok(oid) = ObjectId::parse_str(&current_user) {
                         let user_filter = doc! { "_id": oid };
@@ -297,7 +318,7 @@ pub async fn create_team(
                         let _ = users_collection.update_one(user_filter, user_update).await;
                     }
                     info!("Team created successfully: {:?}", new_team);
-                    HttpResponse::Ok().json(new_team)
+                    ok(new_team)
                 },
                 Err(err) => {
                     error!("Error assigning team admin: {}", err);
@@ -314,25 +335,71 @@ pub async fn create_team(
     }
 }
 
+/// Resolves a user reference to the hex `_id` of an existing user. Accepts the
+/// id itself, an email address or a username, tried in that order.
+pub async fn resolve_user_id(db: &MongoDB, reference: &str) -> Option<String> {
+    let users_collection = db.db.collection::<User>("users");
+    let filters = match ObjectId::parse_str(reference) {
+        Ok(oid) => vec![doc! { "_id": oid }],
+        Err(_) => vec![doc! { "email": reference }, doc! { "username": reference }],
+    };
+    for filter in filters {
+        if let Ok(Some(user)) = users_collection.find_one(filter).await {
+            return Some(user.id.to_hex());
+        }
+    }
+    None
+}
+
+/// Tells the other side of an invitation about it over the WebSocket: the invitee
+/// when it is sent (`team_invitation`), the inviter when it is answered
+/// (`team_invitation_response`). Offline users see pending invitations through
+/// `get_pending_invitations`; users in DND get it in their summary.
+async fn notify_invitation(data: &AppState, invitation: &TeamInvitation, actor_id: &str) {
+    let team_name = match data.mongodb.db.collection::<Team>("teams").find_one(doc! { "team_id": &invitation.team_id }).await {
+        Ok(team) => team.map(|t| t.name),
+        Err(e) => {
+            error!("Error fetching team {}: {}", invitation.team_id, e);
+            None
+        }
+    };
+    let (recipient, payload) = if invitation.status == "pending" {
+        (&invitation.invitee_id, serde_json::json!({
+            "type": "team_invitation",
+            "invitation_id": invitation.invitation_id,
+            "team_id": invitation.team_id,
+            "team_name": team_name,
+            "inviter_id": actor_id,
+            "sent_at": invitation.sent_at,
+        }))
+    } else {
+        (&invitation.inviter_id, serde_json::json!({
+            "type": "team_invitation_response",
+            "invitation_id": invitation.invitation_id,
+            "team_id": invitation.team_id,
+            "team_name": team_name,
+            "invitee_id": actor_id,
+            "status": invitation.status,
+            "responded_at": invitation.responded_at,
+        }))
+    };
+    notify_user(data, recipient, payload.to_string()).await;
+}
+
 /// Updated invite_user endpoint using the "find_user_email" fix logic.
 /// We now attempt to resolve the invitee_id: if it's not a valid ObjectId, we search by email then by username.
 pub async fn invite_user(
     req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     invite_info: web::Json<InviteRequest>,
 ) -> impl Responder {
     let team_id = req.match_info().get("team_id").unwrap_or("").to_string();
 
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.clone()
-    } else {
-        error!("Unauthorized: No authenticated user found in invite_user");
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
 
     let user_teams_collection = data.mongodb.db.collection::<UserTeam>("user_teams");
     let invitations_collection = data.mongodb.db.collection::<TeamInvitation>("team_invitations");
-    let users_collection = data.mongodb.db.collection::<User>("users");
 
     // Ensure the requester is an admin of the team.
     let admin_filter = doc! {
@@ -343,22 +410,10 @@ pub async fn invite_user(
 
     match user_teams_collection.find_one(admin_filter).await {
         Ok(Some(_)) => {
-            // Resolve invitee_id: if it’s a valid ObjectId, use it;
-            // otherwise, try to find a user by email then by username.
-            let resolved_invitee_id = if ObjectId::parse_str(&invite_info.invitee_id).is_ok() {
-                invite_info.invitee_id.clone()
-            } else {
-                let email_filter = doc! { "email": &invite_info.invitee_id };
-                if let Ok(Some(user)) = users_collection.find_one(email_filter).await {
-                    user.id.to_hex()
-                } else {
-                    let username_filter = doc! { "username": &invite_info.invitee_id };
-                    if let Ok(Some(user)) = users_collection.find_one(username_filter).await {
-                        user.id.to_hex()
-                    } else {
-                        return HttpResponse::BadRequest().body("User not found by email or username");
-                    }
-                }
+            // Resolve invitee_id: an existing user's id, email or username.
+            let resolved_invitee_id = match resolve_user_id(&data.mongodb, &invite_info.invitee_id).await {
+                Some(id) => id,
+                None => return HttpResponse::BadRequest().body("User not found by email or username"),
             };
 
             let member_filter = doc! {
@@ -386,12 +441,14 @@ pub async fn invite_user(
                 status: "pending".to_string(),
                 sent_at: Utc::now(),
                 responded_at: None,
+                invitee_email: None,
             };
 
-            match invitations_collection.insert_one(new_invitation).await {
+            match invitations_collection.insert_one(&new_invitation).await {
                 Ok(_) => {
                     info!("User {} invited to team {}", resolved_invitee_id, team_id);
-                    HttpResponse::Ok().body("Invitation sent successfully")
+                    notify_invitation(&data, &new_invitation, &current_user).await;
+                    ok_message("Invitation sent successfully")
                 },
                 Err(err) => {
                     error!("Error inviting user: {}", err);
@@ -406,204 +463,236 @@ pub async fn invite_user(
     }
 }
 
+const MAX_MEMBERS_PAGE_SIZE: u64 = 200;
+/// Caps the skip sent to the database; no team comes near this many pages.
+const MAX_MEMBERS_PAGE: u64 = 100_000;
+
+#[derive(Debug, Deserialize)]
+pub struct TeamMembersQuery {
+    pub page: Option<u64>,
+    pub page_size: Option<u64>,
+}
+
+/// Returns accepted members followed by pending invitations.
+/// Users are resolved with a single batched query instead of one lookup per row.
+/// When `page`/`page_size` are supplied the combined list is paginated and the
+/// overall count is reported in the `X-Total-Count` header.
 pub async fn get_team_members(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     team_id: web::Path<String>,
+    query: web::Query<TeamMembersQuery>,
 ) -> impl Responder {
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().body("You are not a member of this team");
+    }
 
     let user_teams_collection = data.mongodb.db.collection::<UserTeam>("user_teams");
-    let membership_filter = doc! {
-        "team_id": &*team_id,
-        "user_id": &current_user,
-    };
 
-    match user_teams_collection.find_one(membership_filter).await {
-        Ok(Some(_)) => {
-            let mut combined_members: Vec<TeamMemberInfo> = Vec::new();
+    let invitations_collection = data.mongodb.db.collection::<TeamInvitation>("team_invitations");
+    let member_filter = doc! { "team_id": &*team_id };
+    let inv_filter = doc! { "team_id": &*team_id, "status": "pending" };
 
-            // First: get all accepted members in user_teams
-            let filter = doc! { "team_id": &*team_id };
-            let mut cursor = match user_teams_collection.find(filter).await {
-                Ok(cursor) => cursor,
-                Err(err) => {
-                    return HttpResponse::InternalServerError()
-                        .body(format!("Error fetching team members: {}", err))
-                }
-            };
+    let total_members = match user_teams_collection.count_documents(member_filter.clone()).await {
+        Ok(n) => n,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Error counting team members: {}", err))
+        }
+    };
+    let total_invitations = match invitations_collection.count_documents(inv_filter.clone()).await {
+        Ok(n) => n,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Error counting invitations: {}", err))
+        }
+    };
 
-            let users_collection = data.mongodb.db.collection::<User>("users");
-
-            while let Some(member_res) = cursor.next().await {
-                if let Ok(member) = member_res {
-                    if let Ok(member_oid) = ObjectId::parse_str(&member.user_id) {
-                        // If user_id is a valid ObjectId, fetch the user
-                        let user_filter = doc! { "_id": member_oid };
-                        if let Ok(Some(user_doc)) = users_collection.find_one(user_filter).await {
-                            combined_members.push(TeamMemberInfo {
-                                user_id: member.user_id.clone(),
-                                email: user_doc.email.clone(),
-                                username: user_doc.username.clone(),
-                                status: "accepted".to_string(),
-                                invitation_id: None,
-                            });
-                        } else {
-                            // OID didn't match any user; fallback
-                            combined_members.push(TeamMemberInfo {
-                                user_id: member.user_id.clone(),
-                                email: member.user_id.clone(),
-                                username: None,
-                                status: "accepted".to_string(),
-                                invitation_id: None,
-                            });
-                        }
-                    } else {
-                        // user_id is not a valid ObjectId
-                        combined_members.push(TeamMemberInfo {
-                            user_id: member.user_id.clone(),
-                            email: member.user_id.clone(),
-                            username: None,
-                            status: "accepted".to_string(),
-                            invitation_id: None,
-                        });
-                    }
-                }
+    // Window over the combined (members, then invitations) list.
+    let total = total_members + total_invitations;
+    let (offset, limit) = match (query.page, query.page_size) {
+        (None, None) => (0, total),
+        (page, size) => {
+            let size = size.unwrap_or(50).clamp(1, MAX_MEMBERS_PAGE_SIZE);
+            (page.unwrap_or(0).min(MAX_MEMBERS_PAGE).saturating_mul(size), size)
+        }
+    };
+    let member_skip = offset.min(total_members);
+    let member_limit = limit.min(total_members - member_skip);
+    let inv_skip = offset.saturating_sub(total_members);
+    let inv_limit = (limit - member_limit).min(total_invitations.saturating_sub(inv_skip));
+
+    let mut members: Vec<UserTeam> = Vec::new();
+    if member_limit > 0 {
+        let mut cursor = match user_teams_collection
+            .find(member_filter)
+            .sort(doc! { "joined_at": 1 })
+            .skip(member_skip)
+            .limit(member_limit as i64)
+            .await
+        {
+            Ok(cursor) => cursor,
+            Err(err) => {
+                return HttpResponse::InternalServerError()
+                    .body(format!("Error fetching team members: {}", err))
             }
+        };
+        while let Some(member_res) = cursor.next().await {
+            if let Ok(member) = member_res {
+                members.push(member);
+            }
+        }
+    }
 
-            // Next: fetch all pending invitations
-            let invitations_collection = data.mongodb.db.collection::<TeamInvitation>("team_invitations");
-            let inv_filter = doc! {
-                "team_id": &*team_id,
-                "status": "pending"
-            };
-            let mut inv_cursor = match invitations_collection.find(inv_filter).await {
-                Ok(cursor) => cursor,
-                Err(err) => {
-                    return HttpResponse::InternalServerError()
-                        .body(format!("Error fetching invitations: {}", err))
-                }
-            };
+    let mut invitations: Vec<TeamInvitation> = Vec::new();
+    if inv_limit > 0 {
+        let mut cursor = match invitations_collection
+            .find(inv_filter)
+            .sort(doc! { "sent_at": 1 })
+            .skip(inv_skip)
+            .limit(inv_limit as i64)
+            .await
+        {
+            Ok(cursor) => cursor,
+            Err(err) => {
+                return HttpResponse::InternalServerError()
+                    .body(format!("Error fetching invitations: {}", err))
+            }
+        };
+        while let Some(inv_res) = cursor.next().await {
+            if let Ok(inv) = inv_res {
+                invitations.push(inv);
+            }
+        }
+    }
 
-            while let Some(inv_res) = inv_cursor.next().await {
-                if let Ok(inv) = inv_res {
-                    // 1) If invitee_id is a valid ObjectId, try to fetch that user
-                    if let Ok(inv_oid) = ObjectId::parse_str(&inv.invitee_id) {
-                        let user_filter = doc! { "_id": inv_oid };
-                        if let Ok(Some(user_doc)) = users_collection.find_one(user_filter).await {
-                            combined_members.push(TeamMemberInfo {
-                                user_id: inv.invitee_id.clone(),
-                                email: user_doc.email.clone(),
-                                username: user_doc.username.clone(),
-                                status: "pending".to_string(),
-                                invitation_id: Some(inv.invitation_id.clone()),
-                            });
-                        } else {
-                            // Could not find user by that OID
-                            combined_members.push(TeamMemberInfo {
-                                user_id: "".to_string(),
-                                email: inv.invitee_id.clone(),
-                                username: Some(inv.invitee_id.clone()),
-                                status: "pending".to_string(),
-                                invitation_id: Some(inv.invitation_id.clone()),
-                            });
-                        }
-                    } else {
-                        // 2) If not a valid ObjectId, attempt to find a user by email
-                        let email_filter = doc! { "email": &inv.invitee_id };
-                        if let Ok(Some(user_doc)) = users_collection.find_one(email_filter).await {
-                            combined_members.push(TeamMemberInfo {
-                                user_id: user_doc.id.to_hex(),
-                                email: user_doc.email.clone(),
-                                username: user_doc.username.clone(),
-                                status: "pending".to_string(),
-                                invitation_id: Some(inv.invitation_id.clone()),
-                            });
-                        } else {
-                            // 3) If not found by email, try by username
-                            let username_filter = doc! { "username": &inv.invitee_id };
-                            if let Ok(Some(user_doc)) = users_collection.find_one(username_filter).await {
-                                combined_members.push(TeamMemberInfo {
-                                    user_id: user_doc.id.to_hex(),
-                                    email: user_doc.email.clone(),
-                                    username: user_doc.username.clone(),
-                                    status: "pending".to_string(),
-                                    invitation_id: Some(inv.invitation_id.clone()),
-                                });
-                            } else {
-                                // 4) Fallback: store the raw invitee_id
-                                combined_members.push(TeamMemberInfo {
-                                    user_id: "".to_string(),
-                                    email: inv.invitee_id.clone(),
-                                    username: Some(inv.invitee_id.clone()),
-                                    status: "pending".to_string(),
-                                    invitation_id: Some(inv.invitation_id.clone()),
-                                });
-                            }
-                        }
-                    }
+    // Batch-resolve every referenced user: ObjectIds by _id, anything else by email/username.
+    let mut oids: Vec<ObjectId> = Vec::new();
+    let mut raw_ids: Vec<String> = Vec::new();
+    let invitees = invitations.iter().map(|i| i.invitee_email.as_ref().map_or(i.invitee_id.as_str(), |e| e.expose()));
+    for id in members.iter().map(|m| m.user_id.as_str()).chain(invitees) {
+        match ObjectId::parse_str(id) {
+            Ok(oid) => oids.push(oid),
+            Err(_) => raw_ids.push(id.to_string()),
+        }
+    }
+
+    let mut by_id: HashMap<String, User> = HashMap::new();
+    let mut by_email: HashMap<String, User> = HashMap::new();
+    let mut by_username: HashMap<String, User> = HashMap::new();
+    if !oids.is_empty() || !raw_ids.is_empty() {
+        let users_collection = data.mongodb.db.collection::<User>("users");
+        let users_filter = doc! {
+            "$or": [
+                { "_id": { "$in": &oids } },
+                { "email": { "$in": &raw_ids } },
+                { "username": { "$in": &raw_ids } },
+            ]
+        };
+        let mut cursor = match users_collection.find(users_filter).await {
+            Ok(cursor) => cursor,
+            Err(err) => {
+                return HttpResponse::InternalServerError()
+                    .body(format!("Error fetching users: {}", err))
+            }
+        };
+        while let Some(user_res) = cursor.next().await {
+            if let Ok(user) = user_res {
+                by_email.insert(user.email.clone(), user.clone());
+                if let Some(name) = &user.username {
+                    by_username.insert(name.clone(), user.clone());
                 }
+                by_id.insert(user.id.to_hex(), user);
             }
+        }
+    }
 
-            HttpResponse::Ok().json(combined_members)
-        },
-        Ok(None) => HttpResponse::Unauthorized().body("You are not a member of this team"),
-        Err(err) => HttpResponse::InternalServerError()
-            .body(format!("Error checking membership: {}", err)),
+    let mut combined_members: Vec<TeamMemberInfo> = Vec::new();
+
+    for member in members {
+        match by_id.get(&member.user_id) {
+            Some(user_doc) => combined_members.push(TeamMemberInfo {
+                user_id: member.user_id.clone(),
+                email: user_doc.email.clone(),
+                username: user_doc.username.clone(),
+                status: "accepted".to_string(),
+                invitation_id: None,
+            }),
+            // user_id is not a valid ObjectId or matched no user; fallback
+            None => combined_members.push(TeamMemberInfo {
+                user_id: member.user_id.clone(),
+                email: member.user_id.clone(),
+                username: None,
+                status: "accepted".to_string(),
+                invitation_id: None,
+            }),
+        }
+    }
+
+    for inv in invitations {
+        let invitee = inv.invitee_email.as_ref().map_or(inv.invitee_id.clone(), |e| e.expose().to_string());
+        let resolved = if ObjectId::parse_str(&invitee).is_ok() {
+            by_id.get(&invitee)
+        } else {
+            by_email.get(&invitee).or_else(|| by_username.get(&invitee))
+        };
+        match resolved {
+            Some(user_doc) => combined_members.push(TeamMemberInfo {
+                user_id: user_doc.id.to_hex(),
+                email: user_doc.email.clone(),
+                username: user_doc.username.clone(),
+                status: "pending".to_string(),
+                invitation_id: Some(inv.invitation_id.clone()),
+            }),
+            // Fallback: store the raw invitee_id
+            None => combined_members.push(TeamMemberInfo {
+                user_id: "".to_string(),
+                email: invitee.clone(),
+                username: Some(invitee),
+                status: "pending".to_string(),
+                invitation_id: Some(inv.invitation_id.clone()),
+            }),
+        }
     }
+
+    HttpResponse::Ok()
+        .insert_header(("X-Total-Count", total.to_string()))
+        .json(ApiResponse::new(combined_members).with_meta(serde_json::json!({ "total": total })))
 }
 
 pub async fn get_team(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     team_id: web::Path<String>,
 ) -> impl Responder {
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
-
-    let user_teams_collection = data.mongodb.db.collection::<UserTeam>("user_teams");
-    let membership_filter = doc! { "team_id": &*team_id, "user_id": &current_user };
-    match user_teams_collection.find_one(membership_filter).await {
-        Ok(Some(_)) => {}
-        Ok(None) => return HttpResponse::Unauthorized().body("Not a member of the team"),
-        Err(e) => return HttpResponse::InternalServerError().body(format!("Error checking membership: {}", e)),
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().body("Not a member of the team");
     }
 
     let teams_collection = data.mongodb.db.collection::<Team>("teams");
     let filter = doc! { "team_id": &*team_id };
     match teams_collection.find_one(filter).await {
-        Ok(Some(team)) => HttpResponse::Ok().json(team),
-        Ok(None) => HttpResponse::NotFound().body("Team not found"),
+        Ok(Some(team)) => ok(team),
+        Ok(None) => HttpResponse::NotFound().error("team_not_found"),
         Err(e) => HttpResponse::InternalServerError().body(format!("Error fetching team: {}", e)),
     }
 }
 
 pub async fn update_team(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     team_id: web::Path<String>,
     team_info: web::Json<UpdateTeamRequest>,
 ) -> impl Responder {
     let team_id = team_id.into_inner();
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
 
     let teams_collection = data.mongodb.db.collection::<Team>("teams");
 
     let filter = doc! { "team_id": &team_id };
     let team = match teams_collection.find_one(filter.clone()).await {
         Ok(Some(team)) => team,
-        Ok(None) => return HttpResponse::NotFound().body("Team not found"),
+        Ok(None) => return HttpResponse::NotFound().error("team_not_found"),
         Err(e) => return HttpResponse::InternalServerError().body(format!("Error fetching team: {}", e)),
     };
     if team.owner_id != current_user {
@@ -613,6 +702,12 @@ pub async fn update_team(
     let user_teams_collection = data.mongodb.db.collection::<UserTeam>("user_teams");
 
     let mut update_doc = doc! { "$set": { "name": &team_info.name } };
+    if let Some(tz) = &team_info.timezone {
+        if let Err(msg) = parse_timezone(tz) {
+            return HttpResponse::BadRequest().body(msg);
+        }
+        update_doc.get_document_mut("$set").unwrap().insert("timezone", tz);
+    }
 
     if let Some(ref new_owner) = team_info.new_owner_id {
         if new_owner != &current_user {
@@ -629,29 +724,25 @@ pub async fn update_team(
     }
 
     match teams_collection.update_one(filter, update_doc).await {
-        Ok(_) => HttpResponse::Ok().body("Team updated successfully"),
+        Ok(_) => ok_message("Team updated successfully"),
         Err(e) => HttpResponse::InternalServerError().body(format!("Error updating team: {}", e)),
     }
 }
 
 pub async fn delete_team(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     team_id: web::Path<String>,
 ) -> impl Responder {
     let team_id = team_id.into_inner();
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
 
     let teams_collection = data.mongodb.db.collection::<Team>("teams");
     let filter = doc! { "team_id": &team_id };
 
     let team = match teams_collection.find_one(filter.clone()).await {
         Ok(Some(team)) => team,
-        Ok(None) => return HttpResponse::NotFound().body("Team not found"),
+        Ok(None) => return HttpResponse::NotFound().error("team_not_found"),
         Err(e) => return HttpResponse::InternalServerError().body(format!("Error fetching team: {}", e)),
     };
     if team.owner_id != current_user {
@@ -662,37 +753,30 @@ pub async fn delete_team(
         Ok(_) => {
             let user_teams_collection = data.mongodb.db.collection::<UserTeam>("user_teams");
             let membership_filter = doc! { "team_id": &team_id };
+            let members: Vec<String> = user_teams_collection
+                .distinct("user_id", membership_filter.clone())
+                .await
+                .map(|ids| ids.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+                .unwrap_or_default();
             let _ = user_teams_collection.delete_many(membership_filter).await;
-            HttpResponse::Ok().body("Team deleted successfully")
+            role_claims::bump(&data.mongodb, &members).await;
+            ok_message("Team deleted successfully")
         },
         Err(e) => HttpResponse::InternalServerError().body(format!("Error deleting team: {}", e)),
     }
 }
 
 pub async fn remove_team_member(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     info: web::Json<RemoveTeamMemberRequest>,
 ) -> impl Responder {
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    if !auth.is_team_admin(&info.team_id).await {
+        return HttpResponse::Unauthorized().body("Only team admins can remove members");
+    }
 
     let user_teams_collection = data.mongodb.db.collection::<UserTeam>("user_teams");
 
-    let admin_filter = doc! {
-         "team_id": &info.team_id,
-         "user_id": &current_user,
-         "role": "admin"
-    };
-    match user_teams_collection.find_one(admin_filter).await {
-        Ok(Some(_)) => {}
-        Ok(None) => return HttpResponse::Unauthorized().body("Only team admins can remove members"),
-        Err(e) => return HttpResponse::InternalServerError().body(format!("Error verifying admin status: {}", e)),
-    }
-
     let member_filter = doc! {
          "team_id": &info.team_id,
          "user_id": &info.user_id,
@@ -700,7 +784,8 @@ pub async fn remove_team_member(
     match user_teams_collection.delete_one(member_filter).await {
         Ok(result) => {
             if result.deleted_count == 1 {
-                HttpResponse::Ok().body("Member removed successfully")
+                role_claims::bump(&data.mongodb, std::slice::from_ref(&info.user_id)).await;
+                ok_message("Member removed successfully")
             } else {
                 HttpResponse::NotFound().body("Member not found in team")
             }
@@ -710,15 +795,11 @@ pub async fn remove_team_member(
 }
 
 pub async fn accept_invitation(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     info: web::Json<RespondInvitationRequest>,
 ) -> impl Responder {
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
 
     let invitations_collection = data.mongodb.db.collection::<TeamInvitation>("team_invitations");
     let user_teams_collection = data.mongodb.db.collection::<UserTeam>("user_teams");
@@ -738,10 +819,11 @@ pub async fn accept_invitation(
         return HttpResponse::BadRequest().body("Invitation is not pending");
     }
 
+    let responded_at = Utc::now();
     let update = doc! {
         "$set": {
             "status": "accepted",
-            "responded_at": BsonDateTime::from_millis(Utc::now().timestamp_millis())
+            "responded_at": BsonDateTime::from_millis(responded_at.timestamp_millis())
         }
     };
 
@@ -759,28 +841,32 @@ pub async fn accept_invitation(
     }
 
     let new_membership = UserTeam {
-        user_id: current_user,
-        team_id: invitation.team_id,
+        user_id: current_user.clone(),
+        team_id: invitation.team_id.clone(),
         role: "member".to_string(),
         joined_at: Utc::now(),
     };
 
     match user_teams_collection.insert_one(new_membership).await {
-        Ok(_) => HttpResponse::Ok().body("Invitation accepted and team membership added"),
+        Ok(_) => {
+            role_claims::bump(&data.mongodb, std::slice::from_ref(&current_user)).await;
+            record_activity(&data.mongodb, ActivityEvent::new(
+                &invitation.team_id, None, &current_user, "member_joined", &current_user, "joined the team",
+            )).await;
+            let invitation = TeamInvitation { status: "accepted".to_string(), responded_at: Some(responded_at), ..invitation };
+            notify_invitation(&data, &invitation, &current_user).await;
+            ok_message("Invitation accepted and team membership added")
+        }
         Err(e) => HttpResponse::InternalServerError().body(format!("Error adding membership: {}", e)),
     }
 }
 
 pub async fn decline_invitation(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     info: web::Json<RespondInvitationRequest>,
 ) -> impl Responder {
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
 
     let invitations_collection = data.mongodb.db.collection::<TeamInvitation>("team_invitations");
 
@@ -799,29 +885,30 @@ pub async fn decline_invitation(
         return HttpResponse::BadRequest().body("Invitation is not pending");
     }
 
+    let responded_at = Utc::now();
     let update = doc! {
         "$set": {
             "status": "declined",
-            "responded_at": BsonDateTime::from_millis(Utc::now().timestamp_millis())
+            "responded_at": BsonDateTime::from_millis(responded_at.timestamp_millis())
         }
     };
 
     match invitations_collection.update_one(filter, update).await {
-        Ok(_) => HttpResponse::Ok().body("Invitation declined"),
+        Ok(_) => {
+            let invitation = TeamInvitation { status: "declined".to_string(), responded_at: Some(responded_at), ..invitation };
+            notify_invitation(&data, &invitation, &current_user).await;
+            ok_message("Invitation declined")
+        }
         Err(e) => HttpResponse::InternalServerError().body(format!("Error updating invitation: {}", e)),
     }
 }
 
 pub async fn delete_invitations(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     info: web::Json<DeleteInvitationsRequest>,
 ) -> impl Responder {
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
 
     let user_teams_collection = data.mongodb.db.collection::<UserTeam>("user_teams");
     let admin_filter = doc! {
@@ -839,7 +926,7 @@ pub async fn delete_invitations(
             match invitations_collection.delete_many(filter).await {
                 Ok(delete_result) => {
                     let count = delete_result.deleted_count;
-                    HttpResponse::Ok().body(format!("Deleted {} invitation(s)", count))
+                    ok_message(format!("Deleted {} invitation(s)", count))
                 },
                 Err(e) => HttpResponse::InternalServerError().body(format!("Error deleting invitations: {}", e))
             }
@@ -848,3 +935,195 @@ pub async fn delete_invitations(
         Err(e) => HttpResponse::InternalServerError().body(format!("Error verifying admin status: {}", e)),
     }
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TeamInviteLink {
+    pub token: String,
+    pub team_id: String,
+    pub created_by: String,
+    pub created_at: chrono::DateTime<Utc>,
+    pub expires_at: Option<chrono::DateTime<Utc>>,
+    pub max_uses: Option<i64>,
+    pub uses: i64,
+}
+
+/// Longest lifetime an invite link can be given; a year.
+const MAX_INVITE_LINK_HOURS: i64 = 24 * 365;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInviteLinkRequest {
+    /// Lifetime of the link in hours; omitted means the link never expires.
+    pub expires_in_hours: Option<i64>,
+    pub max_uses: Option<i64>,
+}
+
+/// Public preview of the team an invite link points at.
+#[derive(Debug, Serialize)]
+pub struct InviteLinkPreview {
+    pub team_id: String,
+    pub team_name: String,
+    pub description: Option<String>,
+    pub expires_at: Option<chrono::DateTime<Utc>>,
+    pub branding: Option<TeamBranding>,
+}
+
+impl TeamInviteLink {
+    fn is_usable(&self) -> bool {
+        let not_expired = self.expires_at.is_none_or(|exp| exp > Utc::now());
+        let uses_left = self.max_uses.is_none_or(|max| self.uses < max);
+        not_expired && uses_left
+    }
+}
+
+/// POST /teams/{team_id}/invite_links
+/// Admins create a shareable link token with optional expiry and max uses.
+pub async fn create_invite_link(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    team_id: web::Path<String>,
+    payload: web::Json<CreateInviteLinkRequest>,
+) -> impl Responder {
+    let team_id = team_id.into_inner();
+    let current_user = auth.user_id().to_string();
+
+    if !auth.is_team_admin(&team_id).await {
+        return HttpResponse::Unauthorized().body("Only team admins can create invite links");
+    }
+
+    if matches!(payload.max_uses, Some(n) if n <= 0) {
+        return HttpResponse::BadRequest().body("max_uses must be positive");
+    }
+    let expires_at = match payload.expires_in_hours {
+        None => None,
+        Some(h) if (1..=MAX_INVITE_LINK_HOURS).contains(&h) => {
+            match chrono::TimeDelta::try_hours(h).and_then(|d| Utc::now().checked_add_signed(d)) {
+                Some(at) => Some(at),
+                None => return HttpResponse::BadRequest().body("Invalid expires_in_hours"),
+            }
+        }
+        Some(_) => return HttpResponse::BadRequest().body("expires_in_hours must be between 1 and 8760"),
+    };
+
+    let link = TeamInviteLink {
+        token: Uuid::new_v4().simple().to_string(),
+        team_id,
+        created_by: current_user,
+        created_at: Utc::now(),
+        expires_at,
+        max_uses: payload.max_uses,
+        uses: 0,
+    };
+
+    let links_collection = data.mongodb.db.collection::<TeamInviteLink>("team_invite_links");
+    match links_collection.insert_one(&link).await {
+        Ok(_) => {
+            info!("Invite link created for team {}", link.team_id);
+            ok(link)
+        }
+        Err(e) => {
+            error!("Error creating invite link: {}", e);
+            HttpResponse::InternalServerError().body(format!("Error creating invite link: {}", e))
+        }
+    }
+}
+
+/// GET /invite/{token}
+/// Returns basic team info so the frontend can render the join page before login.
+pub async fn get_invite_link(
+    data: web::Data<AppState>,
+    token: web::Path<String>,
+) -> impl Responder {
+    let links_collection = data.mongodb.db.collection::<TeamInviteLink>("team_invite_links");
+    let link = match links_collection.find_one(doc! { "token": &*token }).await {
+        Ok(Some(link)) if link.is_usable() => link,
+        Ok(_) => return HttpResponse::NotFound().body("Invite link is invalid or expired"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error fetching invite link: {}", e)),
+    };
+
+    let teams_collection = data.mongodb.db.collection::<Team>("teams");
+    match teams_collection.find_one(doc! { "team_id": &link.team_id }).await {
+        Ok(Some(team)) => ok(InviteLinkPreview {
+            team_id: team.team_id,
+            team_name: team.name,
+            description: team.description,
+            expires_at: link.expires_at,
+            branding: team.branding,
+        }),
+        Ok(None) => HttpResponse::NotFound().error("team_not_found"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error fetching team: {}", e)),
+    }
+}
+
+/// POST /invite/{token}/join
+/// Adds the authenticated user to the link's team as a member.
+pub async fn join_via_invite_link(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    token: web::Path<String>,
+) -> impl Responder {
+    let current_user = auth.user_id().to_string();
+
+    let links_collection = data.mongodb.db.collection::<TeamInviteLink>("team_invite_links");
+    let link = match links_collection.find_one(doc! { "token": &*token }).await {
+        Ok(Some(link)) if link.is_usable() => link,
+        Ok(_) => return HttpResponse::NotFound().body("Invite link is invalid or expired"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error fetching invite link: {}", e)),
+    };
+
+    let user_teams_collection = data.mongodb.db.collection::<UserTeam>("user_teams");
+    let membership_filter = doc! { "team_id": &link.team_id, "user_id": &current_user };
+    if let Ok(Some(_)) = user_teams_collection.find_one(membership_filter).await {
+        return HttpResponse::BadRequest().body("You are already a member of this team");
+    }
+
+    // Claim a use atomically so concurrent joins cannot exceed max_uses, and a link
+    // expiring meanwhile is not consumed.
+    let now = to_bson(&Utc::now()).unwrap_or_default();
+    let mut claim_filter = doc! {
+        "token": &link.token,
+        "$or": [{ "expires_at": null }, { "expires_at": { "$gt": now } }],
+    };
+    if let Some(max) = link.max_uses {
+        claim_filter.insert("uses", doc! { "$lt": max });
+    }
+    match links_collection.update_one(claim_filter, doc! { "$inc": { "uses": 1 } }).await {
+        Ok(res) if res.modified_count == 1 => {}
+        Ok(_) => return HttpResponse::Gone().body("Invite link has expired or reached its maximum uses"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error updating invite link: {}", e)),
+    }
+
+    let new_membership = UserTeam {
+        user_id: current_user.clone(),
+        team_id: link.team_id.clone(),
+        role: "member".to_string(),
+        joined_at: Utc::now(),
+    };
+
+    match user_teams_collection.insert_one(&new_membership).await {
+        Ok(_) => {
+            role_claims::bump(&data.mongodb, std::slice::from_ref(&current_user)).await;
+            info!("User {} joined team {} via invite link", current_user, link.team_id);
+            record_activity(&data.mongodb, ActivityEvent::new(
+                &link.team_id, None, &current_user, "member_joined", &current_user, "joined the team via invite link",
+            )).await;
+            ok(new_membership)
+        }
+        Err(e) => {
+            // Not joined after all: give the claimed use back.
+            let refund = links_collection
+                .update_one(doc! { "token": &link.token, "uses": { "$gt": 0 } }, doc! { "$inc": { "uses": -1 } })
+                .await;
+            if let Err(refund_err) = refund {
+                error!("Error refunding use of invite link for team {}: {}", link.team_id, refund_err);
+            }
+            if is_duplicate_key(&e) {
+                return HttpResponse::BadRequest().body("You are already a member of this team");
+            }
+            HttpResponse::InternalServerError().body(format!("Error adding membership: {}", e))
+        }
+    }
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(&*e.kind, ErrorKind::Write(WriteFailure::WriteError(w)) if w.code == 11000)
+}
@@ -0,0 +1,46 @@
+// src/team_time.rs
+//
+// Team-local time. Teams may set an IANA time zone (e.g. "Europe/Berlin"); anything
+// that buckets by day, month or sprint should go through these helpers instead of UTC.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use mongodb::bson::{doc, Bson, Document};
+
+use crate::chat_db::MongoDB;
+
+/// Parse a time zone name, returning an error message suitable for a 400 response.
+pub fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.parse::<Tz>().map_err(|_| format!("Unknown time zone '{}'", name))
+}
+
+/// The team's configured zone, or UTC when unset or unreadable.
+pub async fn team_timezone(db: &MongoDB, team_id: &str) -> Tz {
+    db.db
+        .collection::<Document>("teams")
+        .find_one(doc! { "team_id": team_id })
+        .await
+        .ok()
+        .flatten()
+        .and_then(|t| t.get_str("timezone").ok().and_then(|tz| tz.parse::<Tz>().ok()))
+        .unwrap_or(Tz::UTC)
+}
+
+/// Today's date as seen by the team.
+pub fn local_today(tz: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&tz).date_naive()
+}
+
+/// A stored timestamp, either a BSON date or an RFC 3339 string written by serde.
+pub fn timestamp(value: &Bson) -> Option<DateTime<Utc>> {
+    match value {
+        Bson::DateTime(dt) => Some(dt.to_chrono()),
+        Bson::String(s) => Some(DateTime::parse_from_rfc3339(s).ok()?.with_timezone(&Utc)),
+        _ => None,
+    }
+}
+
+/// The team-local calendar day of a stored timestamp.
+pub fn local_date(value: &Bson, tz: Tz) -> Option<NaiveDate> {
+    Some(timestamp(value)?.with_timezone(&tz).date_naive())
+}
@@ -0,0 +1,519 @@
+// src/tenancy.rs
+//
+// Tenant guard for the projects, boards and tickets collections. A `TeamScope` or
+// `ProjectScope` can only be obtained from `AuthContext` after checking that the
+// caller is on the team and, for projects, that the project belongs to that team.
+// Outside this module those collections are only read and written through `Repo`
+// (policy_tests.rs checks the sources for raw access): it cannot be built without a
+// scope, and every query it runs is restricted to the scope's team or project,
+// whatever filter the handler passes. So a handler that mixes up the `team_id` and
+// `project_id` of its path cannot reach another tenant's projects, boards or
+// tickets. Other team data (releases, worklogs, the knowledge base...) is still
+// filtered by the handlers themselves.
+//
+// Work without a request gets its scope from stored documents instead:
+// `ProjectScope::of_project`, `of_board` and `of_ticket` read the owning team from
+// the stored project, `TeamScope::of_member` checks the user's membership, and
+// `ProjectsScope` covers every project of some teams or of the teams a user is on.
+// `AllTenants` is reserved for the ticket log's projection writer, whose callers
+// have already authorized the change, and for background sweeps over every tenant
+// (stale ticket nudges, attachment retention). integrity.rs and migrations.rs are
+// shared with the taskline-admin binary and work below this layer, on every
+// tenant's documents.
+
+use std::collections::HashMap;
+
+use actix_web::HttpResponse;
+use futures_util::StreamExt;
+use log::error;
+use mongodb::action::{Aggregate, Find, FindOneAndUpdate};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::results::{DeleteResult, UpdateResult};
+use mongodb::{ClientSession, Collection};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::auth_context::AuthContext;
+use crate::board::Board;
+use crate::chat_db::MongoDB;
+use crate::db_pool::RetryPolicy;
+use crate::project::Project;
+use crate::response::CodedError;
+use crate::ticket::Ticket;
+
+/// Proof that the caller is a member of `team_id`.
+#[derive(Debug, Clone)]
+pub struct TeamScope {
+    team_id: String,
+}
+
+/// Proof that the caller is a member of `team_id` and that `project_id` belongs to it.
+/// Project membership itself is an authorization question left to the handler.
+#[derive(Debug, Clone)]
+pub struct ProjectScope {
+    team_id: String,
+    project_id: String,
+}
+
+/// Several projects at once, for reads across a team or across a user's teams.
+/// Repos built from it can read and update but not insert.
+#[derive(Debug, Clone, Default)]
+pub struct ProjectsScope {
+    project_ids: Vec<String>,
+}
+
+/// Every tenant; see the module comment for who may use it.
+#[derive(Debug, Clone, Copy)]
+pub struct AllTenants(());
+
+impl TeamScope {
+    /// For work on a user's behalf without a request, such as chat commands.
+    pub(crate) async fn of_member(db: &MongoDB, team_id: &str, user_id: &str) -> mongodb::error::Result<Option<Self>> {
+        let member = db.check_user_team(user_id, team_id).await?;
+        Ok(member.then(|| TeamScope { team_id: team_id.to_string() }))
+    }
+
+    pub fn team_id(&self) -> &str {
+        &self.team_id
+    }
+
+    /// The scope of a project the caller is creating in this team.
+    pub fn new_project(&self, project: &Project) -> Option<ProjectScope> {
+        (project.team_id == self.team_id).then(|| ProjectScope { team_id: self.team_id.clone(), project_id: project.project_id.clone() })
+    }
+
+    /// Every project of the team.
+    pub async fn projects(&self, db: &MongoDB) -> mongodb::error::Result<ProjectsScope> {
+        ProjectsScope::of_team(db, &self.team_id).await
+    }
+}
+
+impl ProjectScope {
+    /// For webhooks and background jobs acting without a user: the team is read from
+    /// the project, so a stored document cannot pair a project with another team.
+    pub(crate) async fn of_project(db: &MongoDB, project_id: &str) -> Option<Self> {
+        let team_id = project_team_id(db, project_id).await?;
+        Some(ProjectScope { team_id, project_id: project_id.to_string() })
+    }
+
+    /// The scope of the project `ticket_id` lives in; `None` when the ticket or its
+    /// project is gone.
+    pub(crate) async fn of_ticket(db: &MongoDB, ticket_id: &str) -> mongodb::error::Result<Option<Self>> {
+        Self::of_owner(db, Ticket::COLLECTION, doc! { "ticket_id": ticket_id }).await
+    }
+
+    /// The scope of the project `board_id` belongs to; `None` when the board or its
+    /// project is gone.
+    pub(crate) async fn of_board(db: &MongoDB, board_id: &str) -> mongodb::error::Result<Option<Self>> {
+        Self::of_owner(db, Board::COLLECTION, doc! { "board_id": board_id }).await
+    }
+
+    async fn of_owner(db: &MongoDB, collection: &str, filter: Document) -> mongodb::error::Result<Option<Self>> {
+        let owned = db.db.collection::<Document>(collection).find_one(filter).projection(doc! { "project_id": 1 }).await?;
+        match owned.as_ref().and_then(|d| d.get_str("project_id").ok()) {
+            Some(project_id) => Ok(Self::of_project(db, project_id).await),
+            None => Ok(None),
+        }
+    }
+
+    pub fn team_id(&self) -> &str {
+        &self.team_id
+    }
+
+    /// The project's team, which the scope already vouches for.
+    pub fn team(&self) -> TeamScope {
+        TeamScope { team_id: self.team_id.clone() }
+    }
+
+    pub fn project_id(&self) -> &str {
+        &self.project_id
+    }
+}
+
+/// Look up the team a project belongs to.
+pub async fn project_team_id(db: &MongoDB, project_id: &str) -> Option<String> {
+    db.db
+        .collection::<Document>(Project::COLLECTION)
+        .find_one(doc! { "project_id": project_id })
+        .await
+        .ok()
+        .flatten()
+        .and_then(|p| p.get_str("team_id").ok().map(String::from))
+}
+
+impl ProjectsScope {
+    /// Every project of `team_id`.
+    pub(crate) async fn of_team(db: &MongoDB, team_id: &str) -> mongodb::error::Result<Self> {
+        Self::of_teams(db, &[team_id.to_string()]).await
+    }
+
+    /// Every project of the teams `user_id` is on.
+    pub(crate) async fn of_user(db: &MongoDB, user_id: &str) -> mongodb::error::Result<Self> {
+        let team_ids = db.user_team_ids(user_id).await?;
+        Self::of_teams(db, &team_ids).await
+    }
+
+    /// Every project of `team_ids`.
+    pub(crate) async fn of_teams(db: &MongoDB, team_ids: &[String]) -> mongodb::error::Result<Self> {
+        let values = db.db.collection::<Document>(Project::COLLECTION).distinct("project_id", doc! { "team_id": { "$in": team_ids } }).await?;
+        Ok(ProjectsScope { project_ids: values.iter().filter_map(|v| v.as_str().map(str::to_string)).collect() })
+    }
+
+    pub fn project_ids(&self) -> &[String] {
+        &self.project_ids
+    }
+
+    /// project_id -> team_id for every project of the scope.
+    pub async fn teams(&self, db: &MongoDB) -> mongodb::error::Result<HashMap<String, String>> {
+        let projects = db.db.collection::<Document>(Project::COLLECTION);
+        let mut cursor = projects.find(self.filter()).projection(doc! { "project_id": 1, "team_id": 1 }).await?;
+        let mut teams = HashMap::new();
+        while let Some(project) = cursor.next().await {
+            let project = project?;
+            if let (Ok(p), Ok(t)) = (project.get_str("project_id"), project.get_str("team_id")) {
+                teams.insert(p.to_string(), t.to_string());
+            }
+        }
+        Ok(teams)
+    }
+
+    /// Narrows the scope, e.g. to the projects the caller is a member of.
+    pub fn retain(&mut self, keep: impl FnMut(&String) -> bool) {
+        self.project_ids.retain(keep);
+    }
+}
+
+impl AllTenants {
+    pub(crate) const fn maintenance() -> Self {
+        AllTenants(())
+    }
+}
+
+pub trait Scope {
+    /// Fields every document in the scope has.
+    fn filter(&self) -> Document;
+}
+
+impl Scope for TeamScope {
+    fn filter(&self) -> Document {
+        doc! { "team_id": &self.team_id }
+    }
+}
+
+impl Scope for ProjectScope {
+    fn filter(&self) -> Document {
+        doc! { "project_id": &self.project_id }
+    }
+}
+
+impl Scope for ProjectsScope {
+    fn filter(&self) -> Document {
+        doc! { "project_id": { "$in": &self.project_ids } }
+    }
+}
+
+/// A model stored in a collection partitioned by team or project.
+pub trait TenantOwned: Serialize + DeserializeOwned + Send + Sync {
+    const COLLECTION: &'static str;
+    type Scope: Scope;
+
+    fn in_scope(&self, scope: &Self::Scope) -> bool;
+}
+
+impl TenantOwned for Project {
+    const COLLECTION: &'static str = "projects";
+    type Scope = TeamScope;
+
+    fn in_scope(&self, scope: &TeamScope) -> bool {
+        self.team_id == scope.team_id
+    }
+}
+
+impl TenantOwned for Board {
+    const COLLECTION: &'static str = "boards";
+    type Scope = ProjectScope;
+
+    fn in_scope(&self, scope: &ProjectScope) -> bool {
+        self.project_id == scope.project_id
+    }
+}
+
+impl TenantOwned for Ticket {
+    const COLLECTION: &'static str = "tickets";
+    type Scope = ProjectScope;
+
+    fn in_scope(&self, scope: &ProjectScope) -> bool {
+        self.project_id == scope.project_id
+    }
+}
+
+impl AuthContext {
+    pub async fn team_scope(&self, team_id: &str) -> Result<TeamScope, HttpResponse> {
+        if !self.is_team_member(team_id).await {
+            return Err(HttpResponse::Unauthorized().error("not_team_member"));
+        }
+        Ok(TeamScope { team_id: team_id.to_string() })
+    }
+
+    pub async fn project_scope(&self, team_id: &str, project_id: &str) -> Result<ProjectScope, HttpResponse> {
+        let team = self.team_scope(team_id).await?;
+        if project_team_id(self.db(), project_id).await.as_deref() != Some(team_id) {
+            return Err(HttpResponse::NotFound().error("project_not_found"));
+        }
+        Ok(ProjectScope { team_id: team.team_id, project_id: project_id.to_string() })
+    }
+
+    /// The scope of the project `ticket_id` lives in, for routes naming only the ticket.
+    pub async fn ticket_scope(&self, ticket_id: &str) -> Result<ProjectScope, HttpResponse> {
+        let scope = match ProjectScope::of_ticket(self.db(), ticket_id).await {
+            Ok(Some(s)) => s,
+            Ok(None) => return Err(HttpResponse::NotFound().error("ticket_not_found")),
+            Err(e) => {
+                error!("Error fetching ticket: {}", e);
+                return Err(HttpResponse::InternalServerError().error("error_fetching_ticket"));
+            }
+        };
+        self.team_scope(&scope.team_id).await?;
+        Ok(scope)
+    }
+
+    /// The scope of the project `board_id` belongs to, for routes naming only the board.
+    pub async fn board_scope(&self, board_id: &str) -> Result<ProjectScope, HttpResponse> {
+        let scope = match ProjectScope::of_board(self.db(), board_id).await {
+            Ok(Some(s)) => s,
+            Ok(None) => return Err(HttpResponse::NotFound().error("board_not_found")),
+            Err(e) => {
+                error!("Error fetching board: {}", e);
+                return Err(HttpResponse::InternalServerError().body("Error fetching board"));
+            }
+        };
+        self.team_scope(&scope.team_id).await?;
+        Ok(scope)
+    }
+}
+
+/// What inserts are checked against.
+enum Owner<'s, S> {
+    One(&'s S),
+    Many,
+    All,
+}
+
+/// Scoped access to `T`'s collection. Single-document reads and writes are retried
+/// on transient errors with the database's `RetryPolicy`; updates passed to it must
+/// therefore be idempotent (`$set`, `$addToSet`, `$pull`, not `$inc`). The `_in`
+/// methods run inside the caller's transaction and are not retried.
+pub struct Repo<'s, T: TenantOwned> {
+    coll: Collection<T>,
+    docs: Collection<Document>,
+    filter: Document,
+    owner: Owner<'s, T::Scope>,
+    retry: RetryPolicy,
+}
+
+impl<'s, T: TenantOwned> Repo<'s, T> {
+    fn build(db: &MongoDB, filter: Document, owner: Owner<'s, T::Scope>) -> Self {
+        Repo {
+            coll: db.db.collection::<T>(T::COLLECTION),
+            docs: db.db.collection::<Document>(T::COLLECTION),
+            filter,
+            owner,
+            retry: db.retry,
+        }
+    }
+
+    pub fn new(db: &MongoDB, scope: &'s T::Scope) -> Self {
+        Self::build(db, scope.filter(), Owner::One(scope))
+    }
+
+    pub fn all_tenants(db: &MongoDB, _: AllTenants) -> Self {
+        Self::build(db, Document::new(), Owner::All)
+    }
+
+    /// `filter` restricted to the scope. A field the caller also filters on is
+    /// combined with `$and`, so the caller can narrow the scope but never widen it.
+    fn scoped(&self, mut filter: Document) -> Document {
+        if self.filter.keys().any(|k| filter.contains_key(k)) {
+            return doc! { "$and": [filter, self.filter.clone()] };
+        }
+        filter.extend(self.filter.clone());
+        filter
+    }
+
+    fn check(&self, item: &T) -> mongodb::error::Result<()> {
+        let allowed = match &self.owner {
+            Owner::One(scope) => item.in_scope(scope),
+            Owner::Many => false,
+            Owner::All => true,
+        };
+        if !allowed {
+            let msg = format!("{} document outside the request's scope", T::COLLECTION);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg).into());
+        }
+        Ok(())
+    }
+
+    pub async fn find_one(&self, filter: Document) -> mongodb::error::Result<Option<T>> {
+        let filter = self.scoped(filter);
+        self.retry.run(|| self.coll.find_one(filter.clone())).await
+    }
+
+    pub fn find(&self, filter: Document) -> Find<'_, T> {
+        self.coll.find(self.scoped(filter))
+    }
+
+    /// Like `find`, for projections that do not deserialize into `T`.
+    pub fn find_docs(&self, filter: Document) -> Find<'_, Document> {
+        self.docs.find(self.scoped(filter))
+    }
+
+    pub async fn count_documents(&self, filter: Document) -> mongodb::error::Result<u64> {
+        let filter = self.scoped(filter);
+        self.retry.run(|| self.coll.count_documents(filter.clone())).await
+    }
+
+    pub async fn distinct(&self, field: &str, filter: Document) -> mongodb::error::Result<Vec<Bson>> {
+        let filter = self.scoped(filter);
+        self.retry.run(|| self.coll.distinct(field, filter.clone())).await
+    }
+
+    /// `pipeline` behind a `$match` on the scope.
+    pub fn aggregate(&self, pipeline: Vec<Document>) -> Aggregate<'_> {
+        let stages = std::iter::once(doc! { "$match": self.filter.clone() }).chain(pipeline);
+        self.coll.aggregate(stages)
+    }
+
+    pub async fn update_one(&self, filter: Document, update: Document) -> mongodb::error::Result<UpdateResult> {
+        let filter = self.scoped(filter);
+        self.retry.run(|| self.coll.update_one(filter.clone(), update.clone())).await
+    }
+
+    /// Not retried, so `update` may be anything; the result is the raw document.
+    pub fn find_one_and_update(&self, filter: Document, update: Document) -> FindOneAndUpdate<'_, Document> {
+        self.docs.find_one_and_update(self.scoped(filter), update)
+    }
+
+    pub async fn delete_one(&self, filter: Document) -> mongodb::error::Result<DeleteResult> {
+        let filter = self.scoped(filter);
+        self.retry.run(|| self.coll.delete_one(filter.clone())).await
+    }
+
+    /// Refuses documents of another team or project.
+    pub async fn insert_one(&self, item: &T) -> mongodb::error::Result<()> {
+        self.check(item)?;
+        self.coll.insert_one(item).await.map(|_| ())
+    }
+
+    /// Refuses the whole batch if any document is of another team or project.
+    pub async fn insert_many(&self, items: &[T]) -> mongodb::error::Result<()> {
+        items.iter().try_for_each(|item| self.check(item))?;
+        self.coll.insert_many(items).await.map(|_| ())
+    }
+
+    pub async fn insert_one_in(&self, item: &T, session: &mut ClientSession) -> mongodb::error::Result<()> {
+        self.check(item)?;
+        self.coll.insert_one(item).session(session).await.map(|_| ())
+    }
+
+    pub async fn insert_many_in(&self, items: &[T], session: &mut ClientSession) -> mongodb::error::Result<()> {
+        items.iter().try_for_each(|item| self.check(item))?;
+        self.coll.insert_many(items).session(session).await.map(|_| ())
+    }
+
+    /// The replacement may leave the scope, as when a ticket moves to another project.
+    pub async fn replace_one_in(&self, filter: Document, item: &T, session: &mut ClientSession) -> mongodb::error::Result<UpdateResult> {
+        self.coll.replace_one(self.scoped(filter), item).session(session).await
+    }
+
+    pub async fn delete_one_in(&self, filter: Document, session: &mut ClientSession) -> mongodb::error::Result<DeleteResult> {
+        self.coll.delete_one(self.scoped(filter)).session(session).await
+    }
+}
+
+impl<'s> Repo<'s, Project> {
+    /// Reads and updates the projects of a `ProjectsScope`; inserts are refused.
+    pub fn across(db: &MongoDB, scope: &ProjectsScope) -> Self {
+        Self::build(db, scope.filter(), Owner::Many)
+    }
+}
+
+impl<'s, T: TenantOwned<Scope = ProjectScope>> Repo<'s, T> {
+    /// Reads and updates across several projects; inserts are refused.
+    pub fn across(db: &MongoDB, scope: &ProjectsScope) -> Self {
+        Self::build(db, scope.filter(), Owner::Many)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use mongodb::Client;
+
+    /// Nothing listens here; the checks under test run before any query is sent.
+    async fn offline() -> MongoDB {
+        let client = Client::with_uri_str("mongodb://127.0.0.1:9/?serverSelectionTimeoutMS=200").await.unwrap();
+        let retry = RetryPolicy { attempts: 1, base_delay: std::time::Duration::ZERO };
+        MongoDB { db: client.database("taskline_tenancy"), client, retry }
+    }
+
+    fn team(team_id: &str) -> TeamScope {
+        TeamScope { team_id: team_id.to_string() }
+    }
+
+    fn project(project_id: &str, team_id: &str) -> Project {
+        Project {
+            project_id: project_id.to_string(),
+            team_id: team_id.to_string(),
+            name: "Project".to_string(),
+            description: None,
+            created_at: Utc::now(),
+            created_by: "someone".to_string(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn filters_can_narrow_the_scope_but_not_widen_it() {
+        let db = offline().await;
+        let scope = ProjectScope { team_id: "team".to_string(), project_id: "mine".to_string() };
+        let repo = Repo::<Ticket>::new(&db, &scope);
+        assert_eq!(repo.scoped(doc! { "status": "Done" }), doc! { "status": "Done", "project_id": "mine" });
+        assert_eq!(
+            repo.scoped(doc! { "project_id": "theirs" }),
+            doc! { "$and": [{ "project_id": "theirs" }, { "project_id": "mine" }] }
+        );
+
+        let projects = ProjectsScope { project_ids: vec!["a".to_string(), "b".to_string()] };
+        let repo = Repo::<Ticket>::across(&db, &projects);
+        assert_eq!(repo.scoped(doc! {}), doc! { "project_id": { "$in": ["a", "b"] } });
+    }
+
+    #[actix_web::test]
+    async fn inserts_outside_the_scope_are_refused() {
+        let db = offline().await;
+        let mine = team("mine");
+        let refused = Repo::<Project>::new(&db, &mine).insert_one(&project("p", "theirs")).await.unwrap_err();
+        assert!(refused.to_string().contains("outside the request's scope"), "{}", refused);
+
+        // Scopes spanning several projects only read and update.
+        let across = Repo::<Project>::across(&db, &ProjectsScope { project_ids: vec!["p".to_string()] });
+        assert!(across.insert_many(&[project("p", "mine")]).await.is_err());
+    }
+
+    #[test]
+    fn new_projects_must_belong_to_the_team() {
+        let mine = team("mine");
+        assert!(mine.new_project(&project("p", "theirs")).is_none());
+        let scope = mine.new_project(&project("p", "mine")).unwrap();
+        assert_eq!((scope.team_id(), scope.project_id()), ("mine", "p"));
+        assert_eq!(scope.team().filter(), doc! { "team_id": "mine" });
+        assert_eq!(scope.filter(), doc! { "project_id": "p" });
+    }
+
+    #[test]
+    fn narrowing_keeps_only_the_retained_projects() {
+        let mut scope = ProjectsScope { project_ids: vec!["a".to_string(), "b".to_string(), "c".to_string()] };
+        scope.retain(|p| p != "b");
+        assert_eq!(scope.project_ids(), ["a", "c"]);
+    }
+}
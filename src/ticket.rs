@@ -1,17 +1,40 @@
 // src/ticket.rs
 
-use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use actix_web::{web, HttpResponse, Responder};
 use futures_util::StreamExt;
-use mongodb::bson::{doc, oid::ObjectId, DateTime as BsonDateTime};
+use mongodb::bson::{doc, oid::ObjectId};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{Utc, DateTime};
-use log::{error, info};
+use log::{error, info, warn};
 
+use crate::activity::{record_activity, ActivityEvent};
+use crate::ai_endpoints::{find_duplicate_tickets, DuplicateCandidate};
 use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::{can_edit_board, find_board};
+use crate::definition_of_done::{check_closing, record_override};
+use crate::fields::{select, FieldSet, FieldsQuery};
+use crate::out_of_office::route_assignment;
+use crate::release::release_in_project;
+use crate::response::{ApiResponse, CodedError, ok, ok_message};
+use crate::tenancy::{ProjectScope, Repo};
+use crate::ticket_assignment::auto_assignee;
+use crate::ticket_events::{commit, CommitError, TicketChange, MAX_ATTEMPTS};
+use crate::ticket_merge;
+use crate::ticket_move::find_redirect;
+use crate::ticket_references::{parse_references, validate_references, TicketReference};
+use crate::ticket_watchers::remove_watches;
+
+/// Statuses that count as finished for progress and "open ticket" queries.
+pub const CLOSED_STATUSES: [&str; 6] = ["Done", "done", "Closed", "closed", "Resolved", "resolved"];
+
+/// How a closed ticket was resolved; "done" unless the update says otherwise.
+pub const RESOLUTIONS: [&str; 5] = ["done", "fixed", "wont_fix", "duplicate", "cannot_reproduce"];
 
 /// The Ticket model, expanded with optional fields like sprint, reporter, assignee, etc.
-#[derive(Debug, Serialize, Deserialize)]
+/// Stored documents are a projection of the ticket's event log, see `crate::ticket_events`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ticket {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
@@ -33,6 +56,10 @@ pub struct Ticket {
     #[serde(default)]
     pub reporter: String,
 
+    /// Address of the person who raised the ticket by email
+    #[serde(default)]
+    pub requester_email: Option<String>,
+
     /// The user who’s assigned to the ticket (optional)
     pub assignee: Option<String>,
 
@@ -45,24 +72,76 @@ pub struct Ticket {
     /// A numeric sprint indicator, if you are using sprints
     pub sprint: Option<i32>,
 
+    /// Position within its column; lower sorts first
+    #[serde(default)]
+    pub rank: Option<f64>,
+
     /// Arbitrary labels
     pub labels: Option<Vec<String>>,
 
+    /// Release (`release_id`) the ticket is planned for, see `crate::release`
+    #[serde(default)]
+    pub fix_version: Option<String>,
+
+    /// Estimated effort in hours, used for sprint capacity planning
+    #[serde(default)]
+    pub estimate_hours: Option<f64>,
+
+    /// Tickets (`ticket_id`) that must be resolved before this one can start
+    #[serde(default)]
+    pub blocked_by: Vec<String>,
+
     /// Attachments or file URLs
     pub attachments: Option<Vec<String>>,
 
     /// Simple comments
     pub comments: Option<Vec<TicketComment>>,
 
+    /// Typed links (`doc:`, `chat:`, `ticket:`) found in the description and comments
+    #[serde(default)]
+    pub references: Vec<TicketReference>,
+
     pub created_at: DateTime<Utc>,
+
+    /// Set when the ticket reaches a closed status, cleared when it is reopened
+    #[serde(default)]
+    pub resolution: Option<String>,
+    #[serde(default)]
+    pub resolved_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub resolved_by: Option<String>,
+
+    /// Number of users who voted for the ticket; the votes live in `ticket_votes`
+    #[serde(default)]
+    pub vote_count: i64,
+
+    /// Sequence number of the last event applied to this projection (0 for legacy tickets)
+    #[serde(default)]
+    pub version: i64,
 }
 
 /// A small struct for comments
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TicketComment {
+    #[serde(default)]
+    pub comment_id: String,
     pub author_id: String,
     pub content: String,
     pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub reactions: Vec<CommentReaction>,
+    /// Question-style comments are resolved once answered
+    #[serde(default)]
+    pub resolved: bool,
+    #[serde(default)]
+    pub resolved_by: Option<String>,
+}
+
+/// One emoji on a comment and the users who reacted with it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentReaction {
+    pub emoji: String,
+    pub user_ids: Vec<String>,
 }
 
 /// Request payload for creating a ticket
@@ -77,8 +156,24 @@ pub struct CreateTicketRequest {
     pub due_date: Option<DateTime<Utc>>,
     pub ticket_type: Option<String>,
     pub sprint: Option<i32>,
+    pub rank: Option<f64>,
     pub labels: Option<Vec<String>>,
+    pub fix_version: Option<String>,
+    pub estimate_hours: Option<f64>,
+    pub blocked_by: Option<Vec<String>>,
     pub attachments: Option<Vec<String>>,
+    /// Also return likely duplicates among the project's open tickets
+    #[serde(default)]
+    pub check_duplicates: bool,
+}
+
+/// Response of `create_ticket` when `check_duplicates` was requested
+#[derive(Debug, Serialize)]
+pub struct CreatedTicket {
+    #[serde(flatten)]
+    pub ticket: Ticket,
+    /// `None` when the AI service could not be reached
+    pub possible_duplicates: Option<Vec<DuplicateCandidate>>,
 }
 
 /// Request payload for updating a ticket
@@ -92,46 +187,201 @@ pub struct UpdateTicketRequest {
     pub due_date: Option<DateTime<Utc>>,
     pub ticket_type: Option<String>,
     pub sprint: Option<i32>,
+    pub rank: Option<f64>,
     pub labels: Option<Vec<String>>,
+    /// Release id; an empty string clears it
+    pub fix_version: Option<String>,
+    pub estimate_hours: Option<f64>,
+    /// Replaces the ticket's blockers; an empty list clears them
+    pub blocked_by: Option<Vec<String>>,
     pub attachments: Option<Vec<String>>,
+    /// One of RESOLUTIONS; only for tickets that are or become closed
+    pub resolution: Option<String>,
+    /// Close the ticket although it misses the board's definition of done; project
+    /// owner only, and recorded as an override
+    pub override_definition_of_done: Option<bool>,
+    /// Version the client read. A ticket changed since then is merged with the update
+    /// or, when both changed the same fields, rejected with 409; see ticket_merge.rs.
+    pub version: Option<i64>,
+}
+
+impl UpdateTicketRequest {
+    /// Ticket fields the request sets.
+    fn requested_fields(&self) -> Vec<&'static str> {
+        [
+            ("title", self.title.is_some()),
+            ("description", self.description.is_some()),
+            ("status", self.status.is_some()),
+            ("priority", self.priority.is_some()),
+            ("assignee", self.assignee.is_some()),
+            ("due_date", self.due_date.is_some()),
+            ("ticket_type", self.ticket_type.is_some()),
+            ("sprint", self.sprint.is_some()),
+            ("rank", self.rank.is_some()),
+            ("labels", self.labels.is_some()),
+            ("fix_version", self.fix_version.is_some()),
+            ("estimate_hours", self.estimate_hours.is_some()),
+            ("blocked_by", self.blocked_by.is_some()),
+            ("attachments", self.attachments.is_some()),
+            ("resolution", self.resolution.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(field, set)| set.then_some(field))
+        .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReopenTicketRequest {
+    pub reason: String,
+    /// Defaults to the board's first open column, or "To Do"
+    pub status: Option<String>,
+}
+
+/// Validates planning fields: estimates must be non-negative and blockers must be
+/// other tickets of the same project that are not themselves blocked by this one.
+async fn validate_planning(
+    data: &AppState,
+    scope: &ProjectScope,
+    ticket_id: Option<&str>,
+    estimate_hours: Option<f64>,
+    blocked_by: Option<&[String]>,
+) -> Result<(), String> {
+    if matches!(estimate_hours, Some(h) if !(h >= 0.0 && h.is_finite())) {
+        return Err("estimate_hours must be a non-negative number".to_string());
+    }
+    let blocked_by = match blocked_by {
+        Some(b) if !b.is_empty() => b,
+        _ => return Ok(()),
+    };
+    if ticket_id.is_some_and(|id| blocked_by.iter().any(|b| b == id)) {
+        return Err("A ticket cannot block itself".to_string());
+    }
+    let mut cursor = Repo::<Ticket>::new(&data.mongodb, scope)
+        .find(doc! { "ticket_id": { "$in": blocked_by } })
+        .await
+        .map_err(|e| {
+            error!("Error fetching blockers: {}", e);
+            "Error validating blocked_by".to_string()
+        })?;
+    let mut found = 0;
+    while let Some(Ok(blocker)) = cursor.next().await {
+        found += 1;
+        if ticket_id.is_some_and(|id| blocker.blocked_by.iter().any(|b| b == id)) {
+            return Err(format!("\"{}\" is already blocked by this ticket", blocker.title));
+        }
+    }
+    let mut unique = blocked_by.to_vec();
+    unique.sort();
+    unique.dedup();
+    if found != unique.len() {
+        return Err("blocked_by must list tickets of this project".to_string());
+    }
+    Ok(())
+}
+
+/// The resolution field changes for moving `ticket` to `new_status`: closing sets
+/// them, leaving the closed statuses clears them, and `resolution` alone relabels an
+/// already closed ticket.
+pub(crate) fn resolution_changes(ticket: &Ticket, new_status: &str, resolution: Option<&str>, actor: &str) -> Vec<TicketChange> {
+    let was_closed = CLOSED_STATUSES.contains(&ticket.status.as_str());
+    let closing = CLOSED_STATUSES.contains(&new_status);
+    let mut changes = Vec::new();
+    match (was_closed, closing) {
+        (false, true) => {
+            changes.extend(TicketChange::field(ticket, "resolution", &Some(resolution.unwrap_or("done"))));
+            changes.extend(TicketChange::field(ticket, "resolved_at", &Some(Utc::now())));
+            changes.extend(TicketChange::field(ticket, "resolved_by", &Some(actor)));
+        }
+        (true, false) => {
+            changes.extend(TicketChange::field(ticket, "resolution", &None::<String>));
+            changes.extend(TicketChange::field(ticket, "resolved_at", &None::<DateTime<Utc>>));
+            changes.extend(TicketChange::field(ticket, "resolved_by", &None::<String>));
+        }
+        (true, true) => {
+            if let Some(resolution) = resolution {
+                changes.extend(TicketChange::field(ticket, "resolution", &Some(resolution)));
+            }
+        }
+        (false, false) => {}
+    }
+    changes
+}
+
+/// 200 for a created ticket, carrying the out-of-office note about its assignee if any.
+fn created_response<T: Serialize>(data: T, ooo_notice: Option<String>) -> HttpResponse {
+    match ooo_notice {
+        Some(notice) => HttpResponse::Ok().json(ApiResponse::new(data).with_message(notice)),
+        None => ok(data),
+    }
 }
 
 /// CREATE a new ticket
 pub async fn create_ticket(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     path: web::Path<(String, String)>, // (team_id, project_id)
     payload: web::Json<CreateTicketRequest>,
 ) -> impl Responder {
     let (team_id, project_id) = path.into_inner();
-    let current_user = match req.extensions().get::<String>() {
-        Some(uid) => uid.clone(),
-        None => return HttpResponse::Unauthorized().body("Unauthorized"),
+    let current_user = auth.user_id().to_string();
+
+    // 1) Check if user is a member of the team that owns the project.
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
     };
 
-    // 1) Check if user is a member of the team.
-    let user_teams = data.mongodb.db.collection::<mongodb::bson::Document>("user_teams");
-    let filter_member = doc! { "team_id": &team_id, "user_id": &current_user };
-    if user_teams.find_one(filter_member).await.ok().flatten().is_none() {
-        return HttpResponse::Unauthorized().body("Not a member of this team");
+    // 2) Check if user is a member of the project, and that the board is one of its boards.
+    if !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
     }
-
-    // 2) Check if user is a member of the project.
-    let project_memberships = data.mongodb.db.collection::<mongodb::bson::Document>("project_memberships");
-    let filter_project_member = doc! { "project_id": &project_id, "user_id": &current_user };
-    if project_memberships.find_one(filter_project_member).await.ok().flatten().is_none() {
-        return HttpResponse::Unauthorized().body("Not a member of this project");
+    if let Err(resp) = find_board(&data, &scope, &payload.board_id).await {
+        return resp;
     }
 
     // 3) If there's an assignee, confirm that user is also a team member
     if let Some(assignee_id) = &payload.assignee {
-        let filter_assignee = doc! { "team_id": &team_id, "user_id": assignee_id };
-        if user_teams.find_one(filter_assignee).await.ok().flatten().is_none() {
+        if !data.mongodb.check_user_team(assignee_id, &team_id).await.unwrap_or(false) {
             return HttpResponse::BadRequest().body("Assignee must be a member of the same team");
         }
     }
 
-    // 4) Create the new ticket.
+    if let Some(release_id) = &payload.fix_version {
+        if !release_in_project(&data, &project_id, release_id).await {
+            return HttpResponse::BadRequest().body("fix_version must be a release of this project");
+        }
+    }
+    if let Err(msg) = validate_planning(&data, &scope, None, payload.estimate_hours, payload.blocked_by.as_deref()).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+
+    // 4) Collect and validate typed references in the description.
+    let references = parse_references(payload.description.as_deref());
+    if let Err(msg) = validate_references(&data.mongodb, &team_id, &current_user, &references).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+
+    // 5) Without an explicit assignee, let the board's auto-assignment rule pick one.
+    let assignee = match &payload.assignee {
+        Some(a) => Some(a.clone()),
+        None => {
+            let labels = payload.labels.as_deref().unwrap_or_default();
+            auto_assignee(&data, &scope, &payload.board_id, labels).await
+        }
+    };
+    // An assignee who is out of office may hand the ticket to their delegate.
+    let (assignee, ooo_notice) = match assignee {
+        Some(a) => {
+            let (a, notice) = route_assignment(&data, &team_id, &a).await;
+            (Some(a), notice)
+        }
+        None => (None, None),
+    };
+
+    // 6) Create the new ticket.
+    let status = payload.status.clone().unwrap_or_else(|| "To Do".to_string());
+    let closed = CLOSED_STATUSES.contains(&status.as_str());
     let new_ticket = Ticket {
         id: None,
         ticket_id: Uuid::new_v4().to_string(),
@@ -139,26 +389,60 @@ pub async fn create_ticket(
         project_id: project_id.clone(),
         title: payload.title.clone(),
         description: payload.description.clone(),
-        status: payload.status.clone().unwrap_or_else(|| "To Do".to_string()),
+        status,
         priority: payload.priority.clone(),
         reporter: current_user.clone(), // set automatically
-        assignee: payload.assignee.clone(),
-        due_date: payload.due_date.clone(),
+        requester_email: None,
+        assignee,
+        due_date: payload.due_date,
         ticket_type: payload.ticket_type.clone(),
         sprint: payload.sprint,
+        rank: payload.rank,
         labels: payload.labels.clone(),
+        fix_version: payload.fix_version.clone(),
+        estimate_hours: payload.estimate_hours,
+        blocked_by: payload.blocked_by.clone().unwrap_or_default(),
         attachments: payload.attachments.clone(),
         comments: Some(vec![]),
+        references,
         created_at: Utc::now(),
+        resolution: closed.then(|| "done".to_string()),
+        resolved_at: closed.then(Utc::now),
+        resolved_by: closed.then(|| current_user.clone()),
+        vote_count: 0,
+        version: 0,
     };
 
-    let tickets_coll = data.mongodb.db.collection::<Ticket>("tickets");
-    match tickets_coll.insert_one(&new_ticket).await {
-        Ok(_) => {
+    let created = TicketChange::Created { ticket: Box::new(new_ticket.clone()) };
+    match commit(&data.mongodb, None, vec![created], &current_user).await {
+        Ok(Some(new_ticket)) => {
             info!("Ticket created: {:?}", new_ticket.ticket_id);
-            HttpResponse::Ok().json(&new_ticket)
+            record_activity(&data.mongodb, ActivityEvent::new(
+                &team_id,
+                Some(&project_id),
+                &current_user,
+                "ticket_created",
+                &new_ticket.ticket_id,
+                format!("created ticket \"{}\"", new_ticket.title),
+            )).await;
+            if !payload.check_duplicates {
+                return created_response(&new_ticket, ooo_notice);
+            }
+            let possible_duplicates = match find_duplicate_tickets(
+                &data, &scope, &new_ticket.title, new_ticket.description.as_deref(), Some(&new_ticket.ticket_id),
+            ).await {
+                Ok(found) => Some(found),
+                Err(e) => {
+                    warn!("Duplicate check failed for ticket {}: {}", new_ticket.ticket_id, e);
+                    None
+                }
+            };
+            created_response(CreatedTicket { ticket: new_ticket, possible_duplicates }, ooo_notice)
         },
-        Err(e) => {
+        Ok(None) | Err(CommitError::Conflict) | Err(CommitError::Invalid(_)) => {
+            HttpResponse::Conflict().body("Ticket already exists")
+        }
+        Err(CommitError::Db(e)) => {
             error!("Error inserting ticket: {}", e);
             HttpResponse::InternalServerError().body("Error inserting ticket")
         }
@@ -167,170 +451,369 @@ pub async fn create_ticket(
 
 /// GET a single ticket
 pub async fn get_ticket(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     path: web::Path<(String, String, String)>, // (team_id, project_id, ticket_id)
+    query: web::Query<FieldsQuery>,
 ) -> impl Responder {
     let (team_id, project_id, ticket_id) = path.into_inner();
-    let current_user = match req.extensions().get::<String>() {
-        Some(uid) => uid.clone(),
-        None => return HttpResponse::Unauthorized().body("Unauthorized"),
-    };
+    let fields = FieldSet::parse(query.fields.as_deref(), &["ticket_id"]);
 
     // Check membership in team and project
-    let user_teams = data.mongodb.db.collection::<mongodb::bson::Document>("user_teams");
-    let filter_member = doc! { "team_id": &team_id, "user_id": &current_user };
-    if user_teams.find_one(filter_member).await.ok().flatten().is_none() {
-        return HttpResponse::Unauthorized().body("Not a member of this team");
-    }
-    let project_memberships = data.mongodb.db.collection::<mongodb::bson::Document>("project_memberships");
-    let filter_project_member = doc! { "project_id": &project_id, "user_id": &current_user };
-    if project_memberships.find_one(filter_project_member).await.ok().flatten().is_none() {
-        return HttpResponse::Unauthorized().body("Not a member of this project");
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    if !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
     }
 
-    let tickets_coll = data.mongodb.db.collection::<Ticket>("tickets");
-    let filter = doc! { "ticket_id": &ticket_id, "project_id": &project_id };
+    let tickets_coll = Repo::<Ticket>::new(&data.mongodb, &scope);
+    let filter = doc! { "ticket_id": &ticket_id };
     match tickets_coll.find_one(filter).await {
-        Ok(Some(ticket)) => HttpResponse::Ok().json(ticket),
-        Ok(None) => HttpResponse::NotFound().body("Ticket not found"),
+        Ok(Some(ticket)) => ok(select(&ticket, &fields)),
+        // Tombstone: the ticket was moved elsewhere, point the client at its new home.
+        Ok(None) => match find_redirect(&data.mongodb, &project_id, &ticket_id).await {
+            Some(moved) => HttpResponse::MovedPermanently()
+                .insert_header((
+                    "Location",
+                    format!(
+                        "/teams/{}/projects/{}/tickets/{}",
+                        moved.to_team_id, moved.to_project_id, moved.ticket_id
+                    ),
+                ))
+                .json(moved),
+            None => HttpResponse::NotFound().error("ticket_not_found"),
+        },
         Err(e) => {
             error!("Error fetching ticket: {}", e);
-            HttpResponse::InternalServerError().body("Error fetching ticket")
+            HttpResponse::InternalServerError().error("error_fetching_ticket")
         }
     }
 }
 
 /// UPDATE an existing ticket
 pub async fn update_ticket(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     path: web::Path<(String, String, String)>, // (team_id, project_id, ticket_id)
     payload: web::Json<UpdateTicketRequest>,
 ) -> impl Responder {
     let (team_id, project_id, ticket_id) = path.into_inner();
-    let current_user = match req.extensions().get::<String>() {
-        Some(uid) => uid.clone(),
-        None => return HttpResponse::Unauthorized().body("Unauthorized"),
-    };
+    let current_user = auth.user_id().to_string();
 
     // Check membership
-    let user_teams = data.mongodb.db.collection::<mongodb::bson::Document>("user_teams");
-    let filter_member = doc! { "team_id": &team_id, "user_id": &current_user };
-    if user_teams.find_one(filter_member).await.ok().flatten().is_none() {
-        return HttpResponse::Unauthorized().body("Not a member of this team");
-    }
-    let project_memberships = data.mongodb.db.collection::<mongodb::bson::Document>("project_memberships");
-    let filter_project_member = doc! { "project_id": &project_id, "user_id": &current_user };
-    if project_memberships.find_one(filter_project_member).await.ok().flatten().is_none() {
-        return HttpResponse::Unauthorized().body("Not a member of this project");
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    if !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
     }
 
-    // If there's an assignee, check membership as well.
-    if let Some(assignee_id) = &payload.assignee {
-        let filter_assignee = doc! { "team_id": &team_id, "user_id": assignee_id };
-        if user_teams.find_one(filter_assignee).await.ok().flatten().is_none() {
+    // If there's an assignee, check membership as well; an empty one unassigns.
+    if let Some(assignee_id) = payload.assignee.as_ref().filter(|a| !a.is_empty()) {
+        if !data.mongodb.check_user_team(assignee_id, &team_id).await.unwrap_or(false) {
             return HttpResponse::BadRequest().body("Assignee must be a member of the same team");
         }
     }
 
-    let tickets_coll = data.mongodb.db.collection::<Ticket>("tickets");
-    let filter = doc! { "ticket_id": &ticket_id, "project_id": &project_id };
+    let p = &*payload;
+    if p.requested_fields().is_empty() {
+        return HttpResponse::BadRequest().error("no_fields_to_update");
+    }
+    if matches!(&p.resolution, Some(r) if !RESOLUTIONS.contains(&r.as_str())) {
+        return HttpResponse::BadRequest().body(format!("resolution must be one of {}", RESOLUTIONS.join(", ")));
+    }
+    let fix_version = p.fix_version.as_ref().map(|v| Some(v.clone()).filter(|v| !v.is_empty()));
+    if let Some(Some(release_id)) = &fix_version {
+        if !release_in_project(&data, &project_id, release_id).await {
+            return HttpResponse::BadRequest().body("fix_version must be a release of this project");
+        }
+    }
+    if let Err(msg) = validate_planning(&data, &scope, Some(&ticket_id), p.estimate_hours, p.blocked_by.as_deref()).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+
+    let tickets_coll = Repo::<Ticket>::new(&data.mongodb, &scope);
+    let filter = doc! { "ticket_id": &ticket_id };
 
-    let mut update_doc = doc! {};
-    if let Some(title) = &payload.title { update_doc.insert("title", title); }
-    if let Some(description) = &payload.description { update_doc.insert("description", description); }
-    if let Some(status) = &payload.status { update_doc.insert("status", status); }
-    if let Some(priority) = &payload.priority { update_doc.insert("priority", priority); }
-    if let Some(assignee) = &payload.assignee { update_doc.insert("assignee", assignee); }
-    if let Some(due_date) = &payload.due_date {
-        // Convert due_date to milliseconds and then to BSON DateTime
-        update_doc.insert("due_date", BsonDateTime::from_millis(due_date.timestamp_millis()));
+    // Diff against the latest projection; on a concurrent write, re-read and diff again.
+    for attempt in 1..=MAX_ATTEMPTS {
+        let ticket = match tickets_coll.find_one(filter.clone()).await {
+            Ok(Some(t)) => t,
+            Ok(None) => return HttpResponse::NotFound().error("ticket_not_found"),
+            Err(e) => {
+                error!("Error fetching ticket: {}", e);
+                return HttpResponse::InternalServerError().error("error_fetching_ticket");
+            }
+        };
+        let mut changes = Vec::new();
+        if let Some(title) = &p.title { changes.extend(TicketChange::field(&ticket, "title", title)); }
+        if let Some(description) = &p.description {
+            changes.extend(TicketChange::field(&ticket, "description", description));
+            // Re-derive references from the new description plus existing comments. Only the
+            // ones this edit adds are checked against the editor's access; references already
+            // on the ticket are kept as they are, even when their target has since gone.
+            let comments = ticket.comments.iter().flatten().map(|c| c.content.as_str());
+            let references = parse_references(std::iter::once(description.as_str()).chain(comments));
+            let added: Vec<TicketReference> =
+                references.iter().filter(|r| !ticket.references.contains(r)).cloned().collect();
+            if let Err(msg) = validate_references(&data.mongodb, &team_id, &current_user, &added).await {
+                return HttpResponse::BadRequest().body(msg);
+            }
+            changes.extend(TicketChange::field(&ticket, "references", &references));
+        }
+        if let Some(status) = &p.status { changes.extend(TicketChange::field(&ticket, "status", status)); }
+        let new_status = p.status.as_deref().unwrap_or(&ticket.status);
+        if p.resolution.is_some() && !CLOSED_STATUSES.contains(&new_status) {
+            return HttpResponse::BadRequest().body("resolution can only be set on a closed ticket");
+        }
+        let mut overridden = Vec::new();
+        if !CLOSED_STATUSES.contains(&ticket.status.as_str()) && CLOSED_STATUSES.contains(&new_status) {
+            // Check the ticket as it will be, so an update can meet the conditions itself.
+            let mut closed = ticket.clone();
+            closed.status = new_status.to_string();
+            if let Some(description) = &p.description { closed.description = Some(description.clone()); }
+            if let Some(estimate_hours) = p.estimate_hours { closed.estimate_hours = Some(estimate_hours); }
+            if let Some(attachments) = &p.attachments { closed.attachments = Some(attachments.clone()); }
+            match check_closing(&auth, &data, &scope, &closed, p.override_definition_of_done.unwrap_or(false)).await {
+                Ok(unmet) => overridden = unmet,
+                Err(resp) => return resp,
+            }
+        }
+        changes.extend(resolution_changes(&ticket, new_status, p.resolution.as_deref(), &current_user));
+        if let Some(priority) = &p.priority { changes.extend(TicketChange::field(&ticket, "priority", priority)); }
+        let mut ooo_notice = None;
+        if let Some(assignee) = &p.assignee {
+            let assignee = if !assignee.is_empty() && ticket.assignee.as_ref() != Some(assignee) {
+                let (routed, notice) = route_assignment(&data, &team_id, assignee).await;
+                ooo_notice = notice;
+                routed
+            } else {
+                assignee.clone()
+            };
+            changes.extend(TicketChange::field(&ticket, "assignee", &assignee));
+        }
+        if let Some(due_date) = &p.due_date { changes.extend(TicketChange::field(&ticket, "due_date", due_date)); }
+        if let Some(ticket_type) = &p.ticket_type { changes.extend(TicketChange::field(&ticket, "ticket_type", ticket_type)); }
+        if let Some(sprint) = &p.sprint { changes.extend(TicketChange::field(&ticket, "sprint", sprint)); }
+        if let Some(rank) = &p.rank { changes.extend(TicketChange::field(&ticket, "rank", rank)); }
+        if let Some(labels) = &p.labels { changes.extend(TicketChange::field(&ticket, "labels", labels)); }
+        if let Some(fix_version) = &fix_version { changes.extend(TicketChange::field(&ticket, "fix_version", fix_version)); }
+        if let Some(estimate_hours) = &p.estimate_hours { changes.extend(TicketChange::field(&ticket, "estimate_hours", estimate_hours)); }
+        if let Some(blocked_by) = &p.blocked_by { changes.extend(TicketChange::field(&ticket, "blocked_by", blocked_by)); }
+        if let Some(attachments) = &p.attachments { changes.extend(TicketChange::field(&ticket, "attachments", attachments)); }
+
+        if let Some(based_on) = p.version.filter(|v| *v != ticket.version) {
+            match ticket_merge::conflicts(&data.mongodb, &ticket, based_on, &changes, &p.requested_fields()).await {
+                Ok(fields) if fields.is_empty() => {}
+                Ok(fields) => return ticket_merge::reject(&ticket, based_on, fields, &current_user),
+                Err(e) => {
+                    error!("Error reading ticket history: {}", e);
+                    return HttpResponse::InternalServerError().body("Error updating ticket");
+                }
+            }
+        }
+        if changes.is_empty() {
+            return HttpResponse::Ok().json(ApiResponse::new(&ticket).with_message("Ticket updated successfully"));
+        }
+        match commit(&data.mongodb, Some(&ticket), changes, &current_user).await {
+            Ok(updated) => {
+                let (kind, summary) = match &p.status {
+                    Some(status) => ("ticket_moved", format!("moved ticket to {}", status)),
+                    None => ("ticket_updated", "updated ticket".to_string()),
+                };
+                record_activity(&data.mongodb, ActivityEvent::new(
+                    &team_id, Some(&project_id), &current_user, kind, &ticket_id, summary,
+                )).await;
+                if let (false, Some(updated)) = (overridden.is_empty(), &updated) {
+                    record_override(&data, &team_id, updated, overridden, &current_user).await;
+                }
+                let message = ooo_notice.unwrap_or_else(|| "Ticket updated successfully".to_string());
+                return HttpResponse::Ok().json(ApiResponse::new(updated).with_message(message));
+            }
+            Err(CommitError::Conflict) if attempt < MAX_ATTEMPTS => continue,
+            Err(CommitError::Conflict) => break,
+            Err(CommitError::Invalid(msg)) => return HttpResponse::BadRequest().body(msg),
+            Err(CommitError::Db(e)) => {
+                error!("Error updating ticket: {}", e);
+                return HttpResponse::InternalServerError().body("Error updating ticket");
+            }
+        }
     }
-    if let Some(ticket_type) = &payload.ticket_type { update_doc.insert("ticket_type", ticket_type); }
-    if let Some(sprint) = &payload.sprint { update_doc.insert("sprint", sprint); }
-    if let Some(labels) = &payload.labels { update_doc.insert("labels", labels); }
-    if let Some(attachments) = &payload.attachments { update_doc.insert("attachments", attachments); }
+    HttpResponse::Conflict().body("Ticket was modified concurrently, please retry")
+}
 
-    if update_doc.is_empty() {
-        return HttpResponse::BadRequest().body("No fields to update");
+/// POST /teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/reopen
+/// Moves a closed ticket back to an open status and clears its resolution; the
+/// reason is kept in the ticket's history.
+pub async fn reopen_ticket(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>, // (team_id, project_id, ticket_id)
+    payload: web::Json<ReopenTicketRequest>,
+) -> impl Responder {
+    let (team_id, project_id, ticket_id) = path.into_inner();
+    let current_user = auth.user_id().to_string();
+
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    if !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
+    let reason = payload.reason.trim();
+    if reason.is_empty() {
+        return HttpResponse::BadRequest().body("A reason is required");
+    }
+    if matches!(&payload.status, Some(s) if CLOSED_STATUSES.contains(&s.as_str())) {
+        return HttpResponse::BadRequest().body("status must be an open status");
     }
 
-    let update_op = doc! { "$set": update_doc };
-    match tickets_coll.update_one(filter, update_op).await {
-        Ok(res) => {
-            if res.matched_count == 0 {
-                HttpResponse::NotFound().body("Ticket not found")
-            } else {
-                HttpResponse::Ok().body("Ticket updated successfully")
+    let tickets_coll = Repo::<Ticket>::new(&data.mongodb, &scope);
+    let filter = doc! { "ticket_id": &ticket_id };
+    for _ in 0..MAX_ATTEMPTS {
+        let ticket = match tickets_coll.find_one(filter.clone()).await {
+            Ok(Some(t)) => t,
+            Ok(None) => return HttpResponse::NotFound().error("ticket_not_found"),
+            Err(e) => {
+                error!("Error fetching ticket: {}", e);
+                return HttpResponse::InternalServerError().error("error_fetching_ticket");
+            }
+        };
+        if !CLOSED_STATUSES.contains(&ticket.status.as_str()) {
+            return HttpResponse::Conflict().body("Ticket is not closed");
+        }
+        let status = match &payload.status {
+            Some(s) => s.clone(),
+            None => find_board(&data, &scope, &ticket.board_id)
+                .await
+                .ok()
+                .and_then(|b| b.columns.into_iter().find(|c| !CLOSED_STATUSES.contains(&c.as_str())))
+                .unwrap_or_else(|| "To Do".to_string()),
+        };
+
+        let mut changes = vec![TicketChange::Reopened { reason: reason.to_string() }];
+        changes.extend(TicketChange::field(&ticket, "status", &status));
+        changes.extend(resolution_changes(&ticket, &status, None, &current_user));
+        match commit(&data.mongodb, Some(&ticket), changes, &current_user).await {
+            Ok(updated) => {
+                record_activity(&data.mongodb, ActivityEvent::new(
+                    &team_id, Some(&project_id), &current_user, "ticket_reopened", &ticket_id,
+                    format!("reopened ticket \"{}\": {}", ticket.title, reason),
+                )).await;
+                return ok(updated);
+            }
+            Err(CommitError::Conflict) => continue,
+            Err(CommitError::Invalid(msg)) => return HttpResponse::BadRequest().body(msg),
+            Err(CommitError::Db(e)) => {
+                error!("Error reopening ticket: {}", e);
+                return HttpResponse::InternalServerError().body("Error reopening ticket");
             }
-        },
-        Err(e) => {
-            error!("Error updating ticket: {}", e);
-            HttpResponse::InternalServerError().body("Error updating ticket")
         }
     }
+    HttpResponse::Conflict().body("Ticket was modified concurrently, please retry")
 }
 
 /// DELETE a ticket
 pub async fn delete_ticket(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     path: web::Path<(String, String, String)>, // (team_id, project_id, ticket_id)
 ) -> impl Responder {
     let (team_id, project_id, ticket_id) = path.into_inner();
-    let current_user = match req.extensions().get::<String>() {
-        Some(uid) => uid.clone(),
-        None => return HttpResponse::Unauthorized().body("Unauthorized"),
-    };
+    let current_user = auth.user_id().to_string();
 
     // Check membership
-    let user_teams = data.mongodb.db.collection::<mongodb::bson::Document>("user_teams");
-    let filter_member = doc! { "team_id": &team_id, "user_id": &current_user };
-    if user_teams.find_one(filter_member).await.ok().flatten().is_none() {
-        return HttpResponse::Unauthorized().body("Not a member of this team");
-    }
-    let project_memberships = data.mongodb.db.collection::<mongodb::bson::Document>("project_memberships");
-    let filter_project_member = doc! { "project_id": &project_id, "user_id": &current_user };
-    if project_memberships.find_one(filter_project_member).await.ok().flatten().is_none() {
-        return HttpResponse::Unauthorized().body("Not a member of this project");
-    }
-
-    let tickets_coll = data.mongodb.db.collection::<Ticket>("tickets");
-    let filter = doc! { "ticket_id": &ticket_id, "project_id": &project_id };
-    match tickets_coll.delete_one(filter).await {
-        Ok(res) => {
-            if res.deleted_count == 0 {
-                HttpResponse::NotFound().body("Ticket not found or already deleted")
-            } else {
-                HttpResponse::Ok().body("Ticket deleted successfully")
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    if !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
+
+    let tickets_coll = Repo::<Ticket>::new(&data.mongodb, &scope);
+    let filter = doc! { "ticket_id": &ticket_id };
+    for _ in 0..MAX_ATTEMPTS {
+        let ticket = match tickets_coll.find_one(filter.clone()).await {
+            Ok(Some(t)) => t,
+            Ok(None) => return HttpResponse::NotFound().body("Ticket not found or already deleted"),
+            Err(e) => {
+                error!("Error fetching ticket: {}", e);
+                return HttpResponse::InternalServerError().body("Error deleting ticket");
+            }
+        };
+        match commit(&data.mongodb, Some(&ticket), vec![TicketChange::Deleted], &current_user).await {
+            Ok(_) => {
+                let votes = data.mongodb.db.collection::<mongodb::bson::Document>("ticket_votes");
+                if let Err(e) = votes.delete_many(doc! { "ticket_id": &ticket_id }).await {
+                    warn!("Error removing votes of deleted ticket {}: {}", ticket_id, e);
+                }
+                if let Err(e) = remove_watches(&data.mongodb, &[&ticket_id]).await {
+                    warn!("Error removing watchers of deleted ticket {}: {}", ticket_id, e);
+                }
+                return ok_message("Ticket deleted successfully");
+            }
+            Err(CommitError::Conflict) => continue,
+            Err(CommitError::Invalid(msg)) => return HttpResponse::BadRequest().body(msg),
+            Err(CommitError::Db(e)) => {
+                error!("Error deleting ticket: {}", e);
+                return HttpResponse::InternalServerError().body("Error deleting ticket");
             }
-        },
-        Err(e) => {
-            error!("Error deleting ticket: {}", e);
-            HttpResponse::InternalServerError().body("Error deleting ticket")
         }
     }
+    HttpResponse::Conflict().body("Ticket was modified concurrently, please retry")
 }
 
 /// LIST tickets for a given board
 #[derive(Debug, Deserialize)]
 pub struct TicketQuery {
     pub board_id: String,
+    /// Optional `?fields=` selection, see `crate::fields`
+    pub fields: Option<String>,
+    /// "votes" lists the most voted tickets first
+    pub sort: Option<String>,
 }
 
+/// LIST the tickets of a board
 pub async fn list_tickets(
-    _req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
+    path: web::Path<(String, String)>, // (team_id, project_id)
     query: web::Query<TicketQuery>,
 ) -> impl Responder {
-    let tickets_coll = data.mongodb.db.collection::<Ticket>("tickets");
+    let (team_id, project_id) = path.into_inner();
+
+    // 1) Caller must be on the team that owns the project.
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+
+    // 2) The board must belong to this project, and the caller must be a
+    //    project member or one of its participants.
+    match find_board(&data, &scope, &query.board_id).await {
+        Ok(board) if can_edit_board(&auth, &board).await => {}
+        Ok(_) => return HttpResponse::Unauthorized().error("not_project_or_board_member"),
+        Err(resp) => return resp,
+    }
+
+    let sort = match query.sort.as_deref() {
+        None => doc! {},
+        Some("votes") => doc! { "vote_count": -1, "created_at": 1 },
+        Some(_) => return HttpResponse::BadRequest().body("sort must be \"votes\""),
+    };
+
+    let tickets_coll = Repo::<Ticket>::new(&data.mongodb, &scope);
     let filter = doc! { "board_id": &query.board_id };
-    let mut cursor = match tickets_coll.find(filter).await {
+    let mut cursor = match tickets_coll.find(filter).sort(sort).await {
         Ok(cur) => cur,
         Err(e) => {
             error!("Error fetching tickets: {}", e);
-            return HttpResponse::InternalServerError().body("Error fetching tickets");
+            return HttpResponse::InternalServerError().error("error_fetching_tickets");
         }
     };
 
@@ -344,5 +827,84 @@ pub async fn list_tickets(
             }
         }
     }
-    HttpResponse::Ok().json(tickets)
+    let fields = FieldSet::parse(query.fields.as_deref(), &["ticket_id"]);
+    ok(select(&tickets, &fields))
+}
+
+/// Request payload for commenting on a ticket
+#[derive(Debug, Deserialize)]
+pub struct AddCommentRequest {
+    pub content: String,
+}
+
+/// ADD a comment to a ticket
+pub async fn add_comment(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>, // (team_id, project_id, ticket_id)
+    payload: web::Json<AddCommentRequest>,
+) -> impl Responder {
+    let (team_id, project_id, ticket_id) = path.into_inner();
+    let current_user = auth.user_id().to_string();
+
+    // Check membership
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    if !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
+
+    if payload.content.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Comment must not be empty");
+    }
+
+    let new_refs = parse_references([payload.content.as_str()]);
+    if let Err(msg) = validate_references(&data.mongodb, &team_id, &current_user, &new_refs).await {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    let comment = TicketComment {
+        comment_id: Uuid::new_v4().to_string(),
+        author_id: current_user.clone(),
+        content: payload.content.clone(),
+        timestamp: Utc::now(),
+        reactions: Vec::new(),
+        resolved: false,
+        resolved_by: None,
+    };
+
+    let tickets_coll = Repo::<Ticket>::new(&data.mongodb, &scope);
+    let filter = doc! { "ticket_id": &ticket_id };
+    for _ in 0..MAX_ATTEMPTS {
+        let ticket = match tickets_coll.find_one(filter.clone()).await {
+            Ok(Some(t)) => t,
+            Ok(None) => return HttpResponse::NotFound().error("ticket_not_found"),
+            Err(e) => {
+                error!("Error fetching ticket: {}", e);
+                return HttpResponse::InternalServerError().error("error_fetching_ticket");
+            }
+        };
+        let mut references = ticket.references.clone();
+        for r in &new_refs {
+            if !references.contains(r) {
+                references.push(r.clone());
+            }
+        }
+
+        let mut changes = vec![TicketChange::Commented { comment: comment.clone() }];
+        changes.extend(TicketChange::field(&ticket, "references", &references));
+        match commit(&data.mongodb, Some(&ticket), changes, &current_user).await {
+            Ok(_) => {
+                return ok(comment);
+            }
+            Err(CommitError::Conflict) => continue,
+            Err(CommitError::Invalid(msg)) => return HttpResponse::BadRequest().body(msg),
+            Err(CommitError::Db(e)) => {
+                error!("Error adding comment: {}", e);
+                return HttpResponse::InternalServerError().body("Error adding comment");
+            }
+        }
+    }
+    HttpResponse::Conflict().body("Ticket was modified concurrently, please retry")
 }
@@ -0,0 +1,222 @@
+// src/ticket_assignment.rs
+//
+// Per-board auto-assignment. A board may carry a rule that picks an assignee for
+// tickets created without one: round-robin over the board's participants, the
+// participant with the fewest open tickets in the project, or a label → user
+// mapping with one of the other two as fallback. Only current team members are
+// ever picked.
+
+use std::collections::HashMap;
+
+use actix_web::{web, HttpResponse, Responder};
+use log::{error, warn};
+use mongodb::bson::{doc, to_bson};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::{can_edit_board, find_board, Board};
+use crate::tenancy::{ProjectScope, Repo};
+use crate::response::{CodedError, ok};
+use crate::ticket::{Ticket, CLOSED_STATUSES};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssignStrategy {
+    RoundRobin,
+    LeastLoaded,
+    ByLabel,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoAssignRule {
+    pub strategy: AssignStrategy,
+    /// Label → user id, consulted in ticket label order; only for `by_label`
+    #[serde(default)]
+    pub label_assignees: HashMap<String, String>,
+    /// Used by `by_label` when no label matches; must not be `by_label` itself
+    pub fallback: Option<AssignStrategy>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoardSettings {
+    pub auto_assign: Option<AutoAssignRule>,
+}
+
+async fn validate_rule(data: &AppState, team_id: &str, rule: &AutoAssignRule) -> Result<(), String> {
+    if rule.fallback == Some(AssignStrategy::ByLabel) {
+        return Err("fallback must be round_robin or least_loaded".to_string());
+    }
+    if rule.strategy != AssignStrategy::ByLabel {
+        return Ok(());
+    }
+    if rule.label_assignees.is_empty() {
+        return Err("by_label needs at least one label_assignees entry".to_string());
+    }
+    for (label, user_id) in &rule.label_assignees {
+        if label.trim().is_empty() {
+            return Err("Labels must not be empty".to_string());
+        }
+        if !data.mongodb.check_user_team(user_id, team_id).await.unwrap_or(false) {
+            return Err(format!("Assignee for label \"{}\" must be a member of this team", label));
+        }
+    }
+    Ok(())
+}
+
+/// Board participants who are still on the team, in board order.
+async fn candidates(data: &AppState, team_id: &str, board: &Board) -> Vec<String> {
+    let mut members = Vec::new();
+    for p in &board.participants {
+        if data.mongodb.check_user_team(p, team_id).await.unwrap_or(false) && !members.contains(p) {
+            members.push(p.clone());
+        }
+    }
+    members
+}
+
+/// Advance the board's turn counter atomically, so concurrent creates get different people.
+async fn round_robin(data: &AppState, scope: &ProjectScope, board: &Board, candidates: &[String]) -> mongodb::error::Result<Option<String>> {
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    let updated = Repo::<Board>::new(&data.mongodb, scope)
+        .find_one_and_update(doc! { "board_id": &board.board_id }, doc! { "$inc": { "auto_assign_turn": 1_i64 } })
+        .return_document(ReturnDocument::After)
+        .await?;
+    let turn = updated.and_then(|b| b.get_i64("auto_assign_turn").ok()).unwrap_or(1);
+    Ok(Some(candidates[(turn - 1).rem_euclid(candidates.len() as i64) as usize].clone()))
+}
+
+/// Fewest open tickets in the board's project; ties go to the earlier participant.
+async fn least_loaded(data: &AppState, scope: &ProjectScope, candidates: &[String]) -> mongodb::error::Result<Option<String>> {
+    let tickets = Repo::<Ticket>::new(&data.mongodb, scope);
+    let mut best: Option<(u64, &String)> = None;
+    for user_id in candidates {
+        let open = tickets
+            .count_documents(doc! {
+                "assignee": user_id,
+                "status": { "$nin": CLOSED_STATUSES.to_vec() },
+            })
+            .await?;
+        if best.is_none_or(|(n, _)| open < n) {
+            best = Some((open, user_id));
+        }
+    }
+    Ok(best.map(|(_, u)| u.clone()))
+}
+
+async fn apply(
+    data: &AppState,
+    scope: &ProjectScope,
+    board: &Board,
+    rule: &AutoAssignRule,
+    labels: &[String],
+) -> mongodb::error::Result<Option<String>> {
+    let strategy = match rule.strategy {
+        AssignStrategy::ByLabel => {
+            for label in labels {
+                if let Some(user_id) = rule.label_assignees.get(label) {
+                    if data.mongodb.check_user_team(user_id, scope.team_id()).await? {
+                        return Ok(Some(user_id.clone()));
+                    }
+                }
+            }
+            match rule.fallback {
+                Some(f) => f,
+                None => return Ok(None),
+            }
+        }
+        s => s,
+    };
+    let candidates = candidates(data, scope.team_id(), board).await;
+    match strategy {
+        AssignStrategy::RoundRobin => round_robin(data, scope, board, &candidates).await,
+        AssignStrategy::LeastLoaded => least_loaded(data, scope, &candidates).await,
+        AssignStrategy::ByLabel => Ok(None),
+    }
+}
+
+/// Assignee chosen by the board's rule for a new ticket, or None when the board has
+/// no rule or nobody qualifies. Failures are logged and leave the ticket unassigned.
+pub(crate) async fn auto_assignee(
+    data: &AppState,
+    scope: &ProjectScope,
+    board_id: &str,
+    labels: &[String],
+) -> Option<String> {
+    let board = find_board(data, scope, board_id).await.ok()?;
+    let rule = board.auto_assign.as_ref()?;
+    match apply(data, scope, &board, rule, labels).await {
+        Ok(assignee) => assignee,
+        Err(e) => {
+            warn!("Auto-assignment failed on board {}: {}", board_id, e);
+            None
+        }
+    }
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/boards/{board_id}/settings
+pub async fn get_board_settings(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (team_id, project_id, board_id) = path.into_inner();
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let board = match find_board(&data, &scope, &board_id).await {
+        Ok(b) => b,
+        Err(resp) => return resp,
+    };
+    if !can_edit_board(&auth, &board).await {
+        return HttpResponse::Unauthorized().error("not_project_or_board_member");
+    }
+    ok(BoardSettings { auto_assign: board.auto_assign })
+}
+
+/// PUT /teams/{team_id}/projects/{project_id}/boards/{board_id}/settings
+/// `{"auto_assign": null}` turns auto-assignment off.
+pub async fn update_board_settings(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    payload: web::Json<BoardSettings>,
+) -> impl Responder {
+    let (team_id, project_id, board_id) = path.into_inner();
+    let scope = match auth.project_scope(&team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    match find_board(&data, &scope, &board_id).await {
+        Ok(board) if can_edit_board(&auth, &board).await => {}
+        Ok(_) => return HttpResponse::Unauthorized().error("not_project_or_board_member"),
+        Err(resp) => return resp,
+    }
+    let settings = payload.into_inner();
+    if let Some(rule) = &settings.auto_assign {
+        if let Err(msg) = validate_rule(&data, &team_id, rule).await {
+            return HttpResponse::BadRequest().body(msg);
+        }
+    }
+
+    let rule = match to_bson(&settings.auto_assign) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Error serializing board settings: {}", e);
+            return HttpResponse::InternalServerError().body("Error updating board settings");
+        }
+    };
+    let boards_coll = Repo::<Board>::new(&data.mongodb, &scope);
+    match boards_coll.update_one(doc! { "board_id": &board_id }, doc! { "$set": { "auto_assign": rule } }).await {
+        Ok(res) if res.matched_count == 1 => ok(settings),
+        Ok(_) => HttpResponse::NotFound().error("board_not_found"),
+        Err(e) => {
+            error!("Error updating board settings: {}", e);
+            HttpResponse::InternalServerError().body("Error updating board settings")
+        }
+    }
+}
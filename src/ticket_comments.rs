@@ -0,0 +1,197 @@
+// src/ticket_comments.rs
+//
+// Review-thread style workflow on ticket comments: emoji reactions, and a
+// resolved flag for question-style comments so open questions can be listed.
+// Both are ticket events, so they show up in the ticket's history like any edit.
+
+use actix_web::{web, HttpResponse, Responder};
+use log::error;
+use mongodb::bson::doc;
+use serde::Deserialize;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::custom_emoji::team_has_emoji;
+use crate::emoji::is_builtin_shortcode;
+use crate::response::{CodedError, ok};
+use crate::tenancy::{ProjectScope, Repo};
+use crate::ticket::{Ticket, TicketComment};
+use crate::ticket_events::{commit, CommitError, TicketChange, MAX_ATTEMPTS};
+
+/// Longest accepted reaction, in characters (covers ZWJ sequences and `:shortcodes:`).
+const MAX_EMOJI_CHARS: usize = 32;
+
+#[derive(Debug, Deserialize)]
+pub struct CommentsQuery {
+    /// Only comments that have not been resolved
+    #[serde(default)]
+    pub unresolved: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReactionRequest {
+    pub emoji: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveCommentRequest {
+    pub resolved: bool,
+}
+
+async fn check_membership(auth: &AuthContext, team_id: &str, project_id: &str) -> Result<ProjectScope, HttpResponse> {
+    let scope = auth.project_scope(team_id, project_id).await?;
+    if !auth.is_project_member(project_id).await {
+        return Err(HttpResponse::Unauthorized().error("not_project_member"));
+    }
+    Ok(scope)
+}
+
+fn find_comment<'a>(ticket: &'a Ticket, comment_id: &str) -> Option<&'a TicketComment> {
+    ticket.comments.iter().flatten().find(|c| c.comment_id == comment_id)
+}
+
+/// Applies the change built from the current comment, retrying on conflicts, and
+/// returns the updated comment.
+async fn change_comment(
+    data: &AppState,
+    actor_id: &str,
+    scope: &ProjectScope,
+    ticket_id: &str,
+    comment_id: &str,
+    change: impl Fn(&TicketComment) -> Option<TicketChange>,
+) -> HttpResponse {
+    let tickets_coll = Repo::<Ticket>::new(&data.mongodb, scope);
+    let filter = doc! { "ticket_id": ticket_id };
+    for _ in 0..MAX_ATTEMPTS {
+        let ticket = match tickets_coll.find_one(filter.clone()).await {
+            Ok(Some(t)) => t,
+            Ok(None) => return HttpResponse::NotFound().error("ticket_not_found"),
+            Err(e) => {
+                error!("Error fetching ticket: {}", e);
+                return HttpResponse::InternalServerError().error("error_fetching_ticket");
+            }
+        };
+        let comment = match find_comment(&ticket, comment_id) {
+            Some(c) => c,
+            None => return HttpResponse::NotFound().body("Comment not found"),
+        };
+        let changes: Vec<TicketChange> = change(comment).into_iter().collect();
+        match commit(&data.mongodb, Some(&ticket), changes, actor_id).await {
+            Ok(Some(updated)) => match find_comment(&updated, comment_id) {
+                Some(c) => return ok(c),
+                None => return HttpResponse::NotFound().body("Comment not found"),
+            },
+            Ok(None) => return HttpResponse::NotFound().error("ticket_not_found"),
+            Err(CommitError::Conflict) => continue,
+            Err(CommitError::Invalid(msg)) => return HttpResponse::BadRequest().body(msg),
+            Err(CommitError::Db(e)) => {
+                error!("Error updating comment: {}", e);
+                return HttpResponse::InternalServerError().body("Error updating comment");
+            }
+        }
+    }
+    HttpResponse::Conflict().body("Ticket was modified concurrently, please retry")
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/comments?unresolved=true
+pub async fn list_comments(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>, // (team_id, project_id, ticket_id)
+    query: web::Query<CommentsQuery>,
+) -> impl Responder {
+    let (team_id, project_id, ticket_id) = path.into_inner();
+    let scope = match check_membership(&auth, &team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+
+    let tickets_coll = Repo::<Ticket>::new(&data.mongodb, &scope);
+    match tickets_coll.find_one(doc! { "ticket_id": &ticket_id }).await {
+        Ok(Some(ticket)) => {
+            let comments: Vec<TicketComment> = ticket
+                .comments
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|c| !query.unresolved || !c.resolved)
+                .collect();
+            ok(comments)
+        }
+        Ok(None) => HttpResponse::NotFound().error("ticket_not_found"),
+        Err(e) => {
+            error!("Error fetching ticket: {}", e);
+            HttpResponse::InternalServerError().error("error_fetching_ticket")
+        }
+    }
+}
+
+/// POST /teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/comments/{comment_id}/reactions
+/// Toggles the caller's reaction: adds it, or removes it when already present.
+pub async fn toggle_reaction(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String, String)>, // (team_id, project_id, ticket_id, comment_id)
+    payload: web::Json<ReactionRequest>,
+) -> impl Responder {
+    let (team_id, project_id, ticket_id, comment_id) = path.into_inner();
+    let scope = match check_membership(&auth, &team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let emoji = payload.emoji.trim().to_string();
+    if emoji.is_empty() || emoji.chars().count() > MAX_EMOJI_CHARS || emoji.chars().any(char::is_whitespace) {
+        return HttpResponse::BadRequest().body("Invalid emoji");
+    }
+    // Shortcode reactions must name a built-in or one of the team's custom emoji.
+    if let Some(code) = emoji.strip_prefix(':').and_then(|e| e.strip_suffix(':')).filter(|c| !c.is_empty()) {
+        if !is_builtin_shortcode(code) {
+            match team_has_emoji(&data.mongodb, &team_id, code).await {
+                Ok(true) => {}
+                Ok(false) => return HttpResponse::BadRequest().body(format!("Unknown emoji :{}:", code)),
+                Err(e) => {
+                    error!("Error checking custom emoji: {}", e);
+                    return HttpResponse::InternalServerError().body("Error checking emoji");
+                }
+            }
+        }
+    }
+
+    let user_id = auth.user_id().to_string();
+    change_comment(&data, &user_id, &scope, &ticket_id, &comment_id, |comment| {
+        let reacted = comment
+            .reactions
+            .iter()
+            .any(|r| r.emoji == emoji && r.user_ids.contains(&user_id));
+        Some(TicketChange::CommentReacted {
+            comment_id: comment_id.clone(),
+            emoji: emoji.clone(),
+            user_id: user_id.clone(),
+            added: !reacted,
+        })
+    })
+    .await
+}
+
+/// PUT /teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/comments/{comment_id}/resolved
+pub async fn set_comment_resolved(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String, String)>, // (team_id, project_id, ticket_id, comment_id)
+    payload: web::Json<ResolveCommentRequest>,
+) -> impl Responder {
+    let (team_id, project_id, ticket_id, comment_id) = path.into_inner();
+    let scope = match check_membership(&auth, &team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+
+    let user_id = auth.user_id().to_string();
+    change_comment(&data, &user_id, &scope, &ticket_id, &comment_id, |comment| {
+        // Already in the requested state: nothing to record.
+        (comment.resolved != payload.resolved).then(|| TicketChange::CommentResolved {
+            comment_id: comment_id.clone(),
+            resolved_by: payload.resolved.then(|| user_id.clone()),
+        })
+    })
+    .await
+}
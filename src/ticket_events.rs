@@ -0,0 +1,431 @@
+// src/ticket_events.rs
+//
+// Tickets are event sourced: every mutation appends to `ticket_events` and the
+// document in `tickets` is a cached projection of that log. The projection stores
+// the sequence number of the last event applied to it (`version`), and writes are
+// conditional on it, so two concurrent edits can no longer overwrite each other:
+// the loser gets `CommitError::Conflict` and retries against the fresh state.
+//
+// Tickets created before the log existed have version 0 and their history starts
+// with the first mutation after the upgrade.
+//
+// Events and projection are written in one transaction. Deployments without
+// transactions (a standalone server) get the same writes in sequence: the unique
+// `(ticket_id, seq)` index lets only one writer append on top of a version, and if
+// the projection has moved on anyway the appended events are taken back out.
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{self, doc, Bson};
+use mongodb::error::{ErrorKind, InsertManyError, WriteFailure, TRANSIENT_TRANSACTION_ERROR};
+use mongodb::ClientSession;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board_automation;
+use crate::board_live;
+use crate::chat_db::MongoDB;
+use crate::response::CodedError;
+use crate::sync::{record_change, Entity, Op, Scope};
+use crate::tenancy::{project_team_id, AllTenants, ProjectScope, Repo};
+use crate::ticket::{CommentReaction, Ticket, TicketComment};
+use crate::ticket_watchers;
+
+/// How many times a handler re-reads and re-applies its change after a conflict.
+pub const MAX_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TicketChange {
+    Created { ticket: Box<Ticket> },
+    FieldChanged { field: String, old: Bson, new: Bson },
+    Commented { comment: TicketComment },
+    /// `user_id` added (or, when `added` is false, removed) `emoji` on a comment
+    CommentReacted { comment_id: String, emoji: String, user_id: String, added: bool },
+    /// `resolved_by` is None when the comment is reopened
+    CommentResolved { comment_id: String, resolved_by: Option<String> },
+    /// A closed ticket was reopened; the status and resolution changes follow as
+    /// separate events
+    Reopened { reason: String },
+    /// `user_id` voted for the ticket, or withdrew their vote when `added` is false
+    Voted { user_id: String, added: bool },
+    Moved {
+        from_project_id: String,
+        from_board_id: String,
+        to_project_id: String,
+        to_board_id: String,
+    },
+    Deleted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TicketEvent {
+    pub ticket_id: String,
+    /// 1-based position in the ticket's log
+    pub seq: i64,
+    pub actor_id: String,
+    pub at: DateTime<Utc>,
+    pub change: TicketChange,
+}
+
+#[derive(Debug)]
+pub enum CommitError {
+    /// The ticket changed (or was created/deleted) since it was read.
+    Conflict,
+    /// The change cannot be applied to the current state.
+    Invalid(String),
+    Db(mongodb::error::Error),
+}
+
+impl From<mongodb::error::Error> for CommitError {
+    fn from(e: mongodb::error::Error) -> Self {
+        if is_conflict(&e) {
+            CommitError::Conflict
+        } else {
+            CommitError::Db(e)
+        }
+    }
+}
+
+fn is_conflict(e: &mongodb::error::Error) -> bool {
+    const DUPLICATE_KEY: i32 = 11000;
+    if e.contains_label(TRANSIENT_TRANSACTION_ERROR) {
+        return true;
+    }
+    match e.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(w)) => w.code == DUPLICATE_KEY,
+        ErrorKind::InsertMany(InsertManyError { write_errors: Some(errs), .. }) => {
+            errs.iter().any(|w| w.code == DUPLICATE_KEY)
+        }
+        _ => false,
+    }
+}
+
+/// Standalone servers refuse transactions, either in the driver or with this code.
+fn transactions_unsupported(e: &mongodb::error::Error) -> bool {
+    const ILLEGAL_OPERATION: i32 = 20;
+    match e.kind.as_ref() {
+        ErrorKind::Transaction { .. } => true,
+        ErrorKind::Command(c) => c.code == ILLEGAL_OPERATION,
+        _ => false,
+    }
+}
+
+impl TicketChange {
+    /// Builds a `FieldChanged` when `new` differs from the current value of `field`.
+    pub fn field<T: Serialize>(ticket: &Ticket, field: &str, new: &T) -> Option<TicketChange> {
+        let old = bson::to_document(ticket)
+            .ok()
+            .and_then(|d| d.get(field).cloned())
+            .unwrap_or(Bson::Null);
+        let new = bson::to_bson(new).unwrap_or(Bson::Null);
+        (old != new).then(|| TicketChange::FieldChanged { field: field.to_string(), old, new })
+    }
+
+    /// Applies the change to a projection; `None` is a ticket that does not exist.
+    pub fn apply(&self, ticket: Option<Ticket>) -> Result<Option<Ticket>, String> {
+        match (self, ticket) {
+            (TicketChange::Created { ticket }, None) => Ok(Some((**ticket).clone())),
+            (TicketChange::Created { .. }, Some(_)) => Err("Ticket already exists".to_string()),
+            (_, None) => Err("Ticket does not exist".to_string()),
+            (TicketChange::FieldChanged { field, new, .. }, Some(t)) => {
+                let mut d = bson::to_document(&t).map_err(|e| e.to_string())?;
+                d.insert(field.as_str(), new.clone());
+                bson::from_document(d).map_err(|e| format!("Invalid value for {}: {}", field, e)).map(Some)
+            }
+            (TicketChange::Commented { comment }, Some(mut t)) => {
+                t.comments.get_or_insert_with(Vec::new).push(comment.clone());
+                Ok(Some(t))
+            }
+            (TicketChange::CommentReacted { comment_id, emoji, user_id, added }, Some(mut t)) => {
+                let comment = find_comment(&mut t, comment_id)?;
+                let pos = comment.reactions.iter().position(|r| &r.emoji == emoji);
+                match (pos, added) {
+                    (Some(i), true) => {
+                        if !comment.reactions[i].user_ids.contains(user_id) {
+                            comment.reactions[i].user_ids.push(user_id.clone());
+                        }
+                    }
+                    (None, true) => comment.reactions.push(CommentReaction {
+                        emoji: emoji.clone(),
+                        user_ids: vec![user_id.clone()],
+                    }),
+                    (Some(i), false) => {
+                        comment.reactions[i].user_ids.retain(|u| u != user_id);
+                        if comment.reactions[i].user_ids.is_empty() {
+                            comment.reactions.remove(i);
+                        }
+                    }
+                    (None, false) => {}
+                }
+                Ok(Some(t))
+            }
+            (TicketChange::CommentResolved { comment_id, resolved_by }, Some(mut t)) => {
+                let comment = find_comment(&mut t, comment_id)?;
+                comment.resolved = resolved_by.is_some();
+                comment.resolved_by = resolved_by.clone();
+                Ok(Some(t))
+            }
+            (TicketChange::Reopened { .. }, Some(t)) => Ok(Some(t)),
+            (TicketChange::Voted { added, .. }, Some(mut t)) => {
+                t.vote_count = if *added { t.vote_count + 1 } else { (t.vote_count - 1).max(0) };
+                Ok(Some(t))
+            }
+            (TicketChange::Moved { to_project_id, to_board_id, .. }, Some(mut t)) => {
+                t.project_id = to_project_id.clone();
+                t.board_id = to_board_id.clone();
+                Ok(Some(t))
+            }
+            (TicketChange::Deleted, Some(_)) => Ok(None),
+        }
+    }
+}
+
+fn find_comment<'a>(ticket: &'a mut Ticket, comment_id: &str) -> Result<&'a mut TicketComment, String> {
+    ticket
+        .comments
+        .iter_mut()
+        .flatten()
+        .find(|c| !c.comment_id.is_empty() && c.comment_id == comment_id)
+        .ok_or_else(|| "Comment not found".to_string())
+}
+
+/// The log entry for a ticket inserted directly (imports, duplication); the ticket
+/// itself must be stored with `version: 1`.
+pub fn created_event(ticket: &Ticket, actor_id: &str) -> TicketEvent {
+    TicketEvent {
+        ticket_id: ticket.ticket_id.clone(),
+        seq: 1,
+        actor_id: actor_id.to_string(),
+        at: ticket.created_at,
+        change: TicketChange::Created { ticket: Box::new(ticket.clone()) },
+    }
+}
+
+/// Appends `changes` on top of `current` and writes the resulting projection, both in
+/// one transaction. Returns the new projection (`None` after a delete).
+pub async fn commit(
+    db: &MongoDB,
+    current: Option<&Ticket>,
+    changes: Vec<TicketChange>,
+    actor_id: &str,
+) -> Result<Option<Ticket>, CommitError> {
+    let ticket_id = match (current, changes.first()) {
+        (Some(t), _) => t.ticket_id.clone(),
+        (None, Some(TicketChange::Created { ticket })) => ticket.ticket_id.clone(),
+        _ => return Err(CommitError::Invalid("Ticket does not exist".to_string())),
+    };
+    let base_version = current.map_or(0, |t| t.version);
+
+    let now = Utc::now();
+    let mut state = current.cloned();
+    let mut events = Vec::with_capacity(changes.len());
+    for (i, change) in changes.into_iter().enumerate() {
+        state = change.apply(state).map_err(CommitError::Invalid)?;
+        events.push(TicketEvent {
+            ticket_id: ticket_id.clone(),
+            seq: base_version + i as i64 + 1,
+            actor_id: actor_id.to_string(),
+            at: now,
+            change,
+        });
+    }
+    if events.is_empty() {
+        return Ok(state);
+    }
+    if let Some(t) = state.as_mut() {
+        t.id = None;
+        t.version = base_version + events.len() as i64;
+    }
+
+    // The projection writer: its caller has already authorized the change.
+    let tickets = Repo::<Ticket>::all_tenants(db, AllTenants::maintenance());
+
+    let mut session = db.client.start_session().await?;
+    let mut result = write(db, &tickets, &events, current, state.as_ref(), &mut session, true).await;
+    if matches!(&result, Err(CommitError::Db(e)) if transactions_unsupported(e)) {
+        let _ = session.abort_transaction().await;
+        result = write(db, &tickets, &events, current, state.as_ref(), &mut session, false).await;
+    }
+    if let Err(e) = result {
+        let _ = session.abort_transaction().await;
+        return Err(e);
+    }
+
+    if let Some(before) = current {
+        if state.as_ref().is_none_or(|t| t.project_id != before.project_id) {
+            record_change(db, Entity::Ticket, &ticket_id, Op::Delete, Scope::Project(&before.project_id)).await;
+        }
+    }
+    if let Some(t) = &state {
+        record_change(db, Entity::Ticket, &ticket_id, Op::Upsert, Scope::Project(&t.project_id)).await;
+    }
+    board_live::publish(current, state.as_ref());
+    ticket_watchers::publish(current, state.as_ref(), actor_id);
+    board_automation::publish(current, state.as_ref(), actor_id);
+    Ok(state)
+}
+
+/// Appends `events` and writes the projection `state` over `current`, inside a
+/// transaction when `transaction` is set.
+async fn write(
+    db: &MongoDB,
+    tickets: &Repo<'_, Ticket>,
+    events: &[TicketEvent],
+    current: Option<&Ticket>,
+    state: Option<&Ticket>,
+    session: &mut ClientSession,
+    transaction: bool,
+) -> Result<(), CommitError> {
+    let ticket_id = &events[0].ticket_id;
+    // Legacy projections have no version field at all.
+    let expected = match current.map_or(0, |t| t.version) {
+        0 => doc! { "ticket_id": ticket_id, "version": { "$in": [0, Bson::Null] } },
+        version => doc! { "ticket_id": ticket_id, "version": version },
+    };
+    if transaction {
+        session.start_transaction().await?;
+    }
+    let log = db.db.collection::<TicketEvent>("ticket_events");
+    log.insert_many(events).session(&mut *session).await?;
+    let projected = match (current, state) {
+        (None, Some(t)) => tickets.insert_one_in(t, session).await.map(|_| 1),
+        (Some(_), Some(t)) => tickets.replace_one_in(expected, t, session).await.map(|r| r.matched_count),
+        (Some(_), None) => tickets.delete_one_in(expected, session).await.map(|r| r.deleted_count),
+        (None, None) => Ok(0),
+    };
+    let projected = match projected {
+        Ok(0) => Err(CommitError::Conflict),
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = projected {
+        if !transaction {
+            let seqs: Vec<i64> = events.iter().map(|e| e.seq).collect();
+            log.delete_many(doc! { "ticket_id": ticket_id, "seq": { "$in": seqs } }).session(&mut *session).await?;
+        }
+        return Err(e);
+    }
+    if transaction {
+        session.commit_transaction().await?;
+    }
+    Ok(())
+}
+
+/// The ticket's log in order, optionally only up to (and including) `until_seq`.
+pub async fn load_events(db: &MongoDB, ticket_id: &str, until_seq: Option<i64>) -> mongodb::error::Result<Vec<TicketEvent>> {
+    let mut filter = doc! { "ticket_id": ticket_id };
+    if let Some(seq) = until_seq {
+        filter.insert("seq", doc! { "$lte": seq });
+    }
+    let mut cursor = db
+        .db
+        .collection::<TicketEvent>("ticket_events")
+        .find(filter)
+        .sort(doc! { "seq": 1 })
+        .await?;
+    let mut events = Vec::new();
+    while let Some(event) = cursor.next().await {
+        events.push(event?);
+    }
+    Ok(events)
+}
+
+/// Fields changed by the events after `seq`; a move counts as a change of `board_id`.
+pub async fn changed_since(db: &MongoDB, ticket_id: &str, seq: i64) -> mongodb::error::Result<Vec<String>> {
+    let mut cursor = db
+        .db
+        .collection::<TicketEvent>("ticket_events")
+        .find(doc! { "ticket_id": ticket_id, "seq": { "$gt": seq } })
+        .await?;
+    let mut fields = Vec::new();
+    while let Some(event) = cursor.next().await {
+        match event?.change {
+            TicketChange::FieldChanged { field, .. } => fields.push(field),
+            TicketChange::Moved { .. } => fields.push("board_id".to_string()),
+            _ => {}
+        }
+    }
+    Ok(fields)
+}
+
+/// Rebuilds a projection from the log. Only possible for tickets whose log starts
+/// with `Created`.
+pub fn replay(events: &[TicketEvent]) -> Result<Option<Ticket>, String> {
+    if !matches!(events.first().map(|e| &e.change), Some(TicketChange::Created { .. })) {
+        return Err("Ticket history predates the event log".to_string());
+    }
+    let mut state = None;
+    for event in events {
+        state = event.change.apply(state)?;
+        if let Some(t) = state.as_mut() {
+            t.version = event.seq;
+        }
+    }
+    Ok(state)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Return the ticket as it was after this event instead of the event list.
+    pub as_of: Option<i64>,
+}
+
+/// GET /tickets/{ticket_id}/history[?as_of=seq]
+pub async fn get_ticket_history(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    ticket_id: web::Path<String>,
+    query: web::Query<HistoryQuery>,
+) -> impl Responder {
+    let mut events = match load_events(&data.mongodb, &ticket_id, None).await {
+        Ok(events) => events,
+        Err(e) => {
+            error!("Error fetching ticket history: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching ticket history");
+        }
+    };
+
+    // Authorize against the project the ticket lives in now, or lived in last when it
+    // was deleted, even for an earlier point of its history.
+    let project_id = match ProjectScope::of_ticket(&data.mongodb, &ticket_id).await {
+        Ok(Some(scope)) => scope.project_id().to_string(),
+        Ok(None) => match events.iter().rev().find_map(|e| match &e.change {
+            TicketChange::Created { ticket } => Some(ticket.project_id.clone()),
+            TicketChange::Moved { to_project_id, .. } => Some(to_project_id.clone()),
+            _ => None,
+        }) {
+            Some(p) => p,
+            None => return HttpResponse::NotFound().error("ticket_not_found"),
+        },
+        Err(e) => {
+            error!("Error fetching ticket {}: {}", ticket_id, e);
+            return HttpResponse::InternalServerError().body("Error fetching ticket history");
+        }
+    };
+    let team_id = match project_team_id(&data.mongodb, &project_id).await {
+        Some(t) => t,
+        None => return HttpResponse::NotFound().error("project_not_found"),
+    };
+    if !auth.is_team_member(&team_id).await {
+        return HttpResponse::Unauthorized().error("not_team_member");
+    }
+    if !auth.is_project_member(&project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
+
+    if let Some(seq) = query.as_of {
+        events.retain(|e| e.seq <= seq);
+    }
+    match query.as_of {
+        Some(_) => match replay(&events) {
+            Ok(Some(ticket)) => HttpResponse::Ok().json(ticket),
+            Ok(None) => HttpResponse::Gone().body("Ticket was deleted at that point"),
+            Err(msg) => HttpResponse::Conflict().body(msg),
+        },
+        None => HttpResponse::Ok().json(events),
+    }
+}
@@ -0,0 +1,83 @@
+// src/ticket_merge.rs
+//
+// Merge rules for ticket updates based on a stale version. A client that sends the
+// `version` it read no longer gets a 409 for any intervening change: the update is
+// rebased onto the current ticket unless it touches what the other writer changed.
+//
+// - Fields nobody else changed since `version` are applied.
+// - A field changed concurrently to the value this update sets is already there.
+// - `rank` orders tickets within a column, so a concurrent reorder is not a
+//   conflict: the later position wins. It is one when the ticket's status changed
+//   concurrently, as the rank was computed for a column the ticket has left.
+// - `status` and every other field changed concurrently to another value conflict:
+//   the first writer wins.
+// - A ticket moved to another board conflicts with any change.
+//
+// On a conflict the update is rejected with a `TicketConflict` body, and the same
+// report goes to the board's WebSocket room (see board_live.rs) so the losing client
+// can reconcile from the ticket it contains.
+
+use std::collections::HashSet;
+
+use actix_web::HttpResponse;
+use serde::Serialize;
+
+use crate::board_live;
+use crate::chat_db::MongoDB;
+use crate::ticket::Ticket;
+use crate::ticket_events::{changed_since, TicketChange};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TicketConflict {
+    pub ticket_id: String,
+    /// Version the update was based on
+    pub based_on: i64,
+    /// Current version, to base a retry on
+    pub version: i64,
+    /// Fields changed concurrently that the update also changes
+    pub fields: Vec<String>,
+    /// Current state of the ticket
+    pub ticket: Ticket,
+}
+
+/// Fields of `changes` that conflict with what was committed after `based_on`.
+/// `requested` are the fields the client asked to change, as opposed to fields the
+/// handler derives from them.
+pub async fn conflicts(
+    db: &MongoDB,
+    ticket: &Ticket,
+    based_on: i64,
+    changes: &[TicketChange],
+    requested: &[&str],
+) -> mongodb::error::Result<Vec<String>> {
+    let concurrent: HashSet<String> = changed_since(db, &ticket.ticket_id, based_on).await?.into_iter().collect();
+    if concurrent.is_empty() {
+        return Ok(Vec::new());
+    }
+    if concurrent.contains("board_id") && !changes.is_empty() {
+        return Ok(vec!["board_id".to_string()]);
+    }
+    let status_moved = concurrent.contains("status");
+    Ok(changes
+        .iter()
+        .filter_map(|c| match c {
+            TicketChange::FieldChanged { field, .. } if requested.contains(&field.as_str()) => Some(field),
+            _ => None,
+        })
+        .filter(|field| concurrent.contains(*field) && (field.as_str() != "rank" || status_moved))
+        .cloned()
+        .collect())
+}
+
+/// Rejects the update and tells the board's room about it.
+pub fn reject(ticket: &Ticket, based_on: i64, fields: Vec<String>, user_id: &str) -> HttpResponse {
+    let conflict = TicketConflict {
+        ticket_id: ticket.ticket_id.clone(),
+        based_on,
+        version: ticket.version,
+        fields,
+        ticket: ticket.clone(),
+    };
+    board_live::conflict(&ticket.board_id, user_id, &conflict);
+    HttpResponse::Conflict().json(conflict)
+}
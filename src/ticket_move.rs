@@ -0,0 +1,186 @@
+// src/ticket_move.rs
+//
+// Moving a ticket to another project/board, possibly in another team. The ticket
+// keeps its id, comments and creation date; the move is appended to the ticket's
+// event log and also recorded in `ticket_moves`, which serves as the redirect from
+// the old project URL.
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+
+use crate::activity::{record_activity, ActivityEvent};
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::board::Board;
+use crate::chat_db::MongoDB;
+use crate::response::CodedError;
+use crate::tenancy::{project_team_id, Repo};
+use crate::ticket::Ticket;
+use crate::ticket_events::{commit, CommitError, TicketChange};
+use crate::ticket_references::validate_references;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TicketMove {
+    pub ticket_id: String,
+    pub from_team_id: String,
+    pub from_project_id: String,
+    pub from_board_id: String,
+    pub to_team_id: String,
+    pub to_project_id: String,
+    pub to_board_id: String,
+    pub moved_by: String,
+    pub moved_at: DateTime<Utc>,
+    /// Assignee that was dropped because they are not in the target team
+    pub dropped_assignee: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveTicketRequest {
+    pub project_id: String,
+    pub board_id: String,
+}
+
+/// Latest move of `ticket_id` out of `project_id`, used to redirect stale links.
+pub async fn find_redirect(db: &MongoDB, project_id: &str, ticket_id: &str) -> Option<TicketMove> {
+    db.db
+        .collection::<TicketMove>("ticket_moves")
+        .find_one(doc! { "ticket_id": ticket_id, "from_project_id": project_id })
+        .sort(doc! { "moved_at": -1 })
+        .await
+        .ok()
+        .flatten()
+}
+
+/// POST /tickets/{ticket_id}/move
+pub async fn move_ticket(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    ticket_id: web::Path<String>,
+    payload: web::Json<MoveTicketRequest>,
+) -> impl Responder {
+    let ticket_id = ticket_id.into_inner();
+    let current_user = auth.user_id().to_string();
+    let db = &data.mongodb;
+
+    let from_scope = match auth.ticket_scope(&ticket_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let ticket = match Repo::<Ticket>::new(db, &from_scope).find_one(doc! { "ticket_id": &ticket_id }).await {
+        Ok(Some(t)) => t,
+        Ok(None) => return HttpResponse::NotFound().error("ticket_not_found"),
+        Err(e) => {
+            error!("Error fetching ticket: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_ticket");
+        }
+    };
+    if ticket.project_id == payload.project_id && ticket.board_id == payload.board_id {
+        return HttpResponse::BadRequest().body("Ticket is already on that board");
+    }
+
+    // 1) Caller must be a member of both the source and the target project and team
+    let from_team_id = from_scope.team_id().to_string();
+    let to_team_id = match project_team_id(db, &payload.project_id).await {
+        Some(t) => t,
+        None => return HttpResponse::NotFound().body("Target project not found"),
+    };
+    let to_scope = match auth.project_scope(&to_team_id, &payload.project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    for project in [&ticket.project_id, &payload.project_id] {
+        if !auth.is_project_member(project).await {
+            return HttpResponse::Unauthorized().error("not_project_member");
+        }
+    }
+    let board_exists = Repo::<Board>::new(db, &to_scope)
+        .find_one(doc! { "board_id": &payload.board_id })
+        .await
+        .ok()
+        .flatten()
+        .is_some();
+    if !board_exists {
+        return HttpResponse::NotFound().body("Target board not found");
+    }
+
+    // 2) Remap what does not survive a team change
+    let mut changes = vec![TicketChange::Moved {
+        from_project_id: ticket.project_id.clone(),
+        from_board_id: ticket.board_id.clone(),
+        to_project_id: payload.project_id.clone(),
+        to_board_id: payload.board_id.clone(),
+    }];
+    let mut dropped_assignee = None;
+    if from_team_id != to_team_id {
+        if let Some(assignee) = &ticket.assignee {
+            if !db.check_user_team(assignee, &to_team_id).await.unwrap_or(false) {
+                dropped_assignee = Some(assignee.clone());
+                changes.extend(TicketChange::field(&ticket, "assignee", &None::<String>));
+            }
+        }
+        // Links to docs/chats/tickets of the old team would now point across teams.
+        let mut kept = Vec::new();
+        for r in &ticket.references {
+            if validate_references(db, &to_team_id, &current_user, std::slice::from_ref(r)).await.is_ok() {
+                kept.push(r.clone());
+            }
+        }
+        changes.extend(TicketChange::field(&ticket, "references", &kept));
+    }
+
+    let record = TicketMove {
+        ticket_id: ticket_id.clone(),
+        from_team_id: from_team_id.clone(),
+        from_project_id: ticket.project_id.clone(),
+        from_board_id: ticket.board_id.clone(),
+        to_team_id: to_team_id.clone(),
+        to_project_id: payload.project_id.clone(),
+        to_board_id: payload.board_id.clone(),
+        moved_by: current_user.clone(),
+        moved_at: Utc::now(),
+        dropped_assignee,
+    };
+
+    match commit(db, Some(&ticket), changes, &current_user).await {
+        Ok(_) => {}
+        Err(CommitError::Conflict) => return HttpResponse::Conflict().body("Ticket was modified concurrently"),
+        Err(CommitError::Invalid(msg)) => return HttpResponse::BadRequest().body(msg),
+        Err(CommitError::Db(e)) => {
+            error!("Error moving ticket: {}", e);
+            return HttpResponse::InternalServerError().body("Error moving ticket");
+        }
+    }
+    if let Err(e) = db.db.collection::<TicketMove>("ticket_moves").insert_one(&record).await {
+        error!("Error recording ticket move: {}", e);
+    }
+
+    info!("Ticket {} moved from {} to {}", ticket_id, ticket.project_id, payload.project_id);
+    let same_project = payload.project_id == ticket.project_id;
+    record_activity(db, ActivityEvent::new(
+        &from_team_id,
+        Some(&ticket.project_id),
+        &current_user,
+        "ticket_moved",
+        &ticket_id,
+        format!(
+            "moved ticket \"{}\" to another {}",
+            ticket.title,
+            if same_project { "board" } else { "project" }
+        ),
+    )).await;
+    if !same_project {
+        record_activity(db, ActivityEvent::new(
+            &to_team_id,
+            Some(&payload.project_id),
+            &current_user,
+            "ticket_moved",
+            &ticket_id,
+            format!("moved ticket \"{}\" into this project", ticket.title),
+        )).await;
+    }
+
+    HttpResponse::Ok().json(record)
+}
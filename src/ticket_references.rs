@@ -0,0 +1,150 @@
+// src/ticket_references.rs
+//
+// Typed links inside ticket text: `doc:{id}`, `chat:{id}` and `ticket:{key}`.
+
+use std::sync::OnceLock;
+
+use actix_web::{web, HttpResponse, Responder};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{doc, Document};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::response::CodedError;
+use crate::tenancy::{ProjectsScope, Repo};
+use crate::ticket::Ticket;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TicketReference {
+    /// "doc", "chat" or "ticket"
+    pub kind: String,
+    pub id: String,
+}
+
+/// Extract every distinct typed reference from the given texts, in order of appearance.
+pub fn parse_references<'a>(texts: impl IntoIterator<Item = &'a str>) -> Vec<TicketReference> {
+    static REFERENCE: OnceLock<Regex> = OnceLock::new();
+    let re = REFERENCE.get_or_init(|| {
+        Regex::new(r"\b(doc|chat|ticket):([A-Za-z0-9][A-Za-z0-9_-]*)").expect("valid reference regex")
+    });
+    let mut refs: Vec<TicketReference> = Vec::new();
+    for text in texts {
+        for cap in re.captures_iter(text) {
+            let reference = TicketReference { kind: cap[1].to_string(), id: cap[2].to_string() };
+            if !refs.contains(&reference) {
+                refs.push(reference);
+            }
+        }
+    }
+    refs
+}
+
+/// Check that every reference points at something inside `team_id`.
+/// Returns the offending reference as an error message.
+pub async fn validate_references(
+    db: &MongoDB,
+    team_id: &str,
+    user_id: &str,
+    refs: &[TicketReference],
+) -> Result<(), String> {
+    let team_projects = if refs.iter().any(|r| r.kind == "ticket") {
+        ProjectsScope::of_team(db, team_id).await.map_err(|e| e.to_string())?
+    } else {
+        ProjectsScope::default()
+    };
+    let team_tickets = Repo::<Ticket>::across(db, &team_projects);
+    for r in refs {
+        let ok = match r.kind.as_str() {
+            "doc" => db
+                .db
+                .collection::<Document>("knowledge_base")
+                .find_one(doc! { "_id": &r.id, "team_id": team_id })
+                .await
+                .map_err(|e| e.to_string())?
+                .is_some(),
+            // Chats created before team scoping have no team_id; fall back to requiring
+            // that the caller participates in them.
+            "chat" => db
+                .db
+                .collection::<Document>("chats")
+                .find_one(doc! {
+                    "_id": &r.id,
+                    "$or": [
+                        { "team_id": team_id },
+                        { "team_id": { "$exists": false }, "participants": user_id },
+                    ]
+                })
+                .await
+                .map_err(|e| e.to_string())?
+                .is_some(),
+            "ticket" => team_tickets.count_documents(doc! { "ticket_id": &r.id }).await.map_err(|e| e.to_string())? > 0,
+            _ => false,
+        };
+        if !ok {
+            return Err(format!("Referenced {}:{} does not exist in this team", r.kind, r.id));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct TicketBacklink {
+    pub ticket_id: String,
+    pub project_id: String,
+    pub title: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TicketReferencesResponse {
+    pub outbound: Vec<TicketReference>,
+    pub backlinks: Vec<TicketBacklink>,
+}
+
+/// GET /tickets/{ticket_id}/references
+pub async fn get_ticket_references(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    ticket_id: web::Path<String>,
+) -> impl Responder {
+    let scope = match auth.ticket_scope(&ticket_id).await {
+        Ok(scope) => scope,
+        Err(resp) => return resp,
+    };
+    let ticket = match Repo::<Ticket>::new(&data.mongodb, &scope).find_one(doc! { "ticket_id": &*ticket_id }).await {
+        Ok(Some(t)) => t,
+        Ok(None) => return HttpResponse::NotFound().error("ticket_not_found"),
+        Err(e) => {
+            error!("Error fetching ticket: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_ticket");
+        }
+    };
+
+    // Backlinks from the ticket's own team only.
+    let team_tickets = match ProjectsScope::of_team(&data.mongodb, scope.team_id()).await {
+        Ok(team_projects) => team_projects,
+        Err(e) => {
+            error!("Error fetching projects: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching backlinks");
+        }
+    };
+    let filter = doc! { "references": { "kind": "ticket", "id": &*ticket_id } };
+    let mut cursor = match Repo::<Ticket>::across(&data.mongodb, &team_tickets).find(filter).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching backlinks: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching backlinks");
+        }
+    };
+    let mut backlinks = Vec::new();
+    while let Some(res) = cursor.next().await {
+        if let Ok(t) = res {
+            backlinks.push(TicketBacklink { ticket_id: t.ticket_id, project_id: t.project_id, title: t.title });
+        }
+    }
+
+    HttpResponse::Ok().json(TicketReferencesResponse { outbound: ticket.references, backlinks })
+}
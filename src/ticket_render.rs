@@ -0,0 +1,304 @@
+// src/ticket_render.rs
+//
+// Standalone rendering of one ticket for printing or attaching to an email: its
+// fields, description, comments, attachment list and history, as self-contained
+// HTML or as a PDF written by the report renderer in dashboard_report.rs. Votes and
+// comment reactions are left out of the history.
+
+use std::collections::HashMap;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::error;
+use mongodb::bson::{doc, Bson};
+use serde::Deserialize;
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::dashboard_report::{html_escape, write_pdf, PDF_MARGIN};
+use crate::response::CodedError;
+use crate::tenancy::Repo;
+use crate::out_of_office::usernames;
+use crate::ticket::Ticket;
+use crate::ticket_events::{load_events, TicketChange, TicketEvent};
+
+/// Characters per PDF line at 11pt Helvetica within the page margins.
+const PDF_LINE_CHARS: usize = 90;
+/// Field values longer than this are not repeated in the history.
+const MAX_HISTORY_VALUE: usize = 80;
+
+#[derive(Debug, Deserialize)]
+pub struct RenderQuery {
+    /// "pdf" or "html" (default)
+    pub format: Option<String>,
+}
+
+/// What the rendering shows, with user ids resolved to names.
+struct Rendered {
+    title: String,
+    fields: Vec<(&'static str, String)>,
+    description: Option<String>,
+    /// (author, time, text)
+    comments: Vec<(String, DateTime<Utc>, String)>,
+    attachments: Vec<String>,
+    /// (actor, time, what happened)
+    history: Vec<(String, DateTime<Utc>, String)>,
+}
+
+fn fmt_time(t: &DateTime<Utc>) -> String {
+    t.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+fn fmt_value(v: &Bson) -> String {
+    match v {
+        Bson::Null => "none".to_string(),
+        Bson::String(s) if s.is_empty() => "none".to_string(),
+        Bson::String(s) => s.clone(),
+        Bson::Array(items) => items.iter().map(fmt_value).collect::<Vec<_>>().join(", "),
+        other => other.to_string(),
+    }
+}
+
+/// One history line, or None for events not worth printing.
+fn describe(change: &TicketChange) -> Option<String> {
+    match change {
+        TicketChange::Created { .. } => Some("created the ticket".to_string()),
+        TicketChange::FieldChanged { field, new, .. } => {
+            let value = fmt_value(new);
+            if value.chars().count() > MAX_HISTORY_VALUE || field == "references" {
+                Some(format!("changed {}", field))
+            } else {
+                Some(format!("set {} to {}", field, value))
+            }
+        }
+        TicketChange::Commented { .. } => Some("commented".to_string()),
+        TicketChange::CommentResolved { resolved_by: Some(_), .. } => Some("resolved a comment".to_string()),
+        TicketChange::CommentResolved { resolved_by: None, .. } => Some("reopened a comment".to_string()),
+        TicketChange::Reopened { reason } => Some(format!("reopened the ticket: {}", reason)),
+        TicketChange::Moved { from_project_id, to_project_id, .. } if from_project_id != to_project_id => {
+            Some("moved the ticket to another project".to_string())
+        }
+        TicketChange::Moved { .. } => Some("moved the ticket to another board".to_string()),
+        TicketChange::Deleted => Some("deleted the ticket".to_string()),
+        TicketChange::CommentReacted { .. } | TicketChange::Voted { .. } => None,
+    }
+}
+
+fn build(ticket: &Ticket, events: &[TicketEvent], names: &HashMap<String, String>) -> Rendered {
+    let name = |id: &str| names.get(id).cloned().unwrap_or_else(|| id.to_string());
+    let mut fields = vec![("Status", ticket.status.clone())];
+    let optional = [
+        ("Type", ticket.ticket_type.clone()),
+        ("Priority", ticket.priority.clone()),
+        ("Assignee", ticket.assignee.as_deref().filter(|a| !a.is_empty()).map(name)),
+        ("Reporter", Some(ticket.reporter.as_str()).filter(|r| !r.is_empty()).map(name)),
+        ("Requester", ticket.requester_email.clone()),
+        ("Due", ticket.due_date.as_ref().map(fmt_time)),
+        ("Sprint", ticket.sprint.map(|s| s.to_string())),
+        ("Estimate (hours)", ticket.estimate_hours.map(|h| h.to_string())),
+        ("Labels", ticket.labels.as_ref().filter(|l| !l.is_empty()).map(|l| l.join(", "))),
+        ("Blocked by", Some(ticket.blocked_by.join(", ")).filter(|b| !b.is_empty())),
+        ("Resolution", ticket.resolution.clone()),
+        ("Created", Some(fmt_time(&ticket.created_at))),
+    ];
+    fields.extend(optional.into_iter().filter_map(|(label, value)| value.map(|v| (label, v))));
+    Rendered {
+        title: ticket.title.clone(),
+        fields,
+        description: ticket.description.clone().filter(|d| !d.trim().is_empty()),
+        comments: ticket
+            .comments
+            .iter()
+            .flatten()
+            .map(|c| (name(&c.author_id), c.timestamp, c.content.clone()))
+            .collect(),
+        attachments: ticket.attachments.clone().unwrap_or_default(),
+        history: events
+            .iter()
+            .filter_map(|e| describe(&e.change).map(|what| (name(&e.actor_id), e.at, what)))
+            .collect(),
+    }
+}
+
+fn render_html(ticket_id: &str, r: &Rendered) -> String {
+    let mut body = format!("<h1>{}</h1>\n<p class=\"id\">{}</p>\n<table>\n", html_escape(&r.title), html_escape(ticket_id));
+    for (label, value) in &r.fields {
+        body.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", label, html_escape(value)));
+    }
+    body.push_str("</table>\n");
+    if let Some(description) = &r.description {
+        body.push_str(&format!("<h2>Description</h2>\n<div class=\"text\">{}</div>\n", html_escape(description)));
+    }
+    if !r.comments.is_empty() {
+        body.push_str("<h2>Comments</h2>\n");
+        for (author, at, text) in &r.comments {
+            body.push_str(&format!(
+                "<div class=\"comment\"><p class=\"meta\">{} &middot; {}</p><div class=\"text\">{}</div></div>\n",
+                html_escape(author),
+                fmt_time(at),
+                html_escape(text)
+            ));
+        }
+    }
+    if !r.attachments.is_empty() {
+        body.push_str("<h2>Attachments</h2>\n<ul>\n");
+        for a in &r.attachments {
+            body.push_str(&format!("<li>{}</li>\n", html_escape(a)));
+        }
+        body.push_str("</ul>\n");
+    }
+    if !r.history.is_empty() {
+        body.push_str("<h2>History</h2>\n<table>\n");
+        for (actor, at, what) in &r.history {
+            body.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                fmt_time(at),
+                html_escape(actor),
+                html_escape(what)
+            ));
+        }
+        body.push_str("</table>\n");
+    }
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{t}</title>\
+         <style>body{{font-family:sans-serif;margin:2em;max-width:50em}}table{{border-collapse:collapse;margin-bottom:1em}}\
+         th,td{{border:1px solid #ccc;padding:4px 12px;text-align:left;vertical-align:top}}\
+         .id,.meta{{color:#666}}.text{{white-space:pre-wrap}}.comment{{margin-bottom:1em}}\
+         @media print{{body{{margin:0}}}}</style></head>\n<body>\n{body}</body></html>\n",
+        t = html_escape(&r.title),
+        body = body
+    )
+}
+
+/// Splits text into lines of at most `width` characters, breaking at spaces where
+/// possible and keeping the text's own line breaks.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut out = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word = word.to_string();
+            while word.chars().count() > width {
+                let head: String = word.chars().take(width).collect();
+                word = word.chars().skip(width).collect();
+                if !line.is_empty() {
+                    out.push(std::mem::take(&mut line));
+                }
+                out.push(head);
+            }
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                out.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        out.push(line);
+    }
+    out
+}
+
+fn render_pdf(ticket_id: &str, r: &Rendered) -> Vec<u8> {
+    const INDENT: i32 = PDF_MARGIN + 16;
+    let mut lines: Vec<(i32, i32, String)> = Vec::new();
+    let text = |lines: &mut Vec<(i32, i32, String)>, x: i32, s: &str| {
+        lines.extend(wrap(s, PDF_LINE_CHARS).into_iter().map(|l| (11, x, l)));
+    };
+    let heading = |lines: &mut Vec<(i32, i32, String)>, s: &str| {
+        lines.push((11, PDF_MARGIN, String::new()));
+        lines.push((13, PDF_MARGIN, s.to_string()));
+    };
+
+    lines.push((18, PDF_MARGIN, r.title.clone()));
+    lines.push((11, PDF_MARGIN, ticket_id.to_string()));
+    lines.push((11, PDF_MARGIN, String::new()));
+    for (label, value) in &r.fields {
+        text(&mut lines, PDF_MARGIN, &format!("{}: {}", label, value));
+    }
+    if let Some(description) = &r.description {
+        heading(&mut lines, "Description");
+        text(&mut lines, PDF_MARGIN, description);
+    }
+    if !r.comments.is_empty() {
+        heading(&mut lines, "Comments");
+        for (author, at, body) in &r.comments {
+            lines.push((11, PDF_MARGIN, format!("{} - {}", author, fmt_time(at))));
+            text(&mut lines, INDENT, body);
+        }
+    }
+    if !r.attachments.is_empty() {
+        heading(&mut lines, "Attachments");
+        for a in &r.attachments {
+            text(&mut lines, INDENT, a);
+        }
+    }
+    if !r.history.is_empty() {
+        heading(&mut lines, "History");
+        for (actor, at, what) in &r.history {
+            text(&mut lines, PDF_MARGIN, &format!("{}  {}  {}", fmt_time(at), actor, what));
+        }
+    }
+    write_pdf(&lines)
+}
+
+/// GET /tickets/{ticket_id}/render?format=html|pdf
+pub async fn render_ticket(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    ticket_id: web::Path<String>,
+    query: web::Query<RenderQuery>,
+) -> impl Responder {
+    let format = query.format.as_deref().unwrap_or("html");
+    if format != "html" && format != "pdf" {
+        return HttpResponse::BadRequest().body("format must be 'pdf' or 'html'");
+    }
+    let scope = match auth.ticket_scope(&ticket_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let ticket = match Repo::<Ticket>::new(&data.mongodb, &scope).find_one(doc! { "ticket_id": &*ticket_id }).await {
+        Ok(Some(t)) => t,
+        Ok(None) => return HttpResponse::NotFound().error("ticket_not_found"),
+        Err(e) => {
+            error!("Error fetching ticket: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_ticket");
+        }
+    };
+    if !auth.is_project_member(&ticket.project_id).await {
+        return HttpResponse::Unauthorized().error("not_project_member");
+    }
+
+    let events = match load_events(&data.mongodb, &ticket.ticket_id, None).await {
+        Ok(events) => events,
+        Err(e) => {
+            error!("Error fetching ticket history: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching ticket history");
+        }
+    };
+    let mut user_ids: Vec<String> = events.iter().map(|e| e.actor_id.clone()).collect();
+    user_ids.extend(ticket.comments.iter().flatten().map(|c| c.author_id.clone()));
+    user_ids.extend(ticket.assignee.clone());
+    user_ids.push(ticket.reporter.clone());
+    user_ids.sort();
+    user_ids.dedup();
+    let names: HashMap<String, String> = match usernames(&data.mongodb, &user_ids).await {
+        Ok(n) => n.into_iter().collect(),
+        Err(e) => {
+            error!("Error fetching usernames: {}", e);
+            HashMap::new()
+        }
+    };
+
+    let rendered = build(&ticket, &events, &names);
+    if format == "pdf" {
+        HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header(("Content-Disposition", format!("inline; filename=\"ticket-{}.pdf\"", ticket.ticket_id)))
+            .body(render_pdf(&ticket.ticket_id, &rendered))
+    } else {
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(render_html(&ticket.ticket_id, &rendered))
+    }
+}
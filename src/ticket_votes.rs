@@ -0,0 +1,227 @@
+// src/ticket_votes.rs
+//
+// Votes on tickets, for feature-request style backlogs. Each vote is a row in
+// `ticket_votes` (unique per ticket and user); the ticket itself only carries the
+// count, kept in step through the event log so it survives concurrent edits.
+
+use std::collections::HashSet;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::doc;
+use mongodb::error::{ErrorKind, WriteFailure};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::response::{CodedError, ok};
+use crate::tenancy::{ProjectScope, ProjectsScope, Repo};
+use crate::ticket::Ticket;
+use crate::ticket_events::{commit, CommitError, TicketChange, MAX_ATTEMPTS};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TicketVote {
+    pub ticket_id: String,
+    pub user_id: String,
+    pub voted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VoteState {
+    pub ticket_id: String,
+    pub voted: bool,
+    pub vote_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VotedTicket {
+    pub voted_at: DateTime<Utc>,
+    pub ticket: Ticket,
+}
+
+fn votes(db: &MongoDB) -> mongodb::Collection<TicketVote> {
+    db.db.collection::<TicketVote>("ticket_votes")
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(&*e.kind, ErrorKind::Write(WriteFailure::WriteError(w)) if w.code == 11000)
+}
+
+/// Team and project members may vote.
+async fn check_access(auth: &AuthContext, team_id: &str, project_id: &str) -> Result<ProjectScope, HttpResponse> {
+    let scope = auth.project_scope(team_id, project_id).await?;
+    if !auth.is_project_member(project_id).await {
+        return Err(HttpResponse::Unauthorized().error("not_project_member"));
+    }
+    Ok(scope)
+}
+
+/// Apply a vote change to the ticket's count, re-reading on conflicts.
+async fn record_vote(
+    db: &MongoDB,
+    scope: &ProjectScope,
+    ticket_id: &str,
+    user_id: &str,
+    added: bool,
+) -> Result<Ticket, HttpResponse> {
+    let tickets_coll = Repo::<Ticket>::new(db, scope);
+    let filter = doc! { "ticket_id": ticket_id };
+    for _ in 0..MAX_ATTEMPTS {
+        let ticket = match tickets_coll.find_one(filter.clone()).await {
+            Ok(Some(t)) => t,
+            Ok(None) => return Err(HttpResponse::NotFound().error("ticket_not_found")),
+            Err(e) => {
+                error!("Error fetching ticket: {}", e);
+                return Err(HttpResponse::InternalServerError().error("error_fetching_ticket"));
+            }
+        };
+        let change = TicketChange::Voted { user_id: user_id.to_string(), added };
+        match commit(db, Some(&ticket), vec![change], user_id).await {
+            Ok(Some(t)) => return Ok(t),
+            Ok(None) => return Err(HttpResponse::NotFound().error("ticket_not_found")),
+            Err(CommitError::Conflict) => continue,
+            Err(CommitError::Invalid(msg)) => return Err(HttpResponse::BadRequest().body(msg)),
+            Err(CommitError::Db(e)) => {
+                error!("Error recording vote: {}", e);
+                return Err(HttpResponse::InternalServerError().body("Error recording vote"));
+            }
+        }
+    }
+    Err(HttpResponse::Conflict().body("Ticket was modified concurrently, please retry"))
+}
+
+/// POST /teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/votes
+pub async fn vote_ticket(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (team_id, project_id, ticket_id) = path.into_inner();
+    let scope = match check_access(&auth, &team_id, &project_id).await {
+        Ok(scope) => scope,
+        Err(resp) => return resp,
+    };
+    match Repo::<Ticket>::new(&data.mongodb, &scope).find_one(doc! { "ticket_id": &ticket_id }).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().error("ticket_not_found"),
+        Err(e) => {
+            error!("Error fetching ticket: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_ticket");
+        }
+    }
+
+    // The unique index makes this the one-vote-per-user check.
+    let vote = TicketVote { ticket_id: ticket_id.clone(), user_id: auth.user_id().to_string(), voted_at: Utc::now() };
+    if let Err(e) = votes(&data.mongodb).insert_one(&vote).await {
+        if is_duplicate_key(&e) {
+            return HttpResponse::Conflict().body("You have already voted for this ticket");
+        }
+        error!("Error recording vote: {}", e);
+        return HttpResponse::InternalServerError().body("Error recording vote");
+    }
+
+    match record_vote(&data.mongodb, &scope, &ticket_id, auth.user_id(), true).await {
+        Ok(ticket) => ok(VoteState { ticket_id, voted: true, vote_count: ticket.vote_count }),
+        Err(resp) => {
+            let _ = votes(&data.mongodb).delete_one(doc! { "ticket_id": &ticket_id, "user_id": auth.user_id() }).await;
+            resp
+        }
+    }
+}
+
+/// DELETE /teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/votes
+pub async fn unvote_ticket(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (team_id, project_id, ticket_id) = path.into_inner();
+    let scope = match check_access(&auth, &team_id, &project_id).await {
+        Ok(scope) => scope,
+        Err(resp) => return resp,
+    };
+
+    let filter = doc! { "ticket_id": &ticket_id, "user_id": auth.user_id() };
+    let removed = match votes(&data.mongodb).find_one_and_delete(filter).await {
+        Ok(Some(v)) => v,
+        Ok(None) => return HttpResponse::NotFound().body("You have not voted for this ticket"),
+        Err(e) => {
+            error!("Error removing vote: {}", e);
+            return HttpResponse::InternalServerError().body("Error removing vote");
+        }
+    };
+
+    match record_vote(&data.mongodb, &scope, &ticket_id, auth.user_id(), false).await {
+        Ok(ticket) => ok(VoteState { ticket_id, voted: false, vote_count: ticket.vote_count }),
+        Err(resp) => {
+            let _ = votes(&data.mongodb).insert_one(&removed).await;
+            resp
+        }
+    }
+}
+
+/// GET /tickets/voted
+/// Tickets the caller voted for, most recent vote first. Tickets in projects the
+/// caller has since left are omitted.
+pub async fn list_voted_tickets(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    let mut cursor = match votes(&data.mongodb).find(doc! { "user_id": auth.user_id() }).sort(doc! { "voted_at": -1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching votes: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching votes");
+        }
+    };
+    let mut voted = Vec::new();
+    while let Some(vote) = cursor.next().await {
+        match vote {
+            Ok(v) => voted.push(v),
+            Err(e) => {
+                error!("Error reading votes: {}", e);
+                return HttpResponse::InternalServerError().body("Error fetching votes");
+            }
+        }
+    }
+
+    let ids: Vec<&String> = voted.iter().map(|v| &v.ticket_id).collect();
+    let scope = match ProjectsScope::of_user(&data.mongodb, auth.user_id()).await {
+        Ok(scope) => scope,
+        Err(e) => {
+            error!("Error fetching projects: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_tickets");
+        }
+    };
+    let mut cursor = match Repo::<Ticket>::across(&data.mongodb, &scope).find(doc! { "ticket_id": { "$in": ids } }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching tickets: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_tickets");
+        }
+    };
+    let mut tickets = Vec::new();
+    let mut allowed_projects = HashSet::new();
+    while let Some(ticket) = cursor.next().await {
+        let ticket = match ticket {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Error reading tickets: {}", e);
+                return HttpResponse::InternalServerError().error("error_fetching_tickets");
+            }
+        };
+        if allowed_projects.contains(&ticket.project_id) || auth.is_project_member(&ticket.project_id).await {
+            allowed_projects.insert(ticket.project_id.clone());
+            tickets.push(ticket);
+        }
+    }
+
+    let result: Vec<VotedTicket> = voted
+        .into_iter()
+        .filter_map(|v| {
+            let pos = tickets.iter().position(|t| t.ticket_id == v.ticket_id)?;
+            Some(VotedTicket { voted_at: v.voted_at, ticket: tickets.swap_remove(pos) })
+        })
+        .collect();
+    ok(result)
+}
@@ -0,0 +1,263 @@
+// src/ticket_watchers.rs
+//
+// Watching tickets. A watcher is notified when the ticket changes, optionally only
+// for some fields ("status", "assignee", "comments", ...): after each update the
+// ticket before and after is diffed and every watcher whose filter overlaps the
+// changed fields gets one notification listing them. Nobody is notified of their
+// own changes. Notifications go out for every change committed through
+// ticket_events.rs, whoever made it; a watcher who has left the ticket's project is
+// not notified and their watch is dropped.
+
+use std::sync::OnceLock;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{self, doc};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::do_not_disturb::notify_user;
+use crate::response::{CodedError, ok};
+use crate::tenancy::{ProjectScope, Repo};
+use crate::ticket::Ticket;
+
+/// Ticket fields a watcher can filter on; "comments" means a comment was added.
+pub const WATCHABLE_FIELDS: [&str; 16] = [
+    "title",
+    "description",
+    "status",
+    "priority",
+    "assignee",
+    "due_date",
+    "ticket_type",
+    "sprint",
+    "labels",
+    "fix_version",
+    "estimate_hours",
+    "blocked_by",
+    "attachments",
+    "comments",
+    "resolution",
+    "resolved_at",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TicketWatch {
+    pub ticket_id: String,
+    pub project_id: String,
+    pub user_id: String,
+    /// Fields that trigger a notification; empty means any change
+    #[serde(default)]
+    pub fields: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WatchRequest {
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+static STATE: OnceLock<AppState> = OnceLock::new();
+
+/// Called once at startup; notifications use the app's database and chat server.
+pub fn init(data: AppState) {
+    let _ = STATE.set(data);
+}
+
+fn watches(db: &MongoDB) -> mongodb::Collection<TicketWatch> {
+    db.db.collection::<TicketWatch>("ticket_watchers")
+}
+
+/// Watchable fields that differ between the two versions of a ticket.
+fn changed_fields(before: &Ticket, after: &Ticket) -> Vec<&'static str> {
+    let (Ok(old), Ok(new)) = (bson::to_document(before), bson::to_document(after)) else {
+        return Vec::new();
+    };
+    WATCHABLE_FIELDS
+        .iter()
+        .copied()
+        .filter(|&field| match field {
+            "comments" => after.comments.as_ref().map_or(0, Vec::len) > before.comments.as_ref().map_or(0, Vec::len),
+            _ => old.get(field) != new.get(field),
+        })
+        .collect()
+}
+
+/// Notifies the watchers of a committed change by `actor`, in the background.
+pub(crate) fn publish(before: Option<&Ticket>, after: Option<&Ticket>, actor: &str) {
+    let (Some(data), Some(before), Some(after)) = (STATE.get(), before, after) else {
+        return;
+    };
+    let (before, after, actor) = (before.clone(), after.clone(), actor.to_string());
+    actix_web::rt::spawn(async move {
+        let changed = changed_fields(&before, &after);
+        if changed.is_empty() {
+            return;
+        }
+        let filter = doc! { "ticket_id": &after.ticket_id, "user_id": { "$ne": &actor } };
+        let mut cursor = match watches(&data.mongodb).find(filter).await {
+            Ok(c) => c,
+            Err(e) => {
+                error!("Error fetching watchers of ticket {}: {}", after.ticket_id, e);
+                return;
+            }
+        };
+        while let Some(watch) = cursor.next().await {
+            let watch = match watch {
+                Ok(w) => w,
+                Err(e) => {
+                    error!("Error reading ticket watcher: {}", e);
+                    continue;
+                }
+            };
+            let fields: Vec<&str> = changed
+                .iter()
+                .copied()
+                .filter(|f| watch.fields.is_empty() || watch.fields.iter().any(|w| w == f))
+                .collect();
+            if fields.is_empty() {
+                continue;
+            }
+            match data.mongodb.check_project_membership(&watch.user_id, &after.project_id).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    let filter = doc! { "ticket_id": &watch.ticket_id, "user_id": &watch.user_id };
+                    if let Err(e) = watches(&data.mongodb).delete_one(filter).await {
+                        error!("Error removing watch of former member {}: {}", watch.user_id, e);
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    error!("Error checking project membership of watcher {}: {}", watch.user_id, e);
+                    continue;
+                }
+            }
+            let payload = serde_json::json!({
+                "type": "ticket_changed",
+                "ticket_id": after.ticket_id,
+                "project_id": after.project_id,
+                "title": after.title,
+                "status": after.status,
+                "fields": fields,
+                "actor_id": actor,
+            });
+            notify_user(data, &watch.user_id, payload.to_string()).await;
+        }
+    });
+}
+
+/// Removes all watches of deleted tickets.
+pub async fn remove_watches(db: &MongoDB, ticket_ids: &[&String]) -> mongodb::error::Result<()> {
+    watches(db).delete_many(doc! { "ticket_id": { "$in": ticket_ids } }).await?;
+    Ok(())
+}
+
+async fn check_access(auth: &AuthContext, team_id: &str, project_id: &str) -> Result<ProjectScope, HttpResponse> {
+    let scope = auth.project_scope(team_id, project_id).await?;
+    if !auth.is_project_member(project_id).await {
+        return Err(HttpResponse::Unauthorized().error("not_project_member"));
+    }
+    Ok(scope)
+}
+
+/// PUT /teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/watch
+/// `{"fields": ["status", "comments"]}`; no fields watches every change. Calling it
+/// again replaces the filter.
+pub async fn watch_ticket(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    payload: web::Json<WatchRequest>,
+) -> impl Responder {
+    let (team_id, project_id, ticket_id) = path.into_inner();
+    let scope = match check_access(&auth, &team_id, &project_id).await {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let mut fields = payload.into_inner().fields;
+    if let Some(unknown) = fields.iter().find(|f| !WATCHABLE_FIELDS.contains(&f.as_str())) {
+        return HttpResponse::BadRequest().body(format!("Cannot watch \"{}\"; fields are {}", unknown, WATCHABLE_FIELDS.join(", ")));
+    }
+    fields.sort();
+    fields.dedup();
+
+    let tickets_coll = Repo::<Ticket>::new(&data.mongodb, &scope);
+    match tickets_coll.find_one(doc! { "ticket_id": &ticket_id }).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().error("ticket_not_found"),
+        Err(e) => {
+            error!("Error fetching ticket: {}", e);
+            return HttpResponse::InternalServerError().error("error_fetching_ticket");
+        }
+    }
+
+    let watch = TicketWatch {
+        ticket_id,
+        project_id,
+        user_id: auth.user_id().to_string(),
+        fields,
+        created_at: Utc::now(),
+    };
+    let filter = doc! { "ticket_id": &watch.ticket_id, "user_id": &watch.user_id };
+    match watches(&data.mongodb).replace_one(filter, &watch).upsert(true).await {
+        Ok(_) => ok(watch),
+        Err(e) => {
+            error!("Error saving ticket watch: {}", e);
+            HttpResponse::InternalServerError().body("Error watching ticket")
+        }
+    }
+}
+
+/// DELETE /teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/watch
+pub async fn unwatch_ticket(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (team_id, project_id, ticket_id) = path.into_inner();
+    if let Err(resp) = auth.project_scope(&team_id, &project_id).await {
+        return resp;
+    }
+    let filter = doc! { "ticket_id": &ticket_id, "project_id": &project_id, "user_id": auth.user_id() };
+    match watches(&data.mongodb).delete_one(filter).await {
+        Ok(res) if res.deleted_count == 0 => HttpResponse::NotFound().body("You are not watching this ticket"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Error removing ticket watch: {}", e);
+            HttpResponse::InternalServerError().body("Error unwatching ticket")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/tickets/{ticket_id}/watchers
+pub async fn list_watchers(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (team_id, project_id, ticket_id) = path.into_inner();
+    if let Err(resp) = check_access(&auth, &team_id, &project_id).await {
+        return resp;
+    }
+    let filter = doc! { "ticket_id": &ticket_id, "project_id": &project_id };
+    let mut cursor = match watches(&data.mongodb).find(filter).sort(doc! { "created_at": 1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching ticket watchers: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching watchers");
+        }
+    };
+    let mut out = Vec::new();
+    while let Some(watch) = cursor.next().await {
+        match watch {
+            Ok(w) => out.push(w),
+            Err(e) => error!("Error reading ticket watcher: {}", e),
+        }
+    }
+    ok(out)
+}
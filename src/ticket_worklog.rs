@@ -0,0 +1,108 @@
+// src/ticket_worklog.rs
+//
+// Time spent on tickets. Entries are written by focus sessions when they end (see
+// focus_sessions.rs) and listed per ticket for project members.
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{self, doc};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::response::CodedError;
+use crate::tenancy::Repo;
+use crate::ticket::Ticket;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorklogEntry {
+    pub entry_id: String,
+    pub ticket_id: String,
+    pub user_id: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub minutes: i64,
+    /// Focus session the time was tracked in
+    pub focus_session_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Worklog {
+    pub ticket_id: String,
+    pub total_minutes: i64,
+    /// Most recent first
+    pub entries: Vec<WorklogEntry>,
+}
+
+fn worklog_coll(db: &MongoDB) -> mongodb::Collection<WorklogEntry> {
+    db.db.collection::<WorklogEntry>("ticket_worklog")
+}
+
+/// Logs the time of a focus session once per session: when it is logged again, by a
+/// retry or a concurrent end, the entry stored first is returned instead.
+pub(crate) async fn log_focus_work(db: &MongoDB, entry: &WorklogEntry) -> mongodb::error::Result<WorklogEntry> {
+    let filter = doc! { "focus_session_id": &entry.focus_session_id };
+    let logged = worklog_coll(db)
+        .find_one_and_update(filter.clone(), doc! { "$setOnInsert": bson::to_document(entry)? })
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .await;
+    let logged = match logged {
+        // Two upserts raced; the unique index let only the other one insert.
+        Err(e) if matches!(&*e.kind, ErrorKind::Write(WriteFailure::WriteError(w)) if w.code == 11000) => {
+            worklog_coll(db).find_one(filter).await?
+        }
+        other => other?,
+    };
+    Ok(logged.unwrap_or_else(|| entry.clone()))
+}
+
+/// The ticket and its team, if the caller is a member of its project.
+pub(crate) async fn member_ticket(auth: &AuthContext, data: &AppState, ticket_id: &str) -> Result<(Ticket, String), HttpResponse> {
+    let scope = auth.ticket_scope(ticket_id).await?;
+    let ticket = match Repo::<Ticket>::new(&data.mongodb, &scope).find_one(doc! { "ticket_id": ticket_id }).await {
+        Ok(Some(t)) => t,
+        Ok(None) => return Err(HttpResponse::NotFound().error("ticket_not_found")),
+        Err(e) => {
+            error!("Error fetching ticket: {}", e);
+            return Err(HttpResponse::InternalServerError().error("error_fetching_ticket"));
+        }
+    };
+    if !auth.is_project_member(&ticket.project_id).await {
+        return Err(HttpResponse::Unauthorized().error("not_project_member"));
+    }
+    Ok((ticket, scope.team_id().to_string()))
+}
+
+/// GET /tickets/{ticket_id}/worklog
+pub async fn get_worklog(auth: AuthContext, data: web::Data<AppState>, ticket_id: web::Path<String>) -> impl Responder {
+    let ticket = match member_ticket(&auth, &data, &ticket_id).await {
+        Ok((t, _)) => t,
+        Err(resp) => return resp,
+    };
+
+    let mut cursor = match worklog_coll(&data.mongodb).find(doc! { "ticket_id": &ticket.ticket_id }).sort(doc! { "started_at": -1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching worklog: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching worklog");
+        }
+    };
+    let mut entries = Vec::new();
+    while let Some(entry) = cursor.next().await {
+        match entry {
+            Ok(e) => entries.push(e),
+            Err(e) => error!("Error reading worklog entry: {}", e),
+        }
+    }
+    HttpResponse::Ok().json(Worklog {
+        ticket_id: ticket.ticket_id,
+        total_minutes: entries.iter().map(|e| e.minutes).sum(),
+        entries,
+    })
+}
@@ -0,0 +1,157 @@
+// src/ui_preferences.rs
+//
+// Frontend preferences that should follow the user between devices: theme, board
+// density, default landing team, hidden columns and the like. The server does not
+// interpret them; it stores one JSON value per key in `ui_preferences` and only
+// enforces size limits, so the frontend can add keys without a backend change.
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::error;
+use mongodb::bson::{doc, to_bson, Bson, Document};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+
+const MAX_KEYS: usize = 100;
+const MAX_KEY_LEN: usize = 64;
+/// Serialized size of one value
+const MAX_VALUE_BYTES: usize = 8 * 1024;
+/// Serialized size of all of a user's preferences
+const MAX_TOTAL_BYTES: usize = 64 * 1024;
+/// Nesting of arrays and objects within one value
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Serialize)]
+pub struct UiPreferences {
+    pub preferences: Map<String, Value>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+fn prefs_coll(db: &MongoDB) -> mongodb::Collection<Document> {
+    db.db.collection::<Document>("ui_preferences")
+}
+
+/// Letters, digits, `_` and `-`; dots and `$` would be read as MongoDB paths and operators.
+fn valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LEN && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn depth(value: &Value) -> usize {
+    match value {
+        Value::Array(items) => 1 + items.iter().map(depth).max().unwrap_or(0),
+        Value::Object(map) => 1 + map.values().map(depth).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn json_len(value: &Value) -> usize {
+    serde_json::to_vec(value).map_or(usize::MAX, |v| v.len())
+}
+
+async fn load(db: &MongoDB, user_id: &str) -> mongodb::error::Result<UiPreferences> {
+    let stored = prefs_coll(db).find_one(doc! { "user_id": user_id }).await?;
+    let Some(stored) = stored else {
+        return Ok(UiPreferences { preferences: Map::new(), updated_at: None });
+    };
+    let preferences = match stored.get("preferences").cloned().map(Bson::into_relaxed_extjson) {
+        Some(Value::Object(map)) => map,
+        _ => Map::new(),
+    };
+    let updated_at = stored.get_datetime("updated_at").ok().map(|t| t.to_chrono());
+    Ok(UiPreferences { preferences, updated_at })
+}
+
+/// GET /users/me/ui-preferences
+pub async fn get_ui_preferences(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    match load(&data.mongodb, auth.user_id()).await {
+        Ok(p) => HttpResponse::Ok().json(p),
+        Err(e) => {
+            error!("Error fetching UI preferences: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching UI preferences")
+        }
+    }
+}
+
+/// PUT /users/me/ui-preferences
+/// Body: an object of keys to set. Keys not mentioned are kept; a null value
+/// removes its key.
+pub async fn update_ui_preferences(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    payload: web::Json<Value>,
+) -> impl Responder {
+    let Value::Object(changes) = payload.into_inner() else {
+        return HttpResponse::BadRequest().body("Body must be a JSON object");
+    };
+    if changes.is_empty() {
+        return HttpResponse::BadRequest().body("No preferences to update");
+    }
+    for (key, value) in &changes {
+        if !valid_key(key) {
+            return HttpResponse::BadRequest().body(format!(
+                "Invalid key '{}': use up to {} letters, digits, '_' or '-'",
+                key, MAX_KEY_LEN
+            ));
+        }
+        if json_len(value) > MAX_VALUE_BYTES {
+            return HttpResponse::BadRequest().body(format!("Value of '{}' is larger than {} bytes", key, MAX_VALUE_BYTES));
+        }
+        if depth(value) > MAX_DEPTH {
+            return HttpResponse::BadRequest().body(format!("Value of '{}' is nested deeper than {} levels", key, MAX_DEPTH));
+        }
+    }
+
+    // Check the limits against the merged result before writing only the changed keys.
+    let mut merged = match load(&data.mongodb, auth.user_id()).await {
+        Ok(p) => p.preferences,
+        Err(e) => {
+            error!("Error fetching UI preferences: {}", e);
+            return HttpResponse::InternalServerError().body("Error updating UI preferences");
+        }
+    };
+    let mut set = doc! { "updated_at": mongodb::bson::DateTime::now() };
+    let mut unset = Document::new();
+    for (key, value) in changes {
+        let path = format!("preferences.{}", key);
+        if value.is_null() {
+            merged.remove(&key);
+            unset.insert(path, "");
+            continue;
+        }
+        match to_bson(&value) {
+            Ok(v) => set.insert(path, v),
+            Err(e) => return HttpResponse::BadRequest().body(format!("Invalid value for '{}': {}", key, e)),
+        };
+        merged.insert(key, value);
+    }
+    if merged.len() > MAX_KEYS {
+        return HttpResponse::BadRequest().body(format!("At most {} preferences can be stored", MAX_KEYS));
+    }
+    if json_len(&Value::Object(merged)) > MAX_TOTAL_BYTES {
+        return HttpResponse::BadRequest().body(format!("Preferences may take at most {} bytes in total", MAX_TOTAL_BYTES));
+    }
+
+    let mut update = doc! { "$set": set };
+    if !unset.is_empty() {
+        update.insert("$unset", unset);
+    }
+    if let Err(e) = prefs_coll(&data.mongodb)
+        .update_one(doc! { "user_id": auth.user_id() }, update)
+        .upsert(true)
+        .await
+    {
+        error!("Error updating UI preferences: {}", e);
+        return HttpResponse::InternalServerError().body("Error updating UI preferences");
+    }
+    match load(&data.mongodb, auth.user_id()).await {
+        Ok(p) => HttpResponse::Ok().json(p),
+        Err(e) => {
+            error!("Error fetching UI preferences: {}", e);
+            HttpResponse::InternalServerError().body("Error updating UI preferences")
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use actix_web::{web, HttpResponse, Responder, HttpRequest, HttpMessage};
+use actix_web::{web, HttpResponse, Responder, HttpRequest};
 use futures_util::StreamExt;
 use mongodb::bson::{doc, to_document, DateTime as BsonDateTime, oid::ObjectId};
 use serde::{Deserialize, Serialize};
@@ -7,6 +7,8 @@ use chrono::Utc;
 use log::{debug, error, info};
 
 use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::response::CodedError;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Team {
@@ -93,15 +95,11 @@ pub struct DeleteInvitationsRequest {
 }
 
 pub async fn get_user_teams(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     user_id: web::Path<String>,
 ) -> impl Responder {
-    let current_user = if let Some(id) = req.extensions().get::<String>() {
-        id.clone()
-    } else {
-        return HttpResponse::Unauthorized().body("Unauthorized");
-    };
+    let current_user = auth.user_id().to_string();
 
     if current_user != *user_id {
         return HttpResponse::Unauthorized().body("Cannot access other user's teams");
@@ -237,6 +235,7 @@ pub struct FindUserQuery {
 }
 
 pub async fn find_user_email(
+    _auth: AuthContext,
     query: web::Query<FindUserQuery>,
     data: web::Data<AppState>,
 ) -> impl Responder {
@@ -261,6 +260,7 @@ pub async fn find_user_email(
 
 // New endpoint: Get user information by id
 pub async fn get_user_by_id(
+    _auth: AuthContext,
     path: web::Path<String>,
     data: web::Data<AppState>,
 ) -> impl Responder {
@@ -270,7 +270,7 @@ pub async fn get_user_by_id(
         let filter = doc! { "_id": object_id };
         match users_collection.find_one(filter).await {
             Ok(Some(user)) => HttpResponse::Ok().json(user),
-            Ok(None) => HttpResponse::NotFound().body("User not found"),
+            Ok(None) => HttpResponse::NotFound().error("user_not_found"),
             Err(e) => HttpResponse::InternalServerError().body(format!("Error fetching user: {}", e)),
         }
     } else {
@@ -284,19 +284,12 @@ pub struct WorkingHoursRequest {
 }
 
 pub async fn set_working_hours(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
     hours: web::Json<WorkingHoursRequest>,
 ) -> impl Responder {
-    // Corrected: Bind extensions to a variable to extend the lifetime
-    let extensions = req.extensions();
-    let user_id = match extensions.get::<String>() {
-        Some(id) => id,
-        None => return HttpResponse::Unauthorized().body("Unauthorized"),
-    };
-
     let users_collection = data.mongodb.db.collection::<User>("users");
-    let object_id = match ObjectId::parse_str(user_id) {
+    let object_id = match ObjectId::parse_str(auth.user_id()) {
         Ok(id) => id,
         Err(_) => return HttpResponse::BadRequest().body("Invalid user ID"),
     };
@@ -310,7 +303,7 @@ pub async fn set_working_hours(
 
     match users_collection.update_one(doc! { "_id": object_id }, update).await {
         Ok(result) if result.modified_count == 1 => HttpResponse::Ok().json("Working hours updated"),
-        Ok(_) => HttpResponse::NotFound().body("User not found"),
+        Ok(_) => HttpResponse::NotFound().error("user_not_found"),
         Err(err) => {
             error!("Error updating working hours: {}", err);
             HttpResponse::InternalServerError().body("Error updating working hours")
@@ -319,18 +312,11 @@ pub async fn set_working_hours(
 }
 
 pub async fn get_working_hours(
-    req: HttpRequest,
+    auth: AuthContext,
     data: web::Data<AppState>,
 ) -> impl Responder {
-    // Corrected: Bind extensions to a variable to extend the lifetime
-    let extensions = req.extensions();
-    let user_id = match extensions.get::<String>() {
-        Some(id) => id,
-        None => return HttpResponse::Unauthorized().body("Unauthorized"),
-    };
-
     let users_collection = data.mongodb.db.collection::<User>("users");
-    let object_id = match ObjectId::parse_str(user_id) {
+    let object_id = match ObjectId::parse_str(auth.user_id()) {
         Ok(id) => id,
         Err(_) => return HttpResponse::BadRequest().body("Invalid user ID"),
     };
@@ -342,7 +328,7 @@ pub async fn get_working_hours(
             response.insert("end".to_string(), user.working_hours_end.unwrap_or_default().into());
             response
         }),
-        Ok(None) => HttpResponse::NotFound().body("User not found"),
+        Ok(None) => HttpResponse::NotFound().error("user_not_found"),
         Err(err) => {
             error!("Error fetching working hours: {}", err);
             HttpResponse::InternalServerError().body("Error fetching working hours")
@@ -0,0 +1,327 @@
+// src/user_status.rs
+//
+// Status messages ("🌴 On holiday", "In a meeting until 3pm"). A user sets an emoji
+// and text, optionally expiring at a given time. Users who opt in to calendar sync
+// show "In a meeting" while one of their calendar events is running, unless they
+// have set a status of their own; during a focus session's busy block they show
+// "Focusing" instead. Whenever the shown status changes, teammates get a
+// `presence` push with it; a background job covers expiries and meetings starting or
+// ending.
+
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::error;
+use mongodb::bson::{doc, Document};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::calendar::CalendarEvent;
+use crate::chat_db::MongoDB;
+use crate::chat_server::{IsOnline, NotifyUser};
+use crate::do_not_disturb::in_dnd_now;
+use crate::emoji::expand_shortcodes;
+use crate::response::CodedError;
+use crate::team_management::resolve_user_id;
+
+const PRESENCE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_TEXT_LEN: usize = 100;
+const MAX_EMOJI_LEN: usize = 32;
+const MEETING_EMOJI: &str = "📅";
+const MEETING_TEXT: &str = "In a meeting";
+const FOCUS_EMOJI: &str = "🎯";
+const FOCUS_TEXT: &str = "Focusing";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserStatus {
+    pub user_id: String,
+    pub emoji: Option<String>,
+    pub text: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Show "In a meeting" during the user's calendar events
+    #[serde(default)]
+    pub calendar_sync: bool,
+    /// Event the last presence push reported the user to be in
+    #[serde(default)]
+    pub meeting_event_id: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl UserStatus {
+    fn has_own_status(&self, now: DateTime<Utc>) -> bool {
+        (self.emoji.is_some() || self.text.is_some()) && self.expires_at.is_none_or(|e| e > now)
+    }
+}
+
+/// The status as shown to others.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusView {
+    pub user_id: String,
+    pub online: bool,
+    pub emoji: Option<String>,
+    pub text: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Set from the user's calendar rather than by the user
+    pub automatic: bool,
+    /// Notifications to the user are held back until their DND ends
+    pub dnd: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetStatusRequest {
+    /// An emoji or a `:shortcode:`
+    pub emoji: Option<String>,
+    pub text: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Left unchanged when omitted
+    pub calendar_sync: Option<bool>,
+}
+
+fn statuses(db: &MongoDB) -> mongodb::Collection<UserStatus> {
+    db.db.collection::<UserStatus>("user_statuses")
+}
+
+/// The calendar event the user is in at `now`; the one ending last if several overlap.
+async fn current_meeting(db: &MongoDB, user_id: &str, now: DateTime<Utc>) -> mongodb::error::Result<Option<CalendarEvent>> {
+    let filter = doc! { "$or": [{ "user_id": user_id }, { "participants": user_id }] };
+    let mut cursor = db.db.collection::<CalendarEvent>("calendar_events").find(filter).await?;
+    let mut current: Option<CalendarEvent> = None;
+    while let Some(event) = cursor.next().await {
+        let event = event?;
+        if event.start <= now && event.end > now && current.as_ref().is_none_or(|c| event.end > c.end) {
+            current = Some(event);
+        }
+    }
+    Ok(current)
+}
+
+fn view(
+    user_id: &str,
+    online: bool,
+    dnd: bool,
+    status: Option<&UserStatus>,
+    meeting: Option<&CalendarEvent>,
+    now: DateTime<Utc>,
+) -> StatusView {
+    match (status, meeting) {
+        (Some(s), _) if s.has_own_status(now) => StatusView {
+            user_id: user_id.to_string(),
+            online,
+            emoji: s.emoji.clone(),
+            text: s.text.clone(),
+            expires_at: s.expires_at,
+            automatic: false,
+            dnd,
+        },
+        (_, Some(event)) => {
+            let (emoji, text) = if event.focus { (FOCUS_EMOJI, FOCUS_TEXT) } else { (MEETING_EMOJI, MEETING_TEXT) };
+            StatusView {
+                user_id: user_id.to_string(),
+                online,
+                emoji: Some(emoji.to_string()),
+                text: Some(text.to_string()),
+                expires_at: Some(event.end),
+                automatic: true,
+                dnd,
+            }
+        }
+        _ => StatusView { user_id: user_id.to_string(), online, emoji: None, text: None, expires_at: None, automatic: false, dnd },
+    }
+}
+
+async fn status_view(data: &AppState, user_id: &str) -> mongodb::error::Result<StatusView> {
+    let now = Utc::now();
+    let status = statuses(&data.mongodb).find_one(doc! { "user_id": user_id }).await?;
+    let meeting = match &status {
+        Some(s) if s.calendar_sync && !s.has_own_status(now) => current_meeting(&data.mongodb, user_id, now).await?,
+        _ => None,
+    };
+    let online = data.chat_server.send(IsOnline { user_id: user_id.to_string() }).await.unwrap_or(false);
+    let dnd = in_dnd_now(&data.mongodb, user_id).await;
+    Ok(view(user_id, online, dnd, status.as_ref(), meeting.as_ref(), now))
+}
+
+/// Everyone sharing a team with the user, the user included.
+async fn teammates(db: &MongoDB, user_id: &str) -> mongodb::error::Result<Vec<String>> {
+    let team_ids = db.user_team_ids(user_id).await?;
+    if team_ids.is_empty() {
+        return Ok(vec![user_id.to_string()]);
+    }
+    let ids = db.db.collection::<Document>("user_teams").distinct("user_id", doc! { "team_id": { "$in": team_ids } }).await?;
+    Ok(ids.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+}
+
+/// Push the user's current status to their teammates' sessions.
+pub(crate) async fn broadcast_presence(data: &AppState, user_id: &str) {
+    let (status, audience) = match (status_view(data, user_id).await, teammates(&data.mongodb, user_id).await) {
+        (Ok(s), Ok(a)) => (s, a),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Error preparing presence of {}: {}", user_id, e);
+            return;
+        }
+    };
+    let payload = serde_json::json!({ "type": "presence", "status": status }).to_string();
+    for recipient in audience {
+        data.chat_server.do_send(NotifyUser { user_id: recipient, payload: payload.clone() });
+    }
+}
+
+/// Clears expired statuses and follows synced users in and out of meetings,
+/// broadcasting each change.
+async fn refresh_statuses(data: &AppState) {
+    let filter = doc! { "$or": [{ "expires_at": { "$ne": null } }, { "calendar_sync": true }] };
+    let mut cursor = match statuses(&data.mongodb).find(filter).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching user statuses: {}", e);
+            return;
+        }
+    };
+    let now = Utc::now();
+    while let Some(status) = cursor.next().await {
+        let status = match status {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Error reading user status: {}", e);
+                continue;
+            }
+        };
+        let mut update = Document::new();
+        if status.expires_at.is_some_and(|e| e <= now) {
+            update.insert("emoji", None::<String>);
+            update.insert("text", None::<String>);
+            update.insert("expires_at", None::<String>);
+        }
+        if status.calendar_sync {
+            let meeting = match current_meeting(&data.mongodb, &status.user_id, now).await {
+                Ok(m) => m.map(|e| e.event_id),
+                Err(e) => {
+                    error!("Error fetching meetings of {}: {}", status.user_id, e);
+                    continue;
+                }
+            };
+            if meeting != status.meeting_event_id {
+                update.insert("meeting_event_id", meeting);
+            }
+        }
+        if update.is_empty() {
+            continue;
+        }
+        if let Err(e) = statuses(&data.mongodb).update_one(doc! { "user_id": &status.user_id }, doc! { "$set": update }).await {
+            error!("Error updating status of {}: {}", status.user_id, e);
+            continue;
+        }
+        broadcast_presence(data, &status.user_id).await;
+    }
+}
+
+pub fn spawn_presence_updates(data: AppState) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(PRESENCE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            refresh_statuses(&data).await;
+        }
+    });
+}
+
+/// GET /users/{id}/status
+/// `id` may be "me", a user id, an email or a username; only teammates can look.
+pub async fn get_user_status(auth: AuthContext, data: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
+    let reference = path.into_inner();
+    let user_id = if reference == "me" {
+        auth.user_id().to_string()
+    } else {
+        match resolve_user_id(&data.mongodb, &reference).await {
+            Some(id) => id,
+            None => return HttpResponse::NotFound().error("user_not_found"),
+        }
+    };
+    if user_id != auth.user_id() {
+        match (data.mongodb.user_team_ids(auth.user_id()).await, data.mongodb.user_team_ids(&user_id).await) {
+            (Ok(mine), Ok(theirs)) if mine.iter().any(|t| theirs.contains(t)) => {}
+            (Ok(_), Ok(_)) => return HttpResponse::Unauthorized().body("You do not share a team with this user"),
+            (Err(e), _) | (_, Err(e)) => {
+                error!("Error fetching teams: {}", e);
+                return HttpResponse::InternalServerError().body("Error fetching status");
+            }
+        }
+    }
+    match status_view(&data, &user_id).await {
+        Ok(s) => HttpResponse::Ok().json(s),
+        Err(e) => {
+            error!("Error fetching status of {}: {}", user_id, e);
+            HttpResponse::InternalServerError().body("Error fetching status")
+        }
+    }
+}
+
+/// PUT /users/me/status
+/// Replaces the status; sending neither emoji nor text clears it.
+pub async fn set_status(auth: AuthContext, data: web::Data<AppState>, payload: web::Json<SetStatusRequest>) -> impl Responder {
+    let req = payload.into_inner();
+    let clean = |v: Option<String>| v.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+    let emoji = clean(req.emoji).map(|e| expand_shortcodes(&e));
+    let text = clean(req.text);
+    if emoji.as_ref().is_some_and(|e| e.chars().count() > MAX_EMOJI_LEN) {
+        return HttpResponse::BadRequest().body("emoji is too long");
+    }
+    if text.as_ref().is_some_and(|t| t.chars().count() > MAX_TEXT_LEN) {
+        return HttpResponse::BadRequest().body(format!("text may be at most {} characters", MAX_TEXT_LEN));
+    }
+    if req.expires_at.is_some_and(|e| e <= Utc::now()) {
+        return HttpResponse::BadRequest().body("expires_at must be in the future");
+    }
+    let expires_at = if emoji.is_some() || text.is_some() { req.expires_at } else { None };
+
+    let mut set = doc! {
+        "emoji": emoji,
+        "text": text,
+        "expires_at": expires_at.map(|e| e.to_rfc3339()),
+        "updated_at": Utc::now().to_rfc3339(),
+    };
+    if let Some(sync) = req.calendar_sync {
+        set.insert("calendar_sync", sync);
+    }
+    update_status(&data, auth.user_id(), set).await
+}
+
+/// DELETE /users/me/status
+/// Clears the user's own status; calendar sync stays as it is.
+pub async fn clear_status(auth: AuthContext, data: web::Data<AppState>) -> impl Responder {
+    let set = doc! {
+        "emoji": None::<String>,
+        "text": None::<String>,
+        "expires_at": None::<String>,
+        "updated_at": Utc::now().to_rfc3339(),
+    };
+    update_status(&data, auth.user_id(), set).await
+}
+
+async fn update_status(data: &AppState, user_id: &str, set: Document) -> HttpResponse {
+    let result = statuses(&data.mongodb)
+        .find_one_and_update(doc! { "user_id": user_id }, doc! { "$set": set })
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .await;
+    match result {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::InternalServerError().body("Error updating status"),
+        Err(e) => {
+            error!("Error updating status of {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().body("Error updating status");
+        }
+    }
+    broadcast_presence(data, user_id).await;
+    match status_view(data, user_id).await {
+        Ok(s) => HttpResponse::Ok().json(s),
+        Err(e) => {
+            error!("Error fetching status of {}: {}", user_id, e);
+            HttpResponse::InternalServerError().body("Error fetching status")
+        }
+    }
+}
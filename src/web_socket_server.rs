@@ -1,14 +1,26 @@
-use actix::{Actor, Handler, StreamHandler, Message, ActorContext, AsyncContext};
-use actix_web::{Error, HttpRequest, HttpResponse, web};
+use actix::{Actor, Handler, StreamHandler, Message, ActorContext, AsyncContext, WrapFuture};
+use actix_web::{Error, HttpMessage, HttpRequest, HttpResponse, web};
 use actix_web_actors::ws;
 use log::{info, error};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use crate::chat_server::{ChatServer, Connect, Disconnect, CreateMessage, ChatMessage, WsMessage, RelaySignal};
+use crate::auth_context::AuthContext;
+use crate::board_live::{JoinBoard, LeaveBoard};
+use crate::bots::{run_command, CommandReply};
+use crate::chat_server::{ChatServer, Connect, Disconnect, CreateMessage, NotifyUser, WsMessage, RelaySignal};
+use crate::doc_collab::{DocServer, DocUpdate, JoinDoc, LeaveDoc};
+use crate::estimation_poker::{PokerAction, PokerCommand};
+use crate::impersonation::{audit_ws_message, Impersonation};
+use crate::whiteboard::{JoinWhiteboard, LeaveWhiteboard, WhiteboardOp, WhiteboardServer};
 
 pub struct WsSession {
     pub user_id: String,
+    /// Set when the connection was opened with an impersonation token
+    pub impersonation: Option<Impersonation>,
+    pub data: web::Data<crate::app_state::AppState>,
     pub chat_server: actix::Addr<ChatServer>,
+    pub doc_server: actix::Addr<DocServer>,
+    pub whiteboard_server: actix::Addr<WhiteboardServer>,
 }
 
 impl Actor for WsSession {
@@ -29,6 +41,20 @@ impl Actor for WsSession {
             user_id: self.user_id.clone(),
             addr: ctx.address().recipient(),
         });
+        self.doc_server.do_send(LeaveDoc {
+            doc_id: None,
+            user_id: self.user_id.clone(),
+            addr: ctx.address().recipient(),
+        });
+        self.whiteboard_server.do_send(LeaveWhiteboard {
+            whiteboard_id: None,
+            user_id: self.user_id.clone(),
+            addr: ctx.address().recipient(),
+        });
+        self.chat_server.do_send(LeaveBoard {
+            board_id: None,
+            addr: ctx.address().recipient(),
+        });
     }
 }
 impl Handler<WsMessage> for WsSession {
@@ -40,13 +66,22 @@ impl Handler<WsMessage> for WsSession {
                 let json = serde_json::json!({
                     "chat_id": chat_msg.chat_id,
                     "sender_id": chat_msg.sender_id,
-                    "content": chat_msg.content
+                    "content": chat_msg.content,
+                    "attachments": chat_msg.attachments,
+                    "silent": chat_msg.silent
                 });
                 ctx.text(json.to_string());
             }
             WsMessage::Signal(signal_msg) => {
                 ctx.text(signal_msg.payload);
             }
+            WsMessage::Close(reason) => {
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Policy,
+                    description: Some(reason),
+                }));
+                ctx.stop();
+            }
         }
     }
 }
@@ -62,7 +97,94 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
         match item {
             Ok(ws::Message::Text(txt)) => {
                 info!("Received from user {}: {}", self.user_id, txt);
+                if let Some(imp) = self.impersonation.clone() {
+                    let data = self.data.clone();
+                    let path = ws_audit_path(&txt);
+                    actix_web::rt::spawn(async move { audit_ws_message(&data.mongodb, &imp, &path).await });
+                }
                 if let Ok(json_val) = serde_json::from_str::<Value>(&txt) {
+                    if let Some(doc_id) = json_val.get("doc_id").and_then(|v| v.as_str()) {
+                        let field = |name: &str| json_val.get(name).and_then(|v| v.as_str()).map(String::from);
+                        let doc_id = doc_id.to_string();
+                        let addr = ctx.address().recipient();
+                        match json_val.get("type").and_then(|v| v.as_str()) {
+                            Some("doc_join") => self.doc_server.do_send(JoinDoc {
+                                doc_id,
+                                user_id: self.user_id.clone(),
+                                state_vector: field("state_vector"),
+                                addr,
+                            }),
+                            Some("doc_update") => self.doc_server.do_send(DocUpdate {
+                                doc_id,
+                                user_id: self.user_id.clone(),
+                                update: field("update").unwrap_or_default(),
+                                addr,
+                            }),
+                            Some("doc_leave") => self.doc_server.do_send(LeaveDoc {
+                                doc_id: Some(doc_id),
+                                user_id: self.user_id.clone(),
+                                addr,
+                            }),
+                            _ => {}
+                        }
+                        return;
+                    }
+                    let kind = json_val.get("type").and_then(|v| v.as_str()).unwrap_or("");
+                    if let Some(whiteboard_id) = json_val.get("whiteboard_id").and_then(|v| v.as_str()) {
+                        let whiteboard_id = whiteboard_id.to_string();
+                        let addr = ctx.address().recipient();
+                        match kind {
+                            "wb_join" => self.whiteboard_server.do_send(JoinWhiteboard {
+                                whiteboard_id,
+                                user_id: self.user_id.clone(),
+                                addr,
+                            }),
+                            "wb_op" => self.whiteboard_server.do_send(WhiteboardOp {
+                                whiteboard_id,
+                                user_id: self.user_id.clone(),
+                                op: json_val.get("op").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                                element: json_val.get("element").cloned(),
+                                element_id: json_val.get("element_id").and_then(|v| v.as_str()).map(String::from),
+                                addr,
+                            }),
+                            "wb_leave" => self.whiteboard_server.do_send(LeaveWhiteboard {
+                                whiteboard_id: Some(whiteboard_id),
+                                user_id: self.user_id.clone(),
+                                addr,
+                            }),
+                            _ => {}
+                        }
+                        return;
+                    }
+                    if let Some(board_id) = json_val.get("board_id").and_then(|v| v.as_str()) {
+                        let board_id = board_id.to_string();
+                        let addr = ctx.address().recipient();
+                        match kind {
+                            "board_join" => self.chat_server.do_send(JoinBoard {
+                                board_id,
+                                user_id: self.user_id.clone(),
+                                addr,
+                            }),
+                            "board_leave" => self.chat_server.do_send(LeaveBoard {
+                                board_id: Some(board_id),
+                                addr,
+                            }),
+                            _ => {}
+                        }
+                        return;
+                    }
+                    if let (Some(action), Some(ticket_id)) = (
+                        PokerAction::from_ws(kind, &json_val),
+                        json_val.get("ticket_id").and_then(|v| v.as_str()),
+                    ) {
+                        self.chat_server.do_send(PokerCommand {
+                            ticket_id: ticket_id.to_string(),
+                            user_id: self.user_id.clone(),
+                            action,
+                            addr: ctx.address().recipient(),
+                        });
+                        return;
+                    }
                     if json_val.get("signalType").is_some() {
                         let chat_id = json_val.get("chat_id")
                             .and_then(|v| v.as_str())
@@ -78,6 +200,34 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
                     }
                 }
                 if let Ok(msg) = serde_json::from_str::<ClientMsg>(&txt) {
+                    if msg.content.trim_start().starts_with('/') {
+                        // Possibly a slash command. The session waits for it before
+                        // handling the next frame, so replies and later messages keep
+                        // the order they were sent in.
+                        let data = self.data.clone();
+                        let user_id = self.user_id.clone();
+                        let command = async move {
+                            match run_command(&data, &user_id, &msg.chat_id, &msg.content).await {
+                                None => data.chat_server.do_send(CreateMessage {
+                                    user_id,
+                                    chat_id: msg.chat_id,
+                                    content: msg.content,
+                                    attachments: None,
+                                }),
+                                Some(Err(e)) => {
+                                    let payload = serde_json::json!({ "type": "command_error", "chat_id": msg.chat_id, "message": e });
+                                    data.chat_server.do_send(NotifyUser { user_id, payload: payload.to_string() });
+                                }
+                                Some(Ok(CommandReply::Private(text))) => {
+                                    let payload = serde_json::json!({ "type": "command_reply", "chat_id": msg.chat_id, "message": text });
+                                    data.chat_server.do_send(NotifyUser { user_id, payload: payload.to_string() });
+                                }
+                                Some(Ok(_)) => {}
+                            }
+                        };
+                        ctx.wait(command.into_actor(self));
+                        return;
+                    }
                     self.chat_server.do_send(CreateMessage {
                         user_id: self.user_id.clone(),
                         chat_id: msg.chat_id,
@@ -95,21 +245,34 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
     }
 }
 
+/// Message type and target of a client message, for the impersonation audit.
+fn ws_audit_path(txt: &str) -> String {
+    let Ok(json_val) = serde_json::from_str::<Value>(txt) else {
+        return "invalid".to_string();
+    };
+    let field = |name: &str| json_val.get(name).and_then(|v| v.as_str());
+    let kind = field("type").or_else(|| json_val.get("signalType").map(|_| "signal")).unwrap_or("message");
+    let target = ["doc_id", "whiteboard_id", "board_id", "ticket_id", "chat_id"].into_iter().find_map(field);
+    match target {
+        Some(target) => format!("{} {}", kind, target),
+        None => kind.to_string(),
+    }
+}
+
+/// The session belongs to the authenticated caller (bearer token or session cookie).
 pub async fn ws_index(
+    auth: AuthContext,
     req: HttpRequest,
     stream: web::Payload,
     data: web::Data<crate::app_state::AppState>,
 ) -> Result<HttpResponse, Error> {
-    let query = req.uri().query().unwrap_or("");
-    let mut user_id = "Anonymous".to_string();
-    for piece in query.split('&') {
-        if let Some(val) = piece.strip_prefix("userId=") {
-            user_id = val.to_string();
-        }
-    }
     let ws_session = WsSession {
-        user_id,
+        user_id: auth.user_id().to_string(),
+        impersonation: req.extensions().get::<Impersonation>().cloned(),
+        data: data.clone(),
         chat_server: data.chat_server.clone(),
+        doc_server: data.doc_server.clone(),
+        whiteboard_server: data.whiteboard_server.clone(),
     };
     ws::start(ws_session, &req, stream)
 }
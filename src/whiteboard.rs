@@ -0,0 +1,537 @@
+// src/whiteboard.rs
+//
+// Collaborative whiteboards: a freeform canvas per project whose elements (shapes,
+// stickies, connectors, ...) are opaque client-defined JSON objects with a string
+// `id`. Boards are stored in `whiteboards`; while people are drawing, the canvas
+// lives in an in-memory room that relays element operations over the WebSocket
+// and is written back to MongoDB periodically:
+//
+//   {"type": "wb_join" | "wb_leave", "whiteboard_id": ...}
+//   {"type": "wb_op", "whiteboard_id": ..., "op": "add" | "update", "element": {"id": ..., ...}}
+//   {"type": "wb_op", "whiteboard_id": ..., "op": "delete", "element_id": ...}
+//
+// A room stays in memory until its last change is saved, and while a join is still
+// loading it, so a snapshot read from MongoDB is never older than a live room.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::prelude::*;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::{error, info};
+use mongodb::bson::{self, doc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::tenancy::project_team_id;
+use crate::app_state::AppState;
+use crate::auth_context::AuthContext;
+use crate::chat_db::MongoDB;
+use crate::chat_server::{SignalMessage, WsMessage};
+use crate::response::{CodedError, ok, ok_message};
+
+/// How often dirty rooms are written back to MongoDB.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_ELEMENTS: usize = 5_000;
+/// Serialized size limit of a single element.
+const MAX_ELEMENT_BYTES: usize = 64 * 1024;
+/// Serialized size limit of all elements, leaving room under MongoDB's 16 MB
+/// document limit for the rest of the whiteboard.
+const MAX_WHITEBOARD_BYTES: usize = 15 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Whiteboard {
+    pub whiteboard_id: String,
+    pub team_id: String,
+    pub project_id: String,
+    pub name: String,
+    /// Canvas elements in drawing order
+    #[serde(default)]
+    pub elements: Vec<Value>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWhiteboardRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWhiteboardRequest {
+    pub name: String,
+}
+
+fn element_id(element: &Value) -> Option<&str> {
+    element.get("id").and_then(Value::as_str).filter(|id| !id.is_empty())
+}
+
+fn element_bytes(element: &Value) -> usize {
+    element.to_string().len()
+}
+
+/* -------------------------------------------------------------------------- */
+/* Live rooms                                                                 */
+/* -------------------------------------------------------------------------- */
+
+struct Room {
+    elements: Vec<Value>,
+    /// Serialized size of `elements`
+    bytes: usize,
+    members: HashMap<String, Vec<Recipient<WsMessage>>>,
+    /// Bumped by every change; the room is clean when it equals `saved_revision`.
+    revision: u64,
+    saved_revision: u64,
+    saving: bool,
+}
+
+impl Room {
+    fn new(elements: Vec<Value>) -> Self {
+        let bytes = elements.iter().map(element_bytes).sum();
+        Room { elements, bytes, members: HashMap::new(), revision: 0, saved_revision: 0, saving: false }
+    }
+
+    fn dirty(&self) -> bool {
+        self.revision != self.saved_revision
+    }
+}
+
+pub struct WhiteboardServer {
+    rooms: HashMap<String, Room>,
+    /// Joins still loading each whiteboard; its room is kept until they finish.
+    loading: HashMap<String, usize>,
+    db: Arc<MongoDB>,
+}
+
+impl WhiteboardServer {
+    pub fn new(db: Arc<MongoDB>) -> Self {
+        WhiteboardServer { rooms: HashMap::new(), loading: HashMap::new(), db }
+    }
+
+    fn send(addr: &Recipient<WsMessage>, payload: Value) {
+        addr.do_send(WsMessage::Signal(SignalMessage { payload: payload.to_string() }));
+    }
+
+    fn send_error(addr: &Recipient<WsMessage>, whiteboard_id: &str, error: &str) {
+        Self::send(addr, json!({ "type": "wb_error", "whiteboard_id": whiteboard_id, "error": error }));
+    }
+
+    /// Persist every dirty room that is not already being saved. Rooms are dropped
+    /// once they have no members, nothing left to save and no join loading them.
+    fn flush(&mut self, ctx: &mut Context<Self>) {
+        let loading = &self.loading;
+        self.rooms.retain(|id, r| !r.members.is_empty() || r.dirty() || r.saving || loading.contains_key(id));
+
+        let mut snapshots = Vec::new();
+        for (whiteboard_id, room) in self.rooms.iter_mut().filter(|(_, r)| r.dirty() && !r.saving) {
+            snapshots.push((whiteboard_id.clone(), room.revision, room.elements.clone()));
+            room.saving = true;
+        }
+        if snapshots.is_empty() {
+            return;
+        }
+        let db = self.db.clone();
+        ctx.spawn(
+            async move {
+                let whiteboards = db.db.collection::<Whiteboard>("whiteboards");
+                let mut saved = Vec::with_capacity(snapshots.len());
+                for (whiteboard_id, revision, elements) in snapshots {
+                    let result = match bson::to_bson(&elements) {
+                        Ok(elements) => {
+                            let update = doc! { "$set": { "elements": elements, "updated_at": Utc::now().to_rfc3339() } };
+                            whiteboards.update_one(doc! { "whiteboard_id": &whiteboard_id }, update).await.map_err(|e| e.to_string())
+                        }
+                        Err(e) => Err(e.to_string()),
+                    };
+                    if let Err(e) = &result {
+                        error!("Error saving whiteboard {}: {}", whiteboard_id, e);
+                    }
+                    saved.push((whiteboard_id, revision, result.is_ok()));
+                }
+                saved
+            }
+            .into_actor(self)
+            .map(|saved, act, _| {
+                // A failed write leaves the room dirty for the next flush.
+                for (whiteboard_id, revision, ok) in saved {
+                    if let Some(room) = act.rooms.get_mut(&whiteboard_id) {
+                        room.saving = false;
+                        if ok {
+                            room.saved_revision = room.saved_revision.max(revision);
+                        }
+                    }
+                }
+            }),
+        );
+    }
+
+    fn join_loaded(&mut self, msg: JoinWhiteboard) {
+        let room = match self.rooms.get_mut(&msg.whiteboard_id) {
+            Some(r) => r,
+            None => return,
+        };
+        let members = room.members.entry(msg.user_id.clone()).or_default();
+        if !members.contains(&msg.addr) {
+            members.push(msg.addr.clone());
+        }
+        info!("User {} joined whiteboard {}", msg.user_id, msg.whiteboard_id);
+        Self::send(
+            &msg.addr,
+            json!({ "type": "wb_sync", "whiteboard_id": msg.whiteboard_id, "elements": room.elements }),
+        );
+    }
+}
+
+impl Actor for WhiteboardServer {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(SNAPSHOT_INTERVAL, |act, ctx| act.flush(ctx));
+    }
+}
+
+/// The whiteboard's elements, provided `user_id` is a member of its project.
+async fn load_for_member(db: &MongoDB, whiteboard_id: &str, user_id: &str) -> Result<Vec<Value>, String> {
+    let whiteboards = db.db.collection::<Whiteboard>("whiteboards");
+    let board = match whiteboards.find_one(doc! { "whiteboard_id": whiteboard_id }).await {
+        Ok(Some(b)) => b,
+        Ok(None) => return Err("Whiteboard not found".to_string()),
+        Err(e) => return Err(e.to_string()),
+    };
+    match db.check_project_membership(user_id, &board.project_id).await {
+        Ok(true) => Ok(board.elements),
+        Ok(false) => Err("Whiteboard not found".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct JoinWhiteboard {
+    pub whiteboard_id: String,
+    pub user_id: String,
+    pub addr: Recipient<WsMessage>,
+}
+
+/// An element operation from a client, relayed to everyone else in the room.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct WhiteboardOp {
+    pub whiteboard_id: String,
+    pub user_id: String,
+    pub op: String,
+    pub element: Option<Value>,
+    pub element_id: Option<String>,
+    pub addr: Recipient<WsMessage>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct LeaveWhiteboard {
+    /// None leaves every room, used when the socket closes.
+    pub whiteboard_id: Option<String>,
+    pub user_id: String,
+    pub addr: Recipient<WsMessage>,
+}
+
+/// The whiteboard was deleted: tell the room and drop it without saving.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CloseWhiteboard {
+    pub whiteboard_id: String,
+}
+
+impl Handler<JoinWhiteboard> for WhiteboardServer {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, msg: JoinWhiteboard, _: &mut Context<Self>) -> Self::Result {
+        let db = self.db.clone();
+        let whiteboard_id = msg.whiteboard_id.clone();
+        let user_id = msg.user_id.clone();
+        *self.loading.entry(msg.whiteboard_id.clone()).or_default() += 1;
+        Box::pin(
+            async move { load_for_member(&db, &whiteboard_id, &user_id).await }
+                .into_actor(self)
+                .map(move |res, act, _| {
+                    if let Some(n) = act.loading.get_mut(&msg.whiteboard_id) {
+                        *n -= 1;
+                        if *n == 0 {
+                            act.loading.remove(&msg.whiteboard_id);
+                        }
+                    }
+                    match res {
+                        Ok(elements) => {
+                            // A live room was kept while loading and is newer than the snapshot.
+                            act.rooms.entry(msg.whiteboard_id.clone()).or_insert_with(|| Room::new(elements));
+                            act.join_loaded(msg);
+                        }
+                        Err(e) => Self::send_error(&msg.addr, &msg.whiteboard_id, &e),
+                    }
+                }),
+        )
+    }
+}
+
+impl Handler<WhiteboardOp> for WhiteboardServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: WhiteboardOp, _: &mut Context<Self>) {
+        let room = match self.rooms.get_mut(&msg.whiteboard_id) {
+            Some(r) if r.members.get(&msg.user_id).is_some_and(|a| a.contains(&msg.addr)) => r,
+            _ => return Self::send_error(&msg.addr, &msg.whiteboard_id, "Join the whiteboard first"),
+        };
+
+        let applied: Result<Value, &str> = match (msg.op.as_str(), &msg.element, &msg.element_id) {
+            ("add" | "update", Some(element), _) => {
+                let id = match element_id(element) {
+                    Some(id) => id.to_string(),
+                    None => return Self::send_error(&msg.addr, &msg.whiteboard_id, "Element needs a string id"),
+                };
+                let bytes = element_bytes(element);
+                if bytes > MAX_ELEMENT_BYTES {
+                    return Self::send_error(&msg.addr, &msg.whiteboard_id, "Element is too large");
+                }
+                match room.elements.iter().position(|e| element_id(e) == Some(id.as_str())) {
+                    Some(i) if msg.op == "update" => {
+                        let total = room.bytes - element_bytes(&room.elements[i]) + bytes;
+                        if total > MAX_WHITEBOARD_BYTES {
+                            Err("Whiteboard is full")
+                        } else {
+                            room.elements[i] = element.clone();
+                            room.bytes = total;
+                            Ok(json!({ "element": element }))
+                        }
+                    }
+                    Some(_) => Err("An element with this id already exists"),
+                    None if msg.op == "add" && (room.elements.len() >= MAX_ELEMENTS || room.bytes + bytes > MAX_WHITEBOARD_BYTES) => {
+                        Err("Whiteboard is full")
+                    }
+                    None if msg.op == "add" => {
+                        room.elements.push(element.clone());
+                        room.bytes += bytes;
+                        Ok(json!({ "element": element }))
+                    }
+                    None => Err("Element not found"),
+                }
+            }
+            ("delete", _, Some(id)) => match room.elements.iter().position(|e| element_id(e) == Some(id.as_str())) {
+                Some(i) => {
+                    room.bytes -= element_bytes(&room.elements.remove(i));
+                    Ok(json!({ "element_id": id }))
+                }
+                None => Err("Element not found"),
+            },
+            _ => Err("Invalid whiteboard operation"),
+        };
+        let mut payload = match applied {
+            Ok(p) => p,
+            Err(e) => return Self::send_error(&msg.addr, &msg.whiteboard_id, e),
+        };
+        room.revision += 1;
+
+        payload["type"] = json!("wb_op");
+        payload["op"] = json!(msg.op);
+        payload["whiteboard_id"] = json!(msg.whiteboard_id);
+        payload["user_id"] = json!(msg.user_id);
+        for addr in room.members.values().flatten().filter(|a| **a != msg.addr) {
+            Self::send(addr, payload.clone());
+        }
+    }
+}
+
+impl Handler<LeaveWhiteboard> for WhiteboardServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: LeaveWhiteboard, _: &mut Context<Self>) {
+        for (whiteboard_id, room) in self.rooms.iter_mut() {
+            if msg.whiteboard_id.as_ref().is_some_and(|w| w != whiteboard_id) {
+                continue;
+            }
+            if let Some(addrs) = room.members.get_mut(&msg.user_id) {
+                addrs.retain(|a| a != &msg.addr);
+                if addrs.is_empty() {
+                    room.members.remove(&msg.user_id);
+                }
+            }
+        }
+    }
+}
+
+impl Handler<CloseWhiteboard> for WhiteboardServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseWhiteboard, _: &mut Context<Self>) {
+        if let Some(room) = self.rooms.remove(&msg.whiteboard_id) {
+            let payload = json!({ "type": "wb_deleted", "whiteboard_id": msg.whiteboard_id });
+            for addr in room.members.values().flatten() {
+                Self::send(addr, payload.clone());
+            }
+        }
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/* REST                                                                       */
+/* -------------------------------------------------------------------------- */
+
+/// Caller must be on the team and in the project, and the project must belong to the team.
+async fn check_access(auth: &AuthContext, data: &AppState, team_id: &str, project_id: &str) -> Option<HttpResponse> {
+    if !auth.is_team_member(team_id).await {
+        return Some(HttpResponse::Unauthorized().error("not_team_member"));
+    }
+    if project_team_id(&data.mongodb, project_id).await.as_deref() != Some(team_id) {
+        return Some(HttpResponse::NotFound().error("project_not_found"));
+    }
+    if !auth.is_project_member(project_id).await {
+        return Some(HttpResponse::Unauthorized().error("not_project_member"));
+    }
+    None
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/whiteboards
+pub async fn list_whiteboards(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (team_id, project_id) = path.into_inner();
+    if let Some(resp) = check_access(&auth, &data, &team_id, &project_id).await {
+        return resp;
+    }
+
+    let whiteboards = data.mongodb.db.collection::<Whiteboard>("whiteboards");
+    let mut cursor = match whiteboards.find(doc! { "project_id": &project_id }).sort(doc! { "created_at": 1 }).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error fetching whiteboards: {}", e);
+            return HttpResponse::InternalServerError().body("Error fetching whiteboards");
+        }
+    };
+    let mut result = Vec::new();
+    while let Some(board) = cursor.next().await {
+        match board {
+            Ok(b) => result.push(b),
+            Err(e) => {
+                error!("Cursor error: {}", e);
+                return HttpResponse::InternalServerError().body("Error reading whiteboards");
+            }
+        }
+    }
+    ok(result)
+}
+
+/// POST /teams/{team_id}/projects/{project_id}/whiteboards
+pub async fn create_whiteboard(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<CreateWhiteboardRequest>,
+) -> impl Responder {
+    let (team_id, project_id) = path.into_inner();
+    if let Some(resp) = check_access(&auth, &data, &team_id, &project_id).await {
+        return resp;
+    }
+    if payload.name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Name must not be empty");
+    }
+
+    let now = Utc::now();
+    let board = Whiteboard {
+        whiteboard_id: Uuid::new_v4().to_string(),
+        team_id,
+        project_id,
+        name: payload.name.trim().to_string(),
+        elements: Vec::new(),
+        created_by: auth.user_id().to_string(),
+        created_at: now,
+        updated_at: now,
+    };
+    match data.mongodb.db.collection::<Whiteboard>("whiteboards").insert_one(&board).await {
+        Ok(_) => ok(board),
+        Err(e) => {
+            error!("Error creating whiteboard: {}", e);
+            HttpResponse::InternalServerError().body("Error creating whiteboard")
+        }
+    }
+}
+
+/// GET /teams/{team_id}/projects/{project_id}/whiteboards/{whiteboard_id}
+/// Elements are as of the last snapshot; live clients get current state on `wb_join`.
+pub async fn get_whiteboard(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (team_id, project_id, whiteboard_id) = path.into_inner();
+    if let Some(resp) = check_access(&auth, &data, &team_id, &project_id).await {
+        return resp;
+    }
+
+    let whiteboards = data.mongodb.db.collection::<Whiteboard>("whiteboards");
+    match whiteboards.find_one(doc! { "whiteboard_id": &whiteboard_id, "project_id": &project_id }).await {
+        Ok(Some(board)) => ok(board),
+        Ok(None) => HttpResponse::NotFound().error("whiteboard_not_found"),
+        Err(e) => {
+            error!("Error fetching whiteboard: {}", e);
+            HttpResponse::InternalServerError().body("Error fetching whiteboard")
+        }
+    }
+}
+
+/// PUT /teams/{team_id}/projects/{project_id}/whiteboards/{whiteboard_id}
+pub async fn update_whiteboard(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    payload: web::Json<UpdateWhiteboardRequest>,
+) -> impl Responder {
+    let (team_id, project_id, whiteboard_id) = path.into_inner();
+    if let Some(resp) = check_access(&auth, &data, &team_id, &project_id).await {
+        return resp;
+    }
+    if payload.name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Name must not be empty");
+    }
+
+    let whiteboards = data.mongodb.db.collection::<Whiteboard>("whiteboards");
+    let filter = doc! { "whiteboard_id": &whiteboard_id, "project_id": &project_id };
+    let update = doc! { "$set": { "name": payload.name.trim(), "updated_at": Utc::now().to_rfc3339() } };
+    match whiteboards.update_one(filter, update).await {
+        Ok(res) if res.matched_count == 1 => ok_message("Whiteboard updated"),
+        Ok(_) => HttpResponse::NotFound().error("whiteboard_not_found"),
+        Err(e) => {
+            error!("Error updating whiteboard: {}", e);
+            HttpResponse::InternalServerError().body("Error updating whiteboard")
+        }
+    }
+}
+
+/// DELETE /teams/{team_id}/projects/{project_id}/whiteboards/{whiteboard_id}
+pub async fn delete_whiteboard(
+    auth: AuthContext,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (team_id, project_id, whiteboard_id) = path.into_inner();
+    if let Some(resp) = check_access(&auth, &data, &team_id, &project_id).await {
+        return resp;
+    }
+
+    let whiteboards = data.mongodb.db.collection::<Whiteboard>("whiteboards");
+    match whiteboards.delete_one(doc! { "whiteboard_id": &whiteboard_id, "project_id": &project_id }).await {
+        Ok(res) if res.deleted_count == 1 => {
+            data.whiteboard_server.do_send(CloseWhiteboard { whiteboard_id });
+            ok_message("Whiteboard deleted")
+        }
+        Ok(_) => HttpResponse::NotFound().error("whiteboard_not_found"),
+        Err(e) => {
+            error!("Error deleting whiteboard: {}", e);
+            HttpResponse::InternalServerError().body("Error deleting whiteboard")
+        }
+    }
+}